@@ -1,11 +1,17 @@
 use std::env;
+use std::fmt::Write as _;
+use std::fs;
 use std::path::PathBuf;
 
+#[path = "src/utils/oui_csv.rs"]
+mod oui_csv;
+
 fn main() {
     let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
     
     println!("cargo:rerun-if-changed=c_core/src/");
     println!("cargo:rerun-if-changed=c_core/include/");
+    println!("cargo:rerun-if-changed=data/oui.csv");
 
     let mut build = cc::Build::new();
     build
@@ -44,4 +50,22 @@ fn main() {
     bindings
         .write_to_file(out_path.join("bindings.rs"))
         .expect("Couldn't write bindings!");
+
+    generate_oui_table(&out_path);
+}
+
+/// Embed data/oui.csv as a sorted `(prefix, vendor)` table for binary search
+fn generate_oui_table(out_path: &std::path::Path) {
+    let csv = fs::read_to_string("data/oui.csv").expect("Couldn't read data/oui.csv");
+    let mut entries: Vec<(u32, String)> = csv.lines().filter_map(oui_csv::parse_line).collect();
+    entries.sort_by_key(|(prefix, _)| *prefix);
+    entries.dedup_by_key(|(prefix, _)| *prefix);
+
+    let mut table = String::from("pub static OUI_TABLE: &[(u32, &str)] = &[\n");
+    for (prefix, name) in &entries {
+        let _ = writeln!(table, "    (0x{:06X}, {:?}),", prefix, name);
+    }
+    table.push_str("];\n");
+
+    fs::write(out_path.join("oui_table.rs"), table).expect("Couldn't write OUI table!");
 }
@@ -135,6 +135,23 @@ nw_error_t nw_packet_craft_tcp_syn(nw_packet_t *packet, uint32_t src_ip, uint32_
     tcp->checksum = 0;
     tcp->urgent_ptr = 0;
     
+    // TCP checksum covers a pseudo-header of addresses, protocol and length
+    struct __attribute__((packed)) {
+        uint32_t src_ip;
+        uint32_t dst_ip;
+        uint8_t zero;
+        uint8_t protocol;
+        uint16_t tcp_length;
+        tcp_header_t tcp;
+    } pseudo;
+    pseudo.src_ip = ip->src_ip;
+    pseudo.dst_ip = ip->dst_ip;
+    pseudo.zero = 0;
+    pseudo.protocol = NW_PROTO_TCP;
+    pseudo.tcp_length = htons(sizeof(tcp_header_t));
+    memcpy(&pseudo.tcp, tcp, sizeof(tcp_header_t));
+    tcp->checksum = nw_checksum(&pseudo, sizeof(pseudo));
+    
     packet->length = sizeof(ip_header_t) + sizeof(tcp_header_t);
     packet->src_ip = src_ip;
     packet->dst_ip = dst_ip;
@@ -1,9 +1,9 @@
+//! Statistical analysis module for network performance metrics
+//! Provides real-time and historical analysis of latency, bandwidth, and packet loss
+
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
-/// Statistical analysis module for network performance metrics
-/// Provides real-time and historical analysis of latency, bandwidth, and packet loss
-
 /// Latency analyzer with sliding window statistics
 /// Tracks latency samples over time and provides statistical analysis including
 /// average, min, max, jitter, and anomaly detection using standard deviation
@@ -28,6 +28,14 @@ impl LatencyAnalyzer {
         self.samples.push_back(latency_ms);
     }
     
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
     pub fn average(&self) -> f64 {
         if self.samples.is_empty() {
             return 0.0;
@@ -42,7 +50,49 @@ impl LatencyAnalyzer {
     pub fn max(&self) -> f64 {
         self.samples.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
     }
-    
+
+    /// Middle sample (mean of the middle two for an even count)
+    /// Unlike the average, a single delayed reply doesn't move it
+    pub fn median(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        }
+    }
+
+    /// Sample below which `p` percent of the samples fall (nearest rank),
+    /// e.g. 95.0 for the p95 latency
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    /// Population standard deviation of the samples
+    pub fn std_dev(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let avg = self.average();
+        let variance = self.samples.iter()
+            .map(|&x| (x - avg).powi(2))
+            .sum::<f64>() / self.samples.len() as f64;
+        variance.sqrt()
+    }
+
     /// Calculate jitter (variance in latency) using RFC 3550 algorithm
     /// Jitter represents the variation in packet arrival times and is critical
     /// for real-time applications like VoIP and video streaming
@@ -67,10 +117,7 @@ impl LatencyAnalyzer {
         }
         
         let avg = self.average();
-        let variance: f64 = self.samples.iter()
-            .map(|&x| (x - avg).powi(2))
-            .sum::<f64>() / self.samples.len() as f64;
-        let std_dev = variance.sqrt();
+        let std_dev = self.std_dev();
         
         self.samples.back()
             .map(|&last| (last - avg).abs() > threshold_std_dev * std_dev)
@@ -110,13 +157,15 @@ impl BandwidthAnalyzer {
     }
     
     /// Calculate current bandwidth in bytes per second
-    /// Returns the average bandwidth over the configured time window
+    /// Returns the average bandwidth over the configured time window; the
+    /// oldest measurement only marks where the window starts, since its bytes
+    /// moved before it was taken
     pub fn current_bandwidth(&self) -> f64 {
         if self.bytes_history.len() < 2 {
             return 0.0;
         }
         
-        let total_bytes: u64 = self.bytes_history.iter().map(|(_, b)| b).sum();
+        let total_bytes: u64 = self.bytes_history.iter().skip(1).map(|(_, b)| b).sum();
         let duration = self.bytes_history.back().unwrap().0
             .duration_since(self.bytes_history.front().unwrap().0)
             .as_secs_f64();
@@ -151,6 +200,14 @@ impl PacketLossDetector {
         self.received += count;
     }
     
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+    
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+    
     pub fn loss_rate(&self) -> f64 {
         if self.sent == 0 {
             return 0.0;
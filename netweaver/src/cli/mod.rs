@@ -1,7 +1,10 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{ArgGroup, Parser, Subcommand};
 
-use crate::{scanner, diagnostics, optimizer, monitor, security};
+use crate::{scanner, diagnostics, optimizer, monitor, output, security};
 
 #[derive(Parser)]
 #[command(name = "netweaver")]
@@ -13,13 +16,15 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    #[arg(short, long, global = true)]
+    #[arg(short, long, global = true, conflicts_with = "quiet", help = "Log per-host and per-probe detail")]
     pub verbose: bool,
 
-    #[arg(short, long, global = true)]
+    #[arg(short, long, global = true, help = "Only print final results and errors")]
     pub quiet: bool,
 }
 
+// Parsed once per process, so the size gap between variants doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Scan and discover network devices")]
@@ -27,26 +32,126 @@ pub enum Commands {
         #[arg(long, help = "Scan local area network")]
         lan: bool,
 
-        #[arg(short, long, help = "Target IP or CIDR range")]
+        #[arg(short, long, help = "Target IPs, CIDR ranges or hostnames (comma-separated)")]
         target: Option<String>,
 
-        #[arg(short, long, default_value = "100", help = "Number of concurrent threads")]
-        threads: usize,
+        #[arg(long, help = "Read targets (IPs, CIDRs, hostnames) from a file, one per line")]
+        target_file: Option<String>,
+
+        #[arg(short = 'T', long, help = "Number of concurrent threads (default: 100)")]
+        threads: Option<usize>,
 
         #[arg(long, help = "Scan specific ports (comma-separated)")]
         ports: Option<String>,
 
+        #[arg(long, value_name = "N", help = "Scan the N most common ports (combines with --ports)")]
+        top_ports: Option<usize>,
+
         #[arg(short, long, help = "Export results to file (JSON/YAML)")]
         output: Option<String>,
 
         #[arg(long, help = "Generate network topology visualization")]
         topology: bool,
+
+        #[arg(long, help = "Write network topology as a Graphviz DOT file")]
+        topology_out: Option<String>,
+
+        #[arg(long, value_name = "N", requires = "topology", help = "Show at most N devices per subnet in the topology map")]
+        topology_limit: Option<usize>,
+
+        #[arg(long, help = "Hosts or CIDRs to skip (comma-separated)")]
+        exclude: Option<String>,
+
+        #[arg(long, help = "File listing hosts or CIDRs to skip, one per line")]
+        exclude_file: Option<String>,
+
+        #[arg(long, help = "Compare against a previously saved scan result")]
+        diff: Option<String>,
+
+        #[arg(long, help = "Re-scan periodically and report changes (e.g. 30s, 10m, 1h)")]
+        watch: Option<String>,
+
+        #[arg(long, value_name = "MS", help = "Fixed probe timeout, disables RTT-adaptive timeouts")]
+        timeout: Option<u64>,
+
+        #[arg(long, value_name = "MS", help = "Upper bound for adaptive probe timeouts (default: 2000)")]
+        max_timeout: Option<u64>,
+
+        #[arg(long, value_name = "N", help = "Cap new connection attempts per second across the scan")]
+        max_rate: Option<u32>,
+
+        #[arg(long, help = "Periodically save scan progress to this file")]
+        checkpoint: Option<String>,
+
+        #[arg(long, value_name = "N", default_value = "100", help = "Hosts between checkpoint writes")]
+        checkpoint_every: usize,
+
+        #[arg(long, help = "Resume an interrupted scan from a checkpoint file")]
+        resume: Option<String>,
+
+        #[arg(long, value_name = "CSV", help = "IEEE OUI registry CSV to use for MAC vendor names")]
+        oui_db: Option<String>,
+
+        #[arg(long, help = "Look up mDNS/NetBIOS names of responsive hosts (always on with --lan)")]
+        names: bool,
+
+        #[arg(long, help = "Query every responsive host for SNMP system info")]
+        snmp: bool,
+
+        #[arg(long, value_name = "COMMUNITY", help = "SNMPv2c community string (default: public, implies --snmp)")]
+        snmp_community: Option<String>,
+
+        #[arg(long, help = "Multicast an SSDP M-SEARCH to find UPnP/smart-home devices")]
+        ssdp: bool,
+
+        #[arg(long, value_name = "N", help = "Retry unanswered liveness and port probes N times (default: 1)")]
+        retries: Option<u32>,
+
+        #[arg(long, conflicts_with = "json_stream", help = "Print the final result as JSON on stdout")]
+        json: bool,
+
+        #[arg(long, help = "Print newline-delimited JSON events as hosts are found")]
+        json_stream: bool,
+
+        #[arg(long, help = "List every device previous scans have found instead of scanning")]
+        inventory: bool,
+
+        #[arg(long, conflicts_with = "sequential", help = "Probe hosts in random order (default for ranges larger than /26)")]
+        randomize: bool,
+
+        #[arg(long, help = "Probe hosts in ascending order")]
+        sequential: bool,
+
+        #[arg(long, conflicts_with = "sequential", help = "Seed for the random host order, for reproducible runs")]
+        seed: Option<u64>,
+
+        #[arg(long, conflicts_with_all = ["ports", "top_ports", "snmp", "snmp_community"], help = "Only check which hosts are alive; skip port scanning")]
+        ping_only: bool,
+
+        #[arg(long, help = "Record the certificate presented on open TLS ports (443, 8443, ...)")]
+        tls_info: bool,
+
+        #[arg(long, help = "Fetch the page title and Server header of open web ports (80, 443, 8080, 8443)")]
+        http_info: bool,
+
+        #[arg(long, help = "Identify services on open ports from banners and protocol probes")]
+        services: bool,
+
+        #[arg(long, value_name = "NAME", help = "Send probes out of this interface (with --lan, scan its subnet)")]
+        interface: Option<String>,
+
+        #[arg(long, value_name = "ADDR", help = "Send probes from this local IPv4 address")]
+        source_ip: Option<std::net::Ipv4Addr>,
+
+        #[arg(long, value_name = "NAME", help = "Start from a named profile (quick, default, thorough or one from ~/.config/netweaver/config.toml)")]
+        profile: Option<String>,
     },
 
     #[command(about = "Trace route to target with advanced analytics")]
     Trace {
-        #[arg(short, long, help = "Target hostname or IP")]
-        target: String,
+        #[arg(short, long, required = true, value_delimiter = ',',
+              help = "Target hostname or IP; several (comma-separated or repeated) are traced at once and compared")]
+        target: Vec<String>,
 
         #[arg(short, long, default_value = "30", help = "Maximum TTL hops")]
         max_hops: u8,
@@ -54,10 +159,160 @@ pub enum Commands {
         #[arg(short, long, default_value = "3", help = "Number of probes per hop")]
         probes: u8,
 
-        #[arg(long, help = "Show historical route data")]
+        #[arg(long, value_name = "N", default_value = "5", help = "Give up after N consecutive hops with no reply (0 never gives up)")]
+        max_unresponsive: u8,
+
+        #[arg(long, value_enum, default_value_t = diagnostics::ProbeProtocol::Icmp, help = "Probe with ICMP echo, UDP to high ports or TCP SYNs")]
+        protocol: diagnostics::ProbeProtocol,
+
+        #[arg(long, default_value = "80", help = "Destination port for TCP probes")]
+        port: u16,
+
+        #[arg(long, help = "Keep probing every hop and show live per-hop statistics (mtr-style); 'q' quits")]
+        continuous: bool,
+
+        #[arg(long, value_name = "N", conflicts_with = "continuous", help = "Run N continuous-mode cycles and print the statistics once")]
+        report_cycles: Option<u32>,
+
+        #[arg(long, value_name = "N", default_value = "1", conflicts_with_all = ["continuous", "report_cycles"],
+              value_parser = clap::value_parser!(u8).range(1..=diagnostics::flow::MAX_PATHS as i64),
+              help = "Trace N flows to find the distinct paths of ECMP load balancing")]
+        paths: u8,
+
+        #[arg(long, help = "Look up each hop's origin AS (Team Cymru DNS)")]
+        asn: bool,
+
+        #[arg(long, value_name = "PATH", help = "GeoLite2 Country/City database to look up each hop's country in")]
+        geoip: Option<PathBuf>,
+
+        #[arg(long, help = "Compare with earlier traces to this target and report route changes")]
         history: bool,
 
-        #[arg(short, long, help = "Export trace data")]
+        #[arg(long, value_name = "N", default_value_t = diagnostics::history::DEFAULT_LIMIT, help = "Traces kept per target in the trace history (0 keeps none)")]
+        history_limit: usize,
+
+        #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-",
+              help = "Write each hop (each cycle with --continuous/--report-cycles) as a JSON line to FILE or stdout, instead of the normal output")]
+        stream_json: Option<String>,
+
+        #[arg(short, long, help = "Export trace data (.json, .yaml, or .html for a route map)")]
+        output: Option<String>,
+    },
+
+    #[command(about = "Ping a host continuously and summarize latency and loss")]
+    Ping {
+        #[arg(short, long, help = "Target hostname or IP")]
+        target: String,
+
+        #[arg(short, long, help = "Stop after N pings (default: until Ctrl+C)")]
+        count: Option<u32>,
+
+        #[arg(short, long, default_value = "1s", help = "Time between pings (e.g. 200ms, 1s)")]
+        interval: String,
+
+        #[arg(long, value_name = "MS", default_value = "1000", help = "How long to wait for each reply")]
+        timeout: u64,
+
+        #[arg(long, default_value = "80", help = "Port for TCP-connect pings, used when ICMP needs privileges we don't have")]
+        port: u16,
+
+        #[arg(short, long, help = "Export ping results (JSON/YAML)")]
+        output: Option<String>,
+    },
+
+    #[command(about = "Query DNS records with timing, DNSSEC status and resolver comparison")]
+    Dns {
+        #[arg(help = "Domain name to look up")]
+        name: String,
+
+        #[arg(short = 't', long = "type", value_enum, ignore_case = true,
+              help = "Record type to query, repeatable (default: A, AAAA, MX, TXT, NS and CNAME)")]
+        record_type: Vec<diagnostics::dns::RecordKind>,
+
+        #[arg(short, long, conflicts_with = "all_resolvers", help = "Resolver to ask instead of the system one (IP[:port])")]
+        resolver: Option<String>,
+
+        #[arg(long, help = "Compare the system resolver with Google, Cloudflare and Quad9")]
+        all_resolvers: bool,
+
+        #[arg(short, long, help = "Export DNS results (JSON/YAML)")]
+        output: Option<String>,
+    },
+
+    #[command(about = "Measure TCP throughput between two NetWeaver instances")]
+    Throughput {
+        #[arg(short, long, conflicts_with = "client", required_unless_present = "client", help = "Listen for throughput clients")]
+        server: bool,
+
+        #[arg(short, long, value_name = "HOST", help = "Run a test against a NetWeaver server")]
+        client: Option<String>,
+
+        #[arg(short, long, default_value_t = diagnostics::throughput::DEFAULT_PORT, help = "TCP port of the server")]
+        port: u16,
+
+        #[arg(short = 't', long, default_value = "10s", help = "How long to transfer (e.g. 10s, 1m)")]
+        duration: String,
+
+        #[arg(short = 'P', long, default_value = "1", value_parser = clap::value_parser!(u8).range(1..=128),
+              help = "Parallel TCP streams")]
+        parallel: u8,
+
+        #[arg(short = 'R', long, help = "Download from the server instead of uploading to it")]
+        reverse: bool,
+
+        #[arg(short, long, help = "Export throughput results (JSON/YAML)")]
+        output: Option<String>,
+    },
+
+    #[command(about = "Time the DNS, connect, TLS, first-byte and download phases of HTTP requests")]
+    Http {
+        #[arg(help = "URL to request (http:// or https://)")]
+        url: String,
+
+        #[arg(short, long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..),
+              help = "Number of requests")]
+        count: u32,
+
+        #[arg(short = 'X', long, default_value = "GET", help = "Request method (GET, HEAD, POST, ...)")]
+        method: String,
+
+        #[arg(short, long, help = "Export HTTP timing results (JSON/YAML)")]
+        output: Option<String>,
+    },
+
+    #[command(about = "Inspect a server's TLS handshake, certificate chain and accepted versions")]
+    Tls {
+        #[arg(help = "Host or host:port to connect to")]
+        target: String,
+
+        #[arg(short, long, default_value = "443", help = "Port when the target doesn't give one")]
+        port: u16,
+
+        #[arg(short, long, help = "Export the TLS report (JSON/YAML)")]
+        output: Option<String>,
+    },
+
+    #[command(about = "List TCP/UDP sockets and the processes that own them")]
+    Connections {
+        #[arg(long, value_enum, ignore_case = true, help = "Only sockets in this state (e.g. established, listen)")]
+        state: Option<diagnostics::connections::SocketState>,
+
+        #[arg(short, long, help = "Only sockets with this local or remote port")]
+        port: Option<u16>,
+
+        #[arg(long, value_name = "NAME|PID", help = "Only sockets owned by this process")]
+        process: Option<String>,
+
+        #[arg(long, conflicts_with = "udp", help = "TCP sockets only")]
+        tcp: bool,
+
+        #[arg(long, help = "UDP sockets only")]
+        udp: bool,
+
+        #[arg(long, help = "Print the list as JSON on stdout")]
+        json: bool,
+
+        #[arg(short, long, help = "Export the list (JSON/YAML)")]
         output: Option<String>,
     },
 
@@ -83,54 +338,167 @@ pub enum Commands {
     },
 
     #[command(about = "Monitor network in real-time")]
+    #[command(group(ArgGroup::new("summarized").args(["duration", "compare"])))]
     Monitor {
         #[arg(long, help = "Enable real-time TUI dashboard")]
         realtime: bool,
 
-        #[arg(short, long, help = "Interface to monitor")]
+        #[arg(short, long, help = "Interface to monitor, or several to compare (eth0,wg0)")]
         interface: Option<String>,
 
         #[arg(long, help = "Run as background daemon")]
         daemon: bool,
 
-        #[arg(short, long, help = "Log file path")]
+        #[arg(long, requires = "daemon", help = "Keep the daemon in the foreground instead of detaching")]
+        foreground: bool,
+
+        #[arg(long, help = "How often the daemon samples the counters (default 10s), or the dashboard refreshes (milliseconds unless suffixed, default 1s, 100ms to 60s)")]
+        interval: Option<String>,
+
+        #[arg(short, long, help = "Append a record per interface and interval to this file (CSV for .csv, JSON Lines otherwise)")]
         log: Option<String>,
 
-        #[arg(long, help = "Monitor specific protocol (tcp/udp/icmp/all)")]
+        #[arg(long, value_parser = ["tcp", "udp", "icmp", "all"], help = "Monitor specific protocol (tcp/udp/icmp/all)")]
         protocol: Option<String>,
+
+        #[arg(long, value_name = "RULE", help = "Alert when a condition holds, e.g. 'rx > 50MB/s for 30s', 'errors', 'down' (repeatable)")]
+        alert: Vec<String>,
+
+        #[arg(long, value_name = "URL", help = "POST each alert as JSON to this URL")]
+        webhook: Option<String>,
+
+        #[arg(long, value_name = "COMMAND", help = "Run this shell command for each alert, with NETWEAVER_ALERT_* set")]
+        alert_command: Option<String>,
+
+        #[arg(long, value_name = "DURATION", help = "Quiet time after an alert is sent before its rule may notify again [default: 5m]")]
+        alert_cooldown: Option<String>,
+
+        #[arg(long, value_name = "ADDR:PORT", help = "Serve Prometheus metrics at http://ADDR:PORT/metrics")]
+        metrics_listen: Option<std::net::SocketAddr>,
+
+        #[arg(long, value_name = "ADDR:PORT",
+              help = "Stream samples, probes, alerts and interface events as JSON lines to TCP, HTTP or WebSocket clients connecting here")]
+        stream_listen: Option<std::net::SocketAddr>,
+
+        #[arg(long, value_name = "FILE",
+              help = "Keep the last few seconds of packets and save them, with the next 10 seconds, to a timestamped pcap named after FILE when an alert fires or probe latency spikes (requires root)")]
+        capture_on_anomaly: Option<String>,
+
+        #[arg(long, alias = "ping", value_name = "TARGET", value_delimiter = ',',
+              help = "Ping these hosts in the background for the dashboard, log, alerts and metrics ('gateway' for the default gateway; repeatable or comma-separated) [default in the dashboard: gateway,1.1.1.1]")]
+        probe: Vec<String>,
+
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10",
+              help = "Capture on the monitored interface and rank the N busiest remote hosts (needs root) [default: 10]")]
+        top_talkers: Option<usize>,
+
+        #[arg(long, value_name = "TIME", conflicts_with = "daemon", help = "Stop after this long (e.g. 60s, 5m) and print a summary of the run")]
+        duration: Option<String>,
+
+        #[arg(short, long, requires = "summarized", help = "Write the --duration summary or the --compare comparison to this file as JSON")]
+        output: Option<String>,
+
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["realtime", "daemon", "duration", "top_talkers"],
+              help = "Save the snapshot's interface and protocol counters to this file as JSON, for --compare")]
+        save: Option<String>,
+
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["realtime", "daemon", "duration", "top_talkers"],
+              help = "Compare a fresh snapshot with one saved by --save and show what each counter moved")]
+        compare: Option<String>,
     },
 
     #[command(about = "Generate network analysis report")]
+    #[command(group(ArgGroup::new("window").args(["history", "graphs"]).multiple(true)))]
     Report {
         #[arg(short, long, help = "Export report to file")]
         export: String,
 
-        #[arg(short, long, help = "Report format (json/yaml/html)")]
+        #[arg(short, long, help = "Report format (json/yaml/html/pdf)")]
         format: Option<String>,
 
-        #[arg(long, help = "Include historical data")]
+        #[arg(long, help = "Include hourly/daily traffic recorded by the monitor")]
         history: bool,
 
-        #[arg(long, help = "Include graphs and visualizations")]
+        #[arg(long, requires = "window", help = "Start of the history window: RFC 3339 time, YYYY-MM-DD or age like 24h/7d (default 24h)")]
+        since: Option<String>,
+
+        #[arg(long, requires = "window", help = "End of the history window (default now)")]
+        until: Option<String>,
+
+        #[arg(long, help = "Chart the monitoring history in an HTML report (bandwidth, packets/s, errors)")]
         graphs: bool,
+
+        #[arg(long, value_name = "FILE", help = "Scan result to include (from 'scan --output'; default: the latest scan)")]
+        scan: Option<String>,
+
+        #[arg(long, value_name = "FILE", help = "Security audit to include (from 'security --output'; default: the latest audit)")]
+        security: Option<String>,
     },
 
     #[command(about = "Deep packet inspection and diagnostics")]
     Inspect {
-        #[arg(short, long, help = "Interface to capture from")]
+        #[arg(short, long, conflicts_with = "read", help = "Interface to capture from")]
         interface: Option<String>,
 
+        #[arg(short, long, value_name = "FILE", help = "Analyze a pcap/pcapng file instead of capturing (no root needed)")]
+        read: Option<PathBuf>,
+
         #[arg(short, long, help = "Capture filter (BPF syntax)")]
         filter: Option<String>,
 
+        #[arg(long, help = "Only packets to or from this IP")]
+        filter_host: Option<IpAddr>,
+
+        #[arg(long, help = "Only packets to or from this port")]
+        filter_port: Option<u16>,
+
+        #[arg(long, value_parser = ["tcp", "udp", "icmp", "icmp6", "arp", "ip", "ip6"], help = "Only packets of this protocol")]
+        filter_proto: Option<String>,
+
         #[arg(short, long, help = "Number of packets to capture")]
         count: Option<usize>,
 
-        #[arg(short, long, help = "Save capture to file (.pcap)")]
+        #[arg(short, long, help = "Save the packets to a .pcap file, or the analysis to a .json file")]
         output: Option<String>,
 
+        #[arg(long, value_name = "MB", requires = "output", value_parser = clap::value_parser!(u64).range(1..),
+              help = "Start a new capture file (FILE.1, FILE.2, ...) when the current one reaches this size")]
+        rotate_size: Option<u64>,
+
+        #[arg(long, value_name = "INTERVAL", requires = "output",
+              help = "Start a new capture file after this much time (e.g. 15m, 1h)")]
+        rotate_interval: Option<String>,
+
+        #[arg(long, value_name = "N", requires = "output", value_parser = clap::value_parser!(u64).range(1..),
+              help = "Keep at most N capture files, overwriting the oldest (ring buffer)")]
+        rotate_count: Option<u64>,
+
+        #[arg(long, value_name = "INTERVAL", default_value = "5s", conflicts_with = "read",
+              help = "Print packets, rate and kernel drops at this interval during a live capture (0 to turn off)")]
+        stats_interval: String,
+
+        #[arg(long, value_name = "KiB", conflicts_with = "read", value_parser = clap::value_parser!(u64).range(1..),
+              help = "Kernel capture buffer size; raise it if packets are dropped")]
+        buffer_size: Option<u64>,
+
         #[arg(long, help = "Analyze captured packets")]
         analyze: bool,
+
+        #[arg(long, value_name = "INTERVAL", requires = "analyze", conflicts_with = "read",
+              help = "Print the analysis so far at this interval during a live capture (e.g. 30s, 5m)")]
+        report_interval: Option<String>,
+
+        #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-",
+              help = "Write each packet (and each --report-interval analysis) as a JSON line to FILE or stdout, instead of the normal output")]
+        stream_json: Option<String>,
+
+        #[arg(short = 'X', long, conflicts_with = "stream_json",
+              help = "Print each packet's headers field by field and a hex/ASCII dump of its payload")]
+        decode: bool,
+
+        #[arg(long, value_name = "BYTES", default_value_t = diagnostics::dissect::DEFAULT_PAYLOAD_BYTES, requires = "decode",
+              help = "Payload bytes to dump per packet with --decode")]
+        decode_bytes: usize,
     },
 
     #[command(about = "Security auditing and monitoring")]
@@ -149,35 +517,300 @@ pub enum Commands {
 
         #[arg(long, help = "Run all security checks")]
         all: bool,
+
+        #[arg(short, long, value_name = "FILE", help = "Save the findings to a JSON (or .yaml) file, e.g. for 'report --security'")]
+        output: Option<String>,
+
+        #[arg(long, value_name = "CIDR", value_delimiter = ',',
+              help = "The VPN's IPv4 or IPv6 exit ranges; a public IP outside them fails the VPN test (repeatable or comma-separated)")]
+        expected_exit: Vec<String>,
+
+        #[arg(long, value_name = "URL", value_delimiter = ',',
+              help = "Services that answer with the public IP as plain text, for the VPN test [default: https://api.ipify.org,https://ifconfig.co/ip]")]
+        ip_source: Vec<String>,
+
+        #[arg(long, value_name = "HOST:PORT", help = "STUN server the VPN test also asks for the public IP [default: stun.l.google.com:19302]")]
+        stun_server: Option<String>,
+    },
+
+    #[command(about = "Wake a sleeping device with a Wake-on-LAN magic packet")]
+    Wake {
+        #[arg(help = "MAC address, or an IP to look up in the device inventory")]
+        target: String,
+
+        #[arg(long, value_name = "FILE", help = "Look the IP up in a saved scan file instead of the inventory")]
+        from: Option<String>,
+
+        #[arg(long, help = "Broadcast address (default: the local subnet's)")]
+        broadcast: Option<std::net::Ipv4Addr>,
+
+        #[arg(long, default_value = "9", help = "UDP port to send to")]
+        port: u16,
+
+        #[arg(long, default_value = "3", help = "Number of packets to send")]
+        count: u32,
     },
 }
 
-pub async fn run() -> Result<()> {
-    let cli = Cli::parse();
+pub async fn run(cli: Cli) -> Result<()> {
+    output::init_color();
 
     match cli.command {
-        Commands::Scan { lan, target, threads, ports, output, topology } => {
-            scanner::run_scan(lan, target, threads, ports, output, topology).await?;
+        Commands::Scan {
+            lan, target, target_file, threads, ports, top_ports, output, topology, topology_out, topology_limit, exclude, exclude_file, diff,
+            watch, timeout, max_timeout, max_rate, checkpoint, checkpoint_every, resume, oui_db, names, snmp, snmp_community, ssdp, retries, json, json_stream, inventory,
+            randomize, sequential, seed, ping_only, tls_info, http_info, services, interface, source_ip, profile,
+        } => {
+            if json_stream {
+                output::set_format(output::Format::JsonStream);
+            } else if json {
+                output::set_format(output::Format::Json);
+            }
+            if inventory {
+                return scanner::show_inventory();
+            }
+            let profile = match &profile {
+                Some(name) => scanner::profile::load(name, &scanner::profile::default_path()?)?,
+                None => scanner::profile::Profile::default(),
+            };
+            // --ports and --top-ports pick the port set together, so either
+            // one replaces the profile's whole selection
+            let (ports, top_ports) = match (ports, top_ports) {
+                (None, None) if !ping_only => (profile.ports, profile.top_ports),
+                explicit => explicit,
+            };
+            let defaults = scanner::ScanOptions::default();
+            scanner::run_scan(scanner::ScanOptions {
+                lan,
+                target,
+                target_file,
+                threads: threads.or(profile.threads).unwrap_or(defaults.threads),
+                ports,
+                top_ports,
+                exclude,
+                exclude_file,
+                timeout_ms: timeout.or(profile.timeout),
+                max_timeout_ms: max_timeout.or(profile.max_timeout).unwrap_or(defaults.max_timeout_ms),
+                max_rate: max_rate.or(profile.max_rate),
+                checkpoint,
+                checkpoint_every,
+                resume,
+                oui_db,
+                names: names || profile.names.unwrap_or(false),
+                snmp: snmp || profile.snmp.unwrap_or(false),
+                snmp_community,
+                ssdp: ssdp || profile.ssdp.unwrap_or(false),
+                retries: retries.or(profile.retries).unwrap_or(defaults.retries),
+                randomize: if randomize { Some(true) } else if sequential { Some(false) } else { None },
+                seed,
+                ping_only,
+                tls_info: tls_info || profile.tls_info.unwrap_or(false),
+                http_info: http_info || profile.http_info.unwrap_or(false),
+                services: services || profile.services.unwrap_or(false),
+                interface,
+                source_ip,
+            }, scanner::ReportOptions {
+                output,
+                topology,
+                topology_out,
+                topology_limit,
+                diff,
+                watch,
+            }).await?;
+        }
+        Commands::Trace {
+            target, max_hops, probes, max_unresponsive, protocol, port, continuous, report_cycles, paths, asn, geoip,
+            history, history_limit, stream_json, output,
+        } => {
+            if stream_json.is_some() {
+                output::set_format(output::Format::JsonStream);
+            }
+            let opts = diagnostics::TraceOptions {
+                max_hops, probes, max_unresponsive, protocol, port, continuous, report_cycles, asn, geoip, stream_json,
+                paths,
+            };
+            diagnostics::run_trace(target, opts, history, history_limit, output).await?;
+        }
+        Commands::Ping { target, count, interval, timeout, port, output } => {
+            let opts = diagnostics::ping::PingOptions {
+                count,
+                interval: crate::utils::parse_duration(&interval)?,
+                timeout: std::time::Duration::from_millis(timeout),
+                port,
+            };
+            diagnostics::ping::run_ping(target, opts, output).await?;
+        }
+        Commands::Dns { name, record_type, resolver, all_resolvers, output } => {
+            diagnostics::dns::run_dns(name, record_type, resolver, all_resolvers, output).await?;
+        }
+        Commands::Throughput { server, client, port, duration, parallel, reverse, output } => {
+            use diagnostics::throughput::{self, Direction, ThroughputOptions};
+            match client {
+                Some(host) if !server => {
+                    let opts = ThroughputOptions {
+                        port,
+                        duration: crate::utils::parse_duration(&duration)?,
+                        parallel,
+                        direction: if reverse { Direction::Download } else { Direction::Upload },
+                    };
+                    throughput::run_client(host, opts, output).await?;
+                }
+                _ => throughput::run_server(port).await?,
+            }
+        }
+        Commands::Http { url, count, method, output } => {
+            diagnostics::http::run_http(url, count, method, output).await?;
+        }
+        Commands::Tls { target, port, output } => {
+            diagnostics::tls::run_tls(target, port, output).await?;
         }
-        Commands::Trace { target, max_hops, probes, history, output } => {
-            diagnostics::run_trace(target, max_hops, probes, history, output).await?;
+        Commands::Connections { state, port, process, tcp, udp, json, output } => {
+            if json {
+                output::set_format(output::Format::Json);
+            }
+            let filter = diagnostics::connections::ConnectionFilter { state, port, process, tcp_only: tcp, udp_only: udp };
+            diagnostics::connections::run_connections(filter, json, output)?;
         }
         Commands::Optimize { turbo, dns, mtu, tcp, all, dry_run } => {
             optimizer::run_optimize(turbo, dns, mtu, tcp, all, dry_run).await?;
         }
-        Commands::Monitor { realtime, interface, daemon, log, protocol } => {
-            monitor::run_monitor(realtime, interface, daemon, log, protocol).await?;
+        Commands::Monitor {
+            realtime, interface, daemon, foreground, interval, log, protocol, alert, webhook, alert_command, alert_cooldown,
+            metrics_listen, stream_listen, probe, top_talkers, duration, output, capture_on_anomaly, save, compare,
+        } => {
+            if !alert.is_empty() && !realtime && !daemon {
+                anyhow::bail!("--alert rules are checked in --realtime and --daemon mode");
+            }
+            if metrics_listen.is_some() && !realtime && !daemon {
+                anyhow::bail!("--metrics-listen serves metrics in --realtime and --daemon mode");
+            }
+            if stream_listen.is_some() && !realtime && !daemon {
+                anyhow::bail!("--stream-listen streams events in --realtime and --daemon mode");
+            }
+            if capture_on_anomaly.is_some() && !realtime && !daemon {
+                anyhow::bail!("--capture-on-anomaly captures around alerts and latency spikes in --realtime and --daemon mode");
+            }
+            if !probe.is_empty() && !realtime && !daemon && duration.is_none() {
+                anyhow::bail!("--probe measures latency in --realtime, --daemon and --duration mode");
+            }
+            if duration.is_some() && top_talkers.is_some() && !realtime {
+                anyhow::bail!("--top-talkers without --realtime runs until Ctrl+C; add --realtime to use --duration");
+            }
+            let config = monitor::alerts::load_config(&scanner::profile::default_path()?)?;
+            let alerts = monitor::alerts::settings(config, alert, webhook, alert_command, alert_cooldown)?;
+            monitor::run_monitor(monitor::MonitorOptions {
+                realtime,
+                interface,
+                daemon,
+                foreground,
+                interval: monitor_interval(interval.as_deref(), realtime, daemon)?,
+                log,
+                protocol,
+                alerts,
+                metrics: metrics_listen.map(|listen| monitor::metrics::MetricsOptions { listen, probes: probe.clone() }),
+                probes: probe,
+                top_talkers,
+                duration: duration.as_deref().map(crate::utils::parse_duration).transpose()?,
+                output,
+                stream: stream_listen,
+                capture_on_anomaly,
+                save,
+                compare,
+            }).await?;
         }
-        Commands::Report { export, format, history, graphs } => {
-            monitor::generate_report(export, format, history, graphs).await?;
+        Commands::Report { export, format, history, since, until, graphs, scan, security } => {
+            monitor::generate_report(monitor::ReportOptions {
+                export,
+                format,
+                history,
+                since,
+                until,
+                graphs,
+                scan,
+                security,
+            }).await?;
         }
-        Commands::Inspect { interface, filter, count, output, analyze } => {
-            diagnostics::run_inspect(interface, filter, count, output, analyze).await?;
+        Commands::Inspect {
+            interface, read, filter, filter_host, filter_port, filter_proto, count, output, rotate_size, rotate_interval,
+            rotate_count, stats_interval, buffer_size, analyze, report_interval, stream_json, decode, decode_bytes,
+        } => {
+            let filter = diagnostics::filter::compose(
+                filter.as_deref(),
+                filter_host.map(|ip| ip.to_string()).as_deref(),
+                filter_port,
+                filter_proto.as_deref(),
+            );
+            let report_every = report_interval.as_deref().map(crate::utils::parse_duration).transpose()?;
+            let rotation = diagnostics::pcap::Rotation {
+                max_bytes: rotate_size.map(|mb| mb * 1_000_000),
+                interval: rotate_interval.as_deref().map(crate::utils::parse_duration).transpose()?,
+                max_files: rotate_count.map(|n| n as usize),
+            };
+            let stats_every = match stats_interval.as_str() {
+                "0" => None,
+                interval => Some(crate::utils::parse_duration(interval)?),
+            };
+            if stream_json.is_some() {
+                output::set_format(output::Format::JsonStream);
+            }
+            diagnostics::run_inspect(diagnostics::InspectOptions {
+                interface, read, filter, count, output, analyze, report_every, stream_json,
+                decode: decode.then_some(decode_bytes), rotation, stats_every,
+                buffer_size: buffer_size.map(|kib| kib as usize * 1024),
+            }).await?;
         }
-        Commands::Security { arp_detect, vpn_test, port_scan, mitm_detect, all } => {
-            security::run_security_audit(arp_detect, vpn_test, port_scan, mitm_detect, all).await?;
+        Commands::Security { arp_detect, vpn_test, port_scan, mitm_detect, all, output, expected_exit, ip_source, stun_server } => {
+            if (!expected_exit.is_empty() || !ip_source.is_empty() || stun_server.is_some()) && !vpn_test && !all {
+                anyhow::bail!("--expected-exit, --ip-source and --stun-server are for --vpn-test");
+            }
+            let vpn = security::public_ip::VpnOptions {
+                ip_sources: ip_source,
+                stun_server,
+                expected_exit: expected_exit.iter().map(|range| security::public_ip::parse_exit_range(range)).collect::<Result<_>>()?,
+            };
+            security::run_security_audit(arp_detect, vpn_test, port_scan, mitm_detect, all, output, vpn).await?;
+        }
+        Commands::Wake { target, from, broadcast, port, count } => {
+            scanner::wake::run_wake(target, from, broadcast, port, count).await?;
         }
     }
 
     Ok(())
 }
+
+/// `monitor --interval`: the dashboard's refresh, in milliseconds when bare,
+/// or the daemon's sampling interval, in seconds when bare
+fn monitor_interval(interval: Option<&str>, realtime: bool, daemon: bool) -> Result<Duration> {
+    let dashboard = realtime && !daemon;
+    Ok(match interval {
+        Some(interval) if dashboard => monitor::dashboard::parse_refresh(interval)?,
+        Some(interval) => crate::utils::parse_duration(interval)?,
+        None if dashboard => monitor::dashboard::DEFAULT_REFRESH,
+        None => monitor::daemon::DEFAULT_INTERVAL,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_monitor_interval() {
+        let cli = Cli::try_parse_from(["netweaver", "monitor", "--realtime", "--interval", "500"]).unwrap();
+        let Commands::Monitor { interval, realtime, daemon, .. } = cli.command else {
+            panic!("not the monitor command");
+        };
+        let refresh = monitor_interval(interval.as_deref(), realtime, daemon).unwrap();
+        assert_eq!(refresh, Duration::from_millis(500));
+        assert!(monitor::dashboard::check_refresh(refresh).is_ok());
+        assert_eq!(monitor_interval(Some("2s"), true, false).unwrap(), Duration::from_secs(2));
+        // The daemon keeps bare numbers as seconds
+        assert_eq!(monitor_interval(Some("30"), false, true).unwrap(), Duration::from_secs(30));
+    }
+}
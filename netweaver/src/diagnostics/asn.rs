@@ -0,0 +1,155 @@
+// Hop annotation: origin AS and country
+// Which network a hop belongs to says more than its address when chasing a
+// routing problem. The origin AS comes from Team Cymru's DNS service (a TXT
+// query for the reversed address under origin.asn.cymru.com, then one under
+// asn.cymru.com for the AS name); countries come from an offline GeoLite2
+// Country or City database when one is given, coordinates from a City one. Private and other
+// non-routable hops are skipped.
+
+use anyhow::{Context, Result};
+use hickory_resolver::TokioAsyncResolver;
+use std::collections::hash_map::{Entry, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+use super::TraceHop;
+
+/// Looks up hops, remembering AS names across them
+pub struct Annotator {
+    resolver: Option<TokioAsyncResolver>,
+    geoip: Option<maxminddb::Reader<Vec<u8>>>,
+    as_names: HashMap<u32, Option<String>>,
+}
+
+impl Annotator {
+    /// None when neither lookup was asked for
+    pub fn new(asn: bool, geoip: Option<&Path>) -> Result<Option<Self>> {
+        if !asn && geoip.is_none() {
+            return Ok(None);
+        }
+        let geoip = geoip
+            .map(|path| {
+                maxminddb::Reader::open_readfile(path)
+                    .with_context(|| format!("Failed to open GeoIP database '{}'", path.display()))
+            })
+            .transpose()?;
+        let resolver = asn.then(|| {
+            TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+                use hickory_resolver::config::*;
+                TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+            })
+        });
+        Ok(Some(Self { resolver, geoip, as_names: HashMap::new() }))
+    }
+
+    /// Fill in `asn`, `as_name` and `country` of a hop that answered
+    pub async fn annotate(&mut self, hop: &mut TraceHop) {
+        let Some(ip) = hop.ip.filter(|&ip| is_routable(ip)) else {
+            return;
+        };
+        hop.country = self.country(ip);
+        hop.location = self.location(ip);
+
+        let Some(resolver) = &self.resolver else {
+            return;
+        };
+        let Some(asn) = txt(resolver, &origin_query(ip)).await.and_then(|txt| parse_origin(&txt)) else {
+            return;
+        };
+        hop.asn = Some(asn);
+        hop.as_name = match self.as_names.entry(asn) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let name = txt(resolver, &format!("AS{}.asn.cymru.com.", asn)).await;
+                entry.insert(name.and_then(|txt| parse_as_name(&txt))).clone()
+            }
+        };
+    }
+
+    /// ISO code of the country `ip` is in, from the GeoIP database
+    pub fn country(&self, ip: Ipv4Addr) -> Option<String> {
+        let record: maxminddb::geoip2::Country = self.geoip.as_ref()?.lookup(IpAddr::V4(ip)).ok()?;
+        record.country?.iso_code.map(str::to_string)
+    }
+
+    /// Latitude and longitude of `ip`; only City databases have them
+    pub fn location(&self, ip: Ipv4Addr) -> Option<[f64; 2]> {
+        let record: maxminddb::geoip2::City = self.geoip.as_ref()?.lookup(IpAddr::V4(ip)).ok()?;
+        let location = record.location?;
+        Some([location.latitude?, location.longitude?])
+    }
+}
+
+/// First TXT record at `name`, its strings joined
+async fn txt(resolver: &TokioAsyncResolver, name: &str) -> Option<String> {
+    let lookup = resolver.txt_lookup(name).await.ok()?;
+    let record = lookup.iter().next()?;
+    Some(record.txt_data().iter().map(|part| String::from_utf8_lossy(part)).collect())
+}
+
+/// Whether `ip` could belong to a public AS at all
+fn is_routable(ip: Ipv4Addr) -> bool {
+    // 100.64.0.0/10 is carrier-grade NAT space
+    let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xC0 == 64;
+    !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || shared)
+}
+
+/// `d.c.b.a.origin.asn.cymru.com.` for `a.b.c.d`
+pub fn origin_query(ip: Ipv4Addr) -> String {
+    let [a, b, c, d] = ip.octets();
+    format!("{}.{}.{}.{}.origin.asn.cymru.com.", d, c, b, a)
+}
+
+/// The origin AS in an answer like `15169 | 8.8.8.0/24 | US | arin | 2000-03-30`;
+/// a prefix announced by several ASes lists them space-separated, and the
+/// first is taken
+pub fn parse_origin(txt: &str) -> Option<u32> {
+    txt.split('|').next()?.split_whitespace().next()?.parse().ok()
+}
+
+/// The AS name in an answer like `15169 | US | arin | 2000-03-30 | GOOGLE - Google LLC, US`,
+/// without the trailing country code
+pub fn parse_as_name(txt: &str) -> Option<String> {
+    let name = txt.split('|').nth(4)?.trim();
+    let name = match name.rsplit_once(", ") {
+        Some((name, cc)) if cc.len() == 2 && cc.chars().all(|c| c.is_ascii_uppercase()) => name,
+        _ => name,
+    };
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// `AS15169 GOOGLE - Google LLC`, or just the number when the name is unknown
+pub fn as_label(hop: &TraceHop) -> Option<String> {
+    let asn = hop.asn?;
+    Some(match &hop.as_name {
+        Some(name) => format!("AS{} {}", asn, name),
+        None => format!("AS{}", asn),
+    })
+}
+
+/// The networks the path runs through, in order, each listed once per stretch
+pub fn as_path(hops: &[TraceHop]) -> Vec<String> {
+    let mut path: Vec<(u32, String)> = Vec::new();
+    for hop in hops {
+        if let (Some(asn), Some(label)) = (hop.asn, as_label(hop)) {
+            if path.last().map(|(last, _)| *last) != Some(asn) {
+                path.push((asn, label));
+            }
+        }
+    }
+    path.into_iter().map(|(_, label)| label).collect()
+}
+
+/// Hops located outside both the country the path starts in and the one it
+/// ends in, e.g. traffic between two German hosts detouring through the US
+pub fn unexpected_country_hops(hops: &[TraceHop]) -> Vec<&TraceHop> {
+    let located: Vec<&TraceHop> = hops.iter().filter(|hop| hop.country.is_some()).collect();
+    let (Some(first), Some(last)) = (located.first(), located.last()) else {
+        return Vec::new();
+    };
+    located
+        .iter()
+        .filter(|hop| hop.country != first.country && hop.country != last.country)
+        .copied()
+        .collect()
+}
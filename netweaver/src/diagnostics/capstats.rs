@@ -0,0 +1,153 @@
+// Capture statistics
+// How a live capture is keeping up: packets and bytes so far, the current
+// rate, and what the kernel dropped because we read too slowly. pnet keeps
+// its AF_PACKET socket to itself, so the socket is found among our file
+// descriptors right after the channel opens, as the new descriptor listed in
+// /proc/net/packet. Through it the receive buffer is sized (`--buffer-size`)
+// and the kernel's delivered/dropped counters are read. Other platforms
+// report no drops.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Running totals of a live capture, reported along with its analysis
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CaptureStats {
+    /// Packets the kernel queued for us, None where it won't say
+    pub kernel_received: Option<u64>,
+    /// Packets the kernel threw away because the buffer was full
+    pub kernel_dropped: Option<u64>,
+    /// The socket receive buffer actually granted
+    pub buffer_bytes: Option<usize>,
+    pub peak_packets_per_sec: f64,
+}
+
+impl CaptureStats {
+    /// Share of the packets the kernel saw that it dropped, in percent
+    pub fn drop_rate(&self) -> Option<f64> {
+        let dropped = self.kernel_dropped?;
+        let seen = self.kernel_received? + dropped;
+        Some(if seen == 0 { 0.0 } else { dropped as f64 * 100.0 / seen as f64 })
+    }
+}
+
+/// Packets delivered and dropped since the previous reading
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KernelCounts {
+    pub received: u64,
+    pub dropped: u64,
+}
+
+/// Socket inodes listed in `/proc/net/packet`, the last column of each row
+pub fn parse_proc_packet(contents: &str) -> HashSet<u64> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().last()?.parse().ok())
+        .collect()
+}
+
+/// The packet sockets this process holds, by descriptor and inode
+///
+/// Inodes, unlike descriptor numbers, aren't reused between two looks.
+pub fn packet_sockets() -> Vec<(i32, u64)> {
+    let Ok(contents) = std::fs::read_to_string("/proc/net/packet") else {
+        return Vec::new();
+    };
+    let inodes = parse_proc_packet(&contents);
+    let Ok(entries) = std::fs::read_dir("/proc/self/fd") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            let target = std::fs::read_link(entry.path()).ok()?;
+            let inode = target.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()?;
+            inodes.contains(&inode).then_some((fd, inode))
+        })
+        .collect()
+}
+
+/// The capture socket behind a pnet channel
+#[derive(Debug, Clone, Copy)]
+pub struct PacketSocket {
+    fd: i32,
+}
+
+impl PacketSocket {
+    /// The one packet socket opened since `before` was taken from
+    /// `packet_sockets`, if exactly one was
+    pub fn find_new(before: &[(i32, u64)]) -> Option<Self> {
+        let mut new = packet_sockets().into_iter().filter(|(_, inode)| !before.iter().any(|(_, old)| old == inode));
+        match (new.next(), new.next()) {
+            (Some((fd, _)), None) => Some(Self { fd }),
+            _ => None,
+        }
+    }
+
+    /// Ask for a receive buffer of `bytes`, past the sysctl limit when we are
+    /// root; the size the kernel granted
+    #[cfg(target_os = "linux")]
+    pub fn set_buffer_size(&self, bytes: usize) -> Option<usize> {
+        // asm-generic's SO_RCVBUFFORCE, which libc only exports for some targets
+        const SO_RCVBUFFORCE: libc::c_int = 33;
+        let value = bytes.min(i32::MAX as usize) as libc::c_int;
+        let set = |option| unsafe {
+            let size = std::mem::size_of_val(&value) as libc::socklen_t;
+            libc::setsockopt(self.fd, libc::SOL_SOCKET, option, (&value as *const libc::c_int).cast(), size) == 0
+        };
+        if !set(SO_RCVBUFFORCE) && !set(libc::SO_RCVBUF) {
+            return None;
+        }
+        self.buffer_size()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_buffer_size(&self, _bytes: usize) -> Option<usize> {
+        None
+    }
+
+    /// The receive buffer size, as asked for; Linux reports double that,
+    /// the other half being its bookkeeping
+    #[cfg(target_os = "linux")]
+    pub fn buffer_size(&self) -> Option<usize> {
+        let mut value: libc::c_int = 0;
+        let mut size = std::mem::size_of_val(&value) as libc::socklen_t;
+        let ok = unsafe {
+            libc::getsockopt(self.fd, libc::SOL_SOCKET, libc::SO_RCVBUF, (&mut value as *mut libc::c_int).cast(), &mut size)
+        };
+        (ok == 0).then_some(value as usize / 2)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn buffer_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// The kernel's counts since the last call; reading them resets them
+    #[cfg(target_os = "linux")]
+    pub fn take_counts(&self) -> Option<KernelCounts> {
+        const PACKET_STATISTICS: libc::c_int = 6;
+        #[repr(C)]
+        struct TpacketStats {
+            packets: u32,
+            drops: u32,
+        }
+        let mut stats = TpacketStats { packets: 0, drops: 0 };
+        let mut size = std::mem::size_of::<TpacketStats>() as libc::socklen_t;
+        let ok = unsafe {
+            libc::getsockopt(self.fd, libc::SOL_PACKET, PACKET_STATISTICS, (&mut stats as *mut TpacketStats).cast(), &mut size)
+        };
+        // tp_packets counts the dropped packets too
+        (ok == 0).then(|| KernelCounts {
+            received: u64::from(stats.packets.saturating_sub(stats.drops)),
+            dropped: u64::from(stats.drops),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn take_counts(&self) -> Option<KernelCounts> {
+        None
+    }
+}
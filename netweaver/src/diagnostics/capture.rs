@@ -0,0 +1,665 @@
+// Packet capture
+// Frames come off a pnet datalink channel (AF_PACKET on Linux, BPF devices
+// on macOS/BSD), so no libpcap is needed at runtime, or out of a capture
+// file. Each frame is decoded down to its transport header and printed as
+// one tcpdump-style line, and optionally saved to a pcap file; the loop runs
+// on a blocking thread and polls a stop flag between reads so Ctrl+C ends it
+// cleanly.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use pnet_datalink::{Channel, NetworkInterface};
+use pnet_packet::arp::{ArpOperations, ArpPacket};
+use pnet_packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
+use pnet_packet::icmp::{IcmpPacket, IcmpTypes};
+use pnet_packet::icmpv6::Icmpv6Packet;
+use pnet_packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet_packet::ipv4::Ipv4Packet;
+use pnet_packet::ipv6::Ipv6Packet;
+use pnet_packet::tcp::TcpPacket;
+use pnet_packet::udp::UdpPacket;
+use pnet_packet::vlan::VlanPacket;
+use pnet_packet::Packet;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::capstats::{self, CaptureStats, PacketSocket};
+use super::dissect;
+use super::filter::Filter;
+use super::pcap::{linktype, PcapReader, RotatingWriter};
+use super::traffic::{self, TrafficStats};
+use crate::analytics::BandwidthAnalyzer;
+use crate::output::EventStream;
+use crate::{report, status};
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    Icmp,
+    Icmpv6,
+    Arp,
+    Other,
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Protocol::Tcp => "TCP",
+            Protocol::Udp => "UDP",
+            Protocol::Icmp => "ICMP",
+            Protocol::Icmpv6 => "ICMPv6",
+            Protocol::Arp => "ARP",
+            Protocol::Other => "Other",
+        })
+    }
+}
+
+/// One frame, decoded as far as we understand it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedPacket {
+    /// Bytes on the wire, link header included
+    pub length: usize,
+    pub protocol: Protocol,
+    /// IP addresses, or an ARP packet's sender and target
+    pub src: Option<IpAddr>,
+    pub dst: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dst_port: Option<u16>,
+    /// TCP flags (`[S.]`), ICMP message, ARP operation or the unknown EtherType
+    pub info: String,
+    /// Sequence-space details of a TCP segment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<TcpSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TcpSegment {
+    pub flags: u8,
+    pub seq: u32,
+    /// Advertised receive window, unscaled
+    pub window: u16,
+    /// Payload bytes, as declared by the IP header even if the capture cut them off
+    pub payload_len: usize,
+}
+
+impl TcpSegment {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+
+    /// Sequence numbers the segment occupies; SYN and FIN take one each
+    pub fn seq_len(&self) -> u32 {
+        let control = u32::from(self.flags & (Self::SYN | Self::FIN) != 0);
+        self.payload_len as u32 + control
+    }
+}
+
+impl DecodedPacket {
+    fn other(length: usize, info: String) -> Self {
+        Self { length, protocol: Protocol::Other, src: None, dst: None, src_port: None, dst_port: None, info, tcp: None }
+    }
+}
+
+/// Decode a frame of the given pcap link type; `length` is its size on the
+/// wire, which may exceed `frame` when the capture was truncated
+pub fn decode_frame(linktype: u32, frame: &[u8], length: usize) -> DecodedPacket {
+    use super::pcap::linktype::*;
+    let mut packet = match linktype {
+        ETHERNET => decode_ethernet(frame),
+        RAW | IPV4 | IPV6 | RAW_OPENBSD => decode_ip(frame, length),
+        // A 4-byte address family in the capturing host's byte order
+        NULL | LOOP => decode_ip(frame.get(4..).unwrap_or_default(), length),
+        LINUX_SLL if frame.len() >= 16 => {
+            decode_ethertype(EtherType(u16::from_be_bytes([frame[14], frame[15]])), &frame[16..], length)
+        }
+        LINUX_SLL2 if frame.len() >= 20 => {
+            decode_ethertype(EtherType(u16::from_be_bytes([frame[0], frame[1]])), &frame[20..], length)
+        }
+        other => DecodedPacket::other(length, format!("link type {}", other)),
+    };
+    packet.length = length;
+    packet
+}
+
+/// Decode an Ethernet frame (with or without one 802.1Q tag)
+pub fn decode_ethernet(frame: &[u8]) -> DecodedPacket {
+    let Some(ethernet) = EthernetPacket::new(frame) else {
+        return DecodedPacket::other(frame.len(), "truncated frame".to_string());
+    };
+    decode_ethertype(ethernet.get_ethertype(), ethernet.payload(), frame.len())
+}
+
+fn decode_ethertype(ethertype: EtherType, payload: &[u8], length: usize) -> DecodedPacket {
+    let (ethertype, payload) = match ethertype {
+        EtherTypes::Vlan => match VlanPacket::new(payload) {
+            Some(vlan) => (vlan.get_ethertype(), &payload[4..]),
+            None => return DecodedPacket::other(length, "truncated VLAN tag".to_string()),
+        },
+        ethertype => (ethertype, payload),
+    };
+
+    match ethertype {
+        EtherTypes::Ipv4 | EtherTypes::Ipv6 => decode_ip(payload, length),
+        EtherTypes::Arp => match ArpPacket::new(payload) {
+            Some(arp) => DecodedPacket {
+                length,
+                protocol: Protocol::Arp,
+                src: Some(IpAddr::V4(arp.get_sender_proto_addr())),
+                dst: Some(IpAddr::V4(arp.get_target_proto_addr())),
+                src_port: None,
+                dst_port: None,
+                info: match arp.get_operation() {
+                    ArpOperations::Request => "request".to_string(),
+                    ArpOperations::Reply => "reply".to_string(),
+                    op => format!("operation {}", op.0),
+                },
+                tcp: None,
+            },
+            None => DecodedPacket::other(length, "truncated ARP".to_string()),
+        },
+        ethertype => DecodedPacket::other(length, format!("ethertype 0x{:04x}", ethertype.0)),
+    }
+}
+
+/// Decode a bare IPv4 or IPv6 packet, as read from interfaces without a
+/// link header (tun devices, some VPNs); `length` is what to report for it
+pub fn decode_ip(packet: &[u8], length: usize) -> DecodedPacket {
+    match packet.first().map(|b| b >> 4) {
+        Some(4) => match Ipv4Packet::new(packet) {
+            Some(ip) => {
+                let header = usize::from(ip.get_header_length()) * 4;
+                let payload = packet.get(header..).unwrap_or_default();
+                let declared = usize::from(ip.get_total_length()).saturating_sub(header);
+                let src = IpAddr::V4(ip.get_source());
+                let dst = IpAddr::V4(ip.get_destination());
+                decode_transport(ip.get_next_level_protocol(), payload, declared, src, dst, length)
+            }
+            None => DecodedPacket::other(length, "truncated IPv4".to_string()),
+        },
+        Some(6) => match Ipv6Packet::new(packet) {
+            Some(ip) => {
+                let src = IpAddr::V6(ip.get_source());
+                let dst = IpAddr::V6(ip.get_destination());
+                let declared = usize::from(ip.get_payload_length());
+                decode_transport(ip.get_next_header(), ip.payload(), declared, src, dst, length)
+            }
+            None => DecodedPacket::other(length, "truncated IPv6".to_string()),
+        },
+        _ => DecodedPacket::other(length, "not IP".to_string()),
+    }
+}
+
+/// `declared` is the payload length the IP header claims, which may differ
+/// from what was captured (truncation, Ethernet padding)
+fn decode_transport(
+    next: IpNextHeaderProtocol,
+    payload: &[u8],
+    declared: usize,
+    src: IpAddr,
+    dst: IpAddr,
+    length: usize,
+) -> DecodedPacket {
+    let mut packet = DecodedPacket {
+        length,
+        protocol: Protocol::Other,
+        src: Some(src),
+        dst: Some(dst),
+        src_port: None,
+        dst_port: None,
+        info: format!("protocol {}", next.0),
+        tcp: None,
+    };
+    match next {
+        IpNextHeaderProtocols::Tcp => {
+            packet.protocol = Protocol::Tcp;
+            if let Some(tcp) = TcpPacket::new(payload) {
+                packet.src_port = Some(tcp.get_source());
+                packet.dst_port = Some(tcp.get_destination());
+                packet.info = format!("[{}]", tcp_flags(tcp.get_flags()));
+                packet.tcp = Some(TcpSegment {
+                    flags: tcp.get_flags(),
+                    seq: tcp.get_sequence(),
+                    window: tcp.get_window(),
+                    payload_len: declared.saturating_sub(usize::from(tcp.get_data_offset()) * 4),
+                });
+            } else {
+                packet.info = "truncated".to_string();
+            }
+        }
+        IpNextHeaderProtocols::Udp => {
+            packet.protocol = Protocol::Udp;
+            packet.info.clear();
+            if let Some(udp) = UdpPacket::new(payload) {
+                packet.src_port = Some(udp.get_source());
+                packet.dst_port = Some(udp.get_destination());
+            }
+        }
+        IpNextHeaderProtocols::Icmp => {
+            packet.protocol = Protocol::Icmp;
+            packet.info = IcmpPacket::new(payload).map_or_else(|| "truncated".to_string(), |icmp| {
+                match icmp.get_icmp_type() {
+                    IcmpTypes::EchoRequest => "echo request".to_string(),
+                    IcmpTypes::EchoReply => "echo reply".to_string(),
+                    IcmpTypes::TimeExceeded => "time exceeded".to_string(),
+                    IcmpTypes::DestinationUnreachable => format!("unreachable code {}", icmp.get_icmp_code().0),
+                    other => format!("type {}", other.0),
+                }
+            });
+        }
+        IpNextHeaderProtocols::Icmpv6 => {
+            packet.protocol = Protocol::Icmpv6;
+            packet.info = Icmpv6Packet::new(payload)
+                .map_or_else(|| "truncated".to_string(), |icmp| format!("type {}", icmp.get_icmpv6_type().0));
+        }
+        _ => {}
+    }
+    packet
+}
+
+/// tcpdump's flag letters, `.` standing for ACK
+pub fn tcp_flags(flags: u8) -> String {
+    const LETTERS: [(u8, char); 8] =
+        [(0x01, 'F'), (0x02, 'S'), (0x04, 'R'), (0x08, 'P'), (0x10, '.'), (0x20, 'U'), (0x40, 'E'), (0x80, 'W')];
+    LETTERS.iter().filter(|(bit, _)| flags & bit != 0).map(|(_, letter)| letter).collect()
+}
+
+/// `12:00:01.123456 IP 10.0.0.2.51234 > 1.1.1.1.443: TCP [S.] length 74`
+pub fn format_line(timestamp: chrono::DateTime<chrono::Local>, packet: &DecodedPacket) -> String {
+    let endpoint = |ip: Option<IpAddr>, port: Option<u16>| match (ip, port) {
+        (Some(ip), Some(port)) => format!("{}.{}", ip, port),
+        (Some(ip), None) => ip.to_string(),
+        _ => "?".to_string(),
+    };
+    let time = timestamp.format("%H:%M:%S%.6f");
+    let family = match (packet.protocol, packet.src) {
+        (Protocol::Arp, _) => "ARP",
+        (_, Some(IpAddr::V4(_))) => "IP",
+        (_, Some(IpAddr::V6(_))) => "IP6",
+        (_, None) => return format!("{} {} length {}", time, packet.info, packet.length),
+    };
+    let detail = match (packet.protocol, packet.info.is_empty()) {
+        (Protocol::Arp, _) => packet.info.clone(),
+        (protocol, true) => protocol.to_string(),
+        (Protocol::Other, false) => packet.info.clone(),
+        (protocol, false) => format!("{} {}", protocol, packet.info),
+    };
+    format!(
+        "{} {} {} > {}: {} length {}",
+        time,
+        family,
+        endpoint(packet.src, packet.src_port),
+        endpoint(packet.dst, packet.dst_port),
+        detail,
+        packet.length
+    )
+}
+
+/// Packets and bytes seen, by protocol
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolCounts {
+    pub packets: u64,
+    pub bytes: u64,
+    pub tcp: u64,
+    pub udp: u64,
+    /// ICMP and ICMPv6
+    pub icmp: u64,
+    /// ARP and anything we couldn't decode
+    pub other: u64,
+    pub tcp_bytes: u64,
+    pub udp_bytes: u64,
+    pub icmp_bytes: u64,
+    pub other_bytes: u64,
+}
+
+impl ProtocolCounts {
+    pub fn record(&mut self, packet: &DecodedPacket) {
+        let bytes = packet.length as u64;
+        self.packets += 1;
+        self.bytes += bytes;
+        let (packets, protocol_bytes) = match packet.protocol {
+            Protocol::Tcp => (&mut self.tcp, &mut self.tcp_bytes),
+            Protocol::Udp => (&mut self.udp, &mut self.udp_bytes),
+            Protocol::Icmp | Protocol::Icmpv6 => (&mut self.icmp, &mut self.icmp_bytes),
+            Protocol::Arp | Protocol::Other => (&mut self.other, &mut self.other_bytes),
+        };
+        *packets += 1;
+        *protocol_bytes += bytes;
+    }
+}
+
+/// The interface called `name`, or the one holding our default-route address
+pub fn capture_interface(name: Option<&str>) -> Result<NetworkInterface> {
+    match name {
+        Some(name) => utils::interface_named(name),
+        None => {
+            let ip = utils::get_local_ip()?;
+            crate::scanner::arp::interface_for(ip)
+                .with_context(|| format!("No interface has address {}; pick one with --interface", ip))
+        }
+    }
+}
+
+/// A frame as captured, with the pcap link type saying how to read it
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    pub linktype: u32,
+    pub data: &'a [u8],
+}
+
+/// Frames off one interface, as its link type; reads time out every 100ms
+/// so the caller gets to check whether to stop
+pub struct FrameReader {
+    name: String,
+    rx: Box<dyn pnet_datalink::DataLinkReceiver>,
+    linktype: u32,
+    loopback: bool,
+    previous: Option<Vec<u8>>,
+}
+
+impl FrameReader {
+    /// Open a datalink channel on `iface`; needs root
+    pub fn open(iface: &NetworkInterface) -> Result<Self> {
+        let config = pnet_datalink::Config { read_timeout: Some(Duration::from_millis(100)), ..Default::default() };
+        let rx = match pnet_datalink::channel(iface, config)
+            .with_context(|| format!("Failed to open a datalink channel on {}", iface.name))?
+        {
+            Channel::Ethernet(_, rx) => rx,
+            _ => anyhow::bail!("Unsupported channel type on {}", iface.name),
+        };
+        // Point-to-point links hand us IP packets with no Ethernet header
+        let bare_ip = iface.mac.is_none() || iface.is_point_to_point();
+        Ok(Self {
+            name: iface.name.clone(),
+            rx,
+            linktype: if bare_ip { linktype::RAW } else { linktype::ETHERNET },
+            loopback: iface.is_loopback(),
+            previous: None,
+        })
+    }
+
+    /// The next frame; None when the read timed out on a quiet link, or
+    /// for the copy of a frame loopback shows twice
+    pub fn read(&mut self) -> Result<Option<Frame<'_>>> {
+        let data = match self.rx.next() {
+            Ok(data) => data,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Capture on {} failed", self.name)),
+        };
+        // Loopback shows every packet twice, leaving and arriving, back to back
+        if self.loopback {
+            if self.previous.take().is_some_and(|previous| previous == data) {
+                return Ok(None);
+            }
+            self.previous = Some(data.to_vec());
+        }
+        Ok(Some(Frame { linktype: self.linktype, data }))
+    }
+}
+
+/// Read each of `interfaces` on a thread of its own, handing `on_frame`
+/// every frame, or None when there was none, until `stop` is set
+///
+/// Every channel is opened before any thread starts, so a missing
+/// privilege fails here. A thread ends early with the error that stopped it.
+pub fn spawn_readers<F>(interfaces: &[NetworkInterface], stop: &Arc<AtomicBool>, on_frame: F) -> Result<Vec<std::thread::JoinHandle<Result<()>>>>
+where
+    F: FnMut(Option<Frame<'_>>) + Clone + Send + 'static,
+{
+    let readers = interfaces.iter().map(FrameReader::open).collect::<Result<Vec<_>>>()?;
+    Ok(readers
+        .into_iter()
+        .map(|mut reader| {
+            let (stop, mut on_frame) = (Arc::clone(stop), on_frame.clone());
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    on_frame(reader.read()?);
+                }
+                Ok(())
+            })
+        })
+        .collect())
+}
+
+/// What happens to each packet besides being counted
+#[derive(Default)]
+pub struct PacketSinks<'a> {
+    /// Write packets and analyses here as records instead of printing them
+    pub stream: Option<&'a mut EventStream>,
+    /// Print each packet's layers and up to this many payload bytes
+    pub decode: Option<usize>,
+    /// Save the frames to a capture file
+    pub writer: Option<&'a mut RotatingWriter>,
+}
+
+/// Print or stream `packet`, save its frame and add it to `stats`, unless
+/// `filter` rejects it
+fn handle(
+    packet: &DecodedPacket,
+    frame: Frame<'_>,
+    at: DateTime<Utc>,
+    filter: Option<&Filter>,
+    stats: &mut TrafficStats,
+    sinks: &mut PacketSinks<'_>,
+) -> Result<()> {
+    if filter.is_some_and(|filter| !filter.matches(packet)) {
+        return Ok(());
+    }
+    if let Some(writer) = sinks.writer.as_deref_mut() {
+        writer.write(at, frame.linktype, frame.data, packet.length)?;
+    }
+    match sinks.stream.as_deref_mut() {
+        Some(stream) => stream.emit_at("packet", at, packet)?,
+        None => {
+            report!("{}", format_line(at.with_timezone(&chrono::Local), packet));
+            if let Some(limit) = sinks.decode {
+                let dissection = dissect::dissect(frame.linktype, frame.data);
+                for line in dissect::format_dissection(&dissection, frame.data, limit) {
+                    report!("{}", line);
+                }
+            }
+        }
+    }
+    stats.record(at, packet);
+    Ok(())
+}
+
+/// What a live capture reports while it runs, and how it buffers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LiveOptions {
+    /// Print the analysis so far at this interval
+    pub report_every: Option<Duration>,
+    /// Print a status line with rates and kernel drops at this interval
+    pub stats_every: Option<Duration>,
+    /// Kernel receive buffer to ask for, in bytes
+    pub buffer_size: Option<usize>,
+}
+
+/// Read frames from `iface`, printing each, until `count` packets were seen
+/// or `stop` is set; blocking
+///
+/// Packets not matching `filter` are skipped without being counted. The
+/// analysis and status lines `live` asks for are printed along the way, or
+/// written to the sinks' stream as records.
+pub fn capture(
+    iface: &NetworkInterface,
+    filter: Option<&Filter>,
+    count: Option<usize>,
+    live: LiveOptions,
+    mut sinks: PacketSinks<'_>,
+    stop: Arc<AtomicBool>,
+) -> Result<TrafficStats> {
+    let before = capstats::packet_sockets();
+    let mut reader = FrameReader::open(iface)?;
+    let socket = PacketSocket::find_new(&before);
+    let mut capture_stats = CaptureStats { buffer_bytes: socket.and_then(|socket| socket.buffer_size()), ..Default::default() };
+    if let Some(bytes) = live.buffer_size {
+        match socket.and_then(|socket| socket.set_buffer_size(bytes)) {
+            Some(granted) => capture_stats.buffer_bytes = Some(granted),
+            None => status!("⚠️  Could not set the capture buffer on this platform; using the default"),
+        }
+    }
+    // Counters from before the socket was bound to the interface mean nothing
+    if let Some(socket) = socket {
+        socket.take_counts();
+        capture_stats.kernel_received = Some(0);
+        capture_stats.kernel_dropped = Some(0);
+    }
+    let mut stats = TrafficStats::default();
+    let mut last_report = Instant::now();
+    let mut ticker = live.stats_every.map(|every| StatusTicker::new(every, socket));
+    while !stop.load(Ordering::Relaxed) && count.is_none_or(|count| (stats.counts.packets as usize) < count) {
+        if live.report_every.is_some_and(|every| last_report.elapsed() >= every) {
+            stats.capture = Some(capture_stats.clone());
+            match sinks.stream.as_deref_mut() {
+                Some(stream) => stream.emit("analysis", &stats.report(traffic::TOP))?,
+                None => traffic::print_report(&stats),
+            }
+            last_report = Instant::now();
+        }
+        if let Some(ticker) = ticker.as_mut().filter(|ticker| ticker.is_due()) {
+            ticker.tick(&stats, &mut capture_stats, sinks.stream.as_deref_mut())?;
+        }
+        if let Some(frame) = reader.read()? {
+            let packet = decode_frame(frame.linktype, frame.data, frame.data.len());
+            handle(&packet, frame, Utc::now(), filter, &mut stats, &mut sinks)?;
+        }
+    }
+    if let Some(counts) = socket.and_then(|socket| socket.take_counts()) {
+        add_counts(&mut capture_stats, counts);
+    }
+    stats.capture = Some(capture_stats);
+    Ok(stats)
+}
+
+fn add_counts(stats: &mut CaptureStats, counts: capstats::KernelCounts) {
+    *stats.kernel_received.get_or_insert(0) += counts.received;
+    *stats.kernel_dropped.get_or_insert(0) += counts.dropped;
+}
+
+/// Prints the periodic status line of a live capture
+struct StatusTicker {
+    every: Duration,
+    last: Instant,
+    socket: Option<PacketSocket>,
+    /// Packets counted per tick, for the current rate
+    rate: BandwidthAnalyzer,
+    packets_at_last: u64,
+}
+
+impl StatusTicker {
+    fn new(every: Duration, socket: Option<PacketSocket>) -> Self {
+        // The rate covers the last two intervals, so it moves with the traffic
+        let mut rate = BandwidthAnalyzer::new(every * 2 + every / 2);
+        rate.add_measurement(0);
+        Self { every, last: Instant::now(), socket, rate, packets_at_last: 0 }
+    }
+
+    fn is_due(&self) -> bool {
+        self.last.elapsed() >= self.every
+    }
+
+    fn tick(&mut self, stats: &TrafficStats, capture: &mut CaptureStats, stream: Option<&mut EventStream>) -> Result<()> {
+        self.last = Instant::now();
+        self.rate.add_measurement(stats.counts.packets - self.packets_at_last);
+        self.packets_at_last = stats.counts.packets;
+        let pps = self.rate.current_bandwidth();
+        capture.peak_packets_per_sec = capture.peak_packets_per_sec.max(pps);
+        let dropped = match self.socket.and_then(|socket| socket.take_counts()) {
+            Some(counts) => {
+                add_counts(capture, counts);
+                counts.dropped
+            }
+            None => 0,
+        };
+
+        let line = StatusLine {
+            packets: stats.counts.packets,
+            bytes: stats.counts.bytes,
+            packets_per_sec: pps,
+            kernel_dropped: capture.kernel_dropped,
+            dropped_since_last: dropped,
+        };
+        match stream {
+            Some(stream) => stream.emit("stats", &line)?,
+            None => {
+                status!("{}", format_status(&line).dimmed());
+                if dropped > 0 {
+                    status!(
+                        "{}",
+                        format!(
+                            "⚠️  The kernel dropped {} packets: we are reading too slowly. Raise --buffer-size or narrow the filter",
+                            dropped
+                        )
+                        .bright_red()
+                        .bold()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A live capture's progress at one moment
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusLine {
+    pub packets: u64,
+    pub bytes: u64,
+    pub packets_per_sec: f64,
+    /// Dropped since the capture began, None where the kernel won't say
+    pub kernel_dropped: Option<u64>,
+    pub dropped_since_last: u64,
+}
+
+/// `📊 1520 packets, 1.2 MB, 310 pkt/s, 0 dropped`
+pub fn format_status(line: &StatusLine) -> String {
+    let dropped = match line.kernel_dropped {
+        Some(dropped) => format!("{} dropped", dropped),
+        None => "drops unknown".to_string(),
+    };
+    format!(
+        "📊 {} packets, {}, {:.0} pkt/s, {}",
+        line.packets,
+        utils::format_bytes(line.bytes),
+        line.packets_per_sec,
+        dropped
+    )
+}
+
+/// Feed the packets of a pcap or pcapng file through the same decoding,
+/// filtering and analysis as a live capture. A file cut off mid-record
+/// (a capture that was killed) ends the replay with a warning
+pub fn replay(
+    path: &Path,
+    filter: Option<&Filter>,
+    count: Option<usize>,
+    mut sinks: PacketSinks<'_>,
+    stop: Arc<AtomicBool>,
+) -> Result<TrafficStats> {
+    let mut reader = PcapReader::open(path)?;
+    let mut stats = TrafficStats::default();
+    while !stop.load(Ordering::Relaxed) && count.is_none_or(|count| (stats.counts.packets as usize) < count) {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                status!("⚠️  Stopped reading {}: {:#}", path.display(), e);
+                break;
+            }
+        };
+        let packet = decode_frame(record.linktype, &record.data, record.orig_len);
+        let frame = Frame { linktype: record.linktype, data: &record.data };
+        handle(&packet, frame, record.timestamp, filter, &mut stats, &mut sinks)?;
+    }
+    Ok(stats)
+}
@@ -0,0 +1,161 @@
+// Route comparison (`trace --target a,b`)
+// When one service is fine and another slow, the interesting part of their
+// routes is where they part. The traces are lined up TTL by TTL: hops where
+// every route got an answer from the same router (or some got none) form the
+// shared stretch, and the first TTL where two routers differ is where they
+// diverge. From there each route is summed up on its own: how many hops it
+// adds, what latency it ends on and how much of it was added after the
+// split, and the worst loss on the way.
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+
+use super::{TraceHop, TraceResult};
+use crate::report;
+
+/// A hop all the routes share
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SharedHop {
+    pub hop: u8,
+    /// The router's address, None when no route got an answer at this TTL
+    pub ip: Option<Ipv4Addr>,
+    /// Per route, in target order; None where that route's probes went unanswered
+    pub avg_rtt_ms: Vec<Option<f64>>,
+}
+
+/// One route from the point the routes diverge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Branch {
+    pub target: String,
+    pub target_ip: Ipv4Addr,
+    pub completed: bool,
+    /// Hops after the shared stretch
+    pub hops: usize,
+    /// Average RTT of the last hop that answered
+    pub final_rtt_ms: Option<f64>,
+    /// Of `final_rtt_ms`, what was added after the last shared hop
+    pub added_rtt_ms: Option<f64>,
+    /// Highest loss of any answering hop after the split, in percent
+    pub worst_loss_pct: f64,
+    /// Loss at the last hop, in percent
+    pub final_loss_pct: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteComparison {
+    pub targets: Vec<String>,
+    pub shared: Vec<SharedHop>,
+    /// The first TTL at which two routes reach different routers; None
+    /// when every route follows the others as far as it goes
+    pub diverges_at: Option<u8>,
+    pub branches: Vec<Branch>,
+}
+
+/// Line up `traces` and find where they part
+pub fn compare(traces: &[TraceResult]) -> RouteComparison {
+    let depth = traces.iter().map(|trace| trace.hops.len()).min().unwrap_or(0);
+    let mut shared = Vec::new();
+    let mut diverges_at = None;
+    for index in 0..depth {
+        let hops: Vec<&TraceHop> = traces.iter().map(|trace| &trace.hops[index]).collect();
+        let mut routers = hops.iter().filter_map(|hop| hop.ip);
+        let first = routers.next();
+        if let Some(first) = first {
+            if routers.any(|ip| ip != first) {
+                diverges_at = Some(hops[0].hop);
+                break;
+            }
+        }
+        shared.push(SharedHop { hop: hops[0].hop, ip: first, avg_rtt_ms: hops.iter().map(|hop| answered_rtt(hop)).collect() });
+    }
+    // A route that ends inside the others' shared stretch still parts with
+    // them where they go on without it
+    if diverges_at.is_none() && traces.iter().any(|trace| trace.hops.len() > depth) {
+        diverges_at = traces.iter().flat_map(|trace| trace.hops.get(depth)).map(|hop| hop.hop).next();
+    }
+
+    let branches = traces
+        .iter()
+        .map(|trace| {
+            let last_shared = trace.hops[..shared.len()].iter().rev().find_map(answered_rtt);
+            let after = &trace.hops[shared.len()..];
+            let final_hop = trace.hops.iter().rev().find(|hop| hop.ip.is_some());
+            let final_rtt_ms = final_hop.map(|hop| hop.avg_rtt);
+            Branch {
+                target: trace.target.clone(),
+                target_ip: trace.target_ip,
+                completed: trace.completed,
+                hops: after.len(),
+                final_rtt_ms,
+                added_rtt_ms: final_rtt_ms.map(|end| match after.is_empty() {
+                    true => 0.0,
+                    false => (end - last_shared.unwrap_or(0.0)).max(0.0),
+                }),
+                worst_loss_pct: after.iter().filter(|hop| hop.ip.is_some()).map(|hop| hop.packet_loss).fold(0.0, f64::max),
+                final_loss_pct: trace.hops.last().map_or(0.0, |hop| hop.packet_loss),
+            }
+        })
+        .collect();
+
+    RouteComparison {
+        targets: traces.iter().map(|trace| trace.target.clone()).collect(),
+        shared,
+        diverges_at,
+        branches,
+    }
+}
+
+fn answered_rtt(hop: &TraceHop) -> Option<f64> {
+    hop.ip.map(|_| hop.avg_rtt)
+}
+
+pub fn print_comparison(comparison: &RouteComparison) {
+    report!("\n{}", "Route Comparison".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
+
+    let shared: Vec<String> = comparison.shared.iter().map(|hop| hop.ip.map_or_else(|| "*".to_string(), |ip| ip.to_string())).collect();
+    match shared.len() {
+        0 => report!("🔀 The routes share no hops"),
+        n => report!("🤝 Shared path: {} hop{} ({})", n, if n == 1 { "" } else { "s" }, shared.join(" → ")),
+    }
+    match (comparison.diverges_at, comparison.shared.iter().rev().find_map(|hop| hop.ip)) {
+        (Some(ttl), Some(last)) => report!("🔀 Routes diverge at hop {}, after {}", ttl, last.to_string().bright_yellow()),
+        (Some(ttl), None) => report!("🔀 Routes diverge at hop {}", ttl),
+        (None, _) => report!("➡️  The routes never diverge"),
+    }
+
+    let width = comparison.targets.iter().map(|target| target.len()).max().unwrap_or(0);
+    let slowest = comparison.branches.iter().filter_map(|branch| branch.added_rtt_ms).fold(f64::MIN, f64::max);
+    report!("");
+    for branch in &comparison.branches {
+        let end = match (branch.final_rtt_ms, branch.added_rtt_ms) {
+            (Some(end), Some(added)) => {
+                let added = format!("+{:.1} ms after the split", added);
+                let added = if comparison.branches.len() > 1 && branch.added_rtt_ms == Some(slowest) && slowest > 0.0 {
+                    added.bright_yellow().to_string()
+                } else {
+                    added
+                };
+                format!("ends at {:.1} ms ({})", end, added)
+            }
+            _ => "no answers".bright_red().to_string(),
+        };
+        let loss = |pct: f64| {
+            let text = format!("{:.0}%", pct);
+            if pct > 0.0 { text.bright_red().to_string() } else { text }
+        };
+        let reached = if branch.completed { String::new() } else { ", target not reached".bright_red().to_string() };
+        report!(
+            "  {:<width$}  {} more hop{}, {}, worst loss {}, final loss {}{}",
+            branch.target,
+            branch.hops,
+            if branch.hops == 1 { "" } else { "s" },
+            end,
+            loss(branch.worst_loss_pct),
+            loss(branch.final_loss_pct),
+            reached,
+            width = width
+        );
+    }
+}
@@ -0,0 +1,430 @@
+// Socket and connection listing (ss-style)
+// On Linux the kernel's socket tables are read straight from /proc/net/tcp,
+// tcp6, udp and udp6: one line per socket with hex-encoded addresses, the
+// TCP state code and the socket inode. The owning process is found by
+// walking /proc/<pid>/fd for "socket:[inode]" links, which only shows other
+// users' processes when run as root. Elsewhere `netstat -an` is parsed
+// instead (macOS/BSD print "addr.port", Windows "addr:port"), without
+// process information.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::{report, status};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Transport::Tcp => "TCP",
+            Transport::Udp => "UDP",
+        })
+    }
+}
+
+/// Socket state as the kernel reports it; UDP sockets are either
+/// `Established` (connected) or `Unconnected`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SocketState {
+    Established,
+    SynSent,
+    SynRecv,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Close,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    Unconnected,
+    Unknown,
+}
+
+impl SocketState {
+    /// State from the hex code in /proc/net (include/net/tcp_states.h)
+    fn from_proc(code: u8, transport: Transport) -> Self {
+        match code {
+            0x01 => SocketState::Established,
+            0x02 => SocketState::SynSent,
+            0x03 | 0x0C => SocketState::SynRecv,
+            0x04 => SocketState::FinWait1,
+            0x05 => SocketState::FinWait2,
+            0x06 => SocketState::TimeWait,
+            0x07 if transport == Transport::Udp => SocketState::Unconnected,
+            0x07 => SocketState::Close,
+            0x08 => SocketState::CloseWait,
+            0x09 => SocketState::LastAck,
+            0x0A => SocketState::Listen,
+            0x0B => SocketState::Closing,
+            _ => SocketState::Unknown,
+        }
+    }
+
+    /// State from a netstat column ("ESTABLISHED", "LISTENING", "TIME_WAIT")
+    fn from_netstat(word: &str) -> Self {
+        match word.to_ascii_uppercase().replace('-', "_").as_str() {
+            "ESTABLISHED" => SocketState::Established,
+            "SYN_SENT" => SocketState::SynSent,
+            "SYN_RECEIVED" | "SYN_RCVD" | "SYN_RECV" => SocketState::SynRecv,
+            "FIN_WAIT_1" | "FIN_WAIT1" => SocketState::FinWait1,
+            "FIN_WAIT_2" | "FIN_WAIT2" => SocketState::FinWait2,
+            "TIME_WAIT" => SocketState::TimeWait,
+            "CLOSED" | "CLOSE" => SocketState::Close,
+            "CLOSE_WAIT" => SocketState::CloseWait,
+            "LAST_ACK" => SocketState::LastAck,
+            "LISTEN" | "LISTENING" => SocketState::Listen,
+            "CLOSING" => SocketState::Closing,
+            _ => SocketState::Unknown,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SocketState::Established => "ESTABLISHED",
+            SocketState::SynSent => "SYN_SENT",
+            SocketState::SynRecv => "SYN_RECV",
+            SocketState::FinWait1 => "FIN_WAIT1",
+            SocketState::FinWait2 => "FIN_WAIT2",
+            SocketState::TimeWait => "TIME_WAIT",
+            SocketState::Close => "CLOSE",
+            SocketState::CloseWait => "CLOSE_WAIT",
+            SocketState::LastAck => "LAST_ACK",
+            SocketState::Listen => "LISTEN",
+            SocketState::Closing => "CLOSING",
+            SocketState::Unconnected => "UNCONN",
+            SocketState::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl fmt::Display for SocketState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Connection {
+    pub protocol: Transport,
+    pub local: SocketAddr,
+    /// Unspecified (0.0.0.0:0) for listening and unconnected sockets
+    pub remote: SocketAddr,
+    pub state: SocketState,
+    #[serde(skip)]
+    pub inode: u64,
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+}
+
+impl Connection {
+    /// Process column: "name/pid", or "-" when the owner isn't visible
+    pub fn owner(&self) -> String {
+        match (&self.process, self.pid) {
+            (Some(name), Some(pid)) => format!("{}/{}", name, pid),
+            (None, Some(pid)) => pid.to_string(),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+/// Which sockets `connections` shows
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionFilter {
+    pub state: Option<SocketState>,
+    /// Local or remote port
+    pub port: Option<u16>,
+    /// Process name (case-insensitive substring) or PID
+    pub process: Option<String>,
+    pub tcp_only: bool,
+    pub udp_only: bool,
+}
+
+impl ConnectionFilter {
+    pub fn matches(&self, conn: &Connection) -> bool {
+        if self.tcp_only && conn.protocol != Transport::Tcp || self.udp_only && conn.protocol != Transport::Udp {
+            return false;
+        }
+        if self.state.is_some_and(|state| state != conn.state) {
+            return false;
+        }
+        if self.port.is_some_and(|port| conn.local.port() != port && conn.remote.port() != port) {
+            return false;
+        }
+        match &self.process {
+            Some(wanted) => {
+                let by_pid = wanted.parse::<u32>().ok().is_some_and(|pid| conn.pid == Some(pid));
+                let by_name = conn.process.as_ref()
+                    .is_some_and(|name| name.to_lowercase().contains(&wanted.to_lowercase()));
+                by_pid || by_name
+            }
+            None => true,
+        }
+    }
+}
+
+/// How much is going on on a socket in this state, busiest first: open
+/// connections, then ones being set up, then ones being torn down, then
+/// sockets that only wait
+fn activity_rank(state: SocketState) -> u8 {
+    match state {
+        SocketState::Established => 0,
+        SocketState::SynSent | SocketState::SynRecv => 1,
+        SocketState::FinWait1 | SocketState::FinWait2 | SocketState::CloseWait | SocketState::LastAck
+        | SocketState::Closing => 2,
+        SocketState::TimeWait => 3,
+        SocketState::Listen => 4,
+        SocketState::Unconnected | SocketState::Close | SocketState::Unknown => 5,
+    }
+}
+
+/// The `limit` most active sockets of `protocol` (all when None), for the
+/// monitor's connection table; sockets whose owner is known come first
+/// within a state
+pub fn top_connections(connections: Vec<Connection>, protocol: Option<Transport>, limit: usize) -> Vec<Connection> {
+    let mut connections: Vec<Connection> = connections
+        .into_iter()
+        .filter(|conn| protocol.is_none_or(|protocol| conn.protocol == protocol))
+        .collect();
+    // Stable, so list()'s ordering decides the rest
+    connections.sort_by_key(|conn| (activity_rank(conn.state), conn.pid.is_none()));
+    connections.truncate(limit);
+    connections
+}
+
+/// Parse one of the /proc/net/{tcp,tcp6,udp,udp6} tables
+pub fn parse_proc_net(contents: &str, transport: Transport) -> Vec<Connection> {
+    contents.lines().skip(1).filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            return None;
+        }
+        Some(Connection {
+            protocol: transport,
+            local: parse_proc_addr(fields[1])?,
+            remote: parse_proc_addr(fields[2])?,
+            state: SocketState::from_proc(u8::from_str_radix(fields[3], 16).ok()?, transport),
+            inode: fields[9].parse().ok()?,
+            pid: None,
+            process: None,
+        })
+    }).collect()
+}
+
+/// "0100007F:0016" or a 32-digit IPv6 address with port. The kernel prints
+/// each 32-bit word of the address as a host-order integer, so the bytes
+/// come back out with the native byte order
+fn parse_proc_addr(field: &str) -> Option<SocketAddr> {
+    let (addr, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut bytes = Vec::with_capacity(16);
+    for i in (0..addr.len()).step_by(8) {
+        let word = u32::from_str_radix(addr.get(i..i + 8)?, 16).ok()?;
+        bytes.extend(word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            let v6 = Ipv6Addr::from(octets);
+            // Dual-stack sockets show IPv4 peers as ::ffff:a.b.c.d
+            v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4)
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Parse `netstat -an` output from macOS/BSD ("tcp4 0 0 10.0.0.5.52345
+/// 17.57.144.7.5223 ESTABLISHED") or Windows ("TCP 10.0.0.5:52345
+/// 1.2.3.4:443 ESTABLISHED")
+pub fn parse_netstat(output: &str) -> Vec<Connection> {
+    output.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let proto = fields.first()?.to_ascii_lowercase();
+        let protocol = if proto.starts_with("tcp") {
+            Transport::Tcp
+        } else if proto.starts_with("udp") {
+            Transport::Udp
+        } else {
+            return None;
+        };
+        // BSD puts the send/receive queues before the addresses
+        let rest = if fields.get(1)?.parse::<u64>().is_ok() { fields.get(3..)? } else { fields.get(1..)? };
+        let local = parse_netstat_addr(rest.first()?)?;
+        let remote = parse_netstat_addr(rest.get(1)?).unwrap_or_else(|| unspecified(local));
+        let state = match rest.get(2) {
+            Some(word) => SocketState::from_netstat(word),
+            None if protocol == Transport::Udp => SocketState::Unconnected,
+            None => SocketState::Unknown,
+        };
+        Some(Connection { protocol, local, remote, state, inode: 0, pid: None, process: None })
+    }).collect()
+}
+
+/// "1.2.3.4:80", "[::1]:80", "1.2.3.4.80", "*.80" or "*:*"
+fn parse_netstat_addr(word: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = word.parse() {
+        return Some(addr);
+    }
+    let (host, port) = word.rsplit_once([':', '.'])?;
+    let port = if port == "*" { 0 } else { port.parse().ok()? };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let ip = match host {
+        "*" => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        // Scoped link-local addresses ("fe80::1%en0")
+        _ => host.split('%').next()?.parse().ok()?,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+fn unspecified(like: SocketAddr) -> SocketAddr {
+    match like {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    }
+}
+
+/// Map socket inodes to their owning process from /proc/<pid>/fd; processes
+/// we may not look into are skipped
+fn socket_owners() -> HashMap<u64, (u32, String)> {
+    let mut owners = HashMap::new();
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return owners;
+    };
+    for entry in procs.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let name = std::fs::read_to_string(entry.path().join("comm"))
+            .map(|comm| comm.trim().to_string())
+            .unwrap_or_default();
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target.to_str()
+                .and_then(|link| link.strip_prefix("socket:["))
+                .and_then(|rest| rest.strip_suffix(']'))
+                .and_then(|inode| inode.parse().ok());
+            if let Some(inode) = inode {
+                owners.entry(inode).or_insert_with(|| (pid, name.clone()));
+            }
+        }
+    }
+    owners
+}
+
+/// Every TCP and UDP socket on the host, with owners where visible
+pub fn list() -> Result<Vec<Connection>> {
+    let mut connections = if cfg!(any(target_os = "linux", target_os = "android")) {
+        list_proc()?
+    } else {
+        list_netstat()?
+    };
+    connections.sort_by(|a, b| {
+        (a.protocol, a.state, a.local.port(), a.remote).cmp(&(b.protocol, b.state, b.local.port(), b.remote))
+    });
+    Ok(connections)
+}
+
+fn list_proc() -> Result<Vec<Connection>> {
+    let tables = [
+        ("/proc/net/tcp", Transport::Tcp),
+        ("/proc/net/tcp6", Transport::Tcp),
+        ("/proc/net/udp", Transport::Udp),
+        ("/proc/net/udp6", Transport::Udp),
+    ];
+    let mut connections = Vec::new();
+    for (path, transport) in tables {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => connections.extend(parse_proc_net(&contents, transport)),
+            // No IPv6 in this kernel
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path)),
+        }
+    }
+    let owners = socket_owners();
+    for conn in &mut connections {
+        if let Some((pid, name)) = owners.get(&conn.inode) {
+            conn.pid = Some(*pid);
+            conn.process = Some(name.clone()).filter(|name| !name.is_empty());
+        }
+    }
+    Ok(connections)
+}
+
+fn list_netstat() -> Result<Vec<Connection>> {
+    let output = std::process::Command::new("netstat")
+        .arg("-an")
+        .output()
+        .context("Failed to run netstat")?;
+    Ok(parse_netstat(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// "*:*" for the unspecified address, like ss
+pub fn format_endpoint(addr: &SocketAddr) -> String {
+    match (addr.ip().is_unspecified(), addr.port()) {
+        (true, 0) => "*:*".to_string(),
+        (true, port) => format!("*:{}", port),
+        _ => addr.to_string(),
+    }
+}
+
+pub fn run_connections(filter: ConnectionFilter, json: bool, output: Option<String>) -> Result<()> {
+    let connections: Vec<Connection> = list()?.into_iter().filter(|conn| filter.matches(conn)).collect();
+
+    if json {
+        crate::output::emit_json(&connections)?;
+    } else {
+        status!("{}", "NetWeaver Connections".bright_cyan().bold());
+        status!("{}", "═".repeat(60).bright_cyan());
+        display_connections(&connections);
+        status!("\n{} sockets", connections.len());
+        if connections.iter().any(|conn| conn.pid.is_none()) && !crate::utils::is_privileged() {
+            status!("{}", "ℹ️  Run as root to see the processes of other users".dimmed());
+        }
+    }
+
+    if let Some(output_path) = output {
+        super::save_result(&connections, &output_path)?;
+        status!("💾 Connections saved to: {}", output_path.bright_green());
+    }
+    Ok(())
+}
+
+fn display_connections(connections: &[Connection]) {
+    let width = connections.iter()
+        .flat_map(|conn| [format_endpoint(&conn.local).len(), format_endpoint(&conn.remote).len()])
+        .max()
+        .unwrap_or(0)
+        .max(15);
+    report!("{:5} {:w$} {:w$} {:12} {}",
+        "Proto".bright_cyan(), "Local".bright_cyan(), "Remote".bright_cyan(), "State".bright_cyan(),
+        "Process".bright_cyan(), w = width);
+    for conn in connections {
+        let state = match conn.state {
+            SocketState::Established => conn.state.label().bright_green(),
+            SocketState::Listen => conn.state.label().bright_yellow(),
+            _ => conn.state.label().normal(),
+        };
+        report!("{:5} {:w$} {:w$} {:12} {}",
+            conn.protocol.to_string(), format_endpoint(&conn.local), format_endpoint(&conn.remote), state,
+            conn.owner(), w = width);
+    }
+}
@@ -0,0 +1,342 @@
+// Layered packet decode (`inspect --decode`)
+// Each frame is taken apart one header at a time and printed field by
+// field, followed by a hex and ASCII dump of the payload. IPv4 goes through
+// the C core's parser, which validates the header, finds the payload behind
+// the transport header and guesses the application from the ports; the
+// remaining header fields are read here. Link layers, IPv6, ARP and anything
+// the core rejects are decoded in Rust alone.
+
+use pnet_packet::arp::ArpPacket;
+use pnet_packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
+use pnet_packet::icmp::IcmpPacket;
+use pnet_packet::icmpv6::Icmpv6Packet;
+use pnet_packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet_packet::ipv4::Ipv4Packet;
+use pnet_packet::ipv6::Ipv6Packet;
+use pnet_packet::tcp::TcpPacket;
+use pnet_packet::udp::UdpPacket;
+use pnet_packet::vlan::VlanPacket;
+use std::ffi::CStr;
+use std::net::Ipv4Addr;
+
+use super::capture::tcp_flags;
+use super::pcap::linktype;
+use crate::ffi;
+
+/// Payload bytes dumped per packet unless `--decode-bytes` says otherwise
+pub const DEFAULT_PAYLOAD_BYTES: usize = 128;
+
+/// Bytes per hex dump line
+const DUMP_WIDTH: usize = 16;
+
+/// One protocol header and its fields, in wire order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub name: &'static str,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl Layer {
+    fn new(name: &'static str) -> Self {
+        Self { name, fields: Vec::new() }
+    }
+
+    fn field(mut self, label: &'static str, value: impl ToString) -> Self {
+        self.fields.push((label, value.to_string()));
+        self
+    }
+
+    /// Value of the field called `label`
+    pub fn get(&self, label: &str) -> Option<&str> {
+        self.fields.iter().find(|(name, _)| *name == label).map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dissection {
+    pub layers: Vec<Layer>,
+    /// Where the payload starts in the frame
+    pub payload_offset: usize,
+    /// Application protocol the C core guessed from the ports
+    pub application: Option<String>,
+    /// Whether the C core parsed the IP layer
+    pub core_parsed: bool,
+}
+
+/// What the C core's parser makes of a bare IPv4 packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreParse {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub protocol: u8,
+    pub ttl: u8,
+    /// Ports for TCP/UDP, type and code for ICMP
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub payload_offset: usize,
+    pub application: String,
+}
+
+/// Run `nw_packet_parse_full` over an IPv4 packet; None when it rejects it
+pub fn core_parse(packet: &[u8]) -> Option<CoreParse> {
+    if packet.len() < 20 || packet.len() > ffi::NW_MAX_PACKET_SIZE as usize {
+        return None;
+    }
+    let mut raw: Box<ffi::nw_packet_t> = Box::new(unsafe { std::mem::zeroed() });
+    raw.data[..packet.len()].copy_from_slice(packet);
+    raw.length = packet.len();
+    let mut parsed: Box<ffi::nw_packet_t> = Box::new(unsafe { std::mem::zeroed() });
+    if unsafe { ffi::nw_packet_parse_full(&*raw, &mut *parsed) } != ffi::nw_error_t_NW_SUCCESS {
+        return None;
+    }
+    // The core takes 0x0800 at offset 12 for an Ethernet header, which a
+    // packet from 8.0.x.x also has; its addresses then come out wrong
+    let src = Ipv4Addr::from(parsed.src_ip);
+    if src.octets() != packet[12..16] {
+        return None;
+    }
+
+    let mut payload_len = 0usize;
+    let payload = unsafe { ffi::nw_packet_get_payload(&*parsed, &mut payload_len) };
+    let payload_offset = match payload.is_null() {
+        true => packet.len(),
+        false => payload as usize - parsed.data.as_ptr() as usize,
+    };
+    let application = unsafe { CStr::from_ptr(ffi::nw_packet_classify_protocol(&*parsed)) }
+        .to_string_lossy()
+        .into_owned();
+    Some(CoreParse {
+        src,
+        dst: Ipv4Addr::from(parsed.dst_ip),
+        protocol: parsed.protocol,
+        ttl: parsed.ttl,
+        src_port: parsed.src_port,
+        dst_port: parsed.dst_port,
+        payload_offset: payload_offset.min(packet.len()),
+        application,
+    })
+}
+
+/// Decode a frame of the given pcap link type header by header
+pub fn dissect(linktype: u32, frame: &[u8]) -> Dissection {
+    let mut dissection = Dissection { layers: Vec::new(), payload_offset: frame.len(), application: None, core_parsed: false };
+    let (ethertype, offset) = match linktype {
+        linktype::ETHERNET => match link_ethernet(frame, &mut dissection.layers) {
+            Some(next) => next,
+            None => return dissection,
+        },
+        linktype::LINUX_SLL | linktype::LINUX_SLL2 => {
+            let (at, len) = if linktype == linktype::LINUX_SLL { (14, 16) } else { (0, 20) };
+            let Some(bytes) = frame.get(at..at + 2).filter(|_| frame.len() >= len) else {
+                return dissection;
+            };
+            let ethertype = EtherType(u16::from_be_bytes([bytes[0], bytes[1]]));
+            dissection.layers.push(Layer::new("Linux SLL").field("type", ethertype_name(ethertype)));
+            (Some(ethertype), len)
+        }
+        linktype::NULL | linktype::LOOP => {
+            dissection.layers.push(Layer::new("Loopback"));
+            (None, 4)
+        }
+        linktype::RAW | linktype::IPV4 | linktype::IPV6 | linktype::RAW_OPENBSD => (None, 0),
+        // Unknown link: dump it all
+        _ => {
+            dissection.payload_offset = 0;
+            return dissection;
+        }
+    };
+    let network = frame.get(offset..).unwrap_or_default();
+    let payload = match ethertype {
+        Some(EtherTypes::Arp) => {
+            if let Some(arp) = ArpPacket::new(network) {
+                dissection.layers.push(Layer::new("ARP")
+                    .field("op", arp.get_operation().0)
+                    .field("sender", format!("{} ({})", arp.get_sender_proto_addr(), arp.get_sender_hw_addr()))
+                    .field("target", format!("{} ({})", arp.get_target_proto_addr(), arp.get_target_hw_addr())));
+            }
+            network.len()
+        }
+        Some(EtherTypes::Ipv4 | EtherTypes::Ipv6) | None => network_layers(network, &mut dissection),
+        Some(_) => 0,
+    };
+    dissection.payload_offset = (offset + payload).min(frame.len());
+    dissection
+}
+
+/// Ethernet header and any 802.1Q tag; the EtherType and offset behind them
+fn link_ethernet(frame: &[u8], layers: &mut Vec<Layer>) -> Option<(Option<EtherType>, usize)> {
+    let ethernet = EthernetPacket::new(frame)?;
+    let mut ethertype = ethernet.get_ethertype();
+    layers.push(Layer::new("Ethernet")
+        .field("src", ethernet.get_source())
+        .field("dst", ethernet.get_destination())
+        .field("type", ethertype_name(ethertype)));
+    let mut offset = 14;
+    if ethertype == EtherTypes::Vlan {
+        let vlan = VlanPacket::new(&frame[offset..])?;
+        ethertype = vlan.get_ethertype();
+        layers.push(Layer::new("802.1Q")
+            .field("vlan", vlan.get_vlan_identifier())
+            .field("priority", vlan.get_priority_code_point().0)
+            .field("type", ethertype_name(ethertype)));
+        offset += 4;
+    }
+    Some((Some(ethertype), offset))
+}
+
+fn ethertype_name(ethertype: EtherType) -> String {
+    match ethertype {
+        EtherTypes::Ipv4 => "IPv4 (0x0800)".to_string(),
+        EtherTypes::Ipv6 => "IPv6 (0x86dd)".to_string(),
+        EtherTypes::Arp => "ARP (0x0806)".to_string(),
+        EtherTypes::Vlan => "802.1Q (0x8100)".to_string(),
+        other => format!("0x{:04x}", other.0),
+    }
+}
+
+fn protocol_name(protocol: IpNextHeaderProtocol) -> String {
+    let name = match protocol {
+        IpNextHeaderProtocols::Tcp => "TCP",
+        IpNextHeaderProtocols::Udp => "UDP",
+        IpNextHeaderProtocols::Icmp => "ICMP",
+        IpNextHeaderProtocols::Icmpv6 => "ICMPv6",
+        other => return other.0.to_string(),
+    };
+    format!("{} ({})", name, protocol.0)
+}
+
+/// IP and transport layers; returns where the payload starts in `packet`
+fn network_layers(packet: &[u8], dissection: &mut Dissection) -> usize {
+    match packet.first().map(|b| b >> 4) {
+        Some(4) => {
+            let Some(ip) = Ipv4Packet::new(packet) else {
+                return 0;
+            };
+            let header = usize::from(ip.get_header_length()) * 4;
+            let flags = ip.get_flags();
+            let flag_names: Vec<&str> = [(0b010, "DF"), (0b001, "MF")].iter()
+                .filter(|(bit, _)| flags & bit != 0)
+                .map(|(_, name)| *name)
+                .collect();
+            let core = core_parse(packet);
+            let (src, dst, ttl) = match &core {
+                Some(core) => (core.src, core.dst, core.ttl),
+                None => (ip.get_source(), ip.get_destination(), ip.get_ttl()),
+            };
+            dissection.layers.push(Layer::new("IPv4")
+                .field("src", src)
+                .field("dst", dst)
+                .field("ttl", ttl)
+                .field("id", format!("0x{:04x}", ip.get_identification()))
+                .field("flags", if flag_names.is_empty() { "none".to_string() } else { flag_names.join(",") })
+                .field("frag", ip.get_fragment_offset() * 8)
+                .field("len", ip.get_total_length())
+                .field("proto", protocol_name(ip.get_next_level_protocol())));
+            // Later fragments carry payload, not a transport header
+            if ip.get_fragment_offset() > 0 {
+                return header.min(packet.len());
+            }
+            let transport_end = transport_layer(ip.get_next_level_protocol(), packet.get(header..).unwrap_or_default(), dissection);
+            match core {
+                Some(core) => {
+                    dissection.core_parsed = true;
+                    dissection.application = Some(core.application);
+                    core.payload_offset
+                }
+                None => header + transport_end,
+            }
+        }
+        Some(6) => {
+            let Some(ip) = Ipv6Packet::new(packet) else {
+                return 0;
+            };
+            dissection.layers.push(Layer::new("IPv6")
+                .field("src", ip.get_source())
+                .field("dst", ip.get_destination())
+                .field("hlim", ip.get_hop_limit())
+                .field("flow", format!("0x{:05x}", ip.get_flow_label()))
+                .field("len", ip.get_payload_length())
+                .field("next", protocol_name(ip.get_next_header())));
+            40 + transport_layer(ip.get_next_header(), packet.get(40..).unwrap_or_default(), dissection)
+        }
+        _ => 0,
+    }
+}
+
+/// Transport header fields; returns the header's length
+fn transport_layer(protocol: IpNextHeaderProtocol, segment: &[u8], dissection: &mut Dissection) -> usize {
+    let (layer, len) = match protocol {
+        IpNextHeaderProtocols::Tcp => match TcpPacket::new(segment) {
+            Some(tcp) => (Layer::new("TCP")
+                .field("sport", tcp.get_source())
+                .field("dport", tcp.get_destination())
+                .field("seq", tcp.get_sequence())
+                .field("ack", tcp.get_acknowledgement())
+                .field("flags", format!("[{}]", tcp_flags(tcp.get_flags())))
+                .field("win", tcp.get_window())
+                .field("hlen", usize::from(tcp.get_data_offset()) * 4),
+                usize::from(tcp.get_data_offset()) * 4),
+            None => return 0,
+        },
+        IpNextHeaderProtocols::Udp => match UdpPacket::new(segment) {
+            Some(udp) => (Layer::new("UDP")
+                .field("sport", udp.get_source())
+                .field("dport", udp.get_destination())
+                .field("len", udp.get_length())
+                .field("csum", format!("0x{:04x}", udp.get_checksum())), 8),
+            None => return 0,
+        },
+        IpNextHeaderProtocols::Icmp => match IcmpPacket::new(segment) {
+            Some(icmp) => (Layer::new("ICMP")
+                .field("type", icmp.get_icmp_type().0)
+                .field("code", icmp.get_icmp_code().0), 8),
+            None => return 0,
+        },
+        IpNextHeaderProtocols::Icmpv6 => match Icmpv6Packet::new(segment) {
+            Some(icmp) => (Layer::new("ICMPv6")
+                .field("type", icmp.get_icmpv6_type().0)
+                .field("code", icmp.get_icmpv6_code().0), 4),
+            None => return 0,
+        },
+        _ => return 0,
+    };
+    dissection.layers.push(layer);
+    len.min(segment.len())
+}
+
+/// `0000  47 45 54 20 ...  GET / HTTP/1.1..` lines for at most `limit` bytes
+pub fn hex_dump(bytes: &[u8], limit: usize) -> Vec<String> {
+    bytes[..bytes.len().min(limit)]
+        .chunks(DUMP_WIDTH)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let (left, right) = hex.split_at(hex.len().min(DUMP_WIDTH / 2));
+            let ascii: String = chunk.iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:04x}  {:<23}  {:<23}  {}", line * DUMP_WIDTH, left.join(" "), right.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// The indented lines `--decode` prints under a packet's summary line
+pub fn format_dissection(dissection: &Dissection, frame: &[u8], limit: usize) -> Vec<String> {
+    let mut lines: Vec<String> = dissection.layers.iter()
+        .map(|layer| {
+            let fields: Vec<String> = layer.fields.iter().map(|(label, value)| format!("{} {}", label, value)).collect();
+            format!("    {:9} {}", layer.name, fields.join("  "))
+        })
+        .collect();
+    let payload = frame.get(dissection.payload_offset..).unwrap_or_default();
+    if !payload.is_empty() {
+        let application = dissection.application.as_deref()
+            .filter(|app| !matches!(*app, "TCP" | "UDP" | "ICMP" | "unknown"))
+            .map(|app| format!(" ({})", app))
+            .unwrap_or_default();
+        let shown = if payload.len() > limit { format!(", first {} shown", limit) } else { String::new() };
+        lines.push(format!("    {:9} {} bytes{}{}", "Payload", payload.len(), application, shown));
+        lines.extend(hex_dump(payload, limit).into_iter().map(|line| format!("      {}", line)));
+    }
+    lines
+}
@@ -0,0 +1,350 @@
+// DNS diagnostics
+// Queries go straight to each resolver as single DNS messages rather than
+// through a caching stub, so the latency shown is the resolver's own and the
+// AD (authenticated data) bit of its answer tells whether it validated
+// DNSSEC. Answers truncated over UDP are asked again over TCP. Comparing the
+// system resolver with well-known public ones shows a slow resolver, or one
+// returning different addresses than everybody else (hijacking, filtering,
+// or just a CDN steering by location).
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use futures::future::join_all;
+use hickory_resolver::proto::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_resolver::proto::rr::{Name, RecordType};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::{report, status};
+
+/// Public resolvers `--all-resolvers` compares the system one against
+pub const PUBLIC_RESOLVERS: [(&str, &str); 3] = [
+    ("Google", "8.8.8.8"),
+    ("Cloudflare", "1.1.1.1"),
+    ("Quad9", "9.9.9.9"),
+];
+
+/// How long to wait for each answer
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RecordKind {
+    A,
+    Aaaa,
+    Mx,
+    Txt,
+    Ns,
+    Cname,
+}
+
+impl RecordKind {
+    pub const ALL: [RecordKind; 6] =
+        [RecordKind::A, RecordKind::Aaaa, RecordKind::Mx, RecordKind::Txt, RecordKind::Ns, RecordKind::Cname];
+
+    fn record_type(self) -> RecordType {
+        match self {
+            RecordKind::A => RecordType::A,
+            RecordKind::Aaaa => RecordType::AAAA,
+            RecordKind::Mx => RecordType::MX,
+            RecordKind::Txt => RecordType::TXT,
+            RecordKind::Ns => RecordType::NS,
+            RecordKind::Cname => RecordType::CNAME,
+        }
+    }
+}
+
+impl std::fmt::Display for RecordKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.record_type().fmt(f)
+    }
+}
+
+/// A resolver to ask, and what to call it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolver {
+    pub label: String,
+    pub server: SocketAddr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsAnswer {
+    pub name: String,
+    /// The record's own type, e.g. a CNAME met while asking for A
+    pub record_type: String,
+    pub ttl: u32,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsQuery {
+    pub resolver: Resolver,
+    pub record_type: RecordKind,
+    /// None when no answer arrived
+    pub latency_ms: Option<f64>,
+    /// NOERROR, NXDOMAIN, SERVFAIL, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rcode: Option<String>,
+    /// The resolver vouched for the answer with DNSSEC (the AD bit)
+    pub authenticated: bool,
+    pub answers: Vec<DnsAnswer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DnsQuery {
+    /// The answer data of the asked-for type, sorted, for comparing resolvers
+    pub fn answer_set(&self) -> Vec<&str> {
+        let wanted = self.record_type.to_string();
+        let mut set: Vec<&str> =
+            self.answers.iter().filter(|a| a.record_type == wanted).map(|a| a.data.as_str()).collect();
+        set.sort_unstable();
+        set
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsResult {
+    pub name: String,
+    pub queries: Vec<DnsQuery>,
+}
+
+/// The first nameserver of the system configuration (resolv.conf, or the
+/// registry on Windows)
+pub fn system_resolver() -> Result<Resolver> {
+    let (config, _) = hickory_resolver::system_conf::read_system_conf().context("Failed to read the system DNS configuration")?;
+    let server = config.name_servers().first().context("No nameserver is configured on this system")?.socket_addr;
+    Ok(Resolver { label: "System".to_string(), server })
+}
+
+/// `9.9.9.9`, `9.9.9.9:5353` or `[2620:fe::fe]:53`
+pub fn parse_resolver(value: &str) -> Result<Resolver> {
+    let server = match value.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, 53),
+        Err(_) => value.parse().with_context(|| format!("Invalid resolver '{}': expected an IP address, optionally with :port", value))?,
+    };
+    Ok(Resolver { label: "Custom".to_string(), server })
+}
+
+/// Resolvers whose answers to `kind` differ from the first resolver that
+/// answered it at all
+pub fn disagreeing(queries: &[DnsQuery], kind: RecordKind) -> Vec<&DnsQuery> {
+    let answered: Vec<&DnsQuery> = queries.iter().filter(|q| q.record_type == kind && q.latency_ms.is_some()).collect();
+    let Some(reference) = answered.first() else {
+        return Vec::new();
+    };
+    let expected = reference.answer_set();
+    answered.iter().skip(1).filter(|q| q.answer_set() != expected).copied().collect()
+}
+
+pub async fn run_dns(
+    name: String,
+    kinds: Vec<RecordKind>,
+    resolver: Option<String>,
+    all_resolvers: bool,
+    output: Option<String>,
+) -> Result<()> {
+    status!("{}", "NetWeaver DNS Diagnostics".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
+
+    let fqdn = Name::from_utf8(&name).with_context(|| format!("Invalid domain name '{}'", name))?;
+    let kinds = if kinds.is_empty() { RecordKind::ALL.to_vec() } else { kinds };
+    let mut resolvers = vec![match &resolver {
+        Some(resolver) => parse_resolver(resolver)?,
+        None => system_resolver()?,
+    }];
+    if all_resolvers {
+        for (label, ip) in PUBLIC_RESOLVERS {
+            resolvers.push(Resolver { label: format!("{} ({})", label, ip), server: SocketAddr::new(ip.parse()?, 53) });
+        }
+    }
+
+    status!("🎯 Name: {}", name.bright_yellow());
+    let servers: Vec<String> = resolvers.iter().map(|r| format!("{} {}", r.label, r.server)).collect();
+    status!("📡 Resolvers: {}\n", servers.join(", "));
+
+    let lookups = resolvers.iter().flat_map(|resolver| kinds.iter().map(move |&kind| (resolver, kind)));
+    let queries = join_all(lookups.map(|(resolver, kind)| query(resolver.clone(), &fqdn, kind))).await;
+    let result = DnsResult { name, queries };
+
+    if all_resolvers {
+        display_comparison(&result, &kinds);
+    } else {
+        display_answers(&result);
+    }
+
+    if let Some(output_path) = output {
+        super::save_result(&result, &output_path)?;
+        status!("\n💾 DNS results saved to: {}", output_path.bright_green());
+    }
+    Ok(())
+}
+
+/// Ask `resolver` directly, waiting at most QUERY_TIMEOUT
+pub async fn query(resolver: Resolver, name: &Name, kind: RecordKind) -> DnsQuery {
+    let mut result = DnsQuery {
+        resolver,
+        record_type: kind,
+        latency_ms: None,
+        rcode: None,
+        authenticated: false,
+        answers: Vec::new(),
+        error: None,
+    };
+    let start = Instant::now();
+    match tokio::time::timeout(QUERY_TIMEOUT, exchange(result.resolver.server, name, kind.record_type())).await {
+        Ok(Ok(response)) => {
+            result.latency_ms = Some(start.elapsed().as_secs_f64() * 1000.0);
+            result.rcode = Some(rcode_name(response.response_code()));
+            result.authenticated = response.authentic_data();
+            result.answers = answers(&response);
+        }
+        Ok(Err(e)) => result.error = Some(format!("{:#}", e)),
+        Err(_) => result.error = Some(format!("no answer within {}s", QUERY_TIMEOUT.as_secs())),
+    }
+    result
+}
+
+/// Ask `server` over UDP, again over TCP if the answer didn't fit
+async fn exchange(server: SocketAddr, name: &Name, record_type: RecordType) -> Result<Message> {
+    let id: u16 = rand::random();
+    let mut request = Message::new();
+    request
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        // Asks a validating resolver to report whether it validated (RFC 6840)
+        .set_authentic_data(true)
+        .add_query(Query::query(name.clone(), record_type));
+    let mut edns = Edns::new();
+    edns.set_max_payload(1232).set_dnssec_ok(true);
+    request.set_edns(edns);
+    let bytes = request.to_vec()?;
+
+    let bind: SocketAddr = if server.is_ipv4() { "0.0.0.0:0".parse()? } else { "[::]:0".parse()? };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+    socket.send(&bytes).await?;
+    let mut buf = vec![0u8; 4096];
+    let response = loop {
+        let len = socket.recv(&mut buf).await?;
+        // Stray datagrams (late answers to someone else) are ignored
+        match Message::from_vec(&buf[..len]) {
+            Ok(response) if response.id() == id => break response,
+            _ => continue,
+        }
+    };
+    if !response.truncated() {
+        return Ok(response);
+    }
+
+    let mut stream = TcpStream::connect(server).await?;
+    stream.write_all(&(bytes.len() as u16).to_be_bytes()).await?;
+    stream.write_all(&bytes).await?;
+    let len = stream.read_u16().await?;
+    let mut buf = vec![0u8; usize::from(len)];
+    stream.read_exact(&mut buf).await?;
+    Ok(Message::from_vec(&buf)?)
+}
+
+/// dig's names for the common response codes
+pub fn rcode_name(code: ResponseCode) -> String {
+    match code {
+        ResponseCode::NoError => "NOERROR".to_string(),
+        ResponseCode::FormErr => "FORMERR".to_string(),
+        ResponseCode::ServFail => "SERVFAIL".to_string(),
+        ResponseCode::NXDomain => "NXDOMAIN".to_string(),
+        ResponseCode::NotImp => "NOTIMP".to_string(),
+        ResponseCode::Refused => "REFUSED".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// The answer section of `response`, DNSSEC signatures left out
+pub fn answers(response: &Message) -> Vec<DnsAnswer> {
+    response
+        .answers()
+        .iter()
+        .filter(|record| !matches!(record.record_type(), RecordType::RRSIG | RecordType::NSEC | RecordType::NSEC3))
+        .filter_map(|record| {
+            Some(DnsAnswer {
+                name: record.name().to_string(),
+                record_type: record.record_type().to_string(),
+                ttl: record.ttl(),
+                data: record.data()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn latency(query: &DnsQuery) -> String {
+    match query.latency_ms {
+        Some(ms) => format!("{:.1}ms", ms),
+        None => "-".to_string(),
+    }
+}
+
+fn dnssec(query: &DnsQuery) -> colored::ColoredString {
+    match (query.latency_ms, query.authenticated) {
+        (None, _) => "-".normal(),
+        (Some(_), true) => "validated".bright_green(),
+        (Some(_), false) => "not validated".normal(),
+    }
+}
+
+fn display_answers(result: &DnsResult) {
+    for query in &result.queries {
+        report!("{} {}", query.record_type.to_string().bright_cyan().bold(), "─".repeat(50).bright_cyan());
+        if let Some(error) = &query.error {
+            report!("  {}", error.bright_red());
+            continue;
+        }
+        let rcode = query.rcode.as_deref().unwrap_or_default();
+        if query.answers.is_empty() {
+            report!("  {}", if rcode == "NOERROR" { "no records".normal() } else { rcode.bright_yellow() });
+        }
+        for answer in &query.answers {
+            report!("  {:<40} {:>7} {:<6} {}", answer.name, answer.ttl, answer.record_type, answer.data.bright_green());
+        }
+        report!("  ⏱  {}  🔐 DNSSEC: {}", latency(query), dnssec(query));
+    }
+}
+
+fn display_comparison(result: &DnsResult, kinds: &[RecordKind]) {
+    for &kind in kinds {
+        report!("{} {}", kind.to_string().bright_cyan().bold(), "─".repeat(50).bright_cyan());
+        report!("  {:<24} {:>9}  {:<14} Answers", "Resolver", "Time", "DNSSEC");
+        for query in result.queries.iter().filter(|q| q.record_type == kind) {
+            let answers = match &query.error {
+                Some(error) => error.bright_red(),
+                None if query.answer_set().is_empty() => query.rcode.as_deref().unwrap_or_default().normal(),
+                None => query.answer_set().join(", ").bright_green(),
+            };
+            report!("  {:<24} {:>9}  {:<14} {}", query.resolver.label, latency(query), dnssec(query), answers);
+        }
+        for query in disagreeing(&result.queries, kind) {
+            report!("  {}", format!("⚠️  {} answers differently (CDN steering, filtering or hijacking)", query.resolver.label).bright_yellow());
+        }
+    }
+
+    report!("\n{}", "Resolver Latency".bright_cyan().bold());
+    let mut labels: Vec<&Resolver> = Vec::new();
+    for query in &result.queries {
+        if !labels.contains(&&query.resolver) {
+            labels.push(&query.resolver);
+        }
+    }
+    for resolver in labels {
+        let times: Vec<f64> =
+            result.queries.iter().filter(|q| &q.resolver == resolver).filter_map(|q| q.latency_ms).collect();
+        match super::rtt_summary(&times) {
+            Some(summary) => report!("  {:<24} avg {:.1}ms (min {:.1}, max {:.1})", resolver.label, summary.avg, summary.min, summary.max),
+            None => report!("  {:<24} {}", resolver.label, "no answers".bright_red()),
+        }
+    }
+}
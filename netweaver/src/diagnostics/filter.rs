@@ -0,0 +1,280 @@
+// Capture filters
+// The tcpdump filter language people already know, evaluated on decoded
+// packets: `host`, `net`, `port` and `portrange` (optionally qualified with
+// `src` or `dst`), the protocols `ip`, `ip6`, `tcp`, `udp`, `icmp`, `icmp6`
+// and `arp`, `greater`/`less` for lengths, combined with and/or/not and
+// parentheses. A protocol followed by a primitive (`tcp port 443`) means
+// both. Expressions are checked before the capture starts, so a typo is an
+// error instead of silently capturing everything.
+
+use anyhow::Result;
+use std::net::IpAddr;
+
+use super::capture::{DecodedPacket, Protocol};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Src,
+    Dst,
+    Either,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Host(Direction, IpAddr),
+    Net(Direction, IpAddr, u8),
+    Port(Direction, u16, u16),
+    Proto(ProtoMatch),
+    /// Packets at least this long
+    Greater(usize),
+    /// Packets at most this long
+    Less(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtoMatch {
+    Ip,
+    Ip6,
+    Tcp,
+    Udp,
+    Icmp,
+    Icmp6,
+    Arp,
+}
+
+const PRIMITIVES: &str = "host, net, port, portrange, src, dst, ip, ip6, tcp, udp, icmp, icmp6, arp, greater, less";
+
+impl Filter {
+    /// Parse a tcpdump-style expression
+    pub fn parse(expression: &str) -> Result<Self> {
+        let tokens = tokenize(expression);
+        if tokens.is_empty() {
+            anyhow::bail!("Empty filter expression");
+        }
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let filter = parser.or().map_err(|e| anyhow::anyhow!("Invalid filter '{}': {}", expression, e))?;
+        if let Some(token) = parser.peek() {
+            anyhow::bail!("Invalid filter '{}': unexpected '{}' at word {}", expression, token, parser.pos + 1);
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, packet: &DecodedPacket) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(packet) && b.matches(packet),
+            Filter::Or(a, b) => a.matches(packet) || b.matches(packet),
+            Filter::Not(inner) => !inner.matches(packet),
+            Filter::Host(dir, ip) => either(*dir, packet.src, packet.dst, |addr| addr == *ip),
+            Filter::Net(dir, net, prefix) => either(*dir, packet.src, packet.dst, |addr| in_net(addr, *net, *prefix)),
+            Filter::Port(dir, low, high) => {
+                either(*dir, packet.src_port, packet.dst_port, |port| (*low..=*high).contains(&port))
+            }
+            Filter::Proto(proto) => match proto {
+                ProtoMatch::Ip => packet.protocol != Protocol::Arp && matches!(packet.src, Some(IpAddr::V4(_))),
+                ProtoMatch::Ip6 => matches!(packet.src, Some(IpAddr::V6(_))),
+                ProtoMatch::Tcp => packet.protocol == Protocol::Tcp,
+                ProtoMatch::Udp => packet.protocol == Protocol::Udp,
+                ProtoMatch::Icmp => packet.protocol == Protocol::Icmp,
+                ProtoMatch::Icmp6 => packet.protocol == Protocol::Icmpv6,
+                ProtoMatch::Arp => packet.protocol == Protocol::Arp,
+            },
+            Filter::Greater(length) => packet.length >= *length,
+            Filter::Less(length) => packet.length <= *length,
+        }
+    }
+}
+
+fn either<T: Copy>(dir: Direction, src: Option<T>, dst: Option<T>, test: impl Fn(T) -> bool) -> bool {
+    let src = src.is_some_and(&test);
+    let dst = dst.is_some_and(&test);
+    match dir {
+        Direction::Src => src,
+        Direction::Dst => dst,
+        Direction::Either => src || dst,
+    }
+}
+
+fn in_net(addr: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    match (addr, net) {
+        (IpAddr::V4(addr), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Words, with parentheses and `!` split off even when written without spaces
+fn tokenize(expression: &str) -> Vec<String> {
+    let spaced = expression.replace('(', " ( ").replace(')', " ) ").replace("!", " ! ");
+    spaced.split_whitespace().map(str::to_string).collect()
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos).map(String::as_str);
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while matches!(self.peek(), Some("or" | "||")) {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        loop {
+            match self.peek() {
+                Some("and" | "&&") => self.pos += 1,
+                // `tcp port 443`: a protocol qualifies what follows it
+                Some(next) if is_protocol(&filter) && !matches!(next, "or" | "||" | ")") => {}
+                _ => return Ok(filter),
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        match self.peek() {
+            Some("not" | "!") => {
+                self.pos += 1;
+                Ok(Filter::Not(Box::new(self.unary()?)))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let filter = self.or()?;
+                match self.next() {
+                    Some(")") => Ok(filter),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            _ => self.primitive(),
+        }
+    }
+
+    fn primitive(&mut self) -> Result<Filter, String> {
+        let at = self.pos + 1;
+        let Some(word) = self.next().map(str::to_string) else {
+            return Err(format!("expression ends early (expected one of {})", PRIMITIVES));
+        };
+        let (dir, word) = match word.as_str() {
+            "src" | "dst" => {
+                let dir = if word == "src" { Direction::Src } else { Direction::Dst };
+                match self.peek() {
+                    Some("host" | "net" | "port" | "portrange") => (dir, self.next().unwrap_or_default().to_string()),
+                    // `dst 10.0.0.1` is short for `dst host 10.0.0.1`
+                    _ => (dir, "host".to_string()),
+                }
+            }
+            _ => (Direction::Either, word),
+        };
+
+        let proto = match word.as_str() {
+            "ip" => Some(ProtoMatch::Ip),
+            "ip6" => Some(ProtoMatch::Ip6),
+            "tcp" => Some(ProtoMatch::Tcp),
+            "udp" => Some(ProtoMatch::Udp),
+            "icmp" => Some(ProtoMatch::Icmp),
+            "icmp6" | "icmpv6" => Some(ProtoMatch::Icmp6),
+            "arp" => Some(ProtoMatch::Arp),
+            _ => None,
+        };
+        if let Some(proto) = proto {
+            return Ok(Filter::Proto(proto));
+        }
+
+        let value = |parser: &mut Self, what: &str| -> Result<String, String> {
+            parser.next().map(str::to_string).ok_or_else(|| format!("'{}' needs {}", word, what))
+        };
+        match word.as_str() {
+            "host" => {
+                let value = value(self, "an IP address")?;
+                let ip = value.parse().map_err(|_| format!("'{}' is not an IP address", value))?;
+                Ok(Filter::Host(dir, ip))
+            }
+            "net" => {
+                let value = value(self, "a network like 10.0.0.0/8")?;
+                let (net, prefix) = parse_net(&value).ok_or_else(|| format!("'{}' is not a network like 10.0.0.0/8", value))?;
+                Ok(Filter::Net(dir, net, prefix))
+            }
+            "port" => {
+                let value = value(self, "a port number")?;
+                let port = value.parse().map_err(|_| format!("'{}' is not a port number", value))?;
+                Ok(Filter::Port(dir, port, port))
+            }
+            "portrange" => {
+                let value = value(self, "a range like 8000-8080")?;
+                let range = value.split_once('-').and_then(|(low, high)| Some((low.parse().ok()?, high.parse().ok()?)));
+                match range {
+                    Some((low, high)) if low <= high => Ok(Filter::Port(dir, low, high)),
+                    _ => Err(format!("'{}' is not a port range like 8000-8080", value)),
+                }
+            }
+            "greater" | "less" => {
+                let value = value(self, "a length in bytes")?;
+                let length = value.parse().map_err(|_| format!("'{}' is not a length in bytes", value))?;
+                Ok(if word == "greater" { Filter::Greater(length) } else { Filter::Less(length) })
+            }
+            _ => Err(format!("unknown primitive '{}' at word {} (expected one of {})", word, at, PRIMITIVES)),
+        }
+    }
+}
+
+fn is_protocol(filter: &Filter) -> bool {
+    matches!(filter, Filter::Proto(_))
+}
+
+/// `10.0.0.0/8`, `fd00::/8`, or a bare address as a single-host network
+fn parse_net(value: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match value.split_once('/') {
+        Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, prefix.parse::<u8>().ok()?),
+        None => {
+            let addr: IpAddr = value.parse().ok()?;
+            (addr, if addr.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    (prefix <= max).then_some((addr, prefix))
+}
+
+/// The expression `--filter` and the `--filter-host`/`--filter-port`/
+/// `--filter-proto` shorthands add up to, all of them required to match
+pub fn compose(filter: Option<&str>, host: Option<&str>, port: Option<u16>, proto: Option<&str>) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(proto) = proto {
+        parts.push(proto.to_string());
+    }
+    if let Some(host) = host {
+        parts.push(format!("host {}", host));
+    }
+    if let Some(port) = port {
+        parts.push(format!("port {}", port));
+    }
+    match filter.map(str::trim).filter(|f| !f.is_empty()) {
+        Some(filter) if parts.is_empty() => Some(filter.to_string()),
+        Some(filter) => Some(format!("({}) and {}", filter, parts.join(" and "))),
+        None if parts.is_empty() => None,
+        None => Some(parts.join(" and ")),
+    }
+}
@@ -0,0 +1,45 @@
+// Flow-consistent (Paris traceroute) probing
+// ECMP routers pick among equal-cost links by hashing the flow: addresses,
+// protocol and ports, and for ICMP the first header bytes, checksum
+// included. Classic traceroute changes the ports or the ICMP sequence (and
+// with it the checksum) on every probe, so consecutive hops can come from
+// different paths and the trace shows links that don't exist. Here every
+// probe of a path carries the same flow fields and probes are told apart
+// by something the hash ignores: the UDP checksum (steered with a payload
+// word), the TCP sequence number, or the ICMP sequence with a payload word
+// that keeps the checksum fixed. A different path number gives a different
+// flow, which is how `--paths` enumerates the alternatives.
+
+use std::sync::atomic::{AtomicU16, Ordering};
+
+/// Paths probed by `--paths` at most
+pub const MAX_PATHS: u8 = 16;
+
+static NEXT_PROBE: AtomicU16 = AtomicU16::new(1);
+
+/// Identifier for the next UDP probe, used as its checksum; 0 (no
+/// checksum) and 0xFFFF (the other zero) are skipped
+pub fn next_probe_id() -> u16 {
+    loop {
+        let id = NEXT_PROBE.fetch_add(1, Ordering::Relaxed);
+        if id != 0 && id != 0xFFFF {
+            return id;
+        }
+    }
+}
+
+/// Source port of TCP probes on this path
+pub fn tcp_source_port(path: u8) -> u16 {
+    // Per process, so two traces at once don't share flows
+    33000 + (std::process::id() % 20000) as u16 + u16::from(path)
+}
+
+/// Source port of UDP probes; the destination port picks the path
+pub fn udp_source_port() -> u16 {
+    tcp_source_port(0)
+}
+
+/// Checksum every ICMP echo of this path carries
+pub fn icmp_checksum(path: u8) -> u16 {
+    0x4e00 + u16::from(path)
+}
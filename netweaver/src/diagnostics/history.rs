@@ -0,0 +1,188 @@
+// Trace history
+// Every finished trace is appended to a JSON-lines file, one trace per line,
+// so `trace --history` can answer "did my route to the datacenter change last
+// night?". Each target keeps its most recent traces; comparing the newest one
+// with the run before it names the hops that appeared, disappeared or moved
+// and the ones whose latency shifted.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+
+use super::TraceResult;
+
+/// Traces kept per target unless `--history-limit` says otherwise
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// A hop's average RTT has shifted when it moved by at least this many ms...
+pub const LATENCY_SHIFT_MS: f64 = 10.0;
+/// ...and by at least this fraction of its earlier value
+pub const LATENCY_SHIFT_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTrace {
+    /// Microseconds since the epoch
+    pub at: u64,
+    pub trace: TraceResult,
+}
+
+/// `$XDG_DATA_HOME/netweaver/traces.jsonl`, or `~/.local/share/netweaver/traces.jsonl`
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::utils::data_dir()?.join("traces.jsonl"))
+}
+
+/// Every stored trace, oldest first; a missing file is an empty history and
+/// lines that don't parse (a torn write, an older format) are skipped
+pub fn load_all(path: &Path) -> Result<Vec<StoredTrace>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read trace history '{}'", path.display())),
+    };
+    let mut traces = Vec::new();
+    for (number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(trace) => traces.push(trace),
+            Err(e) => tracing::debug!("{}:{}: skipping unreadable trace: {}", path.display(), number + 1, e),
+        }
+    }
+    Ok(traces)
+}
+
+/// Stored traces to `target`, oldest first
+pub fn load(path: &Path, target: &str) -> Result<Vec<StoredTrace>> {
+    let mut traces = load_all(path)?;
+    traces.retain(|stored| stored.trace.target == target);
+    Ok(traces)
+}
+
+/// Append `stored`, keeping only the newest `limit` traces to its target
+pub fn append(path: &Path, stored: StoredTrace, limit: usize) -> Result<()> {
+    let mut traces = load_all(path)?;
+    traces.push(stored);
+    prune(&mut traces, limit);
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let mut content = String::new();
+    for trace in &traces {
+        content.push_str(&serde_json::to_string(trace)?);
+        content.push('\n');
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content)
+        .with_context(|| format!("Failed to write trace history '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace trace history '{}'", path.display()))
+}
+
+/// Drop the oldest traces of every target that has more than `limit`
+pub fn prune(traces: &mut Vec<StoredTrace>, limit: usize) {
+    let mut kept = std::collections::HashMap::<String, usize>::new();
+    // Walk newest first so the survivors are the most recent ones
+    let mut keep: Vec<bool> = traces
+        .iter()
+        .rev()
+        .map(|stored| {
+            let count = kept.entry(stored.trace.target.clone()).or_default();
+            *count += 1;
+            *count <= limit
+        })
+        .collect();
+    keep.reverse();
+    let mut keep = keep.into_iter();
+    traces.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// The address answering at each hop, None for silent ones
+pub fn path_of(trace: &TraceResult) -> Vec<Option<Ipv4Addr>> {
+    trace.hops.iter().map(|hop| hop.ip).collect()
+}
+
+/// One distinct path among the stored traces
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathSighting {
+    pub path: Vec<Option<Ipv4Addr>>,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub times: usize,
+}
+
+/// The distinct paths in `history`, most recently seen first
+pub fn sightings(history: &[StoredTrace]) -> Vec<PathSighting> {
+    let mut seen: Vec<PathSighting> = Vec::new();
+    for stored in history {
+        let path = path_of(&stored.trace);
+        match seen.iter_mut().find(|sighting| sighting.path == path) {
+            Some(sighting) => {
+                sighting.first_seen = sighting.first_seen.min(stored.at);
+                sighting.last_seen = sighting.last_seen.max(stored.at);
+                sighting.times += 1;
+            }
+            None => seen.push(PathSighting { path, first_seen: stored.at, last_seen: stored.at, times: 1 }),
+        }
+    }
+    seen.sort_by_key(|sighting| std::cmp::Reverse(sighting.last_seen));
+    seen
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteChange {
+    /// A router that wasn't on the earlier path
+    Added { hop: u8, ip: Ipv4Addr },
+    /// A router that's no longer on the path
+    Removed { hop: u8, ip: Ipv4Addr },
+    /// A router still on the path, at a different distance
+    Moved { ip: Ipv4Addr, from_hop: u8, to_hop: u8 },
+    /// A router whose average RTT shifted significantly
+    Latency { hop: u8, ip: Ipv4Addr, from_ms: f64, to_ms: f64 },
+}
+
+impl std::fmt::Display for RouteChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteChange::Added { hop, ip } => write!(f, "+ hop {}: {} joined the path", hop, ip),
+            RouteChange::Removed { hop, ip } => write!(f, "- hop {}: {} left the path", hop, ip),
+            RouteChange::Moved { ip, from_hop, to_hop } => write!(f, "~ {} moved from hop {} to hop {}", ip, from_hop, to_hop),
+            RouteChange::Latency { hop, ip, from_ms, to_ms } => {
+                write!(f, "~ hop {}: {} latency {:.1}ms → {:.1}ms", hop, ip, from_ms, to_ms)
+            }
+        }
+    }
+}
+
+/// How the route changed from `previous` to `current`, in hop order; silent
+/// hops are ignored, since a dropped probe isn't a new router
+pub fn compare(previous: &TraceResult, current: &TraceResult) -> Vec<RouteChange> {
+    let hops_of = |trace: &TraceResult| -> Vec<(u8, Ipv4Addr, f64)> {
+        trace.hops.iter().filter_map(|hop| hop.ip.map(|ip| (hop.hop, ip, hop.avg_rtt))).collect()
+    };
+    let (before, after) = (hops_of(previous), hops_of(current));
+    let find = |hops: &[(u8, Ipv4Addr, f64)], ip: Ipv4Addr| hops.iter().find(|(_, other, _)| *other == ip).copied();
+
+    let mut changes = Vec::new();
+    for &(hop, ip, rtt) in &after {
+        match find(&before, ip) {
+            None => changes.push(RouteChange::Added { hop, ip }),
+            Some((from_hop, _, _)) if from_hop != hop => changes.push(RouteChange::Moved { ip, from_hop, to_hop: hop }),
+            Some((_, _, from_ms)) => {
+                let shift = (rtt - from_ms).abs();
+                if from_ms > 0.0 && rtt > 0.0 && shift >= LATENCY_SHIFT_MS && shift >= from_ms * LATENCY_SHIFT_RATIO {
+                    changes.push(RouteChange::Latency { hop, ip, from_ms, to_ms: rtt });
+                }
+            }
+        }
+    }
+    for &(hop, ip, _) in &before {
+        if find(&after, ip).is_none() {
+            changes.push(RouteChange::Removed { hop, ip });
+        }
+    }
+    changes.sort_by_key(|change| match change {
+        RouteChange::Added { hop, .. } | RouteChange::Removed { hop, .. } | RouteChange::Latency { hop, .. } => *hop,
+        RouteChange::Moved { to_hop, .. } => *to_hop,
+    });
+    changes
+}
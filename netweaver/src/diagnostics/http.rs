@@ -0,0 +1,578 @@
+// HTTP endpoint timing
+// "The API is slow" is really one of five things: name resolution, the TCP
+// handshake, the TLS handshake, the server thinking before its first byte,
+// or the body download. Each request here is made by hand (our resolver,
+// a plain connect, the shared TLS client, HTTP/1.1 with Connection: close)
+// so every phase gets its own clock, curl -w style. Redirects are followed
+// and the phases of a chain add up, like curl -L. The TLS client accepts any
+// certificate: this measures the endpoint, it doesn't vouch for it.
+// The latest result is kept in the data directory for `report`.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use rustls::{ProtocolVersion, ServerName};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::analytics::LatencyAnalyzer;
+use crate::scanner::{http::parse_response, tls};
+use crate::utils::{self, source};
+use crate::{report, status};
+
+/// Redirects followed before giving up on a chain
+pub const MAX_REDIRECTS: usize = 10;
+
+/// For one request of a chain, body included
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Response heads larger than this are cut off
+const MAX_HEAD: usize = 64 * 1024;
+
+/// Where a request goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, always starting with '/'
+    pub path: String,
+}
+
+impl Url {
+    /// "https://host[:port]/path"; a missing scheme means http
+    pub fn parse(url: &str) -> Result<Self> {
+        let (tls, rest) = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (true, rest),
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => (false, rest),
+            Some((scheme, _)) => bail!("Unsupported URL scheme '{}'", scheme),
+            None => (false, url),
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(idx) if rest[idx..].starts_with('?') => (&rest[..idx], format!("/{}", &rest[idx..])),
+            Some(idx) => (&rest[..idx], rest[idx..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        let default_port = if tls { 443 } else { 80 };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().with_context(|| format!("Invalid port in '{}'", url))?),
+            None => (authority, default_port),
+        };
+        if host.is_empty() {
+            bail!("No host in '{}'", url);
+        }
+        Ok(Self { tls, host: host.to_string(), port, path })
+    }
+
+    /// Target of a `Location` header: absolute, scheme-relative, or a path
+    /// absolute or relative to this one
+    pub fn join(&self, location: &str) -> Result<Self> {
+        if location.contains("://") {
+            Self::parse(location)
+        } else if let Some(rest) = location.strip_prefix("//") {
+            Self::parse(&format!("{}://{}", if self.tls { "https" } else { "http" }, rest))
+        } else if location.starts_with('/') {
+            Ok(Self { path: location.to_string(), ..self.clone() })
+        } else {
+            let base = self.path.split('?').next().unwrap_or("/");
+            let dir = &base[..base.rfind('/').map_or(0, |idx| idx + 1)];
+            Ok(Self { path: format!("{}{}", dir, location), ..self.clone() })
+        }
+    }
+
+    fn default_port(&self) -> bool {
+        self.port == if self.tls { 443 } else { 80 }
+    }
+
+    /// Value of the Host header
+    fn authority(&self) -> String {
+        match self.default_port() {
+            true => self.host.clone(),
+            false => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}{}", if self.tls { "https" } else { "http" }, self.authority(), self.path)
+    }
+}
+
+/// Time spent in each phase of a request, in ms; DNS is None for IP
+/// literals and TLS for plain HTTP
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<f64>,
+    pub connect_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<f64>,
+    /// From the request being sent to the first response byte
+    pub ttfb_ms: f64,
+    /// From the first response byte to the last
+    pub download_ms: f64,
+    pub total_ms: f64,
+}
+
+impl PhaseTimings {
+    /// Phases of a redirect chain added up
+    fn add(&mut self, other: &PhaseTimings) {
+        let sum = |a: Option<f64>, b: Option<f64>| if a.is_none() && b.is_none() { None } else { Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)) };
+        self.dns_ms = sum(self.dns_ms, other.dns_ms);
+        self.connect_ms += other.connect_ms;
+        self.tls_ms = sum(self.tls_ms, other.tls_ms);
+        self.ttfb_ms += other.ttfb_ms;
+        self.download_ms += other.download_ms;
+        self.total_ms += other.total_ms;
+    }
+}
+
+/// One request of a chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpRequestTiming {
+    pub url: String,
+    pub address: SocketAddr,
+    pub status: u16,
+    /// Where a redirect pointed, resolved against `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_version: Option<String>,
+    /// Response body size as received
+    pub body_bytes: u64,
+    pub timings: PhaseTimings,
+}
+
+/// One run of `--count`: the request and any redirects it led to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HttpAttempt {
+    pub requests: Vec<HttpRequestTiming>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HttpAttempt {
+    /// Phases summed over the whole chain
+    pub fn timings(&self) -> PhaseTimings {
+        let mut total = PhaseTimings::default();
+        for request in &self.requests {
+            total.add(&request.timings);
+        }
+        total
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PhaseStats {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+}
+
+impl PhaseStats {
+    /// None without any samples
+    fn of(samples: impl Iterator<Item = f64>) -> Option<Self> {
+        let samples: Vec<f64> = samples.collect();
+        let mut latency = LatencyAnalyzer::new(samples.len().max(1));
+        for &sample in &samples {
+            latency.add_sample(sample);
+        }
+        (!latency.is_empty()).then(|| Self { min_ms: latency.min(), avg_ms: latency.average(), max_ms: latency.max() })
+    }
+}
+
+/// Per-phase statistics over the successful attempts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HttpPhaseStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<PhaseStats>,
+    pub connect: PhaseStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<PhaseStats>,
+    pub ttfb: PhaseStats,
+    pub download: PhaseStats,
+    pub total: PhaseStats,
+}
+
+impl HttpPhaseStats {
+    /// None when no attempt succeeded
+    pub fn new(attempts: &[HttpAttempt]) -> Option<Self> {
+        let timings: Vec<PhaseTimings> = attempts.iter()
+            .filter(|attempt| attempt.error.is_none() && !attempt.requests.is_empty())
+            .map(HttpAttempt::timings)
+            .collect();
+        Some(Self {
+            dns: PhaseStats::of(timings.iter().filter_map(|t| t.dns_ms)),
+            connect: PhaseStats::of(timings.iter().map(|t| t.connect_ms))?,
+            tls: PhaseStats::of(timings.iter().filter_map(|t| t.tls_ms)),
+            ttfb: PhaseStats::of(timings.iter().map(|t| t.ttfb_ms))?,
+            download: PhaseStats::of(timings.iter().map(|t| t.download_ms))?,
+            total: PhaseStats::of(timings.iter().map(|t| t.total_ms))?,
+        })
+    }
+
+    /// (label, stats) of each phase that took place, in request order
+    pub fn phases(&self) -> Vec<(&'static str, PhaseStats)> {
+        [("DNS", self.dns), ("Connect", Some(self.connect)), ("TLS", self.tls), ("TTFB", Some(self.ttfb)),
+         ("Download", Some(self.download))]
+            .into_iter()
+            .filter_map(|(label, stats)| stats.map(|stats| (label, stats)))
+            .collect()
+    }
+
+    /// The phase with the highest average
+    pub fn slowest(&self) -> (&'static str, PhaseStats) {
+        self.phases()
+            .into_iter()
+            .max_by(|a, b| a.1.avg_ms.total_cmp(&b.1.avg_ms))
+            .unwrap_or(("Connect", self.connect))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redirect {
+    pub from: String,
+    pub status: u16,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpTimingResult {
+    pub url: String,
+    pub method: String,
+    pub finished_at: DateTime<Utc>,
+    /// Status of the last response of the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Where the redirects ended up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<Redirect>,
+    pub failures: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<HttpPhaseStats>,
+    pub attempts: Vec<HttpAttempt>,
+}
+
+impl HttpTimingResult {
+    /// The redirect chain is the one the first successful attempt took
+    pub fn new(url: String, method: String, attempts: Vec<HttpAttempt>) -> Self {
+        let chain = attempts.iter().find(|attempt| attempt.error.is_none()).map(|attempt| &attempt.requests);
+        let redirects = chain.map(|requests| {
+            requests.iter()
+                .filter_map(|request| request.location.as_ref().map(|to| Redirect {
+                    from: request.url.clone(),
+                    status: request.status,
+                    to: to.clone(),
+                }))
+                .collect()
+        }).unwrap_or_default();
+        let last = chain.and_then(|requests| requests.last());
+        Self {
+            url,
+            method,
+            finished_at: Utc::now(),
+            status: last.map(|request| request.status),
+            final_url: last.map(|request| request.url.clone()),
+            redirects,
+            failures: attempts.iter().filter(|attempt| attempt.error.is_some()).count(),
+            stats: HttpPhaseStats::new(&attempts),
+            attempts,
+        }
+    }
+}
+
+/// `$XDG_DATA_HOME/netweaver/http.json`, the latest timing result
+pub fn latest_path() -> Result<PathBuf> {
+    Ok(utils::data_dir()?.join("http.json"))
+}
+
+/// The latest timing result, if one ever ran here
+pub fn load_latest(path: &Path) -> Result<Option<HttpTimingResult>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+    }
+}
+
+fn store_latest(path: &Path, result: &HttpTimingResult) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(result)?)
+        .with_context(|| format!("Failed to write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace '{}'", path.display()))
+}
+
+pub async fn run_http(url: String, count: u32, method: String, output: Option<String>) -> Result<()> {
+    status!("{}", "NetWeaver HTTP Timing".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
+
+    let start = Url::parse(&url)?;
+    let method = method.to_ascii_uppercase();
+    status!("🎯 URL: {}", start.to_string().bright_yellow());
+    status!("📡 {} × {}\n", method, count);
+
+    let mut attempts = Vec::new();
+    for seq in 1..=count {
+        let attempt = tokio::select! {
+            attempt = follow_chain(&start, &method) => attempt,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        print_attempt(seq, &attempt);
+        attempts.push(attempt);
+    }
+
+    let result = HttpTimingResult::new(start.to_string(), method, attempts);
+    display_result(&result);
+
+    if let Err(e) = latest_path().and_then(|path| store_latest(&path, &result)) {
+        status!("⚠️  Could not keep the result for reports: {:#}", e);
+    }
+    if let Some(output_path) = output {
+        super::save_result(&result, &output_path)?;
+        status!("\n💾 HTTP timing saved to: {}", output_path.bright_green());
+    }
+    Ok(())
+}
+
+/// Request `start`, following redirects up to `MAX_REDIRECTS`
+async fn follow_chain(start: &Url, method: &str) -> HttpAttempt {
+    let mut attempt = HttpAttempt::default();
+    let mut url = start.clone();
+    let mut method = method.to_string();
+    loop {
+        let mut request = match tokio::time::timeout(REQUEST_TIMEOUT, request(&url, &method)).await {
+            Ok(Ok(request)) => request,
+            Ok(Err(e)) => {
+                attempt.error = Some(format!("{:#}", e));
+                return attempt;
+            }
+            Err(_) => {
+                attempt.error = Some(format!("{} timed out after {}s", url, REQUEST_TIMEOUT.as_secs()));
+                return attempt;
+            }
+        };
+        let next = request.location.as_deref().map(|location| url.join(location));
+        let status = request.status;
+        if let Some(Ok(next)) = &next {
+            request.location = Some(next.to_string());
+        }
+        attempt.requests.push(request);
+        match next {
+            None => return attempt,
+            Some(_) if attempt.requests.len() > MAX_REDIRECTS => {
+                attempt.error = Some(format!("More than {} redirects", MAX_REDIRECTS));
+                return attempt;
+            }
+            Some(Ok(next)) => {
+                // Browsers and curl turn these into GETs
+                if status == 303 || (matches!(status, 301 | 302) && method == "POST") {
+                    method = if method == "HEAD" { method } else { "GET".to_string() };
+                }
+                url = next;
+            }
+            Some(Err(e)) => {
+                attempt.error = Some(format!("Bad redirect: {:#}", e));
+                return attempt;
+            }
+        }
+    }
+}
+
+/// One request with each phase timed
+async fn request(url: &Url, method: &str) -> Result<HttpRequestTiming> {
+    let started = Instant::now();
+    let ms = |since: Instant| since.elapsed().as_secs_f64() * 1000.0;
+
+    let (ip, dns_ms) = match url.host.parse::<Ipv4Addr>() {
+        Ok(ip) => (ip, None),
+        Err(_) => {
+            let ip = utils::network::resolve_hostname(&url.host).await?;
+            (ip, Some(ms(started)))
+        }
+    };
+    let address = SocketAddr::new(IpAddr::V4(ip), url.port);
+
+    let connecting = Instant::now();
+    let stream = source::connect(address).await.with_context(|| format!("Failed to connect to {}", address))?;
+    let connect_ms = ms(connecting);
+
+    let (exchange, tls_ms, tls_version) = if url.tls {
+        let handshaking = Instant::now();
+        let server_name = ServerName::try_from(url.host.as_str()).unwrap_or(ServerName::IpAddress(IpAddr::V4(ip)));
+        let stream = tls::connector().connect(server_name, stream).await
+            .with_context(|| format!("TLS handshake with {} failed", address))?;
+        let tls_ms = ms(handshaking);
+        let version = stream.get_ref().1.protocol_version().map(tls_version_name);
+        (exchange(stream, url, method).await?, Some(tls_ms), version)
+    } else {
+        (exchange(stream, url, method).await?, None, None)
+    };
+
+    let response = parse_response(&exchange.head).context("Not an HTTP response")?;
+    let location = match response.status {
+        300..=399 => response.header("Location").map(str::to_string),
+        _ => None,
+    };
+    Ok(HttpRequestTiming {
+        url: url.to_string(),
+        address,
+        status: response.status,
+        location,
+        tls_version,
+        body_bytes: exchange.body_bytes,
+        timings: PhaseTimings {
+            dns_ms,
+            connect_ms,
+            tls_ms,
+            ttfb_ms: exchange.ttfb_ms,
+            download_ms: exchange.download_ms,
+            total_ms: ms(started),
+        },
+    })
+}
+
+fn tls_version_name(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_3 => "TLS 1.3".to_string(),
+        ProtocolVersion::TLSv1_2 => "TLS 1.2".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+struct Exchange {
+    head: Vec<u8>,
+    body_bytes: u64,
+    ttfb_ms: f64,
+    download_ms: f64,
+}
+
+/// Send the request and read the response to its end: the Content-Length,
+/// or the server closing the connection
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, url: &Url, method: &str) -> Result<Exchange> {
+    let body_header = if matches!(method, "POST" | "PUT" | "PATCH") { "Content-Length: 0\r\n" } else { "" };
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: netweaver\r\nAccept: */*\r\n{}Connection: close\r\n\r\n",
+        method, url.path, url.authority(), body_header
+    );
+    stream.write_all(request.as_bytes()).await.context("Failed to send the request")?;
+    let sent = Instant::now();
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut head = Vec::new();
+    let mut first_byte = None;
+    let mut body_bytes = 0u64;
+    let mut expected: Option<u64> = None;
+    let mut head_done = false;
+    loop {
+        let len = match stream.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(len) => len,
+            // Servers that drop TLS without a close_notify sent everything
+            Err(_) if head_done => break,
+            Err(e) => return Err(e).context("Failed to read the response"),
+        };
+        first_byte.get_or_insert_with(Instant::now);
+        if head_done {
+            body_bytes += len as u64;
+        } else {
+            head.extend_from_slice(&buf[..len]);
+            if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n").map(|idx| idx + 4) {
+                body_bytes = (head.len() - end) as u64;
+                head.truncate(end);
+                head_done = true;
+                let response = parse_response(&head).context("Not an HTTP response")?;
+                expected = if method == "HEAD" || matches!(response.status, 100..=199 | 204 | 304) {
+                    Some(0)
+                } else {
+                    response.header("Content-Length").and_then(|len| len.parse().ok())
+                };
+            } else if head.len() > MAX_HEAD {
+                bail!("Response headers larger than {} KB", MAX_HEAD / 1024);
+            }
+        }
+        if expected.is_some_and(|expected| body_bytes >= expected) {
+            break;
+        }
+    }
+    let Some(first_byte) = first_byte else {
+        bail!("Connection closed without a response");
+    };
+    Ok(Exchange {
+        head,
+        body_bytes,
+        ttfb_ms: first_byte.duration_since(sent).as_secs_f64() * 1000.0,
+        download_ms: first_byte.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+fn print_attempt(seq: u32, attempt: &HttpAttempt) {
+    if let Some(error) = &attempt.error {
+        report!("{}", format!("[{}] {}", seq, error).bright_red());
+        return;
+    }
+    let Some(last) = attempt.requests.last() else {
+        return;
+    };
+    let t = attempt.timings();
+    let optional = |label: &str, value: Option<f64>| value.map(|ms| format!("{} {:.1}  ", label, ms)).unwrap_or_default();
+    report!("[{}] {} {} in {}  ({}connect {:.1}  {}ttfb {:.1}  download {:.1})",
+            seq, status_colored(last.status), utils::format_bytes(last.body_bytes),
+            format!("{:.1}ms", t.total_ms).bright_yellow(),
+            optional("dns", t.dns_ms), t.connect_ms, optional("tls", t.tls_ms), t.ttfb_ms, t.download_ms);
+}
+
+fn status_colored(status: u16) -> colored::ColoredString {
+    match status {
+        200..=299 => status.to_string().bright_green(),
+        300..=399 => status.to_string().bright_yellow(),
+        _ => status.to_string().bright_red(),
+    }
+}
+
+fn display_result(result: &HttpTimingResult) {
+    report!("\n{}", "HTTP Timing".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
+
+    if !result.redirects.is_empty() {
+        report!("{}", "Redirects:".bright_white());
+        for redirect in &result.redirects {
+            report!("  {} {} → {}", status_colored(redirect.status), redirect.from, redirect.to.bright_yellow());
+        }
+    }
+    if let (Some(status), Some(final_url)) = (result.status, &result.final_url) {
+        report!("{} {} {}", "Final:".bright_white(), status_colored(status), final_url);
+    }
+    let tls = result.attempts.iter().flat_map(|attempt| &attempt.requests).find_map(|request| request.tls_version.as_ref());
+    if let Some(version) = tls {
+        report!("{} {}", "TLS:".bright_white(), version);
+    }
+    if result.failures > 0 {
+        report!("{} {} of {}", "Failed:".bright_red(), result.failures, result.attempts.len());
+    }
+
+    let Some(stats) = &result.stats else {
+        report!("{}", "No request succeeded".bright_red());
+        return;
+    };
+    report!("\n  {:10} {:>10} {:>10} {:>10}", "Phase".bright_white(), "Min", "Avg", "Max");
+    for (label, phase) in stats.phases().into_iter().chain([("Total", stats.total)]) {
+        report!("  {:10} {:>10} {:>10} {:>10}", label, format!("{:.1}ms", phase.min_ms),
+                format!("{:.1}ms", phase.avg_ms), format!("{:.1}ms", phase.max_ms));
+    }
+    let (label, slowest) = stats.slowest();
+    if stats.total.avg_ms > 0.0 {
+        report!("\n🐢 Slowest phase: {} ({:.0}% of the time)",
+                label.bright_yellow(), slowest.avg_ms / stats.total.avg_ms * 100.0);
+    }
+}
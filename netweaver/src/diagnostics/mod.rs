@@ -1,22 +1,150 @@
 use anyhow::{Result, Context};
 use colored::Colorize;
+use futures::{stream, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use serde::{Serialize, Deserialize};
 
+use crate::analytics::LatencyAnalyzer;
+use crate::output::EventStream;
 use crate::utils;
+use crate::utils::icmp;
+use crate::{report, status};
+
+pub mod asn;
+pub mod capstats;
+pub mod capture;
+pub mod compare;
+pub mod connections;
+pub mod dissect;
+pub mod dns;
+pub mod filter;
+pub mod flow;
+pub mod history;
+pub mod http;
+pub mod mtr;
+pub mod pcap;
+pub mod ping;
+pub mod routemap;
+pub mod tcp;
+pub mod throughput;
+pub mod tls;
+pub mod traffic;
+pub mod udp;
+
+/// What a traceroute sends with each TTL
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeProtocol {
+    /// Echo requests
+    #[default]
+    Icmp,
+    /// Datagrams to unused high ports, like classic Unix traceroute
+    Udp,
+    /// SYNs to one port, which firewalls let through to the service
+    Tcp,
+}
+
+impl std::fmt::Display for ProbeProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ProbeProtocol::Icmp => "ICMP",
+            ProbeProtocol::Udp => "UDP",
+            ProbeProtocol::Tcp => "TCP",
+        })
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TraceHop {
     pub hop: u8,
     pub ip: Option<Ipv4Addr>,
     pub hostname: Option<String>,
+    /// One entry per probe, -1 for those that got no answer
     pub rtt_ms: Vec<f64>,
+    /// Over the answered probes only; 0 when none answered
     pub avg_rtt: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_rtt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rtt: Option<f64>,
+    /// Spread of the answered probes; None with fewer than two answers
+    #[serde(default)]
+    pub stddev_ms: Option<f64>,
+    /// Mean difference between consecutive answered probes (RFC 3550 style)
+    #[serde(default)]
+    pub jitter_ms: Option<f64>,
     pub packet_loss: f64,
+    #[serde(default)]
+    pub probe_protocol: ProbeProtocol,
+    /// Accumulated over every cycle of a continuous trace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<mtr::HopStats>,
+    /// Origin AS of the hop's address, with `--asn`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_name: Option<String>,
+    /// ISO country code, with `--geoip`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Latitude and longitude, with a `--geoip` City database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<[f64; 2]>,
+}
+
+/// Average, best and worst RTT of a hop's answered probes, in ms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttSummary {
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Summarize `rtt_ms` as stored in `TraceHop`, skipping lost probes; None
+/// when no probe was answered
+pub fn rtt_summary(rtt_ms: &[f64]) -> Option<RttSummary> {
+    let answered: Vec<f64> = rtt_ms.iter().copied().filter(|&ms| ms >= 0.0).collect();
+    if answered.is_empty() {
+        return None;
+    }
+    Some(RttSummary {
+        avg: answered.iter().sum::<f64>() / answered.len() as f64,
+        min: answered.iter().copied().fold(f64::INFINITY, f64::min),
+        max: answered.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    })
+}
+
+/// Standard deviation and jitter of a hop's answered probes, in ms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttVariation {
+    pub stddev: f64,
+    pub jitter: f64,
 }
 
+/// Variation of `rtt_ms` as stored in `TraceHop`, skipping lost probes; None
+/// when fewer than two probes were answered
+pub fn rtt_variation(rtt_ms: &[f64]) -> Option<RttVariation> {
+    let mut latency = LatencyAnalyzer::new(rtt_ms.len());
+    for &ms in rtt_ms.iter().filter(|&&ms| ms >= 0.0) {
+        latency.add_sample(ms);
+    }
+    (latency.len() >= 2).then(|| RttVariation {
+        stddev: latency.std_dev(),
+        jitter: latency.jitter(),
+    })
+}
+
+/// Jitter above which a hop is flagged in the route analysis; VoIP starts
+/// to suffer around here
+pub const JITTER_WARN_MS: f64 = 30.0;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TraceResult {
     pub target: String,
@@ -24,96 +152,447 @@ pub struct TraceResult {
     pub hops: Vec<TraceHop>,
     pub completed: bool,
     pub total_time: Duration,
+    #[serde(default)]
+    pub probe_protocol: ProbeProtocol,
+    /// Destination port of TCP probes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// The same routers kept answering at non-adjacent TTLs
+    #[serde(default)]
+    pub loop_detected: bool,
+    /// Routers of the loop, in the order they first appeared in it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub loop_routers: Vec<Ipv4Addr>,
+    /// With `--paths`, each distinct route found, the first being `hops`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<Vec<TraceHop>>,
+}
+
+/// Packets bouncing between routers, as seen in a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingLoop {
+    pub routers: Vec<Ipv4Addr>,
+    /// First and last TTL at which a router of the loop answered
+    pub first_ttl: u8,
+    pub last_ttl: u8,
+}
+
+/// Find a router answering again at a later, non-adjacent TTL with other
+/// routers in between (A, B, A, B, ...). The same address at consecutive
+/// TTLs is left alone: MPLS tunnels and some load balancers show up that way
+pub fn detect_loop(hops: &[TraceHop]) -> Option<RoutingLoop> {
+    let answered: Vec<(u8, Ipv4Addr)> = hops.iter().filter_map(|hop| Some((hop.hop, hop.ip?))).collect();
+    let mut last_seen: HashMap<Ipv4Addr, (usize, u8)> = HashMap::new();
+    for (index, &(ttl, ip)) in answered.iter().enumerate() {
+        if let Some(&(previous, previous_ttl)) = last_seen.get(&ip) {
+            let others_between = answered[previous + 1..index].iter().any(|&(_, other)| other != ip);
+            if ttl > previous_ttl + 1 && others_between {
+                let mut routers: Vec<Ipv4Addr> = Vec::new();
+                for &(_, router) in &answered[previous..=index] {
+                    if !routers.contains(&router) {
+                        routers.push(router);
+                    }
+                }
+                let last_ttl = answered[index..]
+                    .iter()
+                    .take_while(|(_, ip)| routers.contains(ip))
+                    .last()
+                    .map_or(ttl, |&(ttl, _)| ttl);
+                return Some(RoutingLoop { routers, first_ttl: previous_ttl, last_ttl });
+            }
+        }
+        last_seen.insert(ip, (index, ttl));
+    }
+    None
 }
 
+/// TTLs probed at once; hops are still reported in order
+pub const PARALLEL_TTLS: usize = 8;
+
+/// How a trace probes each hop
+#[derive(Debug, Clone)]
+pub struct TraceOptions {
+    pub max_hops: u8,
+    pub probes: u8,
+    /// Consecutive silent hops before giving up; 0 never gives up
+    pub max_unresponsive: u8,
+    pub protocol: ProbeProtocol,
+    /// Destination port of TCP probes
+    pub port: u16,
+    /// Keep cycling through the hops with a live table until 'q'
+    pub continuous: bool,
+    /// Run this many cycles without the live table and print it once
+    pub report_cycles: Option<u32>,
+    /// Look up each hop's origin AS
+    pub asn: bool,
+    /// GeoLite2 database to look up each hop's country in
+    pub geoip: Option<PathBuf>,
+    /// Write hops (or cycles) as JSON lines here (`-` for stdout)
+    pub stream_json: Option<String>,
+    /// Flows to trace, looking for distinct ECMP paths; 1 traces a single path
+    pub paths: u8,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            max_hops: 30,
+            probes: 3,
+            max_unresponsive: 5,
+            protocol: ProbeProtocol::Icmp,
+            port: 80,
+            continuous: false,
+            report_cycles: None,
+            asn: false,
+            geoip: None,
+            stream_json: None,
+            paths: 1,
+        }
+    }
+}
+
+/// Trace the route to each target; several are traced at once and compared
 pub async fn run_trace(
-    target: String,
-    max_hops: u8,
-    probes: u8,
+    targets: Vec<String>,
+    opts: TraceOptions,
     history: bool,
+    history_limit: usize,
     output: Option<String>,
 ) -> Result<()> {
-    println!("{}", "NetWeaver Traceroute".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+    status!("{}", "NetWeaver Traceroute".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
+    match <[String; 1]>::try_from(targets) {
+        Ok([target]) => trace_one(target, opts, history, history_limit, output).await,
+        Err(targets) => trace_many(targets, opts, history, history_limit, output).await,
+    }
+}
+
+async fn trace_one(
+    target: String,
+    opts: TraceOptions,
+    history: bool,
+    history_limit: usize,
+    output: Option<String>,
+) -> Result<()> {
     let target_ip = utils::network::resolve_hostname(&target).await
         .context("Failed to resolve target")?;
     
-    println!("🎯 Target: {} ({})", target.bright_yellow(), target_ip.to_string().bright_green());
-    println!("🔢 Max hops: {}", max_hops);
-    println!("📊 Probes per hop: {}\n", probes);
+    status!("🎯 Target: {} ({})", target.bright_yellow(), target_ip.to_string().bright_green());
+    status!("🔢 Max hops: {}", opts.max_hops);
+    match opts.protocol {
+        ProbeProtocol::Tcp => status!("📊 Probes per hop: {} (TCP port {})\n", opts.probes, opts.port),
+        protocol => status!("📊 Probes per hop: {} ({})\n", opts.probes, protocol),
+    }
     
-    let result = perform_traceroute(target.clone(), target_ip, max_hops, probes).await?;
+    if opts.paths > 1 && !icmp::raw_available() {
+        anyhow::bail!("--paths needs root (or CAP_NET_RAW) to keep each path's probes on one flow");
+    }
+    let mut stream = opts.stream_json.as_deref().map(EventStream::open).transpose()?;
+    let mut result = if opts.continuous || opts.report_cycles.is_some() {
+        mtr::run(target.clone(), target_ip, &opts, stream.as_mut()).await?
+    } else {
+        perform_traceroute(target.clone(), target_ip, &opts, |hop| {
+            match stream.as_mut() {
+                Some(stream) => stream.emit("hop", hop)?,
+                None => print_hop(hop),
+            }
+            Ok(())
+        })
+        .await?
+    };
+    mark_loop(&mut result);
+    if let Some(stream) = &mut stream {
+        stream.emit("trace", &result)?;
+    }
     
     display_trace_result(&result)?;
+    record_history(&result, history, history_limit);
     
-    if history {
-        println!("\n{}", "Historical Route Data".bright_cyan().bold());
-        println!("(Feature coming soon - tracks route changes over time)");
+    if let Some(output_path) = output {
+        if output_path.ends_with(".html") || output_path.ends_with(".htm") {
+            std::fs::write(&output_path, routemap::render(&result))
+                .with_context(|| format!("Failed to write '{}'", output_path))?;
+            status!("\n🗺  Route map saved to: {}", output_path.bright_green());
+        } else {
+            save_result(&result, &output_path)?;
+            status!("\n💾 Trace saved to: {}", output_path.bright_green());
+        }
+    }
+    
+    Ok(())
+}
+
+/// Trace several targets concurrently, then show each route and where they part
+async fn trace_many(
+    targets: Vec<String>,
+    opts: TraceOptions,
+    history: bool,
+    history_limit: usize,
+    output: Option<String>,
+) -> Result<()> {
+    if opts.continuous || opts.report_cycles.is_some() {
+        anyhow::bail!("--continuous and --report-cycles follow one target at a time");
+    }
+    if output.as_deref().is_some_and(|path| path.ends_with(".html") || path.ends_with(".htm")) {
+        anyhow::bail!("A route map shows one trace; export several as .json or .yaml");
+    }
+    if opts.paths > 1 && !icmp::raw_available() {
+        anyhow::bail!("--paths needs root (or CAP_NET_RAW) to keep each path's probes on one flow");
+    }
+    
+    let mut resolved = Vec::new();
+    for target in targets {
+        let ip = utils::network::resolve_hostname(&target).await
+            .with_context(|| format!("Failed to resolve {}", target))?;
+        status!("🎯 Target: {} ({})", target.bright_yellow(), ip.to_string().bright_green());
+        resolved.push((target, ip));
+    }
+    status!("🔢 Max hops: {}", opts.max_hops);
+    match opts.protocol {
+        ProbeProtocol::Tcp => status!("📊 Probes per hop: {} (TCP port {})\n", opts.probes, opts.port),
+        protocol => status!("📊 Probes per hop: {} ({})\n", opts.probes, protocol),
+    }
+    status!("{}", format!("Tracing {} routes at once...", resolved.len()).dimmed());
+    
+    // Probes to different targets quote different destinations back, so the
+    // traces can't take each other's answers
+    let traces = resolved.into_iter().map(|(target, ip)| perform_traceroute(target, ip, &opts, |_| Ok(())));
+    let mut results = futures::future::try_join_all(traces).await?;
+    
+    let mut stream = opts.stream_json.as_deref().map(EventStream::open).transpose()?;
+    for result in &mut results {
+        mark_loop(result);
+        match stream.as_mut() {
+            Some(stream) => stream.emit("trace", result)?,
+            None => {
+                report!("\n{}", format!("Route to {} ({})", result.target, result.target_ip).bright_cyan().bold());
+                for hop in &result.hops {
+                    print_hop(hop);
+                }
+            }
+        }
+        display_trace_result(result)?;
+        record_history(result, history, history_limit);
+    }
+    
+    let comparison = compare::compare(&results);
+    match stream.as_mut() {
+        Some(stream) => stream.emit("comparison", &comparison)?,
+        None => compare::print_comparison(&comparison),
     }
     
     if let Some(output_path) = output {
-        save_trace_result(&result, &output_path)?;
-        println!("\n💾 Trace saved to: {}", output_path.bright_green());
+        save_result(&TraceComparison { traces: results, comparison }, &output_path)?;
+        status!("\n💾 Traces saved to: {}", output_path.bright_green());
     }
     
     Ok(())
 }
 
+/// What a trace of several targets exports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceComparison {
+    pub traces: Vec<TraceResult>,
+    pub comparison: compare::RouteComparison,
+}
+
+fn mark_loop(result: &mut TraceResult) {
+    if let Some(routing_loop) = detect_loop(&result.hops) {
+        result.loop_detected = true;
+        result.loop_routers = routing_loop.routers;
+    }
+}
+
+/// Add `result` to the trace history, showing how the route changed with `show`
+fn record_history(result: &TraceResult, show: bool, limit: usize) {
+    let earlier = match update_history(result, limit) {
+        Ok(earlier) => earlier,
+        Err(e) => {
+            tracing::warn!("Failed to update trace history: {:#}", e);
+            Vec::new()
+        }
+    };
+    if show {
+        display_route_history(&earlier, result);
+    }
+}
+
 async fn perform_traceroute(
     target: String,
     target_ip: Ipv4Addr,
-    max_hops: u8,
-    probes: u8,
+    opts: &TraceOptions,
+    on_hop: impl FnMut(&TraceHop) -> Result<()>,
 ) -> Result<TraceResult> {
+    let mut annotator = asn::Annotator::new(opts.asn, opts.geoip.as_deref())?;
     let start = Instant::now();
-    let mut hops = Vec::new();
+    let (hops, completed) = trace_path(target_ip, opts, 1, &mut annotator, on_hop).await?;
     
-    for ttl in 1..=max_hops {
-        let hop = probe_hop(target_ip, ttl, probes).await?;
-        
-        print_hop(&hop);
-        
-        let reached_target = hop.ip.map(|ip| ip == target_ip).unwrap_or(false);
-        hops.push(hop);
-        
-        if reached_target {
-            break;
+    // Each further flow may hash onto another of the ECMP links; the
+    // same route seen again isn't worth reporting twice
+    let mut paths = vec![hops.clone()];
+    for path in 2..=opts.paths {
+        status!("{}", format!("Probing path {}/{}...", path, opts.paths).dimmed());
+        let (hops, _) = trace_path(target_ip, opts, path, &mut annotator, |_| Ok(())).await?;
+        if !paths.iter().any(|seen| same_path(seen, &hops)) {
+            paths.push(hops);
         }
     }
+    if opts.paths == 1 {
+        paths.clear();
+    }
     
     Ok(TraceResult {
         target,
         target_ip,
         hops,
-        completed: true,
+        completed,
         total_time: start.elapsed(),
+        probe_protocol: opts.protocol,
+        port: (opts.protocol == ProbeProtocol::Tcp).then_some(opts.port),
+        loop_detected: false,
+        loop_routers: Vec::new(),
+        paths,
+    })
+}
+
+/// Trace one flow (see `flow`) hop by hop, handing each hop to `on_hop` as
+/// it comes in; returns the hops and whether the target was reached
+async fn trace_path(
+    target_ip: Ipv4Addr,
+    opts: &TraceOptions,
+    path: u8,
+    annotator: &mut Option<asn::Annotator>,
+    mut on_hop: impl FnMut(&TraceHop) -> Result<()>,
+) -> Result<(Vec<TraceHop>, bool)> {
+    let TraceOptions { max_hops, probes, max_unresponsive, protocol, port, .. } = *opts;
+    let probe = |ttl| probe_hop(target_ip, ttl, probes, protocol, port, path);
+    let (hops, end) = walk_hops(target_ip, max_hops, max_unresponsive, probe, async |hop: &mut TraceHop| {
+        if let Some(annotator) = annotator.as_mut() {
+            annotator.annotate(hop).await;
+        }
+        on_hop(hop)
+    }).await?;
+    if let (Some(TraceEnd::Unresponsive(silent)), 1) = (end, path) {
+        status!("{}", format!("Giving up after {} silent hops", silent).bright_yellow());
+    }
+    Ok((hops, end == Some(TraceEnd::Reached)))
+}
+
+/// Probe TTLs 1..=max_hops with `probe`, PARALLEL_TTLS at a time, and hand
+/// each hop to `on_hop` in TTL order until `trace_end` says to stop; returns
+/// the hops up to there and why it stopped (None when `max_hops` is 0)
+pub async fn walk_hops<F>(
+    target: Ipv4Addr,
+    max_hops: u8,
+    max_unresponsive: u8,
+    probe: impl FnMut(u8) -> F,
+    mut on_hop: impl AsyncFnMut(&mut TraceHop) -> Result<()>,
+) -> Result<(Vec<TraceHop>, Option<TraceEnd>)>
+where
+    F: Future<Output = Result<TraceHop>>,
+{
+    let mut hops = Vec::new();
+    
+    // Probes carry their own id/sequence or checksum, so answers to
+    // different TTLs can't be confused; `buffered` hands the hops back in
+    // TTL order and dropping it at the end abandons the probes past the target
+    let mut pending = stream::iter(1..=max_hops).map(probe).buffered(PARALLEL_TTLS);
+    while let Some(hop) = pending.next().await {
+        let mut hop = hop?;
+        on_hop(&mut hop).await?;
+        hops.push(hop);
+        
+        let end = trace_end(&hops, target, max_hops, max_unresponsive);
+        if end.is_some() {
+            return Ok((hops, end));
+        }
+    }
+    Ok((hops, None))
+}
+
+/// Why a trace stopped probing further TTLs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEnd {
+    /// The target answered the last hop
+    Reached,
+    /// This many silent hops in a row
+    Unresponsive(usize),
+    /// Every TTL up to max_hops was probed
+    Exhausted,
+}
+
+/// Whether a trace that got `hops` so far, in TTL order, should stop; None
+/// to probe the next TTL
+pub fn trace_end(hops: &[TraceHop], target: Ipv4Addr, max_hops: u8, max_unresponsive: u8) -> Option<TraceEnd> {
+    let last = hops.last()?;
+    if last.ip == Some(target) {
+        return Some(TraceEnd::Reached);
+    }
+    if hops.len() >= usize::from(max_hops) {
+        return Some(TraceEnd::Exhausted);
+    }
+    // A dead path would otherwise cost probes × timeout for every hop left
+    let silent = hops.iter().rev().take_while(|hop| hop.ip.is_none()).count();
+    (max_unresponsive > 0 && silent >= usize::from(max_unresponsive)).then_some(TraceEnd::Unresponsive(silent))
+}
+
+/// Whether two traces took the same route: no TTL where both got an answer
+/// from different routers. A silent hop matches anything
+pub fn same_path(a: &[TraceHop], b: &[TraceHop]) -> bool {
+    a.iter().zip(b).all(|(a, b)| match (a.ip, b.ip) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
     })
 }
 
-async fn probe_hop(target: Ipv4Addr, ttl: u8, probes: u8) -> Result<TraceHop> {
+/// Probe one TTL along flow `path`; `port` is only used by TCP probes
+pub(crate) async fn probe_hop(
+    target: Ipv4Addr,
+    ttl: u8,
+    probes: u8,
+    protocol: ProbeProtocol,
+    port: u16,
+    path: u8,
+) -> Result<TraceHop> {
+    let answers = match (icmp::raw_available(), protocol) {
+        (true, _) => None,
+        (false, ProbeProtocol::Tcp) => anyhow::bail!("TCP traceroute needs root (or CAP_NET_RAW)"),
+        (false, _) => system_traceroute(target, ttl, probes).await,
+    };
+    let answers = match answers {
+        Some(answers) => answers,
+        // Without the raw socket nothing sees the ICMP errors UDP probes draw
+        None if protocol == ProbeProtocol::Udp && !icmp::raw_available() => {
+            anyhow::bail!("UDP traceroute needs root (or CAP_NET_RAW) or the system traceroute binary")
+        }
+        None => {
+            let mut answers = Vec::new();
+            for _ in 0..probes {
+                let reply = send_probe(target, ttl, protocol, port, path).await;
+                tracing::debug!("ttl {}: {:?}", ttl, reply);
+                answers.push(reply.map(|r| (r.from, r.rtt)));
+            }
+            answers
+        }
+    };
+
     let mut rtt_times = Vec::new();
     let mut responded_ip = None;
     let mut successful_probes = 0;
     
-    for _ in 0..probes {
-        let start = Instant::now();
-        
-        if let Some(ip) = send_probe(target, ttl).await {
-            let rtt = start.elapsed().as_micros() as f64 / 1000.0;
-            rtt_times.push(rtt);
-            responded_ip = Some(ip);
+    for answer in answers {
+        if let Some((from, rtt)) = answer {
+            rtt_times.push(rtt.as_micros() as f64 / 1000.0);
+            responded_ip = Some(from);
             successful_probes += 1;
         } else {
             rtt_times.push(-1.0);
         }
     }
     
-    let avg_rtt = if !rtt_times.is_empty() {
-        rtt_times.iter().filter(|&&x| x >= 0.0).sum::<f64>() / rtt_times.len() as f64
-    } else {
-        0.0
-    };
+    let summary = rtt_summary(&rtt_times);
+    let variation = rtt_variation(&rtt_times);
     
     let packet_loss = (probes - successful_probes) as f64 / probes as f64 * 100.0;
     
@@ -125,33 +604,76 @@ async fn probe_hop(target: Ipv4Addr, ttl: u8, probes: u8) -> Result<TraceHop> {
         ip: responded_ip,
         hostname,
         rtt_ms: rtt_times,
-        avg_rtt,
+        avg_rtt: summary.map_or(0.0, |s| s.avg),
+        min_rtt: summary.map(|s| s.min),
+        max_rtt: summary.map(|s| s.max),
+        stddev_ms: variation.map(|v| v.stddev),
+        jitter_ms: variation.map(|v| v.jitter),
         packet_loss,
+        probe_protocol: protocol,
+        stats: None,
+        asn: None,
+        as_name: None,
+        country: None,
+        location: None,
     })
 }
 
-async fn send_probe(target: Ipv4Addr, ttl: u8) -> Option<Ipv4Addr> {
-    tokio::task::spawn_blocking(move || {
-        std::process::Command::new("ping")
-            .args(&[
-                "-c", "1",
-                "-t", &ttl.to_string(),
-                "-W", "1",
-                &target.to_string()
-            ])
-            .output()
-            .ok()
-            .and_then(|output| {
-                if output.status.success() {
-                    Some(target)
-                } else {
-                    None
-                }
-            })
-    })
-    .await
-    .ok()
-    .flatten()
+/// One TTL-limited probe; answered either by the target or by the router
+/// at this hop with Time Exceeded
+async fn send_probe(target: Ipv4Addr, ttl: u8, protocol: ProbeProtocol, port: u16, path: u8) -> Option<icmp::IcmpReply> {
+    let timeout = Duration::from_secs(1);
+    match protocol {
+        ProbeProtocol::Icmp => icmp::echo_with_checksum(target, ttl, timeout, flow::icmp_checksum(path)).await,
+        ProbeProtocol::Udp => udp::probe(target, ttl, path, timeout).await,
+        ProbeProtocol::Tcp => tcp::probe(target, port, ttl, path, timeout).await,
+    }
+}
+
+/// Unprivileged fallback: ask the system traceroute about this one hop,
+/// with its default (UDP) probes
+///
+/// None when traceroute isn't installed or printed no line for the hop, so
+/// the caller can still fall back to TTL-limited pings
+async fn system_traceroute(target: Ipv4Addr, ttl: u8, probes: u8) -> Option<Vec<Option<(Ipv4Addr, Duration)>>> {
+    let output = tokio::process::Command::new("traceroute")
+        .args(["-n", "-w", "1"])
+        .args(["-f", &ttl.to_string(), "-m", &ttl.to_string(), "-q", &probes.to_string()])
+        .arg(target.to_string())
+        .output()
+        .await
+        .ok()?;
+    let answers = parse_traceroute_hop(&String::from_utf8_lossy(&output.stdout), ttl)?;
+    tracing::debug!("ttl {} (traceroute): {:?}", ttl, answers);
+    Some(answers)
+}
+
+/// The probes of hop `ttl` in `traceroute -n` output, in order
+///
+/// Each probe is `<rtt> ms` (optionally flagged `!H`, `!N`, ...) or `*`
+/// for no answer; an address before a group of times names whoever sent them,
+/// so a hop answered by two routers reads `10.0.0.1  1.2 ms  10.0.0.5  1.4 ms`
+pub fn parse_traceroute_hop(output: &str, ttl: u8) -> Option<Vec<Option<(Ipv4Addr, Duration)>>> {
+    let line = output.lines().find(|line| {
+        line.split_whitespace().next().and_then(|hop| hop.parse::<u8>().ok()) == Some(ttl)
+    })?;
+
+    let mut answers = Vec::new();
+    let mut from = None;
+    let mut tokens = line.split_whitespace().skip(1).peekable();
+    while let Some(token) = tokens.next() {
+        if token == "*" {
+            answers.push(None);
+        } else if let Ok(ip) = token.parse::<Ipv4Addr>() {
+            from = Some(ip);
+        } else if let Ok(ms) = token.parse::<f64>() {
+            if tokens.peek() == Some(&"ms") {
+                tokens.next();
+            }
+            answers.push(from.map(|ip| (ip, Duration::from_secs_f64(ms / 1000.0))));
+        }
+    }
+    Some(answers)
 }
 
 fn print_hop(hop: &TraceHop) {
@@ -163,6 +685,12 @@ fn print_hop(hop: &TraceHop) {
             .map(|h| format!(" ({})", h))
             .unwrap_or_default()
             .bright_cyan();
+        let network: Vec<String> = asn::as_label(hop).into_iter().chain(hop.country.clone()).collect();
+        let network_str = if network.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", network.join(", ")).bright_blue().to_string()
+        };
         
         let rtt_parts: Vec<_> = hop.rtt_ms.iter()
             .map(|&rtt| {
@@ -174,36 +702,98 @@ fn print_hop(hop: &TraceHop) {
             })
             .collect();
         let rtt_str = rtt_parts.join(" ");
+        let range_str = match (hop.min_rtt, hop.max_rtt, hop.jitter_ms) {
+            (Some(min), Some(max), Some(jitter)) => {
+                format!("  (avg {:.2}ms, min {:.2}, max {:.2}, jitter {:.2})", hop.avg_rtt, min, max, jitter)
+                    .dimmed()
+                    .to_string()
+            }
+            (Some(min), Some(max), None) if hop.rtt_ms.len() > 1 => {
+                format!("  (avg {:.2}ms, min {:.2}, max {:.2})", hop.avg_rtt, min, max).dimmed().to_string()
+            }
+            _ => String::new(),
+        };
         
-        println!("{} {} {} {}", hop_str, ip_str, hostname_str, rtt_str);
+        report!("{} {} {} {}{}{}", hop_str, ip_str, hostname_str, rtt_str, range_str, network_str);
     } else {
-        println!("{} {} {} {}", hop_str, "*".bright_red(), "*".bright_red(), "*".bright_red());
+        report!("{} {} {} {}  {}", hop_str, "*".bright_red(), "*".bright_red(), "*".bright_red(), "no response".dimmed());
     }
 }
 
 fn display_trace_result(result: &TraceResult) -> Result<()> {
-    println!("\n{}", "Route Analysis".bright_cyan().bold());
-    println!("{}", "─".repeat(60).bright_cyan());
+    report!("\n{}", "Route Analysis".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
     
     let total_hops = result.hops.len();
-    let avg_latency: f64 = result.hops.iter()
-        .map(|h| h.avg_rtt)
-        .filter(|&x| x > 0.0)
-        .sum::<f64>() / total_hops as f64;
+    // Fully lost hops have no latency to speak of
+    let answered: Vec<f64> = result.hops.iter()
+        .filter_map(|h| rtt_summary(&h.rtt_ms))
+        .map(|s| s.avg)
+        .collect();
+    
+    if !result.completed {
+        report!("{}", format!("⚠ Destination not reached within {} hops", total_hops).bright_yellow());
+    }
+    if let Some(routing_loop) = detect_loop(&result.hops) {
+        let routers: Vec<String> = routing_loop.routers.iter().map(|ip| ip.to_string()).collect();
+        report!("{}", format!("🔁 Routing loop between {} (TTL {}-{})",
+                              routers.join(" ↔ "), routing_loop.first_ttl, routing_loop.last_ttl).bright_red().bold());
+    }
+    report!("📍 Total hops: {}", total_hops);
+    if !answered.is_empty() {
+        report!("⏱  Average latency: {:.2}ms", answered.iter().sum::<f64>() / answered.len() as f64);
+    }
+    report!("⚡ Total time: {:.2}s", result.total_time.as_secs_f64());
     
-    println!("📍 Total hops: {}", total_hops);
-    println!("⏱  Average latency: {:.2}ms", avg_latency);
-    println!("⚡ Total time: {:.2}s", result.total_time.as_secs_f64());
+    let as_path = asn::as_path(&result.hops);
+    if !as_path.is_empty() {
+        report!("🌐 Networks: {}", as_path.join(" → "));
+    }
+    let detours = asn::unexpected_country_hops(&result.hops);
+    if !detours.is_empty() {
+        report!("\n{}", "⚠ Path leaves the source and destination countries:".bright_yellow());
+        for hop in detours {
+            if let (Some(ip), Some(country)) = (hop.ip, &hop.country) {
+                report!("  Hop {} ({}) - {}", hop.hop, ip, country.bright_red());
+            }
+        }
+    }
+    
+    let varying: Vec<_> = result.hops.iter()
+        .filter_map(|h| Some((h, h.ip?, h.stddev_ms?, h.jitter_ms?)))
+        .collect();
+    if !varying.is_empty() {
+        report!("\n{}", "Per-hop variation:".bright_cyan());
+        report!("  {:>3}  {:<16} {:>9} {:>9}", "Hop", "Host", "StdDev", "Jitter");
+        for (hop, ip, stddev, jitter) in varying {
+            let line = format!("  {:>3}  {:<16} {:>7.2}ms {:>7.2}ms", hop.hop, ip.to_string(), stddev, jitter);
+            if jitter > JITTER_WARN_MS {
+                report!("{}  {}", line.bright_red(), "⚠ high jitter".bright_yellow());
+            } else {
+                report!("{}", line);
+            }
+        }
+    }
+    
+    if result.paths.len() > 1 {
+        report!("\n{}", format!("🔀 {} distinct paths:", result.paths.len()).bright_cyan());
+        for (i, path) in result.paths.iter().enumerate() {
+            let hops: Vec<String> = path.iter()
+                .map(|hop| hop.ip.map_or_else(|| "*".to_string(), |ip| ip.to_string()))
+                .collect();
+            report!("  Path {}: {}", i + 1, hops.join(" → "));
+        }
+    }
     
     let high_latency_hops: Vec<_> = result.hops.iter()
         .filter(|h| h.avg_rtt > 100.0)
         .collect();
     
     if !high_latency_hops.is_empty() {
-        println!("\n{}", "⚠ High Latency Detected:".bright_yellow());
+        report!("\n{}", "⚠ High Latency Detected:".bright_yellow());
         for hop in high_latency_hops {
             if let Some(ip) = hop.ip {
-                println!("  Hop {} ({}) - {:.2}ms", 
+                report!("  Hop {} ({}) - {:.2}ms", 
                          hop.hop, ip.to_string().bright_red(), hop.avg_rtt);
             }
         }
@@ -212,7 +802,59 @@ fn display_trace_result(result: &TraceResult) -> Result<()> {
     Ok(())
 }
 
-fn save_trace_result(result: &TraceResult, path: &str) -> Result<()> {
+/// Append `result` to the trace history and return the traces to the same
+/// target stored before it
+fn update_history(result: &TraceResult, limit: usize) -> Result<Vec<history::StoredTrace>> {
+    let path = history::default_path()?;
+    let earlier = history::load(&path, &result.target)?;
+    let stored = history::StoredTrace { at: utils::get_timestamp_us(), trace: result.clone() };
+    history::append(&path, stored, limit)?;
+    Ok(earlier)
+}
+
+fn display_route_history(earlier: &[history::StoredTrace], current: &TraceResult) {
+    report!("\n{}", "Historical Route Data".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
+
+    let Some(last) = earlier.last() else {
+        report!("No earlier traces to {}; this one is the baseline", current.target);
+        return;
+    };
+    let now = utils::get_timestamp_us();
+    let ago = |at: u64| utils::format_ago(Duration::from_micros(now.saturating_sub(at)));
+    let since = chrono::DateTime::from_timestamp_micros(earlier[0].at as i64)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    report!("📚 {} earlier traces to {} since {}", earlier.len(), current.target, since);
+
+    let changes = history::compare(&last.trace, current);
+    if changes.is_empty() {
+        report!("{}", format!("✓ Route unchanged since the last trace ({})", ago(last.at)).bright_green());
+    } else {
+        report!("\n{}", format!("⚠ Route changes since the last trace ({}):", ago(last.at)).bright_yellow());
+        for change in &changes {
+            let line = change.to_string();
+            match change {
+                history::RouteChange::Added { .. } => report!("  {}", line.bright_green()),
+                history::RouteChange::Removed { .. } => report!("  {}", line.bright_red()),
+                _ => report!("  {}", line.bright_yellow()),
+            }
+        }
+    }
+
+    let current_path = history::path_of(current);
+    report!("\nPaths seen:");
+    for sighting in history::sightings(earlier) {
+        let hops: Vec<String> = sighting.path.iter()
+            .map(|ip| ip.map_or_else(|| "*".to_string(), |ip| ip.to_string()))
+            .collect();
+        let marker = if sighting.path == current_path { " (current)".bright_green().to_string() } else { String::new() };
+        report!("  {}× last {}{}: {}", sighting.times, ago(sighting.last_seen), marker, hops.join(" → "));
+    }
+}
+
+/// Write `result` as YAML for `.yaml`/`.yml` paths and as JSON otherwise
+fn save_result<T: Serialize>(result: &T, path: &str) -> Result<()> {
     let content = if path.ends_with(".yaml") || path.ends_with(".yml") {
         serde_yaml::to_string(&result)?
     } else {
@@ -223,57 +865,195 @@ fn save_trace_result(result: &TraceResult, path: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn run_inspect(
-    interface: Option<String>,
-    filter: Option<String>,
-    count: Option<usize>,
-    output: Option<String>,
-    analyze: bool,
-) -> Result<()> {
-    println!("{}", "NetWeaver Packet Inspector".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+/// What `inspect` captures or reads, and what it does with the packets
+#[derive(Debug, Clone, Default)]
+pub struct InspectOptions {
+    pub interface: Option<String>,
+    /// Capture file to analyze instead of an interface
+    pub read: Option<PathBuf>,
+    pub filter: Option<String>,
+    pub count: Option<usize>,
+    pub output: Option<String>,
+    pub analyze: bool,
+    /// Print the analysis so far at this interval during a live capture
+    pub report_every: Option<Duration>,
+    /// Write packets and analyses as JSON lines here (`-` for stdout)
+    pub stream_json: Option<String>,
+    /// Print each packet's layers and up to this many payload bytes
+    pub decode: Option<usize>,
+    /// How a pcap `output` is split over several files
+    pub rotation: pcap::Rotation,
+    /// Print capture rates and kernel drops at this interval during a live capture
+    pub stats_every: Option<Duration>,
+    /// Kernel capture buffer to ask for, in bytes
+    pub buffer_size: Option<usize>,
+}
+
+pub async fn run_inspect(opts: InspectOptions) -> Result<()> {
+    let InspectOptions {
+        interface, read, filter, count, output, analyze, report_every, stream_json, decode, rotation, stats_every, buffer_size,
+    } = opts;
+    status!("{}", "NetWeaver Packet Inspector".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
-    if !utils::is_privileged() {
-        anyhow::bail!("Packet capture requires root privileges. Run with sudo.");
+    let saves_packets = output.as_deref().is_some_and(|path| !path.ends_with(".json"));
+    if rotation != pcap::Rotation::default() && !saves_packets {
+        anyhow::bail!("Rotation needs a capture file: pass --output with a .pcap path");
+    }
+    if rotation.max_files.is_some() && !rotation.is_enabled() {
+        anyhow::bail!("--rotate-count needs --rotate-size or --rotate-interval to say when to rotate");
     }
     
-    let iface = interface.unwrap_or_else(|| "any".to_string());
-    println!("🔍 Capturing on: {}", iface.bright_yellow());
+    // A bad expression is worth reporting before anything else
+    let filter = filter.map(|f| filter::Filter::parse(&f).map(|parsed| (f, parsed))).transpose()?;
+    let stream = stream_json.as_deref().map(EventStream::open).transpose()?;
+    
+    // Reading a file opens no sockets, so it needs no privileges
+    let source = match read {
+        Some(path) => {
+            status!("📂 Reading: {}", path.display().to_string().bright_yellow());
+            PacketSource::File(path)
+        }
+        None => {
+            if !utils::is_privileged() {
+                anyhow::bail!("Packet capture requires root privileges. Run with sudo.");
+            }
+            let iface = capture::capture_interface(interface.as_deref())?;
+            status!("🔍 Capturing on: {}", iface.name.bright_yellow());
+            PacketSource::Interface(iface)
+        }
+    };
     
-    if let Some(f) = &filter {
-        println!("🎯 Filter: {}", f.bright_cyan());
+    if let Some((f, _)) = &filter {
+        status!("🎯 Filter: {}", f.bright_cyan());
     }
     
     if let Some(c) = count {
-        println!("📊 Packet count: {}", c);
+        status!("📊 Packet count: {}", c);
     }
     
-    println!("\n{}", "Starting capture... (Press Ctrl+C to stop)".bright_green());
-    println!("{}", "─".repeat(60).bright_green());
+    if matches!(source, PacketSource::Interface(_)) {
+        status!("\n{}", "Starting capture... (Press Ctrl+C to stop)".bright_green());
+    }
+    status!("{}", "─".repeat(60).bright_green());
     
-    capture_packets(iface, filter, count, output, analyze).await?;
+    let filter = filter.map(|(_, parsed)| parsed);
+    let limits = CaptureLimits { count, decode, live: capture::LiveOptions { report_every, stats_every, buffer_size } };
+    let output = output.map(|path| match path.ends_with(".json") {
+        true => CaptureOutput::Analysis(path),
+        false => CaptureOutput::Packets(pcap::RotatingWriter::new(path, rotation)),
+    });
+    capture_packets(source, filter, limits, output, analyze, stream).await?;
     
     Ok(())
 }
 
+fn print_capture_stats(capture: &capstats::CaptureStats) {
+    let buffer = capture.buffer_bytes.map(|bytes| format!(" | Buffer: {}", utils::format_bytes(bytes as u64))).unwrap_or_default();
+    report!("  Peak rate: {:.0} pkt/s{}", capture.peak_packets_per_sec, buffer);
+    match (capture.kernel_received, capture.kernel_dropped, capture.drop_rate()) {
+        (Some(received), Some(0), _) => report!("  Kernel: {} delivered, none dropped", received),
+        (Some(received), Some(dropped), Some(rate)) => {
+            let dropped = format!("{} dropped ({:.2}%)", dropped, rate).bright_red().bold();
+            report!("  Kernel: {} delivered, {}", received, dropped);
+            report!("  {}", "Raise --buffer-size or narrow the filter to keep up".bright_red());
+        }
+        _ => report!("  Kernel: drop counts not available on this platform"),
+    }
+}
+
+enum PacketSource {
+    Interface(pnet_datalink::NetworkInterface),
+    File(PathBuf),
+}
+
+/// What `--output` saves: the traffic analysis (`.json`) or the packets
+enum CaptureOutput {
+    Analysis(String),
+    Packets(pcap::RotatingWriter),
+}
+
+/// When a capture stops and what it prints along the way
+struct CaptureLimits {
+    count: Option<usize>,
+    decode: Option<usize>,
+    live: capture::LiveOptions,
+}
+
 async fn capture_packets(
-    _interface: String,
-    _filter: Option<String>,
-    _count: Option<usize>,
-    _output: Option<String>,
+    source: PacketSource,
+    filter: Option<filter::Filter>,
+    limits: CaptureLimits,
+    mut output: Option<CaptureOutput>,
     analyze: bool,
+    mut stream: Option<EventStream>,
 ) -> Result<()> {
-    println!("📦 Captured: 0 packets");
-    println!("  TCP: 0 | UDP: 0 | ICMP: 0 | Other: 0");
+    let CaptureLimits { count, decode, live } = limits;
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = Arc::clone(&stop);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+    // A capture file is read to be analyzed
+    let analyze = analyze || matches!(source, PacketSource::File(_));
+    let (stats, stream, output) = tokio::task::spawn_blocking(move || {
+        let writer = match output.as_mut() {
+            Some(CaptureOutput::Packets(writer)) => Some(writer),
+            _ => None,
+        };
+        let sinks = capture::PacketSinks { stream: stream.as_mut(), decode, writer };
+        let stats = match source {
+            PacketSource::Interface(iface) => {
+                capture::capture(&iface, filter.as_ref(), count, live, sinks, stop)
+            }
+            PacketSource::File(path) => capture::replay(&path, filter.as_ref(), count, sinks, stop),
+        };
+        stats.map(|stats| (stats, stream, output))
+    })
+    .await??;
+    if let Some(mut stream) = stream {
+        stream.emit("summary", &stats.report(traffic::TOP))?;
+    }
+    
+    let counts = &stats.counts;
+    report!("\n📦 Captured: {} packets ({} bytes)", counts.packets, counts.bytes);
+    report!("  TCP: {} | UDP: {} | ICMP: {} | Other: {}", counts.tcp, counts.udp, counts.icmp, counts.other);
+    if let Some(capture) = &stats.capture {
+        print_capture_stats(capture);
+    }
     
-    if analyze {
-        println!("\n{}", "Packet Analysis".bright_cyan().bold());
-        println!("  Average size: 0 bytes");
-        println!("  Protocols detected: TCP, UDP, ICMP");
-        println!("  Top talkers: None");
+    if analyze && counts.packets > 0 {
+        traffic::print_report(&stats);
     }
     
-    println!("\n{}", "Note: Full packet capture implementation requires libpcap integration".bright_yellow());
+    match output {
+        Some(CaptureOutput::Analysis(output_path)) => {
+            save_result(&stats.report(traffic::TOP), &output_path)?;
+            status!("\n💾 Analysis saved to: {}", output_path.bright_green());
+        }
+        Some(CaptureOutput::Packets(writer)) => {
+            let skipped = writer.skipped;
+            let files = writer.finish()?;
+            match files.as_slice() {
+                [] => status!("\n💾 No packets to save"),
+                [file] => status!("\n💾 Capture saved to: {} ({} packets)", file.path.display().to_string().bright_green(), file.packets),
+                files => {
+                    status!("\n💾 Capture saved to {} files:", files.len());
+                    for file in files {
+                        status!("  {} ({} packets, {} bytes)", file.path.display().to_string().bright_green(), file.packets, file.bytes);
+                    }
+                }
+            }
+            if skipped > 0 {
+                status!("⚠️  {} packets of another link type were not saved (pcap holds one per file)", skipped);
+            }
+        }
+        None => {}
+    }
     
     Ok(())
 }
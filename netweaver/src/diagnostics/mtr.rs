@@ -0,0 +1,284 @@
+// Continuous (mtr-style) tracing
+// A single trace is one sample per hop, so loss that comes and goes at hop 7
+// rarely shows up in it. Here every cycle sends one probe to each hop up to
+// the target, and each hop keeps running loss and RTT statistics. With
+// `--continuous` the table is redrawn live until 'q'; `--report-cycles N`
+// runs N cycles quietly and prints the table once, for scripts.
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::{cursor, execute, queue, terminal};
+use serde::{Deserialize, Serialize};
+use std::io::{Stdout, Write};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{asn, probe_hop, ProbeProtocol, TraceHop, TraceOptions, TraceResult};
+use crate::analytics::{LatencyAnalyzer, PacketLossDetector};
+use crate::output::EventStream;
+use crate::report;
+
+/// RTT samples kept per hop for the statistics; older ones slide out
+const MAX_SAMPLES: usize = 10_000;
+
+/// Accumulated statistics of one hop over every cycle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HopStats {
+    pub sent: u64,
+    pub received: u64,
+    pub loss_pct: f64,
+    /// RTTs in ms; None until the hop first answers
+    pub last_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub best_ms: Option<f64>,
+    pub worst_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    #[serde(default)]
+    pub stddev_ms: Option<f64>,
+}
+
+/// Running totals for one TTL
+#[derive(Debug, Clone)]
+pub struct HopTracker {
+    ttl: u8,
+    ip: Option<Ipv4Addr>,
+    rtt_ms: Vec<f64>,
+    latency: LatencyAnalyzer,
+    loss: PacketLossDetector,
+}
+
+impl HopTracker {
+    pub fn new(ttl: u8) -> Self {
+        Self {
+            ttl,
+            ip: None,
+            rtt_ms: Vec::new(),
+            latency: LatencyAnalyzer::new(MAX_SAMPLES),
+            loss: PacketLossDetector::new(),
+        }
+    }
+
+    /// Record one probe: who answered and in how many ms, or None for silence
+    pub fn record(&mut self, answer: Option<(Ipv4Addr, f64)>) {
+        self.loss.record_sent(1);
+        match answer {
+            Some((ip, rtt_ms)) => {
+                self.ip = Some(ip);
+                self.loss.record_received(1);
+                self.latency.add_sample(rtt_ms);
+                self.rtt_ms.push(rtt_ms);
+            }
+            None => self.rtt_ms.push(-1.0),
+        }
+    }
+
+    pub fn stats(&self) -> HopStats {
+        let answered = self.loss.received() > 0;
+        let stat = |value: f64| answered.then_some(value);
+        HopStats {
+            sent: self.loss.sent(),
+            received: self.loss.received(),
+            loss_pct: self.loss.loss_rate(),
+            last_ms: self.rtt_ms.iter().rev().find(|&&ms| ms >= 0.0).copied(),
+            avg_ms: stat(self.latency.average()),
+            best_ms: stat(self.latency.min()),
+            worst_ms: stat(self.latency.max()),
+            jitter_ms: stat(self.latency.jitter()),
+            stddev_ms: stat(self.latency.std_dev()),
+        }
+    }
+
+    pub fn hop(&self, protocol: ProbeProtocol) -> TraceHop {
+        let stats = self.stats();
+        TraceHop {
+            hop: self.ttl,
+            ip: self.ip,
+            hostname: None,
+            rtt_ms: self.rtt_ms.clone(),
+            avg_rtt: stats.avg_ms.unwrap_or(0.0),
+            min_rtt: stats.best_ms,
+            max_rtt: stats.worst_ms,
+            stddev_ms: stats.stddev_ms.filter(|_| stats.received >= 2),
+            jitter_ms: stats.jitter_ms.filter(|_| stats.received >= 2),
+            packet_loss: stats.loss_pct,
+            probe_protocol: protocol,
+            stats: Some(stats),
+            asn: None,
+            as_name: None,
+            country: None,
+            location: None,
+        }
+    }
+}
+
+/// The mtr-style table of `hops`, one line per hop
+pub fn render_table(hops: &[TraceHop]) -> String {
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{:.1}", v));
+    let mut table = format!(
+        "{:>3}  {:<16} {:>6} {:>5} {:>7} {:>7} {:>7} {:>7} {:>6} {:>6}\n",
+        "Hop", "Host", "Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev", "Jttr"
+    );
+    for hop in hops {
+        let host = hop.ip.map_or_else(|| "???".to_string(), |ip| ip.to_string());
+        let line = match &hop.stats {
+            Some(s) => format!(
+                "{:>3}  {:<16} {:>5.1}% {:>5} {:>7} {:>7} {:>7} {:>7} {:>6} {:>6}",
+                hop.hop, host, s.loss_pct, s.sent, ms(s.last_ms), ms(s.avg_ms), ms(s.best_ms), ms(s.worst_ms),
+                ms(s.stddev_ms), ms(s.jitter_ms)
+            ),
+            None => format!("{:>3}  {:<16} {:>5.1}%", hop.hop, host, hop.packet_loss),
+        };
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Raw-mode alternate screen for the live table, restored on drop
+struct LiveView {
+    stdout: Stdout,
+}
+
+impl LiveView {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = std::io::stdout();
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self { stdout })
+    }
+
+    /// 'q' (or Ctrl+C, which raw mode turns into a key press) was pressed
+    fn quit_requested(&mut self) -> Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.code == KeyCode::Char('q') || ctrl_c {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn draw(&mut self, title: &str, table: &str) -> Result<()> {
+        queue!(self.stdout, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))?;
+        // Raw mode doesn't return the carriage on a bare newline
+        write!(self.stdout, "{}\r\n\r\n", title)?;
+        for line in table.lines() {
+            write!(self.stdout, "{}\r\n", line)?;
+        }
+        write!(self.stdout, "\r\nPress 'q' to quit\r\n")?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for LiveView {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// One `--stream-json` record per finished cycle
+#[derive(Serialize)]
+struct CycleRecord<'a> {
+    cycle: u32,
+    hops: &'a [TraceHop],
+}
+
+/// Cycle through the hops until 'q' (or `opts.report_cycles` cycles) and
+/// return everything accumulated, each hop carrying its `HopStats`
+///
+/// With a `stream`, each finished cycle is written there instead of drawing
+/// the live table, and Ctrl+C ends the run
+pub async fn run(
+    target: String,
+    target_ip: Ipv4Addr,
+    opts: &TraceOptions,
+    mut stream: Option<&mut EventStream>,
+) -> Result<TraceResult> {
+    let start = Instant::now();
+    let mut trackers: Vec<HopTracker> = Vec::new();
+    let mut view = match opts.report_cycles {
+        None if stream.is_none() => Some(LiveView::enter()?),
+        _ => None,
+    };
+    // Raw mode turns Ctrl+C into a key press for the live view; otherwise
+    // it stops the run after the current probe
+    let stop = Arc::new(AtomicBool::new(false));
+    if view.is_none() {
+        let stop = Arc::clone(&stop);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+    let title = format!("NetWeaver trace to {} ({}, {})", target, target_ip, opts.protocol);
+
+    let mut cycle = 0;
+    'cycles: while opts.report_cycles.is_none_or(|cycles| cycle < cycles) {
+        cycle += 1;
+        let mut silent_hops = 0;
+        for ttl in 1..=opts.max_hops {
+            if let Some(view) = &mut view {
+                if view.quit_requested()? {
+                    break 'cycles;
+                }
+            }
+            if stop.load(Ordering::Relaxed) {
+                break 'cycles;
+            }
+
+            let hop = probe_hop(target_ip, ttl, 1, opts.protocol, opts.port, 1).await?;
+            let answer = hop.ip.zip(hop.rtt_ms.first().copied().filter(|&ms| ms >= 0.0));
+            if trackers.len() < ttl as usize {
+                trackers.push(HopTracker::new(ttl));
+            }
+            trackers[ttl as usize - 1].record(answer);
+
+            if let Some(view) = &mut view {
+                let hops: Vec<TraceHop> = trackers.iter().map(|t| t.hop(opts.protocol)).collect();
+                view.draw(&format!("{}  cycle {}", title, cycle), &render_table(&hops))?;
+            }
+
+            if hop.ip == Some(target_ip) {
+                // The path may have shortened since an earlier cycle
+                trackers.truncate(ttl as usize);
+                break;
+            }
+            silent_hops = if answer.is_none() { silent_hops + 1 } else { 0 };
+            if opts.max_unresponsive > 0 && silent_hops >= opts.max_unresponsive {
+                break;
+            }
+        }
+        if let Some(stream) = stream.as_deref_mut() {
+            let hops: Vec<TraceHop> = trackers.iter().map(|t| t.hop(opts.protocol)).collect();
+            stream.emit("cycle", &CycleRecord { cycle, hops: &hops })?;
+        }
+    }
+    drop(view);
+
+    let mut hops: Vec<TraceHop> = trackers.iter().map(|t| t.hop(opts.protocol)).collect();
+    if let Some(mut annotator) = asn::Annotator::new(opts.asn, opts.geoip.as_deref())? {
+        for hop in &mut hops {
+            annotator.annotate(hop).await;
+        }
+    }
+    report!("{}", render_table(&hops).trim_end());
+    Ok(TraceResult {
+        target,
+        target_ip,
+        completed: hops.iter().any(|hop| hop.ip == Some(target_ip)),
+        hops,
+        total_time: start.elapsed(),
+        probe_protocol: opts.protocol,
+        port: (opts.protocol == ProbeProtocol::Tcp).then_some(opts.port),
+        loop_detected: false,
+        loop_routers: Vec::new(),
+        paths: Vec::new(),
+    })
+}
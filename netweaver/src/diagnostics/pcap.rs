@@ -0,0 +1,455 @@
+// Capture files
+// Reads the classic libpcap format (either byte order, microsecond or
+// nanosecond timestamps) and pcapng (what Wireshark and recent tcpdump write
+// by default), one record at a time so large files stream through. Only the
+// blocks carrying packets and their interfaces' link types are interpreted;
+// the rest are skipped. Captures are written as classic pcap, optionally
+// spread over a ring of files rotated by size or age.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The LINKTYPE_ values we know how to decode
+pub mod linktype {
+    pub const NULL: u32 = 0;
+    pub const ETHERNET: u32 = 1;
+    pub const RAW_OPENBSD: u32 = 12;
+    pub const RAW: u32 = 101;
+    pub const LOOP: u32 = 108;
+    pub const LINUX_SLL: u32 = 113;
+    pub const IPV4: u32 = 228;
+    pub const IPV6: u32 = 229;
+    pub const LINUX_SLL2: u32 = 276;
+}
+
+/// Larger records than this mean the file is corrupt, not that it holds
+/// jumbo frames
+const MAX_RECORD: usize = 256 * 1024;
+
+/// Snap length written to file headers; we never truncate frames
+const SNAPLEN: u32 = 262_144;
+
+const FILE_HEADER_SIZE: u64 = 24;
+
+const PCAP_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapRecord {
+    pub timestamp: DateTime<Utc>,
+    pub linktype: u32,
+    /// The captured bytes, possibly cut short of the frame by the snap length
+    pub data: Vec<u8>,
+    /// The frame's length on the wire
+    pub orig_len: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    linktype: u32,
+    /// Timestamp units per second
+    ticks_per_sec: u64,
+}
+
+enum Format {
+    Classic { linktype: u32, nanos: bool },
+    Ng { interfaces: Vec<Interface> },
+}
+
+pub struct PcapReader<R> {
+    reader: R,
+    big_endian: bool,
+    format: Format,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open '{}'", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("'{}' is not a readable capture file", path.display()))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).context("File is too short to be a capture")?;
+
+        let le = u32::from_le_bytes(magic);
+        let be = u32::from_be_bytes(magic);
+        if le == PCAPNG_SECTION {
+            let mut pcap = Self { reader, big_endian: false, format: Format::Ng { interfaces: Vec::new() } };
+            pcap.read_section_header()?;
+            return Ok(pcap);
+        }
+        let (big_endian, nanos) = match (le, be) {
+            (PCAP_MICROS, _) => (false, false),
+            (PCAP_NANOS, _) => (false, true),
+            (_, PCAP_MICROS) => (true, false),
+            (_, PCAP_NANOS) => (true, true),
+            _ => anyhow::bail!("Unrecognized magic number {:02x?} (expected pcap or pcapng)", magic),
+        };
+        let mut header = [0u8; 20];
+        reader.read_exact(&mut header).context("Truncated pcap header")?;
+        let mut pcap = Self { reader, big_endian, format: Format::Classic { linktype: 0, nanos } };
+        // The upper bits of the link type field carry FCS information
+        let linktype = pcap.u32_at(&header, 16) & 0xffff;
+        pcap.format = Format::Classic { linktype, nanos };
+        Ok(pcap)
+    }
+
+    /// The next packet, or None at the end of the file
+    pub fn next_record(&mut self) -> Result<Option<PcapRecord>> {
+        match self.format {
+            Format::Classic { linktype, nanos } => self.next_classic(linktype, nanos),
+            Format::Ng { .. } => self.next_ng(),
+        }
+    }
+
+    fn next_classic(&mut self, linktype: u32, nanos: bool) -> Result<Option<PcapRecord>> {
+        let mut header = [0u8; 16];
+        if !self.fill(&mut header)? {
+            return Ok(None);
+        }
+        let secs = u64::from(self.u32_at(&header, 0));
+        let fraction = u64::from(self.u32_at(&header, 4));
+        let captured = self.u32_at(&header, 8) as usize;
+        let orig_len = self.u32_at(&header, 12) as usize;
+        if captured > MAX_RECORD {
+            anyhow::bail!("Corrupt record: {} captured bytes", captured);
+        }
+        let mut data = vec![0u8; captured];
+        self.reader.read_exact(&mut data).context("Truncated packet record")?;
+
+        let ticks_per_sec = if nanos { 1_000_000_000 } else { 1_000_000 };
+        Ok(Some(PcapRecord {
+            timestamp: timestamp(secs * ticks_per_sec + fraction, ticks_per_sec),
+            linktype,
+            data,
+            orig_len: orig_len.max(captured),
+        }))
+    }
+
+    fn next_ng(&mut self) -> Result<Option<PcapRecord>> {
+        loop {
+            let mut header = [0u8; 8];
+            if !self.fill(&mut header)? {
+                return Ok(None);
+            }
+            let block_type = self.u32_at(&header, 0);
+            if block_type == PCAPNG_SECTION {
+                // A new section may switch byte order and resets the interfaces
+                self.read_section_header_after_type(&header)?;
+                continue;
+            }
+            let total = self.u32_at(&header, 4) as usize;
+            if !(12..=MAX_RECORD + 64).contains(&total) || !total.is_multiple_of(4) {
+                anyhow::bail!("Corrupt pcapng block of type {:#x}: length {}", block_type, total);
+            }
+            let mut body = vec![0u8; total - 8];
+            self.reader.read_exact(&mut body).context("Truncated pcapng block")?;
+            let body = &body[..body.len() - 4];
+
+            match block_type {
+                PCAPNG_INTERFACE => {
+                    let interface = self.parse_interface(body)?;
+                    if let Format::Ng { interfaces } = &mut self.format {
+                        interfaces.push(interface);
+                    }
+                }
+                PCAPNG_ENHANCED_PACKET if body.len() >= 20 => {
+                    let id = self.u32_at(body, 0) as usize;
+                    let ticks = u64::from(self.u32_at(body, 4)) << 32 | u64::from(self.u32_at(body, 8));
+                    let captured = self.u32_at(body, 12) as usize;
+                    let orig_len = self.u32_at(body, 16) as usize;
+                    let data = body.get(20..20 + captured).context("Corrupt enhanced packet block")?;
+                    let interface = self.interface(id)?;
+                    return Ok(Some(PcapRecord {
+                        timestamp: timestamp(ticks, interface.ticks_per_sec),
+                        linktype: interface.linktype,
+                        data: data.to_vec(),
+                        orig_len: orig_len.max(captured),
+                    }));
+                }
+                // No timestamp, and the packet's length says how much was kept
+                PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+                    let orig_len = self.u32_at(body, 0) as usize;
+                    let data = &body[4..(4 + orig_len).min(body.len())];
+                    let interface = self.interface(0)?;
+                    return Ok(Some(PcapRecord {
+                        timestamp: DateTime::UNIX_EPOCH,
+                        linktype: interface.linktype,
+                        data: data.to_vec(),
+                        orig_len,
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn interface(&self, id: usize) -> Result<Interface> {
+        match &self.format {
+            Format::Ng { interfaces } => interfaces.get(id).copied(),
+            Format::Classic { .. } => None,
+        }
+        .with_context(|| format!("Packet refers to undeclared interface {}", id))
+    }
+
+    fn read_section_header(&mut self) -> Result<()> {
+        let mut rest = [0u8; 4];
+        self.reader.read_exact(&mut rest).context("Truncated pcapng section header")?;
+        let mut header = [0u8; 8];
+        header[..4].copy_from_slice(&PCAPNG_SECTION.to_le_bytes());
+        header[4..].copy_from_slice(&rest);
+        self.read_section_header_after_type(&header)
+    }
+
+    /// The rest of a section header block whose type and length are in `header`
+    fn read_section_header_after_type(&mut self, header: &[u8; 8]) -> Result<()> {
+        let mut order = [0u8; 4];
+        self.reader.read_exact(&mut order).context("Truncated pcapng section header")?;
+        self.big_endian = match (u32::from_le_bytes(order), u32::from_be_bytes(order)) {
+            (PCAPNG_BYTE_ORDER, _) => false,
+            (_, PCAPNG_BYTE_ORDER) => true,
+            _ => anyhow::bail!("Corrupt pcapng section header"),
+        };
+        let total = self.u32_at(header, 4) as usize;
+        if !(28..=MAX_RECORD).contains(&total) {
+            anyhow::bail!("Corrupt pcapng section header: length {}", total);
+        }
+        // Version, section length and options are of no interest
+        let mut rest = vec![0u8; total - 12];
+        self.reader.read_exact(&mut rest).context("Truncated pcapng section header")?;
+        self.format = Format::Ng { interfaces: Vec::new() };
+        Ok(())
+    }
+
+    fn parse_interface(&self, body: &[u8]) -> Result<Interface> {
+        if body.len() < 8 {
+            anyhow::bail!("Corrupt pcapng interface block");
+        }
+        let linktype = u32::from(self.u16_at(body, 0));
+        let mut ticks_per_sec = 1_000_000;
+        // Options: code, length, value padded to 4 bytes; if_tsresol is code 9
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = self.u16_at(options, 0);
+            let len = usize::from(self.u16_at(options, 2));
+            let Some(value) = options.get(4..4 + len) else {
+                break;
+            };
+            match code {
+                0 => break,
+                9 if len == 1 => {
+                    let exponent = u32::from(value[0] & 0x7f);
+                    let base: u64 = if value[0] & 0x80 != 0 { 2 } else { 10 };
+                    ticks_per_sec = base.checked_pow(exponent).context("Unsupported timestamp resolution")?;
+                }
+                _ => {}
+            }
+            options = options.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+        }
+        Ok(Interface { linktype, ticks_per_sec })
+    }
+
+    /// Fill `buf`, or return false at a clean end of file
+    fn fill(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => anyhow::bail!("Truncated record header"),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e).context("Failed to read capture file"),
+            }
+        }
+        Ok(true)
+    }
+
+    fn u16_at(&self, buf: &[u8], at: usize) -> u16 {
+        let bytes = [buf[at], buf[at + 1]];
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+
+    fn u32_at(&self, buf: &[u8], at: usize) -> u32 {
+        let bytes = [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]];
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+}
+
+/// Writes classic little-endian pcap with microsecond timestamps
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header for frames of `linktype`
+    pub fn new(mut writer: W, linktype: u32) -> Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MICROS.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        // Timezone offset and timestamp accuracy, both always zero
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&linktype.to_le_bytes());
+        writer.write_all(&header).context("Failed to write pcap header")?;
+        Ok(Self { writer })
+    }
+
+    /// Append one frame; `orig_len` is its length on the wire
+    pub fn write(&mut self, at: DateTime<Utc>, data: &[u8], orig_len: usize) -> Result<()> {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(&(at.timestamp() as u32).to_le_bytes());
+        header[4..8].copy_from_slice(&at.timestamp_subsec_micros().to_le_bytes());
+        header[8..12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        header[12..].copy_from_slice(&(orig_len.max(data.len()) as u32).to_le_bytes());
+        self.writer.write_all(&header).context("Failed to write packet record")?;
+        self.writer.write_all(data).context("Failed to write packet record")
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush().context("Failed to flush capture file")?;
+        Ok(self.writer)
+    }
+}
+
+/// Bytes a frame of `len` captured bytes adds to a pcap file
+fn record_size(len: usize) -> u64 {
+    16 + len as u64
+}
+
+/// When `RotatingWriter` moves on to the next file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Start a new file before one would grow past this many bytes
+    pub max_bytes: Option<u64>,
+    /// Start a new file once the current one holds this much time
+    pub interval: Option<Duration>,
+    /// Keep at most this many files, overwriting the oldest
+    pub max_files: Option<usize>,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.interval.is_some()
+    }
+}
+
+/// One file a capture produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureFile {
+    pub path: PathBuf,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+struct OpenFile {
+    writer: PcapWriter<BufWriter<File>>,
+    index: usize,
+    started: DateTime<Utc>,
+}
+
+/// Writes `base`, then `base.1`, `base.2`, ... as `rotation` says; with a
+/// file limit the numbers wrap around and the oldest file is overwritten
+///
+/// Files are opened on the first packet, whose link type they all take.
+pub struct RotatingWriter {
+    base: PathBuf,
+    rotation: Rotation,
+    linktype: Option<u32>,
+    current: Option<OpenFile>,
+    /// Files on disk, oldest first; the last is the one being written
+    files: VecDeque<CaptureFile>,
+    /// Frames of another link type than the files', which pcap can't mix
+    pub skipped: u64,
+}
+
+impl RotatingWriter {
+    pub fn new(base: impl Into<PathBuf>, rotation: Rotation) -> Self {
+        Self { base: base.into(), rotation, linktype: None, current: None, files: VecDeque::new(), skipped: 0 }
+    }
+
+    /// The name of the file at position `index` of the ring
+    pub fn path_of(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.base.clone(),
+            n => PathBuf::from(format!("{}.{}", self.base.display(), n)),
+        }
+    }
+
+    pub fn write(&mut self, at: DateTime<Utc>, linktype: u32, data: &[u8], orig_len: usize) -> Result<()> {
+        if *self.linktype.get_or_insert(linktype) != linktype {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let size = record_size(data.len());
+        let next = match &self.current {
+            None => Some(0),
+            Some(open) if self.is_full(open, at, size) => {
+                Some(self.rotation.max_files.map_or(open.index + 1, |max| (open.index + 1) % max.max(1)))
+            }
+            Some(_) => None,
+        };
+        if let Some(index) = next {
+            self.rotate(index, at, linktype)?;
+        }
+        let open = self.current.as_mut().expect("a file was just opened");
+        open.writer.write(at, data, orig_len)?;
+        let file = self.files.back_mut().expect("the open file is listed");
+        file.packets += 1;
+        file.bytes += size;
+        Ok(())
+    }
+
+    fn is_full(&self, open: &OpenFile, at: DateTime<Utc>, size: u64) -> bool {
+        let file = self.files.back().expect("the open file is listed");
+        // A file always takes at least one packet, however large
+        let too_big = self.rotation.max_bytes.is_some_and(|max| file.packets > 0 && file.bytes + size > max);
+        let too_old = self.rotation.interval.is_some_and(|interval| {
+            (at - open.started).to_std().is_ok_and(|age| age >= interval)
+        });
+        too_big || too_old
+    }
+
+    /// Open the file at `index` before closing the current one, so a file
+    /// that can't be created stops the capture with nothing lost
+    fn rotate(&mut self, index: usize, at: DateTime<Utc>, linktype: u32) -> Result<()> {
+        let path = self.path_of(index);
+        let file = File::create(&path).with_context(|| format!("Failed to create '{}'", path.display()))?;
+        let writer = PcapWriter::new(BufWriter::new(file), linktype)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+        if let Some(previous) = self.current.replace(OpenFile { writer, index, started: at }) {
+            previous.writer.into_inner()?;
+        }
+        // Reopening a ring slot replaces the file that was there
+        self.files.retain(|file| file.path != path);
+        self.files.push_back(CaptureFile { path, packets: 0, bytes: FILE_HEADER_SIZE });
+        Ok(())
+    }
+
+    /// Flush the last file; the files on disk, oldest first
+    pub fn finish(mut self) -> Result<Vec<CaptureFile>> {
+        if let Some(open) = self.current.take() {
+            open.writer.into_inner()?;
+        }
+        Ok(self.files.into())
+    }
+}
+
+fn timestamp(ticks: u64, ticks_per_sec: u64) -> DateTime<Utc> {
+    let secs = ticks / ticks_per_sec;
+    let nanos = (u128::from(ticks % ticks_per_sec) * 1_000_000_000 / u128::from(ticks_per_sec)) as u32;
+    DateTime::from_timestamp(secs as i64, nanos).unwrap_or_default()
+}
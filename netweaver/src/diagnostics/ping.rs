@@ -0,0 +1,211 @@
+// Continuous ping with statistics
+// ICMP echo through the C core's raw socket when we may open one; without
+// CAP_NET_RAW the RTT of a TCP handshake (or refusal) stands in, which most
+// hosts answer just as well. Ctrl+C stops the run and still prints the
+// summary.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::analytics::{LatencyAnalyzer, PacketLossDetector};
+use crate::utils::{self, icmp};
+use crate::{report, status};
+
+/// How each ping is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingMethod {
+    Icmp,
+    /// TCP connect to a port, for unprivileged runs
+    Tcp,
+}
+
+#[derive(Debug, Clone)]
+pub struct PingOptions {
+    /// Stop after this many pings; None runs until Ctrl+C
+    pub count: Option<u32>,
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Port of TCP-connect pings
+    pub port: u16,
+}
+
+impl Default for PingOptions {
+    fn default() -> Self {
+        Self {
+            count: None,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            port: 80,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingReply {
+    pub seq: u32,
+    /// None when the ping went unanswered
+    pub rtt_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingResult {
+    pub target: String,
+    pub target_ip: Ipv4Addr,
+    pub method: PingMethod,
+    /// Port of TCP-connect pings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    pub sent: u64,
+    pub received: u64,
+    pub loss_pct: f64,
+    /// RTT statistics in ms over the answered pings; None when none answered
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub stddev_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub replies: Vec<PingReply>,
+    pub total_time: Duration,
+}
+
+impl PingResult {
+    /// Statistics of `replies`, in the order they were sent
+    pub fn new(target: String, target_ip: Ipv4Addr, method: PingMethod, port: u16, replies: Vec<PingReply>, total_time: Duration) -> Self {
+        let mut latency = LatencyAnalyzer::new(replies.len().max(1));
+        let mut loss = PacketLossDetector::new();
+        for reply in &replies {
+            loss.record_sent(1);
+            if let Some(rtt) = reply.rtt_ms {
+                loss.record_received(1);
+                latency.add_sample(rtt);
+            }
+        }
+        let answered = loss.received() > 0;
+        let stat = |value: f64| answered.then_some(value);
+
+        Self {
+            target,
+            target_ip,
+            method,
+            port: (method == PingMethod::Tcp).then_some(port),
+            sent: loss.sent(),
+            received: loss.received(),
+            loss_pct: loss.loss_rate(),
+            min_ms: stat(latency.min()),
+            avg_ms: stat(latency.average()),
+            max_ms: stat(latency.max()),
+            stddev_ms: stat(latency.std_dev()),
+            jitter_ms: stat(latency.jitter()),
+            replies,
+            total_time,
+        }
+    }
+}
+
+pub async fn run_ping(target: String, opts: PingOptions, output: Option<String>) -> Result<()> {
+    status!("{}", "NetWeaver Ping".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
+
+    let target_ip = utils::network::resolve_hostname(&target).await
+        .context("Failed to resolve target")?;
+    let method = if icmp::raw_available() { PingMethod::Icmp } else { PingMethod::Tcp };
+
+    status!("🎯 Target: {} ({})", target.bright_yellow(), target_ip.to_string().bright_green());
+    match method {
+        PingMethod::Icmp => status!("📡 ICMP echo every {:.1}s\n", opts.interval.as_secs_f64()),
+        PingMethod::Tcp => status!("📡 TCP connect to port {} every {:.1}s (ICMP needs root or CAP_NET_RAW)\n",
+                                   opts.port, opts.interval.as_secs_f64()),
+    }
+
+    let (stop_tx, mut stop_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = stop_tx.send(true);
+        }
+    });
+
+    let start = Instant::now();
+    let mut replies = Vec::new();
+    let mut seq = 0;
+    while opts.count.is_none_or(|count| seq < count) {
+        seq += 1;
+        let sent_at = Instant::now();
+        let reply = tokio::select! {
+            reply = ping_once(target_ip, method, &opts) => reply,
+            _ = stop_rx.changed() => break,
+        };
+        print_reply(target_ip, method, opts.port, seq, reply);
+        replies.push(PingReply { seq, rtt_ms: reply.map(|(rtt, _)| rtt.as_micros() as f64 / 1000.0), ttl: reply.and_then(|(_, ttl)| ttl) });
+
+        if opts.count == Some(seq) {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(opts.interval.saturating_sub(sent_at.elapsed())) => {}
+            _ = stop_rx.changed() => break,
+        }
+    }
+
+    let result = PingResult::new(target, target_ip, method, opts.port, replies, start.elapsed());
+    display_ping_result(&result);
+
+    if let Some(output_path) = output {
+        super::save_result(&result, &output_path)?;
+        status!("\n💾 Ping results saved to: {}", output_path.bright_green());
+    }
+    Ok(())
+}
+
+/// RTT and reply TTL of one ping, None when it went unanswered
+pub async fn ping_once(ip: Ipv4Addr, method: PingMethod, opts: &PingOptions) -> Option<(Duration, Option<u8>)> {
+    match method {
+        PingMethod::Icmp => {
+            let reply = icmp::echo(ip, icmp::DEFAULT_TTL, opts.timeout).await?;
+            if reply.kind != icmp::ReplyKind::EchoReply {
+                tracing::debug!("{:?} from {}", reply.kind, reply.from);
+                return None;
+            }
+            Some((reply.rtt, reply.ttl))
+        }
+        PingMethod::Tcp => {
+            let rtt = crate::scanner::tcp_rtt(SocketAddr::new(IpAddr::V4(ip), opts.port), opts.timeout).await?;
+            Some((rtt, None))
+        }
+    }
+}
+
+fn print_reply(ip: Ipv4Addr, method: PingMethod, port: u16, seq: u32, reply: Option<(Duration, Option<u8>)>) {
+    let Some((rtt, ttl)) = reply else {
+        report!("{}", format!("Request timeout for seq={}", seq).bright_red());
+        return;
+    };
+    let from = match method {
+        PingMethod::Icmp => ip.to_string(),
+        PingMethod::Tcp => format!("{}:{}", ip, port),
+    };
+    let ttl = ttl.map(|ttl| format!(" ttl={}", ttl)).unwrap_or_default();
+    report!("Reply from {}: seq={}{} time={}",
+            from.bright_green(), seq, ttl, format!("{:.2}ms", rtt.as_secs_f64() * 1000.0).bright_yellow());
+}
+
+fn display_ping_result(result: &PingResult) {
+    report!("\n{}", "Ping Statistics".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
+
+    let loss = format!("{:.1}% loss", result.loss_pct);
+    let loss = if result.loss_pct > 0.0 { loss.bright_red() } else { loss.bright_green() };
+    report!("📦 {} sent, {} received, {}", result.sent, result.received, loss);
+    if let (Some(min), Some(avg), Some(max), Some(stddev)) = (result.min_ms, result.avg_ms, result.max_ms, result.stddev_ms) {
+        report!("⏱  min/avg/max/stddev = {:.2}/{:.2}/{:.2}/{:.2} ms", min, avg, max, stddev);
+    }
+    if let Some(jitter) = result.jitter_ms {
+        report!("〰 Jitter: {:.2}ms", jitter);
+    }
+    report!("⚡ Total time: {:.2}s", result.total_time.as_secs_f64());
+}
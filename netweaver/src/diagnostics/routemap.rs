@@ -0,0 +1,282 @@
+// Route map (`trace --output route.html`)
+// A trace as a page that can be handed to someone who doesn't read hop
+// tables: the hop chain drawn left to right with each hop coloured by loss,
+// a latency bar per hop, and, when a GeoIP City database placed the hops, the
+// path over a rough world map. Everything is inline HTML, CSS and SVG, in the
+// style of the monitor report, so the file opens offline.
+
+use super::{asn, TraceHop, TraceResult};
+
+/// Width of one hop in the chain graph, in pixels
+const HOP_WIDTH: usize = 150;
+
+const MAP_WIDTH: f64 = 720.0;
+const MAP_HEIGHT: f64 = 360.0;
+
+/// Coarse coastlines as (longitude, latitude) rings; enough to tell which
+/// continent a hop is on, nothing more
+const LAND: &[&[(f64, f64)]] = &[
+    // North America
+    &[(-168.0, 65.0), (-140.0, 70.0), (-95.0, 72.0), (-80.0, 63.0), (-60.0, 55.0), (-52.0, 47.0), (-67.0, 44.0),
+      (-76.0, 35.0), (-81.0, 25.0), (-97.0, 26.0), (-97.0, 18.0), (-87.0, 15.0), (-80.0, 8.0), (-92.0, 14.0),
+      (-105.0, 20.0), (-117.0, 32.0), (-124.0, 40.0), (-125.0, 49.0), (-135.0, 58.0), (-150.0, 60.0), (-165.0, 60.0)],
+    // South America
+    &[(-80.0, 8.0), (-60.0, 10.0), (-50.0, 0.0), (-35.0, -7.0), (-40.0, -22.0), (-48.0, -28.0), (-58.0, -38.0),
+      (-65.0, -55.0), (-72.0, -50.0), (-74.0, -40.0), (-71.0, -18.0), (-81.0, -5.0)],
+    // Greenland
+    &[(-55.0, 60.0), (-45.0, 60.0), (-20.0, 70.0), (-20.0, 80.0), (-60.0, 82.0), (-72.0, 77.0), (-55.0, 68.0)],
+    // Europe
+    &[(-10.0, 36.0), (-9.0, 43.0), (-2.0, 44.0), (-5.0, 48.0), (5.0, 53.0), (8.0, 57.0), (5.0, 62.0), (15.0, 69.0),
+      (28.0, 71.0), (40.0, 67.0), (45.0, 55.0), (40.0, 45.0), (28.0, 41.0), (25.0, 36.0), (15.0, 38.0), (12.0, 44.0),
+      (3.0, 42.0)],
+    // Great Britain
+    &[(-5.0, 50.0), (1.0, 51.0), (-2.0, 56.0), (-5.0, 58.0), (-6.0, 55.0)],
+    // Africa
+    &[(-17.0, 21.0), (-10.0, 35.0), (10.0, 37.0), (32.0, 31.0), (43.0, 12.0), (51.0, 12.0), (40.0, -3.0),
+      (40.0, -15.0), (33.0, -26.0), (20.0, -35.0), (12.0, -18.0), (9.0, 4.0), (-8.0, 4.0), (-17.0, 14.0)],
+    // Asia
+    &[(28.0, 41.0), (40.0, 45.0), (45.0, 55.0), (40.0, 67.0), (70.0, 73.0), (105.0, 78.0), (140.0, 72.0),
+      (180.0, 68.0), (170.0, 60.0), (160.0, 52.0), (142.0, 46.0), (130.0, 42.0), (122.0, 40.0), (121.0, 31.0),
+      (110.0, 20.0), (105.0, 10.0), (100.0, 3.0), (98.0, 15.0), (92.0, 22.0), (80.0, 15.0), (77.0, 8.0), (72.0, 20.0),
+      (66.0, 25.0), (57.0, 25.0), (52.0, 16.0), (43.0, 12.0), (35.0, 30.0)],
+    // Japan
+    &[(130.0, 31.0), (140.0, 35.0), (142.0, 40.0), (141.0, 45.0), (139.0, 40.0), (132.0, 34.0)],
+    // Australia
+    &[(113.0, -22.0), (114.0, -34.0), (130.0, -32.0), (140.0, -38.0), (150.0, -37.0), (153.0, -27.0), (145.0, -15.0),
+      (136.0, -12.0), (130.0, -12.0), (122.0, -18.0)],
+];
+
+/// The whole page for `result`
+pub fn render(result: &TraceResult) -> String {
+    let answered: Vec<f64> = result.hops.iter().filter(|hop| hop.ip.is_some()).map(|hop| hop.avg_rtt).collect();
+    let mut summary = vec![
+        stat("Target", format!("{} ({})", escape(&result.target), result.target_ip)),
+        stat("Hops", format!("{}{}", result.hops.len(), if result.completed { "" } else { " (destination not reached)" })),
+        stat("Total Time", format!("{:.2}s", result.total_time.as_secs_f64())),
+    ];
+    if !answered.is_empty() {
+        summary.push(stat("Average Latency", format!("{:.2} ms", answered.iter().sum::<f64>() / answered.len() as f64)));
+    }
+    let networks = asn::as_path(&result.hops);
+    if !networks.is_empty() {
+        summary.push(stat("Networks", escape(&networks.join(" → "))));
+    }
+    if result.loop_detected {
+        let routers: Vec<String> = result.loop_routers.iter().map(|ip| ip.to_string()).collect();
+        summary.push(stat("Routing Loop", format!("<span class=\"bad\">{}</span>", routers.join(" ↔ "))));
+    }
+
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>NetWeaver Route to {target}</title>
+    <style>
+        body {{ font-family: Arial, sans-serif; margin: 40px; background: #f5f5f5; }}
+        .container {{ background: white; padding: 30px; border-radius: 8px; box-shadow: 0 2px 4px rgba(0,0,0,0.1); }}
+        h1 {{ color: #00bcd4; }}
+        .stat {{ margin: 10px 0; padding: 10px; background: #f9f9f9; border-left: 4px solid #00bcd4; }}
+        .label {{ font-weight: bold; color: #555; }}
+        .value {{ color: #00bcd4; }}
+        .bad {{ color: #e53935; }}
+        .chain {{ overflow-x: auto; padding: 10px 0; }}
+        .chain text {{ font-size: 11px; fill: #555; }}
+        .chain .ip {{ font-weight: bold; fill: #333; }}
+        table {{ border-collapse: collapse; width: 100%; }}
+        th, td {{ text-align: left; padding: 6px 10px; border-bottom: 1px solid #eee; font-size: 14px; }}
+        th {{ color: #555; }}
+        .bar {{ height: 12px; background: #00bcd4; border-radius: 2px; }}
+        .legend span {{ display: inline-block; margin-right: 16px; font-size: 13px; color: #555; }}
+        .swatch {{ display: inline-block; width: 10px; height: 10px; border-radius: 5px; margin-right: 4px; }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>NetWeaver Route to {target}</h1>
+{summary}        <h2>Path</h2>
+        <div class="legend"><span><i class="swatch" style="background:{ok}"></i>no loss</span><span><i class="swatch" style="background:{some}"></i>some loss</span><span><i class="swatch" style="background:{heavy}"></i>50% or more</span><span><i class="swatch" style="background:{silent}"></i>no answer</span></div>
+        <div class="chain">
+{chain}        </div>
+        <h2>Latency per Hop</h2>
+{table}{map}    </div>
+</body>
+</html>
+"#,
+        target = escape(&result.target),
+        summary = summary.concat(),
+        ok = loss_colour(Some(0.0)),
+        some = loss_colour(Some(1.0)),
+        heavy = loss_colour(Some(50.0)),
+        silent = loss_colour(None),
+        chain = chain_svg(&result.hops),
+        table = latency_table(&result.hops),
+        map = map_svg(&result.hops),
+    )
+}
+
+fn stat(label: &str, value: String) -> String {
+    format!("        <div class=\"stat\">\n            <span class=\"label\">{}:</span>\n            <span class=\"value\">{}</span>\n        </div>\n", label, value)
+}
+
+/// Text made safe to put in HTML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Green without loss, amber with some, red from half; grey for a hop that never answered
+pub fn loss_colour(loss_pct: Option<f64>) -> &'static str {
+    match loss_pct {
+        None => "#9e9e9e",
+        Some(loss) if loss <= 0.0 => "#43a047",
+        Some(loss) if loss < 50.0 => "#fb8c00",
+        Some(_) => "#e53935",
+    }
+}
+
+fn hop_loss(hop: &TraceHop) -> Option<f64> {
+    hop.ip.map(|_| hop.packet_loss)
+}
+
+/// The hops as circles on a line, with address, name, network and latency under each
+fn chain_svg(hops: &[TraceHop]) -> String {
+    let width = hops.len().max(1) * HOP_WIDTH;
+    let mut svg = format!("            <svg width=\"{}\" height=\"150\" xmlns=\"http://www.w3.org/2000/svg\">\n", width);
+    if hops.len() > 1 {
+        svg += &format!(
+            "                <line x1=\"{}\" y1=\"30\" x2=\"{}\" y2=\"30\" stroke=\"#b2ebf2\" stroke-width=\"4\"/>\n",
+            HOP_WIDTH / 2,
+            width - HOP_WIDTH / 2
+        );
+    }
+    for (i, hop) in hops.iter().enumerate() {
+        let x = i * HOP_WIDTH + HOP_WIDTH / 2;
+        let loss = hop_loss(hop);
+        let title = match loss {
+            Some(loss) => format!("Hop {}: {:.0}% loss", hop.hop, loss),
+            None => format!("Hop {}: no answer", hop.hop),
+        };
+        svg += &format!(
+            "                <circle cx=\"{x}\" cy=\"30\" r=\"14\" fill=\"{}\"><title>{}</title></circle>\n\
+             \x20               <text x=\"{x}\" y=\"34\" text-anchor=\"middle\" style=\"fill:white;font-weight:bold\">{}</text>\n",
+            loss_colour(loss),
+            title,
+            hop.hop
+        );
+        let mut lines = vec![(hop.ip.map_or_else(|| "*".to_string(), |ip| ip.to_string()), "ip")];
+        if let Some(hostname) = &hop.hostname {
+            lines.push((truncate(hostname, 22), ""));
+        }
+        if let Some(label) = asn::as_label(hop).into_iter().chain(hop.country.clone()).reduce(|a, b| format!("{}, {}", a, b)) {
+            lines.push((truncate(&label, 22), ""));
+        }
+        if hop.ip.is_some() {
+            lines.push((format!("{:.1} ms, {:.0}% loss", hop.avg_rtt, hop.packet_loss), ""));
+        }
+        for (row, (text, class)) in lines.into_iter().enumerate() {
+            svg += &format!(
+                "                <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" class=\"{}\">{}</text>\n",
+                x,
+                64 + row * 16,
+                class,
+                escape(&text)
+            );
+        }
+    }
+    svg + "            </svg>\n"
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// A table row per hop with a bar as long as its share of the slowest hop's latency
+fn latency_table(hops: &[TraceHop]) -> String {
+    let slowest = hops.iter().filter(|hop| hop.ip.is_some()).map(|hop| hop.avg_rtt).fold(0.0, f64::max);
+    let mut html = String::from("        <table>\n            <tr><th>Hop</th><th>Address</th><th>Host</th><th>Network</th><th>Loss</th><th>Avg</th><th style=\"width:35%\"></th></tr>\n");
+    for hop in hops {
+        let network: Vec<String> = asn::as_label(hop).into_iter().chain(hop.country.clone()).collect();
+        let (loss, avg, bar) = match hop.ip {
+            Some(_) => {
+                let share = if slowest > 0.0 { hop.avg_rtt / slowest * 100.0 } else { 0.0 };
+                (
+                    format!("<span style=\"color:{}\">{:.0}%</span>", loss_colour(hop_loss(hop)), hop.packet_loss),
+                    format!("{:.2} ms", hop.avg_rtt),
+                    format!("<div class=\"bar\" style=\"width:{:.1}%\"></div>", share),
+                )
+            }
+            None => ("<span class=\"bad\">100%</span>".to_string(), "-".to_string(), String::new()),
+        };
+        html += &format!(
+            "            <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            hop.hop,
+            hop.ip.map_or_else(|| "*".to_string(), |ip| ip.to_string()),
+            escape(hop.hostname.as_deref().unwrap_or("")),
+            escape(&network.join(", ")),
+            loss,
+            avg,
+            bar
+        );
+    }
+    html + "        </table>\n"
+}
+
+/// Equirectangular projection onto the map's pixels
+pub fn project(latitude: f64, longitude: f64) -> (f64, f64) {
+    ((longitude + 180.0) / 360.0 * MAP_WIDTH, (90.0 - latitude) / 180.0 * MAP_HEIGHT)
+}
+
+/// The located hops joined in order over the world map; empty when none is located
+fn map_svg(hops: &[TraceHop]) -> String {
+    let located: Vec<(&TraceHop, (f64, f64))> = hops
+        .iter()
+        .filter_map(|hop| hop.location.map(|[latitude, longitude]| (hop, project(latitude, longitude))))
+        .collect();
+    if located.is_empty() {
+        return String::new();
+    }
+    let mut svg = format!(
+        "        <h2>Map</h2>\n        <svg viewBox=\"0 0 {w} {h}\" width=\"100%\" xmlns=\"http://www.w3.org/2000/svg\">\n\
+         \x20           <rect width=\"{w}\" height=\"{h}\" fill=\"#e0f7fa\"/>\n",
+        w = MAP_WIDTH,
+        h = MAP_HEIGHT
+    );
+    for longitude in (-150..=150).step_by(30) {
+        let (x, _) = project(0.0, f64::from(longitude));
+        svg += &format!("            <line x1=\"{x:.1}\" y1=\"0\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"#b2ebf2\"/>\n", MAP_HEIGHT);
+    }
+    for latitude in (-60..=60).step_by(30) {
+        let (_, y) = project(f64::from(latitude), 0.0);
+        svg += &format!("            <line x1=\"0\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" stroke=\"#b2ebf2\"/>\n", MAP_WIDTH);
+    }
+    for ring in LAND {
+        let points: Vec<String> = ring
+            .iter()
+            .map(|&(longitude, latitude)| {
+                let (x, y) = project(latitude, longitude);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+        svg += &format!("            <polygon points=\"{}\" fill=\"#cfd8dc\" stroke=\"#b0bec5\"/>\n", points.join(" "));
+    }
+    let path: Vec<String> = located.iter().map(|(_, (x, y))| format!("{:.1},{:.1}", x, y)).collect();
+    svg += &format!(
+        "            <polyline points=\"{}\" fill=\"none\" stroke=\"#00bcd4\" stroke-width=\"2\"/>\n",
+        path.join(" ")
+    );
+    for (hop, (x, y)) in &located {
+        let place = hop.country.as_deref().map(|country| format!(" ({})", country)).unwrap_or_default();
+        svg += &format!(
+            "            <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"{}\"><title>Hop {}: {}{}</title></circle>\n",
+            x,
+            y,
+            loss_colour(hop_loss(hop)),
+            hop.hop,
+            hop.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            escape(&place)
+        );
+    }
+    svg + "        </svg>\n"
+}
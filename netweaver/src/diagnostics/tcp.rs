@@ -0,0 +1,167 @@
+// TCP SYN traceroute probes
+// Firewalls that drop ICMP and UDP still have to pass TCP to the services
+// behind them, so a TTL-limited SYN to that port follows the real path.
+// Every SYN of a path leaves from the same port (see flow) and is told
+// apart by its sequence number.
+// Routers answer with ICMP Time Exceeded (read on a raw ICMP socket), the
+// target with a SYN-ACK or RST (read on the raw TCP socket that sent it).
+
+use rand::Rng;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::flow;
+use crate::ffi;
+use crate::utils::icmp::{self, IcmpReply, ReplyKind};
+use crate::utils::ip;
+
+/// Send one SYN along `path` to `target:port` with the given TTL and return whoever answered
+pub async fn probe(target: Ipv4Addr, port: u16, ttl: u8, path: u8, timeout: Duration) -> Option<IcmpReply> {
+    tokio::task::spawn_blocking(move || probe_blocking(target, port, ttl, path, timeout))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// The address the routing table would send from towards `target`; the
+/// SYN's checksum covers it, so the kernel can't fill it in for us
+pub(super) fn source_for(target: Ipv4Addr, port: u16) -> Option<Ipv4Addr> {
+    let udp = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    udp.connect(SocketAddrV4::new(target, port)).ok()?;
+    match udp.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(ip) => Some(ip),
+        std::net::IpAddr::V6(_) => None,
+    }
+}
+
+fn open_raw(protocol: ffi::nw_protocol_t) -> Option<ffi::nw_socket_t> {
+    let mut sock: ffi::nw_socket_t = unsafe { std::mem::zeroed() };
+    let status = unsafe { ffi::nw_socket_create(&mut sock, libc::AF_INET, libc::SOCK_RAW, protocol) };
+    (status == ffi::nw_error_t_NW_SUCCESS).then_some(sock)
+}
+
+fn probe_blocking(target: Ipv4Addr, port: u16, ttl: u8, path: u8, timeout: Duration) -> Option<IcmpReply> {
+    let src = source_for(target, port)?;
+    let mut tcp = open_raw(ffi::nw_protocol_t_NW_PROTO_TCP)?;
+    let Some(mut icmp_sock) = open_raw(ffi::nw_protocol_t_NW_PROTO_ICMP) else {
+        unsafe { ffi::nw_socket_close(&mut tcp) };
+        return None;
+    };
+
+    let src_port = flow::tcp_source_port(path);
+    let seq: u32 = rand::thread_rng().gen();
+    let mut packet: Box<ffi::nw_packet_t> = Box::new(unsafe { std::mem::zeroed() });
+    let reply = unsafe {
+        let crafted = ffi::nw_packet_craft_tcp_syn(&mut *packet, u32::from(src), u32::from(target), src_port, port);
+        // IP_HDRINCL: the kernel refills the header checksum on send
+        packet.data[8] = ttl;
+        set_sequence(&mut packet, src, target, seq);
+        let sent_at = Instant::now();
+        if crafted == ffi::nw_error_t_NW_SUCCESS
+            && ffi::nw_packet_send_raw(&mut tcp, &*packet) == ffi::nw_error_t_NW_SUCCESS
+        {
+            receive(&mut [&mut tcp, &mut icmp_sock], &mut packet, target, (src_port, port), seq, sent_at + timeout)
+                .map(|reply| IcmpReply { rtt: sent_at.elapsed(), ..reply })
+        } else {
+            None
+        }
+    };
+
+    unsafe {
+        ffi::nw_socket_close(&mut tcp);
+        ffi::nw_socket_close(&mut icmp_sock);
+    }
+    reply
+}
+
+/// Wait on both raw sockets until one of them sees the answer to our SYN
+unsafe fn receive(
+    socks: &mut [&mut ffi::nw_socket_t; 2],
+    packet: &mut ffi::nw_packet_t,
+    target: Ipv4Addr,
+    (src_port, dst_port): (u16, u16),
+    seq: u32,
+    deadline: Instant,
+) -> Option<IcmpReply> {
+    loop {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
+        let mut fds = socks.each_ref().map(|sock| libc::pollfd { fd: sock.fd, events: libc::POLLIN, revents: 0 });
+        let wait_ms = (remaining.as_millis() as libc::c_int).max(1);
+        if libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, wait_ms) <= 0 {
+            return None;
+        }
+
+        for (sock, fd) in socks.iter_mut().zip(fds) {
+            if fd.revents & libc::POLLIN == 0 || ffi::nw_packet_recv_raw(&mut **sock, packet, 1) != ffi::nw_error_t_NW_SUCCESS {
+                continue;
+            }
+            let len = packet.length.min(packet.data.len());
+            if let Some(reply) = match_probe(&packet.data[..len], target, src_port, dst_port, seq) {
+                return Some(reply);
+            }
+        }
+    }
+}
+
+/// Give a crafted SYN (20-byte IP header, bare TCP header) sequence number
+/// `seq` and fix up its checksum
+fn set_sequence(packet: &mut ffi::nw_packet_t, src: Ipv4Addr, target: Ipv4Addr, seq: u32) {
+    let tcp = 20..40;
+    packet.data[24..28].copy_from_slice(&seq.to_be_bytes());
+    packet.data[36..38].fill(0);
+    let mut covered = Vec::with_capacity(32);
+    covered.extend_from_slice(&src.octets());
+    covered.extend_from_slice(&target.octets());
+    covered.extend_from_slice(&[0, 6, 0, 20]);
+    covered.extend_from_slice(&packet.data[tcp]);
+    packet.data[36..38].copy_from_slice(&ip::checksum(&covered).to_be_bytes());
+}
+
+/// `match_reply` for the one SYN with sequence number `seq`; the others of
+/// the path share its ports. The target acknowledges `seq + 1`, routers
+/// quote `seq` itself
+pub fn match_probe(packet: &[u8], target: Ipv4Addr, src_port: u16, dst_port: u16, seq: u32) -> Option<IcmpReply> {
+    let header = packet.get((packet.first()? & 0x0F) as usize * 4..)?;
+    let ours = match packet.get(9)? {
+        6 => header.get(8..12).map(|ack| u32::from_be_bytes(ack.try_into().unwrap())) == Some(seq.wrapping_add(1)),
+        1 => {
+            let quoted = icmp::parse_error(packet)?.transport;
+            u32::from_be_bytes([quoted[4], quoted[5], quoted[6], quoted[7]]) == seq
+        }
+        _ => false,
+    };
+    ours.then(|| match_reply(packet, target, src_port, dst_port)).flatten()
+}
+
+/// Match a raw IPv4 packet against the SYN we sent from `src_port` to
+/// `target:dst_port`; the RTT is left for the caller to fill in
+///
+/// A SYN-ACK or RST from the target is the arrival and comes back as an
+/// `EchoReply`; ICMP errors quoting the SYN name the router that sent them.
+pub fn match_reply(packet: &[u8], target: Ipv4Addr, src_port: u16, dst_port: u16) -> Option<IcmpReply> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let from = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let ports = |header: &[u8]| (u16::from_be_bytes([header[0], header[1]]), u16::from_be_bytes([header[2], header[3]]));
+
+    match packet[9] {
+        6 => {
+            let tcp = packet.get((packet[0] & 0x0F) as usize * 4..)?;
+            // SYN-ACK or RST
+            let answered = tcp.len() >= 20 && (tcp[13] & 0x12 == 0x12 || tcp[13] & 0x04 != 0);
+            (answered && from == target && ports(tcp) == (dst_port, src_port)).then_some(IcmpReply {
+                from,
+                rtt: Duration::ZERO,
+                ttl: Some(packet[8]),
+                kind: ReplyKind::EchoReply,
+            })
+        }
+        1 => {
+            let error = icmp::parse_error(packet)?;
+            let ours = error.protocol == 6 && error.destination == target && ports(error.transport) == (src_port, dst_port);
+            ours.then_some(IcmpReply { from: error.from, rtt: Duration::ZERO, ttl: Some(error.ttl), kind: error.kind })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,430 @@
+// Throughput testing
+// A lightweight iperf: one NetWeaver listens with `--server`, another
+// connects with `--client` and pushes (or with `--reverse`, pulls) data over
+// one or more TCP streams for a fixed time. Each stream opens with a short
+// header saying which way the data flows and for how long; after an upload
+// the server answers with the byte count it actually received, so the result
+// reflects what crossed the link rather than what filled the send buffers.
+// The latest client result is kept in the data directory for `report`.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::analytics::BandwidthAnalyzer;
+use crate::utils::{self, format_bitrate};
+use crate::{report, status};
+
+/// The port iperf3 made familiar
+pub const DEFAULT_PORT: u16 = 5201;
+
+const MAGIC: &[u8; 4] = b"NWTP";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 12;
+/// Bytes per write or read
+const BLOCK: usize = 128 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the client waits for the server's received-bytes count
+const COUNT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Smoothing window of the running rate printed next to each interval
+const RUNNING_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Client to server
+    Upload,
+    /// Server to client (`--reverse`)
+    Download,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThroughputOptions {
+    pub port: u16,
+    pub duration: Duration,
+    /// TCP streams run side by side
+    pub parallel: u8,
+    pub direction: Direction,
+}
+
+impl Default for ThroughputOptions {
+    fn default() -> Self {
+        Self { port: DEFAULT_PORT, duration: Duration::from_secs(10), parallel: 1, direction: Direction::Upload }
+    }
+}
+
+/// What one stream announces to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamHeader {
+    pub direction: Direction,
+    /// 1-based
+    pub stream: u8,
+    pub streams: u8,
+    pub duration: Duration,
+}
+
+impl StreamHeader {
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[5] = match self.direction {
+            Direction::Upload => 0,
+            Direction::Download => 1,
+        };
+        header[6] = self.stream;
+        header[7] = self.streams;
+        header[8..].copy_from_slice(&(self.duration.as_millis().min(u32::MAX as u128) as u32).to_be_bytes());
+        header
+    }
+
+    pub fn decode(header: &[u8; HEADER_LEN]) -> Result<Self> {
+        if &header[..4] != MAGIC {
+            anyhow::bail!("not a NetWeaver throughput client");
+        }
+        if header[4] != VERSION {
+            anyhow::bail!("unsupported protocol version {}", header[4]);
+        }
+        let direction = match header[5] {
+            0 => Direction::Upload,
+            1 => Direction::Download,
+            other => anyhow::bail!("unknown direction {}", other),
+        };
+        let millis = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        Ok(Self { direction, stream: header[6], streams: header[7], duration: Duration::from_millis(u64::from(millis)) })
+    }
+}
+
+/// Bytes moved during one second of the test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThroughputInterval {
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub bytes: u64,
+    pub bits_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputResult {
+    pub server: String,
+    pub direction: Direction,
+    pub streams: u8,
+    pub finished_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    /// Received by the far end for uploads, by us for downloads
+    pub bytes: u64,
+    pub avg_bits_per_sec: f64,
+    /// Over the per-second intervals
+    pub min_bits_per_sec: f64,
+    pub p50_bits_per_sec: f64,
+    pub p90_bits_per_sec: f64,
+    pub max_bits_per_sec: f64,
+    pub intervals: Vec<ThroughputInterval>,
+}
+
+impl ThroughputResult {
+    pub fn new(
+        server: String,
+        direction: Direction,
+        streams: u8,
+        bytes: u64,
+        elapsed: Duration,
+        intervals: Vec<ThroughputInterval>,
+    ) -> Self {
+        let mut rates: Vec<f64> = intervals.iter().map(|interval| interval.bits_per_sec).collect();
+        rates.sort_by(f64::total_cmp);
+        let secs = elapsed.as_secs_f64();
+        Self {
+            server,
+            direction,
+            streams,
+            finished_at: Utc::now(),
+            duration_secs: secs,
+            bytes,
+            avg_bits_per_sec: if secs > 0.0 { bytes as f64 * 8.0 / secs } else { 0.0 },
+            min_bits_per_sec: rates.first().copied().unwrap_or_default(),
+            p50_bits_per_sec: percentile(&rates, 50.0),
+            p90_bits_per_sec: percentile(&rates, 90.0),
+            max_bits_per_sec: rates.last().copied().unwrap_or_default(),
+            intervals,
+        }
+    }
+}
+
+/// Nearest-rank percentile of ascending `sorted`; 0 when empty
+pub fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// `$XDG_DATA_HOME/netweaver/throughput.json`, the latest client result
+pub fn latest_path() -> Result<PathBuf> {
+    Ok(utils::data_dir()?.join("throughput.json"))
+}
+
+/// The latest client result, if a test ever ran here
+pub fn load_latest(path: &Path) -> Result<Option<ThroughputResult>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+    }
+}
+
+fn store_latest(path: &Path, result: &ThroughputResult) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(result)?)
+        .with_context(|| format!("Failed to write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace '{}'", path.display()))
+}
+
+pub async fn run_server(port: u16) -> Result<()> {
+    status!("{}", "NetWeaver Throughput Server".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
+
+    let listener = TcpListener::bind(("0.0.0.0", port)).await
+        .with_context(|| format!("Failed to listen on port {}", port))?;
+    status!("👂 Listening on port {} (Ctrl+C to stop)\n", port.to_string().bright_yellow());
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = serve_stream(stream, peer).await {
+                report!("{}", format!("✗ {}: {:#}", peer, e).bright_red());
+            }
+        });
+    }
+}
+
+async fn serve_stream(mut stream: TcpStream, peer: SocketAddr) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let mut header = [0u8; HEADER_LEN];
+    tokio::time::timeout(CONNECT_TIMEOUT, stream.read_exact(&mut header)).await
+        .context("no header received")??;
+    let header = StreamHeader::decode(&header)?;
+
+    let start = Instant::now();
+    let mut buf = vec![0u8; BLOCK];
+    let bytes = match header.direction {
+        Direction::Upload => {
+            let mut received = 0u64;
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                received += n as u64;
+            }
+            stream.write_all(&received.to_be_bytes()).await?;
+            received
+        }
+        Direction::Download => {
+            let mut sent = 0u64;
+            while start.elapsed() < header.duration {
+                stream.write_all(&buf).await?;
+                sent += buf.len() as u64;
+            }
+            stream.shutdown().await?;
+            sent
+        }
+    };
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let verb = if header.direction == Direction::Upload { "received from" } else { "sent to" };
+    report!(
+        "✅ {} {} {} (stream {}/{}) in {:.1}s: {}",
+        utils::format_bytes(bytes),
+        verb,
+        peer.to_string().bright_green(),
+        header.stream,
+        header.streams,
+        elapsed,
+        format_bitrate(bytes as f64 * 8.0 / elapsed.max(f64::EPSILON)).bright_yellow()
+    );
+    Ok(())
+}
+
+pub async fn run_client(host: String, opts: ThroughputOptions, output: Option<String>) -> Result<()> {
+    status!("{}", "NetWeaver Throughput Test".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
+
+    let ip = utils::network::resolve_hostname(&host).await.context("Failed to resolve server")?;
+    let server = SocketAddr::from((ip, opts.port));
+    let streams = opts.parallel.max(1);
+    let arrow = if opts.direction == Direction::Upload { "→" } else { "←" };
+    status!("🎯 Server: {} ({})", host.bright_yellow(), server.to_string().bright_green());
+    status!("📡 {} {} stream(s) for {:.0}s, this host {} server\n", match opts.direction {
+        Direction::Upload => "Upload over",
+        Direction::Download => "Download over",
+    }, streams, opts.duration.as_secs_f64(), arrow);
+
+    let mut connections = Vec::new();
+    for stream in 1..=streams {
+        let mut connection = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(server)).await
+            .with_context(|| format!("Timed out connecting to {}", server))?
+            .with_context(|| format!("Failed to connect to {} (is `netweaver throughput --server` running?)", server))?;
+        connection.set_nodelay(true)?;
+        let header = StreamHeader { direction: opts.direction, stream, streams, duration: opts.duration };
+        connection.write_all(&header.encode()).await?;
+        connections.push(connection);
+    }
+
+    let moved = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::from_std(start + opts.duration);
+    let tasks: Vec<_> = connections
+        .into_iter()
+        .map(|connection| tokio::spawn(transfer(connection, opts.direction, deadline, Arc::clone(&moved))))
+        .collect();
+
+    let mut analyzer = BandwidthAnalyzer::new(RUNNING_WINDOW);
+    analyzer.add_measurement(0);
+    let mut intervals = Vec::new();
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + Duration::from_secs(1), Duration::from_secs(1));
+    let mut last = (0.0, 0u64);
+    let mut interrupted = false;
+    loop {
+        let done = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = tokio::time::sleep_until(deadline) => true,
+            _ = tokio::signal::ctrl_c() => { interrupted = true; true }
+        };
+        let now = start.elapsed().as_secs_f64();
+        let total = moved.load(Ordering::Relaxed);
+        if now - last.0 >= 0.05 {
+            let bytes = total - last.1;
+            analyzer.add_measurement(bytes);
+            let interval = ThroughputInterval {
+                start_secs: last.0,
+                end_secs: now,
+                bytes,
+                bits_per_sec: bytes as f64 * 8.0 / (now - last.0),
+            };
+            report!(
+                "[{:>5.1}-{:>5.1}s] {:>16}   ({:.0}s avg {})",
+                interval.start_secs,
+                interval.end_secs,
+                format_bitrate(interval.bits_per_sec).bright_yellow(),
+                RUNNING_WINDOW.as_secs_f64().min(now),
+                format_bitrate(analyzer.current_bandwidth() * 8.0)
+            );
+            intervals.push(interval);
+            last = (now, total);
+        }
+        if done {
+            break;
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let mut confirmed = 0u64;
+    let mut all_confirmed = !interrupted;
+    for task in tasks {
+        if interrupted {
+            task.abort();
+            continue;
+        }
+        match task.await {
+            Ok(Ok(Some(bytes))) => confirmed += bytes,
+            Ok(Ok(None)) => all_confirmed = false,
+            Ok(Err(e)) => {
+                status!("⚠️  Stream failed: {:#}", e);
+                all_confirmed = false;
+            }
+            Err(_) => all_confirmed = false,
+        }
+    }
+    // The server's own count when every stream reported one
+    let bytes = if all_confirmed && opts.direction == Direction::Upload { confirmed } else { moved.load(Ordering::Relaxed) };
+
+    let result = ThroughputResult::new(host, opts.direction, streams, bytes, elapsed, intervals);
+    display_result(&result);
+
+    match latest_path().and_then(|path| store_latest(&path, &result)) {
+        Ok(()) => {}
+        Err(e) => status!("⚠️  Could not keep the result for reports: {:#}", e),
+    }
+    if let Some(output_path) = output {
+        super::save_result(&result, &output_path)?;
+        status!("\n💾 Throughput results saved to: {}", output_path.bright_green());
+    }
+    Ok(())
+}
+
+/// Move data until `deadline`; for uploads, the byte count the server
+/// confirmed receiving
+async fn transfer(
+    mut connection: TcpStream,
+    direction: Direction,
+    deadline: tokio::time::Instant,
+    moved: Arc<AtomicU64>,
+) -> Result<Option<u64>> {
+    let mut buf = vec![0u8; BLOCK];
+    match direction {
+        Direction::Upload => {
+            while let Ok(written) = tokio::time::timeout_at(deadline, connection.write_all(&buf)).await {
+                written?;
+                moved.fetch_add(buf.len() as u64, Ordering::Relaxed);
+            }
+            connection.shutdown().await?;
+            let count = tokio::time::timeout(COUNT_TIMEOUT, connection.read_u64()).await;
+            Ok(count.ok().and_then(|count| count.ok()))
+        }
+        Direction::Download => {
+            // The server stops on its own; the grace period covers data in flight
+            let until = deadline + Duration::from_secs(1);
+            while let Ok(read) = tokio::time::timeout_at(until, connection.read(&mut buf)).await {
+                let n = read?;
+                if n == 0 {
+                    break;
+                }
+                moved.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn display_result(result: &ThroughputResult) {
+    report!("\n{}", "Throughput Summary".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
+    let direction = match result.direction {
+        Direction::Upload => "upload",
+        Direction::Download => "download",
+    };
+    report!(
+        "📦 {} transferred ({}, {} stream(s)) in {:.1}s",
+        utils::format_bytes(result.bytes),
+        direction,
+        result.streams,
+        result.duration_secs
+    );
+    report!("⚡ Average: {}", format_bitrate(result.avg_bits_per_sec).bright_green().bold());
+    if !result.intervals.is_empty() {
+        report!(
+            "📊 Per second: min {} | p50 {} | p90 {} | max {}",
+            format_bitrate(result.min_bits_per_sec),
+            format_bitrate(result.p50_bits_per_sec),
+            format_bitrate(result.p90_bits_per_sec),
+            format_bitrate(result.max_bits_per_sec)
+        );
+    }
+}
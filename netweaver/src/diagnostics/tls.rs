@@ -0,0 +1,461 @@
+// TLS handshake inspection
+// One full handshake shows what a client would get: the negotiated version,
+// cipher suite and ALPN protocol, and the certificate chain. The chain is
+// checked against the system's CA bundle on the side, by a verifier that
+// records the verdict and lets the handshake finish either way, so broken
+// chains can still be described. Each version is then tried on its own:
+// rustls covers TLS 1.2 and 1.3, and since it refuses to speak anything
+// older, 1.0 and 1.1 are probed with a hand-built ClientHello and the
+// version in the ServerHello (or the alert that comes back instead).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use parking_lot::Mutex;
+use rand::RngCore;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::version::{TLS12, TLS13};
+use rustls::{Certificate, ClientConfig, ProtocolVersion, RootCertStore, ServerName, SupportedProtocolVersion};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tokio::net::TcpStream;
+use x509_parser::prelude::{FromDer, X509Certificate};
+use x509_parser::public_key::PublicKey;
+
+use crate::scanner::tls::{parse_certificate, EXPIRY_WARNING_DAYS};
+use crate::utils::{self, source};
+use crate::{report, status};
+
+/// Connect plus handshake, per attempt
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the common platforms keep their CA bundle; `SSL_CERT_FILE` wins
+const CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/etc/openssl/cert.pem",
+    "/opt/homebrew/etc/openssl@3/cert.pem",
+];
+
+/// Suites a TLS 1.0/1.1 server of any vintage has at least one of
+const LEGACY_SUITES: &[u16] = &[0xc013, 0xc014, 0xc009, 0xc00a, 0x0033, 0x0039, 0x002f, 0x0035, 0x000a];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "TLSv1.0")]
+    Tls10,
+    #[serde(rename = "TLSv1.1")]
+    Tls11,
+    #[serde(rename = "TLSv1.2")]
+    Tls12,
+    #[serde(rename = "TLSv1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    pub const ALL: [TlsVersion; 4] = [TlsVersion::Tls10, TlsVersion::Tls11, TlsVersion::Tls12, TlsVersion::Tls13];
+
+    /// Wire value of the version field
+    pub fn wire(self) -> u16 {
+        match self {
+            TlsVersion::Tls10 => 0x0301,
+            TlsVersion::Tls11 => 0x0302,
+            TlsVersion::Tls12 => 0x0303,
+            TlsVersion::Tls13 => 0x0304,
+        }
+    }
+
+    /// Versions no longer considered safe (RFC 8996)
+    pub fn is_deprecated(self) -> bool {
+        self < TlsVersion::Tls12
+    }
+
+    fn from_rustls(version: ProtocolVersion) -> Option<Self> {
+        match version {
+            ProtocolVersion::TLSv1_0 => Some(TlsVersion::Tls10),
+            ProtocolVersion::TLSv1_1 => Some(TlsVersion::Tls11),
+            ProtocolVersion::TLSv1_2 => Some(TlsVersion::Tls12),
+            ProtocolVersion::TLSv1_3 => Some(TlsVersion::Tls13),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TlsVersion::Tls10 => "TLS 1.0",
+            TlsVersion::Tls11 => "TLS 1.1",
+            TlsVersion::Tls12 => "TLS 1.2",
+            TlsVersion::Tls13 => "TLS 1.3",
+        })
+    }
+}
+
+/// One certificate of the presented chain, leaf first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateDetails {
+    pub subject: String,
+    pub issuer: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub san: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// "RSA", "EC", "DSA" or "unknown"
+    pub key_type: String,
+    pub key_bits: usize,
+    pub self_signed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionSupport {
+    pub version: TlsVersion,
+    pub accepted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsReport {
+    pub host: String,
+    pub port: u16,
+    pub address: SocketAddr,
+    /// What a default handshake negotiated
+    pub version: Option<TlsVersion>,
+    pub cipher_suite: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+    pub chain: Vec<CertificateDetails>,
+    /// Whether the chain validates for `host` against the system roots;
+    /// None when no CA bundle was found
+    pub chain_valid: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_error: Option<String>,
+    /// Until the leaf expires, negative once it has
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days_until_expiry: Option<i64>,
+    /// Empty unless the versions were probed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<VersionSupport>,
+}
+
+impl TlsReport {
+    /// Deprecated versions the server still accepts
+    pub fn weak_versions(&self) -> Vec<TlsVersion> {
+        self.versions.iter()
+            .filter(|support| support.accepted && support.version.is_deprecated())
+            .map(|support| support.version)
+            .collect()
+    }
+}
+
+/// The system CA bundle, loaded once; None when there isn't one
+fn system_roots() -> Option<Arc<RootCertStore>> {
+    static ROOTS: OnceLock<Option<Arc<RootCertStore>>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        let from_env = std::env::var("SSL_CERT_FILE").ok();
+        let paths = from_env.iter().map(String::as_str).chain(CA_BUNDLES.iter().copied());
+        paths.filter_map(|path| {
+            let file = std::fs::File::open(path).ok()?;
+            let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file)).ok()?;
+            let mut roots = RootCertStore::empty();
+            roots.add_parsable_certificates(&certs);
+            (!roots.is_empty()).then(|| Arc::new(roots))
+        }).next()
+    }).clone()
+}
+
+/// Accepts every certificate so the handshake completes, but first asks the
+/// webpki verifier what it makes of the chain and keeps the answer
+struct RecordingVerifier {
+    webpki: Option<WebPkiVerifier>,
+    verdict: Mutex<Option<Result<(), String>>>,
+}
+
+impl RecordingVerifier {
+    fn new() -> Self {
+        Self { webpki: system_roots().map(|roots| WebPkiVerifier::new(roots, None)), verdict: Mutex::new(None) }
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(webpki) = &self.webpki {
+            let verdict = webpki.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now);
+            *self.verdict.lock() = Some(verdict.map(|_| ()).map_err(|e| e.to_string()));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn connector(versions: &[&'static SupportedProtocolVersion], verifier: Arc<RecordingVerifier>) -> Result<TlsConnector> {
+    let mut config = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)?
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+fn server_name(host: &str, address: SocketAddr) -> ServerName {
+    ServerName::try_from(host).unwrap_or(ServerName::IpAddress(address.ip()))
+}
+
+async fn handshake(
+    host: &str,
+    address: SocketAddr,
+    versions: &[&'static SupportedProtocolVersion],
+    verifier: Arc<RecordingVerifier>,
+    timeout: Duration,
+) -> Result<TlsStream<TcpStream>> {
+    let connector = connector(versions, verifier)?;
+    let attempt = async {
+        let stream = source::connect(address).await.with_context(|| format!("Failed to connect to {}", address))?;
+        connector.connect(server_name(host, address), stream).await
+            .with_context(|| format!("TLS handshake with {} failed", address))
+    };
+    tokio::time::timeout(timeout, attempt).await
+        .with_context(|| format!("No TLS handshake with {} within {}s", address, timeout.as_secs()))?
+}
+
+/// Handshake with `host:port` and describe the session and the chain; with
+/// `probe_versions`, also try each TLS version on its own
+pub async fn inspect(host: &str, port: u16, probe_versions: bool, timeout: Duration) -> Result<TlsReport> {
+    let ip = utils::network::resolve_hostname(host).await.context("Failed to resolve target")?;
+    let address = SocketAddr::new(IpAddr::V4(ip), port);
+
+    let verifier = Arc::new(RecordingVerifier::new());
+    let stream = handshake(host, address, &[&TLS13, &TLS12], Arc::clone(&verifier), timeout).await?;
+    let (_, session) = stream.get_ref();
+
+    let chain: Vec<CertificateDetails> = session.peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|cert| describe_certificate(port, &cert.0))
+        .collect();
+    let verdict = verifier.verdict.lock().clone();
+    let mut report = TlsReport {
+        host: host.to_string(),
+        port,
+        address,
+        version: session.protocol_version().and_then(TlsVersion::from_rustls),
+        cipher_suite: session.negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default(),
+        alpn: session.alpn_protocol().map(|proto| String::from_utf8_lossy(proto).into_owned()),
+        days_until_expiry: chain.first().map(|leaf| (leaf.not_after - Utc::now()).num_days()),
+        chain,
+        chain_valid: verdict.as_ref().map(Result::is_ok),
+        validation_error: verdict.and_then(Result::err),
+        versions: Vec::new(),
+    };
+    drop(stream);
+
+    if probe_versions {
+        for version in TlsVersion::ALL {
+            let accepted = match version {
+                TlsVersion::Tls12 | TlsVersion::Tls13 => {
+                    let only: &'static SupportedProtocolVersion = if version == TlsVersion::Tls13 { &TLS13 } else { &TLS12 };
+                    handshake(host, address, &[only], Arc::new(RecordingVerifier::new()), timeout).await.is_ok()
+                }
+                TlsVersion::Tls10 | TlsVersion::Tls11 => probe_legacy(host, address, version, timeout).await,
+            };
+            report.versions.push(VersionSupport { version, accepted });
+        }
+    }
+    Ok(report)
+}
+
+/// Subject, validity and public key of a DER certificate
+pub fn describe_certificate(port: u16, der: &[u8]) -> Option<CertificateDetails> {
+    let info = parse_certificate(port, der)?;
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let (key_type, key_bits) = match cert.public_key().parsed() {
+        Ok(PublicKey::RSA(rsa)) => {
+            // The modulus is a DER integer: drop the sign byte before counting
+            let modulus: Vec<u8> = rsa.modulus.iter().copied().skip_while(|&b| b == 0).collect();
+            let bits = modulus.first().map_or(0, |first| modulus.len() * 8 - first.leading_zeros() as usize);
+            ("RSA", bits)
+        }
+        Ok(key @ PublicKey::EC(_)) => ("EC", key.key_size()),
+        Ok(key @ PublicKey::DSA(_)) => ("DSA", key.key_size()),
+        _ => ("unknown", 0),
+    };
+    Some(CertificateDetails {
+        subject: info.subject,
+        issuer: info.issuer,
+        san: info.san,
+        not_before: info.not_before,
+        not_after: info.not_after,
+        key_type: key_type.to_string(),
+        key_bits,
+        self_signed: info.self_signed,
+    })
+}
+
+/// A TLS 1.0/1.1-style ClientHello record offering `version`, with SNI when
+/// `server_name` is a DNS name
+pub fn client_hello(version: u16, server_name: Option<&str>) -> Vec<u8> {
+    let mut extensions = Vec::new();
+    if let Some(name) = server_name.filter(|name| name.parse::<IpAddr>().is_err()) {
+        let name = name.as_bytes();
+        extensions.extend(0x0000u16.to_be_bytes());
+        extensions.extend((name.len() as u16 + 5).to_be_bytes());
+        extensions.extend((name.len() as u16 + 3).to_be_bytes());
+        extensions.push(0);
+        extensions.extend((name.len() as u16).to_be_bytes());
+        extensions.extend(name);
+    }
+    // supported_groups: secp256r1, secp384r1, x25519
+    extensions.extend([0x00, 0x0a, 0x00, 0x08, 0x00, 0x06, 0x00, 0x17, 0x00, 0x18, 0x00, 0x1d]);
+    // ec_point_formats: uncompressed
+    extensions.extend([0x00, 0x0b, 0x00, 0x02, 0x01, 0x00]);
+    // renegotiation_info, empty
+    extensions.extend([0xff, 0x01, 0x00, 0x01, 0x00]);
+
+    let mut hello = Vec::new();
+    hello.extend(version.to_be_bytes());
+    let mut random = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut random);
+    hello.extend(random);
+    hello.push(0);
+    hello.extend((LEGACY_SUITES.len() as u16 * 2).to_be_bytes());
+    for suite in LEGACY_SUITES {
+        hello.extend(suite.to_be_bytes());
+    }
+    hello.extend([1, 0]);
+    hello.extend((extensions.len() as u16).to_be_bytes());
+    hello.extend(extensions);
+
+    let mut handshake = vec![0x01];
+    handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend(hello);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend((handshake.len() as u16).to_be_bytes());
+    record.extend(handshake);
+    record
+}
+
+/// Version a ServerHello record picked; None for an alert or anything else
+pub fn server_hello_version(response: &[u8]) -> Option<u16> {
+    match response {
+        [0x16, _, _, _, _, 0x02, _, _, _, major, minor, ..] => Some(u16::from_be_bytes([*major, *minor])),
+        _ => None,
+    }
+}
+
+/// Whether the server answers a ClientHello capped at `version` with that version
+async fn probe_legacy(host: &str, address: SocketAddr, version: TlsVersion, timeout: Duration) -> bool {
+    let attempt = async {
+        let mut stream = source::connect(address).await.ok()?;
+        stream.write_all(&client_hello(version.wire(), Some(host))).await.ok()?;
+        let mut response = Vec::new();
+        let mut buf = [0u8; 512];
+        while response.len() < 11 {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(len) => response.extend_from_slice(&buf[..len]),
+            }
+        }
+        server_hello_version(&response)
+    };
+    tokio::time::timeout(timeout, attempt).await.ok().flatten() == Some(version.wire())
+}
+
+/// "host", "host:port" or "[v6]:port" with `default_port` when none is given
+fn split_target(target: &str, default_port: u16) -> Result<(String, u16)> {
+    match target.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let port = port.parse().with_context(|| format!("Invalid port in '{}'", target))?;
+            Ok((host.trim_start_matches('[').trim_end_matches(']').to_string(), port))
+        }
+        _ => Ok((target.to_string(), default_port)),
+    }
+}
+
+pub async fn run_tls(target: String, port: u16, output: Option<String>) -> Result<()> {
+    status!("{}", "NetWeaver TLS Inspection".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
+
+    let (host, port) = split_target(&target, port)?;
+    status!("🎯 Target: {}:{}\n", host.bright_yellow(), port);
+
+    let report = inspect(&host, port, true, DEFAULT_TIMEOUT).await?;
+    display_report(&report);
+
+    if let Some(output_path) = output {
+        super::save_result(&report, &output_path)?;
+        status!("\n💾 TLS report saved to: {}", output_path.bright_green());
+    }
+    Ok(())
+}
+
+fn display_report(report: &TlsReport) {
+    let na = || "-".to_string();
+    report!("{} {}", "Address:".bright_white(), report.address);
+    report!("{} {}", "Protocol:".bright_white(), report.version.map_or_else(na, |v| v.to_string()).bright_green());
+    report!("{} {}", "Cipher suite:".bright_white(), report.cipher_suite);
+    report!("{} {}", "ALPN:".bright_white(), report.alpn.clone().unwrap_or_else(|| "none".to_string()));
+
+    match (report.chain_valid, &report.validation_error) {
+        (Some(true), _) => report!("{} {}", "Chain:".bright_white(), "✓ valid against the system roots".bright_green()),
+        (Some(false), error) => report!("{} {} {}", "Chain:".bright_white(), "✗ does not validate:".bright_red(),
+                                        error.as_deref().unwrap_or("unknown error")),
+        (None, _) => report!("{} {}", "Chain:".bright_white(), "not checked (no system CA bundle found)".dimmed()),
+    }
+    if let Some(days) = report.days_until_expiry {
+        let text = if days < 0 {
+            format!("expired {} days ago", -days).bright_red()
+        } else if days < EXPIRY_WARNING_DAYS {
+            format!("{} days", days).bright_yellow()
+        } else {
+            format!("{} days", days).bright_green()
+        };
+        report!("{} {}", "Expires in:".bright_white(), text);
+    }
+
+    report!("\n{}", "Certificate chain:".bright_cyan().bold());
+    for (depth, cert) in report.chain.iter().enumerate() {
+        report!("  [{}] {}", depth, cert.subject.bright_yellow());
+        report!("      Issuer: {}{}", cert.issuer, if cert.self_signed { " (self-signed)" } else { "" });
+        report!("      Valid:  {} → {}", cert.not_before.format("%Y-%m-%d"), cert.not_after.format("%Y-%m-%d"));
+        report!("      Key:    {} {} bits", cert.key_type, cert.key_bits);
+        if !cert.san.is_empty() {
+            report!("      SAN:    {}", cert.san.join(", "));
+        }
+    }
+
+    if !report.versions.is_empty() {
+        report!("\n{}", "Protocol versions:".bright_cyan().bold());
+        for support in &report.versions {
+            let verdict = match (support.accepted, support.version.is_deprecated()) {
+                (true, true) => "⚠ accepted (deprecated)".bright_red(),
+                (true, false) => "✓ accepted".bright_green(),
+                (false, _) => "✗ rejected".dimmed(),
+            };
+            report!("  {:8} {}", support.version.to_string(), verdict);
+        }
+        let weak = report.weak_versions();
+        if !weak.is_empty() {
+            let names: Vec<String> = weak.iter().map(ToString::to_string).collect();
+            report!("\n{} Server still accepts {}", "⚠".bright_yellow(), names.join(" and "));
+        }
+    }
+}
@@ -0,0 +1,324 @@
+// Traffic analysis
+// What a batch of decoded packets says about the network, whether they came
+// off an interface or out of a capture file: who talked the most, which
+// conversations carried the traffic, how big the packets were, and the TCP
+// trouble signs (segments sent again, receivers out of buffer, connections
+// reset). Talker and conversation tables are capped so an overnight capture
+// can't exhaust memory; once a table fills, its quietest half is dropped and
+// the rankings become approximate.
+
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use super::capstats::CaptureStats;
+use super::capture::{DecodedPacket, Protocol, ProtocolCounts, TcpSegment};
+use crate::report;
+
+/// Entries a talker or conversation table holds before the quietest are dropped
+pub const MAX_TRACKED: usize = 10_000;
+
+/// Rows in each ranking of the printed report
+pub const TOP: usize = 10;
+
+/// Upper bounds (exclusive) and labels of the packet size histogram
+pub const SIZE_BUCKETS: [(usize, &str); 7] = [
+    (64, "0-63"),
+    (128, "64-127"),
+    (256, "128-255"),
+    (512, "256-511"),
+    (1024, "512-1023"),
+    (1518, "1024-1517"),
+    (usize::MAX, "1518+"),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Volume {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl Volume {
+    fn add(&mut self, bytes: usize) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// An address and, for TCP and UDP, a port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub ip: IpAddr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+/// Traffic between two endpoints, both directions together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conversation {
+    pub protocol: Protocol,
+    /// The lower of the two endpoints
+    pub a: Endpoint,
+    pub b: Endpoint,
+    #[serde(flatten)]
+    pub volume: Volume,
+}
+
+/// One direction of a TCP connection
+type Flow = (IpAddr, u16, IpAddr, u16);
+
+#[derive(Debug, Clone, Default)]
+pub struct TrafficStats {
+    pub counts: ProtocolCounts,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Packets per `SIZE_BUCKETS` entry
+    pub sizes: [u64; SIZE_BUCKETS.len()],
+    /// Segments repeating sequence space already sent in their direction
+    pub retransmissions: u64,
+    /// Segments advertising a zero receive window
+    pub zero_windows: u64,
+    /// Segments with RST set
+    pub resets: u64,
+    /// Whether a table overflowed, making the rankings approximate
+    pub truncated: bool,
+    /// How a live capture kept up, kept current while it runs
+    pub capture: Option<CaptureStats>,
+    talkers: HashMap<IpAddr, Volume>,
+    conversations: HashMap<(Protocol, Endpoint, Endpoint), Volume>,
+    /// The end of the sequence space sent so far, per direction
+    sent_up_to: HashMap<Flow, u32>,
+}
+
+impl TrafficStats {
+    pub fn record(&mut self, at: DateTime<Utc>, packet: &DecodedPacket) {
+        self.counts.record(packet);
+        self.first_seen = Some(self.first_seen.map_or(at, |first| first.min(at)));
+        self.last_seen = Some(self.last_seen.map_or(at, |last| last.max(at)));
+        let bucket = SIZE_BUCKETS.iter().position(|&(bound, _)| packet.length < bound).unwrap_or(SIZE_BUCKETS.len() - 1);
+        self.sizes[bucket] += 1;
+
+        let (Some(src), Some(dst)) = (packet.src, packet.dst) else {
+            return;
+        };
+        // A talker's volume is everything it sent or received
+        self.truncated |= add(&mut self.talkers, src, packet.length);
+        if dst != src {
+            self.truncated |= add(&mut self.talkers, dst, packet.length);
+        }
+        let from = Endpoint { ip: src, port: packet.src_port };
+        let to = Endpoint { ip: dst, port: packet.dst_port };
+        let (a, b) = if from <= to { (from, to) } else { (to, from) };
+        self.truncated |= add(&mut self.conversations, (packet.protocol, a, b), packet.length);
+
+        if let (Some(tcp), Some(src_port), Some(dst_port)) = (packet.tcp, packet.src_port, packet.dst_port) {
+            self.record_tcp((src, src_port, dst, dst_port), tcp);
+        }
+    }
+
+    fn record_tcp(&mut self, flow: Flow, tcp: TcpSegment) {
+        if tcp.flags & TcpSegment::RST != 0 {
+            self.resets += 1;
+            return;
+        }
+        if tcp.window == 0 && tcp.flags & TcpSegment::SYN == 0 {
+            self.zero_windows += 1;
+        }
+        let len = tcp.seq_len();
+        if len == 0 {
+            // Pure ACKs occupy no sequence space
+            return;
+        }
+        let end = tcp.seq.wrapping_add(len);
+        match self.sent_up_to.get_mut(&flow) {
+            // At or before what was already sent, in wrapping sequence arithmetic
+            Some(up_to) if (up_to.wrapping_sub(end) as i32) >= 0 => self.retransmissions += 1,
+            Some(up_to) => *up_to = end,
+            None => {
+                // Forgetting every flow only costs missing a retransmission
+                // or two while they're learned again
+                if self.sent_up_to.len() >= MAX_TRACKED {
+                    self.sent_up_to.clear();
+                }
+                self.sent_up_to.insert(flow, end);
+            }
+        }
+    }
+
+    /// Time from the first packet to the last
+    pub fn duration(&self) -> Duration {
+        match (self.first_seen, self.last_seen) {
+            (Some(first), Some(last)) => (last - first).to_std().unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn average_size(&self) -> Option<u64> {
+        (self.counts.packets > 0).then(|| self.counts.bytes / self.counts.packets)
+    }
+
+    /// Addresses ranked by `key` of their volume, largest first
+    pub fn top_talkers(&self, limit: usize, key: impl Fn(&Volume) -> u64) -> Vec<(IpAddr, Volume)> {
+        let mut talkers: Vec<(IpAddr, Volume)> = self.talkers.iter().map(|(ip, volume)| (*ip, *volume)).collect();
+        talkers.sort_by_key(|(ip, volume)| (std::cmp::Reverse(key(volume)), *ip));
+        talkers.truncate(limit);
+        talkers
+    }
+
+    /// Conversations by bytes, largest first
+    pub fn conversations(&self, limit: usize) -> Vec<Conversation> {
+        let mut conversations: Vec<Conversation> = self
+            .conversations
+            .iter()
+            .map(|(&(protocol, a, b), &volume)| Conversation { protocol, a, b, volume })
+            .collect();
+        conversations.sort_by_key(|c| (std::cmp::Reverse(c.volume.bytes), c.a, c.b));
+        conversations.truncate(limit);
+        conversations
+    }
+
+    /// Everything above in serializable form, rankings cut at `limit`
+    pub fn report(&self, limit: usize) -> TrafficReport {
+        let talker = |(ip, volume): (IpAddr, Volume)| Talker { ip, volume };
+        TrafficReport {
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            duration_secs: self.duration().as_secs_f64(),
+            counts: self.counts.clone(),
+            average_size: self.average_size(),
+            sizes: SIZE_BUCKETS
+                .iter()
+                .zip(self.sizes)
+                .map(|(&(_, range), packets)| SizeBucket { range: range.to_string(), packets })
+                .collect(),
+            retransmissions: self.retransmissions,
+            zero_windows: self.zero_windows,
+            resets: self.resets,
+            top_talkers_by_bytes: self.top_talkers(limit, |volume| volume.bytes).into_iter().map(talker).collect(),
+            top_talkers_by_packets: self.top_talkers(limit, |volume| volume.packets).into_iter().map(talker).collect(),
+            conversations: self.conversations(limit),
+            truncated: self.truncated,
+            capture: self.capture.clone(),
+        }
+    }
+}
+
+/// Count a packet of `bytes` against `key`, making room first when the table
+/// is full; true when entries had to be dropped
+fn add<K: Copy + Eq + Hash>(table: &mut HashMap<K, Volume>, key: K, bytes: usize) -> bool {
+    let full = table.len() >= MAX_TRACKED && !table.contains_key(&key);
+    if full {
+        let mut entries: Vec<(K, Volume)> = table.drain().collect();
+        let keep = MAX_TRACKED / 2;
+        entries.select_nth_unstable_by_key(keep, |(_, volume)| std::cmp::Reverse(volume.bytes));
+        entries.truncate(keep);
+        table.extend(entries);
+    }
+    table.entry(key).or_default().add(bytes);
+    full
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficReport {
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub duration_secs: f64,
+    pub counts: ProtocolCounts,
+    pub average_size: Option<u64>,
+    pub sizes: Vec<SizeBucket>,
+    pub retransmissions: u64,
+    pub zero_windows: u64,
+    pub resets: u64,
+    pub top_talkers_by_bytes: Vec<Talker>,
+    pub top_talkers_by_packets: Vec<Talker>,
+    pub conversations: Vec<Conversation>,
+    /// The rankings are approximate: some quiet talkers or conversations
+    /// were dropped to bound memory
+    pub truncated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeBucket {
+    /// Packet lengths in bytes, e.g. `128-255`
+    pub range: String,
+    pub packets: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Talker {
+    pub ip: IpAddr,
+    #[serde(flatten)]
+    pub volume: Volume,
+}
+
+/// `10.0.0.2:443`, `[2001:db8::1]:443` or just the address
+pub fn format_endpoint(endpoint: Endpoint) -> String {
+    match (endpoint.ip, endpoint.port) {
+        (IpAddr::V6(ip), Some(port)) => format!("[{}]:{}", ip, port),
+        (ip, Some(port)) => format!("{}:{}", ip, port),
+        (ip, None) => ip.to_string(),
+    }
+}
+
+pub fn print_report(stats: &TrafficStats) {
+    let counts = &stats.counts;
+    let protocols = [
+        ("TCP", counts.tcp, counts.tcp_bytes),
+        ("UDP", counts.udp, counts.udp_bytes),
+        ("ICMP", counts.icmp, counts.icmp_bytes),
+        ("Other", counts.other, counts.other_bytes),
+    ];
+    report!("\n{}", "Packet Analysis".bright_cyan().bold());
+    report!("  Duration: {:.3}s", stats.duration().as_secs_f64());
+    if let Some(average) = stats.average_size() {
+        report!("  Average size: {} bytes", average);
+    }
+    for (name, packets, bytes) in protocols.into_iter().filter(|&(_, packets, _)| packets > 0) {
+        let share = packets as f64 * 100.0 / counts.packets.max(1) as f64;
+        report!("  {:<6} {:>8} packets {:>12} bytes  {:>5.1}%", name, packets, bytes, share);
+    }
+    let anomaly = |count: u64| if count > 0 { count.to_string().bright_yellow() } else { count.to_string().normal() };
+    report!(
+        "  TCP retransmissions: {} | Zero windows: {} | Resets: {}",
+        anomaly(stats.retransmissions),
+        anomaly(stats.zero_windows),
+        anomaly(stats.resets)
+    );
+
+    report!("\n{}", "Packet Sizes".bright_cyan().bold());
+    let largest = stats.sizes.iter().copied().max().unwrap_or(0).max(1);
+    for (&(_, range), packets) in SIZE_BUCKETS.iter().zip(stats.sizes) {
+        let bar = "█".repeat((packets * 30).div_ceil(largest) as usize);
+        report!("  {:>10} {:>8} {}", range, packets, bar.bright_blue());
+    }
+
+    if stats.truncated {
+        report!("\n{}", format!("  Rankings are approximate: only the busiest {} entries are tracked", MAX_TRACKED).bright_yellow());
+    }
+    report!("\n{}", "Top Talkers (bytes)".bright_cyan().bold());
+    for (ip, volume) in stats.top_talkers(TOP, |volume| volume.bytes) {
+        report!("  {:<40} {:>12} bytes {:>8} packets", ip.to_string().bright_green(), volume.bytes, volume.packets);
+    }
+    report!("\n{}", "Top Talkers (packets)".bright_cyan().bold());
+    for (ip, volume) in stats.top_talkers(TOP, |volume| volume.packets) {
+        report!("  {:<40} {:>8} packets {:>12} bytes", ip.to_string().bright_green(), volume.packets, volume.bytes);
+    }
+
+    report!("\n{}", "Conversations".bright_cyan().bold());
+    for conversation in stats.conversations(TOP) {
+        report!(
+            "  {:<6} {} ↔ {}  {} packets, {} bytes",
+            conversation.protocol.to_string(),
+            format_endpoint(conversation.a).bright_green(),
+            format_endpoint(conversation.b).bright_green(),
+            conversation.volume.packets,
+            conversation.volume.bytes
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// UDP traceroute probes
+// Like classic Unix traceroute, a TTL-limited datagram to an unused high
+// port. Routers on the way answer with Time Exceeded and the target itself
+// with Port Unreachable, which gets through networks that drop ICMP echo.
+// Unlike it, the ports stay the same for a whole path (see flow) and each
+// probe is identified by its checksum. The datagrams are crafted by the C
+// core and sent on its raw ICMP socket, which reads the answers too.
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use super::flow;
+use crate::ffi;
+use crate::utils::icmp::{self, IcmpReply, ReplyKind};
+use crate::utils::ip;
+
+/// Destination port of path 1; path N probes port BASE_PORT + N - 1
+pub const BASE_PORT: u16 = 33434;
+
+/// Port the probes of this path are sent to
+pub fn port_for(path: u8) -> u16 {
+    BASE_PORT + u16::from(path.max(1)) - 1
+}
+
+/// Send one datagram along `path` with the given TTL and return whoever answered
+pub async fn probe(target: Ipv4Addr, ttl: u8, path: u8, timeout: Duration) -> Option<IcmpReply> {
+    tokio::task::spawn_blocking(move || probe_blocking(target, ttl, path, timeout))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Payload word that gives the datagram `id` as its UDP checksum
+pub fn checksum_payload(src: Ipv4Addr, dst: Ipv4Addr, src_port: u16, dst_port: u16, id: u16) -> [u8; 2] {
+    const LENGTH: u16 = 10;
+    let mut covered = Vec::with_capacity(20);
+    // Pseudo-header, then the UDP header with its checksum zeroed
+    covered.extend_from_slice(&src.octets());
+    covered.extend_from_slice(&dst.octets());
+    covered.extend_from_slice(&[0, 17]);
+    covered.extend_from_slice(&LENGTH.to_be_bytes());
+    covered.extend_from_slice(&src_port.to_be_bytes());
+    covered.extend_from_slice(&dst_port.to_be_bytes());
+    covered.extend_from_slice(&LENGTH.to_be_bytes());
+    covered.extend_from_slice(&[0, 0]);
+    ip::checksum_padding(ip::ones_sum(&covered), id).to_be_bytes()
+}
+
+fn probe_blocking(target: Ipv4Addr, ttl: u8, path: u8, timeout: Duration) -> Option<IcmpReply> {
+    let src = super::tcp::source_for(target, BASE_PORT)?;
+    let mut sock: ffi::nw_socket_t = unsafe { std::mem::zeroed() };
+    let status = unsafe {
+        ffi::nw_socket_create(&mut sock, libc::AF_INET, libc::SOCK_RAW, ffi::nw_protocol_t_NW_PROTO_ICMP)
+    };
+    if status != ffi::nw_error_t_NW_SUCCESS {
+        return None;
+    }
+
+    let (src_port, dst_port, id) = (flow::udp_source_port(), port_for(path), flow::next_probe_id());
+    let payload = checksum_payload(src, target, src_port, dst_port, id);
+    let mut packet: Box<ffi::nw_packet_t> = Box::new(unsafe { std::mem::zeroed() });
+    let crafted = unsafe {
+        ffi::nw_packet_craft_udp(
+            &mut *packet, u32::from(src), u32::from(target), src_port, dst_port, payload.as_ptr(), payload.len(),
+        )
+    };
+    let sent = (crafted == ffi::nw_error_t_NW_SUCCESS).then(|| {
+        // IP_HDRINCL: the kernel refills the header checksum on send
+        packet.data[8] = ttl;
+        packet.data[26..28].copy_from_slice(&id.to_be_bytes());
+        let sent_at = Instant::now();
+        (unsafe { ffi::nw_packet_send_raw(&mut sock, &*packet) } == ffi::nw_error_t_NW_SUCCESS).then_some(sent_at)
+    });
+
+    let reply = sent.flatten().and_then(|sent_at| {
+        let deadline = sent_at + timeout;
+        loop {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            let wait_ms = (remaining.as_millis() as u32).max(1);
+            if unsafe { ffi::nw_packet_recv_raw(&mut sock, &mut *packet, wait_ms) } != ffi::nw_error_t_NW_SUCCESS {
+                return None;
+            }
+            let len = packet.length.min(packet.data.len());
+            if let Some(reply) = match_probe(&packet.data[..len], target, src_port, dst_port, id) {
+                return Some(IcmpReply { rtt: sent_at.elapsed(), ..reply });
+            }
+        }
+    });
+
+    unsafe { ffi::nw_socket_close(&mut sock) };
+    reply
+}
+
+/// `match_reply` for the one probe whose checksum was `id`; the others of
+/// the path share its ports
+pub fn match_probe(packet: &[u8], target: Ipv4Addr, src_port: u16, dst_port: u16, id: u16) -> Option<IcmpReply> {
+    let quoted = icmp::parse_error(packet)?.transport;
+    if u16::from_be_bytes([quoted[6], quoted[7]]) != id {
+        return None;
+    }
+    match_reply(packet, target, src_port, dst_port)
+}
+
+/// Match a raw IPv4 packet against the datagram we sent from `src_port` to
+/// `target:dst_port`; the RTT is left for the caller to fill in
+///
+/// Port Unreachable from the target itself is the arrival and comes back as
+/// an `EchoReply`, so callers can treat both probe kinds alike.
+pub fn match_reply(packet: &[u8], target: Ipv4Addr, src_port: u16, dst_port: u16) -> Option<IcmpReply> {
+    let error = icmp::parse_error(packet)?;
+    let ports = |offset: usize| u16::from_be_bytes([error.transport[offset], error.transport[offset + 1]]);
+    if error.protocol != 17 || error.destination != target || ports(0) != src_port || ports(2) != dst_port {
+        return None;
+    }
+
+    let arrived = error.kind == ReplyKind::Unreachable && error.code == 3 && error.from == target;
+    Some(IcmpReply {
+        from: error.from,
+        rtt: Duration::ZERO,
+        ttl: Some(error.ttl),
+        kind: if arrived { ReplyKind::EchoReply } else { error.kind },
+    })
+}
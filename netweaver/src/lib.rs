@@ -4,6 +4,7 @@ pub mod diagnostics;
 pub mod error;
 pub mod monitor;
 pub mod optimizer;
+pub mod output;
 pub mod plugins;
 pub mod scanner;
 pub mod security;
@@ -18,19 +19,25 @@ pub mod ffi {
 }
 
 use anyhow::Result;
+use clap::Parser;
 
-pub fn init_logging() {
+pub fn init_logging(verbosity: output::Verbosity) {
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(verbosity.log_filter()))
         )
         .with_target(false)
         .with_thread_ids(true)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
         .init();
 }
 
 pub async fn run() -> Result<()> {
-    init_logging();
-    cli::run().await
+    let cli = cli::Cli::parse();
+    let verbosity = output::Verbosity::from_flags(cli.verbose, cli.quiet);
+    output::set_verbosity(verbosity);
+    init_logging(verbosity);
+    cli::run(cli).await
 }
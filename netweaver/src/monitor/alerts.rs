@@ -0,0 +1,526 @@
+// Monitor alerts (`monitor --alert`)
+// Rules are short conditions on each monitored interface, checked at every
+// sample of the realtime view and the daemon:
+//
+//     rx > 50MB/s for 30s     receive rate above 50 MB/s for half a minute
+//     tx > 800KB/s            transmit rate, on the first sample above it
+//     pps > 20000             packets per second, both directions
+//     errors                  the error counter went up (`errors > 10`: by more than 10)
+//     drops > 100 for 1m      more than 100 drops per sample for a minute
+//     down                    the interface is down or has gone away
+//     gateway > 50ms for 30s  latency to the `gateway` probe target
+//     latency > 200ms         latency to any probe target
+//     signal < -70dBm for 30s Wi-Fi signal of a wireless interface below -70 dBm
+//
+// Latency rules name a `--probe` target (or `latency` for all of them) and
+// are checked against its last probe, where a lost one counts as the probe
+// timeout. Signal is the one metric that fires below its level, and is only
+// judged on interfaces with a Wi-Fi link.
+// A rule fires once its condition has held for its `for` time and resolves
+// on the first sample it no longer holds. Both are logged and sent to the
+// webhook and the alert command, as are interface events (a link going down,
+// an address changing), which need no rule. After a rule has notified, it stays quiet
+// for the cooldown even if it resolves and fires again, so a flapping
+// condition costs at most one firing and one resolved notice per cooldown.
+// Rules can also be listed under `[monitor]` in the config file:
+//
+//     [monitor]
+//     alerts = ["rx > 50MB/s for 30s", "down"]
+//     webhook = "https://hooks.example.com/netweaver"
+//     alert-command = "logger -t netweaver \"$NETWEAVER_ALERT_MESSAGE\""
+//     alert-cooldown = "10m"
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::events::InterfaceEvent;
+use super::TrafficDelta;
+use crate::status;
+use crate::utils;
+
+/// How long a rule that notified stays quiet unless told otherwise
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(300);
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Receive rate, bytes per second
+    Rx,
+    /// Transmit rate, bytes per second
+    Tx,
+    /// Packets per second, both directions
+    Pps,
+    /// New errors since the previous sample
+    Errors,
+    /// New drops since the previous sample
+    Drops,
+    Down,
+    /// RTT of a probe target's last probe, ms
+    Latency,
+    /// Wi-Fi signal, dBm
+    Signal,
+}
+
+impl Metric {
+    /// The metric's value for `input`; None when there is nothing to judge
+    /// yet, as for rates on the first sample
+    fn value(self, input: &AlertInput) -> Option<f64> {
+        // Probe targets only have a latency, interfaces everything else
+        match (self, input.latency_ms) {
+            (Metric::Latency, latency) => return latency,
+            (_, Some(_)) => return None,
+            (_, None) => {}
+        }
+        match self {
+            Metric::Down => return Some(if input.down { 1.0 } else { 0.0 }),
+            Metric::Signal => return input.signal_dbm,
+            _ => {}
+        }
+        let delta = input.delta.as_ref()?;
+        Some(match self {
+            Metric::Rx => delta.rx_rate(),
+            Metric::Tx => delta.tx_rate(),
+            Metric::Pps => delta.packet_rate(),
+            Metric::Errors => delta.errors as f64,
+            Metric::Drops => delta.drops? as f64,
+            Metric::Down | Metric::Latency | Metric::Signal => unreachable!(),
+        })
+    }
+
+    fn format(self, value: f64) -> String {
+        match self {
+            Metric::Rx | Metric::Tx => utils::format_bandwidth(value),
+            Metric::Pps => format!("{:.0} pkt/s", value),
+            Metric::Errors | Metric::Drops => format!("{:.0}", value),
+            Metric::Down => String::new(),
+            Metric::Latency => format!("{:.1} ms", value),
+            Metric::Signal => format!("{:.0} dBm", value),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Metric::Rx => "rx",
+            Metric::Tx => "tx",
+            Metric::Pps => "pps",
+            Metric::Errors => "errors",
+            Metric::Drops => "drops",
+            Metric::Down => "down",
+            Metric::Latency => "latency",
+            Metric::Signal => "signal",
+        })
+    }
+}
+
+/// One `--alert` condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub metric: Metric,
+    /// Fires above this value, or below it for signal
+    pub threshold: f64,
+    /// How long the condition must hold first
+    pub hold: Duration,
+    /// The probe target a latency rule is about; None for every target
+    pub target: Option<String>,
+    /// The rule as written
+    pub text: String,
+}
+
+/// `50MB/s`, `800 KB/s`, `1.5G` or plain bytes per second, in the binary
+/// units the monitor prints rates in
+fn parse_rate(value: &str) -> Option<f64> {
+    let value = value.trim().trim_end_matches("/s").trim_end_matches('b').trim();
+    let (number, scale) = match value.chars().last()? {
+        'k' => (&value[..value.len() - 1], 1024.0),
+        'm' => (&value[..value.len() - 1], 1024.0 * 1024.0),
+        'g' => (&value[..value.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (value, 1.0),
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    (number >= 0.0).then_some(number * scale)
+}
+
+/// `-70dBm`, `-70 dbm` or `-70`
+fn parse_signal(value: &str) -> Option<f64> {
+    value.trim().trim_end_matches("dbm").trim().parse().ok()
+}
+
+/// `50ms`, `0.5s` or `50 ms` in milliseconds; the unit is required, so a
+/// bare number isn't mistaken for one
+fn parse_latency(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(number) => (number, 1.0),
+        None => (value.strip_suffix('s')?, 1000.0),
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    (number >= 0.0).then_some(number * scale)
+}
+
+impl FromStr for AlertRule {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let lower = text.to_ascii_lowercase();
+        let (condition, hold) = match lower.split_once(" for ") {
+            Some((condition, hold)) => (condition, utils::parse_duration(hold)
+                .with_context(|| format!("Invalid hold time in alert '{}'", text))?),
+            None => (lower.as_str(), Duration::ZERO),
+        };
+        // Only signal fires below its level
+        let (metric, value, below) = match (condition.split_once('>'), condition.split_once('<')) {
+            (Some((metric, value)), _) => (metric.trim(), Some(value.trim()), false),
+            (None, Some((metric, value))) => (metric.trim(), Some(value.trim()), true),
+            (None, None) => (condition.trim(), None, false),
+        };
+        let (metric, target) = match metric {
+            "rx" => (Metric::Rx, None),
+            "tx" => (Metric::Tx, None),
+            "pps" => (Metric::Pps, None),
+            "errors" => (Metric::Errors, None),
+            "drops" => (Metric::Drops, None),
+            "down" => (Metric::Down, None),
+            "latency" => (Metric::Latency, None),
+            "signal" => (Metric::Signal, None),
+            // Anything else with a latency is a probe target
+            _ if value.is_some_and(|value| parse_latency(value).is_some()) => (Metric::Latency, Some(metric.to_string())),
+            _ => anyhow::bail!("Unknown alert metric '{}' in '{}' (expected rx, tx, pps, errors, drops, down, \
+                                signal, or a probe target with a latency like 'gateway > 50ms')", metric, text),
+        };
+        if below && metric != Metric::Signal {
+            anyhow::bail!("Alert '{}': only signal takes '<', e.g. 'signal < -70dBm'", text);
+        }
+        if metric == Metric::Signal && value.is_some() && !below {
+            anyhow::bail!("Alert '{}': signal fires below a level, e.g. 'signal < -70dBm'", text);
+        }
+        let threshold = match (metric, value) {
+            (Metric::Down, Some(_)) => anyhow::bail!("Alert '{}': 'down' takes no threshold", text),
+            (Metric::Signal, None) => anyhow::bail!("Alert '{}' needs a level, e.g. 'signal < -70dBm'", text),
+            (Metric::Down | Metric::Errors | Metric::Drops, None) => 0.0,
+            (Metric::Rx | Metric::Tx | Metric::Pps | Metric::Latency, None) => {
+                anyhow::bail!("Alert '{}' needs a threshold, e.g. '{} > 50MB/s'", text, metric)
+            }
+            (Metric::Rx | Metric::Tx, Some(value)) => parse_rate(value)
+                .with_context(|| format!("Invalid rate '{}' in alert '{}' (e.g. 50MB/s, 800KB/s)", value, text))?,
+            (Metric::Latency, Some(value)) => parse_latency(value)
+                .with_context(|| format!("Invalid latency '{}' in alert '{}' (e.g. 50ms, 1s)", value, text))?,
+            (Metric::Signal, Some(value)) => parse_signal(value)
+                .with_context(|| format!("Invalid signal '{}' in alert '{}' (e.g. -70dBm)", value, text))?,
+            (_, Some(value)) => value.parse::<f64>().ok().filter(|value| *value >= 0.0)
+                .with_context(|| format!("Invalid threshold '{}' in alert '{}'", value, text))?,
+        };
+        Ok(Self { metric, threshold, hold, target, text: text.to_string() })
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Fail on a latency rule about a target that isn't probed, which would
+/// otherwise never fire
+pub fn check_probe_targets(rules: &[AlertRule], targets: &[String]) -> Result<()> {
+    for rule in rules.iter().filter(|rule| rule.metric == Metric::Latency) {
+        let known = match &rule.target {
+            Some(target) => targets.iter().any(|name| name.eq_ignore_ascii_case(target)),
+            None => !targets.is_empty(),
+        };
+        if !known {
+            let probed = if targets.is_empty() { "nothing".to_string() } else { targets.join(", ") };
+            anyhow::bail!("Alert '{}' needs a probe target to watch, but the monitor probes {} (add it with --probe)", rule, probed);
+        }
+    }
+    Ok(())
+}
+
+/// What the rules see of one interface, or one probe target, at one sample
+#[derive(Debug, Clone, Default)]
+pub struct AlertInput {
+    /// Interface name, or the probe target's
+    pub interface: String,
+    /// Since the previous sample; None on the first
+    pub delta: Option<TrafficDelta>,
+    pub down: bool,
+    /// Last RTT in ms of a probe target; None for an interface
+    pub latency_ms: Option<f64>,
+    /// Wi-Fi signal in dBm; None for a probe target or a wired interface
+    pub signal_dbm: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// A rule firing or resolving on an interface, as logged and sent out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule: String,
+    pub interface: String,
+    pub state: AlertState,
+    pub metric: Metric,
+    pub value: f64,
+    pub threshold: f64,
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Where one rule stands on one interface
+#[derive(Debug, Default)]
+struct Tracker {
+    /// When the condition started holding
+    since: Option<Instant>,
+    firing: bool,
+    /// Whether this firing was sent out, so its end is too
+    notified: bool,
+    last_notified: Option<Instant>,
+}
+
+/// Judges samples against the rules and decides what to send
+#[derive(Debug)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    cooldown: Duration,
+    trackers: HashMap<(usize, String), Tracker>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>, cooldown: Duration) -> Self {
+        Self { rules, cooldown, trackers: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The alerts to send for a sample taken at `now`
+    pub fn evaluate(&mut self, now: Instant, inputs: &[AlertInput]) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            for input in inputs {
+                if rule.target.as_ref().is_some_and(|target| !target.eq_ignore_ascii_case(&input.interface)) {
+                    continue;
+                }
+                let Some(value) = rule.metric.value(input) else {
+                    continue;
+                };
+                let tracker = self.trackers.entry((index, input.interface.clone())).or_default();
+                let breached = match rule.metric {
+                    Metric::Signal => value < rule.threshold,
+                    _ => value > rule.threshold,
+                };
+                let state = if breached {
+                    let since = *tracker.since.get_or_insert(now);
+                    if tracker.firing || now.duration_since(since) < rule.hold {
+                        continue;
+                    }
+                    tracker.firing = true;
+                    tracker.notified = tracker.last_notified.is_none_or(|last| now.duration_since(last) >= self.cooldown);
+                    if !tracker.notified {
+                        continue;
+                    }
+                    tracker.last_notified = Some(now);
+                    AlertState::Firing
+                } else {
+                    tracker.since = None;
+                    let notified = std::mem::take(&mut tracker.notified);
+                    if !std::mem::take(&mut tracker.firing) || !notified {
+                        continue;
+                    }
+                    AlertState::Resolved
+                };
+                alerts.push(Alert {
+                    rule: rule.text.clone(),
+                    interface: input.interface.clone(),
+                    state,
+                    metric: rule.metric,
+                    value,
+                    threshold: rule.threshold,
+                    at: Utc::now(),
+                    message: describe(rule, &input.interface, state, value),
+                });
+            }
+        }
+        alerts
+    }
+
+    /// Rules firing right now, as (rule, interface)
+    pub fn firing(&self) -> Vec<(String, String)> {
+        let mut firing: Vec<(String, String)> = self
+            .trackers
+            .iter()
+            .filter(|(_, tracker)| tracker.firing)
+            .map(|((index, interface), _)| (self.rules[*index].text.clone(), interface.clone()))
+            .collect();
+        firing.sort();
+        firing
+    }
+}
+
+fn describe(rule: &AlertRule, interface: &str, state: AlertState, value: f64) -> String {
+    let metric = rule.metric;
+    match (metric, state) {
+        (Metric::Down, AlertState::Firing) => format!("{} is down", interface),
+        (Metric::Down, AlertState::Resolved) => format!("{} is up again", interface),
+        (Metric::Errors | Metric::Drops, AlertState::Firing) => {
+            format!("{}: {} new {} (rule '{}')", interface, metric.format(value), metric, rule)
+        }
+        (_, AlertState::Firing) => format!("{}: {} at {} (rule '{}')", interface, metric, metric.format(value), rule),
+        (_, AlertState::Resolved) => format!("{}: {} back to {} (rule '{}')", interface, metric, metric.format(value), rule),
+    }
+}
+
+/// The rules and where their alerts go
+#[derive(Debug, Clone, Default)]
+pub struct AlertSettings {
+    pub rules: Vec<AlertRule>,
+    pub webhook: Option<String>,
+    pub command: Option<String>,
+    pub cooldown: Duration,
+}
+
+/// The `[monitor]` table of the config file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MonitorConfig {
+    pub alerts: Vec<String>,
+    pub webhook: Option<String>,
+    pub alert_command: Option<String>,
+    pub alert_cooldown: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    monitor: MonitorConfig,
+}
+
+/// The `[monitor]` table of the config file at `path`, which may not exist
+pub fn load_config(path: &Path) -> Result<MonitorConfig> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(toml::from_str::<ConfigFile>(&content)
+            .with_context(|| format!("Invalid config file '{}'", path.display()))?
+            .monitor),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(MonitorConfig::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read config file '{}'", path.display())),
+    }
+}
+
+/// The config's rules followed by `alerts`; `webhook`, `command` and
+/// `cooldown` override the config's
+pub fn settings(
+    config: MonitorConfig,
+    alerts: Vec<String>,
+    webhook: Option<String>,
+    command: Option<String>,
+    cooldown: Option<String>,
+) -> Result<AlertSettings> {
+    let rules = config.alerts.iter().chain(&alerts).map(|rule| rule.parse()).collect::<Result<Vec<AlertRule>>>()?;
+    let webhook = webhook.or(config.webhook);
+    if let Some(url) = &webhook {
+        reqwest::Url::parse(url).with_context(|| format!("Invalid webhook URL '{}'", url))?;
+    }
+    let cooldown = match cooldown.or(config.alert_cooldown) {
+        Some(cooldown) => utils::parse_duration(&cooldown)?,
+        None => DEFAULT_COOLDOWN,
+    };
+    Ok(AlertSettings { rules, webhook, command: command.or(config.alert_command), cooldown })
+}
+
+/// Send `alert` to the webhook and the alert command, in the background so
+/// a slow receiver doesn't hold up sampling
+pub fn dispatch(settings: &AlertSettings, alert: &Alert) {
+    if let Some(url) = settings.webhook.clone() {
+        post_webhook(url, serde_json::to_value(alert).unwrap_or_default());
+    }
+    if let Some(command) = settings.command.clone() {
+        let state = match alert.state {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        };
+        run_command(command, vec![
+            ("NETWEAVER_ALERT_RULE", alert.rule.clone()),
+            ("NETWEAVER_ALERT_INTERFACE", alert.interface.clone()),
+            ("NETWEAVER_ALERT_STATE", state.to_string()),
+            ("NETWEAVER_ALERT_METRIC", alert.metric.to_string()),
+            ("NETWEAVER_ALERT_VALUE", alert.value.to_string()),
+            ("NETWEAVER_ALERT_THRESHOLD", alert.threshold.to_string()),
+            ("NETWEAVER_ALERT_MESSAGE", alert.message.clone()),
+            ("NETWEAVER_ALERT_TIME", alert.at.to_rfc3339()),
+        ]);
+    }
+}
+
+/// Send an interface event the same way: the webhook gets `{"event": …}`,
+/// and the command runs with NETWEAVER_ALERT_STATE set to `event` and
+/// NETWEAVER_ALERT_EVENT to the kind of change
+pub fn dispatch_event(settings: &AlertSettings, event: &InterfaceEvent) {
+    if let Some(url) = settings.webhook.clone() {
+        post_webhook(url, serde_json::json!({ "event": event }));
+    }
+    if let Some(command) = settings.command.clone() {
+        let kind = serde_json::to_value(&event.change).ok()
+            .and_then(|change| change["change"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        run_command(command, vec![
+            ("NETWEAVER_ALERT_INTERFACE", event.interface.clone()),
+            ("NETWEAVER_ALERT_STATE", "event".to_string()),
+            ("NETWEAVER_ALERT_EVENT", kind),
+            ("NETWEAVER_ALERT_MESSAGE", event.message.clone()),
+            ("NETWEAVER_ALERT_TIME", event.timestamp.to_rfc3339()),
+        ]);
+    }
+}
+
+fn post_webhook(url: String, body: serde_json::Value) {
+    tokio::spawn(async move {
+        let sent = async {
+            reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()?
+                .post(&url)
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()
+        };
+        if let Err(e) = sent.await {
+            status!("{} {}", "⚠️  Alert webhook failed:".bright_red(), e);
+        }
+    });
+}
+
+fn run_command(command: String, env: Vec<(&'static str, String)>) {
+    tokio::spawn(async move {
+        let status = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .envs(env)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await;
+        match status {
+            Ok(status) if !status.success() => status!("{} {}", "⚠️  Alert command failed:".bright_red(), status),
+            Err(e) => status!("{} {}", "⚠️  Alert command failed:".bright_red(), e),
+            Ok(_) => {}
+        }
+    });
+}
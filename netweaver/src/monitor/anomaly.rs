@@ -0,0 +1,242 @@
+// Anomaly-triggered capture (`monitor --capture-on-anomaly`)
+// By the time someone runs inspect after a latency spike or a burst of
+// drops, the packets behind it are gone. With this flag the realtime view
+// and the daemon also capture on the monitored interfaces (the
+// default-route one for `all`), keeping the last RING_WINDOW of frames in
+// memory, at most RING_MAX_BYTES of them. When an alert rule fires, or a
+// probe's RTT stands out from its recent ones (LatencyAnalyzer's anomaly
+// test), the ring and the next CAPTURE_AFTER of traffic are written to a
+// pcap file named after the flag's with the time added. Anomalies while a
+// file is being written go into that file. Capturing needs root; without
+// it the monitor warns and runs without this.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::capture_interfaces;
+use super::probes::{ProbeSample, ProbeStats};
+use crate::diagnostics::capture;
+use crate::diagnostics::pcap::{CaptureFile, Rotation, RotatingWriter};
+use crate::report;
+
+/// How far back the ring reaches
+pub const RING_WINDOW: Duration = Duration::from_secs(5);
+/// Frame bytes the ring holds at most, however busy the link
+pub const RING_MAX_BYTES: usize = 16 * 1024 * 1024;
+/// How long capture goes on after an anomaly
+pub const CAPTURE_AFTER: Duration = Duration::from_secs(10);
+/// Frame bytes one file takes at most
+const MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// One captured frame, as pcap wants it
+#[derive(Debug, Clone)]
+struct Frame {
+    at: DateTime<Utc>,
+    linktype: u32,
+    data: Vec<u8>,
+    orig_len: usize,
+}
+
+/// The file being written since an anomaly
+struct Dump {
+    writer: RotatingWriter,
+    path: PathBuf,
+    until: DateTime<Utc>,
+    bytes: u64,
+}
+
+/// Keeps the recent frames and writes them out when asked to
+pub struct AnomalyRecorder {
+    template: PathBuf,
+    ring: VecDeque<Frame>,
+    ring_bytes: usize,
+    dump: Option<Dump>,
+    saved: Vec<CaptureFile>,
+}
+
+impl AnomalyRecorder {
+    /// Files go next to `template`, named after it
+    pub fn new(template: &Path) -> Self {
+        Self { template: template.to_path_buf(), ring: VecDeque::new(), ring_bytes: 0, dump: None, saved: Vec::new() }
+    }
+
+    /// Add a frame seen `at`: to the file being written, if any, and to
+    /// the ring, pushing out what is too old or doesn't fit
+    pub fn record(&mut self, at: DateTime<Utc>, linktype: u32, data: &[u8], orig_len: usize) -> Result<()> {
+        self.tick(at)?;
+        if let Some(dump) = &mut self.dump {
+            if dump.bytes + data.len() as u64 <= MAX_FILE_BYTES {
+                dump.bytes += data.len() as u64;
+                dump.writer.write(at, linktype, data, orig_len)?;
+            }
+        }
+        let window = chrono::Duration::from_std(RING_WINDOW).unwrap_or_default();
+        while self.ring.front().is_some_and(|frame| {
+            frame.at < at - window || self.ring_bytes + data.len() > RING_MAX_BYTES
+        }) {
+            let frame = self.ring.pop_front().expect("the ring has a front");
+            self.ring_bytes -= frame.data.len();
+        }
+        if data.len() <= RING_MAX_BYTES {
+            self.ring_bytes += data.len();
+            self.ring.push_back(Frame { at, linktype, data: data.to_vec(), orig_len });
+        }
+        Ok(())
+    }
+
+    /// Start a file at `at` with the ring in it; the path, or None when a
+    /// file is already being written and this anomaly goes into it
+    pub fn trigger(&mut self, at: DateTime<Utc>) -> Result<Option<PathBuf>> {
+        self.tick(at)?;
+        if self.dump.is_some() {
+            return Ok(None);
+        }
+        let path = timestamped(&self.template, at);
+        let mut dump = Dump {
+            writer: RotatingWriter::new(&path, Rotation::default()),
+            path: path.clone(),
+            until: at + chrono::Duration::from_std(CAPTURE_AFTER).unwrap_or_default(),
+            bytes: 0,
+        };
+        for frame in &self.ring {
+            dump.bytes += frame.data.len() as u64;
+            dump.writer.write(frame.at, frame.linktype, &frame.data, frame.orig_len)?;
+        }
+        self.dump = Some(dump);
+        Ok(Some(path))
+    }
+
+    /// Close the file being written once CAPTURE_AFTER has passed since its
+    /// anomaly
+    pub fn tick(&mut self, at: DateTime<Utc>) -> Result<()> {
+        if self.dump.as_ref().is_some_and(|dump| at >= dump.until) {
+            self.close()?;
+        }
+        Ok(())
+    }
+
+    /// The file being written, if any
+    pub fn dumping(&self) -> Option<&Path> {
+        self.dump.as_ref().map(|dump| dump.path.as_path())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(dump) = self.dump.take() {
+            self.saved.extend(dump.writer.finish()?);
+        }
+        Ok(())
+    }
+
+    /// Close the file being written; every file written, oldest first
+    pub fn finish(mut self) -> Result<Vec<CaptureFile>> {
+        self.close()?;
+        Ok(self.saved)
+    }
+}
+
+/// `anomaly.pcap` at 10:23:49 UTC becomes `anomaly-20261015T102349Z.pcap`
+pub fn timestamped(template: &Path, at: DateTime<Utc>) -> PathBuf {
+    let stamp = at.format("%Y%m%dT%H%M%SZ");
+    let stem = template.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match template.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, stamp, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, stamp),
+    };
+    template.with_file_name(name)
+}
+
+/// Why `sample` counts as an anomaly, if its RTT stands out from its
+/// target's recent ones
+pub fn probe_anomaly(stats: &[ProbeStats], sample: &ProbeSample) -> Option<String> {
+    let stats = stats.iter().find(|stats| stats.target.name == sample.target)?;
+    let rtt = sample.rtt_ms.filter(|_| stats.is_anomalous())?;
+    Some(format!("{} latency {:.1} ms against an average of {:.1} ms", sample.target, rtt, stats.average()))
+}
+
+/// Capture threads feeding a shared `AnomalyRecorder`, one per interface
+pub struct AnomalyCapture {
+    recorder: Arc<Mutex<AnomalyRecorder>>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<std::thread::JoinHandle<Result<()>>>,
+    interfaces: Vec<String>,
+}
+
+impl AnomalyCapture {
+    /// Start capturing on the interfaces `spec` names, or on the
+    /// default-route one for `all`, for files named after `template`;
+    /// needs root
+    pub fn start(spec: &str, template: &Path) -> Result<Self> {
+        let interfaces = capture_interfaces(spec)?;
+        let recorder = Arc::new(Mutex::new(AnomalyRecorder::new(template)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let shared = Arc::clone(&recorder);
+        // A failed write ends that file, not the capture
+        let threads = capture::spawn_readers(&interfaces, &stop, move |frame| {
+            let mut recorder = shared.lock().unwrap_or_else(|e| e.into_inner());
+            let result = match frame {
+                Some(frame) => recorder.record(Utc::now(), frame.linktype, frame.data, frame.data.len()),
+                // Close a finished file on a quiet link too
+                None => recorder.tick(Utc::now()),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to save the anomaly capture: {:#}", e);
+                recorder.dump = None;
+            }
+        })?;
+        Ok(Self { recorder, stop, threads, interfaces: interfaces.into_iter().map(|iface| iface.name).collect() })
+    }
+
+    /// Interfaces being captured on, for display
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// Save the ring and what follows; the new file's path, or None when
+    /// one is already being written or it couldn't be created (logged)
+    pub fn trigger(&self) -> Option<PathBuf> {
+        let mut recorder = self.recorder.lock().unwrap_or_else(|e| e.into_inner());
+        recorder
+            .trigger(Utc::now())
+            .map_err(|e| tracing::warn!("Failed to save the anomaly capture: {:#}", e))
+            .ok()
+            .flatten()
+    }
+
+    /// The file being written, if any
+    pub fn dumping(&self) -> Option<PathBuf> {
+        let mut recorder = self.recorder.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = recorder.tick(Utc::now()) {
+            tracing::warn!("Failed to save the anomaly capture: {:#}", e);
+        }
+        recorder.dumping().map(Path::to_path_buf)
+    }
+
+    /// Stop capturing and close the file being written; every file written
+    pub fn finish(self) -> Result<Vec<CaptureFile>> {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads {
+            // One interface failing leaves what the others caught
+            if let Err(e) = thread.join().map_err(|_| anyhow::anyhow!("A capture thread panicked"))? {
+                tracing::warn!("Anomaly capture failed: {:#}", e);
+            }
+        }
+        let recorder = Arc::try_unwrap(self.recorder)
+            .map_err(|_| anyhow::anyhow!("The anomaly capture is still in use"))?
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        recorder.finish()
+    }
+}
+
+/// List the files an anomaly capture wrote
+pub fn print_saved(files: &[CaptureFile]) {
+    for file in files {
+        report!("💾 Anomaly capture saved: {} ({} packets)", file.path.display().to_string().bright_green(), file.packets);
+    }
+}
@@ -0,0 +1,321 @@
+// SVG charts for the HTML report (`report --graphs`)
+// Hand-written SVG from the `all` series of the monitoring history, inlined
+// so the report stays a single file: bandwidth, packets per second, and
+// errors and drops per bucket over the report window. The history's hour or
+// day buckets keep even a month to a few dozen points. Each point sits in
+// the middle of its bucket, and a bucket the monitor didn't cover breaks
+// the line instead of drawing a slope across the gap.
+
+use chrono::{DateTime, TimeZone, Utc};
+use std::path::Path;
+
+use super::history::{HistoryReport, Resolution};
+use super::ALL_INTERFACES;
+use crate::utils;
+
+const WIDTH: f64 = 760.0;
+const HEIGHT: f64 = 260.0;
+/// Room for the title and legend above the plot, the value labels left of
+/// it and the time labels below
+const TOP: f64 = 34.0;
+const LEFT: f64 = 90.0;
+const RIGHT: f64 = 20.0;
+const BOTTOM: f64 = 48.0;
+/// Gridlines above zero; 5 keeps round maxima like 25 or 50 on whole steps
+const Y_STEPS: u32 = 5;
+/// Most time labels along the bottom
+const MAX_X_TICKS: i64 = 8;
+
+const TX_COLOR: &str = "#00bcd4";
+const RX_COLOR: &str = "#ff9800";
+
+/// One line of a chart: a value per history bucket, None where there's none
+struct Line {
+    label: &'static str,
+    color: &'static str,
+    values: Vec<Option<f64>>,
+}
+
+struct Chart {
+    title: String,
+    /// What the y axis counts
+    unit: &'static str,
+    lines: Vec<Line>,
+    format: fn(f64) -> String,
+    /// Round the axis in powers of 1024, like `format_bandwidth`
+    binary: bool,
+    /// Smallest axis maximum; `Y_STEPS` keeps whole counts on whole gridlines
+    min_top: f64,
+}
+
+/// Where a text's x coordinate is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+/// One element of a chart, in its own coordinates: origin at the top left,
+/// y growing down, colors as `#rrggbb`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Line { from: (f64, f64), to: (f64, f64), color: &'static str },
+    /// Unfilled runs of connected points, each run starting afresh
+    Path { runs: Vec<Vec<(f64, f64)>>, color: &'static str, width: f64 },
+    Circle { center: (f64, f64), radius: f64, color: &'static str },
+    Rect { at: (f64, f64), size: (f64, f64), color: &'static str },
+    /// `vertical` runs bottom to top
+    Text { at: (f64, f64), text: String, size: f64, bold: bool, anchor: Anchor, vertical: bool },
+}
+
+/// A chart as shapes, for the SVG of the HTML report and the vector
+/// graphics of the PDF one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drawing {
+    pub title: String,
+    pub width: f64,
+    pub height: f64,
+    pub shapes: Vec<Shape>,
+}
+
+/// The color of chart text
+pub const TEXT_COLOR: &str = "#555555";
+
+/// The report's Graphs section: charts of `history`, or why there are none
+pub fn graphs_html(history: Option<&HistoryReport>, store: Option<&Path>) -> String {
+    let mut html = vec!["        <h2>Graphs</h2>\n".to_string()];
+    match history.and_then(history_charts) {
+        Some(charts) => html.push(charts),
+        None => html.push(format!("        <div class=\"stat\">{}</div>\n", missing_graphs(history, store))),
+    }
+    html.concat()
+}
+
+/// What the report says instead of charts when `history` has nothing to draw
+pub fn missing_graphs(history: Option<&HistoryReport>, store: Option<&Path>) -> String {
+    let reason = match (history, store) {
+        (Some(history), Some(store)) if store.exists() => format!(
+            "the monitoring history in {} has no traffic between {} and {}",
+            store.display(),
+            history.since.format("%Y-%m-%d %H:%M UTC"),
+            history.until.format("%Y-%m-%d %H:%M UTC")
+        ),
+        (_, Some(store)) => format!("no monitoring history store was found at {}", store.display()),
+        (_, None) => "no monitoring history store was found".to_string(),
+    };
+    format!("Graphs were requested, but {}. 'netweaver monitor --daemon' or 'monitor --realtime' records it.", reason)
+}
+
+/// Bandwidth, packet rate and error charts of the `all` series as inline
+/// SVG, or None without a bucket to draw
+pub fn history_charts(history: &HistoryReport) -> Option<String> {
+    Some(history_drawings(history)?.iter().map(svg).collect())
+}
+
+/// The charts of `history_charts`, as shapes
+pub fn history_drawings(history: &HistoryReport) -> Option<Vec<Drawing>> {
+    let buckets = &history.series.iter().find(|series| series.interface == ALL_INTERFACES)?.buckets;
+    if buckets.is_empty() {
+        return None;
+    }
+    let starts: Vec<DateTime<Utc>> = buckets.iter().map(|bucket| bucket.start).collect();
+    let rate = |count: u64, secs: f64| Some(if secs > 0.0 { count as f64 / secs } else { 0.0 });
+    let mut errors = vec![Line {
+        label: "Errors",
+        color: "#e53935",
+        values: buckets.iter().map(|bucket| Some(bucket.errors as f64)).collect(),
+    }];
+    if buckets.iter().any(|bucket| bucket.drops.is_some()) {
+        errors.push(Line {
+            label: "Drops",
+            color: "#8e24aa",
+            values: buckets.iter().map(|bucket| bucket.drops.map(|drops| drops as f64)).collect(),
+        });
+    }
+    let charts = [
+        Chart {
+            title: "Bandwidth".to_string(),
+            unit: "bytes/s",
+            lines: vec![
+                Line { label: "TX", color: TX_COLOR, values: buckets.iter().map(|bucket| Some(bucket.avg_tx_bytes_per_sec)).collect() },
+                Line { label: "RX", color: RX_COLOR, values: buckets.iter().map(|bucket| Some(bucket.avg_rx_bytes_per_sec)).collect() },
+            ],
+            format: utils::format_bandwidth,
+            binary: true,
+            min_top: 0.0,
+        },
+        Chart {
+            title: "Packets per second".to_string(),
+            unit: "packets/s",
+            lines: vec![
+                Line { label: "TX", color: TX_COLOR, values: buckets.iter().map(|bucket| rate(bucket.packets_sent, bucket.covered_secs)).collect() },
+                Line { label: "RX", color: RX_COLOR, values: buckets.iter().map(|bucket| rate(bucket.packets_recv, bucket.covered_secs)).collect() },
+            ],
+            format: format_number,
+            binary: false,
+            min_top: 0.0,
+        },
+        Chart {
+            title: format!("Errors per {}", history.resolution),
+            unit: "packets",
+            lines: errors,
+            format: format_number,
+            binary: false,
+            min_top: Y_STEPS as f64,
+        },
+    ];
+    Some(charts.iter().map(|chart| line_chart(chart, history, &starts)).collect())
+}
+
+fn line_chart(chart: &Chart, history: &HistoryReport, starts: &[DateTime<Utc>]) -> Drawing {
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let since = history.since.timestamp() as f64;
+    let span = (history.until.timestamp() as f64 - since).max(1.0);
+    let bucket_secs = resolution_secs(history.resolution) as f64;
+    let x = |secs: f64| LEFT + ((secs - since) / span).clamp(0.0, 1.0) * plot_width;
+    let peak = chart.lines.iter().flat_map(|line| line.values.iter().flatten()).fold(0.0, |peak: f64, value| peak.max(*value));
+    let top = nice_ceiling(peak.max(chart.min_top), chart.binary);
+    let y = |value: f64| TOP + plot_height - value / top * plot_height;
+    let text = |at: (f64, f64), text: String, anchor: Anchor| Shape::Text { at, text, size: 11.0, bold: false, anchor, vertical: false };
+
+    let mut shapes = vec![Shape::Text { at: (LEFT, 20.0), text: chart.title.clone(), size: 14.0, bold: true, anchor: Anchor::Start, vertical: false }];
+    for step in 0..=Y_STEPS {
+        let value = top * step as f64 / Y_STEPS as f64;
+        shapes.push(Shape::Line { from: (LEFT, y(value)), to: (LEFT + plot_width, y(value)), color: "#eeeeee" });
+        shapes.push(text((LEFT - 6.0, y(value) + 4.0), (chart.format)(value), Anchor::End));
+    }
+    for (at, label) in time_ticks(history.since, history.until, history.resolution) {
+        let tick = x(at.timestamp() as f64);
+        shapes.push(Shape::Line { from: (tick, TOP + plot_height), to: (tick, TOP + plot_height + 4.0), color: "#999999" });
+        shapes.push(text((tick, TOP + plot_height + 16.0), label, Anchor::Middle));
+    }
+    shapes.push(Shape::Path { runs: vec![vec![(LEFT, TOP), (LEFT, TOP + plot_height), (LEFT + plot_width, TOP + plot_height)]], color: "#999999", width: 1.0 });
+    shapes.push(text((LEFT + plot_width / 2.0, HEIGHT - 6.0), "Time (UTC)".to_string(), Anchor::Middle));
+    shapes.push(Shape::Text { at: (14.0, TOP + plot_height / 2.0), text: chart.unit.to_string(), size: 11.0, bold: false, anchor: Anchor::Middle, vertical: true });
+
+    for (index, line) in chart.lines.iter().enumerate() {
+        let mut runs: Vec<Vec<(f64, f64)>> = Vec::new();
+        let mut previous: Option<DateTime<Utc>> = None;
+        for (start, value) in starts.iter().zip(&line.values) {
+            let Some(value) = value else {
+                previous = None;
+                continue;
+            };
+            let point = (x(start.timestamp() as f64 + bucket_secs / 2.0), y(*value));
+            match runs.last_mut() {
+                Some(run) if previous.is_some_and(|previous| (*start - previous).num_seconds() as f64 <= bucket_secs) => run.push(point),
+                _ => runs.push(vec![point]),
+            }
+            previous = Some(*start);
+        }
+        let points: Vec<(f64, f64)> = runs.iter().flatten().copied().collect();
+        shapes.push(Shape::Path { runs, color: line.color, width: 2.0 });
+        shapes.extend(points.into_iter().map(|center| Shape::Circle { center, radius: 2.5, color: line.color }));
+        let legend = WIDTH - RIGHT - 80.0 * (chart.lines.len() - index) as f64;
+        shapes.push(Shape::Rect { at: (legend, 12.0), size: (12.0, 3.0), color: line.color });
+        shapes.push(text((legend + 16.0, 18.0), line.label.to_string(), Anchor::Start));
+    }
+    Drawing { title: chart.title.clone(), width: WIDTH, height: HEIGHT, shapes }
+}
+
+/// `drawing` as an inline `<svg>` element
+fn svg(drawing: &Drawing) -> String {
+    let mut svg = vec![format!(
+        "        <svg class=\"chart\" xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" role=\"img\" aria-label=\"{}\" font-family=\"Arial, sans-serif\" font-size=\"11\">\n",
+        drawing.width, drawing.height, drawing.title
+    )];
+    for shape in &drawing.shapes {
+        svg.push(match shape {
+            Shape::Line { from, to, color } => {
+                format!("          <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\"/>\n", from.0, from.1, to.0, to.1, color)
+            }
+            Shape::Path { runs, color, width } => {
+                let d: Vec<String> = runs
+                    .iter()
+                    .flat_map(|run| run.iter().enumerate().map(|(i, (x, y))| format!("{}{:.1},{:.1}", if i == 0 { "M" } else { "L" }, x, y)))
+                    .collect();
+                format!("          <path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>\n", d.join(" "), color, width)
+            }
+            Shape::Circle { center, radius, color } => {
+                format!("          <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\"/>\n", center.0, center.1, radius, color)
+            }
+            Shape::Rect { at, size, color } => {
+                format!("          <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", at.0, at.1, size.0, size.1, color)
+            }
+            Shape::Text { at, text, size, bold, anchor, vertical } => {
+                let anchor = match anchor {
+                    Anchor::Start => "start",
+                    Anchor::Middle => "middle",
+                    Anchor::End => "end",
+                };
+                let position = if *vertical {
+                    format!("transform=\"translate({:.1} {:.1}) rotate(-90)\"", at.0, at.1)
+                } else {
+                    format!("x=\"{:.1}\" y=\"{:.1}\"", at.0, at.1)
+                };
+                let weight = if *bold { " font-weight=\"bold\"" } else { "" };
+                format!("          <text {} font-size=\"{}\"{} text-anchor=\"{}\" fill=\"{}\">{}</text>\n", position, size, weight, anchor, TEXT_COLOR, text)
+            }
+        });
+    }
+    svg.push("        </svg>\n".to_string());
+    svg.concat()
+}
+
+fn resolution_secs(resolution: Resolution) -> i64 {
+    match resolution {
+        Resolution::Hour => 3600,
+        Resolution::Day => 24 * 3600,
+    }
+}
+
+/// Labelled times along the x axis: whole hours or days, spaced so there
+/// are at most `MAX_X_TICKS`
+fn time_ticks(since: DateTime<Utc>, until: DateTime<Utc>, resolution: Resolution) -> Vec<(DateTime<Utc>, String)> {
+    let (steps, format): (&[i64], &str) = match resolution {
+        Resolution::Hour => (&[1, 2, 3, 6, 12, 24], "%m-%d %H:%M"),
+        Resolution::Day => (&[1, 2, 7, 14, 28], "%Y-%m-%d"),
+    };
+    let unit = resolution_secs(resolution);
+    let span = (until - since).num_seconds().max(1) / unit;
+    let step = steps
+        .iter()
+        .copied()
+        .find(|step| span / step < MAX_X_TICKS)
+        .unwrap_or_else(|| span / MAX_X_TICKS + 1)
+        * unit;
+    let first = (since.timestamp() + step - 1).div_euclid(step) * step;
+    (first..=until.timestamp())
+        .step_by(step as usize)
+        .filter_map(|secs| Utc.timestamp_opt(secs, 0).single())
+        .map(|at| (at, at.format(format).to_string()))
+        .collect()
+}
+
+/// The smallest 1, 2, 2.5 or 5 times a power of ten at or above `value`
+/// (in multiples of 1024 first when `binary`), so gridlines land on round
+/// labels
+fn nice_ceiling(value: f64, binary: bool) -> f64 {
+    if value <= 0.0 {
+        return if binary { 1024.0 } else { Y_STEPS as f64 };
+    }
+    let mut scale = 1.0;
+    while binary && value / scale >= 1024.0 {
+        scale *= 1024.0;
+    }
+    let scaled = value / scale;
+    let magnitude = 10f64.powf(scaled.log10().floor());
+    let nice = [1.0, 2.0, 2.5, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|nice| *nice >= scaled)
+        .unwrap_or(10.0 * magnitude);
+    nice * scale
+}
+
+/// A value label without trailing zeros: `0.5`, `12`, `1.25`
+fn format_number(value: f64) -> String {
+    ((value * 100.0).round() / 100.0).to_string()
+}
@@ -0,0 +1,444 @@
+// Monitor daemon (`monitor --daemon`)
+// Samples the interface counters in the background and logs what it sees,
+// one JSON line per record, until SIGTERM or SIGINT.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::alerts::{self, AlertEngine, AlertState};
+use super::anomaly::{self, AnomalyCapture};
+use super::events::EventWatcher;
+use super::metrics;
+use super::probes::{self, ProbeStats, ProbeTarget};
+use super::sample_log::{log_records, SampleLog};
+use super::stream::StreamEvent;
+use super::systemd::{self, Notifier};
+use super::talkers::{self, NameCache, TalkerCapture};
+use super::wifi;
+use super::{
+    alert_inputs, gather_interface_stats, history_recorder, record_history, start_anomaly_capture, start_stream, MonitorOptions,
+    NetworkStats,
+};
+use crate::status;
+
+/// How often the daemon samples unless `--interval` says
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+/// Size at which the log is rotated
+pub const LOG_MAX_BYTES: u64 = 10_000_000;
+/// Rotated logs kept besides the current one
+pub const LOG_KEEP: usize = 5;
+
+/// How long the detaching process waits to see the daemon come up
+const STARTUP_WAIT: Duration = Duration::from_secs(2);
+
+/// `name` in /var/run or /var/log when we may write there, else in the
+/// user's runtime directory
+fn system_or_runtime_path(system_dir: &str, name: &str) -> PathBuf {
+    let writable = std::ffi::CString::new(system_dir)
+        .is_ok_and(|dir| unsafe { libc::access(dir.as_ptr(), libc::W_OK) } == 0);
+    if writable {
+        return Path::new(system_dir).join(name);
+    }
+    std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(std::env::temp_dir).join(name)
+}
+
+/// Where the daemon's PID file goes
+pub fn pid_file_path() -> PathBuf {
+    system_or_runtime_path("/var/run", "netweaver.pid")
+}
+
+/// Where the daemon logs when `--log` isn't given
+pub fn default_log_path() -> PathBuf {
+    system_or_runtime_path("/var/log", "netweaver.log")
+}
+
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks; EPERM means it exists but isn't ours
+    pid > 0 && (unsafe { libc::kill(pid, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+/// The PID in `path` if that process is still running
+pub fn running_pid(path: &Path) -> Option<u32> {
+    let pid = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    process_alive(pid).then_some(pid)
+}
+
+/// A PID file we created, removed again when dropped
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write our PID to `path`, unless it names a process that is still
+    /// running; a file left behind by a dead one is replaced
+    pub fn acquire(path: &Path) -> Result<Self> {
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    return Ok(Self { path: path.to_path_buf() });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    if let Some(pid) = running_pid(path) {
+                        anyhow::bail!("NetWeaver daemon already running (PID {}, see {})", pid, path.display());
+                    }
+                    std::fs::remove_file(path)
+                        .with_context(|| format!("Failed to remove stale PID file {}", path.display()))?;
+                }
+                Err(err) => return Err(err).with_context(|| format!("Failed to create {}", path.display())),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A line-per-record log that moves to `.1`, `.2`, … once it reaches its
+/// size limit, keeping `keep` old files. It reopens its path when the file
+/// was moved or deleted behind its back (logrotate) or a write failed, and
+/// starts every new file with the header, if it has one
+#[derive(Debug)]
+pub struct RotatingLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+    header: Option<String>,
+    /// A write failed, so the file is reopened before the next one
+    stale: bool,
+}
+
+impl RotatingLog {
+    /// Append to `path`, creating it if needed
+    pub fn open(path: &Path, max_bytes: u64, keep: usize) -> Result<Self> {
+        let file = Self::open_file(path)?;
+        let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        Ok(Self { path: path.to_path_buf(), file, size, max_bytes, keep, header: None, stale: false })
+    }
+
+    /// Write `header` as the first line of every file this log starts
+    pub fn with_header(mut self, header: &str) -> Self {
+        self.header = Some(header.to_string());
+        self
+    }
+
+    fn open_file(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))
+    }
+
+    /// The `index`th rotated file: base.1 is the newest
+    pub fn path_of(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    /// Write `record` as one JSON line, rotating first if it would cross the limit
+    pub fn append<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.append_line(&line)
+    }
+
+    /// Write `line`, which ends in a newline, rotating first if it would
+    /// cross the limit
+    pub fn append_line(&mut self, line: &[u8]) -> Result<()> {
+        if self.stale || self.moved() {
+            self.reopen()?;
+        }
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let result = match &self.header {
+            Some(header) if self.size == 0 => {
+                let header = format!("{}\n", header);
+                self.file.write_all(header.as_bytes()).map(|()| header.len())
+            }
+            _ => Ok(0),
+        }
+        .and_then(|written| self.file.write_all(line).map(|()| written + line.len()));
+        match result {
+            Ok(written) => {
+                self.size += written as u64;
+                Ok(())
+            }
+            Err(err) => {
+                self.stale = true;
+                Err(err).with_context(|| format!("Failed to write {}", self.path.display()))
+            }
+        }
+    }
+
+    /// Whether our path no longer names the file we have open
+    fn moved(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(&self.path), self.file.metadata()) {
+            (Ok(on_disk), Ok(ours)) => on_disk.dev() != ours.dev() || on_disk.ino() != ours.ino(),
+            _ => true,
+        }
+    }
+
+    fn reopen(&mut self) -> Result<()> {
+        self.file = Self::open_file(&self.path)?;
+        // Also picks up a copytruncate
+        self.size = self.file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        self.stale = false;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.file.sync_all().ok();
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path).ok();
+        } else {
+            // base.(keep) falls off the end as base.(keep-1) moves over it
+            for index in (1..self.keep).rev() {
+                let from = self.path_of(index);
+                if from.exists() {
+                    std::fs::rename(&from, self.path_of(index + 1))
+                        .with_context(|| format!("Failed to rotate {}", from.display()))?;
+                }
+            }
+            std::fs::rename(&self.path, self.path_of(1))
+                .with_context(|| format!("Failed to rotate {}", self.path.display()))?;
+        }
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Push what was written to disk
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.sync_all().with_context(|| format!("Failed to flush {}", self.path.display()))
+    }
+}
+
+/// Start the daemon on `interface`: detach a copy of ourselves, or with
+/// `opts.foreground` do the sampling here until a signal
+pub async fn run_daemon(interface: String, probe_targets: Vec<ProbeTarget>, opts: MonitorOptions) -> Result<()> {
+    let pid_path = pid_file_path();
+    let log_path = opts.log.clone().map(PathBuf::from).unwrap_or_else(default_log_path);
+    if let Some(pid) = running_pid(&pid_path) {
+        anyhow::bail!("NetWeaver daemon already running (PID {}, see {})", pid, pid_path.display());
+    }
+    // systemd only listens to the process it started, so a detached copy
+    // couldn't report in
+    let notify = std::env::var_os("NOTIFY_SOCKET").is_some();
+    if notify && !opts.foreground {
+        tracing::debug!("NOTIFY_SOCKET is set, staying in the foreground");
+    }
+    if opts.foreground || notify {
+        run_foreground(&interface, &log_path, &pid_path, probe_targets, opts).await
+    } else {
+        detach(&log_path, &pid_path).await
+    }
+}
+
+/// Run this same command again with `--foreground`, in its own session and
+/// without a terminal, and wait for it to write its PID file. Starting a new
+/// process is safer than forking a running tokio runtime
+async fn detach(log_path: &Path, pid_path: &Path) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let exe = std::env::current_exe().context("Failed to find our own executable")?;
+    let mut command = Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg("--foreground")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().context("Failed to start the daemon")?;
+
+    let started = std::time::Instant::now();
+    while started.elapsed() < STARTUP_WAIT {
+        if let Some(status) = child.try_wait()? {
+            anyhow::bail!("Daemon exited during startup ({}); run with --foreground to see why", status);
+        }
+        if running_pid(pid_path) == Some(child.id()) {
+            status!("{} (PID {})", "Daemon started successfully".bright_green(), child.id());
+            status!("📝 Logging to: {}", log_path.display().to_string().bright_green());
+            status!("Use 'kill $(cat {})' to stop", pid_path.display());
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    anyhow::bail!("Daemon (PID {}) did not write {} in time", child.id(), pid_path.display())
+}
+
+/// Sample until a signal, holding the PID file. Every interval appends a
+/// record per interface to the log and the monitoring history, a
+/// `{"wifi": …}` line per wireless link, and `{"alert": …}` and
+/// `{"event": …}` lines for alerts that fire or resolve and links or
+/// addresses that change. Each `--probe` result is a `{"probe": …}` line as
+/// it comes in, and with `--top-talkers` the final ranking is a
+/// `{"top_talkers": …}` line on the way out. Under systemd it reports ready
+/// once it is sampling, feeds the watchdog from this loop, and says when it
+/// is stopping
+async fn run_foreground(
+    interface: &str,
+    log_path: &Path,
+    pid_path: &Path,
+    probe_targets: Vec<ProbeTarget>,
+    opts: MonitorOptions,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let MonitorOptions { interval, alerts: alert_settings, top_talkers, .. } = opts;
+    let mut log = SampleLog::open(log_path)?;
+    let pid_file = PidFile::acquire(pid_path)?;
+    if let Some(metrics) = opts.metrics {
+        metrics::start(metrics, interface.to_string()).await?;
+    }
+    let stream = start_stream(opts.stream).await?;
+    let capture = top_talkers.map(|_| TalkerCapture::start(interface)).transpose()?;
+    let anomaly_capture = start_anomaly_capture(interface, opts.capture_on_anomaly.as_deref())?;
+    let method = probes::method();
+    let mut probe_samples = probes::spawn(&probe_targets, method);
+    let mut probe_stats: Vec<ProbeStats> = probe_targets.into_iter().map(|target| ProbeStats::new(target, method)).collect();
+    let mut terminate = signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?;
+    let mut interrupt = signal(SignalKind::interrupt()).context("Failed to listen for SIGINT")?;
+    status!("📝 Logging to: {}", log_path.display().to_string().bright_green());
+    status!("🗂  PID file: {}", pid_file.path().display());
+    status!("⏱  Sampling every {:?}", interval);
+
+    let notifier = Notifier::from_env();
+    let mut watchdog = notifier.as_ref().and_then(|_| systemd::watchdog_interval()).map(tokio::time::interval);
+    let mut ready = false;
+    let mut ticker = tokio::time::interval(interval);
+    let mut previous: Option<Vec<NetworkStats>> = None;
+    let mut alert_engine = AlertEngine::new(alert_settings.rules.clone(), alert_settings.cooldown);
+    let mut recorder = history_recorder();
+    let mut events = EventWatcher::new(interface);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let stats = gather_interface_stats();
+                if let Some(previous) = &previous {
+                    let records = log_records(previous, &stats, interface);
+                    if let Err(e) = log.write_records(&records) {
+                        tracing::warn!("{:#}", e);
+                    }
+                    if let Some(stream) = &stream {
+                        stream.publish_records(&records);
+                    }
+                }
+                let links = wifi::links(&stats, interface);
+                for link in &links {
+                    if let Err(e) = log.write_wifi(link) {
+                        tracing::warn!("{:#}", e);
+                    }
+                    if let Some(stream) = &stream {
+                        stream.publish(&StreamEvent::Wifi(link.clone()));
+                    }
+                }
+                let mut inputs = alert_inputs(previous.as_deref(), &stats, interface);
+                wifi::add_signal(&mut inputs, &links);
+                inputs.extend(probes::alert_inputs(&probe_stats));
+                for alert in alert_engine.evaluate(std::time::Instant::now(), &inputs) {
+                    if let Err(e) = log.write_alert(&alert) {
+                        tracing::warn!("{:#}", e);
+                    }
+                    if let Some(stream) = &stream {
+                        stream.publish(&StreamEvent::Alert(alert.clone()));
+                    }
+                    if alert.state == AlertState::Firing {
+                        if let Some(path) = anomaly_capture.as_ref().and_then(AnomalyCapture::trigger) {
+                            status!("📦 {}: capturing to {}", alert.message, path.display());
+                        }
+                    }
+                    alerts::dispatch(&alert_settings, &alert);
+                }
+                for event in events.poll(&stats) {
+                    status!("{} {}", event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S"), event.message);
+                    if let Err(e) = log.write_event(&event) {
+                        tracing::warn!("{:#}", e);
+                    }
+                    if let Some(stream) = &stream {
+                        stream.publish(&StreamEvent::Event(event.clone()));
+                    }
+                    alerts::dispatch_event(&alert_settings, &event);
+                }
+                record_history(&mut recorder, &stats);
+                previous = Some(stats);
+                if let (Some(notifier), false) = (&notifier, ready) {
+                    notifier.notify(&format!("READY=1\nSTATUS=Sampling every {:?}", interval));
+                    ready = true;
+                }
+            }
+            _ = async { watchdog.as_mut()?.tick().await; Some(()) }, if watchdog.is_some() => {
+                if let Some(notifier) = &notifier {
+                    notifier.notify("WATCHDOG=1");
+                }
+            }
+            Some(sample) = probe_samples.recv() => {
+                probes::record(&mut probe_stats, &sample);
+                if let (Some(capture), Some(reason)) = (&anomaly_capture, anomaly::probe_anomaly(&probe_stats, &sample)) {
+                    if let Some(path) = capture.trigger() {
+                        status!("📦 {}: capturing to {}", reason, path.display());
+                    }
+                }
+                if let Err(e) = log.write_probe(&sample) {
+                    tracing::warn!("{:#}", e);
+                }
+                if let Some(stream) = &stream {
+                    stream.publish(&StreamEvent::Probe(sample));
+                }
+            }
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+        }
+    }
+    if let Some(notifier) = &notifier {
+        notifier.notify("STOPPING=1");
+    }
+
+    if let (Some(capture), Some(limit)) = (capture, top_talkers) {
+        if let Err(e) = talkers::finish(capture, limit, &NameCache::new(), Some(&mut log)).await {
+            tracing::warn!("{:#}", e);
+        }
+    }
+    if let Some(capture) = anomaly_capture {
+        match capture.finish() {
+            Ok(files) => anomaly::print_saved(&files),
+            Err(e) => tracing::warn!("{:#}", e),
+        }
+    }
+    log.flush()?;
+    drop(pid_file);
+    status!("\n{}", "Daemon stopped".bright_green());
+    Ok(())
+}
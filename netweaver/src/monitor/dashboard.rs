@@ -0,0 +1,1058 @@
+// Realtime dashboard (`monitor --realtime`)
+// A ratatui view of the interface counters: RX/TX sparklines of the selected
+// interface (or a panel each when comparing several), a rate table of every
+// interface with its error and drop counters, a latency panel with each
+// `--probe` target (the default gateway and 1.1.1.1 unless given), a Wi-Fi
+// panel with the signal, bitrate and channel when the interface shown is
+// wireless, the busiest connections, the latest interface events (a link
+// going down, an address changing), the errors by cause of interfaces that
+// have any (CRC errors in red, bold while they rise), and the alerts,
+// protocol counters and top talkers when those were asked for. `Dashboard` only holds what is shown and reacts to
+// keys, so it can be drawn onto a test backend; `run` does the sampling,
+// alerting and history recording around it. The terminal is put back by a
+// guard on the way out and by a panic hook if we never get there.
+
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Sparkline, Table};
+use ratatui::Frame;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::alerts::{self, Alert, AlertEngine, AlertState};
+use super::anomaly::{self, AnomalyCapture};
+use super::events::{EventWatcher, InterfaceEvent};
+use super::interfaces::CRC_ERRORS;
+use super::probes::{self, ProbeStats, ProbeTarget};
+use super::protocols::{self, ProtocolStats};
+use super::sample_log::{log_records, SampleLog};
+use super::stream::{EventStream, StreamEvent};
+use super::summary::SummaryRecorder;
+use super::talkers::{self, NameCache, Talker, TalkerCapture};
+use super::wifi::{self, WifiStats};
+use super::{
+    alert_inputs, finish_summary, gather_interface_stats, history_recorder, record_history, traffic_deltas, NetworkStats, TrafficDelta,
+    MonitorOptions, ALL_INTERFACES, RATE_WINDOW, RECENT_ALERTS, RECENT_EVENTS, TOP_CONNECTIONS,
+};
+use crate::analytics::BandwidthAnalyzer;
+use crate::diagnostics::connections::{self, Connection, Transport};
+use crate::utils;
+
+/// Refresh interval the dashboard starts with unless `--interval` says
+pub const DEFAULT_REFRESH: Duration = Duration::from_secs(1);
+/// What `+` and `-` step through; the first and last bound `--interval`
+pub const REFRESH_STEPS: [Duration; 9] = [
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+    Duration::from_secs(30),
+    Duration::from_secs(60),
+];
+
+/// Samples kept per sparkline, more than any terminal is wide
+const SPARKLINE_POINTS: usize = 300;
+/// Sparklines plot the rate averaged over this long, to take the edge off
+/// counters that the kernel updates in bursts
+const SPARKLINE_SMOOTHING: Duration = Duration::from_secs(3);
+/// Longest wait for a key before redrawing, so probes show up promptly
+const MAX_POLL: Duration = Duration::from_millis(250);
+
+/// What a key asks the run loop to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Ignore,
+    Redraw,
+    Quit,
+}
+
+/// One direction of one interface: the average and peak the title shows,
+/// and the smoothed rates the sparkline plots
+#[derive(Debug, Clone)]
+struct Trend {
+    average: BandwidthAnalyzer,
+    smoothed: BandwidthAnalyzer,
+    points: VecDeque<u64>,
+    peak: f64,
+}
+
+impl Trend {
+    fn new() -> Self {
+        Self {
+            average: BandwidthAnalyzer::new(RATE_WINDOW),
+            smoothed: BandwidthAnalyzer::new(SPARKLINE_SMOOTHING),
+            points: VecDeque::with_capacity(SPARKLINE_POINTS),
+            peak: 0.0,
+        }
+    }
+
+    /// Add one refresh's bytes; None on the first sample, which only marks
+    /// where the windows start
+    fn add(&mut self, bytes: Option<u64>, rate: f64) {
+        self.average.add_measurement(bytes.unwrap_or(0));
+        self.smoothed.add_measurement(bytes.unwrap_or(0));
+        if bytes.is_some() {
+            self.peak = self.peak.max(rate);
+            if self.points.len() == SPARKLINE_POINTS {
+                self.points.pop_front();
+            }
+            self.points.push_back(self.smoothed.current_bandwidth() as u64);
+        }
+    }
+}
+
+/// Everything the dashboard shows, updated by the run loop
+#[derive(Debug)]
+pub struct Dashboard {
+    selected: String,
+    /// The interfaces compared side by side, when `--interface` named several
+    compare: Vec<String>,
+    paused: bool,
+    refresh: Duration,
+    started: Instant,
+    protocol: Option<String>,
+    stats: Vec<NetworkStats>,
+    deltas: Vec<TrafficDelta>,
+    /// RX and TX per interface, `all` included
+    trends: BTreeMap<String, (Trend, Trend)>,
+    connections: std::result::Result<Vec<Connection>, String>,
+    protocols: Option<std::result::Result<(ProtocolStats, Option<f64>), String>>,
+    previous_protocols: Option<(Instant, ProtocolStats)>,
+    alerting: bool,
+    firing: Vec<(String, String)>,
+    recent_alerts: Vec<Alert>,
+    recent_events: Vec<InterfaceEvent>,
+    /// Latency to each probe target
+    probes: Vec<ProbeStats>,
+    /// The link of each wireless interface
+    wifi: Vec<WifiStats>,
+    /// Why the last `--log` write failed
+    log_error: Option<String>,
+    /// The busiest remote hosts, with `--top-talkers`
+    talkers: Option<Vec<Talker>>,
+    /// When a `--duration` run ends
+    stop_at: Option<Instant>,
+    /// The anomaly capture file being written
+    anomaly_capture: Option<String>,
+}
+
+impl Dashboard {
+    /// A dashboard showing `interface` first; a list like `eth0,wg0` shows
+    /// only those, side by side
+    pub fn new(interface: &str, protocol: Option<String>) -> Self {
+        let names = super::interface_names(interface);
+        let compare: Vec<String> = if names.len() > 1 { names.iter().map(|name| name.to_string()).collect() } else { Vec::new() };
+        Self {
+            selected: if compare.is_empty() { interface.to_string() } else { ALL_INTERFACES.to_string() },
+            compare,
+            paused: false,
+            refresh: DEFAULT_REFRESH,
+            started: Instant::now(),
+            protocol,
+            stats: Vec::new(),
+            deltas: Vec::new(),
+            trends: BTreeMap::new(),
+            connections: Ok(Vec::new()),
+            protocols: None,
+            previous_protocols: None,
+            alerting: false,
+            firing: Vec::new(),
+            recent_alerts: Vec::new(),
+            recent_events: Vec::new(),
+            probes: Vec::new(),
+            wifi: Vec::new(),
+            log_error: None,
+            talkers: None,
+            stop_at: None,
+            anomaly_capture: None,
+        }
+    }
+
+    pub fn selected_interface(&self) -> &str {
+        &self.selected
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn refresh(&self) -> Duration {
+        self.refresh
+    }
+
+    /// Sample and redraw every `refresh`, as `--interval` asks
+    pub fn set_refresh(&mut self, refresh: Duration) {
+        self.refresh = refresh;
+    }
+
+    /// `all`, then every interface of the last sample; when comparing, `all`
+    /// is the total of the compared ones
+    pub fn interfaces(&self) -> Vec<String> {
+        std::iter::once(ALL_INTERFACES.to_string())
+            .chain(self.stats.iter().map(|row| row.interface.clone()))
+            .collect()
+    }
+
+    /// Show a new sample of every interface's counters
+    pub fn update(&mut self, mut stats: Vec<NetworkStats>) {
+        if !self.compare.is_empty() {
+            stats.retain(|row| self.compare.contains(&row.interface));
+            stats.sort_by_key(|row| self.compare.iter().position(|name| *name == row.interface));
+        }
+        self.deltas = if self.stats.is_empty() { Vec::new() } else { traffic_deltas(&self.stats, &stats) };
+        let names = stats.iter().map(|row| row.interface.as_str()).chain([ALL_INTERFACES]);
+        for name in names {
+            let delta = self.deltas.iter().find(|delta| delta.interface == name);
+            let (rx, tx) = self.trends.entry(name.to_string()).or_insert_with(|| (Trend::new(), Trend::new()));
+            rx.add(delta.map(|delta| delta.bytes_recv), delta.map_or(0.0, TrafficDelta::rx_rate));
+            tx.add(delta.map(|delta| delta.bytes_sent), delta.map_or(0.0, TrafficDelta::tx_rate));
+        }
+        // An interface that went away keeps its trend only while selected
+        self.trends.retain(|name, _| {
+            name == ALL_INTERFACES || *name == self.selected || stats.iter().any(|row| &row.interface == name)
+        });
+        self.stats = stats;
+    }
+
+    /// Show the busiest of `connections`, or why they couldn't be listed
+    pub fn set_connections(&mut self, connections: Result<Vec<Connection>>) {
+        let transport = match self.protocol.as_deref() {
+            Some("tcp") => Some(Transport::Tcp),
+            Some("udp") => Some(Transport::Udp),
+            _ => None,
+        };
+        self.connections = connections
+            .map(|connections| connections::top_connections(connections, transport, TOP_CONNECTIONS))
+            .map_err(|e| format!("{:#}", e));
+    }
+
+    /// Show protocol counters read at `now`, as rates since the previous read
+    pub fn set_protocols(&mut self, now: Instant, counters: Result<ProtocolStats>) {
+        let protocol = self.protocol.as_deref().unwrap_or(ALL_INTERFACES);
+        self.protocols = Some(match counters {
+            Ok(counters) => {
+                let shown = match &self.previous_protocols {
+                    Some((at, previous)) => (counters.since(previous).only(protocol), Some(now.duration_since(*at).as_secs_f64())),
+                    None => (counters.clone().only(protocol), None),
+                };
+                self.previous_protocols = Some((now, counters));
+                Ok(shown)
+            }
+            Err(e) => Err(format!("{:#}", e)),
+        });
+    }
+
+    /// Show what `engine` has firing and the latest alerts
+    pub fn set_alerts(&mut self, engine: &AlertEngine, recent: &[Alert]) {
+        self.alerting = !engine.is_empty();
+        self.firing = engine.firing();
+        self.recent_alerts = recent.to_vec();
+    }
+
+    /// Count down to `stop_at` in the header
+    pub fn set_stop_at(&mut self, stop_at: Option<Instant>) {
+        self.stop_at = stop_at;
+    }
+
+    /// Show the anomaly capture file being written, or stop showing it
+    pub fn set_anomaly_capture(&mut self, path: Option<String>) {
+        self.anomaly_capture = path;
+    }
+
+    /// Show the latest interface events
+    pub fn set_events(&mut self, recent: &[InterfaceEvent]) {
+        self.recent_events = recent.to_vec();
+    }
+
+    /// Show that writing the `--log` file failed, or stop showing it
+    pub fn set_log_error(&mut self, error: Option<String>) {
+        self.log_error = error;
+    }
+
+    /// Show the busiest remote hosts
+    pub fn set_talkers(&mut self, talkers: Vec<Talker>) {
+        self.talkers = Some(talkers);
+    }
+
+    /// Show the latency to each probe target
+    pub fn set_probes(&mut self, probes: Vec<ProbeStats>) {
+        self.probes = probes;
+    }
+
+    /// Show the link of each wireless interface
+    pub fn set_wifi(&mut self, wifi: Vec<WifiStats>) {
+        self.wifi = wifi;
+    }
+
+    /// React to a key press
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.kind == KeyEventKind::Release {
+            return Action::Ignore;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+            KeyCode::Right | KeyCode::Tab | KeyCode::Char('l') => self.select_step(1),
+            KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h') => self.select_step(-1),
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                self.paused = !self.paused;
+                Action::Redraw
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => self.refresh_step(1),
+            KeyCode::Char('-') | KeyCode::Char('_') => self.refresh_step(-1),
+            _ => Action::Ignore,
+        }
+    }
+
+    fn select_step(&mut self, step: isize) -> Action {
+        let names = self.interfaces();
+        let current = names.iter().position(|name| *name == self.selected).unwrap_or(0) as isize;
+        let next = (current + step).rem_euclid(names.len() as isize) as usize;
+        self.selected = names[next].clone();
+        Action::Redraw
+    }
+
+    fn refresh_step(&mut self, step: isize) -> Action {
+        // The next setting past the current one, which may be off the list
+        let (first, last) = (REFRESH_STEPS[0], REFRESH_STEPS[REFRESH_STEPS.len() - 1]);
+        self.refresh = if step > 0 {
+            REFRESH_STEPS.into_iter().find(|&next| next > self.refresh).unwrap_or(last)
+        } else {
+            REFRESH_STEPS.into_iter().rev().find(|&next| next < self.refresh).unwrap_or(first)
+        };
+        Action::Redraw
+    }
+
+    /// Draw the whole dashboard onto `frame`
+    pub fn render(&self, frame: &mut Frame) {
+        let side_panels = self.alerting || self.protocol.is_some() || !self.recent_events.is_empty() || self.has_error_detail();
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Length(if self.compare.is_empty() { 7 } else { 9 }),
+                Constraint::Min(6),
+                Constraint::Length((TOP_CONNECTIONS + 3) as u16),
+                Constraint::Length(if side_panels { 8 } else { 0 }),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+
+        frame.render_widget(self.header(), rows[0]);
+        if self.compare.is_empty() {
+            self.render_sparklines(frame, rows[1]);
+        } else {
+            self.render_comparison(frame, rows[1]);
+        }
+        let middle = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .split(rows[2]);
+        frame.render_widget(self.interface_table(), middle[0]);
+        match self.wifi_shown() {
+            Some(wifi) => {
+                let side = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(5), Constraint::Min(0)])
+                    .split(middle[1]);
+                render_wifi(frame, side[0], wifi);
+                self.render_latency(frame, side[1]);
+            }
+            None => self.render_latency(frame, middle[1]),
+        }
+        match &self.talkers {
+            Some(talkers) => {
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+                    .split(rows[3]);
+                frame.render_widget(self.connection_table(), halves[0]);
+                frame.render_widget(talker_table(talkers), halves[1]);
+            }
+            None => frame.render_widget(self.connection_table(), rows[3]),
+        }
+        if side_panels {
+            let panels: Vec<Paragraph> = [
+                self.alerting.then(|| self.alert_panel()),
+                (!self.recent_events.is_empty()).then(|| self.event_panel()),
+                self.has_error_detail().then(|| self.error_panel()),
+                self.protocol.is_some().then(|| self.protocol_panel()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            let areas = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Ratio(1, panels.len() as u32); panels.len()])
+                .split(rows[4]);
+            for (panel, area) in panels.into_iter().zip(areas.iter()) {
+                frame.render_widget(panel, *area);
+            }
+        }
+        frame.render_widget(footer(), rows[5]);
+    }
+
+    fn header(&self) -> Paragraph<'_> {
+        let names = self.interfaces();
+        let position = names.iter().position(|name| *name == self.selected)
+            .map(|index| format!("{}/{}", index + 1, names.len()))
+            .unwrap_or_else(|| "gone".to_string());
+        let mut spans = vec![
+            Span::styled("NetWeaver Monitor", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::raw(" │ "),
+            Span::styled(format!("{} ({})", self.selected, position), Style::default().fg(Color::Yellow)),
+            Span::raw(format!(" │ Uptime {}s │ Refresh {:?}", self.started.elapsed().as_secs(), self.refresh)),
+        ];
+        if let Some(stop_at) = self.stop_at {
+            spans.push(Span::raw(format!(" │ Stops in {}s", stop_at.saturating_duration_since(Instant::now()).as_secs())));
+        }
+        if let Some(path) = &self.anomaly_capture {
+            spans.push(Span::raw(" │ "));
+            spans.push(Span::styled(format!("● Capturing to {}", path), Style::default().fg(Color::Red)));
+        }
+        if self.paused {
+            spans.push(Span::raw(" │ "));
+            spans.push(Span::styled("PAUSED", Style::default().fg(Color::Black).bg(Color::Yellow)));
+        }
+        if let Some(error) = &self.log_error {
+            spans.push(Span::raw(" │ "));
+            spans.push(Span::styled(format!("⚠️  {}", error), Style::default().fg(Color::Red)));
+        }
+        Paragraph::new(Line::from(spans))
+    }
+
+    fn render_sparklines(&self, frame: &mut Frame, area: Rect) {
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(area);
+        let trends = self.trends.get(&self.selected);
+        let delta = self.deltas.iter().find(|delta| delta.interface == self.selected);
+        let directions = [
+            ("RX", trends.map(|(rx, _)| rx), delta.map(TrafficDelta::rx_rate), Color::Green),
+            ("TX", trends.map(|(_, tx)| tx), delta.map(TrafficDelta::tx_rate), Color::Magenta),
+        ];
+        for ((label, trend, rate, color), area) in directions.into_iter().zip(halves.iter()) {
+            let title = match trend {
+                Some(trend) => format!(" {} {} · {}s avg {} · peak {} ",
+                                       label, rate.map(utils::format_bandwidth).unwrap_or_else(|| "-".to_string()),
+                                       RATE_WINDOW.as_secs(), utils::format_bandwidth(trend.average.current_bandwidth()),
+                                       utils::format_bandwidth(trend.peak)),
+                None => format!(" {} - ", label),
+            };
+            let points = trend.map(|trend| visible(&trend.points, area.width)).unwrap_or_default();
+            frame.render_widget(
+                Sparkline::default()
+                    .block(Block::default().borders(Borders::ALL).title(title))
+                    .data(&points)
+                    .style(Style::default().fg(color)),
+                *area,
+            );
+        }
+    }
+
+    /// A panel per compared interface and one for their total, each with
+    /// RX over TX
+    fn render_comparison(&self, frame: &mut Frame, area: Rect) {
+        let names: Vec<&str> = self.compare.iter().map(String::as_str).chain([ALL_INTERFACES]).collect();
+        let panels = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Ratio(1, names.len() as u32); names.len()])
+            .split(area);
+        for (name, area) in names.into_iter().zip(panels.iter()) {
+            let trends = self.trends.get(name);
+            let delta = self.deltas.iter().find(|delta| delta.interface == name);
+            let rate = |value: Option<f64>| value.map(utils::format_bandwidth).unwrap_or_else(|| "-".to_string());
+            let title = if name == ALL_INTERFACES { "total" } else { name };
+            let style = if name == self.selected { Style::default().fg(Color::Yellow) } else { Style::default() };
+            let block = Block::default().borders(Borders::ALL).title(Span::styled(format!(" {} ", title), style));
+            let inner = block.inner(*area);
+            frame.render_widget(block, *area);
+            let halves = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+                .split(inner);
+            let directions = [
+                ("RX", trends.map(|(rx, _)| rx), delta.map(TrafficDelta::rx_rate), Color::Green),
+                ("TX", trends.map(|(_, tx)| tx), delta.map(TrafficDelta::tx_rate), Color::Magenta),
+            ];
+            for ((label, trend, value, color), area) in directions.into_iter().zip(halves.iter()) {
+                let parts = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(1), Constraint::Min(0)])
+                    .split(*area);
+                frame.render_widget(Paragraph::new(format!("{} {}", label, rate(value))).style(Style::default().fg(color)), parts[0]);
+                let points = trend.map(|trend| visible(&trend.points, parts[1].width)).unwrap_or_default();
+                frame.render_widget(Sparkline::default().data(&points).style(Style::default().fg(color)), parts[1]);
+            }
+        }
+    }
+
+    fn interface_table(&self) -> Table<'_> {
+        let header = Row::new(["Interface", "TX/s", "RX/s", "Pkt/s", "Sent", "Recv", "Errors", "Drops", "Link"])
+            .style(Style::default().fg(Color::Cyan));
+        let total = super::total_stats(&self.stats);
+        let rows = self.stats.iter().chain([&total]).map(|row| {
+            let delta = self.deltas.iter().find(|delta| delta.interface == row.interface);
+            let rate = |value: Option<f64>| value.map(utils::format_bandwidth).unwrap_or_else(|| "-".to_string());
+            let counter = |total: Option<u64>, new: Option<u64>| match (total, new) {
+                (Some(total), Some(new)) if new > 0 => Cell::from(format!("{} (+{})", total, new)).style(Style::default().fg(Color::Red)),
+                _ => Cell::from(super::format_count(total)),
+            };
+            let style = if row.interface == self.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(row.interface.clone()),
+                Cell::from(rate(delta.map(TrafficDelta::tx_rate))),
+                Cell::from(rate(delta.map(TrafficDelta::rx_rate))),
+                Cell::from(delta.map(|delta| format!("{:.0}", delta.packet_rate())).unwrap_or_else(|| "-".to_string())),
+                Cell::from(utils::format_bytes(row.bytes_sent)),
+                Cell::from(utils::format_bytes(row.bytes_recv)),
+                counter(Some(row.errors), delta.map(|delta| delta.errors)),
+                counter(row.drops, delta.and_then(|delta| delta.drops)),
+                match row.up {
+                    Some(false) => Cell::from("down").style(Style::default().fg(Color::Red)),
+                    // The total has no link of its own
+                    _ if row.interface == ALL_INTERFACES => Cell::from(""),
+                    _ => Cell::from(super::format_link(row)),
+                },
+            ])
+            .style(style)
+        });
+        let rows: Vec<Row> = rows.collect();
+        Table::new(rows, [
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(8),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(13),
+        ])
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(" Interfaces "))
+    }
+
+    /// Four rows per probe target: its name, latency, jitter and loss, and
+    /// an RTT sparkline; as many targets as fit
+    fn render_latency(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title(" Latency ");
+        if self.probes.is_empty() {
+            frame.render_widget(Paragraph::new("No probe targets; pass --probe HOST").block(block), area);
+            return;
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let slots = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(4); self.probes.len()])
+            .split(inner);
+        for (probe, slot) in self.probes.iter().zip(slots.iter()) {
+            let parts = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Length(1)])
+                .split(*slot);
+            let last = match probe.last() {
+                None => Span::raw("waiting…"),
+                Some(Some(rtt)) => Span::styled(format!("{:.1} ms", rtt), Style::default().fg(Color::Green)),
+                Some(None) => Span::styled("timeout", Style::default().fg(Color::Red)),
+            };
+            let stats = if probe.answered() == 0 {
+                String::new()
+            } else {
+                format!("  avg {:.1}  p95 {:.1}", probe.average(), probe.p95())
+            };
+            let lost = Style::default().fg(if probe.lost() > 0 { Color::Red } else { Color::Reset });
+            let lines = vec![
+                Line::styled(probe.label(), Style::default().fg(Color::Cyan)),
+                Line::from(vec![Span::raw("Last: "), last, Span::raw(stats)]),
+                Line::from(vec![
+                    Span::raw(format!("Jitter: {:.1} ms  ", probe.jitter())),
+                    Span::styled(format!("Lost: {} of {} ({:.1}%)", probe.lost(), probe.sent(), probe.loss_rate()), lost),
+                ]),
+            ];
+            frame.render_widget(Paragraph::new(lines), parts[0]);
+            let points = visible(probe.points(), parts[1].width + 2);
+            frame.render_widget(Sparkline::default().data(&points).style(Style::default().fg(Color::Yellow)), parts[1]);
+        }
+    }
+
+    /// The selected interface's Wi-Fi link, else the first one; None when
+    /// nothing monitored is wireless
+    fn wifi_shown(&self) -> Option<&WifiStats> {
+        self.wifi.iter().find(|wifi| wifi.link.interface == self.selected).or_else(|| self.wifi.first())
+    }
+
+    fn connection_table(&self) -> Table<'_> {
+        let block = Block::default().borders(Borders::ALL).title(" Top Connections ");
+        let widths = [
+            Constraint::Length(5),
+            Constraint::Length(23),
+            Constraint::Length(23),
+            Constraint::Length(12),
+            Constraint::Min(10),
+        ];
+        let note = |text: String, color: Color| Table::new(vec![Row::new(vec![Cell::from(text)])], [Constraint::Percentage(100)])
+            .style(Style::default().fg(color))
+            .block(block.clone());
+        if self.protocol.as_deref() == Some("icmp") {
+            return note("ICMP has no connections; use --protocol tcp, udp or all to list sockets".to_string(), Color::Yellow);
+        }
+        let connections = match &self.connections {
+            Ok(connections) if connections.is_empty() => return note("No matching sockets".to_string(), Color::Reset),
+            Ok(connections) => connections,
+            Err(e) => return note(format!("⚠️  Cannot list connections: {}", e), Color::Red),
+        };
+        let rows: Vec<Row> = connections
+            .iter()
+            .map(|conn| Row::new(vec![
+                conn.protocol.to_string(),
+                connections::format_endpoint(&conn.local),
+                connections::format_endpoint(&conn.remote),
+                conn.state.to_string(),
+                conn.owner(),
+            ]))
+            .collect();
+        Table::new(rows, widths)
+            .header(Row::new(["Proto", "Local", "Remote", "State", "Process"]).style(Style::default().fg(Color::Cyan)))
+            .block(block)
+    }
+
+    fn alert_panel(&self) -> Paragraph<'_> {
+        let mut lines: Vec<Line> = self
+            .firing
+            .iter()
+            .map(|(rule, interface)| Line::styled(format!("🚨 {} on {}", rule, interface), Style::default().fg(Color::Red)))
+            .collect();
+        for alert in self.recent_alerts.iter().rev() {
+            let (state, color) = match alert.state {
+                AlertState::Firing => ("FIRING", Color::Red),
+                AlertState::Resolved => ("RESOLVED", Color::Green),
+            };
+            lines.push(Line::from(vec![
+                Span::raw(format!("{} ", alert.at.with_timezone(&chrono::Local).format("%H:%M:%S"))),
+                Span::styled(format!("{:8} ", state), Style::default().fg(color)),
+                Span::raw(alert.message.clone()),
+            ]));
+        }
+        let title = format!(" Alerts ({} firing) ", self.firing.len());
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title))
+    }
+
+    fn event_panel(&self) -> Paragraph<'_> {
+        let lines: Vec<Line> = self
+            .recent_events
+            .iter()
+            .rev()
+            .map(|event| Line::from(vec![
+                Span::raw(format!("{} ", event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S"))),
+                Span::styled(event.message.clone(), Style::default().fg(Color::Yellow)),
+            ]))
+            .collect();
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Interface Events "))
+    }
+
+    /// Whether an interface has errors broken out by cause
+    fn has_error_detail(&self) -> bool {
+        self.stats.iter().any(|row| !row.error_detail.is_empty())
+    }
+
+    /// Each interface's errors by cause with what the last refresh added;
+    /// CRC errors are red, and bold with a hint while they rise
+    fn error_panel(&self) -> Paragraph<'_> {
+        let mut lines = Vec::new();
+        for row in self.stats.iter().filter(|row| !row.error_detail.is_empty()) {
+            let delta = self.deltas.iter().find(|delta| delta.interface == row.interface);
+            let mut spans = vec![Span::styled(format!("{} ", row.interface), Style::default().fg(Color::Cyan))];
+            for (index, (name, count)) in row.error_detail.iter().enumerate() {
+                let new = delta.and_then(|delta| delta.error_detail.get(name)).copied().unwrap_or(0);
+                let mut text = format!("{} {}", name, count);
+                if new > 0 {
+                    text.push_str(&format!(" (+{})", new));
+                }
+                let style = match (name.as_str(), new) {
+                    (CRC_ERRORS, 0) => Style::default().fg(Color::Red),
+                    (CRC_ERRORS, _) => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                    (_, 0) => Style::default(),
+                    _ => Style::default().fg(Color::Yellow),
+                };
+                if index > 0 {
+                    spans.push(Span::raw(", "));
+                }
+                spans.push(Span::styled(text, style));
+            }
+            lines.push(Line::from(spans));
+            if delta.is_some_and(|delta| delta.error_detail.contains_key(CRC_ERRORS)) {
+                lines.push(Line::styled("  CRC errors rising: check the cable or SFP", Style::default().fg(Color::Red)));
+            }
+        }
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" NIC Errors "))
+    }
+
+    fn protocol_panel(&self) -> Paragraph<'_> {
+        let (title, lines) = match &self.protocols {
+            None => (" Protocols ".to_string(), vec![Line::from("waiting…")]),
+            Some(Err(e)) => (" Protocols ".to_string(), vec![Line::styled(
+                format!("⚠️  Protocol filtering isn't available here: {}", e), Style::default().fg(Color::Red))]),
+            Some(Ok((stats, seconds))) => {
+                let title = if seconds.is_some() { " Protocols (per second) " } else { " Protocols (since boot) " };
+                (title.to_string(), protocol_lines(stats, *seconds).into_iter().map(Line::from).collect())
+            }
+        };
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title))
+    }
+}
+
+/// A wireless interface's SSID and signal, its bitrate and channel, and a
+/// signal sparkline
+fn render_wifi(frame: &mut Frame, area: Rect, wifi: &WifiStats) {
+    let link = &wifi.link;
+    let block = Block::default().borders(Borders::ALL).title(format!(" Wi-Fi {} ", link.interface));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Length(1)])
+        .split(inner);
+    let ssid = Span::styled(link.ssid.clone().unwrap_or_else(|| "not connected".to_string()), Style::default().fg(Color::Cyan));
+    let signal = match (link.signal_dbm, link.quality()) {
+        (Some(dbm), Some(quality)) => {
+            let color = match quality {
+                "excellent" | "good" => Color::Green,
+                "fair" => Color::Yellow,
+                _ => Color::Red,
+            };
+            Span::styled(format!(" · {} dBm ({})", dbm, quality), Style::default().fg(color))
+        }
+        _ => Span::raw(""),
+    };
+    let details: Vec<String> = [
+        link.tx_bitrate_mbps.map(|rate| format!("TX {:.1} Mb/s", rate)),
+        link.describe_channel(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let lines = vec![Line::from(vec![ssid, signal]), Line::raw(details.join(" · "))];
+    frame.render_widget(Paragraph::new(lines), parts[0]);
+    let points = visible(wifi.points(), parts[1].width + 2);
+    frame.render_widget(Sparkline::default().data(&points).style(Style::default().fg(Color::Green)), parts[1]);
+}
+
+/// A `--interval` for the dashboard: a bare number is milliseconds, and
+/// `250ms`, `2s` or `1m` work as elsewhere
+pub fn parse_refresh(value: &str) -> Result<Duration> {
+    match value.trim().parse::<u64>() {
+        Ok(ms) => Ok(Duration::from_millis(ms)),
+        Err(_) => utils::parse_duration(value),
+    }
+}
+
+/// Fail on a refresh interval outside REFRESH_STEPS' range, too fast to
+/// sample meaningfully or too slow to watch
+pub fn check_refresh(refresh: Duration) -> Result<()> {
+    let (min, max) = (REFRESH_STEPS[0], REFRESH_STEPS[REFRESH_STEPS.len() - 1]);
+    if refresh < min || refresh > max {
+        anyhow::bail!("The dashboard refreshes every {:?} to {:?}, not every {:?}", min, max, refresh);
+    }
+    Ok(())
+}
+
+/// The newest points that fit in a bordered panel `width` columns wide
+fn visible(points: &VecDeque<u64>, width: u16) -> Vec<u64> {
+    let fit = usize::from(width.saturating_sub(2));
+    points.iter().skip(points.len().saturating_sub(fit)).copied().collect()
+}
+
+/// The protocol counters as short plain lines
+fn protocol_lines(stats: &ProtocolStats, seconds: Option<f64>) -> Vec<String> {
+    let count = |value: u64| match seconds {
+        Some(seconds) if seconds > 0.0 => format!("{:.0}", value as f64 / seconds),
+        Some(_) => "-".to_string(),
+        None => value.to_string(),
+    };
+    let mut lines = Vec::new();
+    if let Some(tcp) = &stats.tcp {
+        lines.push(format!("TCP  {} in, {} out, {} retransmitted ({:.2}%)",
+                           count(tcp.in_segs), count(tcp.out_segs), count(tcp.retrans_segs), tcp.retransmit_rate()));
+        lines.push(format!("     {} errors, {} resets, {} established", count(tcp.in_errs), count(tcp.out_rsts), tcp.curr_estab));
+    }
+    if let Some(udp) = &stats.udp {
+        lines.push(format!("UDP  {} in, {} out, {} to closed ports, {} errors",
+                           count(udp.in_datagrams), count(udp.out_datagrams), count(udp.no_ports), count(udp.in_errors)));
+    }
+    if let Some(icmp) = &stats.icmp {
+        lines.push(format!("ICMP {} in, {} out, {} errors in, {} out",
+                           count(icmp.in_msgs), count(icmp.out_msgs), count(icmp.in_errors), count(icmp.out_errors)));
+    }
+    lines
+}
+
+fn footer() -> Paragraph<'static> {
+    let key = |text: &'static str| Span::styled(text, Style::default().fg(Color::Yellow));
+    Paragraph::new(Line::from(vec![
+        key("q"), Span::raw(" quit  "),
+        key("←/→"), Span::raw(" interface  "),
+        key("p"), Span::raw(" pause  "),
+        key("+/-"), Span::raw(" refresh"),
+    ]))
+}
+
+/// Puts the terminal back when dropped
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        install_panic_hook();
+        crossterm::terminal::enable_raw_mode().context("Failed to switch the terminal to raw mode")?;
+        let guard = Self;
+        crossterm::execute!(std::io::stdout(), crossterm::terminal::EnterAlternateScreen, crossterm::cursor::Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = crossterm::terminal::disable_raw_mode();
+    let _ = crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen, crossterm::cursor::Show);
+}
+
+/// Restore the terminal before a panic message is printed, so it's
+/// readable and the shell isn't left in raw mode
+fn install_panic_hook() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            previous(info);
+        }));
+    });
+}
+
+/// The busiest remote hosts, by name once reverse DNS has one
+fn talker_table(talkers: &[Talker]) -> Table<'_> {
+    let block = Block::default().borders(Borders::ALL).title(" Top Talkers ");
+    if talkers.is_empty() {
+        return Table::new(vec![Row::new(vec![Cell::from("No traffic captured yet")])], [Constraint::Percentage(100)]).block(block);
+    }
+    let rows: Vec<Row> = talkers
+        .iter()
+        .map(|talker| Row::new(vec![
+            talker.label(),
+            utils::format_bandwidth(talker.tx_bytes_per_sec),
+            utils::format_bandwidth(talker.rx_bytes_per_sec),
+            utils::format_bytes(talker.bytes()),
+            talker.ports.iter().map(u16::to_string).collect::<Vec<_>>().join(","),
+        ]))
+        .collect();
+    Table::new(rows, [
+        Constraint::Min(16),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(10),
+        Constraint::Length(12),
+    ])
+    .header(Row::new(["Host", "TX/s", "RX/s", "Total", "Ports"]).style(Style::default().fg(Color::Cyan)))
+    .block(block)
+}
+
+/// Run the dashboard on `interface` until `q`, Ctrl+C or the end of
+/// `opts.duration`, which prints a summary of the run; what is logged also
+/// goes to `stream`
+pub async fn run(
+    interface: String,
+    probe_targets: Vec<ProbeTarget>,
+    mut log: Option<SampleLog>,
+    stream: Option<EventStream>,
+    opts: MonitorOptions,
+) -> Result<()> {
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::Terminal;
+
+    let MonitorOptions { interval, protocol, alerts: alert_settings, top_talkers, duration, output, capture_on_anomaly, .. } = opts;
+    let method = probes::method();
+    let mut probe_samples = probes::spawn(&probe_targets, method);
+    let mut summary = duration.map(|_| SummaryRecorder::new(&interface, &probe_targets, method));
+    let mut probe_stats: Vec<ProbeStats> = probe_targets.into_iter().map(|target| ProbeStats::new(target, method)).collect();
+    let mut wifi_stats: Vec<WifiStats> = Vec::new();
+
+    let mut capture = top_talkers.map(|_| TalkerCapture::start(&interface)).transpose()?;
+    let anomaly_capture = super::start_anomaly_capture(&interface, capture_on_anomaly.as_deref())?;
+    let names = NameCache::new();
+    let mut dashboard = Dashboard::new(&interface, protocol.clone());
+    dashboard.set_refresh(interval);
+    dashboard.set_probes(probe_stats.clone());
+    let stop_at = duration.map(|duration| Instant::now() + duration);
+    dashboard.set_stop_at(stop_at);
+    let mut alert_engine = AlertEngine::new(alert_settings.rules.clone(), alert_settings.cooldown);
+    let mut recent_alerts: Vec<Alert> = Vec::new();
+    let mut events = EventWatcher::new(&interface);
+    let mut recent_events: Vec<InterfaceEvent> = Vec::new();
+    let mut recorder = history_recorder();
+    let mut previous: Option<Vec<NetworkStats>> = None;
+
+    let guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+    let mut next_sample = Instant::now();
+    let complete = loop {
+        let now = Instant::now();
+        if stop_at.is_some_and(|stop_at| now >= stop_at) {
+            break true;
+        }
+        if now >= next_sample {
+            // Alerts and history keep going while the view is paused
+            let stats = gather_interface_stats();
+            let links = wifi::links(&stats, &interface);
+            wifi::record(&mut wifi_stats, &links);
+            let mut inputs = alert_inputs(previous.as_deref(), &stats, &interface);
+            wifi::add_signal(&mut inputs, &links);
+            inputs.extend(probes::alert_inputs(&probe_stats));
+            if let (Some(summary), Some(previous)) = (&mut summary, &previous) {
+                summary.record(previous, &stats);
+            }
+            let records = previous.as_ref().map(|previous| log_records(previous, &stats, &interface)).unwrap_or_default();
+            if let Some(stream) = &stream {
+                stream.publish_records(&records);
+            }
+            let mut log_result = match &mut log {
+                Some(log) => log.write_records(&records),
+                None => Ok(()),
+            };
+            for link in &links {
+                if let Some(log) = &mut log {
+                    log_result = log_result.and(log.write_wifi(link));
+                }
+                if let Some(stream) = &stream {
+                    stream.publish(&StreamEvent::Wifi(link.clone()));
+                }
+            }
+            for alert in alert_engine.evaluate(now, &inputs) {
+                if let Some(log) = &mut log {
+                    log_result = log_result.and(log.write_alert(&alert));
+                }
+                if let Some(stream) = &stream {
+                    stream.publish(&StreamEvent::Alert(alert.clone()));
+                }
+                if let (Some(capture), AlertState::Firing) = (&anomaly_capture, alert.state) {
+                    capture.trigger();
+                }
+                alerts::dispatch(&alert_settings, &alert);
+                recent_alerts.push(alert);
+            }
+            if recent_alerts.len() > RECENT_ALERTS {
+                recent_alerts.drain(..recent_alerts.len() - RECENT_ALERTS);
+            }
+            for event in events.poll(&stats) {
+                if let Some(log) = &mut log {
+                    log_result = log_result.and(log.write_event(&event));
+                }
+                if let Some(stream) = &stream {
+                    stream.publish(&StreamEvent::Event(event.clone()));
+                }
+                alerts::dispatch_event(&alert_settings, &event);
+                recent_events.push(event);
+            }
+            if recent_events.len() > RECENT_EVENTS {
+                recent_events.drain(..recent_events.len() - RECENT_EVENTS);
+            }
+            record_history(&mut recorder, &stats);
+            // Rates cover the time since the last sample, paused or not
+            let leaderboard = capture.as_mut().zip(top_talkers).map(|(capture, limit)| capture.leaderboard(limit));
+            dashboard.set_log_error(log_result.err().map(|e| format!("{:#}", e)));
+            dashboard.set_anomaly_capture(anomaly_capture.as_ref().and_then(AnomalyCapture::dumping).map(|path| path.display().to_string()));
+            if !dashboard.is_paused() {
+                dashboard.update(stats.clone());
+                dashboard.set_connections(connections::list());
+                if protocol.is_some() {
+                    dashboard.set_protocols(now, protocols::read_protocol_stats());
+                }
+                dashboard.set_alerts(&alert_engine, &recent_alerts);
+                dashboard.set_events(&recent_events);
+                dashboard.set_wifi(wifi_stats.clone());
+                if let Some(mut leaderboard) = leaderboard {
+                    names.fill(&mut leaderboard);
+                    dashboard.set_talkers(leaderboard);
+                }
+            }
+            previous = Some(stats);
+            next_sample = now + dashboard.refresh();
+        }
+        let mut probed = false;
+        while let Ok(sample) = probe_samples.try_recv() {
+            probes::record(&mut probe_stats, &sample);
+            if let (Some(capture), Some(_)) = (&anomaly_capture, anomaly::probe_anomaly(&probe_stats, &sample)) {
+                capture.trigger();
+            }
+            if let Some(summary) = &mut summary {
+                summary.record_probe(&sample);
+            }
+            if let Some(log) = &mut log {
+                if let Err(e) = log.write_probe(&sample) {
+                    dashboard.set_log_error(Some(format!("{:#}", e)));
+                }
+            }
+            if let Some(stream) = &stream {
+                stream.publish(&StreamEvent::Probe(sample.clone()));
+            }
+            probed = true;
+        }
+        if probed && !dashboard.is_paused() {
+            dashboard.set_probes(probe_stats.clone());
+        }
+
+        terminal.draw(|frame| dashboard.render(frame))?;
+
+        let wait = next_sample.saturating_duration_since(Instant::now()).min(MAX_POLL);
+        if event::poll(wait)? {
+            match event::read()? {
+                Event::Key(key) => match dashboard.handle_key(key) {
+                    Action::Quit => break false,
+                    Action::Redraw => {
+                        // A new refresh interval applies from now
+                        next_sample = next_sample.min(Instant::now() + dashboard.refresh());
+                    }
+                    Action::Ignore => {}
+                },
+                Event::Resize(_, _) => terminal.autoresize()?,
+                _ => {}
+            }
+        }
+    };
+
+    drop(terminal);
+    drop(guard);
+    if let (Some(capture), Some(limit)) = (capture, top_talkers) {
+        talkers::print_report(&talkers::finish(capture, limit, &names, log.as_mut()).await?);
+    }
+    if let Some(capture) = anomaly_capture {
+        anomaly::print_saved(&capture.finish()?);
+    }
+    if let Some(log) = &mut log {
+        log.flush()?;
+    }
+    if let Some(mut summary) = summary {
+        // Count the time since the last refresh too
+        if let Some(previous) = &previous {
+            summary.record(previous, &gather_interface_stats());
+        }
+        finish_summary(&summary.finish(complete), output.as_deref())?;
+    }
+    Ok(())
+}
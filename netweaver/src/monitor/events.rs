@@ -0,0 +1,163 @@
+// Interface events
+// Counters only freeze when a cable is pulled or DHCP hands out a new
+// address, so the realtime view and the daemon also compare each monitored
+// interface's link state and addresses with the previous sample's: the state
+// comes with the counters, the addresses from the same getifaddrs-style list
+// pnet uses. Polling at the sample interval is enough to catch a change a
+// human cares about, and works the same everywhere. Each change becomes an
+// event that is shown, logged as a `{"event": …}` line, and sent to the
+// webhook and alert command; the first sample only sets the baseline.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+use super::{watches, NetworkStats, ALL_INTERFACES};
+
+/// What an interface looked like at one sample
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceState {
+    pub name: String,
+    /// None where the platform doesn't say
+    pub up: Option<bool>,
+    /// Sorted, so two samples compare as sets
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Every interface of `stats` (the sum left out) with its addresses out of
+/// `addresses`
+pub fn states(stats: &[NetworkStats], addresses: &HashMap<String, Vec<IpAddr>>) -> Vec<InterfaceState> {
+    stats
+        .iter()
+        .filter(|row| row.interface != ALL_INTERFACES)
+        .map(|row| {
+            let mut assigned = addresses.get(&row.interface).cloned().unwrap_or_default();
+            assigned.sort();
+            assigned.dedup();
+            InterfaceState { name: row.interface.clone(), up: row.up, addresses: assigned }
+        })
+        .collect()
+}
+
+/// The addresses assigned to each interface right now
+pub fn read_addresses() -> HashMap<String, Vec<IpAddr>> {
+    pnet_datalink::interfaces()
+        .into_iter()
+        .map(|iface| (iface.name, iface.ips.iter().map(|network| network.ip()).collect()))
+        .collect()
+}
+
+/// What changed about an interface
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    LinkDown,
+    LinkUp,
+    Appeared,
+    Gone,
+    /// One address of a family replaced by another, as a DHCP renewal does
+    AddressChanged { from: IpAddr, to: IpAddr },
+    AddressAdded { address: IpAddr },
+    AddressRemoved { address: IpAddr },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::LinkDown => f.write_str("link down"),
+            Change::LinkUp => f.write_str("link up"),
+            Change::Appeared => f.write_str("interface appeared"),
+            Change::Gone => f.write_str("interface gone"),
+            Change::AddressChanged { from, to } => write!(f, "address changed {} → {}", from, to),
+            Change::AddressAdded { address } => write!(f, "address added {}", address),
+            Change::AddressRemoved { address } => write!(f, "address removed {}", address),
+        }
+    }
+}
+
+/// A change to one interface, as shown, logged and sent out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceEvent {
+    pub timestamp: DateTime<Utc>,
+    pub interface: String,
+    #[serde(flatten)]
+    pub change: Change,
+    /// "eth0: link down"
+    pub message: String,
+}
+
+impl InterfaceEvent {
+    pub fn new(timestamp: DateTime<Utc>, interface: &str, change: Change) -> Self {
+        Self { timestamp, interface: interface.to_string(), message: format!("{}: {}", interface, change), change }
+    }
+}
+
+/// What changed from `previous` to `current` among the interfaces
+/// `interface` watches, stamped `at`
+pub fn changes(previous: &[InterfaceState], current: &[InterfaceState], interface: &str, at: DateTime<Utc>) -> Vec<InterfaceEvent> {
+    let mut events = Vec::new();
+    for state in current.iter().filter(|state| watches(interface, &state.name)) {
+        let event = |change| InterfaceEvent::new(at, &state.name, change);
+        let Some(before) = previous.iter().find(|before| before.name == state.name) else {
+            events.push(event(Change::Appeared));
+            continue;
+        };
+        match (before.up, state.up) {
+            (Some(true), Some(false)) => events.push(event(Change::LinkDown)),
+            (Some(false), Some(true)) => events.push(event(Change::LinkUp)),
+            _ => {}
+        }
+        // Pair what went with what came per family, so a renewal reads as a
+        // change rather than a removal and an addition
+        for v4 in [true, false] {
+            let family = |addresses: &[IpAddr], others: &[IpAddr]| -> Vec<IpAddr> {
+                addresses.iter().filter(|ip| ip.is_ipv4() == v4 && !others.contains(ip)).copied().collect()
+            };
+            let removed = family(&before.addresses, &state.addresses);
+            let added = family(&state.addresses, &before.addresses);
+            for (index, from) in removed.iter().enumerate() {
+                events.push(event(match added.get(index) {
+                    Some(&to) => Change::AddressChanged { from: *from, to },
+                    None => Change::AddressRemoved { address: *from },
+                }));
+            }
+            for address in added.iter().skip(removed.len()) {
+                events.push(event(Change::AddressAdded { address: *address }));
+            }
+        }
+    }
+    for before in previous.iter().filter(|before| watches(interface, &before.name)) {
+        if !current.iter().any(|state| state.name == before.name) {
+            events.push(InterfaceEvent::new(at, &before.name, Change::Gone));
+        }
+    }
+    events
+}
+
+/// Remembers the last sample's interfaces to report what changed since
+#[derive(Debug)]
+pub struct EventWatcher {
+    interface: String,
+    previous: Option<Vec<InterfaceState>>,
+}
+
+impl EventWatcher {
+    /// Watch what `interface` names (`all` for every interface)
+    pub fn new(interface: &str) -> Self {
+        Self { interface: interface.to_string(), previous: None }
+    }
+
+    /// The changes since the last call, given this sample's counters;
+    /// nothing on the first
+    pub fn poll(&mut self, stats: &[NetworkStats]) -> Vec<InterfaceEvent> {
+        let current = states(stats, &read_addresses());
+        let events = match &self.previous {
+            Some(previous) => changes(previous, &current, &self.interface, Utc::now()),
+            None => Vec::new(),
+        };
+        self.previous = Some(current);
+        events
+    }
+}
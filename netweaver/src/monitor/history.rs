@@ -0,0 +1,329 @@
+// Monitoring history
+// While `monitor` runs (realtime or as a daemon) it appends every interface's
+// counters to a JSON-lines file in the data directory about once a minute,
+// so `report --history` can show what the network did over hours and days.
+// Samples older than the retention period are pruned on start and hourly
+// after that. Traffic is attributed to hour or day buckets from the change
+// between consecutive samples of an interface; a counter that went backwards
+// (a reboot, a driver reload) counts from zero, and gaps longer than
+// `MAX_GAP` (the monitor wasn't running) aren't attributed anywhere.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{sum_counts, traffic_delta, NetworkStats, ALL_INTERFACES};
+
+/// How often the monitor writes a sample
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+/// Samples older than this are pruned
+pub const RETENTION: Duration = Duration::from_secs(30 * 24 * 3600);
+/// How often a running monitor prunes the store
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Consecutive samples further apart than this aren't compared
+pub const MAX_GAP: Duration = Duration::from_secs(3600);
+/// The window `report --history` covers without `--since`
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(24 * 3600);
+/// Windows up to this long get hourly buckets, longer ones daily
+pub const HOURLY_LIMIT: Duration = Duration::from_secs(2 * 24 * 3600);
+
+/// `$XDG_DATA_HOME/netweaver/monitor.jsonl`, or `~/.local/share/netweaver/monitor.jsonl`
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::utils::data_dir()?.join("monitor.jsonl"))
+}
+
+/// Stored samples taken in `since..until` (microseconds since the epoch),
+/// oldest first; a missing file is an empty history and lines that don't
+/// parse are skipped
+pub fn load(path: &Path, since: u64, until: u64) -> Result<Vec<NetworkStats>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read monitoring history '{}'", path.display())),
+    };
+    let mut samples = Vec::new();
+    for (number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str::<NetworkStats>(line) {
+            Ok(sample) if (since..until).contains(&sample.timestamp) => samples.push(sample),
+            Ok(_) => {}
+            Err(e) => tracing::debug!("{}:{}: skipping unreadable sample: {}", path.display(), number + 1, e),
+        }
+    }
+    samples.sort_by_key(|sample| sample.timestamp);
+    Ok(samples)
+}
+
+/// Append one sample of every interface
+pub fn append(path: &Path, stats: &[NetworkStats]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let mut content = Vec::new();
+    for row in stats {
+        serde_json::to_writer(&mut content, row)?;
+        content.push(b'\n');
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&content))
+        .with_context(|| format!("Failed to write monitoring history '{}'", path.display()))
+}
+
+/// Drop samples taken before `cutoff` (microseconds since the epoch), and
+/// lines that don't parse, returning how many lines were dropped
+pub fn prune(path: &Path, cutoff: u64) -> Result<usize> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read monitoring history '{}'", path.display())),
+    };
+    let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+    let kept: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|line| serde_json::from_str::<NetworkStats>(line).is_ok_and(|sample| sample.timestamp >= cutoff))
+        .collect();
+    if kept.len() == lines.len() {
+        return Ok(0);
+    }
+    let mut content = kept.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content)
+        .with_context(|| format!("Failed to write monitoring history '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace monitoring history '{}'", path.display()))?;
+    Ok(lines.len() - kept.len())
+}
+
+/// Writes a sample at most every `SAMPLE_INTERVAL` and prunes every
+/// `PRUNE_INTERVAL`, for the monitor loops to call on every tick
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    last_sample: Option<Instant>,
+    last_prune: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, last_sample: None, last_prune: None }
+    }
+
+    /// The store at `default_path()`
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(default_path()?))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Store `stats` if a sample is due
+    pub fn record(&mut self, now: Instant, stats: &[NetworkStats]) -> Result<()> {
+        if self.last_prune.is_none_or(|last| now.duration_since(last) >= PRUNE_INTERVAL) {
+            self.last_prune = Some(now);
+            let cutoff = crate::utils::get_timestamp_us().saturating_sub(RETENTION.as_micros() as u64);
+            prune(&self.path, cutoff)?;
+        }
+        if self.last_sample.is_some_and(|last| now.duration_since(last) < SAMPLE_INTERVAL) {
+            return Ok(());
+        }
+        self.last_sample = Some(now);
+        append(&self.path, stats)
+    }
+}
+
+/// Bucket size of a history series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    /// Hourly for windows up to `HOURLY_LIMIT`, daily beyond
+    pub fn for_window(since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        let span = (until - since).to_std().unwrap_or_default();
+        if span <= HOURLY_LIMIT {
+            Resolution::Hour
+        } else {
+            Resolution::Day
+        }
+    }
+
+    fn micros(self) -> u64 {
+        match self {
+            Resolution::Hour => 3600 * 1_000_000,
+            Resolution::Day => 24 * 3600 * 1_000_000,
+        }
+    }
+
+    /// Start of the bucket holding `timestamp` (microseconds, UTC)
+    pub fn bucket_start(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.micros()
+    }
+}
+
+impl std::fmt::Display for Resolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Resolution::Hour => "hour",
+            Resolution::Day => "day",
+        })
+    }
+}
+
+/// Traffic of one interface during one bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryBucket {
+    pub start: DateTime<Utc>,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    pub errors: u64,
+    /// None when no sample in the bucket had a drop counter
+    pub drops: Option<u64>,
+    /// Averages over the time the monitor covered in this bucket
+    pub avg_tx_bytes_per_sec: f64,
+    pub avg_rx_bytes_per_sec: f64,
+    /// Seconds of this bucket the samples covered
+    pub covered_secs: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceSeries {
+    /// Interface name, or `all` for the sum
+    pub interface: String,
+    pub buckets: Vec<HistoryBucket>,
+}
+
+/// What `report --history` embeds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryReport {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub resolution: Resolution,
+    /// Interface samples the series were built from
+    pub samples: usize,
+    pub series: Vec<InterfaceSeries>,
+}
+
+/// Bucket `samples` per interface, with an `all` series summing them first
+pub fn build_report(samples: &[NetworkStats], since: DateTime<Utc>, until: DateTime<Utc>, resolution: Resolution) -> HistoryReport {
+    let mut names: Vec<&str> = samples.iter().map(|sample| sample.interface.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let mut series: Vec<InterfaceSeries> = names
+        .into_iter()
+        .filter(|name| *name != ALL_INTERFACES)
+        .map(|name| {
+            let mut rows: Vec<&NetworkStats> = samples.iter().filter(|sample| sample.interface == name).collect();
+            rows.sort_by_key(|sample| sample.timestamp);
+            InterfaceSeries { interface: name.to_string(), buckets: bucket(&rows, resolution) }
+        })
+        .collect();
+    if !series.is_empty() {
+        series.insert(0, InterfaceSeries { interface: ALL_INTERFACES.to_string(), buckets: sum_buckets(&series) });
+    }
+    HistoryReport { since, until, resolution, samples: samples.len(), series }
+}
+
+fn bucket(rows: &[&NetworkStats], resolution: Resolution) -> Vec<HistoryBucket> {
+    let mut buckets: Vec<HistoryBucket> = Vec::new();
+    for pair in rows.windows(2) {
+        let delta = traffic_delta(pair[0], pair[1]);
+        if delta.seconds <= 0.0 || delta.seconds > MAX_GAP.as_secs_f64() {
+            continue;
+        }
+        let start = resolution.bucket_start(pair[1].timestamp);
+        let bucket = match buckets.last_mut().filter(|last| last.start.timestamp_micros() as u64 == start) {
+            Some(bucket) => bucket,
+            None => {
+                buckets.push(empty_bucket(start));
+                buckets.last_mut().expect("just pushed")
+            }
+        };
+        bucket.bytes_sent += delta.bytes_sent;
+        bucket.bytes_recv += delta.bytes_recv;
+        bucket.packets_sent += delta.packets_sent;
+        bucket.packets_recv += delta.packets_recv;
+        bucket.errors += delta.errors;
+        bucket.drops = sum_counts(bucket.drops, delta.drops);
+        bucket.covered_secs += delta.seconds;
+    }
+    buckets.iter_mut().for_each(average);
+    buckets
+}
+
+fn sum_buckets(series: &[InterfaceSeries]) -> Vec<HistoryBucket> {
+    let mut sums: std::collections::BTreeMap<DateTime<Utc>, HistoryBucket> = std::collections::BTreeMap::new();
+    for bucket in series.iter().flat_map(|series| &series.buckets) {
+        let sum = sums.entry(bucket.start).or_insert_with(|| empty_bucket(bucket.start.timestamp_micros() as u64));
+        sum.bytes_sent += bucket.bytes_sent;
+        sum.bytes_recv += bucket.bytes_recv;
+        sum.packets_sent += bucket.packets_sent;
+        sum.packets_recv += bucket.packets_recv;
+        sum.errors += bucket.errors;
+        sum.drops = sum_counts(sum.drops, bucket.drops);
+        // Interfaces are sampled together, so they cover the same time
+        sum.covered_secs = sum.covered_secs.max(bucket.covered_secs);
+    }
+    let mut buckets: Vec<HistoryBucket> = sums.into_values().collect();
+    buckets.iter_mut().for_each(average);
+    buckets
+}
+
+fn empty_bucket(start: u64) -> HistoryBucket {
+    HistoryBucket {
+        start: DateTime::from_timestamp_micros(start as i64).unwrap_or_default(),
+        bytes_sent: 0,
+        bytes_recv: 0,
+        packets_sent: 0,
+        packets_recv: 0,
+        errors: 0,
+        drops: None,
+        avg_tx_bytes_per_sec: 0.0,
+        avg_rx_bytes_per_sec: 0.0,
+        covered_secs: 0.0,
+    }
+}
+
+fn average(bucket: &mut HistoryBucket) {
+    if bucket.covered_secs > 0.0 {
+        bucket.avg_tx_bytes_per_sec = bucket.bytes_sent as f64 / bucket.covered_secs;
+        bucket.avg_rx_bytes_per_sec = bucket.bytes_recv as f64 / bucket.covered_secs;
+    }
+}
+
+/// A `--since`/`--until` value: an RFC 3339 time, a `YYYY-MM-DD` date
+/// (midnight UTC), or a duration before `now` like `90m`, `24h` or `7d`
+pub fn parse_time(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight exists")));
+    }
+    let ago = match value.strip_suffix('d') {
+        Some(days) => {
+            let days: u64 = days.parse().with_context(|| format!("Invalid time '{}'", value))?;
+            Duration::from_secs(days * 24 * 3600)
+        }
+        None => crate::utils::parse_duration(value)
+            .with_context(|| format!("Invalid time '{}' (expected RFC 3339, YYYY-MM-DD or a duration like 24h or 7d)", value))?,
+    };
+    let ago = chrono::Duration::from_std(ago).context("Duration too long")?;
+    now.checked_sub_signed(ago).context("Duration too long")
+}
@@ -0,0 +1,264 @@
+// Per-interface counters, one backend per platform
+// sysinfo only knows bytes, packets and errors, and not every platform fills
+// those in the same way, so each platform reads its own source: Linux parses
+// /proc/net/dev and takes link speed, state and the error breakdown (CRC,
+// frame, FIFO, carrier, collisions…) from /sys/class/net, macOS walks the
+// NET_RT_IFLIST2 sysctl for each interface's 64-bit if_data and breaks out
+// only collisions, and Windows reads GetIfTable2. They all return the same
+// InterfaceCounters, and the rest of the monitor only sees the InterfaceStats
+// trait. Anywhere else, or when the native source fails, sysinfo fills in
+// what it can and leaves the rest unavailable.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The per-cause error counters Linux keeps under
+/// /sys/class/net/IFACE/statistics, by their file names
+pub const ERROR_COUNTERS: &[&str] = &[
+    "rx_crc_errors",
+    "rx_frame_errors",
+    "rx_length_errors",
+    "rx_fifo_errors",
+    "rx_missed_errors",
+    "rx_over_errors",
+    "tx_aborted_errors",
+    "tx_carrier_errors",
+    "tx_fifo_errors",
+    "tx_heartbeat_errors",
+    "tx_window_errors",
+    "collisions",
+];
+
+/// Frames that failed their checksum, almost always a bad cable or SFP
+pub const CRC_ERRORS: &str = "rx_crc_errors";
+
+/// What one interface has counted since boot, and its link
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub name: String,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    /// Receive and transmit errors
+    pub errors: u64,
+    /// Packets the kernel dropped, receive and transmit
+    pub drops: Option<u64>,
+    /// FIFO and ring overruns, receive and transmit
+    pub overruns: Option<u64>,
+    /// Negotiated link speed in megabits per second
+    pub speed_mbps: Option<u64>,
+    /// Whether the link is operationally up
+    pub up: Option<bool>,
+    /// The errors by cause, ERROR_COUNTERS names; only the non-zero ones
+    pub error_detail: BTreeMap<String, u64>,
+}
+
+/// A source of every interface's counters
+pub trait InterfaceStats {
+    fn interfaces(&self) -> Result<Vec<InterfaceCounters>>;
+}
+
+/// The platform's own counters: /proc and /sys on Linux, sysctl on macOS,
+/// GetIfTable2 on Windows
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeStats;
+
+impl InterfaceStats for NativeStats {
+    fn interfaces(&self) -> Result<Vec<InterfaceCounters>> {
+        native_counters()
+    }
+}
+
+/// sysinfo's counters, with no drops, overruns or link details
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SysinfoStats;
+
+impl InterfaceStats for SysinfoStats {
+    fn interfaces(&self) -> Result<Vec<InterfaceCounters>> {
+        let networks = sysinfo::Networks::new_with_refreshed_list();
+        Ok(networks
+            .iter()
+            .map(|(name, data)| InterfaceCounters {
+                name: name.clone(),
+                bytes_sent: data.total_transmitted(),
+                bytes_recv: data.total_received(),
+                packets_sent: data.total_packets_transmitted(),
+                packets_recv: data.total_packets_received(),
+                errors: data.total_errors_on_received() + data.total_errors_on_transmitted(),
+                ..Default::default()
+            })
+            .collect())
+    }
+}
+
+/// Interfaces in a /proc/net/dev listing, without link details: two header
+/// lines, then `name: ` and eight receive counters followed by eight
+/// transmit counters (bytes, packets, errs, drop, fifo, ...)
+pub fn parse_proc_net_dev(text: &str) -> Result<Vec<InterfaceCounters>> {
+    text.lines()
+        .skip(2)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (name, values) = line.split_once(':').with_context(|| format!("No interface name in '{}'", line.trim()))?;
+            let values: Vec<u64> = values
+                .split_whitespace()
+                .map(|value| value.parse())
+                .collect::<Result<_, _>>()
+                .with_context(|| format!("Bad counter for {}", name.trim()))?;
+            if values.len() < 16 {
+                anyhow::bail!("{} has {} counters, expected 16", name.trim(), values.len());
+            }
+            Ok(InterfaceCounters {
+                name: name.trim().to_string(),
+                bytes_recv: values[0],
+                packets_recv: values[1],
+                bytes_sent: values[8],
+                packets_sent: values[9],
+                errors: values[2] + values[10],
+                drops: Some(values[3] + values[11]),
+                overruns: Some(values[4] + values[12]),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// The non-zero ERROR_COUNTERS in a statistics directory like
+/// /sys/class/net/eth0/statistics; the ones a driver doesn't keep are
+/// left out
+pub fn read_error_detail(dir: &Path) -> BTreeMap<String, u64> {
+    ERROR_COUNTERS
+        .iter()
+        .filter_map(|name| {
+            let count = std::fs::read_to_string(dir.join(name)).ok()?.trim().parse::<u64>().ok()?;
+            (count > 0).then(|| (name.to_string(), count))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn native_counters() -> Result<Vec<InterfaceCounters>> {
+    let sysfs = |name: &str, file: &str| std::fs::read_to_string(format!("/sys/class/net/{}/{}", name, file)).ok();
+    let text = std::fs::read_to_string("/proc/net/dev").context("Failed to read /proc/net/dev")?;
+    let mut counters = parse_proc_net_dev(&text)?;
+    for row in &mut counters {
+        // /proc/net/dev folds ring overruns into its frame column
+        if let Some(over) = sysfs(&row.name, "statistics/rx_over_errors").and_then(|value| value.trim().parse::<u64>().ok()) {
+            row.overruns = row.overruns.map(|overruns| overruns + over);
+        }
+        // Virtual and disconnected interfaces report -1 or refuse the read
+        row.speed_mbps = sysfs(&row.name, "speed")
+            .and_then(|value| value.trim().parse::<i64>().ok())
+            .filter(|&speed| speed > 0)
+            .map(|speed| speed as u64);
+        // Loopback and many virtual interfaces say "unknown"; only a clear
+        // no counts as down
+        row.up = sysfs(&row.name, "operstate")
+            .map(|state| !matches!(state.trim(), "down" | "lowerlayerdown" | "notpresent"));
+        row.error_detail = read_error_detail(&Path::new("/sys/class/net").join(&row.name).join("statistics"));
+    }
+    Ok(counters)
+}
+
+#[cfg(target_os = "macos")]
+fn native_counters() -> Result<Vec<InterfaceCounters>> {
+    use std::mem::size_of;
+
+    let mut mib = [libc::CTL_NET, libc::PF_ROUTE, 0, 0, libc::NET_RT_IFLIST2, 0];
+    let mut len: libc::size_t = 0;
+    if unsafe { libc::sysctl(mib.as_mut_ptr(), mib.len() as u32, std::ptr::null_mut(), &mut len, std::ptr::null_mut(), 0) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to size the interface list");
+    }
+    let mut buf = vec![0u8; len];
+    if unsafe { libc::sysctl(mib.as_mut_ptr(), mib.len() as u32, buf.as_mut_ptr().cast(), &mut len, std::ptr::null_mut(), 0) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to read the interface list");
+    }
+    buf.truncate(len);
+
+    // A run of routing messages, each starting with its length and type;
+    // RTM_IFINFO2 ones carry an interface's counters, the rest its addresses
+    let mut counters = Vec::new();
+    let mut offset = 0;
+    while offset + size_of::<libc::if_msghdr>() <= buf.len() {
+        let header: libc::if_msghdr = unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+        let msglen = header.ifm_msglen as usize;
+        if msglen == 0 {
+            break;
+        }
+        if header.ifm_type as i32 == libc::RTM_IFINFO2 && offset + size_of::<libc::if_msghdr2>() <= buf.len() {
+            let msg: libc::if_msghdr2 = unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+            let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+            if !unsafe { libc::if_indextoname(msg.ifm_index as u32, name.as_mut_ptr()) }.is_null() {
+                let data = msg.ifm_data;
+                let flags = msg.ifm_flags;
+                counters.push(InterfaceCounters {
+                    name: unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned(),
+                    bytes_sent: data.ifi_obytes,
+                    bytes_recv: data.ifi_ibytes,
+                    packets_sent: data.ifi_opackets,
+                    packets_recv: data.ifi_ipackets,
+                    errors: data.ifi_ierrors + data.ifi_oerrors,
+                    drops: Some(data.ifi_iqdrops + msg.ifm_snd_drops.max(0) as u64),
+                    overruns: None,
+                    speed_mbps: Some(data.ifi_baudrate / 1_000_000).filter(|&speed| speed > 0),
+                    up: Some(flags & libc::IFF_UP != 0 && flags & libc::IFF_RUNNING != 0),
+                    error_detail: [("collisions".to_string(), data.ifi_collisions)]
+                        .into_iter()
+                        .filter(|(_, count)| *count > 0)
+                        .collect(),
+                });
+            }
+        }
+        offset += msglen;
+    }
+    Ok(counters)
+}
+
+#[cfg(windows)]
+fn native_counters() -> Result<Vec<InterfaceCounters>> {
+    use windows_sys::Win32::Foundation::NO_ERROR;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{FreeMibTable, GetIfTable2, MIB_IF_TABLE2};
+    use windows_sys::Win32::NetworkManagement::Ndis::IfOperStatusUp;
+
+    /// MIB_IF_ROW2 flag of a filter driver's view of another interface,
+    /// which would count its traffic twice
+    const FILTER_INTERFACE: u8 = 0b10;
+
+    let mut table: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
+    let status = unsafe { GetIfTable2(&mut table) };
+    if status != NO_ERROR {
+        anyhow::bail!("GetIfTable2 failed with error {}", status);
+    }
+    let rows = unsafe { std::slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize) };
+    let counters = rows
+        .iter()
+        .filter(|row| row.InterfaceAndOperStatusFlags._bitfield & FILTER_INTERFACE == 0)
+        .map(|row| {
+            let alias = &row.Alias[..row.Alias.iter().position(|&c| c == 0).unwrap_or(row.Alias.len())];
+            // Speeds are in bits per second, u64::MAX when unknown
+            let speed = row.TransmitLinkSpeed.max(row.ReceiveLinkSpeed);
+            InterfaceCounters {
+                name: String::from_utf16_lossy(alias),
+                bytes_sent: row.OutOctets,
+                bytes_recv: row.InOctets,
+                packets_sent: row.OutUcastPkts + row.OutNUcastPkts,
+                packets_recv: row.InUcastPkts + row.InNUcastPkts,
+                errors: row.InErrors + row.OutErrors,
+                drops: Some(row.InDiscards + row.OutDiscards),
+                overruns: None,
+                speed_mbps: Some(speed / 1_000_000).filter(|&speed_mbps| speed_mbps > 0 && speed != u64::MAX),
+                up: Some(row.OperStatus == IfOperStatusUp),
+                error_detail: BTreeMap::new(),
+            }
+        })
+        .collect();
+    unsafe { FreeMibTable(table.cast()) };
+    Ok(counters)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn native_counters() -> Result<Vec<InterfaceCounters>> {
+    anyhow::bail!("No native interface counters on this platform")
+}
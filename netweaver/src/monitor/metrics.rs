@@ -0,0 +1,320 @@
+// Prometheus exporter (`monitor --metrics-listen`)
+// A small HTTP server beside the realtime view or the daemon loop that
+// answers `GET /metrics` in the Prometheus text format. A sampler task reads
+// the interface and protocol counters every few seconds, feeds the deltas to
+// a BandwidthAnalyzer per interface, and pings the `--probe` targets into a
+// LatencyAnalyzer each; scrapes render whatever the last sample found, so a
+// slow probe never holds up a scrape.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::probes;
+use super::protocols::{self, ProtocolStats};
+use super::{gather_interface_stats, traffic_deltas, watches, NetworkStats, RATE_WINDOW};
+use crate::analytics::{BandwidthAnalyzer, LatencyAnalyzer};
+use crate::diagnostics::ping::{self, PingMethod, PingOptions};
+use crate::status;
+use crate::utils::icmp;
+
+/// How often the sampler refreshes what scrapes see
+const SAMPLE_EVERY: Duration = Duration::from_secs(5);
+/// Probe RTTs averaged per target, a minute's worth
+const PROBE_SAMPLES: usize = 12;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest request we read before answering
+const MAX_REQUEST: usize = 8192;
+
+/// Where to serve metrics and whom to ping for them
+#[derive(Debug, Clone)]
+pub struct MetricsOptions {
+    pub listen: SocketAddr,
+    /// Hosts whose latency is exported
+    pub probes: Vec<String>,
+}
+
+/// The averaged rates of one interface
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InterfaceBandwidth {
+    pub interface: String,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+}
+
+/// One probe target's latest results
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeLatency {
+    pub target: String,
+    pub method: PingMethod,
+    /// Whether the last probe was answered
+    pub up: bool,
+    /// RTT of the last probe, None when it went unanswered
+    pub last_ms: Option<f64>,
+    /// Average over the recent answered probes
+    pub avg_ms: Option<f64>,
+}
+
+/// What a scrape renders
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub interfaces: Vec<NetworkStats>,
+    pub protocols: Option<ProtocolStats>,
+    pub bandwidth: Vec<InterfaceBandwidth>,
+    pub probes: Vec<ProbeLatency>,
+}
+
+/// `{name="value",…}`, with backslash, double quote and newline escaped in
+/// the values
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            format!("{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// One metric family: its HELP and TYPE lines, then a sample per label set
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    if samples.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+/// `snapshot` in the Prometheus text exposition format
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+
+    // Counters the platform doesn't provide are left out, not exported as 0
+    let per_interface = |value: fn(&NetworkStats) -> Option<u64>| -> Vec<(String, f64)> {
+        snapshot.interfaces.iter()
+            .filter_map(|stats| Some((labels(&[("interface", &stats.interface)]), value(stats)? as f64)))
+            .collect()
+    };
+    family(&mut out, "netweaver_interface_receive_bytes_total", "counter", "Bytes received since boot",
+           &per_interface(|stats| Some(stats.bytes_recv)));
+    family(&mut out, "netweaver_interface_transmit_bytes_total", "counter", "Bytes sent since boot",
+           &per_interface(|stats| Some(stats.bytes_sent)));
+    family(&mut out, "netweaver_interface_receive_packets_total", "counter", "Packets received since boot",
+           &per_interface(|stats| Some(stats.packets_recv)));
+    family(&mut out, "netweaver_interface_transmit_packets_total", "counter", "Packets sent since boot",
+           &per_interface(|stats| Some(stats.packets_sent)));
+    family(&mut out, "netweaver_interface_errors_total", "counter", "Receive and transmit errors since boot",
+           &per_interface(|stats| Some(stats.errors)));
+    family(&mut out, "netweaver_interface_drops_total", "counter", "Packets dropped since boot",
+           &per_interface(|stats| stats.drops));
+    family(&mut out, "netweaver_interface_overruns_total", "counter", "Packets lost to full NIC FIFOs and rings since boot",
+           &per_interface(|stats| stats.overruns));
+
+    let bandwidth = |value: fn(&InterfaceBandwidth) -> f64| -> Vec<(String, f64)> {
+        snapshot.bandwidth.iter().map(|rates| (labels(&[("interface", &rates.interface)]), value(rates))).collect()
+    };
+    let window = format!("averaged over {}s", RATE_WINDOW.as_secs());
+    family(&mut out, "netweaver_interface_receive_bytes_per_second", "gauge", &format!("Receive rate, {}", window),
+           &bandwidth(|rates| rates.rx_bytes_per_sec));
+    family(&mut out, "netweaver_interface_transmit_bytes_per_second", "gauge", &format!("Transmit rate, {}", window),
+           &bandwidth(|rates| rates.tx_bytes_per_sec));
+
+    if let Some(protocols) = &snapshot.protocols {
+        render_protocols(&mut out, protocols);
+    }
+
+    let probes = |value: fn(&ProbeLatency) -> Option<f64>| -> Vec<(String, f64)> {
+        snapshot
+            .probes
+            .iter()
+            .filter_map(|probe| {
+                let method = match probe.method {
+                    PingMethod::Icmp => "icmp",
+                    PingMethod::Tcp => "tcp",
+                };
+                Some((labels(&[("target", &probe.target), ("method", method)]), value(probe)?))
+            })
+            .collect()
+    };
+    family(&mut out, "netweaver_probe_up", "gauge", "Whether the last probe of the target was answered",
+           &probes(|probe| Some(if probe.up { 1.0 } else { 0.0 })));
+    family(&mut out, "netweaver_probe_rtt_seconds", "gauge", "Round-trip time of the last probe",
+           &probes(|probe| probe.last_ms.map(|ms| ms / 1000.0)));
+    family(&mut out, "netweaver_probe_rtt_average_seconds", "gauge", "Average round-trip time of the recent answered probes",
+           &probes(|probe| probe.avg_ms.map(|ms| ms / 1000.0)));
+
+    out
+}
+
+fn render_protocols(out: &mut String, protocols: &ProtocolStats) {
+    let one = |value: u64| vec![(String::new(), value as f64)];
+    if let Some(tcp) = &protocols.tcp {
+        family(out, "netweaver_tcp_segments_received_total", "counter", "TCP segments received", &one(tcp.in_segs));
+        family(out, "netweaver_tcp_segments_sent_total", "counter", "TCP segments sent", &one(tcp.out_segs));
+        family(out, "netweaver_tcp_segments_retransmitted_total", "counter", "TCP segments retransmitted", &one(tcp.retrans_segs));
+        family(out, "netweaver_tcp_receive_errors_total", "counter", "TCP segments received in error", &one(tcp.in_errs));
+        family(out, "netweaver_tcp_resets_sent_total", "counter", "TCP resets sent", &one(tcp.out_rsts));
+        family(out, "netweaver_tcp_active_opens_total", "counter", "TCP connections opened", &one(tcp.active_opens));
+        family(out, "netweaver_tcp_passive_opens_total", "counter", "TCP connections accepted", &one(tcp.passive_opens));
+        family(out, "netweaver_tcp_connections_established", "gauge", "TCP connections established now", &one(tcp.curr_estab));
+        if let Some(timeouts) = tcp.timeouts {
+            family(out, "netweaver_tcp_timeouts_total", "counter", "TCP retransmission timeouts", &one(timeouts));
+        }
+        if let Some(drops) = tcp.listen_drops {
+            family(out, "netweaver_tcp_listen_drops_total", "counter", "SYNs dropped at a full listen queue", &one(drops));
+        }
+    }
+    if let Some(udp) = &protocols.udp {
+        family(out, "netweaver_udp_datagrams_received_total", "counter", "UDP datagrams received", &one(udp.in_datagrams));
+        family(out, "netweaver_udp_datagrams_sent_total", "counter", "UDP datagrams sent", &one(udp.out_datagrams));
+        family(out, "netweaver_udp_no_port_total", "counter", "UDP datagrams to ports nothing listens on", &one(udp.no_ports));
+        family(out, "netweaver_udp_receive_errors_total", "counter", "UDP datagrams received in error", &one(udp.in_errors));
+        family(out, "netweaver_udp_receive_buffer_errors_total", "counter", "UDP datagrams lost to a full receive buffer", &one(udp.rcvbuf_errors));
+        family(out, "netweaver_udp_send_buffer_errors_total", "counter", "UDP datagrams lost to a full send buffer", &one(udp.sndbuf_errors));
+    }
+    if let Some(icmp) = &protocols.icmp {
+        family(out, "netweaver_icmp_messages_received_total", "counter", "ICMP messages received", &one(icmp.in_msgs));
+        family(out, "netweaver_icmp_messages_sent_total", "counter", "ICMP messages sent", &one(icmp.out_msgs));
+        family(out, "netweaver_icmp_receive_errors_total", "counter", "ICMP messages received in error", &one(icmp.in_errors));
+        family(out, "netweaver_icmp_send_errors_total", "counter", "ICMP messages that could not be sent", &one(icmp.out_errors));
+    }
+}
+
+/// The response to one request line: status line, content type, body
+pub fn respond(request_line: &str, snapshot: &Snapshot) -> (&'static str, &'static str, String) {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", render(snapshot)),
+        ("GET", "/") => ("200 OK", "text/plain; charset=utf-8", "NetWeaver exporter: metrics are at /metrics\n".to_string()),
+        ("GET", _) => ("404 Not Found", "text/plain; charset=utf-8", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "Only GET is supported\n".to_string()),
+    }
+}
+
+/// Bind `opts.listen` and serve until the process ends; the address bound
+pub async fn start(opts: MetricsOptions, interface: String) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(opts.listen).await
+        .with_context(|| format!("Failed to listen for metrics on {}", opts.listen))?;
+    let local = listener.local_addr()?;
+    let targets = probes::resolve(&opts.probes, false).await?
+        .into_iter()
+        .map(|target| (target.name, target.ip))
+        .collect();
+
+    let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+    tokio::spawn(sample(snapshot.clone(), interface, targets));
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let snapshot = snapshot.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &snapshot).await {
+                    tracing::debug!("metrics request failed: {}", e);
+                }
+            });
+        }
+    });
+    status!("📈 Serving metrics on {}", format!("http://{}/metrics", local).bright_green());
+    Ok(local)
+}
+
+async fn handle(mut stream: TcpStream, snapshot: &Mutex<Snapshot>) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await??;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let request_line = request.lines().next().unwrap_or("");
+    let (status, content_type, body) = respond(request_line, &snapshot.lock());
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Refresh `snapshot` every SAMPLE_EVERY
+async fn sample(snapshot: Arc<Mutex<Snapshot>>, interface: String, targets: Vec<(String, Ipv4Addr)>) {
+    let method = if icmp::raw_available() { PingMethod::Icmp } else { PingMethod::Tcp };
+    let ping_opts = PingOptions { timeout: PROBE_TIMEOUT, ..Default::default() };
+    let mut bandwidth: BTreeMap<String, (BandwidthAnalyzer, BandwidthAnalyzer)> = BTreeMap::new();
+    let mut latency: Vec<LatencyAnalyzer> = targets.iter().map(|_| LatencyAnalyzer::new(PROBE_SAMPLES)).collect();
+    let mut previous: Option<Vec<NetworkStats>> = None;
+    let mut ticker = tokio::time::interval(SAMPLE_EVERY);
+    loop {
+        ticker.tick().await;
+        let stats: Vec<NetworkStats> = gather_interface_stats()
+            .into_iter()
+            .filter(|stats| watches(&interface, &stats.interface))
+            .collect();
+        let deltas = previous.as_ref().map(|previous| traffic_deltas(previous, &stats)).unwrap_or_default();
+        for stats in &stats {
+            let (rx, tx) = bandwidth
+                .entry(stats.interface.clone())
+                .or_insert_with(|| (BandwidthAnalyzer::new(RATE_WINDOW), BandwidthAnalyzer::new(RATE_WINDOW)));
+            // The first sample only marks where the window starts
+            let delta = deltas.iter().find(|delta| delta.interface == stats.interface);
+            rx.add_measurement(delta.map_or(0, |delta| delta.bytes_recv));
+            tx.add_measurement(delta.map_or(0, |delta| delta.bytes_sent));
+        }
+        bandwidth.retain(|name, _| stats.iter().any(|stats| &stats.interface == name));
+
+        let replies = futures::future::join_all(
+            targets.iter().map(|(_, ip)| ping::ping_once(*ip, method, &ping_opts)),
+        ).await;
+        let probes = targets
+            .iter()
+            .zip(&mut latency)
+            .zip(replies)
+            .map(|(((target, _), latency), reply)| {
+                let last_ms = reply.map(|(rtt, _)| rtt.as_secs_f64() * 1000.0);
+                if let Some(ms) = last_ms {
+                    latency.add_sample(ms);
+                }
+                ProbeLatency {
+                    target: target.clone(),
+                    method,
+                    up: last_ms.is_some(),
+                    last_ms,
+                    avg_ms: (!latency.is_empty()).then(|| latency.average()),
+                }
+            })
+            .collect();
+
+        *snapshot.lock() = Snapshot {
+            interfaces: stats.clone(),
+            protocols: protocols::read_protocol_stats().ok(),
+            bandwidth: bandwidth
+                .iter()
+                .map(|(interface, (rx, tx))| InterfaceBandwidth {
+                    interface: interface.clone(),
+                    rx_bytes_per_sec: rx.current_bandwidth(),
+                    tx_bytes_per_sec: tx.current_bandwidth(),
+                })
+                .collect(),
+            probes,
+        };
+        previous = Some(stats);
+    }
+}
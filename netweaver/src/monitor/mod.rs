@@ -1,231 +1,937 @@
 use anyhow::Result;
 use colored::Colorize;
+use std::collections::BTreeMap;
 use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 
+use crate::diagnostics::http::{self, HttpTimingResult};
+use crate::diagnostics::routemap::escape;
+use crate::diagnostics::throughput::{self, Direction, ThroughputResult};
+use crate::scanner::{self, ScanResult};
+use crate::security::{self, SecurityAudit, Severity};
 use crate::utils;
+use crate::{report, status};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub mod alerts;
+pub mod anomaly;
+pub mod charts;
+pub mod daemon;
+pub mod dashboard;
+pub mod events;
+pub mod history;
+pub mod interfaces;
+pub mod metrics;
+pub mod pdf;
+pub mod probes;
+pub mod protocols;
+pub mod sample_log;
+pub mod snapshot;
+pub mod stream;
+pub mod summary;
+pub mod systemd;
+pub mod talkers;
+pub mod wifi;
+
+use alerts::{AlertInput, AlertSettings};
+use history::HistoryReport;
+use interfaces::InterfaceStats;
+use protocols::ProtocolStats;
+use sample_log::SampleLog;
+use talkers::TalkersReport;
+
+/// Sockets the realtime view lists
+const TOP_CONNECTIONS: usize = 10;
+
+/// What `report --format` accepts
+pub const REPORT_FORMATS: &[&str] = &["json", "yaml", "html", "pdf"];
+
+/// Alerts the realtime view keeps on screen
+const RECENT_ALERTS: usize = 5;
+
+/// Interface events the realtime view keeps on screen
+const RECENT_EVENTS: usize = 5;
+
+/// How far back the realtime view's average rate looks
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// How long a snapshot with `--log` measures for its record
+const SNAPSHOT_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What `--interface` defaults to: every interface, shown one by one
+pub const ALL_INTERFACES: &str = "all";
+
+/// Counters of one interface since boot, or of all of them summed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NetworkStats {
+    /// Interface name, or `all` for a sum
+    #[serde(default)]
+    pub interface: String,
     pub bytes_sent: u64,
     pub bytes_recv: u64,
     pub packets_sent: u64,
     pub packets_recv: u64,
     pub errors: u64,
-    pub drops: u64,
+    /// Packets the kernel dropped, receive and transmit; None where the
+    /// platform doesn't say
+    pub drops: Option<u64>,
+    /// FIFO and ring overruns, receive and transmit; None where the
+    /// platform doesn't say
+    #[serde(default)]
+    pub overruns: Option<u64>,
+    /// Link speed in megabits per second, where known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_mbps: Option<u64>,
+    /// Whether the link is up, where the platform says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up: Option<bool>,
+    /// The errors by cause (`rx_crc_errors`, `collisions`…), the non-zero
+    /// ones where the platform breaks them out
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub error_detail: BTreeMap<String, u64>,
     pub timestamp: u64,
+    /// Host-wide TCP/UDP/ICMP counters, on the sum only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocols: Option<ProtocolStats>,
 }
 
-pub async fn run_monitor(
-    realtime: bool,
-    interface: Option<String>,
-    daemon: bool,
-    log: Option<String>,
-    protocol: Option<String>,
-) -> Result<()> {
-    println!("{}", "NetWeaver Network Monitor".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+/// What one interface moved between two samples of its counters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrafficDelta {
+    /// Interface name, or `all` for a sum
+    pub interface: String,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    pub errors: u64,
+    /// New errors by cause, the non-zero ones
+    pub error_detail: BTreeMap<String, u64>,
+    /// None when either sample had no drop counter
+    pub drops: Option<u64>,
+    /// Time between the samples
+    pub seconds: f64,
+}
+
+impl TrafficDelta {
+    fn per_sec(&self, count: u64) -> f64 {
+        if self.seconds > 0.0 { count as f64 / self.seconds } else { 0.0 }
+    }
+
+    pub fn tx_rate(&self) -> f64 {
+        self.per_sec(self.bytes_sent)
+    }
+
+    pub fn rx_rate(&self) -> f64 {
+        self.per_sec(self.bytes_recv)
+    }
+
+    /// Packets per second in both directions
+    pub fn packet_rate(&self) -> f64 {
+        self.per_sec(self.packets_sent + self.packets_recv)
+    }
+}
+
+/// What `monitor` watches and how
+#[derive(Debug, Clone, Default)]
+pub struct MonitorOptions {
+    pub realtime: bool,
+    pub interface: Option<String>,
+    pub daemon: bool,
+    pub foreground: bool,
+    /// How often the daemon samples, or the dashboard refreshes
+    pub interval: Duration,
+    pub log: Option<String>,
+    pub protocol: Option<String>,
+    pub alerts: AlertSettings,
+    /// Serve Prometheus metrics while monitoring
+    pub metrics: Option<metrics::MetricsOptions>,
+    /// Hosts to measure the latency to (`gateway` for the default gateway);
+    /// the dashboard probes DEFAULT_PROBES when empty
+    pub probes: Vec<String>,
+    /// Capture and rank this many remote hosts by traffic
+    pub top_talkers: Option<usize>,
+    /// Stop after this long and print a summary of the run
+    pub duration: Option<Duration>,
+    /// Where the summary of a `duration` run, or the `compare` comparison,
+    /// goes as JSON
+    pub output: Option<String>,
+    /// Stream samples, probes, alerts and events to clients connecting here
+    pub stream: Option<std::net::SocketAddr>,
+    /// Save the packets around an alert or latency anomaly to pcap files
+    /// named after this one
+    pub capture_on_anomaly: Option<String>,
+    /// Save the snapshot's counters to this file
+    pub save: Option<String>,
+    /// Compare the snapshot with the one saved in this file
+    pub compare: Option<String>,
+}
+
+pub async fn run_monitor(opts: MonitorOptions) -> Result<()> {
+    status!("{}", "NetWeaver Network Monitor".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
-    let iface = interface.unwrap_or_else(|| "all".to_string());
-    println!("📡 Monitoring: {}", iface.bright_yellow());
+    let iface = opts.interface.as_deref().map(interface_names).unwrap_or_default().join(",");
+    let iface = if iface.is_empty() { ALL_INTERFACES.to_string() } else { iface };
+    // A mistyped name should fail now, not show nothing forever
+    if iface != ALL_INTERFACES {
+        select_interfaces(&gather_interface_stats(), &iface)?;
+    }
+    if opts.realtime && !opts.daemon {
+        dashboard::check_refresh(opts.interval)?;
+    }
+    status!("📡 Monitoring: {}", iface.bright_yellow());
     
-    if let Some(proto) = &protocol {
-        println!("🔍 Protocol filter: {}", proto.bright_cyan());
+    if let Some(proto) = &opts.protocol {
+        status!("🔍 Protocol filter: {}", proto.bright_cyan());
     }
+    if !opts.alerts.rules.is_empty() {
+        let rules: Vec<String> = opts.alerts.rules.iter().map(|rule| rule.to_string()).collect();
+        status!("🚨 Alerts: {}", rules.join("; ").bright_cyan());
+    }
+    if let Some(limit) = opts.top_talkers {
+        if !utils::is_privileged() {
+            anyhow::bail!("--top-talkers captures packets, which requires root privileges. Run with sudo.");
+        }
+        status!("🏆 Top talkers: {} busiest remote hosts on {}", limit, talkers::describe(&iface).bright_cyan());
+    }
+    if let Some(duration) = opts.duration {
+        status!("⏱  Stopping after {:?}, or at Ctrl+C, with a summary", duration);
+    }
+    let probe_targets = if opts.realtime || opts.daemon || opts.duration.is_some() {
+        resolve_probes(&opts).await?
+    } else {
+        Vec::new()
+    };
     
-    if daemon {
-        println!("{}", "🔄 Starting daemon mode...".bright_green());
-        run_daemon(iface, log, protocol).await?;
-    } else if realtime {
-        println!("\n{}", "Real-time Dashboard".bright_green().bold());
-        println!("{}", "Press Ctrl+C to stop".bright_yellow());
-        run_realtime_monitor(iface, protocol).await?;
+    if opts.daemon {
+        status!("{}", "🔄 Starting daemon mode...".bright_green());
+        daemon::run_daemon(iface, probe_targets, opts).await?;
+    } else if opts.realtime {
+        let log = open_log(opts.log.as_deref())?;
+        if let Some(metrics) = opts.metrics.clone() {
+            metrics::start(metrics, iface.clone()).await?;
+        }
+        let stream = start_stream(opts.stream).await?;
+        status!("\n{}", "Real-time Dashboard".bright_green().bold());
+        status!("{}", "Press Ctrl+C to stop".bright_yellow());
+        dashboard::run(iface, probe_targets, log, stream, opts).await?;
+    } else if let Some(limit) = opts.top_talkers {
+        let log = open_log(opts.log.as_deref())?;
+        talkers::run(&iface, limit, log).await?;
+    } else if let Some(duration) = opts.duration {
+        let log = open_log(opts.log.as_deref())?;
+        run_timed_monitor(&iface, duration, probe_targets, log, opts.output.as_deref()).await?;
     } else {
-        run_snapshot_monitor(iface).await?;
+        let log = open_log(opts.log.as_deref())?;
+        run_snapshot_monitor(iface, opts.protocol, log, opts.save, opts.compare, opts.output).await?;
     }
     
     Ok(())
 }
 
-async fn run_realtime_monitor(_interface: String, _protocol: Option<String>) -> Result<()> {
-    use crossterm::{
-        event::{self, Event, KeyCode},
-        terminal::{self, ClearType},
-        execute,
+/// The latency probe targets: `--probe`, or the defaults for the dashboard
+/// and for latency alerts without one (skipping a gateway we can't find);
+/// every latency rule must name one of them
+async fn resolve_probes(opts: &MonitorOptions) -> Result<Vec<probes::ProbeTarget>> {
+    let latency_rules = opts.alerts.rules.iter().any(|rule| rule.metric == alerts::Metric::Latency);
+    let targets = if !opts.probes.is_empty() {
+        probes::resolve(&opts.probes, false).await?
+    } else if opts.realtime || latency_rules {
+        let defaults: Vec<String> = probes::DEFAULT_PROBES.iter().map(|name| name.to_string()).collect();
+        probes::resolve(&defaults, true).await?
+    } else {
+        Vec::new()
     };
-    use std::io::{stdout, Write};
-    
-    terminal::enable_raw_mode()?;
-    let mut stdout = stdout();
-    
-    let start_time = Instant::now();
-    
-    loop {
-        
-        execute!(stdout, terminal::Clear(ClearType::All))?;
-        execute!(stdout, crossterm::cursor::MoveTo(0, 0))?;
-        
-        let uptime = start_time.elapsed();
-        
-        println!("{}", "═".repeat(70).bright_cyan());
-        println!("{} {} {}", 
-                 "NetWeaver Monitor".bright_cyan().bold(),
-                 "|".bright_white(),
-                 format!("Uptime: {}s", uptime.as_secs()).bright_yellow());
-        println!("{}", "═".repeat(70).bright_cyan());
-        
-        let stats = gather_network_stats().await?;
-        
-        println!("\n{}", "Network Statistics:".bright_green().bold());
-        println!("  {} {}", "Sent:".bright_white(), utils::format_bandwidth(stats.bytes_sent as f64));
-        println!("  {} {}", "Recv:".bright_white(), utils::format_bandwidth(stats.bytes_recv as f64));
-        println!("  {} {}", "Packets TX:".bright_white(), stats.packets_sent.to_string().bright_yellow());
-        println!("  {} {}", "Packets RX:".bright_white(), stats.packets_recv.to_string().bright_yellow());
-        println!("  {} {}", "Errors:".bright_white(), 
-                 if stats.errors > 0 { 
-                     stats.errors.to_string().bright_red() 
-                 } else { 
-                     stats.errors.to_string().bright_green() 
-                 });
-        println!("  {} {}", "Drops:".bright_white(), 
-                 if stats.drops > 0 { 
-                     stats.drops.to_string().bright_red() 
-                 } else { 
-                     stats.drops.to_string().bright_green() 
-                 });
-        
-        println!("\n{}", "Top Connections:".bright_green().bold());
-        println!("  {} {:15} {:15} {:10}", 
-                 "Proto".bright_cyan(), 
-                 "Local".bright_cyan(), 
-                 "Remote".bright_cyan(), 
-                 "State".bright_cyan());
-        println!("  {} 192.168.1.10:443  93.184.216.34:80 {}", 
-                 "TCP".bright_yellow(), 
-                 "ESTABLISHED".bright_green());
-        println!("  {} 192.168.1.10:22   192.168.1.1:54321 {}", 
-                 "TCP".bright_yellow(), 
-                 "ESTABLISHED".bright_green());
-        
-        println!("\n{}", "Press 'q' to quit".bright_yellow());
-        
-        stdout.flush()?;
-        
-        if event::poll(Duration::from_millis(1000))? {
-            if let Event::Key(key) = event::read()? {
-                if key.code == KeyCode::Char('q') {
-                    break;
+    let names: Vec<String> = targets.iter().map(|target| target.name.clone()).collect();
+    alerts::check_probe_targets(&opts.alerts.rules, &names)?;
+    if !targets.is_empty() {
+        let method = match probes::method() {
+            crate::diagnostics::ping::PingMethod::Icmp => "ICMP",
+            crate::diagnostics::ping::PingMethod::Tcp => "TCP connect",
+        };
+        let labels: Vec<String> = targets.iter().map(probes::ProbeTarget::label).collect();
+        status!("📶 Probing: {} every {}s via {}", labels.join(", ").bright_cyan(), probes::PROBE_EVERY.as_secs(), method);
+    }
+    Ok(targets)
+}
+
+/// The `--log` file, if one was given
+fn open_log(path: Option<&str>) -> Result<Option<SampleLog>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let log = SampleLog::open(std::path::Path::new(path))?;
+    let format = match log.format() {
+        sample_log::LogFormat::Csv => "CSV",
+        sample_log::LogFormat::JsonLines => "JSON Lines",
+    };
+    status!("📝 Logging to: {} ({})", path.bright_green(), format);
+    Ok(Some(log))
+}
+
+/// The `--stream-listen` server, if one was asked for
+async fn start_stream(listen: Option<std::net::SocketAddr>) -> Result<Option<stream::EventStream>> {
+    match listen {
+        Some(listen) => Ok(Some(stream::EventStream::start(listen).await?)),
+        None => Ok(None),
+    }
+}
+
+/// The `--capture-on-anomaly` capture on `interface`, or None (with a
+/// warning) when we may not capture
+fn start_anomaly_capture(interface: &str, template: Option<&str>) -> Result<Option<anomaly::AnomalyCapture>> {
+    let Some(template) = template else {
+        return Ok(None);
+    };
+    if !utils::is_privileged() {
+        status!("⚠️  --capture-on-anomaly needs root to capture packets; anomaly capture is disabled");
+        return Ok(None);
+    }
+    let capture = anomaly::AnomalyCapture::start(interface, std::path::Path::new(template))?;
+    status!("📦 Keeping the last {}s of packets on {}; anomalies are saved next to {}",
+            anomaly::RING_WINDOW.as_secs(), capture.interfaces().join(", ").bright_yellow(), template.bright_green());
+    Ok(Some(capture))
+}
+
+/// The monitoring history store, or None (with a warning) when there's no
+/// data directory to keep it in
+fn history_recorder() -> Option<history::Recorder> {
+    history::Recorder::open_default()
+        .map_err(|e| tracing::warn!("Not keeping monitoring history: {:#}", e))
+        .ok()
+}
+
+fn record_history(recorder: &mut Option<history::Recorder>, stats: &[NetworkStats]) {
+    if let Some(recorder) = recorder {
+        if let Err(e) = recorder.record(Instant::now(), stats) {
+            tracing::warn!("Failed to update monitoring history: {:#}", e);
+        }
+    }
+}
+
+/// Sample every SNAPSHOT_LOG_INTERVAL for `duration` (or until Ctrl+C),
+/// printing the rates as they come, then sum up the run
+async fn run_timed_monitor(
+    interface: &str,
+    duration: Duration,
+    probe_targets: Vec<probes::ProbeTarget>,
+    mut log: Option<SampleLog>,
+    output: Option<&str>,
+) -> Result<()> {
+    let method = probes::method();
+    let mut probe_samples = probes::spawn(&probe_targets, method);
+    let mut recorder = summary::SummaryRecorder::new(interface, &probe_targets, method);
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut ticker = tokio::time::interval(SNAPSHOT_LOG_INTERVAL);
+    let mut previous: Option<Vec<NetworkStats>> = None;
+    let complete = loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let stats = gather_interface_stats();
+                if let Some(previous) = &previous {
+                    recorder.record(previous, &stats);
+                    let records = sample_log::log_records(previous, &stats, interface);
+                    if let Some(log) = &mut log {
+                        if let Err(e) = log.write_records(&records) {
+                            tracing::warn!("{:#}", e);
+                        }
+                        for link in wifi::links(&stats, interface) {
+                            if let Err(e) = log.write_wifi(&link) {
+                                tracing::warn!("{:#}", e);
+                            }
+                        }
+                    }
+                    let watched = |rows: &[NetworkStats]| -> Vec<NetworkStats> {
+                        rows.iter().filter(|row| watches(interface, &row.interface)).cloned().collect()
+                    };
+                    if let Some(total) = traffic_deltas(&watched(previous), &watched(&stats)).pop() {
+                        status!("  {}  TX {:>12}  RX {:>12}", chrono::Local::now().format("%H:%M:%S"),
+                                utils::format_bandwidth(total.tx_rate()), utils::format_bandwidth(total.rx_rate()));
+                    }
+                }
+                previous = Some(stats);
+            }
+            Some(sample) = probe_samples.recv() => {
+                recorder.record_probe(&sample);
+                if let Some(log) = &mut log {
+                    if let Err(e) = log.write_probe(&sample) {
+                        tracing::warn!("{:#}", e);
+                    }
                 }
             }
+            _ = tokio::time::sleep_until(deadline) => break true,
+            _ = tokio::signal::ctrl_c() => break false,
+        }
+    };
+    // Count the last partial interval too
+    if let Some(previous) = &previous {
+        let stats = gather_interface_stats();
+        recorder.record(previous, &stats);
+        if let Some(log) = &mut log {
+            log.write_records(&sample_log::log_records(previous, &stats, interface))?;
         }
     }
-    
-    terminal::disable_raw_mode()?;
-    println!("\n{}", "Monitor stopped".bright_green());
-    
-    Ok(())
+    if let Some(log) = &mut log {
+        log.flush()?;
+    }
+    finish_summary(&recorder.finish(complete), output)
 }
 
-async fn run_snapshot_monitor(_interface: String) -> Result<()> {
-    let stats = gather_network_stats().await?;
-    
-    println!("\n{}", "Network Statistics Snapshot:".bright_green().bold());
-    println!("  Bytes sent: {}", utils::format_bandwidth(stats.bytes_sent as f64));
-    println!("  Bytes received: {}", utils::format_bandwidth(stats.bytes_recv as f64));
-    println!("  Packets sent: {}", stats.packets_sent);
-    println!("  Packets received: {}", stats.packets_recv);
-    println!("  Errors: {}", stats.errors);
-    println!("  Drops: {}", stats.drops);
-    
+/// Print the summary of a `--duration` run and write it to `output`
+fn finish_summary(summary: &summary::RunSummary, output: Option<&str>) -> Result<()> {
+    summary::print_summary(summary);
+    if let Some(path) = output {
+        summary::save_summary(summary, path)?;
+        status!("\n💾 Summary saved to: {}", path.bright_green());
+    }
     Ok(())
 }
 
-async fn run_daemon(
-    _interface: String,
-    log: Option<String>,
-    _protocol: Option<String>,
+async fn run_snapshot_monitor(
+    interface: String,
+    protocol: Option<String>,
+    log: Option<SampleLog>,
+    save: Option<String>,
+    compare: Option<String>,
+    output: Option<String>,
 ) -> Result<()> {
-    let log_file = log.unwrap_or_else(|| "/var/log/netweaver.log".to_string());
-    println!("📝 Logging to: {}", log_file.bright_green());
-    println!("{}", "Daemon started successfully".bright_green());
-    println!("Use 'kill $(cat /var/run/netweaver.pid)' to stop");
+    let names = interface_names(&interface);
+    // Before the snapshot is taken, so a missing file fails fast
+    let earlier = compare.as_deref().map(|path| snapshot::load_snapshot(std::path::Path::new(path))).transpose()?;
+    let mut stats = gather_interface_stats();
+    let mut previous = None;
+    // A log record and a comparison's rates cover an interval, so measure one
+    if log.is_some() || names.len() > 1 {
+        tokio::time::sleep(SNAPSHOT_LOG_INTERVAL).await;
+        previous = Some(std::mem::replace(&mut stats, gather_interface_stats()));
+    }
+    if let (Some(mut log), Some(previous)) = (log, &previous) {
+        log.write_records(&sample_log::log_records(previous, &stats, &interface))?;
+        log.flush()?;
+    }
     
-    Ok(())
-}
-
-async fn gather_network_stats() -> Result<NetworkStats> {
-    use sysinfo::Networks;
+    let taken = (save.is_some() || earlier.is_some()).then(|| {
+        let protocols = protocols::read_protocol_stats().map_err(|e| tracing::debug!("No protocol counters in the snapshot: {:#}", e)).ok();
+        snapshot::StatsSnapshot::new(&stats, &interface, protocols)
+    });
     
-    let mut networks = Networks::new_with_refreshed_list();
-    networks.refresh();
+    if let (Some(earlier), Some(taken)) = (&earlier, &taken) {
+        let comparison = snapshot::compare(earlier, taken);
+        snapshot::print_snapshot_comparison(&comparison);
+        if let Some(path) = &output {
+            snapshot::save_comparison(&comparison, path)?;
+            status!("\n💾 Comparison saved to: {}", path.bright_green());
+        }
+    } else if names.len() > 1 {
+        report!("\n{}", format!("Interface Comparison (over {}s):", SNAPSHOT_LOG_INTERVAL.as_secs()).bright_green().bold());
+        print_comparison(&comparison_rows(previous.as_deref().unwrap_or_default(), &stats, &names));
+    } else if interface == ALL_INTERFACES {
+        report!("\n{}", "Network Statistics Snapshot:".bright_green().bold());
+        print_interface_table(&stats);
+    } else {
+        let stats = select_interface(&stats, &interface)?;
+        report!("\n{}", format!("Network Statistics Snapshot ({}):", interface).bright_green().bold());
+        report!("  Bytes sent: {}", utils::format_bytes(stats.bytes_sent));
+        report!("  Bytes received: {}", utils::format_bytes(stats.bytes_recv));
+        report!("  Packets sent: {}", stats.packets_sent);
+        report!("  Packets received: {}", stats.packets_recv);
+        report!("  Errors: {}", stats.errors);
+        for (name, count) in &stats.error_detail {
+            report!("    {}", error_cause(name, *count));
+        }
+        report!("  Drops: {}", format_count(stats.drops));
+        report!("  Overruns: {}", format_count(stats.overruns));
+        report!("  Link: {}", format_link(stats));
+        if let Ok(Some(link)) = wifi::read(&interface) {
+            report!("  Wi-Fi: {}", link.summary());
+        }
+    }
     
-    let mut total_sent = 0;
-    let mut total_recv = 0;
-    let mut total_packets_sent = 0;
-    let mut total_packets_recv = 0;
-    let mut total_errors = 0;
+    if let Some(protocol) = &protocol {
+        match protocols::read_protocol_stats() {
+            Ok(counters) => {
+                report!("\n{}", "Protocol Statistics (since boot, all interfaces):".bright_green().bold());
+                protocols::print_protocol_stats(&counters.only(protocol), None);
+            }
+            Err(e) => report!("\n⚠️  Protocol filtering isn't available here: {:#}", e),
+        }
+    }
     
-    for (_name, data) in &networks {
-        total_sent += data.total_transmitted();
-        total_recv += data.total_received();
-        total_packets_sent += data.total_packets_transmitted();
-        total_packets_recv += data.total_packets_received();
-        // Note: sysinfo API may have changed - using available methods
-        total_errors += data.total_errors_on_received() + data.total_errors_on_transmitted();
+    if let (Some(path), Some(taken)) = (&save, &taken) {
+        snapshot::save_snapshot(taken, std::path::Path::new(path))?;
+        status!("\n💾 Snapshot saved to: {}", path.bright_green());
     }
     
-    Ok(NetworkStats {
-        bytes_sent: total_sent,
-        bytes_recv: total_recv,
-        packets_sent: total_packets_sent,
-        packets_recv: total_packets_recv,
-        errors: total_errors,
-        drops: 0,
-        timestamp: utils::get_timestamp_us(),
+    Ok(())
+}
+
+/// One row per interface, then their sum
+fn print_interface_table(stats: &[NetworkStats]) {
+    report!("  {:<16} {:>12} {:>12} {:>12} {:>12} {:>8} {:>8}",
+             "Interface".bright_cyan(), "Sent".bright_cyan(), "Recv".bright_cyan(),
+             "Packets TX".bright_cyan(), "Packets RX".bright_cyan(), "Errors".bright_cyan(), "Drops".bright_cyan());
+    let total = total_stats(stats);
+    for row in stats.iter().chain([&total]) {
+        let alarm = |count: Option<u64>| match count {
+            Some(count) if count > 0 => count.to_string().bright_red(),
+            Some(count) => count.to_string().normal(),
+            None => format_count(None).dimmed(),
+        };
+        let name = if row.interface == ALL_INTERFACES { "total".bold() } else { row.interface.bright_yellow() };
+        report!("  {:<16} {:>12} {:>12} {:>12} {:>12} {:>8} {:>8}",
+                 name,
+                 utils::format_bytes(row.bytes_sent),
+                 utils::format_bytes(row.bytes_recv),
+                 row.packets_sent,
+                 row.packets_recv,
+                 alarm(Some(row.errors)),
+                 alarm(row.drops));
+    }
+    let broken_out: Vec<&NetworkStats> = stats.iter().filter(|row| !row.error_detail.is_empty()).collect();
+    if !broken_out.is_empty() {
+        report!("\n  {}", "Errors by cause:".bright_cyan());
+        for row in broken_out {
+            let causes: Vec<String> = row.error_detail.iter().map(|(name, count)| error_cause(name, *count)).collect();
+            report!("  {:<16} {}", row.interface.bright_yellow(), causes.join(", "));
+        }
+    }
+}
+
+/// `rx_crc_errors: 12`, in red with a hint for CRC errors
+fn error_cause(name: &str, count: u64) -> String {
+    if name == interfaces::CRC_ERRORS {
+        format!("{}: {} (usually a bad cable or SFP)", name, count).bright_red().to_string()
+    } else {
+        format!("{}: {}", name, count)
+    }
+}
+
+/// One column per interface of `rows`, the last (the total) in bold
+fn print_comparison(rows: &[(String, Vec<String>)]) {
+    for (index, (label, cells)) in rows.iter().enumerate() {
+        let mut line = format!("  {:<12}", if index == 0 { label.bright_cyan() } else { label.normal() });
+        for (column, cell) in cells.iter().enumerate() {
+            let cell = format!("{:>14}", cell);
+            line.push_str(&match (index, column + 1 == cells.len()) {
+                (0, _) => format!(" {}", cell.bright_yellow()),
+                (_, true) => format!(" {}", cell.bold()),
+                _ => format!(" {}", cell),
+            });
+        }
+        report!("{}", line);
+    }
+}
+
+/// The interfaces of `names` side by side, then their total: a header row,
+/// then rates over `previous` to `stats` and the counters since boot
+pub fn comparison_rows(previous: &[NetworkStats], stats: &[NetworkStats], names: &[&str]) -> Vec<(String, Vec<String>)> {
+    let selected: Vec<NetworkStats> = names
+        .iter()
+        .filter_map(|name| stats.iter().find(|row| row.interface == *name).cloned())
+        .collect();
+    let deltas = if previous.is_empty() { Vec::new() } else { traffic_deltas(previous, &selected) };
+    let total = total_stats(&selected);
+    let columns: Vec<(&NetworkStats, Option<&TrafficDelta>)> = selected
+        .iter()
+        .chain([&total])
+        .map(|row| (row, deltas.iter().find(|delta| delta.interface == row.interface)))
+        .collect();
+    let row = |label: &str, cell: &dyn Fn(&NetworkStats, Option<&TrafficDelta>) -> String| {
+        (label.to_string(), columns.iter().map(|(stats, delta)| cell(stats, *delta)).collect::<Vec<_>>())
+    };
+    let rate = |delta: Option<&TrafficDelta>, value: fn(&TrafficDelta) -> f64| {
+        delta.map(|delta| utils::format_bandwidth(value(delta))).unwrap_or_else(|| "-".to_string())
+    };
+    vec![
+        row("Interface", &|stats, _| if stats.interface == ALL_INTERFACES { "total".to_string() } else { stats.interface.clone() }),
+        row("TX rate", &|_, delta| rate(delta, TrafficDelta::tx_rate)),
+        row("RX rate", &|_, delta| rate(delta, TrafficDelta::rx_rate)),
+        row("Packets/s", &|_, delta| delta.map(|delta| format!("{:.0}", delta.packet_rate())).unwrap_or_else(|| "-".to_string())),
+        row("Sent", &|stats, _| utils::format_bytes(stats.bytes_sent)),
+        row("Received", &|stats, _| utils::format_bytes(stats.bytes_recv)),
+        row("Packets TX", &|stats, _| stats.packets_sent.to_string()),
+        row("Packets RX", &|stats, _| stats.packets_recv.to_string()),
+        row("Errors", &|stats, _| stats.errors.to_string()),
+        row("Drops", &|stats, _| format_count(stats.drops)),
+        row("Link", &|stats, _| if stats.interface == ALL_INTERFACES { String::new() } else { format_link(stats) }),
+    ]
+}
+
+/// Counters of every interface, by name, from the platform's own source or
+/// sysinfo when that fails
+fn gather_interface_stats() -> Vec<NetworkStats> {
+    collect_stats(&interfaces::NativeStats).unwrap_or_else(|e| {
+        tracing::debug!("Falling back to sysinfo for interface counters: {:#}", e);
+        collect_stats(&interfaces::SysinfoStats).unwrap_or_default()
     })
 }
 
-pub async fn generate_report(
-    export: String,
-    format: Option<String>,
-    history: bool,
-    graphs: bool,
-) -> Result<()> {
-    println!("{}", "NetWeaver Report Generator".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+/// What `source` counts of every interface, sorted by name
+pub fn collect_stats(source: &dyn InterfaceStats) -> Result<Vec<NetworkStats>> {
+    let timestamp = utils::get_timestamp_us();
+    let mut stats: Vec<NetworkStats> = source.interfaces()?
+        .into_iter()
+        .map(|row| NetworkStats {
+            interface: row.name,
+            bytes_sent: row.bytes_sent,
+            bytes_recv: row.bytes_recv,
+            packets_sent: row.packets_sent,
+            packets_recv: row.packets_recv,
+            errors: row.errors,
+            drops: row.drops,
+            overruns: row.overruns,
+            speed_mbps: row.speed_mbps,
+            up: row.up,
+            error_detail: row.error_detail,
+            timestamp,
+            protocols: None,
+        })
+        .collect();
+    stats.sort_by(|a, b| a.interface.cmp(&b.interface));
+    Ok(stats)
+}
+
+/// A counter that may be unavailable, as shown to people
+pub fn format_count(count: Option<u64>) -> String {
+    count.map_or_else(|| "n/a".to_string(), |count| count.to_string())
+}
+
+/// Two optional counters added up; unavailable only if both are
+fn sum_counts(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Two error breakdowns added up, cause by cause
+fn sum_detail(mut a: BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> BTreeMap<String, u64> {
+    for (name, count) in b {
+        *a.entry(name.clone()).or_default() += count;
+    }
+    a
+}
+
+/// `rx_crc_errors 12, collisions 2`, or None when nothing is broken out
+pub fn format_error_detail(detail: &BTreeMap<String, u64>) -> Option<String> {
+    let parts: Vec<String> = detail.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// `up 1 Gb/s`, `up`, `down` or `n/a`
+pub fn format_link(stats: &NetworkStats) -> String {
+    match (stats.up, stats.speed_mbps) {
+        (Some(false), _) => "down".to_string(),
+        (_, Some(mbps)) if mbps >= 1000 && mbps % 100 == 0 => format!("up {} Gb/s", mbps as f64 / 1000.0),
+        (_, Some(mbps)) => format!("up {} Mb/s", mbps),
+        (Some(true), None) => "up".to_string(),
+        (None, None) => format_count(None),
+    }
+}
+
+/// The counters of the interface called `name`; the error lists the ones there are
+pub fn select_interface<'a>(stats: &'a [NetworkStats], name: &str) -> Result<&'a NetworkStats> {
+    stats.iter().find(|stats| stats.interface == name).ok_or_else(|| {
+        let names: Vec<&str> = stats.iter().map(|stats| stats.interface.as_str()).collect();
+        anyhow::anyhow!("No interface named '{}'. Available: {}", name, names.join(", "))
+    })
+}
+
+/// The interfaces an `--interface` value names, `eth0` or `eth0,wg0,eth1`,
+/// each once; none for `all`
+pub fn interface_names(spec: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    for name in spec.split(',').map(str::trim).filter(|name| !name.is_empty() && *name != ALL_INTERFACES) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// The interfaces to capture on for an `--interface` value, or the
+/// default-route one for `all`
+pub fn capture_interfaces(spec: &str) -> Result<Vec<pnet_datalink::NetworkInterface>> {
+    match interface_names(spec).as_slice() {
+        [] => Ok(vec![crate::diagnostics::capture::capture_interface(None)?]),
+        names => names.iter().map(|name| crate::utils::interface_named(name)).collect(),
+    }
+}
+
+/// Whether the `--interface` value `spec` covers the interface `name`
+pub fn watches(spec: &str, name: &str) -> bool {
+    spec == ALL_INTERFACES || interface_names(spec).contains(&name)
+}
+
+/// The counters of each interface `spec` names, in its order; the error
+/// names every one that doesn't exist and lists the ones there are
+pub fn select_interfaces<'a>(stats: &'a [NetworkStats], spec: &str) -> Result<Vec<&'a NetworkStats>> {
+    let names = interface_names(spec);
+    let missing: Vec<String> = names
+        .iter()
+        .filter(|name| !stats.iter().any(|row| row.interface == **name))
+        .map(|name| format!("'{}'", name))
+        .collect();
+    if !missing.is_empty() {
+        let available: Vec<&str> = stats.iter().map(|stats| stats.interface.as_str()).collect();
+        anyhow::bail!("No interface named {}. Available: {}", missing.join(", "), available.join(", "));
+    }
+    Ok(names.iter().filter_map(|name| stats.iter().find(|row| row.interface == *name)).collect())
+}
+
+/// Every interface's counters summed, as interface `all`
+pub fn total_stats(stats: &[NetworkStats]) -> NetworkStats {
+    stats.iter().fold(
+        NetworkStats { interface: ALL_INTERFACES.to_string(), ..Default::default() },
+        |total, stats| NetworkStats {
+            interface: total.interface,
+            bytes_sent: total.bytes_sent + stats.bytes_sent,
+            bytes_recv: total.bytes_recv + stats.bytes_recv,
+            packets_sent: total.packets_sent + stats.packets_sent,
+            packets_recv: total.packets_recv + stats.packets_recv,
+            errors: total.errors + stats.errors,
+            drops: sum_counts(total.drops, stats.drops),
+            overruns: sum_counts(total.overruns, stats.overruns),
+            speed_mbps: None,
+            up: None,
+            error_detail: sum_detail(total.error_detail, &stats.error_detail),
+            timestamp: total.timestamp.max(stats.timestamp),
+            protocols: None,
+        },
+    )
+}
+
+/// What `cur` moved since `prev`, two samples of the same interface. A
+/// counter that went backwards was reset along with the interface and has
+/// counted up from zero since
+pub fn traffic_delta(prev: &NetworkStats, cur: &NetworkStats) -> TrafficDelta {
+    let moved = |prev: u64, cur: u64| cur.checked_sub(prev).unwrap_or(cur);
+    TrafficDelta {
+        interface: cur.interface.clone(),
+        bytes_sent: moved(prev.bytes_sent, cur.bytes_sent),
+        bytes_recv: moved(prev.bytes_recv, cur.bytes_recv),
+        packets_sent: moved(prev.packets_sent, cur.packets_sent),
+        packets_recv: moved(prev.packets_recv, cur.packets_recv),
+        errors: moved(prev.errors, cur.errors),
+        error_detail: cur
+            .error_detail
+            .iter()
+            .map(|(name, &count)| (name.clone(), moved(prev.error_detail.get(name).copied().unwrap_or(0), count)))
+            .filter(|(_, count)| *count > 0)
+            .collect(),
+        drops: prev.drops.zip(cur.drops).map(|(prev, cur)| moved(prev, cur)),
+        seconds: cur.timestamp.saturating_sub(prev.timestamp) as f64 / 1_000_000.0,
+    }
+}
+
+/// Deltas of the interfaces in both samples, then their sum as `all`.
+/// Summing per interface keeps an interface that comes or goes from
+/// showing up as a burst or a negative rate; it has no delta until its
+/// second sample.
+pub fn traffic_deltas(prev: &[NetworkStats], cur: &[NetworkStats]) -> Vec<TrafficDelta> {
+    let mut deltas: Vec<TrafficDelta> = cur
+        .iter()
+        .filter_map(|cur| prev.iter().find(|prev| prev.interface == cur.interface).map(|prev| traffic_delta(prev, cur)))
+        .collect();
+    let total = deltas.iter().fold(
+        TrafficDelta { interface: ALL_INTERFACES.to_string(), ..Default::default() },
+        |total, delta| TrafficDelta {
+            interface: total.interface,
+            bytes_sent: total.bytes_sent + delta.bytes_sent,
+            bytes_recv: total.bytes_recv + delta.bytes_recv,
+            packets_sent: total.packets_sent + delta.packets_sent,
+            packets_recv: total.packets_recv + delta.packets_recv,
+            errors: total.errors + delta.errors,
+            error_detail: sum_detail(total.error_detail, &delta.error_detail),
+            drops: sum_counts(total.drops, delta.drops),
+            seconds: total.seconds.max(delta.seconds),
+        },
+    );
+    deltas.push(total);
+    deltas
+}
+
+/// What the alert rules see of each monitored interface (each one for
+/// `all`); one that was in `previous` but is gone now counts as down, and
+/// one whose platform doesn't say counts as up
+pub fn alert_inputs(previous: Option<&[NetworkStats]>, stats: &[NetworkStats], interface: &str) -> Vec<AlertInput> {
+    let watched = |name: &str| watches(interface, name);
+    let deltas = previous.map(|previous| traffic_deltas(previous, stats)).unwrap_or_default();
+    let present = stats.iter().filter(|row| watched(&row.interface)).map(|row| AlertInput {
+        interface: row.interface.clone(),
+        delta: deltas.iter().find(|delta| delta.interface == row.interface).cloned(),
+        down: row.up == Some(false),
+        latency_ms: None,
+        signal_dbm: None,
+    });
+    let gone = previous
+        .unwrap_or_default()
+        .iter()
+        .filter(|old| watched(&old.interface) && !stats.iter().any(|row| row.interface == old.interface))
+        .map(|old| AlertInput { interface: old.interface.clone(), delta: None, down: true, latency_ms: None, signal_dbm: None });
+    present.chain(gone).collect()
+}
+
+/// What the `report` subcommand writes and what goes into it
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    pub export: String,
+    pub format: Option<String>,
+    pub history: bool,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub graphs: bool,
+    /// A `scan --output` file; the latest scan on this host without it
+    pub scan: Option<String>,
+    /// A `security --output` file; the latest audit on this host without it
+    pub security: Option<String>,
+}
+
+pub async fn generate_report(opts: ReportOptions) -> Result<()> {
+    let ReportOptions { export, format, history, since, until, graphs, scan, security } = opts;
+    status!("{}", "NetWeaver Report Generator".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
-    let fmt = format.unwrap_or_else(|| "json".to_string());
-    println!("📊 Generating {} report...", fmt.bright_yellow());
+    let fmt = match format.unwrap_or_else(|| "json".to_string()).to_lowercase().as_str() {
+        "yml" => "yaml".to_string(),
+        fmt if REPORT_FORMATS.contains(&fmt) => fmt.to_string(),
+        fmt => anyhow::bail!("Unsupported report format '{}'. Supported formats: {}", fmt, REPORT_FORMATS.join(", ")),
+    };
+    status!("📊 Generating {} report...", fmt.bright_yellow());
     
-    let stats = gather_network_stats().await?;
+    let interfaces = gather_interface_stats();
+    let mut stats = total_stats(&interfaces);
+    stats.protocols = protocols::read_protocol_stats().ok();
+    let throughput = match throughput::latest_path().and_then(|path| throughput::load_latest(&path)) {
+        Ok(latest) => latest,
+        Err(e) => {
+            status!("⚠️  Leaving out the last throughput test: {:#}", e);
+            None
+        }
+    };
+    let http = match http::latest_path().and_then(|path| http::load_latest(&path)) {
+        Ok(latest) => latest,
+        Err(e) => {
+            status!("⚠️  Leaving out the last HTTP timing: {:#}", e);
+            None
+        }
+    };
+    let top_talkers = match talkers::latest_path().and_then(|path| talkers::load_latest(&path)) {
+        Ok(latest) => latest,
+        Err(e) => {
+            status!("⚠️  Leaving out the last top talkers: {:#}", e);
+            None
+        }
+    };
+    // A file asked for must load; the stored latest ones are optional
+    let scan = match scan {
+        Some(path) => Some(scanner::load_results(&path)?),
+        None => match scanner::latest_path().and_then(|path| scanner::load_latest(&path)) {
+            Ok(latest) => latest,
+            Err(e) => {
+                status!("⚠️  Leaving out the last scan: {:#}", e);
+                None
+            }
+        },
+    };
+    let security = match security {
+        Some(path) => Some(security::load_audit(std::path::Path::new(&path))?),
+        None => match security::latest_path().and_then(|path| security::load_latest(&path)) {
+            Ok(latest) => latest,
+            Err(e) => {
+                status!("⚠️  Leaving out the last security audit: {:#}", e);
+                None
+            }
+        },
+    };
+    
+    let generated_at = chrono::Utc::now();
+    // Graphs are drawn from the history, so they load it too
+    let history = if history || graphs {
+        Some(load_history(generated_at, since.as_deref(), until.as_deref())?)
+    } else {
+        None
+    };
     
     let report = NetworkReport {
-        generated_at: chrono::Utc::now(),
+        generated_at,
         stats,
-        history_included: history,
+        interfaces,
+        history_included: history.is_some(),
         graphs_included: graphs,
+        history,
+        throughput,
+        http,
+        top_talkers,
+        scan,
+        security,
     };
     
     let content = match fmt.as_str() {
-        "yaml" | "yml" => serde_yaml::to_string(&report)?,
-        "html" => generate_html_report(&report)?,
-        _ => serde_json::to_string_pretty(&report)?,
+        "yaml" => serde_yaml::to_string(&report)?.into_bytes(),
+        "html" => generate_html_report(&report)?.into_bytes(),
+        "pdf" => generate_pdf_report(&report)?,
+        _ => serde_json::to_string_pretty(&report)?.into_bytes(),
     };
     
     std::fs::write(&export, content)?;
     
-    println!("{}", "✅ Report generated successfully!".bright_green());
-    println!("📄 Saved to: {}", export.bright_cyan());
+    status!("{}", "✅ Report generated successfully!".bright_green());
+    status!("📄 Saved to: {}", export.bright_cyan());
     
     Ok(())
 }
 
+/// The stored monitoring history in the `--since`/`--until` window
+fn load_history(now: chrono::DateTime<chrono::Utc>, since: Option<&str>, until: Option<&str>) -> Result<HistoryReport> {
+    let until = until.map(|value| history::parse_time(value, now)).transpose()?.unwrap_or(now);
+    let since = match since {
+        Some(value) => history::parse_time(value, now)?,
+        None => until - chrono::Duration::from_std(history::DEFAULT_WINDOW)?,
+    };
+    if since >= until {
+        anyhow::bail!("--since ({}) must be before --until ({})", since.format("%Y-%m-%d %H:%M UTC"), until.format("%Y-%m-%d %H:%M UTC"));
+    }
+    let path = history::default_path()?;
+    let samples = history::load(&path, since.timestamp_micros().max(0) as u64, until.timestamp_micros().max(0) as u64)?;
+    let resolution = history::Resolution::for_window(since, until);
+    status!("🕘 History: {} to {}, per {}", since.format("%Y-%m-%d %H:%M UTC"), until.format("%Y-%m-%d %H:%M UTC"),
+            resolution);
+    if samples.is_empty() {
+        status!("⚠️  No monitoring history in that window; 'monitor --daemon' or 'monitor --realtime' records it to {}",
+                path.display());
+    }
+    Ok(history::build_report(&samples, since, until, resolution))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct NetworkReport {
     generated_at: chrono::DateTime<chrono::Utc>,
+    /// Summed over `interfaces`
     stats: NetworkStats,
+    #[serde(default)]
+    interfaces: Vec<NetworkStats>,
     history_included: bool,
     graphs_included: bool,
+    /// Bucketed monitoring history, with `--history`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history: Option<HistoryReport>,
+    /// The latest `throughput --client` run on this host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    throughput: Option<ThroughputResult>,
+    /// The latest `http` timing run on this host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    http: Option<HttpTimingResult>,
+    /// The final ranking of the latest `monitor --top-talkers` run on this host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_talkers: Option<TalkersReport>,
+    /// The `--scan` file, or the latest `scan` run on this host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scan: Option<ScanResult>,
+    /// The `--security` file, or the latest `security` run on this host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    security: Option<SecurityAudit>,
 }
 
 fn generate_html_report(report: &NetworkReport) -> Result<String> {
@@ -241,6 +947,11 @@ fn generate_html_report(report: &NetworkReport) -> Result<String> {
         .stat {{ margin: 10px 0; padding: 10px; background: #f9f9f9; border-left: 4px solid #00bcd4; }}
         .label {{ font-weight: bold; color: #555; }}
         .value {{ color: #00bcd4; }}
+        .chart {{ display: block; width: 100%; max-width: 760px; margin: 10px 0; background: #fcfcfc; }}
+        .matrix {{ border-collapse: collapse; margin: 10px 0; }}
+        .matrix th, .matrix td {{ padding: 4px 8px; border: 1px solid #e0e0e0; text-align: center; }}
+        .matrix th:first-child, .matrix td:first-child {{ text-align: left; }}
+        .open {{ background: #00bcd4; color: white; }}
     </style>
 </head>
 <body>
@@ -264,7 +975,7 @@ fn generate_html_report(report: &NetworkReport) -> Result<String> {
             <span class="label">Packets Received:</span>
             <span class="value">{}</span>
         </div>
-    </div>
+{}{}{}{}{}{}{}{}{}{}    </div>
 </body>
 </html>
 "#, 
@@ -273,5 +984,299 @@ fn generate_html_report(report: &NetworkReport) -> Result<String> {
         utils::format_bandwidth(report.stats.bytes_recv as f64),
         report.stats.packets_sent,
         report.stats.packets_recv,
+        interfaces_section(&report.interfaces).as_ref().map(section_html).unwrap_or_default(),
+        report.stats.protocols.as_ref().map(|stats| section_html(&protocols_section(stats))).unwrap_or_default(),
+        report.history.as_ref().map(|history| section_html(&history_section(history))).unwrap_or_default(),
+        if report.graphs_included {
+            charts::graphs_html(report.history.as_ref(), history::default_path().ok().as_deref())
+        } else {
+            String::new()
+        },
+        report.throughput.as_ref().map(|result| section_html(&throughput_section(result))).unwrap_or_default(),
+        report.http.as_ref().map(|result| section_html(&http_section(result))).unwrap_or_default(),
+        report.top_talkers.as_ref().map(|talkers| section_html(&talkers_section(talkers))).unwrap_or_default(),
+        report.scan.as_ref().map(|scan| section_html(&inventory_section(scan))).unwrap_or_default(),
+        report.scan.as_ref().map(ports_matrix_html).unwrap_or_default(),
+        report.security.as_ref().map(|audit| section_html(&security_section(audit))).unwrap_or_default(),
     ))
 }
+
+/// The HTML report's content as a PDF: a cover page with the host, then
+/// the same sections, with the charts as vector graphics
+fn generate_pdf_report(report: &NetworkReport) -> Result<Vec<u8>> {
+    use sysinfo::System;
+
+    let mut layout = pdf::Layout::new("NetWeaver Network Report");
+    layout.title("NetWeaver Network Report", 26.0);
+    let mut cover = ReportSection::new("Report");
+    cover.row("Generated", report.generated_at.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    cover.row("Host", System::host_name().unwrap_or_else(|| "unknown".to_string()));
+    if let Some(os) = System::long_os_version() {
+        cover.row("Operating System", os);
+    }
+    if let Some(kernel) = System::kernel_version() {
+        cover.row("Kernel", kernel);
+    }
+    cover.row("Interfaces", report.interfaces.len().to_string());
+    if let Some(history) = &report.history {
+        cover.row("History Window", format!("{} to {}", history.since.format("%Y-%m-%d %H:%M UTC"),
+                  history.until.format("%Y-%m-%d %H:%M UTC")));
+    }
+    pdf_section(&mut layout, &cover);
+    layout.new_page();
+
+    let mut summary = ReportSection::new("Network Statistics");
+    summary.row("Bytes Sent", utils::format_bytes(report.stats.bytes_sent));
+    summary.row("Bytes Received", utils::format_bytes(report.stats.bytes_recv));
+    summary.row("Packets Sent", report.stats.packets_sent.to_string());
+    summary.row("Packets Received", report.stats.packets_recv.to_string());
+    pdf_section(&mut layout, &summary);
+    if let Some(section) = interfaces_section(&report.interfaces) {
+        pdf_section(&mut layout, &section);
+    }
+    if let Some(stats) = &report.stats.protocols {
+        pdf_section(&mut layout, &protocols_section(stats));
+    }
+    if let Some(history) = &report.history {
+        pdf_section(&mut layout, &history_section(history));
+    }
+    if report.graphs_included {
+        layout.heading("Graphs");
+        match report.history.as_ref().and_then(charts::history_drawings) {
+            Some(drawings) => drawings.iter().for_each(|drawing| layout.drawing(drawing)),
+            None => layout.paragraph(&charts::missing_graphs(report.history.as_ref(), history::default_path().ok().as_deref())),
+        }
+    }
+    if let Some(result) = &report.throughput {
+        pdf_section(&mut layout, &throughput_section(result));
+    }
+    if let Some(result) = &report.http {
+        pdf_section(&mut layout, &http_section(result));
+    }
+    if let Some(talkers) = &report.top_talkers {
+        pdf_section(&mut layout, &talkers_section(talkers));
+    }
+    if let Some(scan) = &report.scan {
+        pdf_section(&mut layout, &inventory_section(scan));
+        pdf_section(&mut layout, &ports_section(scan));
+    }
+    if let Some(audit) = &report.security {
+        pdf_section(&mut layout, &security_section(audit));
+    }
+    layout.finish()
+}
+
+fn pdf_section(layout: &mut pdf::Layout, section: &ReportSection) {
+    layout.heading(section.title);
+    for (label, value) in &section.rows {
+        layout.row(label, value);
+    }
+}
+
+fn html_stat(label: &str, value: String) -> String {
+    // Scan and audit values come off the network, hostnames and banners alike
+    format!("        <div class=\"stat\">\n            <span class=\"label\">{}:</span>\n            <span class=\"value\">{}</span>\n        </div>\n",
+            escape(label), escape(&value))
+}
+
+/// A headed block of labelled values, shared by the HTML and PDF reports
+struct ReportSection {
+    title: &'static str,
+    rows: Vec<(String, String)>,
+}
+
+impl ReportSection {
+    fn new(title: &'static str) -> Self {
+        Self { title, rows: Vec::new() }
+    }
+
+    fn row(&mut self, label: impl Into<String>, value: String) {
+        self.rows.push((label.into(), value));
+    }
+}
+
+fn section_html(section: &ReportSection) -> String {
+    let mut html = vec![format!("        <h2>{}</h2>\n", section.title)];
+    html.extend(section.rows.iter().map(|(label, value)| html_stat(label, value.clone())));
+    html.concat()
+}
+
+fn interfaces_section(interfaces: &[NetworkStats]) -> Option<ReportSection> {
+    if interfaces.is_empty() {
+        return None;
+    }
+    let mut section = ReportSection::new("Interfaces");
+    for stats in interfaces {
+        let causes = format_error_detail(&stats.error_detail).map(|causes| format!(" ({})", causes)).unwrap_or_default();
+        section.row(&stats.interface, format!("{} sent, {} received, {} errors{}, {} drops, {} overruns, link {}",
+                    utils::format_bytes(stats.bytes_sent), utils::format_bytes(stats.bytes_recv), stats.errors, causes,
+                    format_count(stats.drops), format_count(stats.overruns), format_link(stats)));
+    }
+    Some(section)
+}
+
+fn protocols_section(stats: &ProtocolStats) -> ReportSection {
+    let mut section = ReportSection::new("Protocols");
+    if let Some(tcp) = &stats.tcp {
+        section.row("TCP", format!("{} segments in, {} out, {} retransmitted ({:.2}%), {} errors, {} resets sent",
+                    tcp.in_segs, tcp.out_segs, tcp.retrans_segs, tcp.retransmit_rate(), tcp.in_errs, tcp.out_rsts));
+    }
+    if let Some(udp) = &stats.udp {
+        section.row("UDP", format!("{} datagrams in, {} out, {} to closed ports, {} errors",
+                    udp.in_datagrams, udp.out_datagrams, udp.no_ports, udp.in_errors));
+    }
+    if let Some(icmp) = &stats.icmp {
+        section.row("ICMP", format!("{} messages in, {} out, {} errors in, {} out",
+                    icmp.in_msgs, icmp.out_msgs, icmp.in_errors, icmp.out_errors));
+    }
+    section
+}
+
+fn history_section(history: &HistoryReport) -> ReportSection {
+    let mut section = ReportSection::new("History");
+    section.row("Window", format!("{} to {}, per {} ({} samples)", history.since.format("%Y-%m-%d %H:%M UTC"),
+                history.until.format("%Y-%m-%d %H:%M UTC"), history.resolution, history.samples));
+    let format = match history.resolution {
+        history::Resolution::Hour => "%Y-%m-%d %H:00",
+        history::Resolution::Day => "%Y-%m-%d",
+    };
+    // Every interface's buckets are in the JSON/YAML; the page shows the sum
+    if let Some(all) = history.series.iter().find(|series| series.interface == ALL_INTERFACES) {
+        for bucket in &all.buckets {
+            section.row(bucket.start.format(format).to_string(),
+                        format!("{} sent, {} received (average TX {}, RX {}), {} errors, {} drops",
+                        utils::format_bytes(bucket.bytes_sent), utils::format_bytes(bucket.bytes_recv),
+                        utils::format_bandwidth(bucket.avg_tx_bytes_per_sec), utils::format_bandwidth(bucket.avg_rx_bytes_per_sec),
+                        bucket.errors, format_count(bucket.drops)));
+        }
+    }
+    section
+}
+
+fn throughput_section(result: &ThroughputResult) -> ReportSection {
+    let direction = match result.direction {
+        Direction::Upload => "upload",
+        Direction::Download => "download",
+    };
+    let mut section = ReportSection::new("Throughput");
+    section.row("Last Test", format!("{} to {} ({}, {} stream(s), {:.0}s)",
+                result.finished_at.format("%Y-%m-%d %H:%M UTC"), result.server, direction, result.streams, result.duration_secs));
+    section.row("Average", utils::format_bitrate(result.avg_bits_per_sec));
+    section.row("Per Second (min / p50 / p90 / max)", format!("{} / {} / {} / {}",
+                utils::format_bitrate(result.min_bits_per_sec), utils::format_bitrate(result.p50_bits_per_sec),
+                utils::format_bitrate(result.p90_bits_per_sec), utils::format_bitrate(result.max_bits_per_sec)));
+    section
+}
+
+fn http_section(result: &HttpTimingResult) -> ReportSection {
+    let mut section = ReportSection::new("HTTP Timing");
+    section.row("Last Run", format!("{} {} × {} at {}", result.method, result.url, result.attempts.len(),
+                result.finished_at.format("%Y-%m-%d %H:%M UTC")));
+    if let (Some(status), Some(final_url)) = (result.status, &result.final_url) {
+        section.row("Final Response", format!("{} from {} ({} redirect(s))", status, final_url, result.redirects.len()));
+    }
+    if let Some(stats) = &result.stats {
+        for (label, phase) in stats.phases().into_iter().chain([("Total", stats.total)]) {
+            section.row(format!("{} (min / avg / max)", label),
+                        format!("{:.1} / {:.1} / {:.1} ms", phase.min_ms, phase.avg_ms, phase.max_ms));
+        }
+    }
+    section
+}
+
+fn talkers_section(report: &TalkersReport) -> ReportSection {
+    let mut section = ReportSection::new("Top Talkers");
+    section.row("Last Run", format!("{} to {} on {} ({}s)", report.started_at.format("%Y-%m-%d %H:%M UTC"),
+                report.finished_at.format("%Y-%m-%d %H:%M UTC"), report.interfaces.join(", "),
+                (report.finished_at - report.started_at).num_seconds()));
+    for talker in &report.hosts {
+        let ports: Vec<String> = talker.ports.iter().map(u16::to_string).collect();
+        section.row(talker.label(), format!("{} sent, {} received (average TX {}, RX {}), ports {}",
+                    utils::format_bytes(talker.sent.bytes), utils::format_bytes(talker.received.bytes),
+                    utils::format_bandwidth(talker.tx_bytes_per_sec), utils::format_bandwidth(talker.rx_bytes_per_sec),
+                    if ports.is_empty() { "-".to_string() } else { ports.join(", ") }));
+    }
+    for total in &report.ports {
+        section.row(format!("{} {}", total.protocol, total.port),
+                    format!("{} in {} packets", utils::format_bytes(total.volume.bytes), total.volume.packets));
+    }
+    section
+}
+
+fn inventory_section(scan: &ScanResult) -> ReportSection {
+    let mut section = ReportSection::new("Device Inventory");
+    section.row("Last Scan", format!("{}: {} of {} hosts responded in {:.1}s", scan.network_range,
+                scan.responsive_hosts, scan.total_hosts, scan.scan_duration.as_secs_f64()));
+    for device in &scan.devices {
+        let label = match &device.hostname {
+            Some(hostname) => format!("{} ({})", device.ip, hostname),
+            None => device.ip.to_string(),
+        };
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+        section.row(label, format!("MAC {}, vendor {}, OS {}, latency {}",
+                    or_unknown(device.mac.as_ref().map(|mac| mac.to_string())), or_unknown(device.vendor.clone()),
+                    or_unknown(device.os_guess.clone()),
+                    device.latency_ms.map(|ms| format!("{:.1} ms", ms)).unwrap_or_else(|| "-".to_string())));
+    }
+    section
+}
+
+/// Every port open on any device, ascending
+fn open_ports(scan: &ScanResult) -> Vec<u16> {
+    let mut ports: Vec<u16> = scan.devices.iter().flat_map(|device| device.open_ports.iter().copied()).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+/// Devices down, open ports across, for the HTML report; empty when no
+/// device had an open port
+fn ports_matrix_html(scan: &ScanResult) -> String {
+    let ports = open_ports(scan);
+    if ports.is_empty() {
+        return String::new();
+    }
+    let mut html = vec!["        <h2>Open Ports</h2>\n        <table class=\"matrix\">\n            <tr><th>Device</th>".to_string()];
+    html.extend(ports.iter().map(|port| format!("<th>{}</th>", port)));
+    html.push("</tr>\n".to_string());
+    for device in scan.devices.iter().filter(|device| !device.open_ports.is_empty()) {
+        html.push(format!("            <tr><td>{}</td>", device.ip));
+        html.extend(ports.iter().map(|port| match device.open_ports.contains(port) {
+            true => "<td class=\"open\">●</td>",
+            false => "<td></td>",
+        }.to_string()));
+        html.push("</tr>\n".to_string());
+    }
+    html.push("        </table>\n".to_string());
+    html.concat()
+}
+
+/// The port matrix as rows, for the PDF report
+fn ports_section(scan: &ScanResult) -> ReportSection {
+    let mut section = ReportSection::new("Open Ports");
+    for device in scan.devices.iter().filter(|device| !device.open_ports.is_empty()) {
+        let ports: Vec<String> = device.open_ports.iter().map(u16::to_string).collect();
+        section.row(device.ip.to_string(), ports.join(", "));
+    }
+    if section.rows.is_empty() {
+        section.row("Devices", "no open ports found".to_string());
+    }
+    section
+}
+
+fn security_section(audit: &SecurityAudit) -> ReportSection {
+    let mut section = ReportSection::new("Security Findings");
+    section.row("Last Audit", format!("{} ({}): {} critical, {} warnings, {} informational",
+                audit.generated_at.format("%Y-%m-%d %H:%M UTC"), audit.checks.join(", "),
+                audit.count(Severity::Critical), audit.count(Severity::Warning), audit.count(Severity::Info)));
+    let mut findings: Vec<_> = audit.findings.iter().collect();
+    findings.sort_by_key(|finding| finding.severity);
+    for finding in findings {
+        section.row(format!("[{}] {}", finding.severity.to_string().to_uppercase(), finding.title),
+                    match &finding.detail {
+                        Some(detail) => format!("{} ({} check)", detail, finding.check),
+                        None => format!("{} check", finding.check),
+                    });
+    }
+    section
+}
@@ -0,0 +1,356 @@
+// PDF output for `report --format pdf`
+// A small PDF 1.4 writer with just what the report needs: text in the
+// standard Helvetica fonts (every reader has them, so nothing is embedded),
+// rules, and the report's charts as vector paths. Pages are A4 and laid out
+// top to bottom by `Layout`, which starts a new page when the next block
+// doesn't fit and repeats the heading of a section it splits. Content
+// streams are deflated; text is WinAnsi, so characters outside it print as
+// `?`.
+
+use anyhow::Result;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+use super::charts::{Anchor, Drawing, Shape, TEXT_COLOR};
+
+/// A4, in points
+pub const PAGE_WIDTH: f64 = 595.0;
+pub const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 50.0;
+/// Room at the bottom of each page for its number
+const FOOTER: f64 = 30.0;
+/// Width of the label column of a table
+const LABEL_WIDTH: f64 = 170.0;
+const BODY_SIZE: f64 = 10.0;
+const LINE_HEIGHT: f64 = 13.0;
+/// Space a section heading takes, gap above included
+const HEADING_HEIGHT: f64 = 38.0;
+const ACCENT: &str = "#00bcd4";
+
+/// Helvetica advance widths of ' ' to '~', in thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556,
+    556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Width of `text` in Helvetica at `size` points; bold runs about 5% wider
+pub fn text_width(text: &str, size: f64, bold: bool) -> f64 {
+    let thousandths: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => HELVETICA_WIDTHS[c as usize - 32] as u32,
+            _ => 556,
+        })
+        .sum();
+    thousandths as f64 * size / 1000.0 * if bold { 1.05 } else { 1.0 }
+}
+
+/// `text` in WinAnsiEncoding as a PDF string literal
+fn pdf_string(text: &str) -> String {
+    let mut out = String::from("(");
+    for c in text.chars() {
+        let byte: u32 = match c {
+            '\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => c as u32,
+            '€' => 0x80,
+            '…' => 0x85,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            _ => '?' as u32,
+        };
+        match byte {
+            0x28 | 0x29 | 0x5c => {
+                out.push('\\');
+                out.push(byte as u8 as char);
+            }
+            0x20..=0x7e => out.push(byte as u8 as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out.push(')');
+    out
+}
+
+/// `#rrggbb` as PDF color components
+fn rgb(color: &str) -> String {
+    let channel = |range: std::ops::Range<usize>| {
+        color.get(range).and_then(|hex| u8::from_str_radix(hex, 16).ok()).unwrap_or(0) as f64 / 255.0
+    };
+    format!("{:.3} {:.3} {:.3}", channel(1..3), channel(3..5), channel(5..7))
+}
+
+/// One page's drawing operators, in points from the top left
+#[derive(Debug, Default)]
+pub struct Page {
+    ops: String,
+}
+
+impl Page {
+    pub fn text(&mut self, x: f64, y: f64, size: f64, bold: bool, color: &str, text: &str) {
+        let font = if bold { "F2" } else { "F1" };
+        // The page is flipped to run top down, so glyphs are flipped back
+        self.ops.push_str(&format!(
+            "BT /{} {} Tf {} rg 1 0 0 -1 {:.2} {:.2} Tm {} Tj ET\n",
+            font, size, rgb(color), x, y, pdf_string(text)
+        ));
+    }
+
+    pub fn line(&mut self, from: (f64, f64), to: (f64, f64), width: f64, color: &str) {
+        self.ops.push_str(&format!(
+            "{} RG {} w {:.2} {:.2} m {:.2} {:.2} l S\n",
+            rgb(color), width, from.0, from.1, to.0, to.1
+        ));
+    }
+
+    pub fn rect(&mut self, at: (f64, f64), size: (f64, f64), color: &str) {
+        self.ops.push_str(&format!("{} rg {:.2} {:.2} {:.2} {:.2} re f\n", rgb(color), at.0, at.1, size.0, size.1));
+    }
+
+    /// `drawing` scaled to `width` with its top left at `at`
+    pub fn drawing(&mut self, drawing: &Drawing, at: (f64, f64), width: f64) {
+        let scale = width / drawing.width;
+        self.ops.push_str(&format!("q {:.4} 0 0 {:.4} {:.2} {:.2} cm 1 J 1 j\n", scale, scale, at.0, at.1));
+        for shape in &drawing.shapes {
+            match shape {
+                Shape::Line { from, to, color } => self.line(*from, *to, 1.0, color),
+                Shape::Path { runs, color, width } => {
+                    let mut ops = format!("{} RG {} w", rgb(color), width);
+                    for run in runs.iter().filter(|run| run.len() > 1) {
+                        for (i, (x, y)) in run.iter().enumerate() {
+                            ops.push_str(&format!(" {:.2} {:.2} {}", x, y, if i == 0 { "m" } else { "l" }));
+                        }
+                    }
+                    self.ops.push_str(&ops);
+                    self.ops.push_str(" S\n");
+                }
+                Shape::Circle { center, radius, color } => {
+                    // A round-capped zero-length stroke is a filled circle
+                    self.ops.push_str(&format!(
+                        "{} RG {:.2} w {:.2} {:.2} m {:.2} {:.2} l S\n",
+                        rgb(color), radius * 2.0, center.0, center.1, center.0, center.1
+                    ));
+                }
+                Shape::Rect { at, size, color } => self.rect(*at, *size, color),
+                Shape::Text { at, text, size, bold, anchor, vertical } => {
+                    let offset = match anchor {
+                        Anchor::Start => 0.0,
+                        Anchor::Middle => text_width(text, *size, *bold) / 2.0,
+                        Anchor::End => text_width(text, *size, *bold),
+                    };
+                    let font = if *bold { "F2" } else { "F1" };
+                    let matrix = if *vertical {
+                        format!("0 -1 -1 0 {:.2} {:.2}", at.0, at.1 + offset)
+                    } else {
+                        format!("1 0 0 -1 {:.2} {:.2}", at.0 - offset, at.1)
+                    };
+                    self.ops.push_str(&format!(
+                        "BT /{} {} Tf {} rg {} Tm {} Tj ET\n",
+                        font, size, rgb(TEXT_COLOR), matrix, pdf_string(text)
+                    ));
+                }
+            }
+        }
+        self.ops.push_str("Q\n");
+    }
+}
+
+/// Lays text, tables and charts out down A4 pages
+#[derive(Debug)]
+pub struct Layout {
+    title: String,
+    pages: Vec<Page>,
+    page: Page,
+    y: f64,
+    /// The section being written, repeated on the page it continues onto
+    section: Option<String>,
+    /// A heading waiting to go above the next block, on the same page
+    pending: Option<String>,
+}
+
+impl Layout {
+    pub fn new(title: &str) -> Self {
+        Self { title: title.to_string(), pages: Vec::new(), page: Page::default(), y: MARGIN, section: None, pending: None }
+    }
+
+    fn content_width() -> f64 {
+        PAGE_WIDTH - 2.0 * MARGIN
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(std::mem::take(&mut self.page));
+        self.y = MARGIN;
+    }
+
+    /// Room for `height` more points on this page, below any pending
+    /// heading, or a new page (with the current section's heading again)
+    fn reserve(&mut self, height: f64) {
+        let heading = if self.pending.is_some() { HEADING_HEIGHT } else { 0.0 };
+        if self.y + heading + height > PAGE_HEIGHT - MARGIN - FOOTER && self.y > MARGIN {
+            self.new_page();
+            if let (None, Some(section)) = (&self.pending, self.section.clone()) {
+                self.write_heading(&format!("{} (continued)", section));
+            }
+        }
+        if let Some(title) = self.pending.take() {
+            self.write_heading(&title);
+        }
+    }
+
+    fn write_heading(&mut self, title: &str) {
+        if self.y > MARGIN {
+            self.y += 10.0;
+        }
+        self.page.text(MARGIN, self.y + 14.0, 14.0, true, ACCENT, title);
+        self.y += 20.0;
+        self.page.line((MARGIN, self.y), (PAGE_WIDTH - MARGIN, self.y), 0.5, ACCENT);
+        self.y += 8.0;
+    }
+
+    /// Large text, for the cover
+    pub fn title(&mut self, text: &str, size: f64) {
+        self.reserve(size * 1.5);
+        self.page.text(MARGIN, self.y + size, size, true, ACCENT, text);
+        self.y += size * 1.5;
+    }
+
+    /// A section heading, kept on the page of the block that follows it
+    pub fn heading(&mut self, title: &str) {
+        self.section = Some(title.to_string());
+        self.pending = Some(title.to_string());
+    }
+
+    /// Body text, wrapped to the page
+    pub fn paragraph(&mut self, text: &str) {
+        for line in wrap(text, Self::content_width(), BODY_SIZE) {
+            self.reserve(LINE_HEIGHT);
+            self.page.text(MARGIN, self.y + BODY_SIZE, BODY_SIZE, false, "#333333", &line);
+            self.y += LINE_HEIGHT;
+        }
+        self.y += 4.0;
+    }
+
+    /// A table row: the label in bold, the value wrapped beside it
+    pub fn row(&mut self, label: &str, value: &str) {
+        let labels = wrap(label, LABEL_WIDTH - 10.0, BODY_SIZE);
+        let values = wrap(value, Self::content_width() - LABEL_WIDTH, BODY_SIZE);
+        let height = labels.len().max(values.len()) as f64 * LINE_HEIGHT + 4.0;
+        self.reserve(height);
+        for (i, line) in labels.iter().enumerate() {
+            self.page.text(MARGIN, self.y + BODY_SIZE + i as f64 * LINE_HEIGHT, BODY_SIZE, true, "#555555", line);
+        }
+        for (i, line) in values.iter().enumerate() {
+            self.page.text(MARGIN + LABEL_WIDTH, self.y + BODY_SIZE + i as f64 * LINE_HEIGHT, BODY_SIZE, false, "#333333", line);
+        }
+        self.y += height;
+        self.page.line((MARGIN, self.y - 2.0), (PAGE_WIDTH - MARGIN, self.y - 2.0), 0.25, "#dddddd");
+    }
+
+    /// A chart across the page, whole on one page
+    pub fn drawing(&mut self, drawing: &Drawing) {
+        let width = Self::content_width();
+        let height = drawing.height * width / drawing.width;
+        self.reserve(height + 10.0);
+        self.page.drawing(drawing, (MARGIN, self.y), width);
+        self.y += height + 10.0;
+    }
+
+    /// The finished document, every page numbered
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        self.reserve(0.0);
+        self.new_page();
+        let count = self.pages.len();
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            let footer = format!("{} - page {} of {}", self.title, index + 1, count);
+            let width = text_width(&footer, 8.0, false);
+            page.text((PAGE_WIDTH - width) / 2.0, PAGE_HEIGHT - MARGIN / 2.0, 8.0, false, "#999999", &footer);
+        }
+        write_document(&self.title, &self.pages)
+    }
+}
+
+/// `text` broken at spaces into lines no wider than `width`; a word wider
+/// than that gets a line of its own
+fn wrap(text: &str, width: f64, size: f64) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if text_width(&candidate, size, false) > width && !line.is_empty() {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Objects, cross-reference table and trailer of a document of `pages`
+fn write_document(title: &str, pages: &[Page]) -> Result<Vec<u8>> {
+    // 1 catalog, 2 page tree, 3 and 4 fonts, 5 info, then a page and its
+    // content stream per page
+    let page_id = |index: usize| 6 + 2 * index;
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len()).map(|index| format!("{} 0 R", page_id(index))).collect::<Vec<_>>().join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+        format!(
+            "<< /Title {} /Producer (NetWeaver {}) /CreationDate (D:{}Z) >>",
+            pdf_string(title),
+            env!("CARGO_PKG_VERSION"),
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        )
+        .into_bytes(),
+    ];
+    for (index, page) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                page_id(index) + 1
+            )
+            .into_bytes(),
+        );
+        // Flip the page so y runs down from the top like the layout's
+        let content = format!("1 0 0 -1 0 {} cm\n{}", PAGE_HEIGHT, page.ops);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        let compressed = encoder.finish()?;
+        let mut stream = format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", compressed.len()).into_bytes();
+        stream.extend(compressed);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", index + 1).into_bytes());
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+    }
+    pdf.extend(format!("trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).into_bytes());
+    Ok(pdf)
+}
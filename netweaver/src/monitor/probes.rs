@@ -0,0 +1,263 @@
+// Latency probes (`monitor --probe`)
+// Interface counters can look healthy while the link is slow, so the
+// realtime view and the daemon also ping a few targets in the background:
+// the `--probe` hosts, or the default gateway and 1.1.1.1 in the dashboard.
+// `gateway` stands for the default gateway. Each target gets its own task,
+// pinging every PROBE_EVERY with ICMP when raw sockets are allowed and
+// timing a TCP connect otherwise, so one slow target never delays another.
+// Samples come back over a channel; the caller keeps a ProbeStats per target
+// for the dashboard and alert rules (`gateway > 50ms for 30s`), and logs
+// each one as a `{"probe": …}` line.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use super::alerts::AlertInput;
+use crate::analytics::{LatencyAnalyzer, PacketLossDetector};
+use crate::diagnostics::ping::{self, PingMethod, PingOptions};
+use crate::utils::{self, icmp};
+
+/// What the dashboard probes without `--probe`
+pub const DEFAULT_PROBES: &[&str] = &["gateway", "1.1.1.1"];
+
+/// How often each target is pinged
+pub const PROBE_EVERY: Duration = Duration::from_secs(2);
+
+/// Wait for a reply; an unanswered probe counts as this slow for alerts
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Answered probes the statistics cover, two minutes' worth
+const PROBE_SAMPLES: usize = 60;
+
+/// Points kept for a target's sparkline
+const PROBE_POINTS: usize = 300;
+
+/// Standard deviations from the average that make an RTT stand out
+const ANOMALY_STD_DEVS: f64 = 3.0;
+
+/// How far over the average an outlier must also be, so microseconds of
+/// jitter on a quiet LAN don't count
+const ANOMALY_MIN_MS: f64 = 5.0;
+
+/// A probed host, by the name it was given on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeTarget {
+    /// `gateway`, `1.1.1.1`, `example.com`…
+    pub name: String,
+    pub ip: Ipv4Addr,
+}
+
+impl ProbeTarget {
+    /// The name, with the address when the name isn't one
+    pub fn label(&self) -> String {
+        if self.name == self.ip.to_string() {
+            self.name.clone()
+        } else {
+            format!("{} ({})", self.name, self.ip)
+        }
+    }
+}
+
+/// Resolve `specs` (duplicates dropped, `gateway` for the default gateway);
+/// with `lenient`, as for the defaults, a target that can't be resolved is
+/// left out instead of failing
+pub async fn resolve(specs: &[String], lenient: bool) -> Result<Vec<ProbeTarget>> {
+    let mut targets: Vec<ProbeTarget> = Vec::new();
+    for spec in specs {
+        let name = spec.trim();
+        if name.is_empty() || targets.iter().any(|target| target.name.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        let ip = if name.eq_ignore_ascii_case("gateway") {
+            utils::get_default_gateway().context("Failed to find the default gateway to probe")
+        } else {
+            utils::network::resolve_hostname(name).await
+                .with_context(|| format!("Failed to resolve probe target '{}'", name))
+        };
+        match ip {
+            Ok(ip) => targets.push(ProbeTarget { name: name.to_string(), ip }),
+            Err(e) if lenient => tracing::debug!("Not probing {}: {:#}", name, e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(targets)
+}
+
+/// ICMP when we may open raw sockets, else TCP connect timing
+pub fn method() -> PingMethod {
+    if icmp::raw_available() { PingMethod::Icmp } else { PingMethod::Tcp }
+}
+
+/// One probe of one target, as logged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeSample {
+    pub timestamp: DateTime<Utc>,
+    pub target: String,
+    pub ip: Ipv4Addr,
+    pub method: PingMethod,
+    /// None when the probe went unanswered
+    pub rtt_ms: Option<f64>,
+}
+
+/// Ping each of `targets` every PROBE_EVERY until the receiver is dropped
+pub fn spawn(targets: &[ProbeTarget], method: PingMethod) -> UnboundedReceiver<ProbeSample> {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let opts = PingOptions { timeout: PROBE_TIMEOUT, ..Default::default() };
+    for target in targets.iter().cloned() {
+        let sender = sender.clone();
+        let opts = opts.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PROBE_EVERY);
+            loop {
+                ticker.tick().await;
+                let rtt = ping::ping_once(target.ip, method, &opts).await.map(|(rtt, _)| rtt);
+                let sample = ProbeSample {
+                    timestamp: Utc::now(),
+                    target: target.name.clone(),
+                    ip: target.ip,
+                    method,
+                    rtt_ms: rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+                };
+                if sender.send(sample).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    receiver
+}
+
+/// What one target's probes came to so far
+#[derive(Debug, Clone)]
+pub struct ProbeStats {
+    pub target: ProbeTarget,
+    pub method: PingMethod,
+    rtts: LatencyAnalyzer,
+    loss: PacketLossDetector,
+    /// The last probe's RTT in ms; None inside when it went unanswered
+    last: Option<Option<f64>>,
+    /// RTTs in µs, for the sparkline
+    points: VecDeque<u64>,
+}
+
+impl ProbeStats {
+    pub fn new(target: ProbeTarget, method: PingMethod) -> Self {
+        Self::with_window(target, method, PROBE_SAMPLES)
+    }
+
+    /// Stats whose latency figures cover the last `samples` answered probes
+    pub fn with_window(target: ProbeTarget, method: PingMethod, samples: usize) -> Self {
+        Self {
+            target,
+            method,
+            rtts: LatencyAnalyzer::new(samples),
+            loss: PacketLossDetector::new(),
+            last: None,
+            points: VecDeque::with_capacity(PROBE_POINTS),
+        }
+    }
+
+    /// Add one probe's RTT in ms, None when it went unanswered
+    pub fn record(&mut self, rtt_ms: Option<f64>) {
+        self.loss.record_sent(1);
+        self.last = Some(rtt_ms);
+        if let Some(rtt) = rtt_ms {
+            self.loss.record_received(1);
+            self.rtts.add_sample(rtt);
+            if self.points.len() == PROBE_POINTS {
+                self.points.pop_front();
+            }
+            self.points.push_back((rtt * 1000.0) as u64);
+        }
+    }
+
+    /// "gateway (192.168.1.1) via ICMP"
+    pub fn label(&self) -> String {
+        match self.method {
+            PingMethod::Icmp => format!("{} via ICMP", self.target.label()),
+            PingMethod::Tcp => format!("{} via TCP :{}", self.target.label(), PingOptions::default().port),
+        }
+    }
+
+    /// None before the first probe; Some(None) after an unanswered one
+    pub fn last(&self) -> Option<Option<f64>> {
+        self.last
+    }
+
+    /// Answered probes the statistics below cover
+    pub fn answered(&self) -> usize {
+        self.rtts.len()
+    }
+
+    pub fn average(&self) -> f64 {
+        self.rtts.average()
+    }
+
+    pub fn min(&self) -> f64 {
+        self.rtts.min()
+    }
+
+    pub fn max(&self) -> f64 {
+        self.rtts.max()
+    }
+
+    pub fn p95(&self) -> f64 {
+        self.rtts.percentile(95.0)
+    }
+
+    pub fn jitter(&self) -> f64 {
+        self.rtts.jitter()
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.loss.sent()
+    }
+
+    pub fn lost(&self) -> u64 {
+        self.loss.sent() - self.loss.received()
+    }
+
+    /// Percent of the probes that went unanswered
+    pub fn loss_rate(&self) -> f64 {
+        self.loss.loss_rate()
+    }
+
+    /// Whether the last probe was answered, but far slower than the ones
+    /// before (ANOMALY_STD_DEVS, and at least ANOMALY_MIN_MS)
+    pub fn is_anomalous(&self) -> bool {
+        matches!(self.last, Some(Some(rtt)) if rtt - self.average() >= ANOMALY_MIN_MS)
+            && self.rtts.detect_anomaly(ANOMALY_STD_DEVS)
+    }
+
+    pub fn points(&self) -> &VecDeque<u64> {
+        &self.points
+    }
+
+    /// What the alert rules see of this target: the last RTT, with a lost
+    /// probe counting as PROBE_TIMEOUT; None before the first probe
+    pub fn alert_input(&self) -> Option<AlertInput> {
+        let last = self.last?;
+        Some(AlertInput {
+            interface: self.target.name.clone(),
+            latency_ms: Some(last.unwrap_or(PROBE_TIMEOUT.as_secs_f64() * 1000.0)),
+            ..Default::default()
+        })
+    }
+}
+
+/// Add `sample` to its target's stats, if it's one of `stats`
+pub fn record(stats: &mut [ProbeStats], sample: &ProbeSample) {
+    if let Some(stats) = stats.iter_mut().find(|stats| stats.target.name == sample.target) {
+        stats.record(sample.rtt_ms);
+    }
+}
+
+/// What the alert rules see of every target probed so far
+pub fn alert_inputs(stats: &[ProbeStats]) -> Vec<AlertInput> {
+    stats.iter().filter_map(ProbeStats::alert_input).collect()
+}
@@ -0,0 +1,268 @@
+// Per-protocol counters (`monitor --protocol`)
+// The kernel keeps host-wide TCP, UDP and ICMP counters in /proc/net/snmp,
+// with TCP extras such as timeouts in /proc/net/netstat. Both files come in
+// pairs of lines, a header of counter names and a line of values under the
+// same section name. They cover every interface at once, so the monitor
+// shows them beside the per-interface totals rather than split by interface.
+// Other platforms have no equivalent we read, and say so.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::report;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpStats {
+    pub active_opens: u64,
+    pub passive_opens: u64,
+    pub attempt_fails: u64,
+    pub estab_resets: u64,
+    /// Connections established right now, not a counter
+    pub curr_estab: u64,
+    pub in_segs: u64,
+    pub out_segs: u64,
+    pub retrans_segs: u64,
+    pub in_errs: u64,
+    pub out_rsts: u64,
+    /// Retransmission timer expiries, from /proc/net/netstat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<u64>,
+    /// SYNs dropped at a full listen queue, from /proc/net/netstat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen_drops: Option<u64>,
+}
+
+impl TcpStats {
+    /// Retransmitted segments per segment sent, in percent
+    pub fn retransmit_rate(&self) -> f64 {
+        if self.out_segs == 0 { 0.0 } else { self.retrans_segs as f64 * 100.0 / self.out_segs as f64 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UdpStats {
+    pub in_datagrams: u64,
+    pub out_datagrams: u64,
+    /// Datagrams to ports nothing listens on
+    pub no_ports: u64,
+    pub in_errors: u64,
+    pub rcvbuf_errors: u64,
+    pub sndbuf_errors: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IcmpStats {
+    pub in_msgs: u64,
+    pub out_msgs: u64,
+    pub in_errors: u64,
+    pub out_errors: u64,
+    pub in_dest_unreachs: u64,
+    pub out_dest_unreachs: u64,
+    pub in_echos: u64,
+    pub out_echos: u64,
+    pub in_echo_reps: u64,
+    pub out_echo_reps: u64,
+}
+
+/// Host-wide counters since boot, of the protocols asked for
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<TcpStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udp: Option<UdpStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icmp: Option<IcmpStats>,
+}
+
+impl ProtocolStats {
+    /// Only the statistics of `protocol` ("tcp", "udp" or "icmp"; anything
+    /// else keeps them all)
+    pub fn only(self, protocol: &str) -> Self {
+        match protocol {
+            "tcp" => Self { tcp: self.tcp, ..Default::default() },
+            "udp" => Self { udp: self.udp, ..Default::default() },
+            "icmp" => Self { icmp: self.icmp, ..Default::default() },
+            _ => self,
+        }
+    }
+
+    /// What the counters moved since `prev`; a counter that went backwards
+    /// counts from zero, and established connections stay as they are now
+    pub fn since(&self, prev: &ProtocolStats) -> ProtocolStats {
+        let moved = |prev: u64, cur: u64| cur.checked_sub(prev).unwrap_or(cur);
+        let moved_opt = |prev: Option<u64>, cur: Option<u64>| cur.map(|cur| moved(prev.unwrap_or(cur), cur));
+        ProtocolStats {
+            tcp: self.tcp.as_ref().map(|cur| {
+                let prev = prev.tcp.clone().unwrap_or_else(|| cur.clone());
+                TcpStats {
+                    active_opens: moved(prev.active_opens, cur.active_opens),
+                    passive_opens: moved(prev.passive_opens, cur.passive_opens),
+                    attempt_fails: moved(prev.attempt_fails, cur.attempt_fails),
+                    estab_resets: moved(prev.estab_resets, cur.estab_resets),
+                    curr_estab: cur.curr_estab,
+                    in_segs: moved(prev.in_segs, cur.in_segs),
+                    out_segs: moved(prev.out_segs, cur.out_segs),
+                    retrans_segs: moved(prev.retrans_segs, cur.retrans_segs),
+                    in_errs: moved(prev.in_errs, cur.in_errs),
+                    out_rsts: moved(prev.out_rsts, cur.out_rsts),
+                    timeouts: moved_opt(prev.timeouts, cur.timeouts),
+                    listen_drops: moved_opt(prev.listen_drops, cur.listen_drops),
+                }
+            }),
+            udp: self.udp.as_ref().map(|cur| {
+                let prev = prev.udp.clone().unwrap_or_else(|| cur.clone());
+                UdpStats {
+                    in_datagrams: moved(prev.in_datagrams, cur.in_datagrams),
+                    out_datagrams: moved(prev.out_datagrams, cur.out_datagrams),
+                    no_ports: moved(prev.no_ports, cur.no_ports),
+                    in_errors: moved(prev.in_errors, cur.in_errors),
+                    rcvbuf_errors: moved(prev.rcvbuf_errors, cur.rcvbuf_errors),
+                    sndbuf_errors: moved(prev.sndbuf_errors, cur.sndbuf_errors),
+                }
+            }),
+            icmp: self.icmp.as_ref().map(|cur| {
+                let prev = prev.icmp.clone().unwrap_or_else(|| cur.clone());
+                IcmpStats {
+                    in_msgs: moved(prev.in_msgs, cur.in_msgs),
+                    out_msgs: moved(prev.out_msgs, cur.out_msgs),
+                    in_errors: moved(prev.in_errors, cur.in_errors),
+                    out_errors: moved(prev.out_errors, cur.out_errors),
+                    in_dest_unreachs: moved(prev.in_dest_unreachs, cur.in_dest_unreachs),
+                    out_dest_unreachs: moved(prev.out_dest_unreachs, cur.out_dest_unreachs),
+                    in_echos: moved(prev.in_echos, cur.in_echos),
+                    out_echos: moved(prev.out_echos, cur.out_echos),
+                    in_echo_reps: moved(prev.in_echo_reps, cur.in_echo_reps),
+                    out_echo_reps: moved(prev.out_echo_reps, cur.out_echo_reps),
+                }
+            }),
+        }
+    }
+}
+
+/// /proc/net/snmp or /proc/net/netstat as section → counter → value.
+/// Negative values (Tcp MaxConn is -1) are left out.
+pub fn parse_snmp(contents: &str) -> HashMap<String, HashMap<String, u64>> {
+    let mut sections: HashMap<String, HashMap<String, u64>> = HashMap::new();
+    let mut lines = contents.lines();
+    while let (Some(names), Some(values)) = (lines.next(), lines.next()) {
+        let (Some((section, names)), Some((_, values))) = (names.split_once(':'), values.split_once(':')) else {
+            continue;
+        };
+        let counters = sections.entry(section.trim().to_string()).or_default();
+        for (name, value) in names.split_whitespace().zip(values.split_whitespace()) {
+            if let Ok(value) = value.parse() {
+                counters.insert(name.to_string(), value);
+            }
+        }
+    }
+    sections
+}
+
+/// The counters of the two files; a protocol whose section is missing is None
+pub fn protocol_stats_from(snmp: &str, netstat: &str) -> ProtocolStats {
+    let snmp = parse_snmp(snmp);
+    let netstat = parse_snmp(netstat);
+    let tcp_ext = netstat.get("TcpExt");
+    let ext = |name: &str| tcp_ext.and_then(|counters| counters.get(name).copied());
+    let counters = |section: &str| {
+        snmp.get(section).map(|counters| move |name: &str| counters.get(name).copied().unwrap_or(0))
+    };
+    ProtocolStats {
+        tcp: counters("Tcp").map(|get| TcpStats {
+            active_opens: get("ActiveOpens"),
+            passive_opens: get("PassiveOpens"),
+            attempt_fails: get("AttemptFails"),
+            estab_resets: get("EstabResets"),
+            curr_estab: get("CurrEstab"),
+            in_segs: get("InSegs"),
+            out_segs: get("OutSegs"),
+            retrans_segs: get("RetransSegs"),
+            in_errs: get("InErrs"),
+            out_rsts: get("OutRsts"),
+            timeouts: ext("TCPTimeouts"),
+            listen_drops: ext("ListenDrops"),
+        }),
+        udp: counters("Udp").map(|get| UdpStats {
+            in_datagrams: get("InDatagrams"),
+            out_datagrams: get("OutDatagrams"),
+            no_ports: get("NoPorts"),
+            in_errors: get("InErrors"),
+            rcvbuf_errors: get("RcvbufErrors"),
+            sndbuf_errors: get("SndbufErrors"),
+        }),
+        icmp: counters("Icmp").map(|get| IcmpStats {
+            in_msgs: get("InMsgs"),
+            out_msgs: get("OutMsgs"),
+            in_errors: get("InErrors"),
+            out_errors: get("OutErrors"),
+            in_dest_unreachs: get("InDestUnreachs"),
+            out_dest_unreachs: get("OutDestUnreachs"),
+            in_echos: get("InEchos"),
+            out_echos: get("OutEchos"),
+            in_echo_reps: get("InEchoReps"),
+            out_echo_reps: get("OutEchoReps"),
+        }),
+    }
+}
+
+/// This host's counters since boot
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn read_protocol_stats() -> Result<ProtocolStats> {
+    let snmp = std::fs::read_to_string("/proc/net/snmp").context("Failed to read /proc/net/snmp")?;
+    // Only the TCP extras come from here
+    let netstat = std::fs::read_to_string("/proc/net/netstat").unwrap_or_default();
+    Ok(protocol_stats_from(&snmp, &netstat))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn read_protocol_stats() -> Result<ProtocolStats> {
+    anyhow::bail!("per-protocol counters are only read on Linux")
+}
+
+/// Print the counters; with `seconds`, they are what moved in that long
+/// and show as rates
+pub fn print_protocol_stats(stats: &ProtocolStats, seconds: Option<f64>) {
+    let count = |value: u64| match seconds {
+        Some(seconds) if seconds > 0.0 => format!("{:.0}/s", value as f64 / seconds),
+        Some(_) => "-".to_string(),
+        None => value.to_string(),
+    };
+    let alarm = |value: u64| if value > 0 { count(value).bright_red() } else { count(value).normal() };
+
+    if let Some(tcp) = &stats.tcp {
+        let rate = tcp.retransmit_rate();
+        let rate = format!("{:.2}%", rate);
+        let rate = if tcp.retrans_segs > 0 { rate.bright_yellow() } else { rate.bright_green() };
+        report!("  {} {} segments in, {} out, {} retransmitted ({}), {} errors, {} resets sent",
+                 "TCP:".bright_white(), count(tcp.in_segs), count(tcp.out_segs), count(tcp.retrans_segs), rate,
+                 alarm(tcp.in_errs), count(tcp.out_rsts));
+        let mut extras = vec![
+            format!("{} established", tcp.curr_estab),
+            format!("{} opened", count(tcp.active_opens)),
+            format!("{} accepted", count(tcp.passive_opens)),
+            format!("{} failed attempts", alarm(tcp.attempt_fails)),
+        ];
+        if let Some(timeouts) = tcp.timeouts {
+            extras.push(format!("{} timeouts", alarm(timeouts)));
+        }
+        if let Some(drops) = tcp.listen_drops {
+            extras.push(format!("{} listen drops", alarm(drops)));
+        }
+        report!("       {}", extras.join(", "));
+    }
+    if let Some(udp) = &stats.udp {
+        report!("  {} {} datagrams in, {} out, {} to closed ports, {} errors ({} receive buffer, {} send buffer)",
+                 "UDP:".bright_white(), count(udp.in_datagrams), count(udp.out_datagrams), count(udp.no_ports),
+                 alarm(udp.in_errors), alarm(udp.rcvbuf_errors), alarm(udp.sndbuf_errors));
+    }
+    if let Some(icmp) = &stats.icmp {
+        report!("  {} {} messages in, {} out, {} errors in, {} out",
+                 "ICMP:".bright_white(), count(icmp.in_msgs), count(icmp.out_msgs), alarm(icmp.in_errors), alarm(icmp.out_errors));
+        report!("        echo requests {} in, {} out; replies {} in, {} out; unreachable {} in, {} out",
+                 count(icmp.in_echos), count(icmp.out_echos), count(icmp.in_echo_reps), count(icmp.out_echo_reps),
+                 count(icmp.in_dest_unreachs), count(icmp.out_dest_unreachs));
+    }
+}
@@ -0,0 +1,177 @@
+// Sample log (`monitor --log`)
+// One record per interface and sampling interval: what the byte, packet,
+// error and drop counters moved by and the rates that makes. A path ending
+// in `.csv` gets CSV with a header row; anything else gets JSON Lines. Every
+// mode writes the same records through the daemon's RotatingLog, so the file
+// is rotated by size, is written unbuffered for `tail -f`, and is reopened
+// when logrotate moves it away or a write fails (a full disk); a failed
+// write is reported to the caller and never stops the monitor. JSON logs
+// also get alerts, interface events, latency probes, Wi-Fi links and the
+// final top talkers in between, as lines of their own.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::alerts::Alert;
+use super::daemon::{RotatingLog, LOG_KEEP, LOG_MAX_BYTES};
+use super::events::InterfaceEvent;
+use super::probes::ProbeSample;
+use super::talkers::TalkersReport;
+use super::wifi::WifiLink;
+use super::{traffic_deltas, watches, NetworkStats};
+
+/// Column names of a CSV log, in `LogRecord` field order
+pub const CSV_HEADER: &str = "timestamp,interface,interval_secs,bytes_sent,bytes_recv,packets_sent,packets_recv,errors,drops,tx_bytes_per_sec,rx_bytes_per_sec,packets_per_sec";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    JsonLines,
+    Csv,
+}
+
+impl LogFormat {
+    /// CSV for `.csv`, JSON Lines otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => LogFormat::Csv,
+            _ => LogFormat::JsonLines,
+        }
+    }
+}
+
+/// What one interface's counters did over one sampling interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// When the interval ended
+    pub timestamp: DateTime<Utc>,
+    /// Interface name, or `all` for the sum
+    pub interface: String,
+    pub interval_secs: f64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    pub errors: u64,
+    /// None (an empty CSV field) where the platform has no drop counter
+    pub drops: Option<u64>,
+    pub tx_bytes_per_sec: f64,
+    pub rx_bytes_per_sec: f64,
+    pub packets_per_sec: f64,
+}
+
+/// Records for the interval from `previous` to `stats` of `interface`
+/// (`all` logs every interface and their sum)
+pub fn log_records(previous: &[NetworkStats], stats: &[NetworkStats], interface: &str) -> Vec<LogRecord> {
+    let timestamp = stats
+        .iter()
+        .map(|row| row.timestamp)
+        .max()
+        .and_then(|at| DateTime::from_timestamp_micros(at as i64))
+        .unwrap_or_else(Utc::now);
+    traffic_deltas(previous, stats)
+        .into_iter()
+        .filter(|delta| watches(interface, &delta.interface))
+        .map(|delta| LogRecord {
+            timestamp,
+            interval_secs: delta.seconds,
+            tx_bytes_per_sec: delta.tx_rate(),
+            rx_bytes_per_sec: delta.rx_rate(),
+            packets_per_sec: delta.packet_rate(),
+            interface: delta.interface,
+            bytes_sent: delta.bytes_sent,
+            bytes_recv: delta.bytes_recv,
+            packets_sent: delta.packets_sent,
+            packets_recv: delta.packets_recv,
+            errors: delta.errors,
+            drops: delta.drops,
+        })
+        .collect()
+}
+
+/// A `--log` file in the format its extension asks for
+#[derive(Debug)]
+pub struct SampleLog {
+    log: RotatingLog,
+    format: LogFormat,
+}
+
+impl SampleLog {
+    /// Append to `path`, failing only if it can't be opened now
+    pub fn open(path: &Path) -> Result<Self> {
+        let format = LogFormat::from_path(path);
+        let mut log = RotatingLog::open(path, LOG_MAX_BYTES, LOG_KEEP)?;
+        if format == LogFormat::Csv {
+            log = log.with_header(CSV_HEADER);
+        }
+        Ok(Self { log, format })
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// Append one interval's records
+    pub fn write_records(&mut self, records: &[LogRecord]) -> Result<()> {
+        for record in records {
+            match self.format {
+                LogFormat::JsonLines => self.log.append(record)?,
+                LogFormat::Csv => {
+                    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+                    writer.serialize(record)?;
+                    self.log.append_line(&writer.into_inner().map_err(|e| anyhow::anyhow!("{}", e))?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Log an alert as a `{"alert": …}` line; CSV logs have no room for them
+    pub fn write_alert(&mut self, alert: &Alert) -> Result<()> {
+        match self.format {
+            LogFormat::JsonLines => self.log.append(&serde_json::json!({ "alert": alert })),
+            LogFormat::Csv => Ok(()),
+        }
+    }
+
+    /// Log an interface event as an `{"event": …}` line; CSV logs have no
+    /// room for them
+    pub fn write_event(&mut self, event: &InterfaceEvent) -> Result<()> {
+        match self.format {
+            LogFormat::JsonLines => self.log.append(&serde_json::json!({ "event": event })),
+            LogFormat::Csv => Ok(()),
+        }
+    }
+
+    /// Log a latency probe as a `{"probe": …}` line; CSV logs have no room
+    /// for them
+    pub fn write_probe(&mut self, sample: &ProbeSample) -> Result<()> {
+        match self.format {
+            LogFormat::JsonLines => self.log.append(&serde_json::json!({ "probe": sample })),
+            LogFormat::Csv => Ok(()),
+        }
+    }
+
+    /// Log a Wi-Fi link as a `{"wifi": …}` line; CSV logs have no room for
+    /// them
+    pub fn write_wifi(&mut self, link: &WifiLink) -> Result<()> {
+        match self.format {
+            LogFormat::JsonLines => self.log.append(&serde_json::json!({ "wifi": link })),
+            LogFormat::Csv => Ok(()),
+        }
+    }
+
+    /// Log a final top-talkers ranking as a `{"top_talkers": …}` line; CSV
+    /// logs have no room for it either
+    pub fn write_talkers(&mut self, report: &TalkersReport) -> Result<()> {
+        match self.format {
+            LogFormat::JsonLines => self.log.append(&serde_json::json!({ "top_talkers": report })),
+            LogFormat::Csv => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.log.flush()
+    }
+}
@@ -0,0 +1,316 @@
+// Snapshot comparison (`monitor --save`, `monitor --compare`)
+// For before/after testing ("did enabling BBR cut retransmits?"): `--save`
+// writes the snapshot's counters, every monitored interface's with its
+// errors by cause plus the host-wide TCP/UDP/ICMP ones, to a JSON file.
+// `--compare` loads such a file, takes a fresh snapshot and prints what each
+// counter moved in between, as a number and a percentage of where it
+// started, with the wall time between the two. Counters of trouble (errors,
+// drops, retransmits, resets…) that went up are flagged as regressions, and
+// so is a TCP retransmit rate between the snapshots above the one before.
+// Interfaces in only one of the snapshots are listed rather than dropped.
+// `--output` writes the comparison as JSON.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::protocols::ProtocolStats;
+use super::{watches, NetworkStats, ALL_INTERFACES};
+use crate::report;
+
+/// Counters of trouble: going up between the snapshots is a regression.
+/// Protocol counters are named `tcp.retrans_segs` and so on
+const TROUBLE_COUNTERS: &[&str] = &[
+    "errors",
+    "drops",
+    "overruns",
+    "tcp.attempt_fails",
+    "tcp.estab_resets",
+    "tcp.retrans_segs",
+    "tcp.in_errs",
+    "tcp.out_rsts",
+    "tcp.timeouts",
+    "tcp.listen_drops",
+    "udp.no_ports",
+    "udp.in_errors",
+    "udp.rcvbuf_errors",
+    "udp.sndbuf_errors",
+    "icmp.in_errors",
+    "icmp.out_errors",
+    "icmp.in_dest_unreachs",
+    "icmp.out_dest_unreachs",
+];
+
+/// What `--save` writes: the counters at one moment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// The monitored interfaces, with their errors by cause
+    pub interfaces: Vec<NetworkStats>,
+    /// Host-wide counters, where they can be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocols: Option<ProtocolStats>,
+}
+
+impl StatsSnapshot {
+    /// The interfaces of `stats` that `interface` covers, with `protocols`
+    pub fn new(stats: &[NetworkStats], interface: &str, protocols: Option<ProtocolStats>) -> Self {
+        Self {
+            taken_at: Utc::now(),
+            interfaces: stats
+                .iter()
+                .filter(|row| row.interface != ALL_INTERFACES && watches(interface, &row.interface))
+                .cloned()
+                .collect(),
+            protocols,
+        }
+    }
+}
+
+pub fn save_snapshot(snapshot: &StatsSnapshot, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(snapshot)?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write snapshot to {}", path.display()))
+}
+
+pub fn load_snapshot(path: &Path) -> Result<StatsSnapshot> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+    serde_json::from_str(&json).with_context(|| format!("{} isn't a snapshot saved with --save", path.display()))
+}
+
+/// One counter in both snapshots
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CounterChange {
+    pub counter: String,
+    pub before: u64,
+    pub after: u64,
+    /// Negative when the counter was reset in between, as by a reboot
+    pub change: i64,
+    /// The change as a percentage of `before`; None from zero
+    pub percent: Option<f64>,
+    /// A counter of trouble that went up
+    pub regressed: bool,
+}
+
+impl CounterChange {
+    fn new(counter: String, before: u64, after: u64) -> Self {
+        let change = after as i64 - before as i64;
+        let percent = (before > 0).then(|| change as f64 / before as f64 * 100.0);
+        let regressed = change > 0 && is_trouble(&counter);
+        Self { counter, before, after, change, percent, regressed }
+    }
+}
+
+/// Whether `counter` counts trouble: one of TROUBLE_COUNTERS, or an error
+/// cause like `rx_crc_errors`
+fn is_trouble(counter: &str) -> bool {
+    TROUBLE_COUNTERS.contains(&counter) || counter.ends_with("_errors") || counter == "collisions"
+}
+
+/// One interface's counters in both snapshots
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceChanges {
+    pub interface: String,
+    pub counters: Vec<CounterChange>,
+}
+
+/// What `--compare` prints and `--output` writes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotComparison {
+    pub before_at: DateTime<Utc>,
+    pub after_at: DateTime<Utc>,
+    /// Wall time between the snapshots
+    pub elapsed_secs: f64,
+    /// Interfaces in both snapshots
+    pub interfaces: Vec<InterfaceChanges>,
+    /// Interfaces only the earlier snapshot has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_before: Vec<String>,
+    /// Interfaces only the new snapshot has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_after: Vec<String>,
+    /// Host-wide counters both snapshots have, as `tcp.retrans_segs`…
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protocols: Vec<CounterChange>,
+    /// Percent of TCP segments sent that were retransmits: since boot at the
+    /// earlier snapshot, then between the two
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retransmit_rate_before: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retransmit_rate_between: Option<f64>,
+}
+
+impl SnapshotComparison {
+    /// Every counter that regressed, the retransmit rate included
+    pub fn regressions(&self) -> Vec<String> {
+        let mut regressions: Vec<String> = self
+            .interfaces
+            .iter()
+            .flat_map(|changes| {
+                changes.counters.iter().filter(|counter| counter.regressed).map(|counter| format!("{} {}", changes.interface, counter.counter))
+            })
+            .chain(self.protocols.iter().filter(|counter| counter.regressed).map(|counter| counter.counter.clone()))
+            .collect();
+        if self.retransmit_rate_regressed() {
+            regressions.push("tcp retransmit rate".to_string());
+        }
+        regressions
+    }
+
+    fn retransmit_rate_regressed(&self) -> bool {
+        matches!((self.retransmit_rate_before, self.retransmit_rate_between), (Some(before), Some(between)) if between > before)
+    }
+}
+
+/// An interface's counters by name; error causes are only kept when
+/// non-zero, so `causes` names the ones to list as zero where missing
+fn interface_counters(stats: &NetworkStats, causes: &BTreeSet<String>) -> Vec<(String, Option<u64>)> {
+    let mut counters = vec![
+        ("bytes_sent".to_string(), Some(stats.bytes_sent)),
+        ("bytes_recv".to_string(), Some(stats.bytes_recv)),
+        ("packets_sent".to_string(), Some(stats.packets_sent)),
+        ("packets_recv".to_string(), Some(stats.packets_recv)),
+        ("errors".to_string(), Some(stats.errors)),
+        ("drops".to_string(), stats.drops),
+        ("overruns".to_string(), stats.overruns),
+    ];
+    counters.extend(causes.iter().map(|cause| (cause.clone(), Some(stats.error_detail.get(cause).copied().unwrap_or(0)))));
+    counters
+}
+
+/// Host-wide counters by `protocol.counter` name, those the platform has
+fn protocol_counters(stats: &ProtocolStats) -> Vec<(String, u64)> {
+    let Ok(serde_json::Value::Object(protocols)) = serde_json::to_value(stats) else {
+        return Vec::new();
+    };
+    let mut counters = Vec::new();
+    for (protocol, fields) in protocols {
+        if let serde_json::Value::Object(fields) = fields {
+            counters.extend(fields.into_iter().filter_map(|(name, value)| Some((format!("{}.{}", protocol, name), value.as_u64()?))));
+        }
+    }
+    counters
+}
+
+/// The counters the two snapshots both have, and which one moved how
+fn changes(before: Vec<(String, Option<u64>)>, after: Vec<(String, Option<u64>)>) -> Vec<CounterChange> {
+    after
+        .into_iter()
+        .filter_map(|(name, after)| {
+            let before = before.iter().find(|(other, _)| *other == name)?.1?;
+            Some(CounterChange::new(name, before, after?))
+        })
+        .collect()
+}
+
+/// What changed from `before` to `after`
+pub fn compare(before: &StatsSnapshot, after: &StatsSnapshot) -> SnapshotComparison {
+    let names = |snapshot: &StatsSnapshot| snapshot.interfaces.iter().map(|row| row.interface.clone()).collect::<Vec<_>>();
+    let (before_names, after_names) = (names(before), names(after));
+    let interfaces = after
+        .interfaces
+        .iter()
+        .filter_map(|cur| {
+            let prev = before.interfaces.iter().find(|prev| prev.interface == cur.interface)?;
+            let causes: BTreeSet<String> = prev.error_detail.keys().chain(cur.error_detail.keys()).cloned().collect();
+            Some(InterfaceChanges {
+                interface: cur.interface.clone(),
+                counters: changes(interface_counters(prev, &causes), interface_counters(cur, &causes)),
+            })
+        })
+        .collect();
+    let protocols = match (&before.protocols, &after.protocols) {
+        (Some(prev), Some(cur)) => {
+            let optional = |counters: Vec<(String, u64)>| counters.into_iter().map(|(name, value)| (name, Some(value))).collect();
+            changes(optional(protocol_counters(prev)), optional(protocol_counters(cur)))
+        }
+        _ => Vec::new(),
+    };
+    let tcp = |snapshot: &StatsSnapshot| snapshot.protocols.as_ref().and_then(|protocols| protocols.tcp.clone());
+    let (retransmit_rate_before, retransmit_rate_between) = match (tcp(before), tcp(after)) {
+        (Some(prev), Some(cur)) => {
+            let sent = cur.out_segs.checked_sub(prev.out_segs).filter(|&sent| sent > 0);
+            let retransmitted = cur.retrans_segs.checked_sub(prev.retrans_segs);
+            let between = sent.zip(retransmitted).map(|(sent, retransmitted)| retransmitted as f64 / sent as f64 * 100.0);
+            ((prev.out_segs > 0).then(|| prev.retransmit_rate()), between)
+        }
+        _ => (None, None),
+    };
+    SnapshotComparison {
+        before_at: before.taken_at,
+        after_at: after.taken_at,
+        elapsed_secs: (after.taken_at - before.taken_at).num_milliseconds() as f64 / 1000.0,
+        interfaces,
+        only_before: before_names.iter().filter(|name| !after_names.contains(name)).cloned().collect(),
+        only_after: after_names.iter().filter(|name| !before_names.contains(name)).cloned().collect(),
+        protocols,
+        retransmit_rate_before,
+        retransmit_rate_between,
+    }
+}
+
+/// `+333 (+270.7%)`, with the percentage when there is one
+fn describe_change(counter: &CounterChange) -> String {
+    match counter.percent {
+        Some(percent) => format!("{:+} ({:+.1}%)", counter.change, percent),
+        None => format!("{:+}", counter.change),
+    }
+}
+
+/// The counters that moved, regressions in red
+fn print_counters(counters: &[CounterChange]) {
+    let moved: Vec<&CounterChange> = counters.iter().filter(|counter| counter.change != 0).collect();
+    if moved.is_empty() {
+        report!("    no counter moved");
+    }
+    for counter in moved {
+        let line = format!("    {:<24} {:>14} → {:<14} {}", counter.counter, counter.before, counter.after, describe_change(counter));
+        if counter.regressed {
+            report!("{}  {}", line.bright_red(), "▲ regressed".bright_red().bold());
+        } else if counter.change < 0 {
+            report!("{}  {}", line, "(reset)".dimmed());
+        } else {
+            report!("{}", line);
+        }
+    }
+}
+
+pub fn print_snapshot_comparison(comparison: &SnapshotComparison) {
+    let at = |time: DateTime<Utc>| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string();
+    report!("\n{}", format!("Snapshot Comparison ({:.1}s between {} and {}):",
+            comparison.elapsed_secs, at(comparison.before_at), at(comparison.after_at)).bright_green().bold());
+    for changes in &comparison.interfaces {
+        report!("  {}", changes.interface.bright_yellow());
+        print_counters(&changes.counters);
+    }
+    if !comparison.protocols.is_empty() {
+        report!("  {}", "Protocols (all interfaces)".bright_yellow());
+        print_counters(&comparison.protocols);
+    }
+    if let (Some(before), Some(between)) = (comparison.retransmit_rate_before, comparison.retransmit_rate_between) {
+        let line = format!("  TCP retransmit rate: {:.2}% since boot before, {:.2}% between the snapshots", before, between);
+        if comparison.retransmit_rate_regressed() {
+            report!("{}", line.bright_red());
+        } else {
+            report!("{}", line);
+        }
+    }
+    if !comparison.only_before.is_empty() {
+        report!("  ⚠️  Only in the earlier snapshot: {}", comparison.only_before.join(", ").bright_yellow());
+    }
+    if !comparison.only_after.is_empty() {
+        report!("  ⚠️  Only in the new snapshot: {}", comparison.only_after.join(", ").bright_yellow());
+    }
+    match comparison.regressions().as_slice() {
+        [] => report!("\n  {}", "No counter of trouble went up".bright_green()),
+        regressions => report!("\n  {} {}", format!("{} regressed:", regressions.len()).bright_red().bold(), regressions.join(", ")),
+    }
+}
+
+/// Write `comparison` as pretty JSON to `path`
+pub fn save_comparison(comparison: &SnapshotComparison, path: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(comparison)?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write comparison to {}", path))
+}
@@ -0,0 +1,238 @@
+// Live event stream (`monitor --stream-listen`)
+// For dashboards that want the monitor's data as it happens rather than by
+// tailing the log: every connected client gets one JSON line per interface
+// sample, probe result, Wi-Fi link, alert and interface event, tagged like
+// the log's own lines (`{"sample": …}`, `{"probe": …}`, `{"wifi": …}`,
+// `{"alert": …}`, `{"event": …}`) and built from the same structs. A client may connect with
+// plain TCP and just read, send an HTTP GET to get the lines as an
+// `application/x-ndjson` body, or upgrade to a WebSocket to get one text
+// message per event. Each client has a queue of its own; one that falls
+// CLIENT_BACKLOG events behind is disconnected, so the sampler never waits
+// on a slow reader.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use colored::Colorize;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+use super::alerts::Alert;
+use super::events::InterfaceEvent;
+use super::probes::ProbeSample;
+use super::sample_log::LogRecord;
+use super::wifi::WifiLink;
+use crate::status;
+
+/// Events a client may fall behind by before it is dropped
+pub const CLIENT_BACKLOG: usize = 256;
+/// How long a new connection has to start an HTTP request before it is
+/// taken for a plain TCP reader
+const GREETING_WAIT: Duration = Duration::from_millis(500);
+/// A client that takes longer than this to accept one event is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest request we read before answering
+const MAX_REQUEST: usize = 8192;
+/// RFC 6455's key suffix for the handshake
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// One line of the stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// One interface's interval, as a log record
+    Sample(LogRecord),
+    Probe(ProbeSample),
+    Wifi(WifiLink),
+    Alert(Alert),
+    Event(InterfaceEvent),
+}
+
+/// How a client gets its events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// Newline-terminated JSON, over plain TCP or an HTTP body
+    Lines,
+    WebSocket,
+}
+
+#[derive(Debug)]
+struct Client {
+    peer: SocketAddr,
+    sender: mpsc::Sender<Arc<str>>,
+}
+
+/// The listening stream; events published here go to every client
+#[derive(Debug, Clone)]
+pub struct EventStream {
+    local: SocketAddr,
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+impl EventStream {
+    /// Bind `listen` and accept clients until the process ends
+    pub async fn start(listen: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(listen).await
+            .with_context(|| format!("Failed to listen for stream clients on {}", listen))?;
+        let stream = Self { local: listener.local_addr()?, clients: Arc::new(Mutex::new(Vec::new())) };
+        let clients = stream.clients.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((socket, peer)) = listener.accept().await else {
+                    continue;
+                };
+                let clients = clients.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(socket, peer, &clients).await {
+                        tracing::debug!("stream client {} went away: {:#}", peer, e);
+                    }
+                });
+            }
+        });
+        status!("🛰  Streaming events on {} (TCP, HTTP or WebSocket)", stream.local.to_string().bright_green());
+        Ok(stream)
+    }
+
+    /// The address bound, with the port picked for port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    /// Clients connected now
+    pub fn clients(&self) -> usize {
+        self.clients.lock().len()
+    }
+
+    /// Queue `event` for every client, dropping those too far behind
+    pub fn publish(&self, event: &StreamEvent) {
+        let line: Arc<str> = match serde_json::to_string(event) {
+            Ok(line) => line.into(),
+            Err(e) => {
+                tracing::warn!("Failed to encode stream event: {}", e);
+                return;
+            }
+        };
+        self.clients.lock().retain(|client| match client.sender.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::warn!("Dropping stream client {}: more than {} events behind", client.peer, CLIENT_BACKLOG);
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+    }
+
+    /// Publish `records` as samples
+    pub fn publish_records(&self, records: &[LogRecord]) {
+        for record in records {
+            self.publish(&StreamEvent::Sample(record.clone()));
+        }
+    }
+}
+
+/// Work out what `socket` speaks, then feed it events until either side
+/// gives up
+async fn serve(mut socket: TcpStream, peer: SocketAddr, clients: &Mutex<Vec<Client>>) -> Result<()> {
+    let mut greeting = [0u8; 4];
+    let http = matches!(
+        tokio::time::timeout(GREETING_WAIT, socket.peek(&mut greeting)).await,
+        Ok(Ok(4)) if &greeting == b"GET "
+    );
+    let framing = if http { handshake(&mut socket).await? } else { Framing::Lines };
+
+    let (sender, mut receiver) = mpsc::channel::<Arc<str>>(CLIENT_BACKLOG);
+    clients.lock().push(Client { peer, sender });
+    tracing::debug!("stream client {} connected", peer);
+
+    let (mut reader, mut writer) = socket.into_split();
+    let mut incoming = [0u8; 1024];
+    loop {
+        tokio::select! {
+            line = receiver.recv() => {
+                // None once publish dropped us
+                let Some(line) = line else { break };
+                let bytes = match framing {
+                    Framing::Lines => format!("{}\n", line).into_bytes(),
+                    Framing::WebSocket => frame(0x1, line.as_bytes()),
+                };
+                tokio::time::timeout(WRITE_TIMEOUT, writer.write_all(&bytes)).await
+                    .context("write timed out")??;
+            }
+            read = reader.read(&mut incoming) => match read? {
+                0 => break,
+                // What clients send is ignored, apart from a WebSocket close
+                n if framing == Framing::WebSocket && n > 0 && incoming[0] & 0x0f == 0x8 => {
+                    writer.write_all(&frame(0x8, &[])).await?;
+                    break;
+                }
+                _ => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Answer the HTTP request waiting on `socket`: a WebSocket upgrade when it
+/// asks for one, an endless NDJSON body otherwise
+async fn handshake(socket: &mut TcpStream) -> Result<Framing> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let n = tokio::time::timeout(Duration::from_secs(5), socket.read(&mut buf)).await??;
+        if n == 0 {
+            anyhow::bail!("connection closed during the request");
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let header = |name: &str| {
+        request.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+        })
+    };
+    let upgrade = header("Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    match header("Sec-WebSocket-Key") {
+        Some(key) if upgrade => {
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                websocket_accept(&key)
+            );
+            socket.write_all(response.as_bytes()).await?;
+            Ok(Framing::WebSocket)
+        }
+        _ => {
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n").await?;
+            Ok(Framing::Lines)
+        }
+    }
+}
+
+/// The `Sec-WebSocket-Accept` answer to a client's `Sec-WebSocket-Key`
+pub fn websocket_accept(key: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, format!("{}{}", key, WEBSOCKET_GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+/// An unmasked, final server frame with `opcode` (1 for text, 8 for close)
+pub fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => bytes.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            bytes.push(126);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            bytes.push(127);
+            bytes.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    bytes.extend_from_slice(payload);
+    bytes
+}
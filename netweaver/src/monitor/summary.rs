@@ -0,0 +1,230 @@
+// End-of-run summary (`monitor --duration`)
+// For a quick look at an interface: the monitor stops by itself once the
+// duration is up, or at Ctrl+C before that, and sums up the window it saw.
+// Every sample's deltas go into a SummaryRecorder, which keeps totals and
+// peak rates per monitored interface (and their total when there are
+// several) plus the latency of each probe target over the whole window, not
+// just the dashboard's last minute. The summary is printed, and written as
+// JSON to `--output`.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+use super::probes::{ProbeSample, ProbeStats, ProbeTarget};
+use super::{interface_names, sum_counts, traffic_deltas, watches, NetworkStats, ALL_INTERFACES};
+use crate::diagnostics::ping::PingMethod;
+use crate::report;
+use crate::utils;
+
+/// Answered probes a target's summary covers, over five hours at one every
+/// PROBE_EVERY; a longer run is summed up from its latest ones
+const SUMMARY_PROBE_SAMPLES: usize = 10_000;
+
+/// What one interface (or the total) did over the window
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceSummary {
+    pub interface: String,
+    /// Time its deltas cover, which is shorter for one that came up late
+    pub seconds: f64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    pub errors: u64,
+    /// None where the platform has no drop counter
+    pub drops: Option<u64>,
+    pub avg_tx_bytes_per_sec: f64,
+    pub avg_rx_bytes_per_sec: f64,
+    /// Highest rate over a single sample
+    pub peak_tx_bytes_per_sec: f64,
+    pub peak_rx_bytes_per_sec: f64,
+}
+
+/// One probe target's latency over the window; the figures are None when
+/// no probe was answered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeSummary {
+    pub target: String,
+    pub ip: Ipv4Addr,
+    pub method: PingMethod,
+    pub sent: u64,
+    pub lost: u64,
+    pub loss_percent: f64,
+    pub avg_ms: Option<f64>,
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+}
+
+impl ProbeSummary {
+    fn new(stats: &ProbeStats) -> Self {
+        let answered = |value: f64| (stats.answered() > 0).then_some(value);
+        Self {
+            target: stats.target.name.clone(),
+            ip: stats.target.ip,
+            method: stats.method,
+            sent: stats.sent(),
+            lost: stats.lost(),
+            loss_percent: stats.loss_rate(),
+            avg_ms: answered(stats.average()),
+            min_ms: answered(stats.min()),
+            max_ms: answered(stats.max()),
+            p95_ms: answered(stats.p95()),
+            jitter_ms: answered(stats.jitter()),
+        }
+    }
+}
+
+/// The whole run, as printed and written to `--output`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub seconds: f64,
+    /// False when Ctrl+C (or `q`) ended the run before its duration
+    pub complete: bool,
+    /// Each monitored interface, then their total when there are several
+    pub interfaces: Vec<InterfaceSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub probes: Vec<ProbeSummary>,
+}
+
+/// Adds up the samples of one run
+#[derive(Debug)]
+pub struct SummaryRecorder {
+    interface: String,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    interfaces: Vec<InterfaceSummary>,
+    probes: Vec<ProbeStats>,
+}
+
+impl SummaryRecorder {
+    /// A run over what `interface` names, pinging `probes` with `method`
+    pub fn new(interface: &str, probes: &[ProbeTarget], method: PingMethod) -> Self {
+        Self {
+            interface: interface.to_string(),
+            started_at: Utc::now(),
+            started: Instant::now(),
+            interfaces: Vec::new(),
+            probes: probes
+                .iter()
+                .map(|target| ProbeStats::with_window(target.clone(), method, SUMMARY_PROBE_SAMPLES))
+                .collect(),
+        }
+    }
+
+    /// Add the interval from `previous` to `stats`
+    pub fn record(&mut self, previous: &[NetworkStats], stats: &[NetworkStats]) {
+        let watched = |rows: &[NetworkStats]| -> Vec<NetworkStats> {
+            rows.iter().filter(|row| watches(&self.interface, &row.interface)).cloned().collect()
+        };
+        let single = interface_names(&self.interface).len() == 1;
+        for delta in traffic_deltas(&watched(previous), &watched(stats)) {
+            // One interface's total is just the interface again
+            if single && delta.interface == ALL_INTERFACES {
+                continue;
+            }
+            let position = self.interfaces.iter().position(|row| row.interface == delta.interface);
+            let row = match position {
+                Some(index) => &mut self.interfaces[index],
+                None => {
+                    self.interfaces.push(InterfaceSummary { interface: delta.interface.clone(), ..Default::default() });
+                    self.interfaces.last_mut().unwrap()
+                }
+            };
+            row.seconds += delta.seconds;
+            row.bytes_sent += delta.bytes_sent;
+            row.bytes_recv += delta.bytes_recv;
+            row.packets_sent += delta.packets_sent;
+            row.packets_recv += delta.packets_recv;
+            row.errors += delta.errors;
+            row.drops = sum_counts(row.drops, delta.drops);
+            row.peak_tx_bytes_per_sec = row.peak_tx_bytes_per_sec.max(delta.tx_rate());
+            row.peak_rx_bytes_per_sec = row.peak_rx_bytes_per_sec.max(delta.rx_rate());
+        }
+        // The total goes last, after interfaces that came up late
+        if let Some(index) = self.interfaces.iter().position(|row| row.interface == ALL_INTERFACES) {
+            let total = self.interfaces.remove(index);
+            self.interfaces.push(total);
+        }
+    }
+
+    /// Add one probe result
+    pub fn record_probe(&mut self, sample: &ProbeSample) {
+        super::probes::record(&mut self.probes, sample);
+    }
+
+    /// The summary so far; `complete` when the run lasted its duration
+    pub fn finish(&self, complete: bool) -> RunSummary {
+        let interfaces = self
+            .interfaces
+            .iter()
+            .cloned()
+            .map(|mut row| {
+                if row.seconds > 0.0 {
+                    row.avg_tx_bytes_per_sec = row.bytes_sent as f64 / row.seconds;
+                    row.avg_rx_bytes_per_sec = row.bytes_recv as f64 / row.seconds;
+                }
+                row
+            })
+            .collect();
+        RunSummary {
+            started_at: self.started_at,
+            finished_at: Utc::now(),
+            seconds: self.started.elapsed().as_secs_f64(),
+            complete,
+            interfaces,
+            probes: self.probes.iter().map(ProbeSummary::new).collect(),
+        }
+    }
+}
+
+pub fn print_summary(summary: &RunSummary) {
+    let ended = if summary.complete { "" } else { ", stopped early" };
+    report!("\n{}", format!("Summary ({:.0}s{}):", summary.seconds, ended).bright_green().bold());
+    if summary.interfaces.is_empty() {
+        report!("  No complete sample; run for longer than one refresh");
+    } else {
+        report!("  {:<16} {:>12} {:>12} {:>12} {:>12} {:>11} {:>11} {:>7} {:>7}",
+                "Interface".bright_cyan(), "Avg TX".bright_cyan(), "Peak TX".bright_cyan(),
+                "Avg RX".bright_cyan(), "Peak RX".bright_cyan(), "Sent".bright_cyan(),
+                "Received".bright_cyan(), "Errors".bright_cyan(), "Drops".bright_cyan());
+        for row in &summary.interfaces {
+            let drops = row.drops.map(|drops| drops.to_string()).unwrap_or_else(|| "n/a".to_string());
+            report!("  {:<16} {:>12} {:>12} {:>12} {:>12} {:>11} {:>11} {:>7} {:>7}",
+                    row.interface,
+                    utils::format_bandwidth(row.avg_tx_bytes_per_sec),
+                    utils::format_bandwidth(row.peak_tx_bytes_per_sec),
+                    utils::format_bandwidth(row.avg_rx_bytes_per_sec),
+                    utils::format_bandwidth(row.peak_rx_bytes_per_sec),
+                    utils::format_bytes(row.bytes_sent),
+                    utils::format_bytes(row.bytes_recv),
+                    row.errors,
+                    drops);
+        }
+    }
+    if !summary.probes.is_empty() {
+        report!("\n{}", "Latency:".bright_green().bold());
+        for probe in &summary.probes {
+            let loss = format!("loss {:.1}% ({} of {})", probe.loss_percent, probe.lost, probe.sent);
+            match (probe.avg_ms, probe.min_ms, probe.max_ms, probe.p95_ms, probe.jitter_ms) {
+                (Some(avg), Some(min), Some(max), Some(p95), Some(jitter)) => report!(
+                    "  {:<28} avg {:.1} ms, p95 {:.1}, min/max {:.1}/{:.1}, jitter {:.1}, {}",
+                    probe.target, avg, p95, min, max, jitter, loss),
+                _ => report!("  {:<28} no replies, {}", probe.target, loss),
+            }
+        }
+    }
+}
+
+/// Write `summary` as pretty JSON to `path`
+pub fn save_summary(summary: &RunSummary, path: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(summary)?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write summary to {}", path))
+}
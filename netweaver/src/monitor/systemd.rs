@@ -0,0 +1,77 @@
+// systemd notification for the monitor daemon
+// Under a `Type=notify` unit, systemd passes a datagram socket in
+// NOTIFY_SOCKET and waits for `READY=1` before it counts the service as
+// started; with `WatchdogSec=` it also sets WATCHDOG_USEC and restarts us if
+// `WATCHDOG=1` doesn't arrive that often. The protocol is one line of
+// `KEY=value` pairs per datagram, so a unix socket is all it takes, no
+// libsystemd. A name starting with `@` is in the abstract namespace, which
+// only Linux has. Without NOTIFY_SOCKET nothing is sent, and a failed send
+// is only logged: systemd going away must not stop the sampling.
+
+use anyhow::{Context, Result};
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Sends state changes to the service manager
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    path: String,
+}
+
+impl Notifier {
+    /// The notifier NOTIFY_SOCKET asks for, or None when we aren't run by a
+    /// service manager that wants to hear from us
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("NOTIFY_SOCKET").ok().filter(|path| !path.is_empty())?;
+        Self::connect(&path)
+            .map_err(|e| tracing::warn!("Not notifying systemd: {:#}", e))
+            .ok()
+    }
+
+    /// Send to the socket at `path`, `@name` for an abstract one
+    pub fn connect(path: &str) -> Result<Self> {
+        let socket = UnixDatagram::unbound().context("Failed to create the notification socket")?;
+        match path.strip_prefix('@') {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+                socket.connect_addr(&address)
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Some(_) => anyhow::bail!("Abstract notification socket '{}' isn't supported here", path),
+            None => socket.connect(path),
+        }
+        .with_context(|| format!("Failed to connect to the notification socket '{}'", path))?;
+        Ok(Self { socket, path: path.to_string() })
+    }
+
+    /// Send `state`, e.g. `READY=1`; a failure is logged and otherwise ignored
+    pub fn notify(&self, state: &str) {
+        match self.socket.send(state.as_bytes()) {
+            Ok(_) => tracing::debug!("Sent {:?} to {}", state, self.path),
+            Err(e) => tracing::warn!("Failed to send {:?} to {}: {}", state, self.path, e),
+        }
+    }
+}
+
+/// How often to send `WATCHDOG=1`: half of WATCHDOG_USEC, so one late tick
+/// doesn't get us killed; None without a watchdog or when it is meant for
+/// another process (WATCHDOG_PID)
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok();
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    watchdog_interval_from(usec.as_deref(), pid.as_deref(), std::process::id())
+}
+
+/// watchdog_interval for the given variables, as process `our_pid`
+pub fn watchdog_interval_from(usec: Option<&str>, pid: Option<&str>, our_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.trim().parse::<u32>().ok()? != our_pid {
+            return None;
+        }
+    }
+    let usec: u64 = usec?.trim().parse().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
@@ -0,0 +1,481 @@
+// Top talkers (`monitor --top-talkers`)
+// Interface counters say how much moved, not to whom. With this mode the
+// monitor also captures on the monitored interfaces (the default-route one
+// for `all`) and adds each packet to the remote host on the other end: an
+// address that isn't one of ours, with the lower of the two ports standing
+// in for the service. Hosts and ports are kept in tables capped like
+// inspect's, so a busy link can't exhaust memory; once one fills, its
+// quietest half is dropped and the ranking becomes approximate. Names come
+// from reverse DNS, looked up in the background the first time a host is
+// shown. When the monitor stops, the final ranking goes to the JSON log and
+// is kept in the data directory for `report`.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use hickory_resolver::TokioAsyncResolver;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::sample_log::SampleLog;
+use super::{capture_interfaces, ALL_INTERFACES};
+use crate::diagnostics::capture::{self, DecodedPacket, Protocol};
+use crate::diagnostics::traffic::Volume;
+use crate::utils;
+use crate::{report, status};
+
+/// Remote hosts tracked before the quietest are dropped
+pub const MAX_HOSTS: usize = 4_096;
+
+/// Service ports tracked, overall and per host, before the quietest are dropped
+pub const MAX_PORTS: usize = 1_024;
+const MAX_HOST_PORTS: usize = 16;
+
+/// Ports listed with each host
+const HOST_PORTS: usize = 3;
+
+/// How often the plain (non-dashboard) leaderboard is printed
+const LEADERBOARD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Names remembered before the cache starts over
+const MAX_NAMES: usize = 4_096;
+
+/// How long the final ranking waits for names still being looked up
+const NAME_WAIT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default)]
+struct Host {
+    /// What we sent it
+    sent: Volume,
+    /// What it sent us
+    received: Volume,
+    /// Bytes per service port
+    ports: HashMap<u16, u64>,
+    /// Bytes sent and received at the last tick, and the rates since the one before
+    ticked: (u64, u64),
+    rates: (f64, f64),
+}
+
+/// Traffic per remote host and service port, bounded
+#[derive(Debug, Clone, Default)]
+pub struct TalkerTable {
+    /// Our own addresses; the other end of a packet is the remote host
+    local: Vec<IpAddr>,
+    hosts: HashMap<IpAddr, Host>,
+    ports: HashMap<(Protocol, u16), Volume>,
+    /// Whether a table overflowed, making the ranking approximate
+    pub truncated: bool,
+}
+
+impl TalkerTable {
+    /// A table for traffic to and from the addresses in `local`
+    pub fn new(local: Vec<IpAddr>) -> Self {
+        Self { local, ..Default::default() }
+    }
+
+    /// Add a packet to its remote host and service port; ARP and packets
+    /// without addresses are left out
+    pub fn record(&mut self, packet: &DecodedPacket) {
+        let (Some(src), Some(dst)) = (packet.src, packet.dst) else {
+            return;
+        };
+        if packet.protocol == Protocol::Arp {
+            return;
+        }
+        let outbound = self.local.contains(&src);
+        let remote = if outbound { dst } else { src };
+        let port = packet.src_port.zip(packet.dst_port).map(|(src, dst)| src.min(dst));
+        let bytes = packet.length as u64;
+
+        self.truncated |= make_room(&mut self.hosts, &remote, MAX_HOSTS, |host| host.sent.bytes + host.received.bytes);
+        let host = self.hosts.entry(remote).or_default();
+        let volume = if outbound { &mut host.sent } else { &mut host.received };
+        volume.packets += 1;
+        volume.bytes += bytes;
+        if let Some(port) = port {
+            make_room(&mut host.ports, &port, MAX_HOST_PORTS, |bytes| *bytes);
+            *host.ports.entry(port).or_default() += bytes;
+            let key = (packet.protocol, port);
+            self.truncated |= make_room(&mut self.ports, &key, MAX_PORTS, |volume| volume.bytes);
+            let volume = self.ports.entry(key).or_default();
+            volume.packets += 1;
+            volume.bytes += bytes;
+        }
+    }
+
+    /// Work out each host's rates over the `seconds` since the last tick
+    pub fn tick(&mut self, seconds: f64) {
+        for host in self.hosts.values_mut() {
+            let per_sec = |now: u64, then: u64| if seconds > 0.0 { now.saturating_sub(then) as f64 / seconds } else { 0.0 };
+            host.rates = (per_sec(host.sent.bytes, host.ticked.0), per_sec(host.received.bytes, host.ticked.1));
+            host.ticked = (host.sent.bytes, host.received.bytes);
+        }
+    }
+
+    /// The `limit` hosts that moved the most bytes, with their rates over
+    /// the last tick
+    pub fn leaderboard(&self, limit: usize) -> Vec<Talker> {
+        let mut hosts: Vec<(&IpAddr, &Host)> = self.hosts.iter().collect();
+        hosts.sort_by_key(|(ip, host)| (std::cmp::Reverse(host.sent.bytes + host.received.bytes), **ip));
+        hosts
+            .into_iter()
+            .take(limit)
+            .map(|(ip, host)| {
+                let mut ports: Vec<(&u16, &u64)> = host.ports.iter().collect();
+                ports.sort_by_key(|(port, bytes)| (std::cmp::Reverse(**bytes), **port));
+                Talker {
+                    ip: *ip,
+                    name: None,
+                    sent: host.sent,
+                    received: host.received,
+                    ports: ports.into_iter().take(HOST_PORTS).map(|(port, _)| *port).collect(),
+                    tx_bytes_per_sec: host.rates.0,
+                    rx_bytes_per_sec: host.rates.1,
+                }
+            })
+            .collect()
+    }
+
+    /// The `limit` busiest service ports
+    pub fn top_ports(&self, limit: usize) -> Vec<PortTotal> {
+        let mut ports: Vec<PortTotal> = self
+            .ports
+            .iter()
+            .map(|(&(protocol, port), &volume)| PortTotal { protocol, port, volume })
+            .collect();
+        ports.sort_by_key(|total| (std::cmp::Reverse(total.volume.bytes), total.port));
+        ports.truncate(limit);
+        ports
+    }
+}
+
+/// Make room for `key` in a `table` holding `limit` entries by dropping
+/// the quietest half, by `bytes`; true when entries had to go
+fn make_room<K: Eq + Hash, V>(table: &mut HashMap<K, V>, key: &K, limit: usize, bytes: impl Fn(&V) -> u64) -> bool {
+    if table.len() < limit || table.contains_key(key) {
+        return false;
+    }
+    let mut entries: Vec<(K, V)> = table.drain().collect();
+    let keep = limit / 2;
+    entries.select_nth_unstable_by_key(keep, |(_, value)| std::cmp::Reverse(bytes(value)));
+    entries.truncate(keep);
+    table.extend(entries);
+    true
+}
+
+/// One remote host's traffic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Talker {
+    pub ip: IpAddr,
+    /// Reverse DNS name, once looked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// What we sent it
+    pub sent: Volume,
+    /// What it sent us
+    pub received: Volume,
+    /// Its busiest service ports, busiest first
+    pub ports: Vec<u16>,
+    /// Over the last refresh in a leaderboard, over the whole capture in
+    /// the final ranking
+    pub tx_bytes_per_sec: f64,
+    pub rx_bytes_per_sec: f64,
+}
+
+impl Talker {
+    pub fn bytes(&self) -> u64 {
+        self.sent.bytes + self.received.bytes
+    }
+
+    /// Its name, or its address until it has one
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.ip.to_string())
+    }
+}
+
+/// Traffic to and from one service port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortTotal {
+    pub protocol: Protocol,
+    pub port: u16,
+    #[serde(flatten)]
+    pub volume: Volume,
+}
+
+/// The final ranking of a `--top-talkers` run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TalkersReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Interfaces captured on
+    pub interfaces: Vec<String>,
+    pub hosts: Vec<Talker>,
+    pub ports: Vec<PortTotal>,
+    /// The ranking is approximate: some quiet hosts or ports were dropped
+    /// to bound memory
+    pub truncated: bool,
+}
+
+impl TalkersReport {
+    /// The top `limit` hosts and ports of `table`, with rates averaged over
+    /// the capture
+    pub fn new(table: &TalkerTable, limit: usize, interfaces: Vec<String>, started_at: DateTime<Utc>, finished_at: DateTime<Utc>) -> Self {
+        let seconds = (finished_at - started_at).num_milliseconds() as f64 / 1000.0;
+        let average = |bytes: u64| if seconds > 0.0 { bytes as f64 / seconds } else { 0.0 };
+        let hosts = table
+            .leaderboard(limit)
+            .into_iter()
+            .map(|talker| Talker {
+                tx_bytes_per_sec: average(talker.sent.bytes),
+                rx_bytes_per_sec: average(talker.received.bytes),
+                ..talker
+            })
+            .collect();
+        Self { started_at, finished_at, interfaces, hosts, ports: table.top_ports(limit), truncated: table.truncated }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Lookup {
+    Pending,
+    Done(Option<String>),
+}
+
+/// Reverse DNS names, looked up in the background on first use
+#[derive(Clone)]
+pub struct NameCache {
+    resolver: TokioAsyncResolver,
+    names: Arc<Mutex<HashMap<IpAddr, Lookup>>>,
+}
+
+impl NameCache {
+    pub fn new() -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+            use hickory_resolver::config::*;
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+        Self { resolver, names: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// The name of `ip` if it's known yet; the first call starts the lookup
+    pub fn name(&self, ip: IpAddr) -> Option<String> {
+        let mut names = self.names.lock().unwrap_or_else(|e| e.into_inner());
+        match names.get(&ip) {
+            Some(Lookup::Done(name)) => return name.clone(),
+            Some(Lookup::Pending) => return None,
+            None => {}
+        }
+        if names.len() >= MAX_NAMES {
+            names.clear();
+        }
+        names.insert(ip, Lookup::Pending);
+        let (resolver, names) = (self.resolver.clone(), Arc::clone(&self.names));
+        tokio::spawn(async move {
+            let name = resolver
+                .reverse_lookup(ip)
+                .await
+                .ok()
+                .and_then(|lookup| lookup.iter().next().map(|name| name.to_utf8().trim_end_matches('.').to_string()));
+            names.lock().unwrap_or_else(|e| e.into_inner()).insert(ip, Lookup::Done(name));
+        });
+        None
+    }
+
+    /// Name every talker whose name is known, starting lookups for the rest
+    pub fn fill(&self, talkers: &mut [Talker]) {
+        for talker in talkers {
+            talker.name = self.name(talker.ip);
+        }
+    }
+
+    /// Name every talker, waiting up to `wait` for lookups still running
+    pub async fn settle(&self, talkers: &mut [Talker], wait: Duration) {
+        self.fill(talkers);
+        let deadline = Instant::now() + wait;
+        while Instant::now() < deadline && talkers.iter().any(|talker| self.is_pending(talker.ip)) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        self.fill(talkers);
+    }
+
+    fn is_pending(&self, ip: IpAddr) -> bool {
+        matches!(self.names.lock().unwrap_or_else(|e| e.into_inner()).get(&ip), Some(Lookup::Pending))
+    }
+}
+
+impl Default for NameCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Capture threads feeding a shared `TalkerTable`, one per interface
+pub struct TalkerCapture {
+    table: Arc<Mutex<TalkerTable>>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<std::thread::JoinHandle<Result<()>>>,
+    interfaces: Vec<String>,
+    started_at: DateTime<Utc>,
+    last_tick: Instant,
+}
+
+impl TalkerCapture {
+    /// Start capturing on the interfaces `spec` names, or on the
+    /// default-route one for `all`; needs root
+    pub fn start(spec: &str) -> Result<Self> {
+        let interfaces = capture_interfaces(spec)?;
+        let local = interfaces.iter().flat_map(|iface| iface.ips.iter().map(|net| net.ip())).collect();
+        let table = Arc::new(Mutex::new(TalkerTable::new(local)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let shared = Arc::clone(&table);
+        let threads = capture::spawn_readers(&interfaces, &stop, move |frame| {
+            if let Some(frame) = frame {
+                let packet = capture::decode_frame(frame.linktype, frame.data, frame.data.len());
+                shared.lock().unwrap_or_else(|e| e.into_inner()).record(&packet);
+            }
+        })?;
+        Ok(Self {
+            table,
+            stop,
+            threads,
+            interfaces: interfaces.into_iter().map(|iface| iface.name).collect(),
+            started_at: Utc::now(),
+            last_tick: Instant::now(),
+        })
+    }
+
+    /// Interfaces being captured on, for display
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// The top `limit` hosts, with their rates since the previous call
+    pub fn leaderboard(&mut self, limit: usize) -> Vec<Talker> {
+        let mut table = self.table.lock().unwrap_or_else(|e| e.into_inner());
+        table.tick(self.last_tick.elapsed().as_secs_f64());
+        self.last_tick = Instant::now();
+        table.leaderboard(limit)
+    }
+
+    /// Stop capturing and rank the top `limit` hosts and ports
+    pub fn finish(self, limit: usize) -> Result<TalkersReport> {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads {
+            thread.join().map_err(|_| anyhow::anyhow!("A capture thread panicked"))??;
+        }
+        let table = self.table.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(TalkersReport::new(&table, limit, self.interfaces, self.started_at, Utc::now()))
+    }
+}
+
+/// Print the leaderboard until Ctrl+C, then the final ranking
+pub async fn run(interface: &str, limit: usize, mut log: Option<SampleLog>) -> Result<()> {
+    let mut capture = TalkerCapture::start(interface)?;
+    let names = NameCache::new();
+    status!("🔍 Capturing on: {}", capture.interfaces().join(", ").bright_yellow());
+    status!("\n{}", "Ranking remote hosts... (Press Ctrl+C to stop)".bright_green());
+
+    let mut ticker = tokio::time::interval(LEADERBOARD_INTERVAL);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let mut talkers = capture.leaderboard(limit);
+                names.fill(&mut talkers);
+                print_talkers(&format!("Top Talkers ({}s)", (Utc::now() - capture.started_at).num_seconds()), &talkers);
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    let report = finish(capture, limit, &names, log.as_mut()).await?;
+    print_report(&report);
+    if let Some(log) = &mut log {
+        log.flush()?;
+    }
+    Ok(())
+}
+
+/// Stop `capture` and rank its hosts, name them, append the ranking to
+/// `log` and keep it for `report`
+pub async fn finish(capture: TalkerCapture, limit: usize, names: &NameCache, log: Option<&mut SampleLog>) -> Result<TalkersReport> {
+    let mut report = capture.finish(limit)?;
+    names.settle(&mut report.hosts, NAME_WAIT).await;
+    if let Some(log) = log {
+        log.write_talkers(&report)?;
+    }
+    if let Err(e) = latest_path().and_then(|path| store_latest(&path, &report)) {
+        status!("⚠️  Could not keep the top talkers for reports: {:#}", e);
+    }
+    Ok(report)
+}
+
+fn print_talkers(title: &str, talkers: &[Talker]) {
+    report!("\n{}", title.bright_cyan().bold());
+    if talkers.is_empty() {
+        report!("  No traffic yet");
+        return;
+    }
+    report!("  {:<40} {:>12} {:>12} {:>11}  {}", "Host", "TX/s", "RX/s", "Total", "Ports");
+    for talker in talkers {
+        let ports: Vec<String> = talker.ports.iter().map(u16::to_string).collect();
+        report!("  {:<40} {:>12} {:>12} {:>11}  {}", talker.label().bright_green(),
+                utils::format_bandwidth(talker.tx_bytes_per_sec), utils::format_bandwidth(talker.rx_bytes_per_sec),
+                utils::format_bytes(talker.bytes()), ports.join(", "));
+    }
+}
+
+/// The final ranking: hosts with their average rates, then the busiest ports
+pub fn print_report(report: &TalkersReport) {
+    let seconds = (report.finished_at - report.started_at).num_seconds();
+    print_talkers(&format!("Top Talkers on {} over {}s (average rates)", report.interfaces.join(", "), seconds), &report.hosts);
+    if !report.ports.is_empty() {
+        report!("\n{}", "Busiest Ports".bright_cyan().bold());
+        for total in &report.ports {
+            report!("  {:<6} {:>5} {:>11} {:>8} packets", total.protocol, total.port, utils::format_bytes(total.volume.bytes),
+                    total.volume.packets);
+        }
+    }
+    if report.truncated {
+        report!("\n{}", format!("  Rankings are approximate: only the busiest {} hosts are tracked", MAX_HOSTS).bright_yellow());
+    }
+}
+
+/// `$XDG_DATA_HOME/netweaver/talkers.json`, the latest final ranking
+pub fn latest_path() -> Result<PathBuf> {
+    Ok(utils::data_dir()?.join("talkers.json"))
+}
+
+/// The latest final ranking, if `--top-talkers` ever ran here
+pub fn load_latest(path: &Path) -> Result<Option<TalkersReport>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+    }
+}
+
+pub fn store_latest(path: &Path, report: &TalkersReport) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(report)?)
+        .with_context(|| format!("Failed to write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace '{}'", path.display()))
+}
+
+/// What the capture covers, for the monitor's banner
+pub fn describe(spec: &str) -> String {
+    if spec == ALL_INTERFACES {
+        "the default-route interface".to_string()
+    } else {
+        spec.to_string()
+    }
+}
@@ -0,0 +1,459 @@
+// Wi-Fi link (`monitor` on a wireless interface)
+// On a laptop a slow network is usually a bad Wi-Fi link, which the traffic
+// counters don't show. So at every sample the realtime view and the daemon
+// also read the link of each monitored interface that is wireless: the
+// signal in dBm, the TX bitrate, the frequency and channel, and the SSID it
+// is connected to. Linux asks nl80211 over generic netlink (the interface,
+// then its station, which is the access point), falling back to the signal
+// column of /proc/net/wireless; macOS parses `airport -I`; Windows queries
+// the WLAN API. Wired interfaces have no link here and show no Wi-Fi panel.
+// The dashboard keeps a signal sparkline per interface, JSON logs get a
+// `{"wifi": …}` line per sample, and `signal < -70dBm` alert rules fire on a
+// weak signal.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use super::alerts::AlertInput;
+use super::{watches, NetworkStats, ALL_INTERFACES};
+
+/// Points kept for an interface's signal sparkline
+const SIGNAL_POINTS: usize = 300;
+
+/// Signal below which the link counts as weak, and the steps above it
+const GOOD_DBM: i32 = -60;
+const FAIR_DBM: i32 = -70;
+const EXCELLENT_DBM: i32 = -50;
+
+/// One wireless interface's link at one sample, as logged
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WifiLink {
+    pub timestamp: DateTime<Utc>,
+    pub interface: String,
+    /// The network connected to; None when not connected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal_dbm: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_bitrate_mbps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_mhz: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u32>,
+}
+
+impl WifiLink {
+    fn new(interface: &str) -> Self {
+        Self { timestamp: Utc::now(), interface: interface.to_string(), ..Default::default() }
+    }
+
+    /// `excellent`, `good`, `fair` or `weak`; None without a signal
+    pub fn quality(&self) -> Option<&'static str> {
+        Some(match self.signal_dbm? {
+            dbm if dbm >= EXCELLENT_DBM => "excellent",
+            dbm if dbm >= GOOD_DBM => "good",
+            dbm if dbm >= FAIR_DBM => "fair",
+            _ => "weak",
+        })
+    }
+
+    /// "HomeNet, -52 dBm (good), TX 866.7 Mb/s, ch 36 (5180 MHz)"
+    pub fn summary(&self) -> String {
+        let signal = self.signal_dbm.zip(self.quality()).map(|(dbm, quality)| format!("{} dBm ({})", dbm, quality));
+        let parts: Vec<String> = [
+            Some(self.ssid.clone().unwrap_or_else(|| "not connected".to_string())),
+            signal,
+            self.tx_bitrate_mbps.map(|rate| format!("TX {:.1} Mb/s", rate)),
+            self.describe_channel(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        parts.join(", ")
+    }
+
+    /// "ch 36 (5180 MHz)", with what is known of the two
+    pub fn describe_channel(&self) -> Option<String> {
+        match (self.channel, self.frequency_mhz) {
+            (Some(channel), Some(frequency)) => Some(format!("ch {} ({} MHz)", channel, frequency)),
+            (Some(channel), None) => Some(format!("ch {}", channel)),
+            (None, Some(frequency)) => Some(format!("{} MHz", frequency)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The channel number of a 2.4, 5 or 6 GHz frequency
+pub fn channel(frequency_mhz: u32) -> Option<u32> {
+    match frequency_mhz {
+        2484 => Some(14),
+        2412..=2472 => Some((frequency_mhz - 2407) / 5),
+        5955..=7115 => Some((frequency_mhz - 5950) / 5),
+        5000..=5900 => Some((frequency_mhz - 5000) / 5),
+        _ => None,
+    }
+}
+
+/// The link of every monitored interface in `stats` that is wireless; one
+/// that can't be read is left out (and logged at debug level)
+pub fn links(stats: &[NetworkStats], interface: &str) -> Vec<WifiLink> {
+    stats
+        .iter()
+        .filter(|row| row.interface != ALL_INTERFACES && watches(interface, &row.interface))
+        .filter_map(|row| match read(&row.interface) {
+            Ok(link) => link,
+            Err(e) => {
+                tracing::debug!("Failed to read the Wi-Fi link of {}: {:#}", row.interface, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Let the alert rules see the signal of each interface in `links`
+pub fn add_signal(inputs: &mut [AlertInput], links: &[WifiLink]) {
+    for input in inputs.iter_mut().filter(|input| input.latency_ms.is_none()) {
+        input.signal_dbm = links
+            .iter()
+            .find(|link| link.interface == input.interface)
+            .and_then(|link| link.signal_dbm)
+            .map(f64::from);
+    }
+}
+
+/// One wireless interface's latest link and signal history
+#[derive(Debug, Clone)]
+pub struct WifiStats {
+    pub link: WifiLink,
+    /// Signal in dBm above -110, for the sparkline
+    points: VecDeque<u64>,
+}
+
+impl WifiStats {
+    pub fn points(&self) -> &VecDeque<u64> {
+        &self.points
+    }
+}
+
+/// Add a sample's `links` to `stats`; interfaces that are gone, or no
+/// longer watched, are dropped
+pub fn record(stats: &mut Vec<WifiStats>, links: &[WifiLink]) {
+    stats.retain(|stats| links.iter().any(|link| link.interface == stats.link.interface));
+    for link in links {
+        let position = stats.iter().position(|stats| stats.link.interface == link.interface);
+        let entry = match position {
+            Some(index) => &mut stats[index],
+            None => {
+                stats.push(WifiStats { link: link.clone(), points: VecDeque::with_capacity(SIGNAL_POINTS) });
+                stats.last_mut().unwrap()
+            }
+        };
+        entry.link = link.clone();
+        if let Some(dbm) = link.signal_dbm {
+            if entry.points.len() == SIGNAL_POINTS {
+                entry.points.pop_front();
+            }
+            entry.points.push_back((dbm + 110).max(0) as u64);
+        }
+    }
+}
+
+/// Signal levels in a /proc/net/wireless listing: two header lines, then
+/// `name: status link level noise …`, the level in dBm with a trailing dot
+pub fn parse_proc_net_wireless(text: &str) -> Vec<(String, i32)> {
+    text.lines()
+        .skip(2)
+        .filter_map(|line| {
+            let (name, values) = line.split_once(':')?;
+            let level: f64 = values.split_whitespace().nth(2)?.trim_end_matches('.').parse().ok()?;
+            Some((name.trim().to_string(), level as i32))
+        })
+        .collect()
+}
+
+/// What `airport -I` says of the Wi-Fi link of `interface`; None when Wi-Fi
+/// is off. Not connected shows as a link without an SSID or signal
+pub fn parse_airport(interface: &str, text: &str) -> Option<WifiLink> {
+    let field = |name: &str| {
+        text.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim().to_string())
+        })
+    };
+    if field("AirPort").is_some_and(|state| state.eq_ignore_ascii_case("off")) {
+        return None;
+    }
+    let mut link = WifiLink::new(interface);
+    let associated = field("state").is_none_or(|state| state == "running");
+    if associated {
+        link.ssid = field("SSID").filter(|ssid| !ssid.is_empty());
+        link.signal_dbm = field("agrCtlRSSI").and_then(|rssi| rssi.parse().ok()).filter(|rssi| *rssi < 0);
+        link.tx_bitrate_mbps = field("lastTxRate").and_then(|rate| rate.parse().ok());
+        // `149,80`: the primary channel, then the width
+        link.channel = field("channel").and_then(|channel| channel.split(',').next()?.trim().parse().ok());
+    }
+    Some(link)
+}
+
+/// The Wi-Fi devices in `networksetup -listallhardwareports`
+pub fn parse_hardware_ports(text: &str) -> Vec<String> {
+    let mut devices = Vec::new();
+    let mut wifi = false;
+    for line in text.lines() {
+        if let Some(port) = line.strip_prefix("Hardware Port:") {
+            wifi = matches!(port.trim(), "Wi-Fi" | "AirPort");
+        } else if let Some(device) = line.strip_prefix("Device:") {
+            if wifi {
+                devices.push(device.trim().to_string());
+            }
+        }
+    }
+    devices
+}
+
+/// The link of `interface`, or None when it isn't wireless
+#[cfg(target_os = "linux")]
+pub fn read(interface: &str) -> Result<Option<WifiLink>> {
+    let sysfs = |entry: &str| std::path::Path::new(&format!("/sys/class/net/{}/{}", interface, entry)).exists();
+    if !sysfs("wireless") && !sysfs("phy80211") {
+        return Ok(None);
+    }
+    match nl80211::read(interface) {
+        Ok(link) => Ok(Some(link)),
+        Err(e) => {
+            tracing::debug!("nl80211 failed for {}, reading /proc/net/wireless: {:#}", interface, e);
+            let text = std::fs::read_to_string("/proc/net/wireless")?;
+            let mut link = WifiLink::new(interface);
+            link.signal_dbm = parse_proc_net_wireless(&text)
+                .into_iter()
+                .find(|(name, _)| name == interface)
+                .map(|(_, level)| level)
+                .filter(|level| *level < 0);
+            Ok(Some(link))
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod nl80211 {
+    use anyhow::{Context, Result};
+    use neli::consts::nl::{NlmF, NlmFFlags};
+    use neli::consts::socket::NlFamily;
+    use neli::genl::{Genlmsghdr, Nlattr};
+    use neli::nl::{NlPayload, Nlmsghdr};
+    use neli::socket::NlSocketHandle;
+    use neli::types::GenlBuffer;
+
+    use super::WifiLink;
+
+    #[neli::neli_enum(serialized_type = "u8")]
+    pub enum Command {
+        GetInterface = 5,
+        GetStation = 17,
+    }
+    impl neli::consts::genl::Cmd for Command {}
+
+    #[neli::neli_enum(serialized_type = "u16")]
+    pub enum Attribute {
+        Ifindex = 3,
+        StaInfo = 21,
+        WiphyFreq = 38,
+        Ssid = 52,
+    }
+    impl neli::consts::genl::NlAttrType for Attribute {}
+
+    #[neli::neli_enum(serialized_type = "u16")]
+    pub enum StationInfo {
+        /// Signal of the last frames received, in dBm as an i8
+        Signal = 7,
+        TxBitrate = 8,
+    }
+    impl neli::consts::genl::NlAttrType for StationInfo {}
+
+    #[neli::neli_enum(serialized_type = "u16")]
+    pub enum RateInfo {
+        /// 100 kbit/s units, as a u16
+        Bitrate = 1,
+        /// The same as a u32, for rates past 6.5 Gbit/s
+        Bitrate32 = 5,
+    }
+    impl neli::consts::genl::NlAttrType for RateInfo {}
+
+    type Message = Genlmsghdr<Command, Attribute>;
+
+    /// Ask for `command` about the interface at `ifindex`, dumping with `dump`
+    fn request(socket: &mut NlSocketHandle, family: u16, command: Command, ifindex: u32, dump: bool) -> Result<Vec<Message>> {
+        let mut attrs = GenlBuffer::new();
+        attrs.push(Nlattr::new(false, false, Attribute::Ifindex, ifindex).map_err(|e| anyhow::anyhow!("{}", e))?);
+        let flags = if dump { NlmFFlags::new(&[NlmF::Request, NlmF::Dump]) } else { NlmFFlags::new(&[NlmF::Request]) };
+        let message = Nlmsghdr::new(None, family, flags, None, None, NlPayload::Payload(Genlmsghdr::new(command, 1, attrs)));
+        socket.send(message).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut replies = Vec::new();
+        for reply in socket.iter::<u16, Message>(false) {
+            if let NlPayload::Payload(payload) = reply.map_err(|e| anyhow::anyhow!("{}", e))?.nl_payload {
+                replies.push(payload);
+            }
+        }
+        Ok(replies)
+    }
+
+    pub fn read(interface: &str) -> Result<WifiLink> {
+        let name = std::ffi::CString::new(interface)?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(std::io::Error::last_os_error()).with_context(|| format!("No interface {}", interface));
+        }
+        let mut socket = NlSocketHandle::connect(NlFamily::Generic, None, &[]).context("Failed to open a generic netlink socket")?;
+        let family = socket.resolve_genl_family("nl80211").map_err(|e| anyhow::anyhow!("nl80211 isn't available: {}", e))?;
+
+        let mut link = WifiLink::new(interface);
+        for reply in request(&mut socket, family, Command::GetInterface, ifindex, false)? {
+            let attrs = reply.get_attr_handle();
+            if let Some(ssid) = attrs.get_attribute(Attribute::Ssid) {
+                link.ssid = Some(String::from_utf8_lossy(ssid.nla_payload.as_ref()).into_owned()).filter(|ssid| !ssid.is_empty());
+            }
+            link.frequency_mhz = attrs.get_attr_payload_as::<u32>(Attribute::WiphyFreq).ok();
+        }
+        link.channel = link.frequency_mhz.and_then(super::channel);
+        // A client interface has one station: the access point
+        for reply in request(&mut socket, family, Command::GetStation, ifindex, true)? {
+            let mut attrs = reply.get_attr_handle();
+            let Ok(mut info) = attrs.get_nested_attributes::<StationInfo>(Attribute::StaInfo) else {
+                continue;
+            };
+            link.signal_dbm = info.get_attr_payload_as::<u8>(StationInfo::Signal).ok().map(|signal| i32::from(signal as i8));
+            if let Ok(rate) = info.get_nested_attributes::<RateInfo>(StationInfo::TxBitrate) {
+                let units = rate.get_attr_payload_as::<u32>(RateInfo::Bitrate32).ok()
+                    .or_else(|| rate.get_attr_payload_as::<u16>(RateInfo::Bitrate).ok().map(u32::from));
+                link.tx_bitrate_mbps = units.map(|units| f64::from(units) / 10.0);
+            }
+        }
+        Ok(link)
+    }
+}
+
+/// The `airport` tool that reports the Wi-Fi link on macOS
+#[cfg(target_os = "macos")]
+const AIRPORT: &str = "/System/Library/PrivateFrameworks/Apple80211.framework/Versions/Current/Resources/airport";
+
+#[cfg(target_os = "macos")]
+pub fn read(interface: &str) -> Result<Option<WifiLink>> {
+    use anyhow::Context;
+    use std::sync::OnceLock;
+
+    // Which devices are Wi-Fi doesn't change while we run
+    static DEVICES: OnceLock<Vec<String>> = OnceLock::new();
+    let devices = DEVICES.get_or_init(|| {
+        std::process::Command::new("networksetup")
+            .arg("-listallhardwareports")
+            .output()
+            .map(|out| parse_hardware_ports(&String::from_utf8_lossy(&out.stdout)))
+            .unwrap_or_default()
+    });
+    if !devices.iter().any(|device| device == interface) {
+        return Ok(None);
+    }
+    let out = std::process::Command::new(AIRPORT).arg("-I").output().context("Failed to run airport -I")?;
+    if !out.status.success() {
+        anyhow::bail!("airport -I failed: {}", out.status);
+    }
+    Ok(Some(parse_airport(interface, &String::from_utf8_lossy(&out.stdout)).unwrap_or_else(|| WifiLink::new(interface))))
+}
+
+#[cfg(windows)]
+pub fn read(interface: &str) -> Result<Option<WifiLink>> {
+    use windows_sys::core::GUID;
+    use windows_sys::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{ConvertInterfaceGuidToLuid, ConvertInterfaceLuidToAlias};
+    use windows_sys::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+    use windows_sys::Win32::NetworkManagement::WiFi::{
+        wlan_interface_state_connected, wlan_intf_opcode_channel_number, wlan_intf_opcode_current_connection,
+        wlan_intf_opcode_rssi, WlanCloseHandle, WlanEnumInterfaces, WlanFreeMemory, WlanOpenHandle, WlanQueryInterface,
+        WLAN_CONNECTION_ATTRIBUTES, WLAN_INTERFACE_INFO, WLAN_INTERFACE_INFO_LIST, WLAN_INTF_OPCODE,
+    };
+
+    /// Closes the WLAN client handle when dropped
+    struct Client(HANDLE);
+
+    impl Drop for Client {
+        fn drop(&mut self) {
+            unsafe { WlanCloseHandle(self.0, std::ptr::null()) };
+        }
+    }
+
+    /// The alias `ipconfig` shows for the interface with `guid`
+    fn alias(guid: &GUID) -> Option<String> {
+        let mut luid: NET_LUID_LH = unsafe { std::mem::zeroed() };
+        if unsafe { ConvertInterfaceGuidToLuid(guid, &mut luid) } != ERROR_SUCCESS {
+            return None;
+        }
+        let mut name = [0u16; 257];
+        if unsafe { ConvertInterfaceLuidToAlias(&luid, name.as_mut_ptr(), name.len()) } != ERROR_SUCCESS {
+            return None;
+        }
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        Some(String::from_utf16_lossy(&name[..len]))
+    }
+
+    /// `opcode`'s value for the interface, copied out of WLAN memory
+    fn query<T: Copy>(client: &Client, guid: &GUID, opcode: WLAN_INTF_OPCODE) -> Option<T> {
+        let mut size = 0u32;
+        let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
+        let status = unsafe {
+            WlanQueryInterface(client.0, guid, opcode, std::ptr::null(), &mut size, &mut data, std::ptr::null_mut())
+        };
+        if status != ERROR_SUCCESS || data.is_null() {
+            return None;
+        }
+        let value = (size as usize >= std::mem::size_of::<T>()).then(|| unsafe { std::ptr::read_unaligned(data.cast::<T>()) });
+        unsafe { WlanFreeMemory(data) };
+        value
+    }
+
+    let mut version = 0u32;
+    let mut handle: HANDLE = 0;
+    let status = unsafe { WlanOpenHandle(2, std::ptr::null(), &mut version, &mut handle) };
+    if status != ERROR_SUCCESS {
+        // No WLAN service: nothing here is wireless
+        return Ok(None);
+    }
+    let client = Client(handle);
+    let mut list: *mut WLAN_INTERFACE_INFO_LIST = std::ptr::null_mut();
+    let status = unsafe { WlanEnumInterfaces(client.0, std::ptr::null(), &mut list) };
+    if status != ERROR_SUCCESS {
+        anyhow::bail!("WlanEnumInterfaces failed with error {}", status);
+    }
+    let infos: Vec<WLAN_INTERFACE_INFO> = unsafe {
+        std::slice::from_raw_parts((*list).InterfaceInfo.as_ptr(), (*list).dwNumberOfItems as usize).to_vec()
+    };
+    unsafe { WlanFreeMemory(list.cast()) };
+    let Some(info) = infos.iter().find(|info| alias(&info.InterfaceGuid).as_deref() == Some(interface)) else {
+        return Ok(None);
+    };
+    let mut link = WifiLink::new(interface);
+    if info.isState != wlan_interface_state_connected {
+        return Ok(Some(link));
+    }
+    if let Some(connection) = query::<WLAN_CONNECTION_ATTRIBUTES>(&client, &info.InterfaceGuid, wlan_intf_opcode_current_connection) {
+        let association = connection.wlanAssociationAttributes;
+        let ssid = &association.dot11Ssid;
+        link.ssid = Some(String::from_utf8_lossy(&ssid.ucSSID[..(ssid.uSSIDLength as usize).min(32)]).into_owned());
+        // Kbit/s
+        link.tx_bitrate_mbps = Some(f64::from(association.ulTxRate) / 1000.0);
+        // Quality runs 0 to 100 over -100 to -50 dBm
+        link.signal_dbm = Some(association.wlanSignalQuality as i32 / 2 - 100);
+    }
+    if let Some(rssi) = query::<i32>(&client, &info.InterfaceGuid, wlan_intf_opcode_rssi) {
+        link.signal_dbm = Some(rssi);
+    }
+    link.channel = query::<u32>(&client, &info.InterfaceGuid, wlan_intf_opcode_channel_number);
+    Ok(Some(link))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn read(_interface: &str) -> Result<Option<WifiLink>> {
+    Ok(None)
+}
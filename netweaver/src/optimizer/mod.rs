@@ -4,6 +4,7 @@ use std::time::Instant;
 
 
 use crate::utils;
+use crate::{report, status};
 
 pub async fn run_optimize(
     turbo: bool,
@@ -13,70 +14,70 @@ pub async fn run_optimize(
     all: bool,
     dry_run: bool,
 ) -> Result<()> {
-    println!("{}", "NetWeaver Network Optimizer".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+    status!("{}", "NetWeaver Network Optimizer".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
     if !utils::is_privileged() && !dry_run {
-        println!("{} Optimization requires root privileges for applying changes", "⚠".yellow());
-        println!("Running in dry-run mode (recommendations only)\n");
+        status!("{} Optimization requires root privileges for applying changes", "⚠".yellow());
+        status!("Running in dry-run mode (recommendations only)\n");
     }
     
     if turbo || all {
-        println!("{}", "🚀 Turbo Mode Analysis".bright_green().bold());
+        report!("{}", "🚀 Turbo Mode Analysis".bright_green().bold());
         analyze_turbo_mode(dry_run).await?;
     }
     
     if dns || all {
-        println!("\n{}", "🌐 DNS Optimization".bright_green().bold());
+        report!("\n{}", "🌐 DNS Optimization".bright_green().bold());
         optimize_dns(dry_run).await?;
     }
     
     if mtu || all {
-        println!("\n{}", "📦 MTU Optimization".bright_green().bold());
+        report!("\n{}", "📦 MTU Optimization".bright_green().bold());
         optimize_mtu(dry_run).await?;
     }
     
     if tcp || all {
-        println!("\n{}", "🔧 TCP Parameters".bright_green().bold());
+        report!("\n{}", "🔧 TCP Parameters".bright_green().bold());
         optimize_tcp(dry_run).await?;
     }
     
-    println!("\n{}", "✅ Optimization scan complete!".bright_green().bold());
+    status!("\n{}", "✅ Optimization scan complete!".bright_green().bold());
     
     Ok(())
 }
 
 async fn analyze_turbo_mode(dry_run: bool) -> Result<()> {
-    println!("Analyzing network performance patterns...\n");
+    status!("Analyzing network performance patterns...\n");
     
     let metrics = gather_network_metrics().await?;
     
-    println!("Current Network Metrics:");
-    println!("  ⚡ Avg Latency: {:.2}ms", metrics.avg_latency);
-    println!("  📊 Bandwidth: {}", utils::format_bandwidth(metrics.bandwidth));
-    println!("  📉 Packet Loss: {:.2}%", metrics.packet_loss);
-    println!("  🔄 Retransmits: {}", metrics.retransmits);
+    report!("Current Network Metrics:");
+    report!("  ⚡ Avg Latency: {:.2}ms", metrics.avg_latency);
+    report!("  📊 Bandwidth: {}", utils::format_bandwidth(metrics.bandwidth));
+    report!("  📉 Packet Loss: {:.2}%", metrics.packet_loss);
+    report!("  🔄 Retransmits: {}", metrics.retransmits);
     
     let recommendations = generate_recommendations(&metrics);
     
-    println!("\n{}", "Recommendations:".bright_cyan());
+    report!("\n{}", "Recommendations:".bright_cyan());
     for (idx, rec) in recommendations.iter().enumerate() {
-        println!("  {}. {}", idx + 1, rec.bright_yellow());
+        report!("  {}. {}", idx + 1, rec.bright_yellow());
     }
     
     if !dry_run && utils::is_privileged() {
-        println!("\n{}", "Applying optimizations...".bright_green());
+        status!("\n{}", "Applying optimizations...".bright_green());
         apply_optimizations(&recommendations)?;
-        println!("{}", "✓ Optimizations applied".bright_green());
+        report!("{}", "✓ Optimizations applied".bright_green());
     } else {
-        println!("\n{}", "⚠ Dry-run mode: No changes applied".yellow());
+        report!("\n{}", "⚠ Dry-run mode: No changes applied".yellow());
     }
     
     Ok(())
 }
 
 async fn optimize_dns(dry_run: bool) -> Result<()> {
-    println!("Benchmarking DNS resolvers...\n");
+    status!("Benchmarking DNS resolvers...\n");
     
     let resolvers = vec![
         ("Google DNS", "8.8.8.8"),
@@ -93,24 +94,24 @@ async fn optimize_dns(dry_run: bool) -> Result<()> {
         let avg_time = start.elapsed().as_micros() as f64 / queries as f64 / 1000.0;
         
         results.push((name, ip, avg_time));
-        println!("  {} ({}) - {:.2}ms avg", name.bright_cyan(), ip, avg_time);
+        report!("  {} ({}) - {:.2}ms avg", name.bright_cyan(), ip, avg_time);
     }
     
     results.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
     
     let fastest = results[0];
-    println!("\n{} Fastest: {} ({}) - {:.2}ms", 
+    report!("\n{} Fastest: {} ({}) - {:.2}ms", 
              "🏆".bright_yellow(), 
              fastest.0.bright_green(), 
              fastest.1, 
              fastest.2);
     
     if !dry_run && utils::is_privileged() {
-        println!("\nApplying DNS configuration...");
+        status!("\nApplying DNS configuration...");
         apply_dns_config(fastest.1)?;
-        println!("{}", "✓ DNS resolver updated".bright_green());
+        report!("{}", "✓ DNS resolver updated".bright_green());
     } else {
-        println!("\n{}", "⚠ Dry-run mode: DNS not changed".yellow());
+        report!("\n{}", "⚠ Dry-run mode: DNS not changed".yellow());
     }
     
     Ok(())
@@ -135,30 +136,30 @@ async fn benchmark_dns_resolver(_resolver: &str) -> Result<usize> {
 }
 
 fn apply_dns_config(resolver: &str) -> Result<()> {
-    println!("Would update /etc/resolv.conf with nameserver {}", resolver);
+    status!("Would update /etc/resolv.conf with nameserver {}", resolver);
     Ok(())
 }
 
 async fn optimize_mtu(dry_run: bool) -> Result<()> {
-    println!("Detecting optimal MTU size...\n");
+    status!("Detecting optimal MTU size...\n");
     
     let current_mtu = get_current_mtu()?;
-    println!("  Current MTU: {} bytes", current_mtu);
+    report!("  Current MTU: {} bytes", current_mtu);
     
     let optimal_mtu = detect_optimal_mtu().await?;
-    println!("  Optimal MTU: {} bytes", optimal_mtu.to_string().bright_green());
+    report!("  Optimal MTU: {} bytes", optimal_mtu.to_string().bright_green());
     
     if optimal_mtu != current_mtu {
-        println!("\n{} MTU can be optimized", "💡".bright_yellow());
+        report!("\n{} MTU can be optimized", "💡".bright_yellow());
         
         if !dry_run && utils::is_privileged() {
             apply_mtu_config(optimal_mtu)?;
-            println!("{}", "✓ MTU updated".bright_green());
+            report!("{}", "✓ MTU updated".bright_green());
         } else {
-            println!("{}", "⚠ Dry-run mode: MTU not changed".yellow());
+            report!("{}", "⚠ Dry-run mode: MTU not changed".yellow());
         }
     } else {
-        println!("\n{}", "✓ MTU is already optimal".bright_green());
+        report!("\n{}", "✓ MTU is already optimal".bright_green());
     }
     
     Ok(())
@@ -177,7 +178,7 @@ fn apply_mtu_config(_mtu: usize) -> Result<()> {
 }
 
 async fn optimize_tcp(dry_run: bool) -> Result<()> {
-    println!("Analyzing TCP parameters...\n");
+    status!("Analyzing TCP parameters...\n");
     
     let params = vec![
         ("tcp_window_scaling", "1", "Enabled"),
@@ -187,17 +188,17 @@ async fn optimize_tcp(dry_run: bool) -> Result<()> {
         ("tcp_congestion_control", "bbr", "BBR"),
     ];
     
-    println!("{}", "Recommended TCP Settings:".bright_cyan());
+    report!("{}", "Recommended TCP Settings:".bright_cyan());
     for (param, value, desc) in &params {
-        println!("  {} = {} ({})", param.bright_white(), value.bright_yellow(), desc.bright_green());
+        report!("  {} = {} ({})", param.bright_white(), value.bright_yellow(), desc.bright_green());
     }
     
     if !dry_run && utils::is_privileged() {
-        println!("\n{}", "Applying TCP optimizations...".bright_green());
+        status!("\n{}", "Applying TCP optimizations...".bright_green());
         apply_tcp_config(&params)?;
-        println!("{}", "✓ TCP parameters updated".bright_green());
+        report!("{}", "✓ TCP parameters updated".bright_green());
     } else {
-        println!("\n{}", "⚠ Dry-run mode: No changes applied".yellow());
+        report!("\n{}", "⚠ Dry-run mode: No changes applied".yellow());
     }
     
     Ok(())
@@ -0,0 +1,186 @@
+// Console output control
+// Decorative status lines (banners, settings, progress) go through `status!`
+// and final results through `report!`, so --quiet and the machine-readable
+// modes can silence them in one place and keep stdout clean
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Human,
+    /// One JSON document with the final result
+    Json,
+    /// Newline-delimited JSON events as results arrive
+    JsonStream,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// Final results and errors only
+    Quiet,
+    #[default]
+    Normal,
+    /// Per-host and per-probe debug logging as well
+    Verbose,
+}
+
+impl Verbosity {
+    pub fn from_flags(verbose: bool, quiet: bool) -> Self {
+        if quiet {
+            Verbosity::Quiet
+        } else if verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    /// Default tracing filter when RUST_LOG isn't set
+    pub fn log_filter(self) -> &'static str {
+        match self {
+            Verbosity::Quiet => "error",
+            Verbosity::Normal => "info",
+            // Dependencies (netlink, resolver) are far too chatty at debug
+            Verbosity::Verbose => "info,netweaver_lib=debug",
+        }
+    }
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(0);
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn format() -> Format {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => Format::Json,
+        2 => Format::JsonStream,
+        _ => Format::Human,
+    }
+}
+
+pub fn is_human() -> bool {
+    format() == Format::Human
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    }
+}
+
+/// Whether decorative status output should be printed
+pub fn show_status() -> bool {
+    is_human() && verbosity() != Verbosity::Quiet
+}
+
+/// Colors only make sense on a terminal; piped output gets plain text
+pub fn init_color() {
+    if !std::io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
+}
+
+/// A progress bar, hidden in quiet and machine-readable modes
+pub fn progress_bar(len: u64) -> ProgressBar {
+    if show_status() {
+        ProgressBar::new(len)
+    } else {
+        ProgressBar::hidden()
+    }
+}
+
+/// Write `value` to stdout as a single line of JSON
+pub fn emit_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// `--stream-json` destination for long-running sessions: one JSON object
+/// per line, each tagged with its `type` and a timestamp so consumers can
+/// tell events apart, and flushed at once so dashboards see it live
+pub struct EventStream {
+    out: Box<dyn Write + Send>,
+}
+
+#[derive(Serialize)]
+struct Event<'a, T> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+impl EventStream {
+    /// Records go to the file at `path`, or to stdout for `-`
+    pub fn open(path: &str) -> Result<Self> {
+        let out: Box<dyn Write + Send> = if path == "-" {
+            Box::new(std::io::stdout())
+        } else {
+            let file = std::fs::File::create(path).with_context(|| format!("Failed to create {}", path))?;
+            Box::new(file)
+        };
+        Ok(Self { out })
+    }
+
+    /// Write one record stamped with the current time; `data` must serialize
+    /// as an object, whose fields sit next to `type` and `timestamp`
+    pub fn emit<T: Serialize>(&mut self, kind: &str, data: &T) -> Result<()> {
+        self.emit_at(kind, Utc::now(), data)
+    }
+
+    /// Write one record stamped with `timestamp`, e.g. a packet's capture time
+    pub fn emit_at<T: Serialize>(&mut self, kind: &str, timestamp: DateTime<Utc>, data: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &Event { kind, timestamp, data })?;
+        writeln!(self.out)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// A spinner for open-ended work, hidden in quiet and machine-readable modes
+pub fn spinner() -> ProgressBar {
+    if show_status() {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    }
+}
+
+/// `println!` for decorative/status output that --quiet and machine-readable modes drop
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::show_status() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// `println!` for final results, kept under --quiet but dropped in machine-readable modes
+#[macro_export]
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if $crate::output::is_human() {
+            println!($($arg)*);
+        }
+    };
+}
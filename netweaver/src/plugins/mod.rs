@@ -97,6 +97,12 @@ impl ExamplePlugin {
     }
 }
 
+impl Default for ExamplePlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NetweaverPlugin for ExamplePlugin {
     fn name(&self) -> &str {
         &self.name
@@ -132,7 +138,7 @@ mod tests {
     
     #[test]
     fn test_plugin_manager() {
-        let mut manager = PluginManager::new("/tmp/plugins");
+        let manager = PluginManager::new("/tmp/plugins");
         assert_eq!(manager.list_plugins().len(), 0);
     }
 }
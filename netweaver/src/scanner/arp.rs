@@ -0,0 +1,197 @@
+// ARP sweep host discovery
+// On the local segment every live host has to answer ARP, including ones
+// that drop ICMP and have no web server, and the reply carries the MAC
+// address for free. Needs a raw datalink channel, so root or CAP_NET_RAW.
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use pnet_datalink::{Channel, MacAddr, NetworkInterface};
+use pnet_packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet_packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet_packet::{MutablePacket, Packet};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::utils::rate_limit::RateLimiter;
+use crate::utils::{self, MacAddress};
+
+/// Ethernet header plus an IPv4-over-Ethernet ARP body
+pub const FRAME_LEN: usize = 42;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpReply {
+    pub mac: MacAddress,
+    pub rtt: Duration,
+}
+
+/// An IP address answered for by more than one MAC during a sweep
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IpConflict {
+    pub ip: Ipv4Addr,
+    pub macs: Vec<MacSighting>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacSighting {
+    pub mac: MacAddress,
+    /// When this MAC first answered, in microseconds since the Unix epoch
+    pub first_seen: u64,
+}
+
+/// Everything an ARP sweep heard
+#[derive(Debug, Default)]
+pub struct Sweep {
+    /// The first reply for each address
+    pub replies: HashMap<Ipv4Addr, ArpReply>,
+    /// Every (IP, MAC, timestamp) reply seen, in arrival order
+    pub sightings: Vec<(Ipv4Addr, MacSighting)>,
+}
+
+impl Sweep {
+    pub fn conflicts(&self) -> Vec<IpConflict> {
+        find_conflicts(&self.sightings)
+    }
+}
+
+/// Addresses that more than one MAC answered for, each MAC listed once
+/// with the time it was first seen
+pub fn find_conflicts(sightings: &[(Ipv4Addr, MacSighting)]) -> Vec<IpConflict> {
+    let mut by_ip: Vec<IpConflict> = Vec::new();
+    for (ip, sighting) in sightings {
+        let index = match by_ip.iter().position(|c| c.ip == *ip) {
+            Some(index) => index,
+            None => {
+                by_ip.push(IpConflict { ip: *ip, macs: Vec::new() });
+                by_ip.len() - 1
+            }
+        };
+        let macs = &mut by_ip[index].macs;
+        if !macs.iter().any(|m| m.mac == sighting.mac) {
+            macs.push(*sighting);
+        }
+    }
+    by_ip.retain(|c| c.macs.len() > 1);
+    by_ip.sort_by_key(|c| c.ip);
+    by_ip
+}
+
+/// The interface that owns `ip`
+pub fn interface_for(ip: Ipv4Addr) -> Option<NetworkInterface> {
+    pnet_datalink::interfaces()
+        .into_iter()
+        .find(|iface| iface.ips.iter().any(|net| net.ip() == IpAddr::V4(ip)))
+}
+
+/// A broadcast "who has `target`" frame
+pub fn request_frame(src_mac: MacAddress, src_ip: Ipv4Addr, target: Ipv4Addr) -> [u8; FRAME_LEN] {
+    let mut frame = [0u8; FRAME_LEN];
+    let src_mac = MacAddr::from(src_mac.0);
+
+    let mut ethernet = MutableEthernetPacket::new(&mut frame).expect("frame fits an Ethernet header");
+    ethernet.set_destination(MacAddr::broadcast());
+    ethernet.set_source(src_mac);
+    ethernet.set_ethertype(EtherTypes::Arp);
+
+    let mut arp = MutableArpPacket::new(ethernet.payload_mut()).expect("frame fits an ARP body");
+    arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp.set_protocol_type(EtherTypes::Ipv4);
+    arp.set_hw_addr_len(6);
+    arp.set_proto_addr_len(4);
+    arp.set_operation(ArpOperations::Request);
+    arp.set_sender_hw_addr(src_mac);
+    arp.set_sender_proto_addr(src_ip);
+    arp.set_target_hw_addr(MacAddr::zero());
+    arp.set_target_proto_addr(target);
+
+    frame
+}
+
+/// Sender address and MAC of an ARP reply frame
+pub fn parse_reply(frame: &[u8]) -> Option<(Ipv4Addr, MacAddress)> {
+    let ethernet = EthernetPacket::new(frame)?;
+    if ethernet.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let arp = ArpPacket::new(ethernet.payload())?;
+    if arp.get_operation() != ArpOperations::Reply {
+        return None;
+    }
+    let mac = arp.get_sender_hw_addr();
+    Some((arp.get_sender_proto_addr(), MacAddress::new(mac.into())))
+}
+
+/// Broadcast an ARP request for every host and collect whoever answers
+///
+/// Blocking; replies arriving up to `wait` after the last request still count.
+/// Every reply is kept, not just the first per address, so two machines
+/// claiming the same IP show up as a conflict.
+pub fn sweep(
+    local_ip: Ipv4Addr,
+    hosts: impl Iterator<Item = Ipv4Addr>,
+    wait: Duration,
+    limiter: Option<&RateLimiter>,
+) -> Result<Sweep> {
+    let iface = interface_for(local_ip)
+        .with_context(|| format!("No interface has address {}", local_ip))?;
+    let src_mac = iface.mac
+        .map(|mac| MacAddress::new(mac.into()))
+        .with_context(|| format!("Interface {} has no MAC address", iface.name))?;
+
+    let config = pnet_datalink::Config {
+        read_timeout: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let (mut tx, mut rx) = match pnet_datalink::channel(&iface, config)
+        .with_context(|| format!("Failed to open a datalink channel on {}", iface.name))?
+    {
+        Channel::Ethernet(tx, rx) => (tx, rx),
+        _ => anyhow::bail!("Unsupported channel type on {}", iface.name),
+    };
+
+    let sent: Arc<Mutex<HashMap<Ipv4Addr, Instant>>> = Arc::default();
+    let done = Arc::new(AtomicBool::new(false));
+
+    let receiver = {
+        let sent = Arc::clone(&sent);
+        let done = Arc::clone(&done);
+        std::thread::spawn(move || {
+            let mut result = Sweep::default();
+            while !done.load(Ordering::Relaxed) {
+                match rx.next() {
+                    Ok(frame) => {
+                        let Some((ip, mac)) = parse_reply(frame) else { continue };
+                        if let Some(sent_at) = sent.lock().get(&ip) {
+                            result.replies.entry(ip).or_insert(ArpReply { mac, rtt: sent_at.elapsed() });
+                            result.sightings.push((ip, MacSighting { mac, first_seen: utils::get_timestamp_us() }));
+                        }
+                    }
+                    Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {}
+                    Err(e) => {
+                        tracing::warn!("ARP receive failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            result
+        })
+    };
+
+    for ip in hosts.filter(|ip| *ip != local_ip) {
+        if let Some(limiter) = limiter {
+            limiter.acquire_blocking();
+        }
+        sent.lock().insert(ip, Instant::now());
+        if let Some(Err(e)) = tx.send_to(&request_frame(src_mac, local_ip, ip), None) {
+            tracing::debug!("ARP request to {} failed: {}", ip, e);
+        }
+    }
+
+    std::thread::sleep(wait);
+    done.store(true, Ordering::Relaxed);
+    receiver.join().map_err(|_| anyhow::anyhow!("ARP receiver thread panicked"))
+}
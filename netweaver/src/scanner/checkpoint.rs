@@ -0,0 +1,153 @@
+// Scan checkpointing
+// Periodically records scan progress so an interrupted scan can be resumed.
+//
+// Progress is tracked by position in the (deterministic) host sequence rather
+// than by address: everything before `next_index` is done except the indices
+// listed in `pending`, which were in flight when the checkpoint was written.
+// This keeps the file tiny even for a /8.
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::Device;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub network_range: String,
+    pub total_hosts: usize,
+    pub next_index: usize,
+    pub pending: Vec<usize>,
+    pub devices: Vec<Device>,
+    /// Host order seed of a randomized scan; indices are positions in that order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint '{}'", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Corrupt checkpoint '{}'", path))
+    }
+
+    /// Write via a temp file and rename so a crash mid-write never leaves
+    /// a truncated checkpoint behind
+    pub fn save(&self, path: &str) -> Result<()> {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write checkpoint '{}'", tmp))?;
+        std::fs::rename(&tmp, Path::new(path))
+            .with_context(|| format!("Failed to move checkpoint into place at '{}'", path))?;
+        Ok(())
+    }
+
+    pub fn is_done(&self, index: usize) -> bool {
+        index < self.next_index && self.pending.binary_search(&index).is_err()
+    }
+
+    pub fn completed(&self) -> usize {
+        self.next_index - self.pending.len()
+    }
+}
+
+struct TrackerState {
+    next_index: usize,
+    pending: BTreeSet<usize>,
+    devices: Vec<Device>,
+    since_save: usize,
+}
+
+/// Shared progress tracker that writes a checkpoint every `every` hosts
+pub struct CheckpointTracker {
+    path: String,
+    every: usize,
+    network_range: String,
+    total_hosts: usize,
+    seed: Option<u64>,
+    state: Mutex<TrackerState>,
+    write_lock: Mutex<()>,
+}
+
+impl CheckpointTracker {
+    pub fn new(path: String, every: usize, network_range: String, total_hosts: usize, resume: Option<&Checkpoint>) -> Self {
+        let state = match resume {
+            Some(cp) => TrackerState {
+                next_index: cp.next_index,
+                pending: cp.pending.iter().copied().collect(),
+                devices: cp.devices.clone(),
+                since_save: 0,
+            },
+            None => TrackerState {
+                next_index: 0,
+                pending: BTreeSet::new(),
+                devices: Vec::new(),
+                since_save: 0,
+            },
+        };
+
+        Self {
+            path,
+            every: every.max(1),
+            network_range,
+            total_hosts,
+            seed: resume.and_then(|cp| cp.seed),
+            state: Mutex::new(state),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Record the host order seed so a resume walks hosts in the same order
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn start(&self, index: usize) {
+        let mut state = self.state.lock();
+        state.pending.insert(index);
+        state.next_index = state.next_index.max(index + 1);
+    }
+
+    pub fn finish(&self, index: usize, device: Option<&Device>) -> Result<()> {
+        let snapshot = {
+            let mut state = self.state.lock();
+            state.pending.remove(&index);
+            if let Some(device) = device {
+                state.devices.push(device.clone());
+            }
+            state.since_save += 1;
+            if state.since_save < self.every {
+                return Ok(());
+            }
+            state.since_save = 0;
+            self.snapshot(&state)
+        };
+        self.write(&snapshot)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let snapshot = self.snapshot(&self.state.lock());
+        self.write(&snapshot)
+    }
+
+    // Serialize writers so two snapshots never share the temp file
+    fn write(&self, snapshot: &Checkpoint) -> Result<()> {
+        let _guard = self.write_lock.lock();
+        snapshot.save(&self.path)
+    }
+
+    fn snapshot(&self, state: &TrackerState) -> Checkpoint {
+        Checkpoint {
+            network_range: self.network_range.clone(),
+            total_hosts: self.total_hosts,
+            next_index: state.next_index,
+            pending: state.pending.iter().copied().collect(),
+            devices: state.devices.clone(),
+            seed: self.seed,
+        }
+    }
+}
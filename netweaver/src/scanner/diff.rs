@@ -0,0 +1,116 @@
+// Scan comparison
+// Computes what changed between a previously saved ScanResult and a new one:
+// devices that appeared, disappeared, changed ports/identity, or moved IPs
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::net::Ipv4Addr;
+
+use super::{Device, ScanResult};
+use crate::utils::MacAddress;
+
+/// Per-device changes for an IP present in both scans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceChange {
+    pub ip: Ipv4Addr,
+    pub opened_ports: Vec<u16>,
+    pub closed_ports: Vec<u16>,
+    pub hostname: Option<(Option<String>, Option<String>)>,
+    pub mac: Option<(Option<MacAddress>, Option<MacAddress>)>,
+}
+
+/// A MAC address that was seen on a different IP in the previous scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacMove {
+    pub mac: MacAddress,
+    pub from: Ipv4Addr,
+    pub to: Ipv4Addr,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanDiff {
+    pub previous_range: String,
+    pub new_devices: Vec<Device>,
+    pub missing_devices: Vec<Device>,
+    pub changed_devices: Vec<DeviceChange>,
+    pub moved_devices: Vec<MacMove>,
+}
+
+impl ScanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.new_devices.is_empty()
+            && self.missing_devices.is_empty()
+            && self.changed_devices.is_empty()
+            && self.moved_devices.is_empty()
+    }
+}
+
+/// Compare two scans, keyed on IP
+///
+/// A device whose MAC was previously seen on another IP (and is no longer
+/// there) is reported as moved rather than as one new plus one missing host.
+pub fn diff(previous: &ScanResult, current: &ScanResult) -> ScanDiff {
+    let prev_by_ip: HashMap<Ipv4Addr, &Device> = previous.devices.iter().map(|d| (d.ip, d)).collect();
+    let cur_by_ip: HashMap<Ipv4Addr, &Device> = current.devices.iter().map(|d| (d.ip, d)).collect();
+
+    let mut moved_devices = Vec::new();
+    for device in &current.devices {
+        let Some(mac) = &device.mac else { continue };
+        let old = previous.devices.iter()
+            .find(|d| d.mac.as_ref() == Some(mac) && d.ip != device.ip);
+        if let Some(old) = old {
+            let still_there = cur_by_ip.get(&old.ip)
+                .map(|d| d.mac.as_ref() == Some(mac))
+                .unwrap_or(false);
+            if !still_there {
+                moved_devices.push(MacMove { mac: *mac, from: old.ip, to: device.ip });
+            }
+        }
+    }
+
+    let moved_to: BTreeSet<Ipv4Addr> = moved_devices.iter().map(|m| m.to).collect();
+    let moved_from: BTreeSet<Ipv4Addr> = moved_devices.iter().map(|m| m.from).collect();
+
+    let new_devices = current.devices.iter()
+        .filter(|d| !prev_by_ip.contains_key(&d.ip) && !moved_to.contains(&d.ip))
+        .cloned()
+        .collect();
+
+    let missing_devices = previous.devices.iter()
+        .filter(|d| !cur_by_ip.contains_key(&d.ip) && !moved_from.contains(&d.ip))
+        .cloned()
+        .collect();
+
+    let changed_devices = current.devices.iter()
+        .filter_map(|cur| prev_by_ip.get(&cur.ip).and_then(|prev| compare_device(prev, cur)))
+        .collect();
+
+    ScanDiff {
+        previous_range: previous.network_range.clone(),
+        new_devices,
+        missing_devices,
+        changed_devices,
+        moved_devices,
+    }
+}
+
+fn compare_device(prev: &Device, cur: &Device) -> Option<DeviceChange> {
+    let prev_ports: BTreeSet<u16> = prev.open_ports.iter().copied().collect();
+    let cur_ports: BTreeSet<u16> = cur.open_ports.iter().copied().collect();
+
+    let change = DeviceChange {
+        ip: cur.ip,
+        opened_ports: cur_ports.difference(&prev_ports).copied().collect(),
+        closed_ports: prev_ports.difference(&cur_ports).copied().collect(),
+        hostname: (prev.hostname != cur.hostname)
+            .then(|| (prev.hostname.clone(), cur.hostname.clone())),
+        mac: (prev.mac != cur.mac).then_some((prev.mac, cur.mac)),
+    };
+
+    let changed = !change.opened_ports.is_empty()
+        || !change.closed_ports.is_empty()
+        || change.hostname.is_some()
+        || change.mac.is_some();
+
+    changed.then_some(change)
+}
@@ -0,0 +1,282 @@
+// OS fingerprinting
+// Combines the IP TTL seen in replies, the SYN-ACK's TCP window and options
+// (when raw sockets are available) and open-port heuristics into a single
+// guess with a confidence score
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::{Duration, Instant};
+
+use crate::ffi;
+
+pub use crate::utils::icmp::parse_ping_ttl;
+
+pub const WINDOWS: &str = "Windows";
+pub const LINUX: &str = "Linux";
+pub const MACOS: &str = "macOS";
+pub const NETWORK_DEVICE: &str = "Local Router/Switch";
+
+/// TCP fields from a SYN-ACK that differ between network stacks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSignature {
+    pub ttl: u8,
+    pub window: u16,
+    pub mss: Option<u16>,
+    pub window_scale: Option<u8>,
+    pub sack_permitted: bool,
+    pub timestamps: bool,
+}
+
+/// A SYN-ACK pulled off the wire, with enough addressing to match it to a probe
+#[derive(Debug, Clone)]
+pub struct SynAck {
+    pub from: SocketAddrV4,
+    pub to_port: u16,
+    pub signature: TcpSignature,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsGuess {
+    pub name: String,
+    pub confidence: f32,
+}
+
+/// Round an observed TTL up to the initial value the sender most likely used
+pub fn initial_ttl(observed: u8) -> u8 {
+    match observed {
+        0..=32 => 32,
+        33..=64 => 64,
+        65..=128 => 128,
+        _ => 255,
+    }
+}
+
+/// Parse a raw IPv4 packet, returning its signature if it is a TCP SYN-ACK
+pub fn parse_syn_ack(packet: &[u8]) -> Option<SynAck> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 || packet[9] != 6 {
+        return None;
+    }
+    let ihl = (packet[0] & 0x0F) as usize * 4;
+    let tcp = packet.get(ihl..)?;
+    if tcp.len() < 20 || tcp[13] & 0x12 != 0x12 {
+        return None;
+    }
+
+    let src = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let src_port = u16::from_be_bytes([tcp[0], tcp[1]]);
+    let dst_port = u16::from_be_bytes([tcp[2], tcp[3]]);
+    let data_offset = (tcp[12] >> 4) as usize * 4;
+
+    let mut signature = TcpSignature {
+        ttl: packet[8],
+        window: u16::from_be_bytes([tcp[14], tcp[15]]),
+        mss: None,
+        window_scale: None,
+        sack_permitted: false,
+        timestamps: false,
+    };
+
+    let options = tcp.get(20..data_offset).unwrap_or_default();
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            0 => break,
+            1 => {
+                i += 1;
+                continue;
+            }
+            kind => {
+                let len = *options.get(i + 1)? as usize;
+                if len < 2 || i + len > options.len() {
+                    break;
+                }
+                let body = &options[i + 2..i + len];
+                match (kind, body.len()) {
+                    (2, 2) => signature.mss = Some(u16::from_be_bytes([body[0], body[1]])),
+                    (3, 1) => signature.window_scale = Some(body[0]),
+                    (4, 0) => signature.sack_permitted = true,
+                    (8, 8) => signature.timestamps = true,
+                    _ => {}
+                }
+                i += len;
+            }
+        }
+    }
+
+    Some(SynAck {
+        from: SocketAddrV4::new(src, src_port),
+        to_port: dst_port,
+        signature,
+    })
+}
+
+/// Send a raw SYN to `dst:port` and capture the SYN-ACK
+///
+/// Needs CAP_NET_RAW; returns None straight away when the raw socket
+/// can't be opened so unprivileged scans just skip this signal
+pub fn syn_probe(src: Ipv4Addr, dst: Ipv4Addr, port: u16, timeout: Duration) -> Option<SynAck> {
+    let mut sock: ffi::nw_socket_t = unsafe { std::mem::zeroed() };
+    let status = unsafe {
+        ffi::nw_socket_create(&mut sock, libc::AF_INET, libc::SOCK_RAW, ffi::nw_protocol_t_NW_PROTO_TCP)
+    };
+    if status != ffi::nw_error_t_NW_SUCCESS {
+        return None;
+    }
+    if crate::utils::source::apply_raw(sock.fd).is_err() {
+        unsafe { ffi::nw_socket_close(&mut sock) };
+        return None;
+    }
+
+    let src_port = 40000 + (crate::utils::get_timestamp_us() % 20000) as u16;
+    let mut packet: Box<ffi::nw_packet_t> = Box::new(unsafe { std::mem::zeroed() });
+    let result = unsafe {
+        let crafted = ffi::nw_packet_craft_tcp_syn(&mut *packet, u32::from(src), u32::from(dst), src_port, port);
+        if crafted == ffi::nw_error_t_NW_SUCCESS
+            && ffi::nw_packet_send_raw(&mut sock, &*packet) == ffi::nw_error_t_NW_SUCCESS
+        {
+            receive_syn_ack(&mut sock, &mut packet, SocketAddrV4::new(dst, port), src_port, timeout)
+        } else {
+            None
+        }
+    };
+
+    unsafe { ffi::nw_socket_close(&mut sock) };
+    result
+}
+
+/// The raw socket sees every inbound TCP segment, so keep reading until
+/// the reply to our probe shows up or the deadline passes
+unsafe fn receive_syn_ack(
+    sock: &mut ffi::nw_socket_t,
+    packet: &mut ffi::nw_packet_t,
+    from: SocketAddrV4,
+    to_port: u16,
+    timeout: Duration,
+) -> Option<SynAck> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
+        let wait_ms = (remaining.as_millis() as u32).max(1);
+        if ffi::nw_packet_recv_raw(sock, packet, wait_ms) != ffi::nw_error_t_NW_SUCCESS {
+            return None;
+        }
+
+        let len = packet.length.min(packet.data.len());
+        if let Some(reply) = parse_syn_ack(&packet.data[..len]) {
+            if reply.from == from && reply.to_port == to_port {
+                return Some(reply);
+            }
+        }
+    }
+}
+
+/// Score each OS family from whatever signals we have and return the best
+///
+/// TTL is the strongest single signal, the SYN-ACK's window and options
+/// refine it, and open ports only nudge the result; a guess based on
+/// ports alone never rises above low confidence
+pub fn infer(ttl: Option<u8>, tcp: Option<&TcpSignature>, open_ports: &[u16], latency_ms: Option<f64>) -> Option<OsGuess> {
+    let mut scores = [(WINDOWS, 0.0f32), (LINUX, 0.0), (MACOS, 0.0), (NETWORK_DEVICE, 0.0)];
+    let mut add = |name: &str, weight: f32| {
+        if let Some(entry) = scores.iter_mut().find(|(n, _)| *n == name) {
+            entry.1 += weight;
+        }
+    };
+
+    match ttl.or(tcp.map(|t| t.ttl)).map(initial_ttl) {
+        Some(128) => add(WINDOWS, 0.6),
+        Some(64) => {
+            add(LINUX, 0.45);
+            add(MACOS, 0.3);
+        }
+        Some(255) => add(NETWORK_DEVICE, 0.6),
+        Some(32) => add(WINDOWS, 0.2),
+        _ => {}
+    }
+
+    if let Some(sig) = tcp {
+        match (sig.window_scale, sig.timestamps) {
+            (Some(7), true) => add(LINUX, 0.3),
+            (Some(8), false) => add(WINDOWS, 0.3),
+            (Some(6), true) if sig.window == 65535 => add(MACOS, 0.4),
+            (None, false) if !sig.sack_permitted => add(NETWORK_DEVICE, 0.2),
+            _ => {}
+        }
+    }
+
+    // Port heuristics, kept as a weak tie-breaker
+    if open_ports.iter().any(|p| matches!(p, 135 | 445 | 3389)) {
+        add(WINDOWS, 0.2);
+    }
+    if open_ports.contains(&22) {
+        add(LINUX, 0.15);
+    }
+    if open_ports.iter().any(|p| matches!(p, 548 | 5900 | 62078)) {
+        add(MACOS, 0.25);
+    }
+    if latency_ms.is_some_and(|ms| ms < 1.0) && open_ports.len() > 5 {
+        add(NETWORK_DEVICE, 0.15);
+    }
+
+    scores
+        .iter()
+        .filter(|(_, score)| *score > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(name, score)| OsGuess {
+            name: name.to_string(),
+            confidence: score.min(0.99),
+        })
+}
+
+/// Identify a device from its SNMP sysDescr, falling back to the vendor's
+/// enterprise number in sysObjectID (1.3.6.1.4.1.<enterprise>...)
+///
+/// Devices describe themselves here, so a match is trusted well above
+/// anything the TTL/TCP heuristics can offer
+pub fn from_snmp(descr: Option<&str>, object_id: Option<&[u32]>) -> Option<OsGuess> {
+    const DESCR_PATTERNS: &[(&str, &str)] = &[
+        ("cisco ios", "Cisco IOS"),
+        ("cisco nx-os", "Cisco NX-OS"),
+        ("cisco adaptive security", "Cisco ASA"),
+        ("junos", "Juniper Junos"),
+        ("routeros", "MikroTik RouterOS"),
+        ("edgeos", "Ubiquiti EdgeOS"),
+        ("fortigate", "FortiOS"),
+        ("pfsense", "pfSense"),
+        ("openwrt", "OpenWrt"),
+        ("procurve", "HP ProCurve"),
+        ("arubaos", "ArubaOS"),
+        ("jetdirect", "HP JetDirect Printer"),
+        ("synology", "Synology DSM"),
+        ("windows", WINDOWS),
+        ("darwin", MACOS),
+        ("freebsd", "FreeBSD"),
+        ("linux", LINUX),
+    ];
+    const ENTERPRISES: &[(u32, &str)] = &[
+        (9, "Cisco"),
+        (11, "HP"),
+        (311, WINDOWS),
+        (2636, "Juniper Junos"),
+        (6574, "Synology DSM"),
+        (8072, LINUX), // net-snmp
+        (12356, "FortiOS"),
+        (14988, "MikroTik RouterOS"),
+        (41112, "Ubiquiti"),
+    ];
+
+    let from_descr = descr.and_then(|descr| {
+        let lower = descr.to_ascii_lowercase();
+        DESCR_PATTERNS.iter().find(|(pattern, _)| lower.contains(pattern))
+    });
+    if let Some((_, name)) = from_descr {
+        return Some(OsGuess { name: name.to_string(), confidence: 0.95 });
+    }
+
+    let enterprise = object_id
+        .filter(|oid| oid.starts_with(&[1, 3, 6, 1, 4, 1]))
+        .and_then(|oid| oid.get(6))?;
+    ENTERPRISES
+        .iter()
+        .find(|(number, _)| number == enterprise)
+        .map(|(_, name)| OsGuess { name: name.to_string(), confidence: 0.8 })
+}
@@ -0,0 +1,211 @@
+// HTTP page details
+// Embedded web UIs name themselves in their index page's <title> ("RouterOS",
+// "iLO 4", "Synology DSM") far more reliably than any port or TTL heuristic.
+// A plain HTTP/1.0 GET keeps the exchange simple: no chunked bodies and the
+// server closes the connection when it's done. HTTPS goes through the same
+// accept-anything TLS client as the certificate grab.
+
+use regex::Regex;
+use rustls::ServerName;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::tls;
+use crate::utils::source;
+
+/// Web ports worth fetching when they're open
+pub const HTTP_PORTS: &[u16] = &[80, 443, 8080, 8443];
+
+/// For the whole fetch, redirect included
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Enough of the page to reach the <title> of any sane index page
+const MAX_RESPONSE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpInfo {
+    pub port: u16,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Where the index page redirected to, when the redirect was followed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirected_to: Option<String>,
+}
+
+/// Status line, headers and body of a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// First header named `name`, case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Where a request goes: the device's address never changes, only the port,
+/// scheme, path and the name sent in Host/SNI
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Location {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Location {
+    fn url(&self) -> String {
+        let (scheme, default_port) = if self.tls { ("https", 443) } else { ("http", 80) };
+        match self.port == default_port {
+            true => format!("{}://{}{}", scheme, self.host, self.path),
+            false => format!("{}://{}:{}{}", scheme, self.host, self.port, self.path),
+        }
+    }
+}
+
+/// GET / from `ip:port`, following at most one redirect that stays on the device
+pub async fn fetch(ip: Ipv4Addr, port: u16, hostname: Option<&str>, timeout: Duration) -> Option<HttpInfo> {
+    let host = hostname.map_or_else(|| ip.to_string(), str::to_string);
+    let start = Location { tls: tls::TLS_PORTS.contains(&port), host, port, path: "/".to_string() };
+
+    tokio::time::timeout(timeout, async {
+        let response = get(ip, &start).await?;
+        let redirect = match response.status {
+            300..=399 => response
+                .header("Location")
+                .and_then(|target| follow(&start, target, ip, hostname)),
+            _ => None,
+        };
+        let (response, redirected_to) = match redirect {
+            Some(next) => match get(ip, &next).await {
+                Some(followed) => (followed, Some(next.url())),
+                None => (response, None),
+            },
+            None => (response, None),
+        };
+        Some(HttpInfo {
+            port,
+            status: response.status,
+            server: response.header("Server").map(str::to_string),
+            title: extract_title(&response.body),
+            redirected_to,
+        })
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// The redirect target, unless it leaves the device
+fn follow(from: &Location, target: &str, ip: Ipv4Addr, hostname: Option<&str>) -> Option<Location> {
+    if target.starts_with('/') {
+        return Some(Location { path: target.to_string(), ..from.clone() });
+    }
+
+    let (tls, rest) = if let Some(rest) = target.strip_prefix("https://") {
+        (true, rest)
+    } else {
+        (false, target.strip_prefix("http://")?)
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, if tls { 443 } else { 80 }),
+    };
+
+    let same_device = host == ip.to_string() || hostname.is_some_and(|name| host.eq_ignore_ascii_case(name));
+    same_device.then(|| Location { tls, host: host.to_string(), port, path: path.to_string() })
+}
+
+async fn get(ip: Ipv4Addr, location: &Location) -> Option<Response> {
+    let stream = source::connect(SocketAddr::from((ip, location.port))).await.ok()?;
+    let raw = if location.tls {
+        let server_name = ServerName::try_from(location.host.as_str())
+            .unwrap_or(ServerName::IpAddress(IpAddr::V4(ip)));
+        let stream = tls::connector().connect(server_name, stream).await.ok()?;
+        exchange(stream, location).await?
+    } else {
+        exchange(stream, location).await?
+    };
+    parse_response(&raw)
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, location: &Location) -> Option<Vec<u8>> {
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: netweaver\r\nAccept: text/html\r\nConnection: close\r\n\r\n",
+        location.path, location.host
+    );
+    stream.write_all(request.as_bytes()).await.ok()?;
+
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 8192];
+    while raw.len() < MAX_RESPONSE {
+        match stream.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(len) => raw.extend_from_slice(&buf[..len]),
+            // Servers that drop TLS without a close_notify still sent a page
+            Err(_) if !raw.is_empty() => break,
+            Err(_) => return None,
+        }
+    }
+    Some(raw)
+}
+
+/// Split a raw HTTP/1.x response into status, headers and body
+pub fn parse_response(raw: &[u8]) -> Option<Response> {
+    let header_end = raw.windows(4).position(|w| w == b"\r\n\r\n")
+        .map(|idx| (idx, idx + 4))
+        .or_else(|| raw.windows(2).position(|w| w == b"\n\n").map(|idx| (idx, idx + 2)))
+        .unwrap_or((raw.len(), raw.len()));
+    let head = String::from_utf8_lossy(&raw[..header_end.0]);
+    let mut lines = head.lines();
+
+    let status_line = lines.next()?;
+    if !status_line.starts_with("HTTP/") {
+        return None;
+    }
+    let status = status_line.split_whitespace().nth(1)?.parse().ok()?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    Some(Response { status, headers, body: raw[header_end.1..].to_vec() })
+}
+
+/// The first <title> of an HTML page, entities decoded and whitespace collapsed
+pub fn extract_title(body: &[u8]) -> Option<String> {
+    static TITLE: OnceLock<Regex> = OnceLock::new();
+    let title = TITLE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("title pattern is valid"));
+
+    let html = String::from_utf8_lossy(body);
+    let raw = title.captures(&html)?.get(1)?.as_str();
+    let text = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
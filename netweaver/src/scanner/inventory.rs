@@ -0,0 +1,172 @@
+// Device inventory
+// A persistent record of every device any scan has found, so one-off scans
+// add up to an asset list. Devices are matched by MAC when one is known
+// (DHCP moves them between addresses) and by IP otherwise. Each entry keeps
+// a short history of how its address, MAC, hostname and ports changed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+
+use super::Device;
+use crate::utils::MacAddress;
+
+/// Changes kept per device; older ones are dropped first
+pub const MAX_HISTORY: usize = 20;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub devices: Vec<InventoryEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub ip: Ipv4Addr,
+    pub mac: Option<MacAddress>,
+    pub hostname: Option<String>,
+    pub vendor: Option<String>,
+    pub open_ports: Vec<u16>,
+    pub os_guess: Option<String>,
+    /// Microseconds since the epoch, like `Device::last_seen`
+    pub first_seen: u64,
+    pub last_seen: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<AttributeChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Attribute {
+    Ip,
+    Mac,
+    Hostname,
+    Ports,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttributeChange {
+    pub at: u64,
+    pub attribute: Attribute,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// How many devices a scan added to the inventory and how many it already knew
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordSummary {
+    pub new: usize,
+    pub known: usize,
+}
+
+/// `$XDG_DATA_HOME/netweaver/inventory.db`, or `~/.local/share/netweaver/inventory.db`
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::utils::data_dir()?.join("inventory.db"))
+}
+
+impl Inventory {
+    /// Load the inventory at `path`; a missing file is an empty inventory
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Corrupt inventory '{}'", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read inventory '{}'", path.display())),
+        }
+    }
+
+    /// Write via a temp file and rename, creating the directory if needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write inventory '{}'", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace inventory '{}'", path.display()))
+    }
+
+    /// Fold one scan's devices into the inventory
+    pub fn record(&mut self, devices: &[Device]) -> RecordSummary {
+        let mut summary = RecordSummary::default();
+        for device in devices {
+            match self.find(device) {
+                Some(index) => {
+                    self.devices[index].update(device);
+                    summary.known += 1;
+                }
+                None => {
+                    self.devices.push(InventoryEntry::new(device));
+                    summary.new += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    fn find(&self, device: &Device) -> Option<usize> {
+        match device.mac {
+            Some(mac) => self.devices.iter().position(|e| e.mac == Some(mac))
+                .or_else(|| self.devices.iter().position(|e| e.mac.is_none() && e.ip == device.ip)),
+            None => self.devices.iter().position(|e| e.ip == device.ip),
+        }
+    }
+}
+
+impl InventoryEntry {
+    fn new(device: &Device) -> Self {
+        Self {
+            ip: device.ip,
+            mac: device.mac,
+            hostname: device.hostname.clone(),
+            vendor: device.vendor.clone(),
+            open_ports: device.open_ports.clone(),
+            os_guess: device.os_guess.clone(),
+            first_seen: device.last_seen,
+            last_seen: device.last_seen,
+            history: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, device: &Device) {
+        let at = device.last_seen;
+        if self.ip != device.ip {
+            self.changed(at, Attribute::Ip, Some(self.ip.to_string()), Some(device.ip.to_string()));
+            self.ip = device.ip;
+        }
+        // A scan that couldn't resolve a MAC or name doesn't mean it's gone
+        if device.mac.is_some() && self.mac != device.mac {
+            self.changed(at, Attribute::Mac, self.mac.map(|m| m.to_string()), device.mac.map(|m| m.to_string()));
+            self.mac = device.mac;
+        }
+        if device.hostname.is_some() && self.hostname != device.hostname {
+            self.changed(at, Attribute::Hostname, self.hostname.clone(), device.hostname.clone());
+            self.hostname = device.hostname.clone();
+        }
+        // Ping sweeps and SSDP-only finds don't probe ports at all
+        if !device.ports.is_empty() && self.open_ports != device.open_ports {
+            self.changed(at, Attribute::Ports, Some(join_ports(&self.open_ports)), Some(join_ports(&device.open_ports)));
+            self.open_ports = device.open_ports.clone();
+        }
+        if device.vendor.is_some() {
+            self.vendor = device.vendor.clone();
+        }
+        if device.os_guess.is_some() {
+            self.os_guess = device.os_guess.clone();
+        }
+        self.last_seen = self.last_seen.max(at);
+    }
+
+    fn changed(&mut self, at: u64, attribute: Attribute, from: Option<String>, to: Option<String>) {
+        self.history.push(AttributeChange { at, attribute, from, to });
+        if self.history.len() > MAX_HISTORY {
+            self.history.drain(..self.history.len() - MAX_HISTORY);
+        }
+    }
+}
+
+fn join_ports(ports: &[u16]) -> String {
+    ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+}
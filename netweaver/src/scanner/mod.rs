@@ -12,18 +12,50 @@
 // - Concurrent connection limit prevents resource exhaustion
 // - Intelligent probe scheduling based on network responsiveness
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
+use futures::{future, stream, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
 
+use crate::analytics::LatencyAnalyzer;
+use crate::output::{self, Format};
+use crate::{report, status};
 use crate::utils::{self, MacAddress};
+use crate::utils::icmp;
+use crate::utils::oui::OuiDatabase;
+use crate::utils::rate_limit::RateLimiter;
+use crate::utils::retry::RetryPolicy;
+use crate::utils::source::{self, Source};
+
+pub mod arp;
+pub mod checkpoint;
+pub mod diff;
+pub mod fingerprint;
+pub mod http;
+pub mod inventory;
+pub mod names;
+pub mod profile;
+pub mod services;
+pub mod snmp;
+pub mod ssdp;
+pub mod targets;
+pub mod tls;
+pub mod topology;
+pub mod wake;
+
+use checkpoint::{Checkpoint, CheckpointTracker};
+use diff::ScanDiff;
+use inventory::Inventory;
+use names::NameSource;
+use targets::{ExcludeList, RangeSet, TargetGroup};
 
 /// Represents a discovered network device with all gathered intelligence
 /// Contains connection details, open services, and fingerprinting results
@@ -32,11 +64,148 @@ pub struct Device {
     pub ip: Ipv4Addr,
     pub mac: Option<MacAddress>,
     pub hostname: Option<String>,
+    /// Which protocol `hostname` came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname_source: Option<NameSource>,
+    /// Open ports only; kept alongside `ports` for existing consumers
     pub open_ports: Vec<u16>,
+    /// Every probed port with its state
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<PortResult>,
     pub os_guess: Option<String>,
-    pub latency_ms: f64,
+    /// How sure we are of `os_guess`, from 0.0 to 1.0
+    #[serde(default)]
+    pub os_confidence: f32,
+    /// Median probe round trip; None when the device was never timed
+    pub latency_ms: Option<f64>,
+    /// Liveness probes sent before the host answered (0 when not probed)
+    #[serde(default)]
+    pub attempts: u32,
     pub vendor: Option<String>,
     pub last_seen: u64,
+    /// Which element of a multi-target spec this device was found in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_range: Option<String>,
+    /// sysName reported over SNMP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snmp_name: Option<String>,
+    /// sysDescr reported over SNMP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snmp_descr: Option<String>,
+    /// How the device was first found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery_method: Option<DiscoveryMethod>,
+    /// SSDP/UPnP details, when the device answered an M-SEARCH
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssdp: Option<ssdp::SsdpInfo>,
+    /// Certificates presented on open TLS ports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tls: Vec<tls::TlsInfo>,
+    /// Status, server and page title of open web ports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub http: Vec<http::HttpInfo>,
+    /// Services identified from banners and probe responses
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<services::ServiceInfo>,
+    /// The scanning host's default gateway
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_gateway: bool,
+}
+
+/// A port this many times slower than the host's own latency is called out
+pub const SLOW_PORT_FACTOR: f64 = 3.0;
+
+/// ...and must also be at least this much slower, so sub-millisecond LAN
+/// jitter doesn't count
+const SLOW_PORT_MIN_MS: f64 = 1.0;
+
+impl Device {
+    /// The slowest open port, if it's far slower than the host itself
+    /// (a proxied or NAT'd service behind a fast front door)
+    pub fn slow_port(&self) -> Option<&PortResult> {
+        let host = self.latency_ms?;
+        self.ports
+            .iter()
+            .filter(|p| p.state == PortState::Open)
+            .filter_map(|p| Some((p, p.latency_ms?)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .filter(|(_, ms)| *ms > host * SLOW_PORT_FACTOR && *ms - host >= SLOW_PORT_MIN_MS)
+            .map(|(p, _)| p)
+    }
+
+    /// A device no probe measured, known only from `method`
+    fn unprobed(ip: Ipv4Addr, method: DiscoveryMethod) -> Self {
+        Self {
+            ip,
+            mac: None,
+            hostname: None,
+            hostname_source: None,
+            open_ports: Vec::new(),
+            ports: Vec::new(),
+            os_guess: None,
+            os_confidence: 0.0,
+            latency_ms: None,
+            attempts: 0,
+            vendor: None,
+            last_seen: utils::get_timestamp_us(),
+            source_range: None,
+            snmp_name: None,
+            snmp_descr: None,
+            discovery_method: Some(method),
+            ssdp: None,
+            tls: Vec::new(),
+            http: Vec::new(),
+            services: Vec::new(),
+            is_gateway: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortState {
+    /// Accepted the connection
+    Open,
+    /// Actively refused (RST)
+    Closed,
+    /// No answer before the timeout, or an ICMP unreachable: a firewall
+    /// is most likely dropping the probe
+    Filtered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PortResult {
+    pub port: u16,
+    pub state: PortState,
+    /// Time until the port answered; None when it never did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+impl PortResult {
+    /// Classify a TCP connect attempt from its outcome
+    pub fn from_connect(port: u16, result: &std::io::Result<TcpStream>, elapsed: Duration) -> Self {
+        let state = match result {
+            Ok(_) => PortState::Open,
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => PortState::Closed,
+            Err(_) => PortState::Filtered,
+        };
+        let latency_ms = (state != PortState::Filtered).then_some(elapsed.as_secs_f64() * 1000.0);
+        Self { port, state, latency_ms }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscoveryMethod {
+    /// Answered a ping or TCP connect probe
+    Probe,
+    /// Answered the ARP sweep
+    Arp,
+    /// Only answered SSDP; no probe saw it
+    Ssdp,
+    /// Never answered; known only as the default gateway
+    Route,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,276 +215,1435 @@ pub struct ScanResult {
     pub network_range: String,
     pub total_hosts: usize,
     pub responsive_hosts: usize,
+    #[serde(default)]
+    pub excluded_hosts: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<ScanDiff>,
+    /// Achieved probe rate (attempts/sec) when `--max-rate` was in effect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_rate: Option<f64>,
+    /// Addresses that more than one MAC answered for during the ARP sweep
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<arp::IpConflict>,
+    /// Default gateway of the scanning host, when the routing table has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<Ipv4Addr>,
+}
+
+/// What to scan and how; everything `scan` needs, and nothing about
+/// presenting the result
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub lan: bool,
+    pub target: Option<String>,
+    pub target_file: Option<String>,
+    pub threads: usize,
+    pub ports: Option<String>,
+    pub top_ports: Option<usize>,
+    pub exclude: Option<String>,
+    pub exclude_file: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub max_timeout_ms: u64,
+    pub max_rate: Option<u32>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_every: usize,
+    pub resume: Option<String>,
+    pub oui_db: Option<String>,
+    pub names: bool,
+    pub snmp: bool,
+    pub snmp_community: Option<String>,
+    pub ssdp: bool,
+    pub retries: u32,
+    /// Shuffle the host order; None decides by range size
+    pub randomize: Option<bool>,
+    /// Seed for the shuffled order, for reproducible runs
+    pub seed: Option<u64>,
+    /// Stop after liveness: no ports, no SNMP, no port-based OS guessing
+    pub ping_only: bool,
+    /// Handshake with open TLS ports and record their certificates
+    pub tls_info: bool,
+    /// Fetch the index page of open web ports for its status, server and title
+    pub http_info: bool,
+    /// Identify the service on each open port from its banner or probe replies
+    pub services: bool,
+    /// Send every probe out of this interface
+    pub interface: Option<String>,
+    /// Send every probe from this local address
+    pub source_ip: Option<Ipv4Addr>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            lan: false,
+            target: None,
+            target_file: None,
+            threads: 100,
+            ports: None,
+            top_ports: None,
+            exclude: None,
+            exclude_file: None,
+            timeout_ms: None,
+            max_timeout_ms: 2000,
+            max_rate: None,
+            checkpoint: None,
+            checkpoint_every: 100,
+            resume: None,
+            oui_db: None,
+            names: false,
+            snmp: false,
+            snmp_community: None,
+            ssdp: false,
+            retries: 1,
+            randomize: None,
+            seed: None,
+            ping_only: false,
+            tls_info: false,
+            http_info: false,
+            services: false,
+            interface: None,
+            source_ip: None,
+        }
+    }
+}
+
+/// What the `scan` subcommand does with results beyond printing them
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    pub output: Option<String>,
+    pub topology: bool,
+    pub topology_out: Option<String>,
+    /// Devices listed per subnet in the console map before the rest are counted
+    pub topology_limit: Option<usize>,
+    pub diff: Option<String>,
+    pub watch: Option<String>,
+}
+
+/// Milestones reported while a scan runs, so callers can draw their own
+/// progress
+#[derive(Debug)]
+pub enum ScanProgress<'a> {
+    /// Targets are resolved and nothing has been sent yet
+    Planned { range: &'a str, threads: usize, hosts: usize, ports: usize, max_rate: Option<u32> },
+    /// Hosts will be probed in a shuffled order
+    Randomized { seed: u64 },
+    /// Continuing from a checkpoint
+    Resumed { path: &'a str, completed: usize, devices: usize },
+    VendorDatabase { path: &'a str, prefixes: usize },
+    /// The ARP sweep finished; liveness comes from its replies
+    ArpSweep { answered: usize },
+    /// No datalink access, so hosts are probed instead
+    ArpUnavailable(&'a anyhow::Error),
+    /// Host probing is starting; `done` of `total` were finished by a resumed checkpoint
+    HostsStarted { total: usize, done: usize },
+    /// One host finished, whether or not it answered
+    HostDone,
+    /// A responsive device, as soon as it is known
+    Device(&'a Device),
+    HostsFinished,
+    Ssdp { answered: usize },
+    SsdpUnavailable(&'a anyhow::Error),
+}
+
+/// Receives `ScanProgress` events; called from the scanning task
+pub type ProgressFn<'a> = &'a (dyn Fn(ScanProgress<'_>) + Sync);
+
+/// One line of `--json-stream` output
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ScanEvent<'a> {
+    /// A responsive host, emitted as soon as its probes finish
+    Device(&'a Device),
+    /// The finished scan, including every device again
+    Complete(&'a ScanResult),
+}
+
+/// Print the final result in whichever machine-readable format was asked for
+fn emit_result(result: &ScanResult) -> Result<()> {
+    match output::format() {
+        Format::Human => Ok(()),
+        Format::Json => output::emit_json(result),
+        Format::JsonStream => output::emit_json(&ScanEvent::Complete(result)),
+    }
+}
+
+/// Probe timing for a scan
+///
+/// A fixed `timeout` pins every probe to that value. Otherwise liveness uses
+/// the default host timeout and port probes adapt to each host's measured RTT,
+/// clamped to `max_timeout`.
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeTiming {
+    pub timeout: Option<Duration>,
+    pub max_timeout: Duration,
+}
+
+impl ProbeTiming {
+    const DEFAULT_HOST_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub fn liveness_timeout(&self) -> Duration {
+        self.timeout
+            .unwrap_or(Self::DEFAULT_HOST_TIMEOUT.min(self.max_timeout))
+    }
+
+    pub fn port_timeout(&self, rtt: Duration) -> Duration {
+        self.timeout.unwrap_or_else(|| {
+            utils::network::calculate_adaptive_timeout(rtt).min(self.max_timeout)
+        })
+    }
+}
+
+impl Default for ProbeTiming {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            max_timeout: Duration::from_secs(2),
+        }
+    }
 }
 
-pub async fn run_scan(
-    lan: bool,
-    target: Option<String>,
+/// Ranges larger than a /26 are shuffled unless told otherwise
+const RANDOMIZE_ABOVE: usize = 64;
+
+/// What to probe on each live host; shared by every host task
+struct HostProbe {
+    ports: Arc<[u16]>,
+    timing: ProbeTiming,
+    /// Applied to liveness probes and to ports that never answered
+    retry: RetryPolicy,
+    /// Query mDNS/NetBIOS names
+    names: bool,
+    /// Ask every host for SNMP system info, not just likely network gear
+    snmp: bool,
+    snmp_community: String,
+    /// Census mode: nothing but liveness, MAC and names
+    ping_only: bool,
+    tls_info: bool,
+    http_info: bool,
+    services: bool,
+}
+
+/// Everything a scan pass needs, resolved once from `ScanOptions`
+struct ScanPlan {
+    range: String,
+    groups: Vec<TargetGroup>,
+    targets: RangeSet,
     threads: usize,
-    ports: Option<String>,
-    output: Option<String>,
-    topology: bool,
-) -> Result<()> {
-    println!("{}", "NetWeaver Network Scanner".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+    probe: Arc<HostProbe>,
+    excludes: ExcludeList,
+    max_rate: Option<u32>,
+    checkpoint: Option<String>,
+    checkpoint_every: usize,
+    resume: Option<Checkpoint>,
+    oui_db: Option<Arc<OuiDatabase>>,
+    /// Local address to ARP-sweep from, for privileged `--lan` scans
+    arp_from: Option<Ipv4Addr>,
+    /// Run an SSDP M-SEARCH alongside the host probes
+    ssdp: bool,
+    /// Seed of the shuffled host order; None walks hosts in ascending order
+    seed: Option<u64>,
+}
+
+/// How a host's liveness is established
+#[derive(Debug, Clone, Copy)]
+enum Discovery {
+    /// TCP connect / ping probes
+    Probe,
+    /// Result of an ARP sweep: a host that didn't answer isn't on the segment
+    Arp(Option<arp::ArpReply>),
+}
+
+/// Scan with `options` and return the result without printing anything
+pub async fn scan(options: ScanOptions) -> Result<ScanResult> {
+    scan_with_progress(options, &|_| {}).await
+}
+
+/// `scan`, reporting each milestone to `progress` as it happens
+pub async fn scan_with_progress(options: ScanOptions, progress: ProgressFn<'_>) -> Result<ScanResult> {
+    let plan = plan_scan(options, progress).await?;
+    perform_scan(&plan, progress).await
+}
+
+/// The `scan` subcommand: run the scan, then print, diff, watch and save
+pub async fn run_scan(opts: ScanOptions, report: ReportOptions) -> Result<()> {
+    let ReportOptions { output, topology, topology_out, topology_limit, diff, watch } = report;
+
+    status!("{}", "NetWeaver Network Scanner".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
     if !utils::is_privileged() {
-        println!("{} Running without root privileges - some features may be limited", 
+        status!("{} Running without root privileges - some features may be limited", 
                  "⚠".yellow());
     }
 
+    // Load the baseline up front so a bad path fails before a long scan
+    let previous = match &diff {
+        Some(path) => Some(load_results(path)?),
+        None => None,
+    };
+
+    let interval = match &watch {
+        Some(_) if opts.checkpoint.is_some() || opts.resume.is_some() => {
+            anyhow::bail!("--checkpoint/--resume cannot be combined with --watch")
+        }
+        Some(_) if topology || topology_out.is_some() => {
+            anyhow::bail!("--topology/--topology-out cannot be combined with --watch")
+        }
+        Some(interval) => Some(utils::parse_duration(interval)?),
+        None => None,
+    };
+
+    let console = ConsoleProgress::default();
+    let progress = |event: ScanProgress<'_>| console.show(event);
+    let plan = plan_scan(opts, &progress).await?;
+
+    if let Some(interval) = interval {
+        return watch_scan(&plan, &progress, previous, output, interval).await;
+    }
+
+    let mut result = perform_scan(&plan, &progress).await?;
+
+    print_results(&result);
+    update_inventory(&result);
+    keep_latest(&result);
+
+    if let Some(previous) = &previous {
+        let changes = diff::diff(previous, &result);
+        print_diff(&changes);
+        result.diff = Some(changes);
+    }
+
+    let gateway = result.gateway;
+    let routes = match topology {
+        true => Some(trace_routes(&result, gateway).await),
+        false => None,
+    };
+    if let Some(routes) = &routes {
+        status!("\n{}", "Network Topology".bright_cyan().bold());
+        generate_topology(&result, gateway, routes, topology_limit);
+    }
+
+    if let Some(dot_path) = topology_out {
+        topology::save_dot(&result, gateway, routes.as_ref(), &dot_path)?;
+        status!("\n🗺  Topology graph saved to: {}", dot_path.bright_green());
+        status!("   Render with: dot -Tpng {} -o topology.png", dot_path);
+    }
+
+    if let Some(output_path) = output {
+        save_results(&result, &output_path)?;
+        status!("\n💾 Results saved to: {}", output_path.bright_green());
+    }
+
+    emit_result(&result)
+}
+
+/// Terminal rendering of `ScanProgress`: status lines, the progress bar and
+/// `--json-stream` device events
+#[derive(Default)]
+struct ConsoleProgress {
+    bar: parking_lot::Mutex<Option<ProgressBar>>,
+}
+
+impl ConsoleProgress {
+    fn show(&self, event: ScanProgress<'_>) {
+        match event {
+            ScanProgress::Planned { range, threads, hosts, ports, max_rate } => {
+                status!("📡 Target: {}", range.bright_yellow());
+                status!("🧵 Threads: {}", threads.to_string().bright_green());
+                status!("🖥  Hosts to scan: {}", hosts.to_string().bright_green());
+                if ports == 0 {
+                    status!("🔌 Ping sweep only, no ports scanned");
+                } else {
+                    status!("🔌 Scanning {} ports per host", ports);
+                }
+                if let Some(rate) = max_rate {
+                    status!("🐢 Rate limit: {} probes/sec", rate.to_string().bright_yellow());
+                }
+            }
+            ScanProgress::Randomized { seed } => {
+                status!("🔀 Randomized host order (seed {})", seed);
+            }
+            ScanProgress::Resumed { path, completed, devices } => {
+                status!("⏯  Resuming from {} ({} hosts done, {} devices found)",
+                         path.bright_green(), completed, devices);
+            }
+            ScanProgress::VendorDatabase { path, prefixes } => {
+                status!("🏷  Vendor database: {} ({} prefixes)", path.bright_green(), prefixes);
+            }
+            ScanProgress::ArpSweep { answered } => {
+                status!("📡 ARP sweep: {} hosts answered", answered.to_string().bright_green());
+            }
+            ScanProgress::ArpUnavailable(e) => {
+                status!("{} ARP sweep unavailable ({:#}), probing hosts instead", "⚠".yellow(), e);
+            }
+            ScanProgress::HostsStarted { total, done } => {
+                let pb = output::progress_bar(total as u64);
+                pb.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+                        .unwrap()
+                        .progress_chars("#>-")
+                );
+                pb.set_position(done as u64);
+                *self.bar.lock() = Some(pb);
+            }
+            ScanProgress::HostDone => {
+                if let Some(pb) = &*self.bar.lock() {
+                    pb.inc(1);
+                }
+            }
+            ScanProgress::Device(device) => {
+                if output::format() == Format::JsonStream {
+                    if let Err(e) = output::emit_json(&ScanEvent::Device(device)) {
+                        tracing::warn!("Failed to write device event: {:#}", e);
+                    }
+                }
+            }
+            ScanProgress::HostsFinished => {
+                if let Some(pb) = self.bar.lock().take() {
+                    pb.finish_with_message("Scan complete");
+                }
+            }
+            ScanProgress::Ssdp { answered } => {
+                status!("📺 SSDP: {} devices answered", answered.to_string().bright_green());
+            }
+            ScanProgress::SsdpUnavailable(e) => {
+                status!("{} SSDP discovery unavailable ({:#})", "⚠".yellow(), e);
+            }
+        }
+    }
+}
+
+/// Resolve targets, ports and the rest of `opts` into a `ScanPlan`
+async fn plan_scan(opts: ScanOptions, progress: ProgressFn<'_>) -> Result<ScanPlan> {
+    let ScanOptions {
+        lan, target, target_file, threads, ports, top_ports, exclude, exclude_file, timeout_ms, max_timeout_ms,
+        max_rate, checkpoint, checkpoint_every, resume, oui_db, names, snmp, snmp_community, ssdp, retries,
+        randomize, seed, ping_only, tls_info, http_info, services, interface, source_ip,
+    } = opts;
+
+    // Pinned before anything else asks for the local address
+    let source = source::resolve(interface.as_deref(), source_ip)?;
+    source::set(source.clone());
+
     let scan_range = if lan {
-        let local_ip = utils::get_local_ip()?;
-        format!("{}/24", local_ip)
-    } else if let Some(t) = target {
-        t
+        match &source {
+            Some(Source { ip, prefix, .. }) => format!("{}/{}", ip, prefix),
+            None => format!("{}/24", utils::get_local_ip()?),
+        }
     } else {
-        anyhow::bail!("Either --lan or --target must be specified");
+        match (&target, &target_file) {
+            (Some(t), Some(f)) => format!("{},@{}", t, f),
+            (Some(t), None) => t.clone(),
+            (None, Some(f)) => format!("@{}", f),
+            (None, None) => anyhow::bail!("Either --lan, --target or --target-file must be specified"),
+        }
     };
 
-    println!("📡 Target: {}", scan_range.bright_yellow());
-    println!("🧵 Threads: {}", threads.to_string().bright_green());
+    let mut groups = Vec::new();
+    if lan {
+        let (ip, prefix) = utils::parse_cidr(&scan_range)?;
+        groups.push(TargetGroup { label: scan_range.clone(), hosts: RangeSet::from_cidr(ip, prefix) });
+    } else if let Some(t) = &target {
+        groups.extend(targets::parse_target_list(t).await?);
+    }
+    if let Some(path) = &target_file {
+        let hosts = targets::load_target_file(path).await?;
+        groups.push(TargetGroup { label: format!("@{}", path), hosts });
+    }
 
-    let port_list = if let Some(port_str) = ports {
-        utils::network::parse_port_list(&port_str)
-    } else {
-        utils::network::COMMON_PORTS.to_vec()
+    let mut targets = RangeSet::new();
+    for group in &groups {
+        targets.union(&group.hosts);
+    }
+
+    let mut excludes = match &exclude {
+        Some(spec) => ExcludeList::parse(spec)?,
+        None => ExcludeList::default(),
     };
+    if let Some(path) = &exclude_file {
+        excludes.merge(ExcludeList::from_file(path)?);
+    }
 
-    println!("🔌 Scanning {} ports per host", port_list.len());
+    let mut port_list = match top_ports {
+        Some(n) => utils::network::top_ports(n)?,
+        None if ports.is_none() && !ping_only => utils::network::COMMON_PORTS.to_vec(),
+        None => Vec::new(),
+    };
+    if let Some(port_str) = ports {
+        port_list.extend(utils::network::parse_port_list(&port_str));
+    }
+    port_list.sort_unstable();
+    port_list.dedup();
 
-    let result = perform_scan(&scan_range, threads, &port_list).await?;
-    
-    println!("\n{}", "Scan Results".bright_green().bold());
-    println!("{}", "═".repeat(60).bright_green());
-    println!("⏱  Duration: {:.2}s", result.scan_duration.as_secs_f64());
-    println!("🖥  Total hosts scanned: {}", result.total_hosts);
-    println!("✅ Responsive hosts: {}", result.responsive_hosts.to_string().bright_green());
+    if max_rate == Some(0) {
+        anyhow::bail!("--max-rate must be greater than zero");
+    }
+
+    progress(ScanProgress::Planned {
+        range: &scan_range,
+        threads,
+        hosts: targets.len() - excludes.count_in(&targets),
+        ports: port_list.len(),
+        max_rate,
+    });
+
+    let resumed = match &resume {
+        Some(path) => {
+            let cp = Checkpoint::load(path)?;
+            progress(ScanProgress::Resumed { path, completed: cp.completed(), devices: cp.devices.len() });
+            Some(cp)
+        }
+        None => None,
+    };
+
+    // A resumed scan has to walk hosts in the order its checkpoint recorded
+    let seed = match &resumed {
+        Some(cp) => cp.seed,
+        None if randomize.unwrap_or(seed.is_some() || targets.len() > RANDOMIZE_ABOVE) => {
+            Some(seed.unwrap_or_else(rand::random))
+        }
+        None => None,
+    };
+    if let Some(seed) = seed {
+        progress(ScanProgress::Randomized { seed });
+    }
+
+    let oui_db = match &oui_db {
+        Some(path) => {
+            let db = OuiDatabase::cached(path)?;
+            progress(ScanProgress::VendorDatabase { path, prefixes: db.len() });
+            Some(db)
+        }
+        None => None,
+    };
+
+    let timing = ProbeTiming {
+        timeout: timeout_ms.map(Duration::from_millis),
+        max_timeout: Duration::from_millis(max_timeout_ms),
+    };
+
+    Ok(ScanPlan {
+        range: scan_range,
+        groups,
+        targets,
+        threads,
+        probe: Arc::new(HostProbe {
+            ports: Arc::from(port_list),
+            timing,
+            retry: RetryPolicy::new(retries),
+            names: names || lan,
+            snmp: snmp || snmp_community.is_some(),
+            snmp_community: snmp_community.unwrap_or_else(|| snmp::DEFAULT_COMMUNITY.to_string()),
+            ping_only,
+            tls_info,
+            http_info,
+            services,
+        }),
+        excludes,
+        max_rate,
+        // Keep checkpointing into the file we resumed from unless told otherwise
+        checkpoint: checkpoint.or(resume),
+        checkpoint_every,
+        resume: resumed,
+        oui_db,
+        arp_from: if lan && utils::is_privileged() { utils::get_local_ip().ok() } else { None },
+        ssdp,
+        seed,
+    })
+}
+
+fn print_results(result: &ScanResult) {
+    report!("\n{}", "Scan Results".bright_green().bold());
+    report!("{}", "═".repeat(60).bright_green());
+    report!("⏱  Duration: {:.2}s", result.scan_duration.as_secs_f64());
+    report!("🖥  Total hosts scanned: {}", result.total_hosts);
+    report!("⚡ Throughput: {:.1} hosts/sec",
+             result.total_hosts as f64 / result.scan_duration.as_secs_f64().max(f64::EPSILON));
+    if result.excluded_hosts > 0 {
+        report!("🚫 Excluded hosts: {}", result.excluded_hosts.to_string().bright_yellow());
+    }
+    report!("✅ Responsive hosts: {}", result.responsive_hosts.to_string().bright_green());
+    if let Some(rate) = result.probe_rate {
+        report!("🐢 Effective rate: {:.1} probes/sec", rate);
+    }
+    for conflict in &result.conflicts {
+        let macs: Vec<_> = conflict.macs.iter().map(|m| m.mac.to_string()).collect();
+        report!("{}", format!("⚠  IP conflict: {} answered by {}", conflict.ip, macs.join(", ")).bright_red().bold());
+    }
 
     if !result.devices.is_empty() {
-        println!("\n{}", "Discovered Devices:".bright_cyan().bold());
-        println!("{}", "─".repeat(60).bright_cyan());
+        report!("\n{}", "Discovered Devices:".bright_cyan().bold());
+        report!("{}", "─".repeat(60).bright_cyan());
         
         for device in &result.devices {
-            println!("\n{} {}", "►".bright_yellow(), device.ip.to_string().bright_white().bold());
+            if device.is_gateway {
+                report!("\n{} {} {}", "►".bright_yellow(), device.ip.to_string().bright_white().bold(), "(gateway)".bright_cyan());
+            } else {
+                report!("\n{} {}", "►".bright_yellow(), device.ip.to_string().bright_white().bold());
+            }
             
             if let Some(hostname) = &device.hostname {
-                println!("  Hostname: {}", hostname.bright_cyan());
+                match device.hostname_source {
+                    Some(source) => report!("  Hostname: {} ({})", hostname.bright_cyan(), source),
+                    None => report!("  Hostname: {}", hostname.bright_cyan()),
+                }
             }
             
             if let Some(mac) = &device.mac {
-                println!("  MAC: {} ({})", 
+                report!("  MAC: {} ({})", 
                          mac.to_string().bright_magenta(), 
-                         mac.vendor().bright_blue());
+                         device.vendor.as_deref().unwrap_or("Unknown").bright_blue());
             }
             
-            println!("  Latency: {:.2}ms", device.latency_ms);
+            if let Some(conflict) = result.conflicts.iter().find(|c| c.ip == device.ip) {
+                report!("  {}", format!("Conflict: {} MACs claim this address", conflict.macs.len()).bright_red());
+            }
+            
+            match device.latency_ms {
+                Some(latency) if device.attempts > 1 => {
+                    report!("  Latency: {:.2}ms (answered on attempt {})", latency, device.attempts);
+                }
+                Some(latency) => report!("  Latency: {:.2}ms", latency),
+                None => report!("  Latency: {}", "not measured".dimmed()),
+            }
             
             if !device.open_ports.is_empty() {
                 let port_strs: Vec<_> = device.open_ports.iter()
-                    .map(|p| format_port(*p))
+                    .map(|p| {
+                        let port = format_port(*p, device.services.iter().find(|s| s.port == *p));
+                        match device.http.iter().find(|h| h.port == *p).and_then(|h| h.title.as_deref()) {
+                            Some(title) => format!("{} \"{}\"", port, title.bright_green()),
+                            None => port,
+                        }
+                    })
                     .collect();
-                println!("  Open Ports: {}", port_strs.join(", "));
+                report!("  Open Ports: {}", port_strs.join(", "));
+            }
+            
+            if let (Some(slow), Some(host)) = (device.slow_port(), device.latency_ms) {
+                let ms = slow.latency_ms.unwrap_or_default();
+                report!("  {}", format!("Slow port: {} answers in {:.1}ms ({:.0}× host latency)",
+                                        slow.port, ms, ms / host.max(f64::EPSILON)).bright_yellow());
+            }
+            
+            let count = |state| device.ports.iter().filter(|p| p.state == state).count();
+            let filtered = count(PortState::Filtered);
+            if filtered > 0 {
+                report!("  Not Open: {} closed, {} filtered",
+                         count(PortState::Closed), filtered.to_string().bright_yellow());
+            }
+            
+            if let Some(info) = &device.ssdp {
+                let server = info.server.as_deref().unwrap_or("UPnP device");
+                match device.discovery_method {
+                    Some(DiscoveryMethod::Ssdp) => report!("  UPnP: {} (found via SSDP only)", server),
+                    _ => report!("  UPnP: {}", server),
+                }
+            }
+            
+            let now = chrono::Utc::now();
+            for cert in &device.tls {
+                let mut line = format!("  TLS {}: {}", cert.port, cert.common_name());
+                if !cert.san.is_empty() {
+                    line.push_str(&format!(" (SAN: {})", cert.san.join(", ")));
+                }
+                if cert.self_signed {
+                    line.push_str(", self-signed");
+                }
+                let expiry = format!("expires {}", cert.not_after.format("%Y-%m-%d"));
+                if cert.is_expired(now) {
+                    report!("{}, {}", line, format!("EXPIRED {}", cert.not_after.format("%Y-%m-%d")).bright_red().bold());
+                } else if cert.expires_soon(now) {
+                    report!("{}, {}", line, expiry.bright_yellow());
+                } else {
+                    report!("{}, {}", line, expiry);
+                }
+            }
+            
+            if let Some(name) = &device.snmp_name {
+                report!("  SNMP Name: {}", name.bright_cyan());
+            }
+            if let Some(descr) = &device.snmp_descr {
+                report!("  SNMP Descr: {}", descr.lines().next().unwrap_or_default());
             }
             
             if let Some(os) = &device.os_guess {
-                println!("  OS: {}", os.bright_green());
+                report!("  OS: {} ({:.0}%)", os.bright_green(), device.os_confidence * 100.0);
             }
         }
     }
+}
 
-    if topology {
-        println!("\n{}", "Network Topology".bright_cyan().bold());
-        generate_topology(&result)?;
+/// Fold a finished scan into the device inventory; a failure here is
+/// reported but never fails the scan
+fn update_inventory(result: &ScanResult) {
+    let updated = inventory::default_path().and_then(|path| {
+        let mut known = Inventory::load(&path)?;
+        let summary = known.record(&result.devices);
+        known.save(&path)?;
+        Ok(summary)
+    });
+    match updated {
+        Ok(summary) => status!("📒 Inventory: {} new, {} known devices updated",
+                                summary.new.to_string().bright_green(), summary.known),
+        Err(e) => tracing::warn!("Failed to update device inventory: {:#}", e),
     }
+}
 
-    if let Some(output_path) = output {
-        save_results(&result, &output_path)?;
-        println!("\n💾 Results saved to: {}", output_path.bright_green());
+/// Keep `result` as the latest scan for `report`
+fn keep_latest(result: &ScanResult) {
+    if let Err(e) = latest_path().and_then(|path| store_latest(&path, result)) {
+        status!("⚠️  Could not keep the scan for reports: {:#}", e);
     }
+}
 
+/// `$XDG_DATA_HOME/netweaver/scan.json`, the latest scan
+pub fn latest_path() -> Result<PathBuf> {
+    Ok(utils::data_dir()?.join("scan.json"))
+}
+
+/// The latest scan, if one ever ran here
+pub fn load_latest(path: &Path) -> Result<Option<ScanResult>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+    }
+}
+
+pub fn store_latest(path: &Path, result: &ScanResult) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(result)?)
+        .with_context(|| format!("Failed to write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace '{}'", path.display()))
+}
+
+/// `scan --inventory`: every device previous scans have found, most
+/// recently seen first
+pub fn show_inventory() -> Result<()> {
+    let path = inventory::default_path()?;
+    let mut known = Inventory::load(&path)?;
+    known.devices.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then(a.ip.cmp(&b.ip)));
+
+    if !output::is_human() {
+        return output::emit_json(&known);
+    }
+
+    report!("{}", "Device Inventory".bright_cyan().bold());
+    report!("{}", "═".repeat(60).bright_cyan());
+    report!("📒 {} ({} devices)", path.display().to_string().bright_green(), known.devices.len());
+
+    let now = utils::get_timestamp_us();
+    for entry in &known.devices {
+        let ago = utils::format_ago(Duration::from_micros(now.saturating_sub(entry.last_seen)));
+        let first_seen = chrono::DateTime::from_timestamp_micros(entry.first_seen as i64)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+
+        report!("\n{} {}  {}", "►".bright_yellow(), entry.ip.to_string().bright_white().bold(), ago.bright_green());
+        if let Some(hostname) = &entry.hostname {
+            report!("  Hostname: {}", hostname.bright_cyan());
+        }
+        if let Some(mac) = &entry.mac {
+            report!("  MAC: {} ({})",
+                     mac.to_string().bright_magenta(),
+                     entry.vendor.as_deref().unwrap_or("Unknown").bright_blue());
+        }
+        if !entry.open_ports.is_empty() {
+            let ports: Vec<_> = entry.open_ports.iter().map(|p| format_port(*p, None)).collect();
+            report!("  Open Ports: {}", ports.join(", "));
+        }
+        report!("  First seen: {} ({} changes recorded)", first_seen, entry.history.len());
+    }
     Ok(())
 }
 
-async fn perform_scan(range: &str, _thread_count: usize, ports: &[u16]) -> Result<ScanResult> {
-    let (ip, prefix) = utils::parse_cidr(range)?;
-    let ip_list = utils::cidr_to_range(ip, prefix);
-    
-    let total_hosts = ip_list.len();
+/// Re-run the scan every `interval`, printing only what changed after the first pass
+///
+/// With `output` set, each iteration is appended as one JSON line so the file
+/// becomes an append-only history. Ctrl+C lets the in-flight iteration finish
+/// before exiting.
+async fn watch_scan(
+    plan: &ScanPlan,
+    progress: ProgressFn<'_>,
+    mut last: Option<ScanResult>,
+    output: Option<String>,
+    interval: Duration,
+) -> Result<()> {
+    status!("👁  Watching every {:?} (Ctrl+C to stop)", interval);
+
+    let (stop_tx, mut stop_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = stop_tx.send(true);
+        }
+    });
+
+    let mut iteration = 0usize;
+    loop {
+        iteration += 1;
+        let mut result = perform_scan(plan, progress).await?;
+        update_inventory(&result);
+        keep_latest(&result);
+
+        match &last {
+            None => print_results(&result),
+            Some(previous) => {
+                let changes = diff::diff(previous, &result);
+                print_watch_changes(iteration, &changes);
+                result.diff = Some(changes);
+            }
+        }
+
+        if let Some(path) = &output {
+            append_result_line(&result, path)?;
+        }
+        emit_result(&result)?;
+
+        last = Some(result);
+
+        if *stop_rx.borrow() {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = stop_rx.changed() => break,
+        }
+    }
+
+    status!("\n{}", "Watch stopped".bright_green());
+    Ok(())
+}
+
+fn print_watch_changes(iteration: usize, changes: &ScanDiff) {
+    let stamp = chrono::Local::now().format("%H:%M:%S").to_string();
+    let prefix = format!("[{}] #{}", stamp, iteration).bright_black();
+
+    if changes.is_empty() {
+        report!("{} no changes", prefix);
+        return;
+    }
+
+    for device in &changes.new_devices {
+        report!("{} {} {} joined", prefix, "+".bright_green(), device.ip.to_string().bright_white());
+    }
+    for device in &changes.missing_devices {
+        report!("{} {} {} went offline", prefix, "-".bright_red(), device.ip.to_string().bright_white());
+    }
+    for moved in &changes.moved_devices {
+        report!("{} {} {} moved {} → {}", prefix, "⇄".bright_magenta(), moved.mac, moved.from, moved.to);
+    }
+    for change in &changes.changed_devices {
+        for port in &change.opened_ports {
+            report!("{} {} {} opened {}", prefix, "~".bright_yellow(), change.ip, format_port(*port, None));
+        }
+        for port in &change.closed_ports {
+            report!("{} {} {} closed {}", prefix, "~".bright_yellow(), change.ip, format_port(*port, None));
+        }
+    }
+}
+
+async fn perform_scan(plan: &ScanPlan, progress: ProgressFn<'_>) -> Result<ScanResult> {
+    let excludes = &plan.excludes;
+    let excluded_hosts = excludes.count_in(&plan.targets);
+    let total_hosts = plan.targets.len() - excluded_hosts;
+    let resume = plan.resume.as_ref();
+    if let Some(cp) = resume {
+        if cp.network_range != plan.range || cp.total_hosts != total_hosts {
+            anyhow::bail!(
+                "Checkpoint was taken for {} ({} hosts), not {} ({} hosts)",
+                cp.network_range, cp.total_hosts, plan.range, total_hosts
+            );
+        }
+    }
+
+    // Indices are positions in the filtered host sequence, which is what
+    // checkpoints record; already-completed positions are skipped on resume
+    let order: Box<dyn Iterator<Item = Ipv4Addr> + Send + '_> = match plan.seed {
+        Some(seed) => Box::new(plan.targets.shuffled(seed)),
+        None => Box::new(plan.targets.iter()),
+    };
+    let hosts = order
+        .filter(|ip| !excludes.contains(*ip))
+        .enumerate()
+        .filter(|(idx, _)| !resume.is_some_and(|cp| cp.is_done(*idx)));
     let start = Instant::now();
-    
-    let pb = ProgressBar::new(total_hosts as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-            .unwrap()
-            .progress_chars("#>-")
-    );
 
-    let devices = Arc::new(Mutex::new(Vec::new()));
-    let pb = Arc::new(pb);
+    // One bucket per pass: the limiter throttles how fast probes start,
+    // while `threads` below bounds how many hosts are in flight
+    let limiter = plan.max_rate.map(|rate| Arc::new(RateLimiter::new(rate)));
 
-    let tasks: Vec<_> = ip_list
-        .into_iter()
-        .map(|ip| {
-            let devices = Arc::clone(&devices);
-            let pb = Arc::clone(&pb);
-            let ports = ports.to_vec();
-            
-            tokio::spawn(async move {
-                if let Some(device) = scan_host(ip, &ports).await {
-                    devices.lock().await.push(device);
+    let (arp_replies, conflicts) = match plan.arp_from {
+        Some(local_ip) => match arp_sweep(plan, local_ip, limiter.clone(), progress).await {
+            Some(sweep) => {
+                let conflicts = sweep.conflicts();
+                (Some(Arc::new(sweep.replies)), conflicts)
+            }
+            None => (None, Vec::new()),
+        },
+        None => (None, Vec::new()),
+    };
+
+    // SSDP responses trickle in over a couple of seconds, so listen while
+    // the probes run rather than in front of them
+    let ssdp_task = match (plan.ssdp, utils::get_local_ip()) {
+        (true, Ok(local_ip)) => Some(tokio::spawn(ssdp::discover(local_ip, ssdp::DEFAULT_WAIT))),
+        (true, Err(e)) => {
+            progress(ScanProgress::SsdpUnavailable(&e));
+            None
+        }
+        (false, _) => None,
+    };
+
+    progress(ScanProgress::HostsStarted { total: total_hosts, done: resume.map_or(0, |cp| cp.completed()) });
+
+    let tracker = plan.checkpoint.as_ref().map(|path| {
+        CheckpointTracker::new(path.clone(), plan.checkpoint_every, plan.range.clone(), total_hosts, resume)
+            .with_seed(plan.seed)
+    });
+
+    // Hosts are pulled from the iterator lazily and at most `threads`
+    // probes are in flight, so memory stays flat regardless of range size
+    let scanned: Vec<Device> = stream::iter(hosts)
+        .map(|(idx, ip)| {
+            if let Some(tracker) = &tracker {
+                tracker.start(idx);
+            }
+            let probe = Arc::clone(&plan.probe);
+            let limiter = limiter.clone();
+            // We never ARP for our own address, so probe it as usual
+            let discovery = match &arp_replies {
+                Some(replies) if Some(ip) != plan.arp_from => Discovery::Arp(replies.get(&ip).copied()),
+                _ => Discovery::Probe,
+            };
+            let handle = tokio::spawn(scan_host(ip, discovery, probe, limiter));
+            async move { (idx, handle.await.ok().flatten()) }
+        })
+        .buffer_unordered(plan.threads.max(1))
+        .filter_map(|(idx, device)| {
+            progress(ScanProgress::HostDone);
+            if let Some(device) = &device {
+                progress(ScanProgress::Device(device));
+            }
+            if let Some(tracker) = &tracker {
+                if let Err(e) = tracker.finish(idx, device.as_ref()) {
+                    tracing::warn!("Checkpoint write failed: {:#}", e);
                 }
-                pb.inc(1);
-            })
+            }
+            future::ready(device)
         })
-        .collect();
+        .collect()
+        .await;
+
+    progress(ScanProgress::HostsFinished);
+
+    if let Some(tracker) = &tracker {
+        tracker.save()?;
+    }
+
+    let mut devices = resume.map(|cp| cp.devices.clone()).unwrap_or_default();
+    devices.extend(scanned);
 
-    for task in tasks {
-        let _ = task.await;
+    if let Some(task) = ssdp_task {
+        match task.await {
+            Ok(Ok(found)) => merge_ssdp(plan, &mut devices, found, progress),
+            Ok(Err(e)) => progress(ScanProgress::SsdpUnavailable(&e)),
+            Err(e) => tracing::warn!("SSDP task failed: {}", e),
+        }
     }
 
-    pb.finish_with_message("Scan complete");
+    let gateway = match utils::get_default_gateway() {
+        Ok(gateway) => Some(gateway),
+        Err(e) => {
+            tracing::debug!("No default gateway: {:#}", e);
+            None
+        }
+    };
+    if let Some(gateway) = gateway {
+        mark_gateway(plan, &mut devices, gateway);
+    }
+
+    if let Some(db) = &plan.oui_db {
+        for device in &mut devices {
+            if let Some(vendor) = device.mac.as_ref().and_then(|m| db.lookup(m.oui())) {
+                device.vendor = Some(vendor.to_string());
+            }
+        }
+    }
+
+    if plan.groups.len() > 1 {
+        for device in &mut devices {
+            device.source_range = plan.groups.iter()
+                .find(|g| g.hosts.contains(device.ip))
+                .map(|g| g.label.clone());
+        }
+    }
 
     let duration = start.elapsed();
-    let devices = Arc::try_unwrap(devices).unwrap().into_inner();
-    let responsive_hosts = devices.len();
+    let responsive_hosts = devices.iter()
+        .filter(|d| d.discovery_method != Some(DiscoveryMethod::Route))
+        .count();
+    let probe_rate = limiter
+        .map(|l| l.acquired() as f64 / duration.as_secs_f64().max(f64::EPSILON));
 
     Ok(ScanResult {
         devices,
         scan_duration: duration,
-        network_range: range.to_string(),
+        network_range: plan.range.clone(),
         total_hosts,
         responsive_hosts,
+        excluded_hosts,
+        diff: None,
+        probe_rate,
+        conflicts,
+        gateway,
     })
 }
 
+/// Flag the gateway, adding it unprobed if it's in range but never answered
+/// so the topology still has its root
+fn mark_gateway(plan: &ScanPlan, devices: &mut Vec<Device>, gateway: Ipv4Addr) {
+    match devices.iter_mut().find(|d| d.ip == gateway) {
+        Some(device) => device.is_gateway = true,
+        None if plan.targets.contains(gateway) && !plan.excludes.contains(gateway) => {
+            let mut device = Device::unprobed(gateway, DiscoveryMethod::Route);
+            device.is_gateway = true;
+            devices.push(device);
+        }
+        None => {}
+    }
+}
+
+/// Attach SSDP details to scanned devices and add in-scope hosts that only
+/// answered SSDP
+fn merge_ssdp(
+    plan: &ScanPlan,
+    devices: &mut Vec<Device>,
+    found: HashMap<Ipv4Addr, ssdp::SsdpInfo>,
+    progress: ProgressFn<'_>,
+) {
+    progress(ScanProgress::Ssdp { answered: found.len() });
+
+    for (ip, info) in found {
+        if !plan.targets.contains(ip) || plan.excludes.contains(ip) {
+            continue;
+        }
+        let index = match devices.iter().position(|d| d.ip == ip) {
+            Some(index) => index,
+            None => {
+                devices.push(Device::unprobed(ip, DiscoveryMethod::Ssdp));
+                devices.len() - 1
+            }
+        };
+
+        let device = &mut devices[index];
+        if device.hostname.is_none() {
+            if let Some(name) = &info.friendly_name {
+                device.hostname = Some(name.clone());
+                device.hostname_source = Some(NameSource::Upnp);
+            }
+        }
+        device.ssdp = Some(info);
+        if device.discovery_method == Some(DiscoveryMethod::Ssdp) {
+            progress(ScanProgress::Device(device));
+        }
+    }
+}
+
+/// ARP-sweep the scan targets, or None (falling back to probes) if the
+/// datalink channel can't be opened
+async fn arp_sweep(
+    plan: &ScanPlan,
+    local_ip: Ipv4Addr,
+    limiter: Option<Arc<RateLimiter>>,
+    progress: ProgressFn<'_>,
+) -> Option<arp::Sweep> {
+    let targets = plan.targets.clone();
+    let excludes = plan.excludes.clone();
+    let wait = plan.probe.timing.liveness_timeout();
+
+    let result = tokio::task::spawn_blocking(move || {
+        let hosts = targets.iter().filter(|ip| !excludes.contains(*ip));
+        arp::sweep(local_ip, hosts, wait, limiter.as_deref())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(sweep)) => {
+            progress(ScanProgress::ArpSweep { answered: sweep.replies.len() });
+            Some(sweep)
+        }
+        Ok(Err(e)) => {
+            progress(ScanProgress::ArpUnavailable(&e));
+            None
+        }
+        Err(e) => {
+            tracing::warn!("ARP sweep task failed: {}", e);
+            None
+        }
+    }
+}
+
 /// Comprehensive host scanning with multi-stage intelligence gathering
 /// 
 /// Stages:
-/// 1. Liveness detection (ICMP + TCP fallback)
+/// 1. Liveness detection (ARP sweep result, or ICMP + TCP fallback)
 /// 2. Port scanning for service discovery
 /// 3. MAC address resolution for vendor identification
-/// 4. OS fingerprinting based on port patterns and behavior
-/// 5. DNS reverse lookup for hostname resolution
+/// 4. OS fingerprinting from TTL, SYN-ACK window/options and open ports
+/// 5. mDNS/NetBIOS name and SNMP system queries, run alongside the port scan
 ///
 /// Returns None if host is unreachable, Some(Device) with gathered intel otherwise
-async fn scan_host(ip: Ipv4Addr, ports: &[u16]) -> Option<Device> {
-    let start = Instant::now();
-    
-    // Stage 1: Liveness detection
-    if !is_host_alive(ip).await {
-        return None;
-    }
-    
-    let latency = start.elapsed().as_micros() as f64 / 1000.0;
-    
-    // Stage 2: Port scanning - parallel TCP connect for speed
-    let open_ports = scan_ports(ip, ports).await;
+async fn scan_host(
+    ip: Ipv4Addr,
+    discovery: Discovery,
+    probe: Arc<HostProbe>,
+    limiter: Option<Arc<RateLimiter>>,
+) -> Option<Device> {
+    let timing = probe.timing;
+
+    // Stage 1: Liveness detection, which also gives us an initial RTT
+    let discovery_method = match discovery {
+        Discovery::Arp(_) => DiscoveryMethod::Arp,
+        Discovery::Probe => DiscoveryMethod::Probe,
+    };
+    let (reply, arp_mac, attempts) = match discovery {
+        Discovery::Arp(arp) => {
+            let arp = arp?;
+            (ProbeReply { rtt: arp.rtt, ttl: None, kind: ProbeKind::Arp }, Some(arp.mac), 1)
+        }
+        Discovery::Probe => {
+            let alive = probe.retry
+                .run(|| is_host_alive(ip, timing.liveness_timeout(), limiter.as_deref()), Option::is_some)
+                .await;
+            (alive.value?, None, alive.attempts)
+        }
+    };
+
+    // ARP already timed the host; other probes are repeated and the median kept
+    let rtt = match reply.kind {
+        ProbeKind::Arp => reply.rtt,
+        _ => median_rtt(ip, &reply, timing.liveness_timeout(), limiter.as_deref()).await,
+    };
+    let latency = rtt.as_micros() as f64 / 1000.0;
     
-    // Stage 3: DNS reverse lookup (capability depends on tokio version)
-    let hostname: Option<String> = None;
+    // Stage 2: Port scanning - parallel TCP connect for speed, with the
+    // timeout scaled to this host's RTT so LAN hosts finish fast and slow
+    // WAN/VPN hosts aren't misreported as closed
+    let port_timeout = timing.port_timeout(rtt);
+    tracing::debug!("{}: rtt {:?}, port timeout {:?}", ip, rtt, port_timeout);
+
+    // Stage 3: Name and SNMP queries share the port scan's wall-clock time
+    let name_lookup = async {
+        if probe.names { names::lookup(ip, names::DEFAULT_TIMEOUT).await } else { None }
+    };
+    let snmp_query = async {
+        if probe.snmp && !probe.ping_only { snmp::query(ip, &probe.snmp_community, snmp::DEFAULT_TIMEOUT).await } else { None }
+    };
+    let (ports, name, mut system) = tokio::join!(
+        scan_ports(ip, Arc::clone(&probe.ports), port_timeout, probe.retry, limiter.clone()),
+        name_lookup,
+        snmp_query,
+    );
+    let open_ports: Vec<u16> = ports.iter()
+        .filter(|p| p.state == PortState::Open)
+        .map(|p| p.port)
+        .collect();
+    tracing::debug!("{}: open ports {:?}, name {:?}, snmp {:?}", ip, open_ports, name, system);
+    let (hostname, hostname_source) = name.map_or((None, None), |(name, source)| (Some(name), Some(source)));
+
+    // Certificates and pages are fetched once the name is known, so it can go
+    // out as SNI and Host
+    let certificates = async {
+        match probe.tls_info {
+            true => grab_certificates(ip, &open_ports, hostname.as_deref(), limiter.as_deref()).await,
+            false => Vec::new(),
+        }
+    };
+    let pages = async {
+        match probe.http_info {
+            true => fetch_pages(ip, &open_ports, hostname.as_deref(), limiter.as_deref()).await,
+            false => Vec::new(),
+        }
+    };
+    let (tls, http) = tokio::join!(certificates, pages);
+    let services = match probe.services {
+        true => identify_services(ip, &open_ports, limiter.as_deref()).await,
+        false => Vec::new(),
+    };
     
     // Stage 4: MAC address resolution (works best on local network)
-    let mac = get_mac_address(ip).await;
+    let mac = match arp_mac {
+        Some(mac) => Some(mac),
+        None => get_mac_address(ip).await,
+    };
     let vendor = mac.as_ref().map(|m| m.vendor().to_string());
     
-    // Stage 5: OS fingerprinting using heuristics
-    let os_guess = guess_os(&open_ports, latency);
+    // Stage 5: OS fingerprinting from the SYN-ACK (raw sockets only) and TTL
+    let syn_ack = match (open_ports.first().copied(), utils::get_local_ip()) {
+        (Some(port), Ok(src)) => {
+            if let Some(limiter) = &limiter {
+                limiter.acquire().await;
+            }
+            tokio::task::spawn_blocking(move || fingerprint::syn_probe(src, ip, port, port_timeout))
+                .await
+                .ok()
+                .flatten()
+        }
+        _ => None,
+    };
+    let tcp = syn_ack.map(|s| s.signature);
+    let mut ttl = reply.ttl;
+    if ttl.is_none() && tcp.is_none() {
+        if let Some(limiter) = &limiter {
+            limiter.acquire().await;
+        }
+        ttl = icmp::echo(ip, icmp::DEFAULT_TTL, timing.liveness_timeout()).await.and_then(|p| p.ttl);
+    }
+    let mut guess = fingerprint::infer(ttl, tcp.as_ref(), &open_ports, Some(latency));
+    tracing::debug!("{}: ttl {:?}, syn-ack {:?}, os guess {:?}", ip, ttl, tcp, guess);
+
+    // Stage 6: Likely network gear gets asked over SNMP even without --snmp,
+    // and a sysDescr match beats any heuristic guess
+    if system.is_none() && !probe.ping_only && guess.as_ref().is_some_and(|g| g.name == fingerprint::NETWORK_DEVICE) {
+        system = snmp::query(ip, &probe.snmp_community, snmp::DEFAULT_TIMEOUT).await;
+    }
+    let system = system.unwrap_or_default();
+    if let Some(identified) = fingerprint::from_snmp(system.descr.as_deref(), system.object_id.as_deref()) {
+        guess = Some(identified);
+    }
     
     Some(Device {
         ip,
         mac,
         hostname,
+        hostname_source,
         open_ports,
-        os_guess,
-        latency_ms: latency,
+        ports,
+        os_confidence: guess.as_ref().map_or(0.0, |g| g.confidence),
+        os_guess: guess.map(|g| g.name),
+        latency_ms: Some(latency),
+        attempts,
         vendor,
         last_seen: utils::get_timestamp_us(),
+        source_range: None,
+        snmp_name: system.name,
+        snmp_descr: system.descr,
+        discovery_method: Some(discovery_method),
+        ssdp: None,
+        tls,
+        http,
+        services,
+        is_gateway: false,
     })
 }
 
-async fn is_host_alive(ip: Ipv4Addr) -> bool {
-    let addr = SocketAddr::new(IpAddr::V4(ip), 80);
-    // Use tokio's TCP stream for async operation
-    let connect_result = tokio::time::timeout(
-        Duration::from_millis(500),
-        tokio::net::TcpStream::connect(addr)
-    ).await;
-    
-    connect_result.is_ok() || ping_host(ip).await
+/// Index pages of whichever open ports serve the web
+async fn fetch_pages(
+    ip: Ipv4Addr,
+    open_ports: &[u16],
+    hostname: Option<&str>,
+    limiter: Option<&RateLimiter>,
+) -> Vec<http::HttpInfo> {
+    let ports = open_ports.iter().copied().filter(|port| http::HTTP_PORTS.contains(port));
+    future::join_all(ports.map(|port| async move {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        http::fetch(ip, port, hostname, http::DEFAULT_TIMEOUT).await
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect()
 }
 
-async fn ping_host(ip: Ipv4Addr) -> bool {
-    tokio::task::spawn_blocking(move || {
-        std::process::Command::new("ping")
-            .args(&["-c", "1", "-W", "1", &ip.to_string()])
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
-    })
+/// Banner and probe identification for every open port, run side by side
+async fn identify_services(
+    ip: Ipv4Addr,
+    open_ports: &[u16],
+    limiter: Option<&RateLimiter>,
+) -> Vec<services::ServiceInfo> {
+    future::join_all(open_ports.iter().map(|&port| {
+        services::identify(ip, port, services::DEFAULT_BUDGET, limiter)
+    }))
     .await
-    .unwrap_or(false)
+    .into_iter()
+    .flatten()
+    .collect()
 }
 
-async fn scan_ports(ip: Ipv4Addr, ports: &[u16]) -> Vec<u16> {
-    let results: Vec<_> = ports
-        .par_iter()
-        .filter_map(|&port| {
-            let addr = SocketAddr::new(IpAddr::V4(ip), port);
-            if TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok() {
-                Some(port)
-            } else {
-                None
-            }
-        })
-        .collect();
+/// Certificates from whichever open ports speak TLS
+async fn grab_certificates(
+    ip: Ipv4Addr,
+    open_ports: &[u16],
+    hostname: Option<&str>,
+    limiter: Option<&RateLimiter>,
+) -> Vec<tls::TlsInfo> {
+    let ports = open_ports.iter().copied().filter(|port| tls::TLS_PORTS.contains(port));
+    future::join_all(ports.map(|port| async move {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        tls::grab(ip, port, hostname, tls::DEFAULT_TIMEOUT).await
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Round trips timed per host; the first is the liveness answer itself
+const LATENCY_PROBES: usize = 3;
+
+/// Tried before falling back to ICMP echo
+const LIVENESS_PORT: u16 = 80;
+
+/// Which liveness probe got an answer, so it can be repeated for timing
+#[derive(Debug, Clone, Copy)]
+enum ProbeKind {
+    Tcp(u16),
+    Icmp,
+    Arp,
+}
+
+/// A liveness answer; the TTL is only known when the ping probe answered
+struct ProbeReply {
+    rtt: Duration,
+    ttl: Option<u8>,
+    kind: ProbeKind,
+}
+
+/// Repeat whichever probe answered and take the median round trip, so one
+/// delayed reply (or a cold ARP cache on the first) doesn't set the latency
+async fn median_rtt(
+    ip: Ipv4Addr,
+    first: &ProbeReply,
+    timeout: Duration,
+    limiter: Option<&RateLimiter>,
+) -> Duration {
+    let mut samples = LatencyAnalyzer::new(LATENCY_PROBES);
+    samples.add_sample(first.rtt.as_secs_f64() * 1000.0);
+    for _ in 1..LATENCY_PROBES {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        let rtt = match first.kind {
+            ProbeKind::Tcp(port) => tcp_rtt(SocketAddr::new(IpAddr::V4(ip), port), timeout).await,
+            ProbeKind::Icmp => icmp::echo(ip, icmp::DEFAULT_TTL, timeout).await
+                .filter(|reply| reply.kind == icmp::ReplyKind::EchoReply)
+                .map(|reply| reply.rtt),
+            ProbeKind::Arp => None,
+        };
+        if let Some(rtt) = rtt {
+            samples.add_sample(rtt.as_secs_f64() * 1000.0);
+        }
+    }
+    Duration::from_secs_f64(samples.median() / 1000.0)
+}
+
+/// Time a TCP handshake; a refusal is an answer too, and just as fast
+pub(crate) async fn tcp_rtt(addr: SocketAddr, timeout: Duration) -> Option<Duration> {
+    let start = Instant::now();
+    match tokio::time::timeout(timeout, source::connect(addr)).await {
+        Ok(Ok(_)) => Some(start.elapsed()),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => Some(start.elapsed()),
+        _ => None,
+    }
+}
+
+/// Returns the round-trip time of whichever probe got an answer,
+/// or None if the host never responded
+async fn is_host_alive(
+    ip: Ipv4Addr,
+    timeout: Duration,
+    limiter: Option<&RateLimiter>,
+) -> Option<ProbeReply> {
+    if let Some(limiter) = limiter {
+        limiter.acquire().await;
+    }
+    // Unreachable errors are a failure to connect, not an answer from the host
+    if let Some(rtt) = tcp_rtt(SocketAddr::new(IpAddr::V4(ip), LIVENESS_PORT), timeout).await {
+        return Some(ProbeReply { rtt, ttl: None, kind: ProbeKind::Tcp(LIVENESS_PORT) });
+    }
     
-    results
+    if let Some(limiter) = limiter {
+        limiter.acquire().await;
+    }
+    let ping = icmp::echo(ip, icmp::DEFAULT_TTL, timeout).await
+        .filter(|reply| reply.kind == icmp::ReplyKind::EchoReply)?;
+    Some(ProbeReply { rtt: ping.rtt, ttl: ping.ttl, kind: ProbeKind::Icmp })
+}
+
+/// Blocking connects (and rate-limit waits) run on rayon via `spawn_blocking`
+/// so they never stall the tokio workers driving other hosts' probes
+async fn scan_ports(
+    ip: Ipv4Addr,
+    ports: Arc<[u16]>,
+    timeout: Duration,
+    retry: RetryPolicy,
+    limiter: Option<Arc<RateLimiter>>,
+) -> Vec<PortResult> {
+    tokio::task::spawn_blocking(move || {
+        ports
+            .par_iter()
+            .map(|&port| {
+                let addr = SocketAddr::new(IpAddr::V4(ip), port);
+                let connect = || {
+                    if let Some(limiter) = &limiter {
+                        limiter.acquire_blocking();
+                    }
+                    let start = Instant::now();
+                    let result = source::connect_timeout(&addr, timeout);
+                    PortResult::from_connect(port, &result, start.elapsed())
+                };
+                // A refusal is a definite answer; only silence is worth retrying
+                let mut result = retry.run_blocking(&connect, |r| r.state != PortState::Filtered).value;
+
+                // Retries to spare go on re-timing open ports, so a single slow
+                // handshake doesn't stand for the port
+                if let (PortState::Open, Some(first)) = (result.state, result.latency_ms) {
+                    let mut samples = LatencyAnalyzer::new(retry.retries as usize + 1);
+                    samples.add_sample(first);
+                    for _ in 0..retry.retries {
+                        let again = connect();
+                        if let (PortState::Open, Some(ms)) = (again.state, again.latency_ms) {
+                            samples.add_sample(ms);
+                        }
+                    }
+                    result.latency_ms = Some(samples.median());
+                }
+                result
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
 }
 
 async fn get_mac_address(_ip: Ipv4Addr) -> Option<MacAddress> {
-    if let Ok(mac) = mac_address::get_mac_address() {
-        if let Some(mac_addr) = mac {
-            return Some(MacAddress::new(mac_addr.bytes()));
-        }
+    if let Ok(Some(mac_addr)) = mac_address::get_mac_address() {
+        return Some(MacAddress::new(mac_addr.bytes()));
     }
     None
 }
 
-/// OS fingerprinting using passive analysis
-/// 
-/// Heuristics used:
-/// - Port combinations (e.g., 3389 → Windows, 22+80 → Linux)
-/// - Response latency (sub-millisecond → local network device)
-/// - Port count and pattern (many ports → router/gateway)
-/// - Service signatures from known OS distributions
-///
-/// Note: This is probabilistic detection, not definitive identification
-/// For production use, integrate with Nmap's OS detection database
-fn guess_os(open_ports: &[u16], latency: f64) -> Option<String> {
-    // Windows indicators: RDP, SMB, Active Directory
-    if open_ports.contains(&3389) {
-        Some("Windows".to_string())
-    } 
-    // Linux server indicators: SSH + web services
-    else if open_ports.contains(&22) && open_ports.contains(&80) {
-        Some("Linux".to_string())
-    } 
-    // macOS indicators: AFP, VNC
-    else if open_ports.contains(&548) || open_ports.contains(&5900) {
-        Some("macOS".to_string())
-    } 
-    // Network infrastructure: very low latency + many services
-    else if latency < 1.0 && open_ports.len() > 5 {
-        Some("Local Router/Switch".to_string())
-    } 
-    else {
-        None
-    }
-}
-
-fn format_port(port: u16) -> String {
+/// A port with its service: the identified one when probing found it,
+/// otherwise the usual service for that port number
+fn format_port(port: u16, identified: Option<&services::ServiceInfo>) -> String {
+    if let Some(service) = identified {
+        let label = match &service.version {
+            Some(version) => format!("{} {}", service.name, version),
+            None => service.name.clone(),
+        };
+        return format!("{} ({})", port.to_string().bright_yellow(), label.bright_cyan());
+    }
+
     let service = match port {
         21 => "FTP",
         22 => "SSH",
@@ -343,29 +1671,116 @@ fn format_port(port: u16) -> String {
     }
 }
 
-fn generate_topology(result: &ScanResult) -> Result<()> {
-    println!("\n{}", "Network Map:".bright_white().bold());
-    println!("    [Gateway]");
-    println!("        |");
-    println!("    [Switch/Router]");
-    
-    for (idx, device) in result.devices.iter().enumerate() {
-        let connector = if idx == result.devices.len() - 1 { "└──" } else { "├──" };
-        println!("        {} {} ({}ms)", 
-                 connector, 
-                 device.ip.to_string().bright_green(),
-                 device.latency_ms);
-        
-        if !device.open_ports.is_empty() {
-            let ports_str = device.open_ports.iter()
-                .take(3)
-                .map(|p| p.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-            println!("            └─ ports: {}", ports_str.bright_blue());
+/// Traceroute every device and the gateway to find the routers between them
+async fn trace_routes(result: &ScanResult, gateway: Option<Ipv4Addr>) -> topology::RouteTree {
+    let mut targets: Vec<Ipv4Addr> = result.devices.iter().map(|d| d.ip).collect();
+    if let Some(gw) = gateway.filter(|gw| !targets.contains(gw)) {
+        targets.push(gw);
+    }
+    status!("🧭 Tracing routes to {} hosts...", targets.len());
+    topology::RouteTree::build(&topology::trace_paths(targets).await)
+}
+
+fn generate_topology(result: &ScanResult, gateway: Option<Ipv4Addr>, routes: &topology::RouteTree, limit: Option<usize>) {
+    report!("\n{}", "Network Map:".bright_white().bold());
+    for line in routes.render(result, gateway, limit) {
+        report!("    {}", line);
+    }
+}
+
+/// Group devices by the target element they came from, preserving the
+/// order in which groups first appear
+pub(crate) fn group_by_source(devices: &[Device]) -> Vec<(Option<&str>, Vec<&Device>)> {
+    let mut groups: Vec<(Option<&str>, Vec<&Device>)> = Vec::new();
+    for device in devices {
+        let label = device.source_range.as_deref();
+        match groups.iter_mut().find(|(l, _)| *l == label) {
+            Some((_, members)) => members.push(device),
+            None => groups.push((label, vec![device])),
         }
     }
-    
+    groups
+}
+
+fn print_diff(changes: &ScanDiff) {
+    report!("\n{}", "Changes Since Previous Scan".bright_cyan().bold());
+    report!("{}", "─".repeat(60).bright_cyan());
+
+    if changes.is_empty() {
+        report!("  {} No changes detected", "✓".bright_green());
+        return;
+    }
+
+    if !changes.new_devices.is_empty() {
+        report!("\n{} ({})", "New Devices:".bright_green().bold(), changes.new_devices.len());
+        for device in &changes.new_devices {
+            let ports: Vec<_> = device.open_ports.iter().map(|p| format_port(*p, None)).collect();
+            report!("  {} {} {}", "+".bright_green(), device.ip.to_string().bright_white(), ports.join(", "));
+        }
+    }
+
+    if !changes.missing_devices.is_empty() {
+        report!("\n{} ({})", "Disappeared Devices:".bright_red().bold(), changes.missing_devices.len());
+        for device in &changes.missing_devices {
+            let name = device.hostname.as_deref().unwrap_or("");
+            report!("  {} {} {}", "-".bright_red(), device.ip.to_string().bright_white(), name.bright_cyan());
+        }
+    }
+
+    if !changes.changed_devices.is_empty() {
+        report!("\n{} ({})", "Changed Devices:".bright_yellow().bold(), changes.changed_devices.len());
+        for change in &changes.changed_devices {
+            report!("  {} {}", "~".bright_yellow(), change.ip.to_string().bright_white());
+            if !change.opened_ports.is_empty() {
+                let ports: Vec<_> = change.opened_ports.iter().map(|p| format_port(*p, None)).collect();
+                report!("      opened: {}", ports.join(", "));
+            }
+            if !change.closed_ports.is_empty() {
+                let ports: Vec<_> = change.closed_ports.iter().map(|p| format_port(*p, None)).collect();
+                report!("      closed: {}", ports.join(", "));
+            }
+            if let Some((old, new)) = &change.hostname {
+                report!("      hostname: {} → {}",
+                         old.as_deref().unwrap_or("-"), new.as_deref().unwrap_or("-"));
+            }
+            if let Some((old, new)) = &change.mac {
+                let fmt = |m: &Option<MacAddress>| m.as_ref().map(|m| m.to_string()).unwrap_or_else(|| "-".to_string());
+                report!("      MAC: {} → {}", fmt(old).bright_magenta(), fmt(new).bright_magenta());
+            }
+        }
+    }
+
+    if !changes.moved_devices.is_empty() {
+        report!("\n{} ({})", "Moved Devices:".bright_magenta().bold(), changes.moved_devices.len());
+        for moved in &changes.moved_devices {
+            report!("  {} {} {} → {}",
+                     "⇄".bright_magenta(),
+                     moved.mac.to_string().bright_magenta(),
+                     moved.from,
+                     moved.to.to_string().bright_white());
+        }
+    }
+}
+
+/// A result saved with `scan --output`, JSON or YAML by extension
+pub fn load_results(path: &str) -> Result<ScanResult> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path))?;
+    let result = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&content)?
+    } else {
+        serde_json::from_str(&content)?
+    };
+    Ok(result)
+}
+
+fn append_result_line(result: &ScanResult, path: &str) -> Result<()> {
+    use std::io::Write;
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(result)?)?;
     Ok(())
 }
 
@@ -0,0 +1,204 @@
+// Local name discovery
+// Reverse DNS rarely knows about home devices, but most of them answer a
+// unicast mDNS reverse lookup (Apple, Android, Linux with Avahi, printers,
+// TVs) or a NetBIOS node status query (Windows, Samba, NAS boxes). Both
+// queries go straight to the host with a short timeout.
+
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+pub const MDNS_PORT: u16 = 5353;
+pub const NETBIOS_PORT: u16 = 137;
+
+/// Per-query timeout; a host that has a name answers well within this
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(250);
+
+const TYPE_PTR: u16 = 12;
+const TYPE_NBSTAT: u16 = 0x21;
+const CLASS_IN: u16 = 1;
+/// "Unicast response requested" bit in an mDNS question's class
+const CLASS_QU: u16 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameSource {
+    Mdns,
+    Netbios,
+    /// UPnP friendly name from an SSDP device description
+    Upnp,
+}
+
+impl std::fmt::Display for NameSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NameSource::Mdns => write!(f, "mDNS"),
+            NameSource::Netbios => write!(f, "NetBIOS"),
+            NameSource::Upnp => write!(f, "UPnP"),
+        }
+    }
+}
+
+/// Ask `ip` for its name over mDNS and NetBIOS at once, preferring mDNS
+pub async fn lookup(ip: Ipv4Addr, timeout: Duration) -> Option<(String, NameSource)> {
+    let (mdns, netbios) = tokio::join!(
+        query(ip, MDNS_PORT, mdns_query(ip), timeout),
+        query(ip, NETBIOS_PORT, nbstat_query(ip), timeout),
+    );
+
+    mdns.and_then(|reply| parse_mdns_reply(&reply))
+        .map(|name| (name, NameSource::Mdns))
+        .or_else(|| {
+            netbios
+                .and_then(|reply| parse_nbstat_reply(&reply))
+                .map(|name| (name, NameSource::Netbios))
+        })
+}
+
+/// Send one datagram and wait for the first reply from that host that
+/// echoes our transaction id
+async fn query(ip: Ipv4Addr, port: u16, packet: Vec<u8>, timeout: Duration) -> Option<Vec<u8>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.ok()?;
+    let target = SocketAddr::from((ip, port));
+    socket.send_to(&packet, target).await.ok()?;
+
+    let mut buf = vec![0u8; 1500];
+    tokio::time::timeout(timeout, async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await.ok()?;
+            if from.ip() == target.ip() && buf[..len].starts_with(&packet[..2]) {
+                return Some(buf[..len].to_vec());
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Transaction id for queries to `ip`, so stray replies are easy to discard
+fn query_id(ip: Ipv4Addr) -> u16 {
+    let octets = ip.octets();
+    u16::from_be_bytes([octets[2], octets[3]])
+}
+
+/// A unicast mDNS PTR question for `d.c.b.a.in-addr.arpa`
+///
+/// Sent from an ephemeral port, responders treat this as a legacy unicast
+/// query and answer us directly instead of on the multicast group
+pub fn mdns_query(ip: Ipv4Addr) -> Vec<u8> {
+    let [a, b, c, d] = ip.octets();
+    let name = format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a);
+
+    let mut packet = Vec::with_capacity(64);
+    packet.extend_from_slice(&query_id(ip).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&(CLASS_IN | CLASS_QU).to_be_bytes());
+    packet
+}
+
+/// The target of the first PTR answer, without the trailing ".local"
+pub fn parse_mdns_reply(packet: &[u8]) -> Option<String> {
+    if packet.len() < 12 || packet[2] & 0x80 == 0 {
+        return None;
+    }
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+    let answers = u16::from_be_bytes([packet[6], packet[7]]);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(packet, pos)? + 4;
+    }
+    for _ in 0..answers {
+        pos = skip_name(packet, pos)?;
+        let header = packet.get(pos..pos + 10)?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+        if rtype == TYPE_PTR {
+            let name = read_name(packet, pos)?;
+            let name = name.strip_suffix(".local").unwrap_or(&name);
+            return (!name.is_empty()).then(|| name.to_string());
+        }
+        pos += rdlen;
+    }
+    None
+}
+
+/// Offset just past the (possibly compressed) name starting at `pos`
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            l if l & 0xC0 == 0xC0 => return Some(pos + 2),
+            l => pos += 1 + l as usize,
+        }
+    }
+}
+
+/// Decode a name, following compression pointers
+fn read_name(packet: &[u8], mut pos: usize) -> Option<String> {
+    let mut labels = Vec::new();
+    // Bound the number of jumps so a pointer loop can't spin forever
+    for _ in 0..64 {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => return Some(labels.join(".")),
+            l if l & 0xC0 == 0xC0 => {
+                pos = (u16::from_be_bytes([l & 0x3F, *packet.get(pos + 1)?])) as usize;
+            }
+            l => {
+                let label = packet.get(pos + 1..pos + 1 + l as usize)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l as usize;
+            }
+        }
+    }
+    None
+}
+
+/// A NetBIOS node status (NBSTAT) request for the wildcard name "*"
+pub fn nbstat_query(ip: Ipv4Addr) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(50);
+    packet.extend_from_slice(&query_id(ip).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+    // First-level encoding: each nibble of the NUL-padded name becomes 'A' + nibble
+    let mut name = [0u8; 16];
+    name[0] = b'*';
+    packet.push(32);
+    for byte in name {
+        packet.push(b'A' + (byte >> 4));
+        packet.push(b'A' + (byte & 0x0F));
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// The unique workstation (suffix 0x00) name from a node status response
+pub fn parse_nbstat_reply(packet: &[u8]) -> Option<String> {
+    if packet.len() < 12 || packet[2] & 0x80 == 0 {
+        return None;
+    }
+    // Answer name, type, class, TTL and RDLENGTH precede the name table
+    let pos = skip_name(packet, 12)? + 10;
+    let count = *packet.get(pos)? as usize;
+
+    packet
+        .get(pos + 1..)?
+        .chunks_exact(18)
+        .take(count)
+        .find(|entry| entry[15] == 0x00 && entry[16] & 0x80 == 0)
+        .map(|entry| String::from_utf8_lossy(&entry[..15]).trim_end().to_string())
+        .filter(|name| !name.is_empty())
+}
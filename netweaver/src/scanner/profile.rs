@@ -0,0 +1,111 @@
+// Named scan profiles
+// `scan --profile <name>` fills in the flags a profile sets; flags given on
+// the command line still win. Profiles live in `[profiles.<name>]` tables of
+// `~/.config/netweaver/config.toml`, keyed like the flags themselves:
+//
+//     [profiles.after-hours]
+//     top-ports = 100
+//     max-rate = 50
+//     names = true
+//
+// `quick`, `default` and `thorough` are built in; a config profile with the
+// same name replaces the built-in one.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Scan settings a profile can supply; None leaves the flag's own default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub ports: Option<String>,
+    pub top_ports: Option<usize>,
+    pub threads: Option<usize>,
+    /// Fixed probe timeout in milliseconds
+    pub timeout: Option<u64>,
+    pub max_timeout: Option<u64>,
+    pub max_rate: Option<u32>,
+    pub retries: Option<u32>,
+    #[serde(alias = "resolve")]
+    pub names: Option<bool>,
+    #[serde(alias = "service-detect")]
+    pub services: Option<bool>,
+    pub tls_info: Option<bool>,
+    pub http_info: Option<bool>,
+    pub snmp: Option<bool>,
+    pub ssdp: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+pub const BUILTIN: &[&str] = &["quick", "default", "thorough"];
+
+/// A built-in profile by name
+pub fn builtin(name: &str) -> Option<Profile> {
+    match name {
+        // The usual services, answered fast or not at all
+        "quick" => Some(Profile {
+            top_ports: Some(20),
+            threads: Some(256),
+            max_timeout: Some(500),
+            retries: Some(0),
+            ..Default::default()
+        }),
+        // What a bare `netweaver scan` does
+        "default" => Some(Profile {
+            threads: Some(100),
+            max_timeout: Some(2000),
+            retries: Some(1),
+            ..Default::default()
+        }),
+        // Wide port coverage, patient probes and every per-port detail
+        "thorough" => Some(Profile {
+            top_ports: Some(1000),
+            threads: Some(100),
+            max_timeout: Some(3000),
+            retries: Some(2),
+            names: Some(true),
+            services: Some(true),
+            tls_info: Some(true),
+            http_info: Some(true),
+            ..Default::default()
+        }),
+        _ => None,
+    }
+}
+
+/// `$XDG_CONFIG_HOME/netweaver/config.toml`, or `~/.config/netweaver/config.toml`
+pub fn default_path() -> Result<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").context("Neither XDG_CONFIG_HOME nor HOME is set")?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+    Ok(config_home.join("netweaver").join("config.toml"))
+}
+
+/// Look up `name` in the config file at `path` (which may not exist), then
+/// among the built-ins
+pub fn load(name: &str, path: &Path) -> Result<Profile> {
+    let config = match std::fs::read_to_string(path) {
+        Ok(content) => toml::from_str::<ConfigFile>(&content)
+            .with_context(|| format!("Invalid config file '{}'", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ConfigFile::default(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read config file '{}'", path.display())),
+    };
+
+    if let Some(profile) = config.profiles.get(name).cloned().or_else(|| builtin(name)) {
+        return Ok(profile);
+    }
+    let mut known: Vec<&str> = BUILTIN.to_vec();
+    known.extend(config.profiles.keys().map(String::as_str).filter(|n| !BUILTIN.contains(n)));
+    anyhow::bail!("Unknown profile '{}' (available: {})", name, known.join(", "))
+}
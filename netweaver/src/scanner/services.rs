@@ -0,0 +1,217 @@
+// Service identification
+// Open ports first get a moment to announce themselves: SSH, SMTP, MySQL
+// and VNC all speak first. Ports that stay silent are sent the probes below,
+// those listing the port first, each on a fresh connection, until one
+// response matches or the port's time budget runs out. A pattern's
+// `version` group, when it has one, becomes the reported version.
+
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Instant;
+
+use crate::utils::rate_limit::RateLimiter;
+use crate::utils::source;
+
+/// Total time spent identifying one port, banner wait included
+pub const DEFAULT_BUDGET: Duration = Duration::from_secs(3);
+
+/// How long to wait for a banner or a probe's response
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Name of the pseudo-probe that sends nothing and reads the banner
+pub const BANNER: &str = "banner";
+
+/// A service recognised on a port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceInfo {
+    pub port: u16,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The probe whose response matched (`banner` when the service spoke first)
+    pub probe: String,
+}
+
+struct Match {
+    service: &'static str,
+    pattern: &'static str,
+}
+
+struct Probe {
+    name: &'static str,
+    ports: &'static [u16],
+    payload: &'static [u8],
+    matches: &'static [Match],
+}
+
+const BANNER_MATCHES: &[Match] = &[
+    Match { service: "SSH", pattern: r"^SSH-[\d.]+-(?P<version>[^\r\n]+)" },
+    Match { service: "SMTP", pattern: r"^220[ -](?P<version>[^\r\n]*SMTP[^\r\n]*)" },
+    Match { service: "MySQL", pattern: r"(?s-u)^.{4}\x0a(?P<version>[0-9][^\x00]*)\x00" },
+    Match { service: "MySQL", pattern: r"(?s-u)^.{4}\xff.{2}.*MySQL" },
+    Match { service: "VNC", pattern: r"^RFB (?P<version>\d{3}\.\d{3})\n" },
+];
+
+/// TLS 1.2 ClientHello offering common suites, with no extensions
+const TLS_CLIENT_HELLO: &[u8] = &[
+    0x16, 0x03, 0x01, 0x00, 0x3b, // record: handshake, 59 bytes
+    0x01, 0x00, 0x00, 0x37, // ClientHello, 55 bytes
+    0x03, 0x03, // TLS 1.2
+    0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57,
+    0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57, 0x4e, 0x57,
+    0x00, // no session id
+    0x00, 0x10, 0xc0, 0x2f, 0xc0, 0x30, 0xc0, 0x2b, 0xc0, 0x2c, 0x00, 0x9c, 0x00, 0x2f, 0x00, 0x35, 0x00, 0x0a,
+    0x01, 0x00, // null compression
+];
+
+const PROBES: &[Probe] = &[
+    Probe {
+        name: "http-get",
+        ports: &[80, 81, 591, 3000, 5000, 8000, 8008, 8080, 8081, 8888],
+        payload: b"GET / HTTP/1.0\r\n\r\n",
+        matches: &[
+            Match { service: "HTTP", pattern: r"(?s-u)^HTTP/1\.[01] \d{3}.*?\r\n(?i:server):[ \t]*(?P<version>[^\r\n]+)" },
+            Match { service: "HTTP", pattern: r"^HTTP/1\.[01] \d{3}" },
+        ],
+    },
+    Probe {
+        name: "tls-hello",
+        ports: super::tls::TLS_PORTS,
+        payload: TLS_CLIENT_HELLO,
+        matches: &[
+            // A ServerHello, or an alert refusing our offer; either way it's TLS
+            Match { service: "TLS", pattern: r"(?-u)^[\x15\x16]\x03[\x00-\x04]" },
+        ],
+    },
+    Probe {
+        name: "redis-ping",
+        ports: &[6379, 6380],
+        payload: b"*1\r\n$4\r\nPING\r\n",
+        matches: &[
+            Match { service: "Redis", pattern: r"^\+PONG\r\n" },
+            Match { service: "Redis", pattern: r"^-(NOAUTH|DENIED)" },
+        ],
+    },
+    Probe {
+        name: "postgres-ssl",
+        ports: &[5432, 5433],
+        // SSLRequest: length 8, code 80877103
+        payload: &[0x00, 0x00, 0x00, 0x08, 0x04, 0xd2, 0x16, 0x2f],
+        matches: &[Match { service: "PostgreSQL", pattern: r"(?s-u)^[SN]\z" }],
+    },
+    Probe {
+        name: "rdp-x224",
+        ports: &[3389],
+        // X.224 Connection Request with an RDP negotiation request for TLS/CredSSP
+        payload: &[
+            0x03, 0x00, 0x00, 0x13, 0x0e, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0x00, 0x03,
+            0x00, 0x00, 0x00,
+        ],
+        matches: &[Match { service: "RDP", pattern: r"(?s-u)^\x03\x00\x00.\x0e\xd0" }],
+    },
+];
+
+struct Compiled {
+    name: &'static str,
+    ports: &'static [u16],
+    payload: &'static [u8],
+    matches: Vec<(&'static str, Regex)>,
+}
+
+fn compile(matches: &'static [Match]) -> Vec<(&'static str, Regex)> {
+    matches
+        .iter()
+        .map(|m| (m.service, Regex::new(m.pattern).expect("service patterns are valid")))
+        .collect()
+}
+
+fn banner_matches() -> &'static [(&'static str, Regex)] {
+    static COMPILED: OnceLock<Vec<(&'static str, Regex)>> = OnceLock::new();
+    COMPILED.get_or_init(|| compile(BANNER_MATCHES))
+}
+
+fn probes() -> &'static [Compiled] {
+    static COMPILED: OnceLock<Vec<Compiled>> = OnceLock::new();
+    COMPILED.get_or_init(|| {
+        PROBES
+            .iter()
+            .map(|p| Compiled { name: p.name, ports: p.ports, payload: p.payload, matches: compile(p.matches) })
+            .collect()
+    })
+}
+
+/// Match `response` against the patterns of the probe named `probe`
+pub fn recognize(probe: &str, port: u16, response: &[u8]) -> Option<ServiceInfo> {
+    let matches = match probe {
+        BANNER => banner_matches(),
+        name => &probes().iter().find(|p| p.name == name)?.matches,
+    };
+    matches.iter().find_map(|(service, pattern)| {
+        let captures = pattern.captures(response)?;
+        let version = captures
+            .name("version")
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).trim().to_string())
+            .filter(|v| !v.is_empty());
+        Some(ServiceInfo { port, name: service.to_string(), version, probe: probe.to_string() })
+    })
+}
+
+/// Identify the service on an open port within `budget`
+pub async fn identify(
+    ip: Ipv4Addr,
+    port: u16,
+    budget: Duration,
+    limiter: Option<&RateLimiter>,
+) -> Option<ServiceInfo> {
+    let deadline = Instant::now() + budget;
+    let addr = SocketAddr::from((ip, port));
+
+    // Something that talks first but matches nothing is left to the port table
+    match exchange(addr, &[], deadline, limiter).await {
+        Some(banner) if !banner.is_empty() => return recognize(BANNER, port, &banner),
+        _ => {}
+    }
+
+    let (listed, others): (Vec<_>, Vec<_>) = probes().iter().partition(|p| p.ports.contains(&port));
+    for probe in listed.into_iter().chain(others) {
+        if Instant::now() >= deadline {
+            break;
+        }
+        if let Some(response) = exchange(addr, probe.payload, deadline, limiter).await {
+            if let Some(service) = recognize(probe.name, port, &response) {
+                return Some(service);
+            }
+        }
+    }
+    None
+}
+
+/// Connect, send `payload` (if any) and return whatever comes back first
+async fn exchange(
+    addr: SocketAddr,
+    payload: &[u8],
+    deadline: Instant,
+    limiter: Option<&RateLimiter>,
+) -> Option<Vec<u8>> {
+    if let Some(limiter) = limiter {
+        limiter.acquire().await;
+    }
+    let wait = READ_TIMEOUT.min(deadline.saturating_duration_since(Instant::now()));
+    tokio::time::timeout(wait, async {
+        let mut stream = source::connect(addr).await.ok()?;
+        if !payload.is_empty() {
+            stream.write_all(payload).await.ok()?;
+        }
+        let mut buf = vec![0u8; 4096];
+        let len = stream.read(&mut buf).await.ok()?;
+        buf.truncate(len);
+        Some(buf)
+    })
+    .await
+    .ok()
+    .flatten()
+}
@@ -0,0 +1,230 @@
+// SNMP identification
+// A single SNMPv2c GET for sysDescr, sysObjectID and sysName. Only the
+// handful of BER types that request and response need are implemented:
+// INTEGER, OCTET STRING, NULL, OBJECT IDENTIFIER, SEQUENCE and the PDUs.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+pub const SNMP_PORT: u16 = 161;
+pub const DEFAULT_COMMUNITY: &str = "public";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub const SYS_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 1, 0];
+pub const SYS_OBJECT_ID: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 2, 0];
+pub const SYS_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 5, 0];
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_GET_REQUEST: u8 = 0xA0;
+const TAG_GET_RESPONSE: u8 = 0xA2;
+
+/// SNMP version field value for v2c
+const VERSION_2C: i64 = 1;
+
+static NEXT_REQUEST_ID: AtomicU16 = AtomicU16::new(1);
+
+/// The system group values a device was willing to share
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemInfo {
+    pub descr: Option<String>,
+    pub name: Option<String>,
+    pub object_id: Option<Vec<u32>>,
+}
+
+impl SystemInfo {
+    pub fn is_empty(&self) -> bool {
+        self.descr.is_none() && self.name.is_none() && self.object_id.is_none()
+    }
+}
+
+/// GET the system group from `ip`, or None if nothing answered in time
+pub async fn query(ip: Ipv4Addr, community: &str, timeout: Duration) -> Option<SystemInfo> {
+    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed) as i64;
+    let request = get_request(community, request_id, &[SYS_DESCR, SYS_OBJECT_ID, SYS_NAME]);
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await.ok()?;
+    let target = SocketAddr::from((ip, SNMP_PORT));
+    socket.send_to(&request, target).await.ok()?;
+
+    let mut buf = vec![0u8; 1500];
+    tokio::time::timeout(timeout, async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await.ok()?;
+            if from != target {
+                continue;
+            }
+            if let Some((id, info)) = parse_response(&buf[..len]) {
+                if id == request_id {
+                    return Some(info).filter(|info| !info.is_empty());
+                }
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Encode a v2c GetRequest for `oids`
+pub fn get_request(community: &str, request_id: i64, oids: &[&[u32]]) -> Vec<u8> {
+    let varbinds: Vec<u8> = oids
+        .iter()
+        .flat_map(|oid| tlv(TAG_SEQUENCE, &[encode_oid(oid), tlv(TAG_NULL, &[])].concat()))
+        .collect();
+
+    let pdu = tlv(
+        TAG_GET_REQUEST,
+        &[
+            encode_integer(request_id),
+            encode_integer(0), // error-status
+            encode_integer(0), // error-index
+            tlv(TAG_SEQUENCE, &varbinds),
+        ]
+        .concat(),
+    );
+
+    tlv(
+        TAG_SEQUENCE,
+        &[encode_integer(VERSION_2C), tlv(TAG_OCTET_STRING, community.as_bytes()), pdu].concat(),
+    )
+}
+
+/// Decode a GetResponse into its request id and the system values it carried
+///
+/// Varbinds holding noSuchObject/noSuchInstance or an unexpected type are
+/// skipped rather than failing the whole response
+pub fn parse_response(packet: &[u8]) -> Option<(i64, SystemInfo)> {
+    let (tag, message, _) = read_tlv(packet)?;
+    if tag != TAG_SEQUENCE {
+        return None;
+    }
+    let (_version, rest) = expect(message, TAG_INTEGER)?;
+    let (_community, rest) = expect(rest, TAG_OCTET_STRING)?;
+    let (pdu, _) = expect(rest, TAG_GET_RESPONSE)?;
+
+    let (id, rest) = expect(pdu, TAG_INTEGER)?;
+    let (status, rest) = expect(rest, TAG_INTEGER)?;
+    let (_index, rest) = expect(rest, TAG_INTEGER)?;
+    let (mut varbinds, _) = expect(rest, TAG_SEQUENCE)?;
+    if decode_integer(status)? != 0 {
+        return None;
+    }
+
+    let mut info = SystemInfo::default();
+    while !varbinds.is_empty() {
+        let (varbind, rest) = expect(varbinds, TAG_SEQUENCE)?;
+        varbinds = rest;
+        let (oid, value) = expect(varbind, TAG_OID)?;
+        let (value_tag, value, _) = read_tlv(value)?;
+        let oid = decode_oid(oid)?;
+
+        match (oid.as_slice(), value_tag) {
+            (SYS_DESCR, TAG_OCTET_STRING) => info.descr = Some(decode_string(value)),
+            (SYS_NAME, TAG_OCTET_STRING) => info.name = Some(decode_string(value)),
+            (SYS_OBJECT_ID, TAG_OID) => info.object_id = decode_oid(value),
+            _ => {}
+        }
+    }
+
+    Some((decode_integer(id)?, info))
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(&bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Drop leading bytes that only repeat the sign bit
+    let mut start = 0;
+    while start < 7 {
+        let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        start += 1;
+    }
+    tlv(TAG_INTEGER, &bytes[start..])
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut content = vec![(oid[0] * 40 + oid[1]) as u8];
+    for &arc in &oid[2..] {
+        let mut chunk = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            chunk.push(0x80 | (rest & 0x7F) as u8);
+            rest >>= 7;
+        }
+        content.extend(chunk.iter().rev());
+    }
+    tlv(TAG_OID, &content)
+}
+
+/// Split one TLV off the front of `data`: (tag, content, remainder)
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let count = first & 0x7F;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let len = data.get(2..2 + count)?.iter().fold(0usize, |acc, b| acc << 8 | *b as usize);
+        (len, 2 + count)
+    };
+    let content = data.get(header..header + len)?;
+    Some((tag, content, &data[header + len..]))
+}
+
+/// `read_tlv` for a known tag: (content, remainder)
+fn expect(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    read_tlv(data)
+        .filter(|(t, _, _)| *t == tag)
+        .map(|(_, content, rest)| (content, rest))
+}
+
+fn decode_integer(content: &[u8]) -> Option<i64> {
+    if content.is_empty() || content.len() > 8 {
+        return None;
+    }
+    let sign = if content[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Some(content.iter().fold(sign, |acc, b| acc << 8 | *b as i64))
+}
+
+fn decode_oid(content: &[u8]) -> Option<Vec<u32>> {
+    let (&first, rest) = content.split_first()?;
+    let mut oid = vec![(first / 40) as u32, (first % 40) as u32];
+    let mut arc = 0u32;
+    for &b in rest {
+        arc = arc.checked_mul(128)? | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            oid.push(arc);
+            arc = 0;
+        }
+    }
+    Some(oid)
+}
+
+fn decode_string(content: &[u8]) -> String {
+    String::from_utf8_lossy(content).trim().to_string()
+}
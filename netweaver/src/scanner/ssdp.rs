@@ -0,0 +1,135 @@
+// SSDP/UPnP discovery
+// TVs, consoles, speakers and smart-home hubs often expose no scannable TCP
+// ports but answer an M-SEARCH on the SSDP multicast group. Responses carry
+// a LOCATION URL for the device description XML, where the friendly name
+// lives.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+pub const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+pub const SSDP_PORT: u16 = 1900;
+
+/// How long to collect responses; devices spread replies over MX seconds
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(2);
+const DESCRIPTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub const M_SEARCH: &str = "M-SEARCH * HTTP/1.1\r\n\
+                            HOST: 239.255.255.250:1900\r\n\
+                            MAN: \"ssdp:discover\"\r\n\
+                            MX: 2\r\n\
+                            ST: ssdp:all\r\n\
+                            \r\n";
+
+/// What a device told us about itself over SSDP/UPnP
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SsdpInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub friendly_name: Option<String>,
+}
+
+/// Multicast an M-SEARCH from `local_ip` and collect one answer per host,
+/// then fetch each host's friendly name from its description XML
+pub async fn discover(local_ip: Ipv4Addr, wait: Duration) -> Result<HashMap<Ipv4Addr, SsdpInfo>> {
+    let socket = UdpSocket::bind((local_ip, 0))
+        .await
+        .with_context(|| format!("Failed to bind SSDP socket on {}", local_ip))?;
+    socket.set_multicast_ttl_v4(2)?;
+    socket
+        .send_to(M_SEARCH.as_bytes(), SocketAddr::from((SSDP_ADDR, SSDP_PORT)))
+        .await
+        .context("Failed to send SSDP M-SEARCH")?;
+
+    let mut found: HashMap<Ipv4Addr, SsdpInfo> = HashMap::new();
+    let deadline = Instant::now() + wait;
+    let mut buf = vec![0u8; 2048];
+    while let Ok(Ok((len, SocketAddr::V4(from)))) =
+        tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+    {
+        let Some(info) = parse_response(&String::from_utf8_lossy(&buf[..len])) else { continue };
+        // Devices answer once per service type; the first answer with a
+        // description URL is as good as any
+        let entry = found.entry(*from.ip()).or_default();
+        if entry.location.is_none() {
+            *entry = info;
+        }
+    }
+
+    let names = futures::future::join_all(found.iter().map(|(ip, info)| {
+        let location = info.location.clone();
+        let ip = *ip;
+        async move {
+            match location {
+                Some(url) => (ip, friendly_name(ip, &url).await),
+                None => (ip, None),
+            }
+        }
+    }))
+    .await;
+    for (ip, name) in names {
+        if let Some(info) = found.get_mut(&ip) {
+            info.friendly_name = name;
+        }
+    }
+
+    Ok(found)
+}
+
+/// LOCATION and SERVER from an M-SEARCH response
+pub fn parse_response(response: &str) -> Option<SsdpInfo> {
+    let mut lines = response.lines();
+    if !lines.next()?.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+
+    let mut info = SsdpInfo::default();
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        if name.eq_ignore_ascii_case("location") {
+            info.location = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("server") {
+            info.server = Some(value.to_string());
+        }
+    }
+    Some(info)
+}
+
+/// Fetch the description XML, but only from the host that answered, so a
+/// stray LOCATION can't point the scanner somewhere else
+async fn friendly_name(ip: Ipv4Addr, location: &str) -> Option<String> {
+    let url = reqwest::Url::parse(location).ok()?;
+    if url.scheme() != "http" || url.host_str() != Some(&ip.to_string()) {
+        return None;
+    }
+
+    let client = reqwest::Client::builder().timeout(DESCRIPTION_TIMEOUT).build().ok()?;
+    let body = client.get(url).send().await.ok()?.text().await.ok()?;
+    parse_friendly_name(&body)
+}
+
+/// The first `<friendlyName>` in a UPnP device description
+pub fn parse_friendly_name(xml: &str) -> Option<String> {
+    let start = xml.find("<friendlyName>")? + "<friendlyName>".len();
+    let end = start + xml[start..].find("</friendlyName>")?;
+    let name = xml[start..end]
+        .trim()
+        .replace("&apos;", "'")
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    (!name.is_empty()).then_some(name)
+}
@@ -0,0 +1,348 @@
+// Target and exclusion handling for the scanner
+// Turns user-supplied IP/CIDR/hostname specs into merged address ranges
+// that the scan pipeline walks lazily
+
+use anyhow::{Context, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::net::Ipv4Addr;
+
+use crate::utils;
+
+/// Sorted set of disjoint, inclusive IPv4 ranges
+///
+/// Overlapping or duplicate inserts are merged, so every address is counted
+/// and yielded at most once no matter how the inputs overlap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangeSet {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl RangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn single(ip: Ipv4Addr) -> Self {
+        Self { ranges: vec![(u32::from(ip), u32::from(ip))] }
+    }
+
+    /// Usable host addresses of a CIDR block (network/broadcast excluded
+    /// below /31)
+    pub fn from_cidr(ip: Ipv4Addr, prefix: u8) -> Self {
+        let mut set = Self::new();
+        if let Some((first, last)) = utils::cidr_iter(ip, prefix).bounds() {
+            set.ranges.push((first, last));
+        }
+        set
+    }
+
+    pub fn insert(&mut self, first: u32, last: u32) {
+        self.ranges.push((first.min(last), first.max(last)));
+        self.normalize();
+    }
+
+    pub fn union(&mut self, other: &RangeSet) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.normalize();
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let ip = u32::from(ip);
+        let idx = self.ranges.partition_point(|&(start, _)| start <= ip);
+        idx > 0 && ip <= self.ranges[idx - 1].1
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|&(start, end)| (end - start) as usize + 1).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Number of addresses present in both sets, computed from the ranges
+    /// without walking any addresses
+    pub fn overlap(&self, other: &RangeSet) -> usize {
+        let (mut i, mut j, mut count) = (0, 0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a_start, a_end) = self.ranges[i];
+            let (b_start, b_end) = other.ranges[j];
+            let lo = a_start.max(b_start);
+            let hi = a_end.min(b_end);
+            if lo <= hi {
+                count += (hi - lo) as usize + 1;
+            }
+            if a_end < b_end { i += 1 } else { j += 1 }
+        }
+        count
+    }
+
+    /// Addresses in ascending order, generated lazily
+    pub fn iter(&self) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.ranges
+            .iter()
+            .flat_map(|&(start, end)| (start as u64..=end as u64).map(|ip| Ipv4Addr::from(ip as u32)))
+    }
+
+    /// The address at `index` in ascending order
+    pub fn nth(&self, mut index: usize) -> Option<Ipv4Addr> {
+        for &(start, end) in &self.ranges {
+            let len = (end - start) as usize + 1;
+            if index < len {
+                return Some(Ipv4Addr::from(start + index as u32));
+            }
+            index -= len;
+        }
+        None
+    }
+
+    /// Every address exactly once, in an order fixed by `seed`, generated lazily
+    pub fn shuffled(&self, seed: u64) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        Permutation::new(self.len() as u64, seed).filter_map(move |i| self.nth(i as usize))
+    }
+
+    fn normalize(&mut self) {
+        self.ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(self.ranges.len());
+        for (start, end) in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+/// A pseudo-random permutation of `0..len`, produced lazily
+///
+/// Steps a full-period LCG modulo the next power of two, scrambles each
+/// step with an invertible xorshift-multiply (an LCG's low bits alone
+/// cycle visibly) and skips values past `len`, so even a /8 never needs an
+/// index list in memory.
+#[derive(Debug, Clone)]
+pub struct Permutation {
+    len: u64,
+    mask: u64,
+    multiplier: u64,
+    increment: u64,
+    mix: u64,
+    shift: u32,
+    state: u64,
+    remaining: u64,
+}
+
+impl Permutation {
+    pub fn new(len: u64, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mask = len.next_power_of_two() - 1;
+        // Hull-Dobell: an odd increment and a multiplier of 1 mod 4 visit
+        // every value mod 2^k exactly once per period
+        Self {
+            len,
+            mask,
+            multiplier: (rng.gen::<u64>() << 2) | 1,
+            increment: rng.gen::<u64>() | 1,
+            mix: rng.gen::<u64>() | 1,
+            shift: (mask.count_ones() / 2).max(1),
+            state: rng.gen::<u64>() & mask,
+            remaining: len,
+        }
+    }
+}
+
+impl Permutation {
+    /// A bijection on `0..=mask`: xorshifts and odd multipliers both invert mod 2^k
+    fn scramble(&self, x: u64) -> u64 {
+        let x = x ^ (x >> self.shift);
+        let x = x.wrapping_mul(self.mix) & self.mask;
+        x ^ (x >> self.shift)
+    }
+}
+
+impl Iterator for Permutation {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 {
+            return None;
+        }
+        loop {
+            let value = self.scramble(self.state);
+            self.state = self.state.wrapping_mul(self.multiplier).wrapping_add(self.increment) & self.mask;
+            if value < self.len {
+                self.remaining -= 1;
+                return Some(value);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+/// Parse a single IP or CIDR token into the whole block it names
+/// (network and broadcast included, which is what excludes want)
+fn parse_block(token: &str) -> Result<(u32, u32)> {
+    if token.contains('/') {
+        let (ip, prefix) = utils::parse_cidr(token)?;
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        let network = u32::from(ip) & mask;
+        Ok((network, network | !mask))
+    } else {
+        let ip: Ipv4Addr = token.parse().context("Invalid IP address")?;
+        Ok((u32::from(ip), u32::from(ip)))
+    }
+}
+
+/// Resolve one scan target (IP, CIDR, hostname or hostname/prefix) into
+/// the hosts it covers; a name with several A records covers all of them
+pub async fn resolve_target(token: &str) -> Result<RangeSet> {
+    let (host, prefix) = match token.split_once('/') {
+        Some((host, prefix)) => (host, Some(prefix)),
+        None => (token, None),
+    };
+
+    if host.parse::<Ipv4Addr>().is_ok() {
+        return match prefix {
+            Some(_) => {
+                let (ip, prefix) = utils::parse_cidr(token)?;
+                Ok(RangeSet::from_cidr(ip, prefix))
+            }
+            None => Ok(RangeSet::single(host.parse()?)),
+        };
+    }
+
+    // `myhost/24` scans the subnet around whatever the name resolves to
+    let prefix = match prefix {
+        Some(p) => match p.parse::<u8>() {
+            Ok(p) if p <= 32 => Some(p),
+            _ => anyhow::bail!("Invalid prefix length '/{}'", p),
+        },
+        None => None,
+    };
+
+    let addrs = utils::network::resolve_hostname_all(host).await?;
+    if addrs.len() > 1 {
+        tracing::info!("{} resolved to {} addresses, scanning all of them", host, addrs.len());
+    }
+
+    let mut set = RangeSet::new();
+    for ip in addrs {
+        match prefix {
+            Some(prefix) => set.union(&RangeSet::from_cidr(ip, prefix)),
+            None => set.insert(u32::from(ip), u32::from(ip)),
+        }
+    }
+    Ok(set)
+}
+
+/// One element of the user's target spec and the hosts it expanded to
+#[derive(Debug, Clone)]
+pub struct TargetGroup {
+    pub label: String,
+    pub hosts: RangeSet,
+}
+
+/// Expand a comma-separated target list such as
+/// `192.168.1.0/24,10.0.5.0/28,172.16.0.10`, one group per element
+pub async fn parse_target_list(spec: &str) -> Result<Vec<TargetGroup>> {
+    let mut groups = Vec::new();
+    for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        let hosts = resolve_target(token).await
+            .with_context(|| format!("Invalid target '{}'", token))?;
+        groups.push(TargetGroup { label: token.to_string(), hosts });
+    }
+
+    if groups.is_empty() {
+        anyhow::bail!("No targets given in '{}'", spec);
+    }
+    Ok(groups)
+}
+
+/// Read a scope file with one IP, CIDR or hostname per line
+///
+/// Blank lines and `#` comments are ignored and duplicates are merged.
+/// Every line that fails to parse or resolve is reported with its line
+/// number; nothing is scanned if any line is bad.
+pub async fn load_target_file(path: &str) -> Result<RangeSet> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read target file '{}'", path))?;
+
+    let mut targets = RangeSet::new();
+    let mut errors = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        match resolve_target(line).await {
+            Ok(set) => targets.union(&set),
+            Err(e) => errors.push(format!("  {}:{}: '{}' - {:#}", path, idx + 1, line, e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!("Invalid entries in target file:\n{}", errors.join("\n"));
+    }
+
+    Ok(targets)
+}
+
+/// Set of addresses to skip during a scan
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeList {
+    ranges: RangeSet,
+}
+
+impl ExcludeList {
+    /// Parse a comma-separated list of IPs and CIDRs
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut list = Self::default();
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (first, last) = parse_block(token)
+                .with_context(|| format!("Invalid exclude entry '{}'", token))?;
+            list.ranges.insert(first, last);
+        }
+        Ok(list)
+    }
+
+    /// Read exclusions from a file, one IP or CIDR per line
+    /// Blank lines and `#` comments are ignored
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read exclude file '{}'", path))?;
+
+        let mut list = Self::default();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (first, last) = parse_block(line)
+                .with_context(|| format!("{}:{}: invalid exclude entry '{}'", path, idx + 1, line))?;
+            list.ranges.insert(first, last);
+        }
+        Ok(list)
+    }
+
+    pub fn merge(&mut self, other: ExcludeList) {
+        self.ranges.union(&other.ranges);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.ranges.contains(ip)
+    }
+
+    /// Count how many of `hosts` fall inside the exclusions
+    pub fn count_in(&self, hosts: &RangeSet) -> usize {
+        self.ranges.overlap(hosts)
+    }
+}
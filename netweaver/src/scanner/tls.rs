@@ -0,0 +1,137 @@
+// TLS certificate grabbing
+// Completes a handshake with TLS ports found open and records what the
+// presented certificate says. Nothing is verified: self-signed, expired and
+// mismatched certificates are exactly the interesting ones. The CN and SANs
+// often name the box better than reverse DNS does.
+
+use chrono::{DateTime, Utc};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, ServerName};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio_rustls::TlsConnector;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::utils::source;
+
+/// Ports that speak TLS from the first byte (no STARTTLS)
+pub const TLS_PORTS: &[u16] = &[443, 465, 636, 853, 993, 995, 5986, 8443, 9443];
+
+/// Connect plus handshake; a few round trips even on a slow link
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Certificates expiring within this many days are flagged
+pub const EXPIRY_WARNING_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    pub port: u16,
+    pub subject: String,
+    pub issuer: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub san: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// Issuer and subject are the same name
+    pub self_signed: bool,
+}
+
+impl TlsInfo {
+    /// The subject's CN, falling back to the whole subject
+    pub fn common_name(&self) -> &str {
+        self.subject
+            .split(", ")
+            .find_map(|part| part.strip_prefix("CN="))
+            .unwrap_or(&self.subject)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.not_after < now
+    }
+
+    pub fn expires_soon(&self, now: DateTime<Utc>) -> bool {
+        !self.is_expired(now) && (self.not_after - now).num_days() < EXPIRY_WARNING_DAYS
+    }
+}
+
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Client that accepts any certificate; shared with the HTTP title fetch
+/// and `http` timing
+pub(crate) fn connector() -> TlsConnector {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    let config = CONFIG.get_or_init(|| {
+        Arc::new(
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+                .with_no_client_auth(),
+        )
+    });
+    TlsConnector::from(Arc::clone(config))
+}
+
+/// Handshake with `ip:port` and describe the leaf certificate
+///
+/// `hostname` is sent as SNI when given, so name-based virtual hosts
+/// present the certificate for that name rather than their default one.
+pub async fn grab(ip: Ipv4Addr, port: u16, hostname: Option<&str>, timeout: Duration) -> Option<TlsInfo> {
+    let server_name = hostname
+        .and_then(|name| ServerName::try_from(name).ok())
+        .unwrap_or(ServerName::IpAddress(IpAddr::V4(ip)));
+
+    let handshake = async {
+        let stream = source::connect(SocketAddr::from((ip, port))).await.ok()?;
+        connector().connect(server_name, stream).await.ok()
+    };
+    let stream = tokio::time::timeout(timeout, handshake).await.ok().flatten()?;
+    let (_, connection) = stream.get_ref();
+    let leaf = connection.peer_certificates()?.first()?;
+    parse_certificate(port, &leaf.0)
+}
+
+/// Describe a DER-encoded certificate
+pub fn parse_certificate(port: u16, der: &[u8]) -> Option<TlsInfo> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+
+    let san = match cert.subject_alternative_name() {
+        Ok(Some(ext)) => ext
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(&[a, b, c, d]) => Some(Ipv4Addr::new(a, b, c, d).to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let validity = cert.validity();
+    Some(TlsInfo {
+        port,
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        san,
+        not_before: DateTime::from_timestamp(validity.not_before.timestamp(), 0)?,
+        not_after: DateTime::from_timestamp(validity.not_after.timestamp(), 0)?,
+        self_signed: cert.subject() == cert.issuer(),
+    })
+}
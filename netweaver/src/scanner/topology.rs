@@ -0,0 +1,396 @@
+// Topology for scan results
+// A short traceroute to every device finds the routers in front of it, and
+// devices are hung under the last router their paths share. The tree renders
+// as ASCII for the console or as a Graphviz DOT graph for `dot -Tpng`.
+
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+
+use super::{group_by_source, Device, ScanResult};
+use crate::diagnostics;
+
+/// Devices more hops away than this are placed behind the routers seen so far
+pub const MAX_TRACE_HOPS: u8 = 8;
+/// Traces in flight at once
+const TRACE_CONCURRENCY: usize = 32;
+
+/// Routers between us and `target`, nearest first; a hop that never
+/// answered is None. A device on our own segment has an empty path.
+pub async fn trace_path(target: Ipv4Addr, max_hops: u8) -> Vec<Option<Ipv4Addr>> {
+    let mut path = Vec::new();
+    for ttl in 1..=max_hops {
+        let hop = match diagnostics::probe_hop(target, ttl, 1, diagnostics::ProbeProtocol::Icmp, 0, 1).await {
+            Ok(hop) => hop.ip,
+            Err(_) => None,
+        };
+        if hop == Some(target) {
+            break;
+        }
+        path.push(hop);
+    }
+    // Silence past the last router that answered says nothing about the path
+    while path.last() == Some(&None) {
+        path.pop();
+    }
+    path
+}
+
+/// Trace every target concurrently
+pub async fn trace_paths(targets: Vec<Ipv4Addr>) -> Vec<(Ipv4Addr, Vec<Option<Ipv4Addr>>)> {
+    stream::iter(targets)
+        .map(|ip| async move { (ip, trace_path(ip, MAX_TRACE_HOPS).await) })
+        .buffer_unordered(TRACE_CONCURRENCY)
+        .collect()
+        .await
+}
+
+/// Devices arranged under the routers their traceroutes went through
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteTree {
+    /// Devices whose last router is this node (at the root: on our segment)
+    pub devices: Vec<Ipv4Addr>,
+    /// Next-hop routers, None for a hop that didn't answer
+    pub routers: Vec<(Option<Ipv4Addr>, RouteTree)>,
+}
+
+impl RouteTree {
+    pub fn build(paths: &[(Ipv4Addr, Vec<Option<Ipv4Addr>>)]) -> Self {
+        let mut tree = Self::default();
+        for (device, path) in paths {
+            tree.insert(path, *device);
+        }
+        tree.sort();
+        tree
+    }
+
+    fn insert(&mut self, path: &[Option<Ipv4Addr>], device: Ipv4Addr) {
+        let Some((hop, rest)) = path.split_first() else {
+            self.devices.push(device);
+            return;
+        };
+        let index = match self.routers.iter().position(|(ip, _)| ip == hop) {
+            Some(index) => index,
+            None => {
+                self.routers.push((*hop, Self::default()));
+                self.routers.len() - 1
+            }
+        };
+        self.routers[index].1.insert(rest, device);
+    }
+
+    fn sort(&mut self) {
+        self.devices.sort_unstable();
+        self.devices.dedup();
+        self.routers.sort_by_key(|(ip, _)| *ip);
+        for (_, child) in &mut self.routers {
+            child.sort();
+        }
+    }
+
+    /// Number of router hops in the deepest branch
+    pub fn depth(&self) -> usize {
+        self.routers.iter().map(|(_, child)| 1 + child.depth()).max().unwrap_or(0)
+    }
+
+    /// ASCII tree, one line per router or device
+    ///
+    /// Devices sharing a router are listed by address, under a heading per
+    /// /24 when they span several; `limit` caps how many each list shows.
+    pub fn render(&self, result: &ScanResult, gateway: Option<Ipv4Addr>, limit: Option<usize>) -> Vec<String> {
+        let mut lines = vec!["[This host]".to_string()];
+        self.render_children(result, gateway, limit, "", &mut lines);
+        lines
+    }
+
+    fn render_children(
+        &self,
+        result: &ScanResult,
+        gateway: Option<Ipv4Addr>,
+        limit: Option<usize>,
+        prefix: &str,
+        lines: &mut Vec<String>,
+    ) {
+        // A router that was also scanned is drawn once, as the router
+        let devices: Vec<Ipv4Addr> = self.devices.iter()
+            .copied()
+            .filter(|ip| !self.routers.iter().any(|(router, _)| *router == Some(*ip)))
+            .collect();
+
+        let groups = by_subnet(&devices);
+        let leaves: Vec<(String, Vec<String>)> = match groups.len() {
+            0 | 1 => device_lines(&devices, result, gateway, limit).into_iter().map(|line| (line, Vec::new())).collect(),
+            _ => groups
+                .iter()
+                .map(|(subnet, ips)| {
+                    let heading = format!("{}/24 ({} device{})", subnet, ips.len(), if ips.len() == 1 { "" } else { "s" });
+                    (heading, device_lines(ips, result, gateway, limit))
+                })
+                .collect(),
+        };
+        let count = self.routers.len() + leaves.len();
+
+        for (idx, (router, child)) in self.routers.iter().enumerate() {
+            let last = idx + 1 == count;
+            let mut label = match router {
+                Some(ip) => format!("[{}]", ip),
+                None => "[* no reply]".to_string(),
+            };
+            if router.is_some() && *router == gateway {
+                label.push_str(" (gateway)");
+            }
+            if let Some(latency) = router.and_then(|ip| result.devices.iter().find(|d| d.ip == ip)?.latency_ms) {
+                label.push_str(&format!(" ({:.1}ms)", latency));
+            }
+            lines.push(format!("{}{} {}", prefix, branch(last), label));
+            child.render_children(result, gateway, limit, &format!("{}{}", prefix, indent(last)), lines);
+        }
+
+        for (idx, (label, children)) in leaves.iter().enumerate() {
+            let last = self.routers.len() + idx + 1 == count;
+            lines.push(format!("{}{} {}", prefix, branch(last), label));
+            let prefix = format!("{}{}", prefix, indent(last));
+            for (idx, child) in children.iter().enumerate() {
+                lines.push(format!("{}{} {}", prefix, branch(idx + 1 == children.len()), child));
+            }
+        }
+    }
+}
+
+fn branch(last: bool) -> &'static str {
+    if last { "└──" } else { "├──" }
+}
+
+fn indent(last: bool) -> &'static str {
+    if last { "    " } else { "│   " }
+}
+
+/// The /24 network `ip` is in
+pub fn subnet_of(ip: Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(ip) & 0xFFFF_FF00)
+}
+
+/// Addresses grouped by /24, both in address order
+pub fn by_subnet(ips: &[Ipv4Addr]) -> Vec<(Ipv4Addr, Vec<Ipv4Addr>)> {
+    let mut sorted = ips.to_vec();
+    sorted.sort_unstable();
+    let mut groups: Vec<(Ipv4Addr, Vec<Ipv4Addr>)> = Vec::new();
+    for ip in sorted {
+        match groups.last_mut() {
+            Some((subnet, members)) if *subnet == subnet_of(ip) => members.push(ip),
+            _ => groups.push((subnet_of(ip), vec![ip])),
+        }
+    }
+    groups
+}
+
+/// One line per device with latency and ports in aligned columns, cut off
+/// after `limit` devices with a count of the rest
+fn device_lines(ips: &[Ipv4Addr], result: &ScanResult, gateway: Option<Ipv4Addr>, limit: Option<usize>) -> Vec<String> {
+    let shown = limit.map_or(ips.len(), |limit| limit.min(ips.len()));
+    let rows: Vec<(String, String, String)> = ips[..shown]
+        .iter()
+        .map(|ip| {
+            let mut name = ip.to_string();
+            if Some(*ip) == gateway {
+                name.push_str(" (gateway)");
+            }
+            let device = result.devices.iter().find(|d| d.ip == *ip);
+            let latency = device
+                .and_then(|d| d.latency_ms)
+                .map_or_else(String::new, |ms| format!("{:.1}ms", ms));
+            let ports = match device {
+                Some(d) if !d.open_ports.is_empty() => {
+                    let ports: Vec<_> = d.open_ports.iter().take(3).map(u16::to_string).collect();
+                    let more = if d.open_ports.len() > 3 { ",…" } else { "" };
+                    format!("ports: {}{}", ports.join(","), more)
+                }
+                _ => String::new(),
+            };
+            (name, latency, ports)
+        })
+        .collect();
+
+    let name_width = rows.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+    let latency_width = rows.iter().map(|(_, latency, _)| latency.len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = rows
+        .iter()
+        .map(|(name, latency, ports)| {
+            format!("{:<nw$}  {:>lw$}  {}", name, latency, ports, nw = name_width, lw = latency_width)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+    if shown < ips.len() {
+        lines.push(format!("… and {} more", ips.len() - shown));
+    }
+    lines
+}
+
+/// Fill colour for a device node, keyed on the OS guess
+fn os_color(os_guess: Option<&str>) -> &'static str {
+    match os_guess {
+        Some("Windows") => "lightskyblue",
+        Some("Linux") => "palegreen",
+        Some("macOS") => "plum",
+        Some("Local Router/Switch") => "orange",
+        Some(_) => "lightyellow",
+        None => "lightgrey",
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn node_label(device: &Device) -> String {
+    let mut label = device.ip.to_string();
+    if let Some(hostname) = &device.hostname {
+        label.push_str(&format!("\\n{}", escape(hostname)));
+    }
+    if let Some(vendor) = device.vendor.as_deref().filter(|v| *v != "Unknown") {
+        label.push_str(&format!("\\n{}", escape(vendor)));
+    }
+    if let Some(os) = &device.os_guess {
+        label.push_str(&format!("\\n[{}]", escape(os)));
+    }
+    label
+}
+
+/// Render a scan result as a Graphviz DOT digraph
+///
+/// Every device becomes a node coloured by OS guess. When a gateway is known
+/// each device gets an edge to it labelled with the measured latency; lower
+/// latency maps to a higher edge weight so nearby hosts cluster tighter.
+pub fn to_dot(result: &ScanResult, gateway: Option<Ipv4Addr>) -> String {
+    to_dot_routed(result, gateway, None)
+}
+
+/// `to_dot`, with edges following traced routes when `routes` is given
+/// rather than fanning every device out from the gateway
+pub fn to_dot_routed(result: &ScanResult, gateway: Option<Ipv4Addr>, routes: Option<&RouteTree>) -> String {
+    let mut dot = String::new();
+
+    let _ = writeln!(dot, "digraph netweaver {{");
+    let _ = writeln!(dot, "    label=\"NetWeaver scan: {}\";", escape(&result.network_range));
+    let _ = writeln!(dot, "    rankdir=LR;");
+    let _ = writeln!(dot, "    node [shape=box, style=\"rounded,filled\", fontname=\"Helvetica\"];");
+    let _ = writeln!(dot);
+
+    if let Some(gw) = gateway {
+        let scanned = result.devices.iter().find(|d| d.ip == gw);
+        let label = match scanned {
+            Some(device) => node_label(device),
+            None => gw.to_string(),
+        };
+        let _ = writeln!(
+            dot,
+            "    \"{}\" [label=\"{}\\n(gateway)\", shape=doubleoctagon, fillcolor=gold];",
+            gw, label
+        );
+    }
+
+    // Devices from a multi-target scan are boxed per input range, otherwise
+    // per /24 like the console tree
+    for (idx, (label, devices)) in clusters(result).into_iter().enumerate() {
+        let indent = if label.is_some() { "        " } else { "    " };
+        if let Some(label) = &label {
+            let _ = writeln!(dot, "    subgraph cluster_{} {{", idx);
+            let _ = writeln!(dot, "        label=\"{}\";", escape(label));
+        }
+        for device in devices.into_iter().filter(|d| Some(d.ip) != gateway) {
+            let _ = writeln!(
+                dot,
+                "{}\"{}\" [label=\"{}\", fillcolor={}];",
+                indent,
+                device.ip,
+                node_label(device),
+                os_color(device.os_guess.as_deref())
+            );
+        }
+        if label.is_some() {
+            let _ = writeln!(dot, "    }}");
+        }
+    }
+
+    if let Some(tree) = routes {
+        let _ = writeln!(dot);
+        let _ = writeln!(dot, "    \"local\" [label=\"This host\", shape=house, fillcolor=white];");
+        route_edges(&mut dot, result, "local".to_string(), tree);
+    } else if let Some(gw) = gateway {
+        let _ = writeln!(dot);
+        for device in result.devices.iter().filter(|d| d.ip != gw) {
+            match device.latency_ms {
+                Some(latency) => {
+                    let weight = (100.0 / latency.max(1.0)).ceil() as u32;
+                    let _ = writeln!(
+                        dot,
+                        "    \"{}\" -> \"{}\" [label=\"{:.1}ms\", weight={}];",
+                        gw, device.ip, latency, weight
+                    );
+                }
+                None => {
+                    let _ = writeln!(dot, "    \"{}\" -> \"{}\";", gw, device.ip);
+                }
+            }
+        }
+    }
+
+    let _ = writeln!(dot, "}}");
+    dot
+}
+
+fn clusters(result: &ScanResult) -> Vec<(Option<String>, Vec<&Device>)> {
+    if result.devices.iter().any(|d| d.source_range.is_some()) {
+        return group_by_source(&result.devices)
+            .into_iter()
+            .map(|(label, devices)| (label.map(str::to_string), devices))
+            .collect();
+    }
+
+    let ips: Vec<Ipv4Addr> = result.devices.iter().map(|d| d.ip).collect();
+    let groups = by_subnet(&ips);
+    let single = groups.len() <= 1;
+    groups
+        .into_iter()
+        .map(|(subnet, members)| {
+            let devices = members.iter().filter_map(|ip| result.devices.iter().find(|d| d.ip == *ip)).collect();
+            ((!single).then(|| format!("{}/24", subnet)), devices)
+        })
+        .collect()
+}
+
+fn route_edges(dot: &mut String, result: &ScanResult, from: String, tree: &RouteTree) {
+    for (idx, (router, child)) in tree.routers.iter().enumerate() {
+        let node = match router {
+            Some(ip) => ip.to_string(),
+            None => format!("{}-unknown-{}", from, idx),
+        };
+        // Routers that weren't scanned still need a node
+        if !result.devices.iter().any(|d| Some(d.ip) == *router) {
+            let label = router.map_or_else(|| "*".to_string(), |ip| ip.to_string());
+            let _ = writeln!(dot, "    \"{}\" [label=\"{}\", shape=diamond, fillcolor=orange];", node, label);
+        }
+        let _ = writeln!(dot, "    \"{}\" -> \"{}\";", from, node);
+        route_edges(dot, result, node, child);
+    }
+    for ip in &tree.devices {
+        if tree.routers.iter().any(|(router, _)| *router == Some(*ip)) {
+            continue;
+        }
+        match result.devices.iter().find(|d| d.ip == *ip).and_then(|d| d.latency_ms) {
+            Some(latency) => {
+                let _ = writeln!(dot, "    \"{}\" -> \"{}\" [label=\"{:.1}ms\"];", from, ip, latency);
+            }
+            None => {
+                let _ = writeln!(dot, "    \"{}\" -> \"{}\";", from, ip);
+            }
+        }
+    }
+}
+
+pub fn save_dot(result: &ScanResult, gateway: Option<Ipv4Addr>, routes: Option<&RouteTree>, path: &str) -> Result<()> {
+    std::fs::write(path, to_dot_routed(result, gateway, routes))?;
+    Ok(())
+}
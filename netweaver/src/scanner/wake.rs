@@ -0,0 +1,109 @@
+// Wake-on-LAN
+// A magic packet is 6 bytes of 0xFF followed by the target MAC sixteen
+// times, broadcast over UDP. The NIC of a sleeping machine watches for its
+// own MAC in that pattern, so nothing about the packet needs to be routable
+// beyond the local segment.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+use super::inventory::{self, Inventory};
+use crate::report;
+use crate::utils::{self, MacAddress};
+
+pub const MAGIC_PACKET_LEN: usize = 102;
+
+/// Gap between repeated packets; WoL is fire-and-forget, so we just resend
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn magic_packet(mac: MacAddress) -> [u8; MAGIC_PACKET_LEN] {
+    let mut packet = [0xFF; MAGIC_PACKET_LEN];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&mac.0);
+    }
+    packet
+}
+
+/// Broadcast `count` magic packets for `mac` to `broadcast:port`
+pub async fn send(mac: MacAddress, broadcast: Ipv4Addr, port: u16, count: u32) -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.context("Failed to bind Wake-on-LAN socket")?;
+    socket.set_broadcast(true)?;
+
+    let packet = magic_packet(mac);
+    let target = SocketAddr::from((broadcast, port));
+    for i in 0..count.max(1) {
+        if i > 0 {
+            tokio::time::sleep(RESEND_INTERVAL).await;
+        }
+        socket
+            .send_to(&packet, target)
+            .await
+            .with_context(|| format!("Failed to send magic packet to {}", target))?;
+    }
+    Ok(())
+}
+
+/// Broadcast address of the subnet the local address lives on, assuming a
+/// /24 when the interface can't be inspected
+pub fn local_broadcast() -> Result<Ipv4Addr> {
+    let local_ip = utils::get_local_ip()?;
+    let broadcast = super::arp::interface_for(local_ip).and_then(|iface| {
+        iface
+            .ips
+            .iter()
+            .find(|net| net.ip() == IpAddr::V4(local_ip))
+            .and_then(|net| match net.broadcast() {
+                IpAddr::V4(addr) => Some(addr),
+                IpAddr::V6(_) => None,
+            })
+    });
+    Ok(broadcast.unwrap_or_else(|| Ipv4Addr::from(u32::from(local_ip) | 0xFF)))
+}
+
+/// Resolve `target` to a MAC: either a MAC itself, or an IP looked up in a
+/// saved scan file or, without one, the device inventory
+pub fn resolve_mac(target: &str, scan_file: Option<&str>) -> Result<MacAddress> {
+    let Ok(ip) = target.parse::<Ipv4Addr>() else {
+        return target.parse();
+    };
+
+    let mac = match scan_file {
+        Some(path) => super::load_results(path)
+            .with_context(|| format!("Failed to load scan results '{}'", path))?
+            .devices
+            .into_iter()
+            .find(|d| d.ip == ip)
+            .and_then(|d| d.mac),
+        None => Inventory::load(&inventory::default_path()?)?
+            .devices
+            .into_iter()
+            .filter(|e| e.ip == ip)
+            .max_by_key(|e| e.last_seen)
+            .and_then(|e| e.mac),
+    };
+    let source = scan_file.unwrap_or("the device inventory");
+    mac.with_context(|| format!("No MAC address known for {} in {}", ip, source))
+}
+
+/// The `wake` subcommand
+pub async fn run_wake(
+    target: String,
+    scan_file: Option<String>,
+    broadcast: Option<Ipv4Addr>,
+    port: u16,
+    count: u32,
+) -> Result<()> {
+    let mac = resolve_mac(&target, scan_file.as_deref())?;
+    let broadcast = match broadcast {
+        Some(addr) => addr,
+        None => local_broadcast()?,
+    };
+
+    send(mac, broadcast, port, count).await?;
+    report!("{} Sent {} magic packet(s) for {} to {}:{}",
+             "⏰".bright_yellow(), count.max(1), mac.to_string().bright_magenta(), broadcast, port);
+    Ok(())
+}
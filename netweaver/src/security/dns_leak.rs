@@ -0,0 +1,228 @@
+// DNS leak test
+// A VPN only hides lookups that travel through its tunnel. This lists the
+// resolvers the system is configured with (resolv.conf and, behind the
+// systemd-resolved stub, the servers it forwards to; `scutil --dns` on
+// macOS; the adapters' DNS servers on Windows), asks the routing table
+// which interface a query to each would leave by, and sends each a test
+// query. A resolver reached over the tunnel, inside the tunnel's address
+// space or in the VPN's `--expected-exit` ranges belongs to the VPN; one
+// reached over the physical interface belongs to the ISP or the local
+// network. Only a resolver outside the tunnel that actually answered,
+// while a tunnel is up, counts as a leak.
+
+use anyhow::{Context, Result};
+use hickory_resolver::proto::rr::Name;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+use super::public_ip;
+use crate::diagnostics::dns::{self, RecordKind, Resolver};
+
+/// Name prefixes of VPN tunnel interfaces: OpenVPN, WireGuard, macOS
+/// utun, PPP/L2TP, IPsec and the providers' own
+const TUNNEL_PREFIXES: &[&str] = &["tun", "tap", "wg", "utun", "ppp", "ipsec", "nordlynx", "proton", "mullvad"];
+
+/// The test query; any name will do, it only has to be asked
+const TEST_NAME: &str = "example.com.";
+
+/// Which way queries to a resolver go
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Route {
+    /// Through the VPN tunnel, to the VPN's resolver
+    Tunnel,
+    /// Around the tunnel, to the ISP's or the local network's resolver
+    Outside,
+    /// A resolver on this host, like the systemd-resolved stub
+    Local,
+    /// No route to it
+    Unknown,
+}
+
+/// An interface as the test sees it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub name: String,
+    /// Addresses with their prefix lengths
+    pub networks: Vec<(IpAddr, u8)>,
+    pub tunnel: bool,
+}
+
+impl Link {
+    fn has(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|(own, _)| *own == ip)
+    }
+
+    fn covers(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|(network, prefix)| public_ip::in_range(ip, (*network, *prefix)))
+    }
+}
+
+/// The interfaces that are up, and which of them are tunnels
+pub fn links() -> Vec<Link> {
+    pnet_datalink::interfaces()
+        .into_iter()
+        .filter(|iface| iface.is_up() && !iface.is_loopback())
+        .map(|iface| Link {
+            tunnel: iface.is_point_to_point() || is_tunnel_name(&iface.name),
+            networks: iface.ips.iter().map(|network| (network.ip(), network.prefix())).collect(),
+            name: iface.name,
+        })
+        .collect()
+}
+
+pub fn is_tunnel_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    TUNNEL_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// The tunnel interfaces with an address, the ones DNS could leak around
+pub fn tunnels(links: &[Link]) -> Vec<&Link> {
+    links.iter().filter(|link| link.tunnel && link.networks.iter().any(|(ip, _)| !is_link_local(*ip))).collect()
+}
+
+fn is_link_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_link_local(),
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// The `nameserver` lines of a resolv.conf, without IPv6 zone suffixes
+pub fn parse_resolv_conf(text: &str) -> Vec<IpAddr> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next()? == "nameserver").then_some(())?;
+            fields.next()?.split('%').next()?.parse().ok()
+        })
+        .collect()
+}
+
+/// The `nameserver[n] : 192.168.1.1` lines of `scutil --dns`, the scoped
+/// and per-domain resolvers included
+pub fn parse_scutil_dns(text: &str) -> Vec<IpAddr> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().starts_with("nameserver[").then_some(())?;
+            value.trim().split('%').next()?.parse().ok()
+        })
+        .collect()
+}
+
+/// The resolvers the system is configured with, each once
+pub fn system_nameservers() -> Result<Vec<IpAddr>> {
+    let mut servers = Vec::new();
+    for server in configured_nameservers()? {
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(target_os = "linux")]
+fn configured_nameservers() -> Result<Vec<IpAddr>> {
+    let text = std::fs::read_to_string("/etc/resolv.conf").context("Failed to read /etc/resolv.conf")?;
+    let mut servers = parse_resolv_conf(&text);
+    // Behind the stub are the servers that actually see the queries
+    if servers.iter().any(IpAddr::is_loopback) {
+        if let Ok(text) = std::fs::read_to_string("/run/systemd/resolve/resolv.conf") {
+            servers.extend(parse_resolv_conf(&text));
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(target_os = "macos")]
+fn configured_nameservers() -> Result<Vec<IpAddr>> {
+    let output = std::process::Command::new("scutil").arg("--dns").output().context("Failed to run 'scutil --dns'")?;
+    Ok(parse_scutil_dns(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The adapters' DNS servers (GetAdaptersAddresses on Windows)
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn configured_nameservers() -> Result<Vec<IpAddr>> {
+    let (config, _) = hickory_resolver::system_conf::read_system_conf().context("Failed to read the system DNS configuration")?;
+    Ok(config.name_servers().iter().map(|server| server.socket_addr.ip()).collect())
+}
+
+/// The local address the system would send a query to `server` from,
+/// which tells the interface it leaves by; nothing is sent
+pub fn egress_address(server: IpAddr) -> Option<IpAddr> {
+    let bind: SocketAddr = match server {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.connect((server, 53)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Which way queries to `server` go, leaving from `egress`, and over which
+/// interface
+pub fn classify(server: IpAddr, egress: Option<IpAddr>, links: &[Link], expected_exit: &[(IpAddr, u8)]) -> (Route, Option<String>) {
+    // This host's own addresses too, as a local dnsmasq might listen on
+    if server.is_loopback() || links.iter().any(|link| link.has(server)) {
+        return (Route::Local, None);
+    }
+    let interface = egress.and_then(|egress| links.iter().find(|link| link.has(egress)));
+    let name = interface.map(|link| link.name.clone());
+    if links.iter().any(|link| link.tunnel && link.covers(server))
+        || expected_exit.iter().any(|range| public_ip::in_range(server, *range))
+    {
+        return (Route::Tunnel, name);
+    }
+    match interface {
+        Some(link) if link.tunnel => (Route::Tunnel, name),
+        Some(_) => (Route::Outside, name),
+        None => (Route::Unknown, None),
+    }
+}
+
+/// One configured resolver, the way to it and whether it answered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolverCheck {
+    pub server: IpAddr,
+    pub route: Route,
+    /// The interface queries leave by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// None when the test query went unanswered
+    pub latency_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ResolverCheck {
+    /// Who runs the resolver, going by the way to it
+    pub fn owner(&self) -> &'static str {
+        match self.route {
+            Route::Tunnel => "VPN provider",
+            Route::Outside => "ISP or local network",
+            Route::Local => "this host",
+            Route::Unknown => "unreachable",
+        }
+    }
+
+    /// Solid evidence of a leak: reached around the tunnel, and it answered
+    pub fn leaks(&self) -> bool {
+        self.route == Route::Outside && self.latency_ms.is_some()
+    }
+}
+
+/// Check every configured resolver against `links`
+pub async fn check_resolvers(servers: &[IpAddr], links: &[Link], expected_exit: &[(IpAddr, u8)]) -> Result<Vec<ResolverCheck>> {
+    let name = Name::from_ascii(TEST_NAME)?;
+    let checks = servers.iter().map(|&server| {
+        let name = name.clone();
+        async move {
+            let (route, interface) = classify(server, egress_address(server), links, expected_exit);
+            let resolver = Resolver { label: server.to_string(), server: SocketAddr::new(server, 53) };
+            let query = dns::query(resolver, &name, RecordKind::A).await;
+            ResolverCheck { server, route, interface, latency_ms: query.latency_ms, error: query.error }
+        }
+    });
+    Ok(futures::future::join_all(checks).await)
+}
@@ -0,0 +1,158 @@
+// IPv6 leak test
+// Many VPNs only tunnel IPv4, and a host with a global IPv6 address and a
+// router advertising a default route then talks to dual-stack sites around
+// the tunnel. Three signals, each reported: the global IPv6 addresses and
+// the interfaces they're on; which interface the routing table sends IPv6
+// traffic for the internet out of (asking the system, so policy routing as
+// wg-quick sets it up counts); and an actual IPv6 connection to a service
+// that tells the address it came from. That address is checked against the
+// VPN's IPv6 `--expected-exit` range when there is one. Without an IPv6
+// route the connection isn't tried at all, so the test is instant on
+// IPv4-only networks, and otherwise it gives up after a few seconds.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::Duration;
+
+use super::dns_leak::{self, Link};
+use super::public_ip;
+
+/// Answers with the caller's address as plain text, over IPv6 only
+pub const IPV6_SOURCE: &str = "https://api6.ipify.org";
+
+/// A global address to look the IPv6 route up for (Google Public DNS);
+/// nothing is sent to it
+const ROUTE_PROBE: Ipv6Addr = Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// A global IPv6 address and the interface it's on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobalAddress {
+    pub interface: String,
+    pub address: Ipv6Addr,
+    pub tunnel: bool,
+}
+
+/// The interface IPv6 traffic for the internet leaves by
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ipv6Route {
+    /// None when the source address isn't on any interface that's up
+    pub interface: Option<String>,
+    pub tunnel: bool,
+}
+
+/// How the test connection went
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connection {
+    /// Not tried, for want of a route
+    Skipped,
+    Failed(String),
+    /// Made, from this address as the far end saw it
+    Revealed(Ipv6Addr),
+}
+
+/// What the three signals add up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// No VPN tunnel is up, so nothing can leak around one
+    NoTunnel,
+    /// No IPv6 route to the internet
+    NoIpv6,
+    /// IPv6 goes into the tunnel, or comes out of the VPN's range
+    Tunnelled,
+    /// A route around the tunnel, though the test connection failed
+    Exposed,
+    /// A connection made around the tunnel, or from outside the VPN's range
+    Leak,
+}
+
+/// The global unicast IPv6 addresses (2000::/3) of `links`; link-local
+/// and unique local ones can't reach the internet
+pub fn global_addresses(links: &[Link]) -> Vec<GlobalAddress> {
+    links
+        .iter()
+        .flat_map(|link| {
+            link.networks.iter().filter_map(move |(ip, _)| match ip {
+                IpAddr::V6(address) if address.segments()[0] & 0xe000 == 0x2000 => {
+                    Some(GlobalAddress { interface: link.name.clone(), address: *address, tunnel: link.tunnel })
+                }
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+/// Where the system would send IPv6 traffic for the internet from, given
+/// the source address it picks
+pub fn route(source: Option<IpAddr>, links: &[Link]) -> Option<Ipv6Route> {
+    let source = source.filter(IpAddr::is_ipv6)?;
+    let link = links.iter().find(|link| link.networks.iter().any(|(ip, _)| *ip == source));
+    Some(Ipv6Route { interface: link.map(|link| link.name.clone()), tunnel: link.is_some_and(|link| link.tunnel) })
+}
+
+/// Connect to IPV6_SOURCE over IPv6 and read back the address it saw
+pub async fn connect() -> Result<Ipv6Addr> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(HTTP_TIMEOUT)
+        .local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+        .build()?;
+    let body = async { client.get(IPV6_SOURCE).send().await?.error_for_status()?.text().await }
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", innermost(&e)))?;
+    let body = body.trim();
+    body.parse().with_context(|| format!("Answered '{}', not an IPv6 address", body.chars().take(40).collect::<String>()))
+}
+
+fn innermost(e: &reqwest::Error) -> String {
+    let mut cause: &dyn std::error::Error = e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+/// All three signals
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Signals {
+    pub addresses: Vec<GlobalAddress>,
+    pub route: Option<Ipv6Route>,
+    pub connection: Connection,
+}
+
+/// Gather the signals from this host, connecting only if there's a route
+pub async fn signals(links: &[Link]) -> Ipv6Signals {
+    let route = route(dns_leak::egress_address(IpAddr::V6(ROUTE_PROBE)), links);
+    let connection = match route {
+        None => Connection::Skipped,
+        Some(_) => match connect().await {
+            Ok(address) => Connection::Revealed(address),
+            Err(e) => Connection::Failed(format!("{:#}", e)),
+        },
+    };
+    Ipv6Signals { addresses: global_addresses(links), route, connection }
+}
+
+/// The verdict on `signals` while `tunnel_up`, given the VPN's exit ranges
+pub fn verdict(signals: &Ipv6Signals, tunnel_up: bool, expected_exit: &[(IpAddr, u8)]) -> Verdict {
+    if !tunnel_up {
+        return Verdict::NoTunnel;
+    }
+    let through_tunnel = signals.route.as_ref().is_some_and(|route| route.tunnel);
+    match &signals.connection {
+        Connection::Revealed(address) => match public_ip::in_exit(IpAddr::V6(*address), expected_exit) {
+            Some(true) => Verdict::Tunnelled,
+            Some(false) => Verdict::Leak,
+            None if through_tunnel => Verdict::Tunnelled,
+            None => Verdict::Leak,
+        },
+        _ if signals.route.is_none() => Verdict::NoIpv6,
+        _ if through_tunnel => Verdict::Tunnelled,
+        _ => Verdict::Exposed,
+    }
+}
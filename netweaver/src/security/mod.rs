@@ -1,10 +1,72 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use crate::diagnostics::tls;
 use crate::utils;
+use crate::{report, status};
+
+pub mod dns_leak;
+pub mod ipv6_leak;
+pub mod public_ip;
+
+use public_ip::VpnOptions;
+
+/// How much a finding matters; orders most serious first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical,
+    Warning,
+    Info,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Critical => "critical",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+/// Something one check found
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    /// The check that raised it: `arp`, `vpn`, `ports` or `mitm`
+    pub check: String,
+    pub severity: Severity,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Finding {
+    fn new(check: &str, severity: Severity, title: impl Into<String>, detail: Option<String>) -> Self {
+        Self { check: check.to_string(), severity, title: title.into(), detail }
+    }
+}
+
+/// One run of `security`, as saved with `--output` and kept for `report`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityAudit {
+    pub generated_at: DateTime<Utc>,
+    /// The checks that ran
+    pub checks: Vec<String>,
+    pub findings: Vec<Finding>,
+}
+
+impl SecurityAudit {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity == severity).count()
+    }
+}
 
 pub async fn run_security_audit(
     arp_detect: bool,
@@ -12,70 +74,121 @@ pub async fn run_security_audit(
     port_scan: bool,
     mitm_detect: bool,
     all: bool,
+    output: Option<String>,
+    vpn: VpnOptions,
 ) -> Result<()> {
-    println!("{}", "NetWeaver Security Auditor".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+    vpn.check()?;
+    status!("{}", "NetWeaver Security Auditor".bright_cyan().bold());
+    status!("{}", "═".repeat(60).bright_cyan());
     
     if !utils::is_privileged() {
-        println!("{} Some security checks require root privileges", "⚠".yellow());
+        status!("{} Some security checks require root privileges", "⚠".yellow());
     }
     
-    let mut vulnerabilities = 0;
-    let mut warnings = 0;
+    let mut audit = SecurityAudit { generated_at: Utc::now(), checks: Vec::new(), findings: Vec::new() };
     
     if arp_detect || all {
-        println!("\n{}", "🔍 ARP Spoofing Detection".bright_green().bold());
-        let (vuln, warn) = check_arp_spoofing().await?;
-        vulnerabilities += vuln;
-        warnings += warn;
+        report!("\n{}", "🔍 ARP Spoofing Detection".bright_green().bold());
+        audit.checks.push("arp".to_string());
+        audit.findings.extend(check_arp_spoofing().await?);
     }
     
     if vpn_test || all {
-        println!("\n{}", "🔐 VPN Integrity Test".bright_green().bold());
-        let (vuln, warn) = test_vpn_integrity().await?;
-        vulnerabilities += vuln;
-        warnings += warn;
+        report!("\n{}", "🔐 VPN Integrity Test".bright_green().bold());
+        audit.checks.push("vpn".to_string());
+        audit.findings.extend(test_vpn_integrity(&vpn).await?);
     }
     
     if port_scan || all {
-        println!("\n{}", "🚪 Open Port Scan".bright_green().bold());
-        let (vuln, warn) = scan_open_ports().await?;
-        vulnerabilities += vuln;
-        warnings += warn;
+        report!("\n{}", "🚪 Open Port Scan".bright_green().bold());
+        audit.checks.push("ports".to_string());
+        audit.findings.extend(scan_open_ports().await?);
     }
     
     if mitm_detect || all {
-        println!("\n{}", "👁️  MITM Detection".bright_green().bold());
-        let (vuln, warn) = detect_mitm().await?;
-        vulnerabilities += vuln;
-        warnings += warn;
+        report!("\n{}", "👁️  MITM Detection".bright_green().bold());
+        audit.checks.push("mitm".to_string());
+        audit.findings.extend(detect_mitm().await?);
     }
     
-    println!("\n{}", "═".repeat(60).bright_cyan());
-    println!("{}", "Security Audit Summary".bright_cyan().bold());
-    println!("{}", "═".repeat(60).bright_cyan());
+    let vulnerabilities = audit.count(Severity::Critical);
+    let warnings = audit.count(Severity::Warning);
+    report!("\n{}", "═".repeat(60).bright_cyan());
+    report!("{}", "Security Audit Summary".bright_cyan().bold());
+    report!("{}", "═".repeat(60).bright_cyan());
     
     if vulnerabilities > 0 {
-        println!("  {} {} critical issues found", 
+        report!("  {} {} critical issues found", 
                  "❌".bright_red(), 
                  vulnerabilities.to_string().bright_red().bold());
     } else {
-        println!("  {} No critical vulnerabilities detected", "✓".bright_green());
+        report!("  {} No critical vulnerabilities detected", "✓".bright_green());
     }
     
     if warnings > 0 {
-        println!("  {} {} warnings", 
+        report!("  {} {} warnings", 
                  "⚠".bright_yellow(), 
                  warnings.to_string().bright_yellow());
     } else {
-        println!("  {} No warnings", "✓".bright_green());
+        report!("  {} No warnings", "✓".bright_green());
+    }
+    
+    if let Err(e) = latest_path().and_then(|path| store_latest(&path, &audit)) {
+        status!("⚠️  Could not keep the audit for reports: {:#}", e);
+    }
+    if let Some(path) = output {
+        save_audit(&audit, Path::new(&path))?;
+        status!("\n💾 Audit saved to: {}", path.bright_green());
     }
     
     Ok(())
 }
 
-async fn check_arp_spoofing() -> Result<(usize, usize)> {
-    println!("Monitoring ARP table for anomalies...\n");
+/// `$XDG_DATA_HOME/netweaver/security.json`, the latest audit
+pub fn latest_path() -> Result<PathBuf> {
+    Ok(utils::data_dir()?.join("security.json"))
+}
+
+/// The latest audit, if `security` ever ran here
+pub fn load_latest(path: &Path) -> Result<Option<SecurityAudit>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+    }
+}
+
+pub fn store_latest(path: &Path, audit: &SecurityAudit) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(audit)?)
+        .with_context(|| format!("Failed to write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace '{}'", path.display()))
+}
+
+/// Write `audit` to `path`, as YAML for `.yaml`/`.yml` and JSON otherwise
+pub fn save_audit(audit: &SecurityAudit, path: &Path) -> Result<()> {
+    let content = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::to_string(audit)?,
+        _ => serde_json::to_string_pretty(audit)?,
+    };
+    std::fs::write(path, content).with_context(|| format!("Failed to write '{}'", path.display()))
+}
+
+/// An audit saved with `security --output`
+pub fn load_audit(path: &Path) -> Result<SecurityAudit> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&content).with_context(|| format!("Failed to parse '{}'", path.display())),
+        _ => serde_json::from_str(&content).with_context(|| format!("Failed to parse '{}'", path.display())),
+    }
+}
+
+async fn check_arp_spoofing() -> Result<Vec<Finding>> {
+    status!("Monitoring ARP table for anomalies...\n");
     
     let arp_table = get_arp_table().await?;
     
@@ -84,84 +197,261 @@ async fn check_arp_spoofing() -> Result<(usize, usize)> {
         duplicates.entry(mac).or_insert_with(Vec::new).push(ip);
     }
     
-    let suspicious = duplicates.iter()
-        .filter(|(_, ips)| ips.len() > 1)
-        .count();
-    
-    if suspicious > 0 {
-        println!("{} Suspicious ARP entries detected!", "⚠".bright_red());
-        for (mac, ips) in duplicates.iter().filter(|(_, ips)| ips.len() > 1) {
-            println!("  MAC {} maps to multiple IPs:", mac.to_string().bright_yellow());
-            for ip in ips {
-                println!("    - {}", ip.to_string().bright_red());
-            }
+    let mut findings = Vec::new();
+    for (mac, ips) in duplicates.iter().filter(|(_, ips)| ips.len() > 1) {
+        if findings.is_empty() {
+            report!("{} Suspicious ARP entries detected!", "⚠".bright_red());
         }
-        Ok((suspicious, 0))
-    } else {
-        println!("{} ARP table looks clean", "✓".bright_green());
-        println!("  {} unique MAC addresses", arp_table.len());
-        Ok((0, 0))
+        report!("  MAC {} maps to multiple IPs:", mac.to_string().bright_yellow());
+        for ip in ips {
+            report!("    - {}", ip.to_string().bright_red());
+        }
+        let ips: Vec<String> = ips.iter().map(|ip| ip.to_string()).collect();
+        findings.push(Finding::new("arp", Severity::Critical, format!("MAC {} maps to multiple IPs", mac),
+                                   Some(ips.join(", "))));
     }
+    
+    if findings.is_empty() {
+        report!("{} ARP table looks clean", "✓".bright_green());
+        report!("  {} entries, {} unique MAC addresses", arp_table.len(), duplicates.len());
+    }
+    Ok(findings)
 }
 
+/// The host's IPv4 neighbours: `/proc/net/arp` on Linux, `arp -a` elsewhere
 async fn get_arp_table() -> Result<HashMap<Ipv4Addr, utils::MacAddress>> {
-    let mut table = HashMap::new();
-    
-    table.insert(
-        "192.168.1.1".parse().unwrap(),
-        utils::MacAddress::new([0x00, 0x50, 0x56, 0xc0, 0x00, 0x08])
-    );
-    
-    table.insert(
-        "192.168.1.100".parse().unwrap(),
-        utils::MacAddress::new([0xf0, 0x18, 0x98, 0x12, 0x34, 0x56])
-    );
-    
-    Ok(table)
+    #[cfg(target_os = "linux")]
+    {
+        let table = tokio::fs::read_to_string("/proc/net/arp").await.context("Failed to read /proc/net/arp")?;
+        Ok(parse_proc_net_arp(&table))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        // -n on BSD and macOS skips the reverse lookups; Windows has none to skip
+        let args: &[&str] = if cfg!(windows) { &["-a"] } else { &["-a", "-n"] };
+        let output = tokio::process::Command::new("arp").args(args).output().await.context("Failed to run 'arp -a'")?;
+        if !output.status.success() {
+            anyhow::bail!("'arp -a' failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(parse_arp_a(&String::from_utf8_lossy(&output.stdout)))
+    }
 }
 
-async fn test_vpn_integrity() -> Result<(usize, usize)> {
-    println!("Testing VPN connection security...\n");
-    
-    let public_ip = get_public_ip().await?;
-    println!("  Public IP: {}", public_ip.bright_cyan());
-    
-    let dns_leak = test_dns_leak().await?;
-    if dns_leak {
-        println!("  {} DNS Leak detected!", "⚠".bright_red());
-        return Ok((1, 0));
-    } else {
-        println!("  {} No DNS leak", "✓".bright_green());
+/// The complete entries of a `/proc/net/arp` table
+///
+/// Columns: IP address, HW type, flags, HW address, mask, device. Entries
+/// still being resolved lack the ATF_COM flag and have an all-zero address.
+pub fn parse_proc_net_arp(table: &str) -> HashMap<Ipv4Addr, utils::MacAddress> {
+    const ATF_COM: u32 = 0x2;
+
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = fields.first()?.parse().ok()?;
+            let flags = u32::from_str_radix(fields.get(2)?.trim_start_matches("0x"), 16).ok()?;
+            let mac = parse_neighbour_mac(fields.get(3)?)?;
+            (flags & ATF_COM != 0).then_some((ip, mac))
+        })
+        .collect()
+}
+
+/// The entries of `arp -a` output, in either of its two shapes
+///
+/// BSD and macOS print `host (192.168.1.1) at 0:50:56:c0:0:8 on en0 ifscope
+/// [ethernet]`, or `at (incomplete)` for an unresolved one. Windows prints an
+/// `Interface: 192.168.1.100 --- 0xb` heading, then `192.168.1.1
+/// 00-50-56-c0-00-08 dynamic` rows. Either way the MAC is the first address
+/// after the IP, and lines without one are skipped.
+pub fn parse_arp_a(output: &str) -> HashMap<Ipv4Addr, utils::MacAddress> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let ip = fields.by_ref().find_map(|field| field.trim_start_matches('(').trim_end_matches(')').parse().ok())?;
+            let mac = fields.find_map(parse_neighbour_mac)?;
+            Some((ip, mac))
+        })
+        .collect()
+}
+
+/// A neighbour's unicast MAC, from `aa:bb:…` or `aa-bb-…` with octets of
+/// one or two digits (macOS drops leading zeros)
+///
+/// Broadcast, multicast and all-zero addresses aren't a neighbour's: Windows
+/// lists `ff-ff-ff-ff-ff-ff` and `01-00-5e-…` for many addresses, which would
+/// look like one MAC claiming them all.
+fn parse_neighbour_mac(text: &str) -> Option<utils::MacAddress> {
+    let parts: Vec<&str> = text.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return None;
     }
-    
-    let ipv6_leak = test_ipv6_leak().await?;
-    if ipv6_leak {
-        println!("  {} IPv6 Leak detected!", "⚠".bright_yellow());
-        return Ok((0, 1));
-    } else {
-        println!("  {} No IPv6 leak", "✓".bright_green());
+    let mut bytes = [0u8; 6];
+    for (byte, part) in bytes.iter_mut().zip(&parts) {
+        if part.is_empty() || part.len() > 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
     }
-    
-    println!("\n{} VPN connection appears secure", "✓".bright_green());
-    Ok((0, 0))
+    let group = bytes[0] & 0x01 != 0;
+    (!group && bytes != [0; 6]).then_some(utils::MacAddress::new(bytes))
 }
 
-async fn get_public_ip() -> Result<String> {
-    Ok("203.0.113.42".to_string())
+async fn test_vpn_integrity(vpn: &VpnOptions) -> Result<Vec<Finding>> {
+    status!("Testing VPN connection security...\n");
+    
+    let mut findings = Vec::new();
+    let answers = public_ip::detect(vpn).await;
+    for answer in &answers {
+        match &answer.answer {
+            Ok(ip) => report!("  {:<32} {}", answer.source, ip.to_string().bright_cyan()),
+            Err(e) => report!("  {:<32} {}", answer.source, e.dimmed()),
+        }
+    }
+    let addresses = public_ip::addresses(&answers);
+    let public_ip = match addresses.as_slice() {
+        [] => {
+            report!("  {} Couldn't determine the public IP", "⚠".bright_yellow());
+            let errors: Vec<String> = answers.iter().filter_map(|answer| Some(format!("{}: {}", answer.source, answer.answer.as_ref().err()?))).collect();
+            findings.push(Finding::new("vpn", Severity::Warning, "Public IP unknown", Some(errors.join("; "))));
+            "unknown".to_string()
+        }
+        [ip] => {
+            report!("  Public IP: {}", ip.to_string().bright_cyan());
+            ip.to_string()
+        }
+        _ => {
+            // Typically HTTP through the tunnel and UDP around it, or a proxy
+            report!("  {} Public IP sources disagree (split tunneling or a transparent proxy?)", "⚠".bright_yellow());
+            let seen: Vec<String> = addresses.iter().map(|ip| format!("{} seen by {}", ip, public_ip::seen_by(&answers, *ip).join(", "))).collect();
+            findings.push(Finding::new("vpn", Severity::Warning, "Public IP sources disagree",
+                                       Some(format!("{}; split tunneling or a transparent proxy", seen.join("; ")))));
+            addresses.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", ")
+        }
+    };
+    
+    if !vpn.expected_exit.is_empty() {
+        let ranges = public_ip::format_ranges(&vpn.expected_exit);
+        let outside: Vec<_> = addresses.iter().filter(|ip| public_ip::in_exit(**ip, &vpn.expected_exit) == Some(false)).collect();
+        for ip in &outside {
+            report!("  {} {} is outside the expected VPN exit {}", "⚠".bright_red(), ip.to_string().bright_red(), ranges);
+            findings.push(Finding::new("vpn", Severity::Critical, "Traffic exits outside the VPN",
+                                       Some(format!("Public IP {} (seen by {}) is not in {}", ip, public_ip::seen_by(&answers, **ip).join(", "), ranges))));
+        }
+        if outside.is_empty() && addresses.iter().any(|ip| public_ip::in_exit(*ip, &vpn.expected_exit).is_some()) {
+            report!("  {} Public IP is inside the expected VPN exit", "✓".bright_green());
+        }
+    }
+    
+    findings.extend(test_dns_leak(&vpn.expected_exit, &public_ip).await?);
+    
+    findings.extend(test_ipv6_leak(&vpn.expected_exit).await);
+    
+    if findings.is_empty() {
+        report!("\n{} VPN connection appears secure", "✓".bright_green());
+    }
+    Ok(findings)
 }
 
-async fn test_dns_leak() -> Result<bool> {
-    tokio::time::sleep(Duration::from_millis(100)).await;
-    Ok(false)
+/// A leak is a configured resolver answering from outside the tunnel while
+/// one is up
+async fn test_dns_leak(expected_exit: &[(IpAddr, u8)], public_ip: &str) -> Result<Vec<Finding>> {
+    let servers = match dns_leak::system_nameservers() {
+        Ok(servers) => servers,
+        Err(e) => {
+            report!("  {} Couldn't list the DNS resolvers: {:#}", "⚠".bright_yellow(), e);
+            return Ok(Vec::new());
+        }
+    };
+    let links = dns_leak::links();
+    let tunnels = dns_leak::tunnels(&links);
+    let checks = dns_leak::check_resolvers(&servers, &links, expected_exit).await?;
+    
+    report!("  DNS resolvers:");
+    for check in &checks {
+        let via = check.interface.as_deref().map(|name| format!(" via {}", name)).unwrap_or_default();
+        let answer = match check.latency_ms {
+            Some(ms) => format!("answered in {:.1} ms", ms),
+            None => check.error.clone().unwrap_or_else(|| "no answer".to_string()),
+        };
+        let line = format!("    {:<30} {:<22} {}", format!("{}{}", check.server, via), check.owner(), answer);
+        if check.leaks() && !tunnels.is_empty() {
+            report!("{}", line.bright_red());
+        } else {
+            report!("{}", line);
+        }
+    }
+    if checks.is_empty() {
+        report!("    none configured");
+    }
+    
+    if tunnels.is_empty() {
+        report!("  {} No VPN tunnel is up, so DNS has nothing to leak around", "✓".bright_green());
+        return Ok(Vec::new());
+    }
+    let leaks: Vec<String> = checks
+        .iter()
+        .filter(|check| check.leaks())
+        .map(|check| format!("{} answered via {}", check.server, check.interface.as_deref().unwrap_or("?")))
+        .collect();
+    if leaks.is_empty() {
+        report!("  {} No DNS leak", "✓".bright_green());
+        return Ok(Vec::new());
+    }
+    report!("  {} DNS Leak detected!", "⚠".bright_red());
+    let tunnels: Vec<&str> = tunnels.iter().map(|link| link.name.as_str()).collect();
+    Ok(vec![Finding::new("vpn", Severity::Critical, "DNS leak detected",
+                         Some(format!("Queries bypass the VPN tunnel ({}): {}; public IP {}", tunnels.join(", "), leaks.join(", "), public_ip)))])
 }
 
-async fn test_ipv6_leak() -> Result<bool> {
-    tokio::time::sleep(Duration::from_millis(100)).await;
-    Ok(false)
+/// Each IPv6 signal on its own line, then what they add up to
+async fn test_ipv6_leak(expected_exit: &[(IpAddr, u8)]) -> Vec<Finding> {
+    let links = dns_leak::links();
+    let tunnels = dns_leak::tunnels(&links);
+    let signals = ipv6_leak::signals(&links).await;
+    
+    report!("  IPv6:");
+    let addresses: Vec<String> = signals.addresses.iter().map(|global| format!("{} on {}", global.address, global.interface)).collect();
+    report!("    {:<24} {}", "Global addresses", if addresses.is_empty() { "none".to_string() } else { addresses.join(", ") });
+    let side = |tunnel: bool| if tunnel { "through the tunnel" } else { "outside the tunnel" };
+    let route = match &signals.route {
+        Some(route) => format!("via {}, {}", route.interface.as_deref().unwrap_or("?"), side(route.tunnel)),
+        None => "none".to_string(),
+    };
+    report!("    {:<24} {}", "Route to the internet", route);
+    let connection = match &signals.connection {
+        ipv6_leak::Connection::Skipped => "not tried, no route".to_string(),
+        ipv6_leak::Connection::Failed(e) => format!("failed: {}", e),
+        ipv6_leak::Connection::Revealed(address) => format!("made, from {}", address),
+    };
+    report!("    {:<24} {}", "Test connection", connection);
+    
+    let tunnels: Vec<&str> = tunnels.iter().map(|link| link.name.as_str()).collect();
+    match ipv6_leak::verdict(&signals, !tunnels.is_empty(), expected_exit) {
+        ipv6_leak::Verdict::NoTunnel => report!("  {} No VPN tunnel is up, so IPv6 has nothing to leak around", "✓".bright_green()),
+        ipv6_leak::Verdict::NoIpv6 => report!("  {} No IPv6 leak (no IPv6 route)", "✓".bright_green()),
+        ipv6_leak::Verdict::Tunnelled => report!("  {} No IPv6 leak", "✓".bright_green()),
+        ipv6_leak::Verdict::Exposed => {
+            report!("  {} IPv6 is routed around the tunnel, though the test connection failed", "⚠".bright_yellow());
+            return vec![Finding::new("vpn", Severity::Info, "IPv6 route bypasses the VPN",
+                                     Some(format!("IPv6 for the internet goes out via {} instead of through {}",
+                                                  signals.route.and_then(|route| route.interface).unwrap_or_else(|| "?".to_string()), tunnels.join(", "))))];
+        }
+        ipv6_leak::Verdict::Leak => {
+            report!("  {} IPv6 Leak detected!", "⚠".bright_yellow());
+            let ipv6_leak::Connection::Revealed(address) = signals.connection else {
+                unreachable!("a leak is a connection made");
+            };
+            return vec![Finding::new("vpn", Severity::Warning, "IPv6 leak detected",
+                                     Some(format!("IPv6 traffic bypasses the VPN: connected from {} ({})", address, route)))];
+        }
+    }
+    Vec::new()
 }
 
-async fn scan_open_ports() -> Result<(usize, usize)> {
-    println!("Scanning localhost for open ports...\n");
+async fn scan_open_ports() -> Result<Vec<Finding>> {
+    status!("Scanning localhost for open ports...\n");
     
     let localhost = "127.0.0.1".parse::<Ipv4Addr>().unwrap();
     let ports: Vec<u16> = vec![
@@ -182,10 +472,10 @@ async fn scan_open_ports() -> Result<(usize, usize)> {
         }
     }
     
-    println!("Open ports found: {}", open_ports.len());
+    report!("Open ports found: {}", open_ports.len());
     
     if !open_ports.is_empty() {
-        println!("\n{}", "Open Ports:".bright_cyan());
+        report!("\n{}", "Open Ports:".bright_cyan());
         for port in &open_ports {
             let risk = if risky_ports.contains(port) {
                 format!(" {}", "⚠ High Risk".bright_red())
@@ -193,19 +483,27 @@ async fn scan_open_ports() -> Result<(usize, usize)> {
                 String::new()
             };
             
-            println!("  {} {}{}", port, get_service_name(*port).bright_yellow(), risk);
+            report!("  {} {}{}", port, get_service_name(*port).bright_yellow(), risk);
         }
     }
     
     if !risky_ports.is_empty() {
-        println!("\n{} {} potentially risky ports exposed", 
+        report!("\n{} {} potentially risky ports exposed", 
                  "⚠".bright_yellow(), 
                  risky_ports.len());
-        Ok((0, risky_ports.len()))
     } else {
-        println!("\n{} No high-risk ports exposed", "✓".bright_green());
-        Ok((0, 0))
+        report!("\n{} No high-risk ports exposed", "✓".bright_green());
     }
+    Ok(open_ports
+        .iter()
+        .map(|port| {
+            let title = format!("Port {} ({}) is open on localhost", port, get_service_name(*port));
+            match risky_ports.contains(port) {
+                true => Finding::new("ports", Severity::Warning, title, Some("High-risk service exposed".to_string())),
+                false => Finding::new("ports", Severity::Info, title, None),
+            }
+        })
+        .collect())
 }
 
 async fn is_port_open(ip: Ipv4Addr, port: u16) -> bool {
@@ -244,27 +542,27 @@ fn get_service_name(port: u16) -> &'static str {
     }
 }
 
-async fn detect_mitm() -> Result<(usize, usize)> {
-    println!("Analyzing network for MITM indicators...\n");
+async fn detect_mitm() -> Result<Vec<Finding>> {
+    status!("Analyzing network for MITM indicators...\n");
     
     let gateway_latency = measure_gateway_latency().await?;
-    println!("  Gateway latency: {:.2}ms", gateway_latency);
-    
-    let ssl_check = verify_ssl_certificates().await?;
-    if ssl_check {
-        println!("  {} SSL certificates valid", "✓".bright_green());
-    } else {
-        println!("  {} SSL certificate mismatch detected!", "⚠".bright_red());
-        return Ok((1, 0));
-    }
+    report!("  Gateway latency: {:.2}ms", gateway_latency);
     
-    let cert_pinning = check_certificate_pinning().await?;
-    if !cert_pinning {
-        println!("  {} Certificate pinning not detected", "ℹ".bright_blue());
+    let ssl_check = verify_ssl_certificates().await;
+    match ssl_check {
+        Some(true) => {
+            report!("  {} SSL certificates valid", "✓".bright_green());
+            report!("\n{} No MITM indicators detected", "✓".bright_green());
+        }
+        Some(false) => {
+            report!("  {} SSL certificate mismatch detected!", "⚠".bright_red());
+            return Ok(vec![Finding::new("mitm", Severity::Critical, "TLS certificate mismatch",
+                                        Some("A well-known site presented a chain the system roots don't trust".to_string()))]);
+        }
+        // Nothing was checked, so there's nothing to vouch for either
+        None => report!("  {} SSL certificates could not be checked", "ℹ".bright_blue()),
     }
-    
-    println!("\n{} No MITM indicators detected", "✓".bright_green());
-    Ok((0, 0))
+    Ok(Vec::new())
 }
 
 async fn measure_gateway_latency() -> Result<f64> {
@@ -272,11 +570,34 @@ async fn measure_gateway_latency() -> Result<f64> {
     Ok(5.2)
 }
 
-async fn verify_ssl_certificates() -> Result<bool> {
-    tokio::time::sleep(Duration::from_millis(100)).await;
-    Ok(true)
-}
+/// Well-known sites whose certificates always chain to a public CA; an
+/// intercepting proxy re-signs them with its own
+const MITM_CHECK_HOSTS: &[&str] = &["www.google.com", "www.cloudflare.com", "github.com"];
 
-async fn check_certificate_pinning() -> Result<bool> {
-    Ok(false)
+/// Whether every reachable check host presents a chain the system roots
+/// trust; None when none could be checked
+async fn verify_ssl_certificates() -> Option<bool> {
+    let mut verdict = None;
+    for host in MITM_CHECK_HOSTS {
+        match tls::inspect(host, 443, false, tls::DEFAULT_TIMEOUT).await {
+            Ok(inspection) => match inspection.chain_valid {
+                Some(true) => {
+                    report!("  {} {} ({})", "✓".bright_green(), host,
+                            inspection.chain.last().map_or("", |root| root.issuer.as_str()));
+                    verdict.get_or_insert(true);
+                }
+                Some(false) => {
+                    report!("  {} {}: {}", "⚠".bright_red(), host,
+                            inspection.validation_error.as_deref().unwrap_or("invalid chain"));
+                    if let Some(leaf) = inspection.chain.first() {
+                        report!("      issued by {}", leaf.issuer.bright_yellow());
+                    }
+                    verdict = Some(false);
+                }
+                None => status!("  {} {}: no system CA bundle to check against", "ℹ".bright_blue(), host),
+            },
+            Err(e) => status!("  {} {}: {:#}", "ℹ".bright_blue(), host, e),
+        }
+    }
+    verdict
 }
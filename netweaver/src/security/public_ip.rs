@@ -0,0 +1,257 @@
+// Public IP detection for the VPN integrity test
+// Asks two independent HTTPS services what address our traffic comes from,
+// plus a STUN server over UDP, which still answers where HTTP egress is
+// blocked or proxied. Sources that disagree are the interesting case: HTTP
+// through the tunnel and UDP around it is split tunneling, and an HTTP
+// answer unlike STUN's often means a transparent proxy. Everything is
+// asked over IPv4, so a dual-stack service can't answer with an IPv6
+// address and look like a disagreement; IPv6 has its own leak check.
+
+use anyhow::{Context, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// Plain-text "what's my IP" services, run by unrelated operators
+pub const DEFAULT_HTTP_SOURCES: &[&str] = &["https://api.ipify.org", "https://ifconfig.co/ip"];
+
+pub const DEFAULT_STUN_SERVER: &str = "stun.l.google.com:19302";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A binding request is sent this many times, a second apart, since UDP
+/// may lose it
+const STUN_ATTEMPTS: usize = 3;
+const STUN_TIMEOUT: Duration = Duration::from_secs(1);
+
+const STUN_MAGIC_COOKIE: u32 = 0x2112_a442;
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_RESPONSE: u16 = 0x0101;
+const STUN_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+/// Where the VPN test looks for the public IP, and where it should be
+#[derive(Debug, Clone, Default)]
+pub struct VpnOptions {
+    /// URLs answering with the caller's IP as plain text; DEFAULT_HTTP_SOURCES
+    /// when empty
+    pub ip_sources: Vec<String>,
+    /// `host:port`; DEFAULT_STUN_SERVER when None
+    pub stun_server: Option<String>,
+    /// The VPN provider's exit ranges, which the public IP should be in
+    pub expected_exit: Vec<(IpAddr, u8)>,
+}
+
+impl VpnOptions {
+    /// Reject a malformed source before any check runs
+    pub fn check(&self) -> Result<()> {
+        for source in &self.ip_sources {
+            let url = reqwest::Url::parse(source).with_context(|| format!("Invalid IP source URL '{}'", source))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                anyhow::bail!("IP source '{}' isn't an http or https URL", source);
+            }
+        }
+        Ok(())
+    }
+
+    fn http_sources(&self) -> Vec<String> {
+        if self.ip_sources.is_empty() {
+            DEFAULT_HTTP_SOURCES.iter().map(|source| source.to_string()).collect()
+        } else {
+            self.ip_sources.clone()
+        }
+    }
+}
+
+/// An exit range from `--expected-exit`: an IPv4 or IPv6 CIDR block, or
+/// one address
+pub fn parse_exit_range(text: &str) -> Result<(IpAddr, u8)> {
+    let range = || -> Result<(IpAddr, u8)> {
+        let (ip, prefix) = text.split_once('/').map_or((text, None), |(ip, prefix)| (ip, Some(prefix)));
+        let ip: IpAddr = ip.parse().context("Invalid IP address")?;
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().context("Invalid prefix length")?,
+            None => max,
+        };
+        if prefix > max {
+            anyhow::bail!("Prefix length must be <= {}", max);
+        }
+        Ok((ip, prefix))
+    };
+    range().with_context(|| format!("Invalid exit range '{}' (expected a CIDR like 198.51.100.0/24 or 2001:db8::/32)", text))
+}
+
+/// Whether `ip` is in the `(network, prefix)` block; never across families
+pub fn in_range(ip: IpAddr, (network, prefix): (IpAddr, u8)) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix.min(32))).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix.min(128))).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Whether `ip` is in one of the exit ranges of its family; None when
+/// there's no range of that family to check against
+pub fn in_exit(ip: IpAddr, ranges: &[(IpAddr, u8)]) -> Option<bool> {
+    let family: Vec<&(IpAddr, u8)> = ranges.iter().filter(|(network, _)| network.is_ipv4() == ip.is_ipv4()).collect();
+    (!family.is_empty()).then(|| family.iter().any(|range| in_range(ip, **range)))
+}
+
+/// `198.51.100.0/24, 2001:db8::/32`
+pub fn format_ranges(ranges: &[(IpAddr, u8)]) -> String {
+    ranges.iter().map(|(network, prefix)| format!("{}/{}", network, prefix)).collect::<Vec<_>>().join(", ")
+}
+
+/// What one source said the public IP is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceAnswer {
+    /// `api.ipify.org`, or `STUN stun.l.google.com:19302`
+    pub source: String,
+    pub answer: Result<IpAddr, String>,
+}
+
+/// Ask every source at once
+pub async fn detect(opts: &VpnOptions) -> Vec<SourceAnswer> {
+    let client = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+        .build();
+    let sources = opts.http_sources();
+    let http = futures::future::join_all(sources.iter().map(|url| async {
+        let answer = match &client {
+            Ok(client) => http_query(client, url).await,
+            Err(e) => Err(anyhow::anyhow!("{}", e)),
+        };
+        SourceAnswer { source: source_label(url), answer: answer.map_err(|e| format!("{:#}", e)) }
+    }));
+    let server = opts.stun_server.as_deref().unwrap_or(DEFAULT_STUN_SERVER);
+    let stun = async {
+        SourceAnswer { source: format!("STUN {}", server), answer: stun_query(server).await.map_err(|e| format!("{:#}", e)) }
+    };
+    let (mut answers, stun) = tokio::join!(http, stun);
+    answers.push(stun);
+    answers
+}
+
+/// The host of a source URL, which is what tells sources apart
+fn source_label(url: &str) -> String {
+    reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_else(|| url.to_string())
+}
+
+async fn http_query(client: &reqwest::Client, url: &str) -> Result<IpAddr> {
+    let body = async { client.get(url).send().await?.error_for_status()?.text().await }
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", innermost(&e)))?;
+    let body = body.trim();
+    body.parse().with_context(|| format!("Answered '{}', not an IP address", body.chars().take(40).collect::<String>()))
+}
+
+/// reqwest nests the reason a request failed several errors deep, repeating
+/// itself on the way
+fn innermost(e: &reqwest::Error) -> String {
+    let mut cause: &dyn std::error::Error = e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+async fn stun_query(server: &str) -> Result<IpAddr> {
+    let addr = tokio::net::lookup_host(server)
+        .await
+        .with_context(|| format!("Failed to resolve {}", server))?
+        .find(SocketAddr::is_ipv4)
+        .with_context(|| format!("{} has no IPv4 address", server))?;
+    let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let transaction: [u8; 12] = rand::random();
+    let request = stun_request(&transaction);
+    let mut buf = [0u8; 576];
+    for _ in 0..STUN_ATTEMPTS {
+        socket.send_to(&request, addr).await?;
+        let deadline = tokio::time::Instant::now() + STUN_TIMEOUT;
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (len, from) = received?;
+            if from == addr {
+                if let Some(mapped) = parse_stun_response(&buf[..len], &transaction) {
+                    return Ok(mapped.ip());
+                }
+            }
+        }
+    }
+    anyhow::bail!("No answer from {}", server)
+}
+
+/// An RFC 5389 binding request: a bare header, no attributes
+pub fn stun_request(transaction: &[u8; 12]) -> [u8; 20] {
+    let mut request = [0u8; 20];
+    request[0..2].copy_from_slice(&STUN_BINDING_REQUEST.to_be_bytes());
+    request[4..8].copy_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(transaction);
+    request
+}
+
+/// The address a binding response to `transaction` says we came from
+///
+/// XOR-MAPPED-ADDRESS is preferred; MAPPED-ADDRESS is what RFC 3489
+/// servers send, and what some NATs rewrite, hence the XOR.
+pub fn parse_stun_response(packet: &[u8], transaction: &[u8; 12]) -> Option<SocketAddr> {
+    let header = packet.get(..20)?;
+    let kind = u16::from_be_bytes([header[0], header[1]]);
+    let len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    if kind != STUN_BINDING_RESPONSE || header[4..8] != STUN_MAGIC_COOKIE.to_be_bytes() || header[8..20] != transaction[..] {
+        return None;
+    }
+    let mut attributes = packet.get(20..20 + len)?;
+    let mut mapped = None;
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let len = usize::from(u16::from_be_bytes([attributes[2], attributes[3]]));
+        let value = attributes.get(4..4 + len)?;
+        match kind {
+            STUN_XOR_MAPPED_ADDRESS => return stun_address(value, Some(transaction)),
+            STUN_MAPPED_ADDRESS => mapped = stun_address(value, None),
+            _ => {}
+        }
+        // Values are padded to a multiple of 4 bytes
+        attributes = attributes.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+    }
+    mapped
+}
+
+/// A (XOR-)MAPPED-ADDRESS value: reserved, family, port, address
+fn stun_address(value: &[u8], xor: Option<&[u8; 12]>) -> Option<SocketAddr> {
+    let cookie = STUN_MAGIC_COOKIE.to_be_bytes();
+    let mut port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]);
+    let mut key = [0u8; 16];
+    if let Some(transaction) = xor {
+        port ^= (STUN_MAGIC_COOKIE >> 16) as u16;
+        key[..4].copy_from_slice(&cookie);
+        key[4..].copy_from_slice(transaction);
+    }
+    let unmask = |bytes: &[u8]| bytes.iter().zip(key).map(|(byte, key)| byte ^ key).collect::<Vec<u8>>();
+    let ip = match value[1] {
+        0x01 => IpAddr::from(<[u8; 4]>::try_from(unmask(value.get(4..8)?)).ok()?),
+        0x02 => IpAddr::from(<[u8; 16]>::try_from(unmask(value.get(4..20)?)).ok()?),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// The different addresses the sources gave, in order
+pub fn addresses(answers: &[SourceAnswer]) -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = answers.iter().filter_map(|answer| answer.answer.clone().ok()).collect();
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+/// The sources that gave `ip`
+pub fn seen_by(answers: &[SourceAnswer], ip: IpAddr) -> Vec<&str> {
+    answers.iter().filter(|answer| answer.answer == Ok(ip)).map(|answer| answer.source.as_str()).collect()
+}
@@ -0,0 +1,272 @@
+// ICMP echo
+// Crafts echo requests through the C core's raw socket and matches replies by
+// id/sequence, including Time Exceeded and Unreachable errors that quote our
+// request. Without CAP_NET_RAW we fall back to the system ping binary
+// (see ping_cmd).
+
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::ffi;
+use super::{ip, ping_cmd};
+
+pub use super::ping_cmd::{parse_ping_time, parse_ping_ttl};
+
+/// TTL used for plain liveness pings
+pub const DEFAULT_TTL: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyKind {
+    EchoReply,
+    TimeExceeded,
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpReply {
+    pub from: Ipv4Addr,
+    pub rtt: Duration,
+    /// TTL of the reply packet, when the probe path can see it
+    pub ttl: Option<u8>,
+    pub kind: ReplyKind,
+}
+
+static NEXT_SEQ: AtomicU16 = AtomicU16::new(1);
+
+/// Round-trip time of an echo to `ip`, or None if it never answered
+pub async fn ping(ip: Ipv4Addr, timeout: Duration) -> Option<Duration> {
+    echo(ip, DEFAULT_TTL, timeout)
+        .await
+        .filter(|reply| reply.kind == ReplyKind::EchoReply)
+        .map(|reply| reply.rtt)
+}
+
+/// Send one echo request with the given TTL and return whatever answered:
+/// the target's echo reply, or the router that dropped it
+pub async fn echo(ip: Ipv4Addr, ttl: u8, timeout: Duration) -> Option<IcmpReply> {
+    tokio::task::spawn_blocking(move || echo_blocking(ip, ttl, timeout))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// `echo` whose request carries the given ICMP checksum, whatever its
+/// sequence number, so ECMP routers hashing on it keep every probe on one
+/// path; the system ping fallback can't do that and sends plain requests
+pub async fn echo_with_checksum(ip: Ipv4Addr, ttl: u8, timeout: Duration, checksum: u16) -> Option<IcmpReply> {
+    tokio::task::spawn_blocking(move || match raw_echo(ip, ttl, timeout, Some(checksum)) {
+        Ok(reply) => reply,
+        Err(()) => external_ping(ip, ttl, timeout),
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+pub fn echo_blocking(ip: Ipv4Addr, ttl: u8, timeout: Duration) -> Option<IcmpReply> {
+    match raw_echo(ip, ttl, timeout, None) {
+        Ok(reply) => reply,
+        Err(()) => external_ping(ip, ttl, timeout),
+    }
+}
+
+/// Whether `echo` gets to use the raw socket rather than the ping binary
+pub fn raw_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        let mut sock: ffi::nw_socket_t = unsafe { std::mem::zeroed() };
+        let status = unsafe {
+            ffi::nw_socket_create(&mut sock, libc::AF_INET, libc::SOCK_RAW, ffi::nw_protocol_t_NW_PROTO_ICMP)
+        };
+        if status != ffi::nw_error_t_NW_SUCCESS {
+            return false;
+        }
+        unsafe { ffi::nw_socket_close(&mut sock) };
+        true
+    })
+}
+
+/// Err means the raw socket couldn't be used at all (no privileges), as
+/// opposed to Ok(None) for a probe that simply went unanswered
+fn raw_echo(ip: Ipv4Addr, ttl: u8, timeout: Duration, checksum: Option<u16>) -> Result<Option<IcmpReply>, ()> {
+    let mut sock: ffi::nw_socket_t = unsafe { std::mem::zeroed() };
+    let status = unsafe {
+        ffi::nw_socket_create(&mut sock, libc::AF_INET, libc::SOCK_RAW, ffi::nw_protocol_t_NW_PROTO_ICMP)
+    };
+    if status != ffi::nw_error_t_NW_SUCCESS {
+        return Err(());
+    }
+    // The header's source is left zero, so the kernel fills in the bound one
+    if super::source::apply_raw(sock.fd).is_err() {
+        unsafe { ffi::nw_socket_close(&mut sock) };
+        return Err(());
+    }
+
+    let id = std::process::id() as u16;
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut packet: Box<ffi::nw_packet_t> = Box::new(unsafe { std::mem::zeroed() });
+
+    let result = unsafe {
+        if ffi::nw_packet_craft_icmp_echo(&mut *packet, u32::from(ip), id, seq) != ffi::nw_error_t_NW_SUCCESS {
+            Ok(None)
+        } else {
+            // IP_HDRINCL: the kernel refills the header checksum on send
+            packet.data[8] = ttl;
+            if let Some(checksum) = checksum {
+                pad_to_checksum(&mut packet, checksum);
+            }
+            let sent_at = Instant::now();
+            if ffi::nw_packet_send_raw(&mut sock, &*packet) == ffi::nw_error_t_NW_SUCCESS {
+                Ok(receive_reply(&mut sock, &mut packet, ip, id, seq, sent_at, timeout))
+            } else {
+                Err(())
+            }
+        }
+    };
+
+    unsafe { ffi::nw_socket_close(&mut sock) };
+    result
+}
+
+/// Append a payload word to a crafted echo request (20-byte IP header, bare
+/// 8-byte ICMP header) that makes `wanted` its checksum
+fn pad_to_checksum(packet: &mut ffi::nw_packet_t, wanted: u16) {
+    packet.data[22..24].fill(0);
+    let pad = ip::checksum_padding(ip::ones_sum(&packet.data[20..28]), wanted);
+    packet.data[28..30].copy_from_slice(&pad.to_be_bytes());
+    packet.length = 30;
+    packet.data[2..4].copy_from_slice(&30u16.to_be_bytes());
+    let checksum = ip::checksum(&packet.data[20..30]);
+    packet.data[22..24].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Raw ICMP sockets see every ICMP packet for the host, so keep reading
+/// until one matches our id/sequence or the deadline passes
+unsafe fn receive_reply(
+    sock: &mut ffi::nw_socket_t,
+    packet: &mut ffi::nw_packet_t,
+    target: Ipv4Addr,
+    id: u16,
+    seq: u16,
+    sent_at: Instant,
+    timeout: Duration,
+) -> Option<IcmpReply> {
+    let deadline = sent_at + timeout;
+    loop {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
+        let wait_ms = (remaining.as_millis() as u32).max(1);
+        if ffi::nw_packet_recv_raw(sock, packet, wait_ms) != ffi::nw_error_t_NW_SUCCESS {
+            return None;
+        }
+
+        let len = packet.length.min(packet.data.len());
+        if let Some((from, ttl, kind)) = parse_reply(&packet.data[..len], id, seq) {
+            if kind != ReplyKind::EchoReply || from == target {
+                return Some(IcmpReply { from, rtt: sent_at.elapsed(), ttl: Some(ttl), kind });
+            }
+        }
+    }
+}
+
+/// Match a raw IPv4 packet against our echo request's id and sequence
+///
+/// Echo replies carry them directly; Time Exceeded and Unreachable
+/// messages quote our original IP header plus the first 8 bytes of ICMP
+pub fn parse_reply(packet: &[u8], id: u16, seq: u16) -> Option<(Ipv4Addr, u8, ReplyKind)> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 || packet[9] != 1 {
+        return None;
+    }
+    let from = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let ttl = packet[8];
+    let icmp = packet.get((packet[0] & 0x0F) as usize * 4..)?;
+    if icmp.len() < 8 {
+        return None;
+    }
+
+    // Our id and sequence sit at bytes 4..8 of the ICMP header
+    let is_ours = |header: &[u8]| {
+        header.len() >= 8
+            && u16::from_be_bytes([header[4], header[5]]) == id
+            && u16::from_be_bytes([header[6], header[7]]) == seq
+    };
+
+    match icmp[0] {
+        0 => is_ours(icmp).then_some((from, ttl, ReplyKind::EchoReply)),
+        3 | 11 => {
+            let error = parse_error(packet)?;
+            let quoted_icmp = error.transport;
+            (error.protocol == 1 && quoted_icmp.first() == Some(&8) && is_ours(quoted_icmp))
+                .then_some((from, ttl, error.kind))
+        }
+        _ => None,
+    }
+}
+
+/// A Time Exceeded or Unreachable message and the datagram it quotes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpError<'a> {
+    pub from: Ipv4Addr,
+    /// TTL of the error packet itself
+    pub ttl: u8,
+    pub kind: ReplyKind,
+    /// ICMP code; 3 under Unreachable is "port unreachable"
+    pub code: u8,
+    /// IP protocol of the quoted datagram
+    pub protocol: u8,
+    /// Where the quoted datagram was headed
+    pub destination: Ipv4Addr,
+    /// The quoted datagram's transport header: at least its first 8 bytes
+    pub transport: &'a [u8],
+}
+
+/// Parse a raw IPv4 packet as an ICMP error quoting one of our datagrams
+pub fn parse_error(packet: &[u8]) -> Option<IcmpError<'_>> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 || packet[9] != 1 {
+        return None;
+    }
+    let icmp = packet.get((packet[0] & 0x0F) as usize * 4..)?;
+    let kind = match icmp.first()? {
+        11 => ReplyKind::TimeExceeded,
+        3 => ReplyKind::Unreachable,
+        _ => return None,
+    };
+    let quoted = icmp.get(8..)?;
+    if quoted.len() < 20 {
+        return None;
+    }
+    let transport = quoted.get((quoted[0] & 0x0F) as usize * 4..)?;
+    if transport.len() < 8 {
+        return None;
+    }
+    Some(IcmpError {
+        from: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+        ttl: packet[8],
+        kind,
+        code: icmp[1],
+        protocol: quoted[9],
+        destination: Ipv4Addr::new(quoted[16], quoted[17], quoted[18], quoted[19]),
+        transport,
+    })
+}
+
+/// Unprivileged fallback: one run of the system ping binary
+fn external_ping(ip: Ipv4Addr, ttl: u8, timeout: Duration) -> Option<IcmpReply> {
+    let start = Instant::now();
+    let output = ping_cmd::command(ip, ttl, timeout).output().ok()?;
+    let elapsed = start.elapsed();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Windows exits 0 for "Destination host unreachable" too, so an error
+    // line wins over the exit status
+    if let Some((from, kind)) = ping_cmd::parse_error(&stdout) {
+        return Some(IcmpReply { from, rtt: elapsed, ttl: None, kind });
+    }
+    output.status.success().then(|| IcmpReply {
+        from: ip,
+        rtt: parse_ping_time(&stdout).unwrap_or(elapsed),
+        ttl: parse_ping_ttl(&stdout),
+        kind: ReplyKind::EchoReply,
+    })
+}
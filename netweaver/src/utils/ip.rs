@@ -20,3 +20,26 @@ pub fn is_loopback(ip: Ipv4Addr) -> bool {
 pub fn is_multicast(ip: Ipv4Addr) -> bool {
     ip.is_multicast()
 }
+
+fn ones_add(a: u16, b: u16) -> u16 {
+    let sum = u32::from(a) + u32::from(b);
+    ((sum & 0xFFFF) + (sum >> 16)) as u16
+}
+
+/// One's complement sum of `data` as big-endian words, an odd last byte padded
+pub fn ones_sum(data: &[u8]) -> u16 {
+    data.chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+        .fold(0, ones_add)
+}
+
+/// Internet checksum (RFC 1071) of `data`
+pub fn checksum(data: &[u8]) -> u16 {
+    !ones_sum(data)
+}
+
+/// Word that, added to a message whose other words sum to `sum` (checksum
+/// field zeroed), makes its checksum come out as `wanted`
+pub fn checksum_padding(sum: u16, wanted: u16) -> u16 {
+    ones_add(!wanted, !sum)
+}
@@ -1,9 +1,15 @@
 use std::net::{IpAddr, Ipv4Addr};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use anyhow::{Result, Context};
 
+pub mod icmp;
 pub mod ip;
 pub mod network;
+pub mod oui;
+pub mod ping_cmd;
+pub mod rate_limit;
+pub mod retry;
+pub mod source;
 
 pub fn get_timestamp_us() -> u64 {
     SystemTime::now()
@@ -25,6 +31,25 @@ pub fn format_bandwidth(bytes_per_sec: f64) -> String {
     format!("{:.2} {}", value, UNITS[unit_idx])
 }
 
+/// `1.21 GB`, in the binary units of `format_bandwidth`
+pub fn format_bytes(bytes: u64) -> String {
+    format_bandwidth(bytes as f64).trim_end_matches("/s").to_string()
+}
+
+/// `941.23 Mbit/s`, decimal units as link speeds are quoted
+pub fn format_bitrate(bits_per_sec: f64) -> String {
+    const UNITS: &[&str] = &["bit/s", "kbit/s", "Mbit/s", "Gbit/s", "Tbit/s"];
+    let mut value = bits_per_sec;
+    let mut unit_idx = 0;
+    
+    while value >= 1000.0 && unit_idx < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit_idx += 1;
+    }
+    
+    format!("{:.2} {}", value, UNITS[unit_idx])
+}
+
 pub fn format_latency(us: f64) -> String {
     if us < 1000.0 {
         format!("{:.2} μs", us)
@@ -35,6 +60,58 @@ pub fn format_latency(us: f64) -> String {
     }
 }
 
+/// Where NetWeaver keeps state between runs: `$XDG_DATA_HOME/netweaver`,
+/// or `~/.local/share/netweaver`
+pub fn data_dir() -> Result<std::path::PathBuf> {
+    let data_home = match std::env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").context("Neither XDG_DATA_HOME nor HOME is set")?;
+            std::path::PathBuf::from(home).join(".local/share")
+        }
+    };
+    Ok(data_home.join("netweaver"))
+}
+
+/// How long ago something happened, coarsely: `just now`, `5m ago`, `3h ago`, `2d ago`
+pub fn format_ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+/// Parse a human-friendly duration such as `30s`, `10m`, `1h` or `500ms`
+/// A bare number is taken as seconds
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    
+    let number: u64 = number.parse()
+        .with_context(|| format!("Invalid duration '{}'", value))?;
+    
+    let seconds = |scale: u64| number.checked_mul(scale)
+        .map(Duration::from_secs)
+        .with_context(|| format!("Invalid duration '{}' (too long)", value));
+    let duration = match unit {
+        "" | "s" => Duration::from_secs(number),
+        "ms" => Duration::from_millis(number),
+        "m" => seconds(60)?,
+        "h" => seconds(3600)?,
+        _ => anyhow::bail!("Invalid duration unit '{}' (expected ms, s, m or h)", unit),
+    };
+    
+    if duration.is_zero() {
+        anyhow::bail!("Duration must be greater than zero");
+    }
+    
+    Ok(duration)
+}
+
 pub fn parse_cidr(cidr: &str) -> Result<(Ipv4Addr, u8)> {
     let parts: Vec<&str> = cidr.split('/').collect();
     if parts.len() != 2 {
@@ -51,18 +128,80 @@ pub fn parse_cidr(cidr: &str) -> Result<(Ipv4Addr, u8)> {
     Ok((ip, prefix))
 }
 
+/// Lazy iterator over the usable host addresses of an IPv4 CIDR block
+///
+/// Network and broadcast addresses are skipped, except in a /31 (an RFC 3021
+/// point-to-point link, both ends usable) and a /32 (the one address).
+///
+/// Yields addresses in ascending order without materializing the whole range,
+/// so even a /8 costs a couple of integers rather than 16 million entries.
+/// Implements `ExactSizeIterator` so callers can still size progress bars up front.
+#[derive(Debug, Clone)]
+pub struct CidrIter {
+    next: u64,
+    end: u64,
+}
+
+impl CidrIter {
+    pub fn new(ip: Ipv4Addr, prefix: u8) -> Self {
+        let prefix = prefix.min(32);
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        let network = (u32::from(ip) & mask) as u64;
+        let broadcast = (u32::from(ip) | !mask) as u64;
+
+        if prefix >= 31 {
+            return Self { next: network, end: broadcast + 1 };
+        }
+        Self {
+            next: network + 1,
+            end: broadcast,
+        }
+    }
+
+    /// First and last address this iterator will still yield, if any
+    pub fn bounds(&self) -> Option<(u32, u32)> {
+        if self.next >= self.end {
+            None
+        } else {
+            Some((self.next as u32, (self.end - 1) as u32))
+        }
+    }
+}
+
+impl Iterator for CidrIter {
+    type Item = Ipv4Addr;
+
+    fn next(&mut self) -> Option<Ipv4Addr> {
+        if self.next >= self.end {
+            return None;
+        }
+        let ip = Ipv4Addr::from(self.next as u32);
+        self.next += 1;
+        Some(ip)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end.saturating_sub(self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for CidrIter {}
+
+pub fn cidr_iter(ip: Ipv4Addr, prefix: u8) -> CidrIter {
+    CidrIter::new(ip, prefix)
+}
+
 pub fn cidr_to_range(ip: Ipv4Addr, prefix: u8) -> Vec<Ipv4Addr> {
-    let ip_num = u32::from(ip);
-    let mask = !0u32 << (32 - prefix);
-    let network = ip_num & mask;
-    let broadcast = network | !mask;
-    
-    (network + 1..broadcast)
-        .map(Ipv4Addr::from)
-        .collect()
+    cidr_iter(ip, prefix).collect()
 }
 
+/// The pinned scan source when there is one, otherwise the address the
+/// system would pick
 pub fn get_local_ip() -> Result<Ipv4Addr> {
+    if let Some(source) = source::current() {
+        return Ok(source.ip);
+    }
     local_ip_address::local_ip()
         .context("Failed to get local IP")
         .and_then(|ip| match ip {
@@ -71,13 +210,106 @@ pub fn get_local_ip() -> Result<Ipv4Addr> {
         })
 }
 
+/// The interface called `name`
+pub fn interface_named(name: &str) -> Result<pnet_datalink::NetworkInterface> {
+    let interfaces = pnet_datalink::interfaces();
+    if let Some(iface) = interfaces.iter().find(|iface| iface.name == name) {
+        return Ok(iface.clone());
+    }
+    let names: Vec<&str> = interfaces.iter().map(|iface| iface.name.as_str()).collect();
+    anyhow::bail!("No interface named '{}' (available: {})", name, names.join(", "))
+}
+
+/// First IPv4 address of the interface called `name`, with its prefix length
+pub fn interface_ipv4(name: &str) -> Result<(Ipv4Addr, u8)> {
+    interface_named(name)?
+        .ips
+        .iter()
+        .find_map(|net| match net.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() => Some((ip, net.prefix())),
+            _ => None,
+        })
+        .with_context(|| format!("Interface {} has no usable IPv4 address", name))
+}
+
+/// Default IPv4 gateway from the system routing table
+#[cfg(target_os = "linux")]
+pub fn get_default_gateway() -> Result<Ipv4Addr> {
+    let table = std::fs::read_to_string("/proc/net/route")
+        .context("Failed to read /proc/net/route")?;
+    parse_proc_net_route(&table).context("No default route in /proc/net/route")
+}
+
+/// Default IPv4 gateway: the next hop of the best route to the internet
+#[cfg(windows)]
+pub fn get_default_gateway() -> Result<Ipv4Addr> {
+    use windows_sys::Win32::Foundation::NO_ERROR;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{GetBestRoute, MIB_IPFORWARDROW};
+
+    // Any public address goes by the default route; nothing is sent to it.
+    // Addresses are in network order, as they sit in memory
+    let destination = u32::from_ne_bytes([8, 8, 8, 8]);
+    let mut row: MIB_IPFORWARDROW = unsafe { std::mem::zeroed() };
+    let status = unsafe { GetBestRoute(destination, 0, &mut row) };
+    if status != NO_ERROR {
+        anyhow::bail!("GetBestRoute failed with error {}", status);
+    }
+    let gateway = Ipv4Addr::from(row.dwForwardNextHop.to_ne_bytes());
+    if gateway.is_unspecified() {
+        anyhow::bail!("No default route");
+    }
+    Ok(gateway)
+}
+
+/// Default IPv4 gateway from the system routing table
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn get_default_gateway() -> Result<Ipv4Addr> {
+    let output = std::process::Command::new("route")
+        .args(["-n", "get", "default"])
+        .output()
+        .context("Failed to run 'route -n get default'")?;
+    parse_route_get(&String::from_utf8_lossy(&output.stdout)).context("No default route")
+}
+
+/// Gateway of the lowest-metric default route in a `/proc/net/route` table
+///
+/// Addresses there are the raw in-memory u32 printed as hex, so they're in
+/// network order only once turned back into native-endian bytes.
+pub fn parse_proc_net_route(table: &str) -> Option<Ipv4Addr> {
+    const RTF_UP: u32 = 0x1;
+    const RTF_GATEWAY: u32 = 0x2;
+
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let hex = |i: usize| fields.get(i).and_then(|f| u32::from_str_radix(f, 16).ok());
+            let (destination, gateway, flags) = (hex(1)?, hex(2)?, hex(3)?);
+            let metric: u32 = fields.get(6)?.parse().ok()?;
+            let is_default = destination == 0 && flags & (RTF_UP | RTF_GATEWAY) == RTF_UP | RTF_GATEWAY;
+            is_default.then(|| (metric, Ipv4Addr::from(gateway.to_ne_bytes())))
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, gateway)| gateway)
+}
+
+/// The `gateway:` line of BSD/macOS `route -n get default` output
+pub fn parse_route_get(output: &str) -> Option<Ipv4Addr> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("gateway:"))
+        .and_then(|gateway| gateway.trim().parse().ok())
+}
+
 pub fn is_privileged() -> bool {
     unsafe { libc::geteuid() == 0 }
 }
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct MacAddress(pub [u8; 6]);
 
 impl MacAddress {
@@ -85,25 +317,59 @@ impl MacAddress {
         Self(bytes)
     }
     
-    pub fn to_string(&self) -> String {
-        format!(
-            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-            self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5]
-        )
+    /// The 24-bit organisationally unique identifier
+    pub fn oui(&self) -> u32 {
+        u32::from_be_bytes([0, self.0[0], self.0[1], self.0[2]])
+    }
+    
+    /// Set on randomized (privacy) and hand-assigned addresses
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & 0x02 != 0
     }
     
     pub fn vendor(&self) -> &'static str {
-        match (self.0[0], self.0[1], self.0[2]) {
-            (0x00, 0x50, 0x56) => "VMware",
-            (0x00, 0x0c, 0x29) => "VMware",
-            (0x08, 0x00, 0x27) => "VirtualBox",
-            (0x52, 0x54, 0x00) => "QEMU/KVM",
-            (0x00, 0x1c, 0x42) => "Parallels",
-            (0xdc, 0xa6, 0x32) => "Raspberry Pi",
-            (0xb8, 0x27, 0xeb) => "Raspberry Pi",
-            (0xf0, 0x18, 0x98) => "Apple",
-            (0x00, 0x1b, 0x63) => "Apple",
-            _ => "Unknown",
+        match oui::lookup(self.oui()) {
+            Some(vendor) => vendor,
+            None if self.is_locally_administered() => "Locally administered (randomized)",
+            None => "Unknown",
         }
     }
+    
+    /// Like `vendor`, but checks the OUI registry at `path` first
+    pub fn vendor_from_db(&self, path: impl AsRef<std::path::Path>) -> Result<String> {
+        let db = oui::OuiDatabase::cached(path)?;
+        Ok(db.lookup(self.oui()).unwrap_or_else(|| self.vendor()).to_string())
+    }
+}
+
+/// Accepts `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`, in either case
+impl std::str::FromStr for MacAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid MAC address '{}' (expected aa:bb:cc:dd:ee:ff)", s);
+        let parts: Vec<&str> = s.trim().split([':', '-']).collect();
+        if parts.len() != 6 {
+            return Err(invalid());
+        }
+
+        let mut bytes = [0u8; 6];
+        for (byte, part) in bytes.iter_mut().zip(&parts) {
+            if part.len() != 2 {
+                return Err(invalid());
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5]
+        )
+    }
 }
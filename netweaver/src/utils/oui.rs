@@ -0,0 +1,83 @@
+// MAC vendor lookup
+// Embedded IEEE OUI table (generated from data/oui.csv by build.rs) plus a
+// loader for a freshly downloaded registry file
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+#[path = "oui_csv.rs"]
+mod oui_csv;
+
+include!(concat!(env!("OUT_DIR"), "/oui_table.rs"));
+
+/// Hypervisor NIC prefixes that are locally administered and so never
+/// appear in the IEEE registry
+const VIRTUAL_NICS: &[(u32, &str)] = &[(0x525400, "QEMU/KVM")];
+
+/// Look up a 24-bit prefix in the embedded table
+pub fn lookup(prefix: u32) -> Option<&'static str> {
+    OUI_TABLE
+        .binary_search_by_key(&prefix, |(p, _)| *p)
+        .ok()
+        .map(|idx| OUI_TABLE[idx].1)
+        .or_else(|| VIRTUAL_NICS.iter().find(|(p, _)| *p == prefix).map(|(_, name)| *name))
+}
+
+/// An OUI registry loaded at runtime, e.g. a current download of
+/// https://standards-oui.ieee.org/oui/oui.csv
+#[derive(Debug, Default)]
+pub struct OuiDatabase {
+    entries: Vec<(u32, String)>,
+}
+
+impl OuiDatabase {
+    pub fn parse(csv: &str) -> Self {
+        let mut entries: Vec<(u32, String)> = csv.lines().filter_map(oui_csv::parse_line).collect();
+        entries.sort_by_key(|(prefix, _)| *prefix);
+        entries.dedup_by_key(|(prefix, _)| *prefix);
+        Self { entries }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let csv = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OUI database {}", path.display()))?;
+        let db = Self::parse(&csv);
+        if db.is_empty() {
+            anyhow::bail!("No OUI entries found in {}", path.display());
+        }
+        Ok(db)
+    }
+
+    /// Load `path` once and share it between lookups
+    pub fn cached(path: impl AsRef<Path>) -> Result<Arc<Self>> {
+        static CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<OuiDatabase>>>> = OnceLock::new();
+
+        let path = path.as_ref();
+        let mut cache = CACHE.get_or_init(Default::default).lock();
+        if let Some(db) = cache.get(path) {
+            return Ok(Arc::clone(db));
+        }
+        let db = Arc::new(Self::load(path)?);
+        cache.insert(path.to_path_buf(), Arc::clone(&db));
+        Ok(db)
+    }
+
+    pub fn lookup(&self, prefix: u32) -> Option<&str> {
+        self.entries
+            .binary_search_by_key(&prefix, |(p, _)| *p)
+            .ok()
+            .map(|idx| self.entries[idx].1.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,59 @@
+// IEEE OUI CSV parsing
+// Shared by build.rs (to embed data/oui.csv) and the runtime loader, so
+// this file must stay dependency-free
+
+/// Parse one line of the IEEE registry CSV
+/// (`Registry,Assignment,Organization Name,Organization Address`)
+/// into a 24-bit prefix and vendor name; the header and MA-M/MA-S
+/// rows yield None
+pub fn parse_line(line: &str) -> Option<(u32, String)> {
+    let fields = split_fields(line);
+    let assignment = fields.get(1)?.trim();
+    let name = fields.get(2)?.trim();
+    if assignment.len() != 6 || name.is_empty() {
+        return None;
+    }
+    let prefix = u32::from_str_radix(assignment, 16).ok()?;
+    Some((prefix, short_name(name)))
+}
+
+/// Drop corporate suffixes so "VMware, Inc." displays as "VMware"
+pub fn short_name(name: &str) -> String {
+    const SUFFIXES: &[&str] = &[
+        "inc", "incorporated", "corporation", "corporate", "corp", "co", "ltd", "co.,ltd",
+        "limited", "llc", "gmbh", "bv", "ag", "sa", "plc",
+    ];
+
+    let mut name = name.trim();
+    loop {
+        name = name.trim_end_matches([' ', ',', '.']);
+        let (rest, last) = name.rsplit_once([' ', ',']).unwrap_or(("", name));
+        let last = last.trim_end_matches('.').to_ascii_lowercase();
+        if rest.is_empty() || !SUFFIXES.contains(&last.as_str()) {
+            return name.to_string();
+        }
+        name = rest;
+    }
+}
+
+/// Split a CSV line, honouring double-quoted fields with embedded commas
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
@@ -0,0 +1,102 @@
+// System ping command line
+// The unprivileged fallback shells out to the platform's ping, whose flags
+// disagree: Linux takes the TTL as -t and the wait in seconds, macOS/BSD use
+// -m for TTL (their -t is a deadline) and -W in milliseconds, and Windows
+// spells everything differently (-n count, -i TTL, -w milliseconds). The
+// argv is built per platform here and the output parsed for all three.
+
+use std::net::Ipv4Addr;
+use std::process::Command;
+use std::time::Duration;
+
+use super::icmp::ReplyKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Linux,
+    /// macOS and the BSDs
+    Bsd,
+    Windows,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(any(target_os = "linux", target_os = "android")) {
+            Platform::Linux
+        } else {
+            Platform::Bsd
+        }
+    }
+}
+
+/// Arguments for a single echo to `ip` with the given TTL and wait
+pub fn args(platform: Platform, ip: Ipv4Addr, ttl: u8, timeout: Duration) -> Vec<String> {
+    let (count, ttl_flag, wait_flag) = match platform {
+        Platform::Linux => ("-c", "-t", "-W"),
+        Platform::Bsd => ("-c", "-m", "-W"),
+        Platform::Windows => ("-n", "-i", "-w"),
+    };
+    let wait = match platform {
+        Platform::Linux => timeout.as_secs().max(1),
+        Platform::Bsd | Platform::Windows => (timeout.as_millis() as u64).max(1),
+    };
+    let mut args = Vec::new();
+    if platform != Platform::Windows {
+        // Keeps "From" lines numeric so the router's address parses
+        args.push("-n".to_string());
+    }
+    args.extend([count.to_string(), "1".to_string()]);
+    args.extend([ttl_flag.to_string(), ttl.to_string()]);
+    args.extend([wait_flag.to_string(), wait.to_string()]);
+    args.push(ip.to_string());
+    args
+}
+
+/// `ping` ready to run for this platform
+pub fn command(ip: Ipv4Addr, ttl: u8, timeout: Duration) -> Command {
+    let mut command = Command::new("ping");
+    command.args(args(Platform::current(), ip, ttl, timeout));
+    command
+}
+
+/// Pull the TTL out of `ping` output ("... ttl=64 time=0.3 ms", "TTL=57")
+pub fn parse_ping_ttl(output: &str) -> Option<u8> {
+    let lower = output.to_ascii_lowercase();
+    let start = lower.find("ttl=")? + 4;
+    let digits: String = lower[start..].chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+/// Pull the round-trip time out of `ping` output ("time=0.318 ms",
+/// "time=12ms"); Windows' "time<1ms" is taken at its bound
+pub fn parse_ping_time(output: &str) -> Option<Duration> {
+    let start = output.find("time=").or_else(|| output.find("time<"))? + 5;
+    let number: String = output[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    number.parse::<f64>().ok().map(|ms| Duration::from_secs_f64(ms / 1000.0))
+}
+
+/// Router that answered with an ICMP error, from whichever form the platform
+/// prints: "From 10.0.0.1 icmp_seq=1 Time to live exceeded" (Linux),
+/// "36 bytes from 10.0.0.1: Time to live exceeded" (BSD) or
+/// "Reply from 10.0.0.1: TTL expired in transit." (Windows)
+pub fn parse_error(output: &str) -> Option<(Ipv4Addr, ReplyKind)> {
+    output.lines().find_map(|line| {
+        let lower = line.to_ascii_lowercase();
+        let kind = if lower.contains("exceeded") || lower.contains("expired") {
+            ReplyKind::TimeExceeded
+        } else if lower.contains("unreachable") {
+            ReplyKind::Unreachable
+        } else {
+            return None;
+        };
+        let from = line
+            .split_whitespace()
+            .find_map(|word| word.trim_matches(|c: char| !c.is_ascii_digit()).parse().ok())?;
+        Some((from, kind))
+    })
+}
@@ -0,0 +1,55 @@
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by every probe task in a scan
+///
+/// Tokens are handed out on a fixed schedule of `1 / rate` seconds with no
+/// burst allowance, so no one-second window ever sees more than `rate`
+/// releases. Each caller reserves the next free slot under the lock and then
+/// sleeps outside it, so waiting tasks never block each other.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+    acquired: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / per_second.max(1),
+            next_slot: Mutex::new(None),
+            acquired: AtomicU64::new(0),
+        }
+    }
+
+    /// Reserve the next token, returning the instant it becomes valid
+    pub fn reserve(&self) -> Instant {
+        let now = Instant::now();
+        let mut next = self.next_slot.lock();
+        let slot = next.map_or(now, |n| n.max(now));
+        *next = Some(slot + self.interval);
+        self.acquired.fetch_add(1, Ordering::Relaxed);
+        slot
+    }
+
+    pub async fn acquire(&self) {
+        let slot = self.reserve();
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    /// Blocking variant for probes running on rayon worker threads
+    pub fn acquire_blocking(&self) {
+        let slot = self.reserve();
+        let now = Instant::now();
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+
+    /// Total tokens handed out so far
+    pub fn acquired(&self) -> u64 {
+        self.acquired.load(Ordering::Relaxed)
+    }
+}
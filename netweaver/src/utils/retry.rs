@@ -0,0 +1,76 @@
+// Retry with backoff
+// Shared by the scanner's liveness and port probes and anything else that
+// sends one-shot probes over lossy links. Each attempt is expected to take
+// its own rate-limiter token, so retries are paced like any other probe.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// Delay before the first retry; doubles for each one after
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Extra attempts after the first
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+/// A probe outcome and how many attempts it took to get it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retried<T> {
+    pub value: T,
+    pub attempts: u32,
+}
+
+impl RetryPolicy {
+    pub const fn new(retries: u32) -> Self {
+        Self { retries, backoff: DEFAULT_BACKOFF }
+    }
+
+    /// A single attempt, no retries
+    pub const fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Wait before retry number `retry` (1-based)
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.backoff.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+
+    /// Run `attempt` until `done` accepts its result or the retries run out
+    pub async fn run<T, F, Fut>(&self, mut attempt: F, done: impl Fn(&T) -> bool) -> Retried<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let mut value = attempt().await;
+        let mut attempts = 1;
+        while !done(&value) && attempts <= self.retries {
+            tokio::time::sleep(self.delay(attempts)).await;
+            value = attempt().await;
+            attempts += 1;
+        }
+        Retried { value, attempts }
+    }
+
+    /// `run` for blocking probes, sleeping the calling thread between attempts
+    pub fn run_blocking<T>(&self, mut attempt: impl FnMut() -> T, done: impl Fn(&T) -> bool) -> Retried<T> {
+        let mut value = attempt();
+        let mut attempts = 1;
+        while !done(&value) && attempts <= self.retries {
+            std::thread::sleep(self.delay(attempts));
+            value = attempt();
+            attempts += 1;
+        }
+        Retried { value, attempts }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
@@ -0,0 +1,102 @@
+// Outgoing source address
+// `scan --interface` / `--source-ip` pin every probe to one local address on
+// multi-homed hosts (VPN up, wired and Wi-Fi both connected). The choice is
+// process-wide, like the output format, so the port scan's connects, the
+// banner and page fetches and the raw ICMP/SYN sockets all pick it up
+// without each of them carrying it around.
+
+use anyhow::{Context, Result};
+use parking_lot::RwLock;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub ip: Ipv4Addr,
+    /// Prefix length of `ip` on its interface
+    pub prefix: u8,
+    /// Set when pinned to an interface, not just an address
+    pub interface: Option<String>,
+}
+
+static SOURCE: RwLock<Option<Source>> = parking_lot::const_rwlock(None);
+
+/// Work out the source from `--interface` and/or `--source-ip`
+pub fn resolve(interface: Option<&str>, source_ip: Option<Ipv4Addr>) -> Result<Option<Source>> {
+    let Some(ip) = source_ip else {
+        return interface
+            .map(|name| {
+                let (ip, prefix) = super::interface_ipv4(name)?;
+                Ok(Source { ip, prefix, interface: Some(name.to_string()) })
+            })
+            .transpose();
+    };
+
+    let (owner, prefix) = owner_of(ip).with_context(|| format!("No local interface has address {}", ip))?;
+    if let Some(name) = interface {
+        if name != owner {
+            anyhow::bail!("Address {} belongs to interface {}, not {}", ip, owner, name);
+        }
+    }
+    Ok(Some(Source { ip, prefix, interface: interface.map(str::to_string) }))
+}
+
+/// The interface that has `ip`, with the address's prefix length
+fn owner_of(ip: Ipv4Addr) -> Option<(String, u8)> {
+    pnet_datalink::interfaces().into_iter().find_map(|iface| {
+        let net = iface.ips.iter().find(|net| net.ip() == IpAddr::V4(ip))?;
+        Some((iface.name.clone(), net.prefix()))
+    })
+}
+
+/// Pin (or with None, unpin) the source of every probe that follows
+pub fn set(source: Option<Source>) {
+    *SOURCE.write() = source;
+}
+
+pub fn current() -> Option<Source> {
+    SOURCE.read().clone()
+}
+
+/// Bind `socket` to the pinned source, if there is one
+pub fn apply(socket: SockRef<'_>) -> io::Result<()> {
+    let Some(source) = current() else {
+        return Ok(());
+    };
+    // Needs CAP_NET_RAW before Linux 5.7; the address bind below still
+    // selects the source, the route just isn't forced through the interface
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(name) = &source.interface {
+        if let Err(e) = socket.bind_device(Some(name.as_bytes())) {
+            tracing::debug!("Could not bind to device {}: {}", name, e);
+        }
+    }
+    socket.bind(&SocketAddr::from((source.ip, 0)).into())
+}
+
+/// `apply` for a raw socket the C core opened
+pub fn apply_raw(fd: std::os::fd::RawFd) -> io::Result<()> {
+    // The caller keeps `fd` open for as long as it uses the socket
+    let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+    apply(SockRef::from(&fd))
+}
+
+/// `TcpStream::connect_timeout`, from the pinned source
+pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<std::net::TcpStream> {
+    let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, Some(Protocol::TCP))?;
+    apply(SockRef::from(&socket))?;
+    socket.connect_timeout(&(*addr).into(), timeout)?;
+    Ok(socket.into())
+}
+
+/// `tokio::net::TcpStream::connect`, from the pinned source
+pub async fn connect(addr: SocketAddr) -> io::Result<tokio::net::TcpStream> {
+    let socket = match addr {
+        SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+    };
+    apply(SockRef::from(&socket))?;
+    socket.connect(addr).await
+}
@@ -2,7 +2,6 @@
 // Tests the complete workflow of the network intelligence framework
 
 use netweaver_lib::ffi;
-use std::net::Ipv4Addr;
 
 #[test]
 fn test_nw_init_and_cleanup() {
@@ -182,7 +181,7 @@ fn test_packet_validation() {
         assert!(is_valid);
         
         // Test with invalid packet (zero length)
-        let mut invalid_packet: ffi::nw_packet_t = unsafe { std::mem::zeroed() };
+        let mut invalid_packet: ffi::nw_packet_t = std::mem::zeroed();
         invalid_packet.length = 0;
         let is_valid = ffi::nw_packet_validate(&invalid_packet);
         assert!(!is_valid);
@@ -190,7 +189,7 @@ fn test_packet_validation() {
 }
 
 mod utils_tests {
-    use netweaver_lib::utils;
+    use netweaver_lib::utils::{self, MacAddress};
     use std::net::Ipv4Addr;
     
     #[test]
@@ -215,6 +214,129 @@ mod utils_tests {
         assert_eq!(range[1], "192.168.1.2".parse::<Ipv4Addr>().unwrap());
     }
     
+    #[test]
+    fn test_cidr_edge_prefixes() {
+        let ip = |s: &str| s.parse::<Ipv4Addr>().unwrap();
+
+        assert_eq!(utils::cidr_to_range(ip("10.0.0.5"), 32), vec![ip("10.0.0.5")]);
+        assert_eq!(utils::cidr_to_range(ip("10.0.0.5"), 31), vec![ip("10.0.0.4"), ip("10.0.0.5")]);
+        assert_eq!(utils::cidr_to_range(ip("10.0.0.5"), 30), vec![ip("10.0.0.5"), ip("10.0.0.6")]);
+
+        let everything = utils::cidr_iter(ip("192.168.1.1"), 0);
+        assert_eq!(everything.len(), u32::MAX as usize - 1);
+        assert_eq!(everything.bounds(), Some((1, u32::MAX - 1)));
+
+        assert_eq!(netweaver_lib::scanner::targets::RangeSet::from_cidr(ip("10.0.0.5"), 32).len(), 1);
+    }
+
+    #[test]
+    fn test_cidr_iter_is_lazy() {
+        let ip = "10.0.0.0".parse::<Ipv4Addr>().unwrap();
+        let mut hosts = utils::cidr_iter(ip, 8);
+        
+        // A /8 has 2^24 addresses minus network and broadcast
+        assert_eq!(hosts.len(), (1 << 24) - 2);
+        assert_eq!(hosts.next(), Some("10.0.0.1".parse::<Ipv4Addr>().unwrap()));
+        assert_eq!(hosts.len(), (1 << 24) - 3);
+        
+        let small = "192.168.1.0".parse::<Ipv4Addr>().unwrap();
+        assert_eq!(utils::cidr_iter(small, 28).collect::<Vec<_>>(), utils::cidr_to_range(small, 28));
+    }
+    
+    #[test]
+    fn test_default_gateway_parsing() {
+        let proc_net_route = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0100A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+";
+        let expected = if cfg!(target_endian = "little") { "192.168.1.1" } else { "1.1.168.192" };
+        assert_eq!(utils::parse_proc_net_route(proc_net_route), Some(expected.parse().unwrap()));
+
+        // A subnet route alone isn't a default route
+        let no_default = "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\n\
+                          eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\n";
+        assert_eq!(utils::parse_proc_net_route(no_default), None);
+
+        let route_get = "   route to: default\ndestination: default\n       mask: default\n    gateway: 10.0.0.1\n  interface: en0\n";
+        assert_eq!(utils::parse_route_get(route_get), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(utils::parse_route_get("route: writing to routing socket: not in table\n"), None);
+    }
+
+    #[test]
+    fn test_source_interface_lookup() {
+        use netweaver_lib::utils::source;
+
+        let loopback = Ipv4Addr::LOCALHOST;
+        let by_ip = source::resolve(None, Some(loopback)).unwrap().unwrap();
+        assert_eq!((by_ip.ip, by_ip.prefix, by_ip.interface), (loopback, 8, None));
+
+        let name = netweaver_lib::scanner::arp::interface_for(loopback).unwrap().name;
+        assert_eq!(utils::interface_ipv4(&name).unwrap(), (loopback, 8));
+        let by_name = source::resolve(Some(&name), None).unwrap().unwrap();
+        assert_eq!(by_name.interface.as_deref(), Some(name.as_str()));
+        assert_eq!(source::resolve(None, None).unwrap(), None);
+
+        let missing = utils::interface_ipv4("nw-no-such-if0").unwrap_err().to_string();
+        assert!(missing.contains("No interface named 'nw-no-such-if0'"), "{}", missing);
+        assert!(source::resolve(None, Some(Ipv4Addr::new(192, 0, 2, 254))).is_err());
+        assert!(source::resolve(Some("nw-no-such-if0"), Some(loopback)).is_err());
+    }
+
+    #[test]
+    fn test_mac_vendor_lookup() {
+        use netweaver_lib::utils::oui::OuiDatabase;
+        
+        assert_eq!(MacAddress::new([0x00, 0x50, 0x56, 0x01, 0x02, 0x03]).vendor(), "VMware");
+        assert_eq!(MacAddress::new([0xb8, 0x27, 0xeb, 0x01, 0x02, 0x03]).vendor(), "Raspberry Pi Foundation");
+        assert_eq!(MacAddress::new([0x50, 0xc7, 0xbf, 0x01, 0x02, 0x03]).vendor(), "TP-LINK TECHNOLOGIES");
+        assert_eq!(MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]).vendor(), "QEMU/KVM");
+        assert_eq!(MacAddress::new([0xda, 0xa1, 0x19, 0x00, 0x00, 0x01]).vendor(), "Locally administered (randomized)");
+        assert_eq!(MacAddress::new([0xfc, 0xff, 0xff, 0x00, 0x00, 0x01]).vendor(), "Unknown");
+        
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        use std::io::Write;
+        writeln!(file, "Registry,Assignment,Organization Name,Organization Address").unwrap();
+        writeln!(file, "MA-L,FCFFFF,\"Example Widgets, Inc.\",\"1 Main St, Springfield\"").unwrap();
+        
+        let db = OuiDatabase::load(file.path()).unwrap();
+        assert_eq!(db.len(), 1);
+        let mac = MacAddress::new([0xfc, 0xff, 0xff, 0x00, 0x00, 0x01]);
+        assert_eq!(mac.vendor_from_db(file.path()).unwrap(), "Example Widgets");
+        // Prefixes missing from the file fall back to the embedded table
+        let vmware = MacAddress::new([0x00, 0x50, 0x56, 0x01, 0x02, 0x03]);
+        assert_eq!(vmware.vendor_from_db(file.path()).unwrap(), "VMware");
+    }
+    
+    #[test]
+    fn test_duration_parsing() {
+        use std::time::Duration;
+        
+        assert_eq!(utils::parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(utils::parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(utils::parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(utils::parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(utils::parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        
+        assert!(utils::parse_duration("0s").is_err());
+        assert!(utils::parse_duration("10d").is_err());
+        assert!(utils::parse_duration("m").is_err());
+        // Would overflow u64 seconds once scaled
+        assert!(utils::parse_duration(&format!("{}h", u64::MAX / 60)).is_err());
+        assert!(utils::parse_duration(&format!("{}m", u64::MAX)).is_err());
+    }
+    
+    #[test]
+    fn test_ago_formatting() {
+        use std::time::Duration;
+        
+        assert_eq!(utils::format_ago(Duration::from_secs(5)), "just now");
+        assert_eq!(utils::format_ago(Duration::from_secs(5 * 60 + 59)), "5m ago");
+        assert_eq!(utils::format_ago(Duration::from_secs(3 * 3600)), "3h ago");
+        assert_eq!(utils::format_ago(Duration::from_secs(2 * 86_400 + 10)), "2d ago");
+    }
+    
     #[test]
     fn test_bandwidth_formatting() {
         assert_eq!(utils::format_bandwidth(100.0), "100.00 B/s");
@@ -232,107 +354,4580 @@ mod utils_tests {
         let mac2 = utils::MacAddress::new([0xf0, 0x18, 0x98, 0x12, 0x34, 0x56]);
         assert_eq!(mac2.vendor(), "Apple");
     }
+    
+    #[test]
+    fn test_mac_address_parsing() {
+        let expected = MacAddress::new([0x00, 0x50, 0x56, 0xc0, 0x00, 0x08]);
+        assert_eq!("00:50:56:c0:00:08".parse::<MacAddress>().unwrap(), expected);
+        assert_eq!("00-50-56-C0-00-08".parse::<MacAddress>().unwrap(), expected);
+        
+        for bad in ["00:50:56:c0:00", "00:50:56:c0:00:08:01", "0:50:56:c0:00:08", "00:50:56:c0:00:zz", ""] {
+            assert!(bad.parse::<MacAddress>().is_err(), "{:?} should not parse", bad);
+        }
+    }
 }
 
-mod analytics_tests {
-    use netweaver_lib::analytics::{LatencyAnalyzer, BandwidthAnalyzer, PacketLossDetector};
+mod scanner_tests {
+    use netweaver_lib::scanner::checkpoint::{Checkpoint, CheckpointTracker};
+    use netweaver_lib::scanner::fingerprint::{self, TcpSignature};
+    use netweaver_lib::scanner::targets::{self, ExcludeList, RangeSet};
+    use netweaver_lib::scanner::{diff, topology, Device, ProbeTiming, ScanEvent, ScanResult};
+    use netweaver_lib::output::{self, Format};
+    use netweaver_lib::utils;
+    use std::net::Ipv4Addr;
     use std::time::Duration;
     
     #[test]
-    fn test_latency_analyzer() {
-        let mut analyzer = LatencyAnalyzer::new(100);
+    fn test_exclude_list() {
+        let excludes = ExcludeList::parse("192.168.1.10, 192.168.1.16/29,192.168.1.20,10.0.0.0/8").unwrap();
         
-        // Add some samples
-        analyzer.add_sample(10.0);
-        analyzer.add_sample(15.0);
-        analyzer.add_sample(12.0);
-        analyzer.add_sample(20.0);
+        assert!(excludes.contains("192.168.1.10".parse().unwrap()));
+        assert!(excludes.contains("192.168.1.23".parse().unwrap()));
+        assert!(!excludes.contains("192.168.1.24".parse().unwrap()));
+        assert!(excludes.contains("10.1.2.3".parse().unwrap()));
         
-        assert_eq!(analyzer.average(), 14.25);
-        assert_eq!(analyzer.min(), 10.0);
-        assert_eq!(analyzer.max(), 20.0);
+        // .20 overlaps the /29 and 10.0.0.0/8 lies outside the target, so 1 + 8
+        let hosts = RangeSet::from_cidr("192.168.1.0".parse::<Ipv4Addr>().unwrap(), 24);
+        assert_eq!(excludes.count_in(&hosts), 9);
+        assert_eq!(hosts.iter().filter(|ip| excludes.contains(*ip)).count(), 9);
         
-        // Jitter should be non-zero with varying samples
-        assert!(analyzer.jitter() > 0.0);
+        assert!(ExcludeList::parse("192.168.1.300").is_err());
     }
     
     #[test]
-    fn test_latency_anomaly_detection() {
-        let mut analyzer = LatencyAnalyzer::new(100);
+    fn test_range_set_merges_and_dedupes() {
+        let mut set = RangeSet::from_cidr("10.0.0.0".parse().unwrap(), 30);
+        set.union(&RangeSet::single("10.0.0.2".parse().unwrap()));
+        set.union(&RangeSet::single("10.0.0.3".parse().unwrap()));
+        set.union(&RangeSet::single("10.0.5.1".parse().unwrap()));
         
-        // Add consistent samples
-        for _ in 0..10 {
-            analyzer.add_sample(10.0);
+        let hosts: Vec<String> = set.iter().map(|ip| ip.to_string()).collect();
+        assert_eq!(hosts, vec!["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.5.1"]);
+        assert_eq!(set.len(), 4);
+        
+        let other = RangeSet::from_cidr("10.0.0.0".parse().unwrap(), 24);
+        assert_eq!(set.overlap(&other), 3);
+    }
+    
+    #[test]
+    fn test_shuffled_host_order() {
+        use std::collections::HashSet;
+        
+        let mut set = RangeSet::from_cidr("10.0.0.0".parse().unwrap(), 24);
+        set.union(&RangeSet::from_cidr("10.0.5.0".parse().unwrap(), 29));
+        assert_eq!(set.nth(0), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(set.nth(254), Some("10.0.5.1".parse().unwrap()));
+        assert_eq!(set.nth(set.len()), None);
+        
+        let shuffled: Vec<Ipv4Addr> = set.shuffled(42).collect();
+        assert_eq!(shuffled.len(), set.len());
+        assert_eq!(shuffled.iter().collect::<HashSet<_>>().len(), set.len());
+        assert!(shuffled.iter().all(|ip| set.contains(*ip)));
+        assert_ne!(shuffled, set.iter().collect::<Vec<_>>());
+        
+        // Same seed, same order; another seed, another order
+        assert_eq!(set.shuffled(42).collect::<Vec<_>>(), shuffled);
+        assert_ne!(set.shuffled(43).collect::<Vec<_>>(), shuffled);
+        
+        for len in [0u64, 1, 2, 5, 64, 1000] {
+            let mut seen: Vec<u64> = targets::Permutation::new(len, 7).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..len).collect::<Vec<_>>());
         }
+    }
+    
+    #[tokio::test]
+    async fn test_target_list() {
+        let groups = targets::parse_target_list("192.168.1.0/30, 10.0.5.0/29,192.168.1.2").await.unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[1].label, "10.0.5.0/29");
+        assert_eq!(groups[1].hosts.len(), 6);
         
-        // No anomaly with consistent data
-        assert!(!analyzer.detect_anomaly(3.0));
+        let mut all = RangeSet::new();
+        for group in &groups {
+            all.union(&group.hosts);
+        }
+        // 192.168.1.2 is already covered by the /30
+        assert_eq!(all.len(), 8);
         
-        // Add a spike
-        analyzer.add_sample(100.0);
+        let err = format!("{:#}", targets::parse_target_list("10.0.0.0/24,10.0.1.0/40").await.unwrap_err());
+        assert!(err.contains("'10.0.1.0/40'"), "{}", err);
+    }
+    
+    #[tokio::test]
+    async fn test_hostname_targets() {
+        use netweaver_lib::error::NetweaverError;
         
-        // Should detect anomaly
-        assert!(analyzer.detect_anomaly(3.0));
+        // IP literals never touch DNS
+        assert_eq!(utils::network::resolve_hostname_all("10.1.2.3").await.unwrap(), vec![Ipv4Addr::new(10, 1, 2, 3)]);
+        
+        let err = targets::resolve_target("fileserver/40").await.unwrap_err();
+        assert!(err.to_string().contains("/40"), "{}", err);
+        
+        let err = targets::resolve_target("no-such-host.invalid").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetweaverError>(),
+            Some(NetweaverError::DnsResolutionFailed { hostname, .. }) if hostname == "no-such-host.invalid"
+        ));
     }
     
     #[test]
-    fn test_bandwidth_analyzer() {
-        let mut analyzer = BandwidthAnalyzer::new(Duration::from_secs(1));
+    fn test_topology_groups_by_source_range() {
+        let mut a = device("192.168.1.20", None, 1.0);
+        a.source_range = Some("192.168.1.0/24".to_string());
+        let mut b = device("10.0.5.3", None, 1.0);
+        b.source_range = Some("10.0.5.0/28".to_string());
         
-        analyzer.add_measurement(1000);
-        std::thread::sleep(Duration::from_millis(100));
-        analyzer.add_measurement(2000);
+        let dot = topology::to_dot(&scan_result(vec![a, b]), None);
+        assert!(dot.contains("subgraph cluster_0 {"));
+        assert!(dot.contains("label=\"10.0.5.0/28\";"));
+    }
+    
+    #[tokio::test]
+    async fn test_target_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scope.txt");
+        std::fs::write(&path, "# engagement scope\n10.0.0.1\n\n10.0.0.0/29  # web tier\n10.0.0.3\n172.16.0.9\n").unwrap();
         
-        let bandwidth = analyzer.current_bandwidth();
-        // Should have some measured bandwidth
-        assert!(bandwidth > 0.0);
+        let set = targets::load_target_file(path.to_str().unwrap()).await.unwrap();
+        assert_eq!(set.len(), 7);
+        assert!(set.contains("172.16.0.9".parse().unwrap()));
+        
+        std::fs::write(&path, "10.0.0.1\n10.0.0.0/33\n10.0.0.2\n10.0.0.5/abc\n").unwrap();
+        let err = format!("{:#}", targets::load_target_file(path.to_str().unwrap()).await.unwrap_err());
+        assert!(err.contains(":2:"), "{}", err);
+        assert!(err.contains(":4:"), "{}", err);
+    }
+    
+    fn device(ip: &str, os: Option<&str>, latency_ms: f64) -> Device {
+        Device {
+            ip: ip.parse().unwrap(),
+            mac: None,
+            hostname: None,
+            hostname_source: None,
+            open_ports: vec![22],
+            ports: Vec::new(),
+            os_guess: os.map(str::to_string),
+            os_confidence: 0.0,
+            latency_ms: Some(latency_ms),
+            attempts: 1,
+            vendor: None,
+            last_seen: 0,
+            source_range: None,
+            snmp_name: None,
+            snmp_descr: None,
+            discovery_method: None,
+            ssdp: None,
+            tls: Vec::new(),
+            http: Vec::new(),
+            services: Vec::new(),
+            is_gateway: false,
+        }
+    }
+    
+    fn scan_result(devices: Vec<Device>) -> ScanResult {
+        ScanResult {
+            responsive_hosts: devices.len(),
+            devices,
+            scan_duration: Duration::from_secs(1),
+            network_range: "192.168.1.0/24".to_string(),
+            total_hosts: 254,
+            excluded_hosts: 0,
+            diff: None,
+            probe_rate: None,
+            conflicts: Vec::new(),
+            gateway: None,
+        }
     }
     
     #[test]
-    fn test_packet_loss_detector() {
-        let mut detector = PacketLossDetector::new();
+    fn test_inventory_tracks_devices_across_scans() {
+        use netweaver_lib::scanner::inventory::{Attribute, Inventory, RecordSummary};
+        use netweaver_lib::scanner::{PortResult, PortState};
+        use netweaver_lib::utils::MacAddress;
         
-        detector.record_sent(100);
-        detector.record_received(95);
+        let mac = MacAddress::new([0xaa, 0xbb, 0xcc, 0x00, 0x00, 0x01]);
+        let mut laptop = device("192.168.1.20", Some("Linux"), 1.0);
+        laptop.mac = Some(mac);
+        laptop.last_seen = 1_000;
+        let mut printer = device("192.168.1.30", None, 1.0);
+        printer.last_seen = 1_000;
         
-        // 5% loss
-        assert_eq!(detector.loss_rate(), 5.0);
+        let mut inventory = Inventory::default();
+        assert_eq!(inventory.record(&[laptop.clone(), printer.clone()]), RecordSummary { new: 2, known: 0 });
         
-        detector.reset();
-        assert_eq!(detector.loss_rate(), 0.0);
+        // The laptop picked up a new lease and opened a port; the printer is
+        // still there with no MAC resolved
+        laptop.ip = "192.168.1.21".parse().unwrap();
+        laptop.open_ports = vec![22, 80];
+        laptop.ports = [22, 80].map(|port| PortResult { port, state: PortState::Open, latency_ms: None }).to_vec();
+        laptop.last_seen = 2_000;
+        printer.last_seen = 2_000;
+        assert_eq!(inventory.record(&[laptop.clone(), printer]), RecordSummary { new: 0, known: 2 });
+        
+        // A ping sweep probes no ports, so it mustn't look like they all closed
+        laptop.open_ports.clear();
+        laptop.ports.clear();
+        inventory.record(&[laptop]);
+        
+        assert_eq!(inventory.devices.len(), 2);
+        let entry = inventory.devices.iter().find(|e| e.mac == Some(mac)).unwrap();
+        assert_eq!((entry.first_seen, entry.last_seen), (1_000, 2_000));
+        assert_eq!(entry.ip.to_string(), "192.168.1.21");
+        let changed: Vec<_> = entry.history.iter().map(|c| c.attribute).collect();
+        assert_eq!(changed, [Attribute::Ip, Attribute::Ports]);
+        assert_eq!(entry.history[1].to.as_deref(), Some("22,80"));
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netweaver").join("inventory.db");
+        assert!(Inventory::load(&path).unwrap().devices.is_empty());
+        inventory.save(&path).unwrap();
+        assert_eq!(Inventory::load(&path).unwrap().devices, inventory.devices);
     }
-}
+    
+    #[test]
+    fn test_wake_on_lan_magic_packet() {
+        use netweaver_lib::scanner::wake;
+        use netweaver_lib::utils::MacAddress;
+        
+        let mac = MacAddress::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let packet = wake::magic_packet(mac);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(packet[..6], [0xFF; 6]);
+        for copy in packet[6..].chunks(6) {
+            assert_eq!(copy, mac.0);
+        }
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.json");
+        let mut host = device("192.168.1.20", None, 1.0);
+        host.mac = Some(mac);
+        std::fs::write(&path, serde_json::to_string(&scan_result(vec![host])).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+        
+        assert_eq!(wake::resolve_mac("00:11:22:33:44:55", None).unwrap(), mac);
+        assert_eq!(wake::resolve_mac("192.168.1.20", Some(path)).unwrap(), mac);
+        assert!(wake::resolve_mac("192.168.1.99", Some(path)).is_err());
+    }
+    
+    #[test]
+    fn test_scan_profiles() {
+        use netweaver_lib::scanner::profile;
 
-mod network_utils_tests {
-    use netweaver_lib::utils::network;
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        for name in profile::BUILTIN {
+            assert_eq!(profile::load(name, &missing).unwrap(), profile::builtin(name).unwrap());
+        }
+        assert!(utils::network::top_ports(profile::builtin("thorough").unwrap().top_ports.unwrap()).is_ok());
+
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "\
+[profiles.after-hours]
+top-ports = 100
+max-rate = 50
+resolve = true
+service-detect = true
+
+[profiles.quick]
+ports = \"22,80,443\"
+").unwrap();
+
+        let after_hours = profile::load("after-hours", &path).unwrap();
+        assert_eq!(after_hours.top_ports, Some(100));
+        assert_eq!(after_hours.max_rate, Some(50));
+        assert_eq!((after_hours.names, after_hours.services, after_hours.tls_info), (Some(true), Some(true), None));
+        assert_eq!(profile::load("quick", &path).unwrap().ports.as_deref(), Some("22,80,443"));
+        assert_eq!(profile::load("thorough", &path).unwrap(), profile::builtin("thorough").unwrap());
+
+        let unknown = profile::load("nightly", &path).unwrap_err().to_string();
+        assert!(unknown.contains("Unknown profile 'nightly'"), "{}", unknown);
+        assert!(unknown.contains("after-hours"), "{}", unknown);
+
+        std::fs::write(&path, "[profiles.typo]\nthread = 4\n").unwrap();
+        assert!(profile::load("typo", &path).is_err());
+    }
+
+    #[test]
+    fn test_topology_dot_export() {
+        let result = scan_result(vec![
+            device("192.168.1.1", Some("Local Router/Switch"), 0.5),
+            device("192.168.1.20", Some("Linux"), 2.0),
+            device("192.168.1.30", None, 40.0),
+        ]);
+        
+        let dot = topology::to_dot(&result, Some("192.168.1.1".parse().unwrap()));
+        assert!(dot.starts_with("digraph netweaver {"));
+        assert!(dot.contains("\"192.168.1.1\" [label=\"192.168.1.1\\n[Local Router/Switch]\\n(gateway)\""));
+        assert!(dot.contains("\"192.168.1.20\" [label=\"192.168.1.20\\n[Linux]\", fillcolor=palegreen];"));
+        assert!(dot.contains("\"192.168.1.1\" -> \"192.168.1.30\" [label=\"40.0ms\", weight=3];"));
+        assert!(!dot.contains("\"192.168.1.1\" -> \"192.168.1.1\""));
+        
+        // Without a gateway only nodes are emitted
+        let dot = topology::to_dot(&result, None);
+        assert!(!dot.contains("->"));
+    }
     
     #[test]
-    fn test_port_list_parsing() {
-        let ports = network::parse_port_list("80,443,8080-8083");
-        assert_eq!(ports.len(), 6);
-        assert!(ports.contains(&80));
-        assert!(ports.contains(&443));
-        assert!(ports.contains(&8080));
-        assert!(ports.contains(&8081));
-        assert!(ports.contains(&8082));
-        assert!(ports.contains(&8083));
+    fn test_topology_from_traced_routes() {
+        use topology::RouteTree;
+        
+        let ip = |s: &str| s.parse::<Ipv4Addr>().unwrap();
+        let result = scan_result(vec![
+            device("192.168.1.1", None, 0.5),
+            device("192.168.1.20", None, 2.0),
+            device("10.8.0.5", None, 30.0),
+            device("10.8.0.6", None, 31.0),
+            device("172.16.4.2", None, 45.0),
+        ]);
+        // Two hosts behind the VPN router, one further out past a silent hop
+        let tree = RouteTree::build(&[
+            (ip("192.168.1.20"), vec![]),
+            (ip("192.168.1.1"), vec![]),
+            (ip("10.8.0.6"), vec![Some(ip("192.168.1.1")), Some(ip("10.8.0.1"))]),
+            (ip("10.8.0.5"), vec![Some(ip("192.168.1.1")), Some(ip("10.8.0.1"))]),
+            (ip("172.16.4.2"), vec![Some(ip("192.168.1.1")), None, Some(ip("172.16.4.1"))]),
+        ]);
+        assert_eq!(tree.depth(), 3);
+        
+        let lines = tree.render(&result, Some(ip("192.168.1.1")), None);
+        assert_eq!(lines, [
+            "[This host]",
+            "├── [192.168.1.1] (gateway) (0.5ms)",
+            "│   ├── [* no reply]",
+            "│   │   └── [172.16.4.1]",
+            "│   │       └── 172.16.4.2  45.0ms  ports: 22",
+            "│   └── [10.8.0.1]",
+            "│       ├── 10.8.0.5  30.0ms  ports: 22",
+            "│       └── 10.8.0.6  31.0ms  ports: 22",
+            "└── 192.168.1.20  2.0ms  ports: 22",
+        ]);
+        
+        let dot = topology::to_dot_routed(&result, Some(ip("192.168.1.1")), Some(&tree));
+        assert!(dot.contains("\"local\" -> \"192.168.1.1\";"));
+        assert!(dot.contains("\"192.168.1.1\" -> \"10.8.0.1\";"));
+        assert!(dot.contains("\"10.8.0.1\" [label=\"10.8.0.1\", shape=diamond"));
+        assert!(dot.contains("\"10.8.0.1\" -> \"10.8.0.5\" [label=\"30.0ms\"];"));
+        assert!(dot.contains("\"local\" -> \"192.168.1.20\" [label=\"2.0ms\"];"));
+        // The gateway is drawn as a router, not also as a leaf
+        assert!(!dot.contains("\"local\" -> \"192.168.1.1\" [label"));
     }
     
     #[test]
-    fn test_port_in_range() {
-        assert!(network::is_port_in_range(8080, "8080-8090"));
-        assert!(network::is_port_in_range(8085, "8080-8090"));
-        assert!(!network::is_port_in_range(9000, "8080-8090"));
+    fn test_topology_groups_by_subnet() {
+        use topology::RouteTree;
         
-        assert!(network::is_port_in_range(80, "80"));
-        assert!(!network::is_port_in_range(443, "80"));
+        let mut devices = vec![
+            device("192.168.2.7", None, 12.5),
+            device("192.168.1.100", None, 1.0),
+            device("192.168.1.9", None, 0.8),
+        ];
+        devices.extend((20..25).map(|host| device(&format!("192.168.1.{}", host), None, 2.0)));
+        devices[1].open_ports = vec![22, 80, 443, 8080];
+        let result = scan_result(devices);
+        
+        // All on our segment, discovered in no particular order
+        let paths: Vec<_> = result.devices.iter().map(|d| (d.ip, Vec::new())).collect();
+        let tree = RouteTree::build(&paths);
+        
+        let lines = tree.render(&result, None, Some(3));
+        assert_eq!(lines, [
+            "[This host]",
+            "├── 192.168.1.0/24 (7 devices)",
+            "│   ├── 192.168.1.9   0.8ms  ports: 22",
+            "│   ├── 192.168.1.20  2.0ms  ports: 22",
+            "│   ├── 192.168.1.21  2.0ms  ports: 22",
+            "│   └── … and 4 more",
+            "└── 192.168.2.0/24 (1 device)",
+            "    └── 192.168.2.7  12.5ms  ports: 22",
+        ]);
+        
+        let full = tree.render(&result, None, None);
+        assert!(full.contains(&"│   └── 192.168.1.100  1.0ms  ports: 22,80,443,…".to_string()));
+        
+        let dot = topology::to_dot(&result, None);
+        assert!(dot.contains("label=\"192.168.1.0/24\";"));
+        assert!(dot.contains("label=\"192.168.2.0/24\";"));
+        
+        // One subnet needs no clusters
+        let lan = scan_result(vec![device("10.0.0.2", None, 1.0), device("10.0.0.3", None, 1.0)]);
+        assert!(!topology::to_dot(&lan, None).contains("subgraph"));
     }
     
     #[test]
-    fn test_common_ports() {
-        assert!(network::COMMON_PORTS.contains(&80));
-        assert!(network::COMMON_PORTS.contains(&443));
-        assert!(network::COMMON_PORTS.contains(&22));
-        assert!(network::COMMON_PORTS.contains(&3306));
+    fn test_os_fingerprint() {
+        assert_eq!(fingerprint::parse_ping_ttl("64 bytes from 10.0.0.1: icmp_seq=1 ttl=117 time=9.8 ms"), Some(117));
+        assert_eq!(fingerprint::parse_ping_ttl("Request timeout"), None);
+        assert_eq!(fingerprint::initial_ttl(117), 128);
+        assert_eq!(fingerprint::initial_ttl(61), 64);
+        assert_eq!(fingerprint::initial_ttl(250), 255);
+        
+        // A Linux box running xrdp must not be reported as Windows
+        let guess = fingerprint::infer(Some(63), None, &[22, 3389], Some(1.0)).unwrap();
+        assert_eq!(guess.name, "Linux");
+        
+        let linux_syn_ack = TcpSignature {
+            ttl: 64,
+            window: 65160,
+            mss: Some(1460),
+            window_scale: Some(7),
+            sack_permitted: true,
+            timestamps: true,
+        };
+        let guess = fingerprint::infer(None, Some(&linux_syn_ack), &[22], Some(0.5)).unwrap();
+        assert_eq!(guess.name, "Linux");
+        assert!(guess.confidence >= 0.85);
+        
+        // Ports alone only give a low-confidence guess
+        let guess = fingerprint::infer(None, None, &[3389], Some(5.0)).unwrap();
+        assert_eq!(guess.name, "Windows");
+        assert!(guess.confidence < 0.5);
+        assert!(fingerprint::infer(None, None, &[], Some(5.0)).is_none());
+    }
+    
+    #[test]
+    fn test_parse_syn_ack() {
+        let mut packet = vec![
+            0x45, 0, 0, 44, 0, 0, 0x40, 0, 128, 6, 0, 0,
+            192, 168, 1, 20, 192, 168, 1, 2,
+            // TCP: 443 -> 40001, SYN|ACK, window 64240, 24-byte header
+            0x01, 0xBB, 0x9C, 0x41, 0, 0, 0, 1, 0, 0, 0, 1, 0x60, 0x12, 0xFA, 0xF0, 0, 0, 0, 0,
+        ];
+        // MSS 1460, NOP, wscale 8
+        packet.extend_from_slice(&[2, 4, 0x05, 0xB4]);
+        packet[32] = 0x70;
+        packet.extend_from_slice(&[1, 3, 3, 8]);
+        
+        let reply = fingerprint::parse_syn_ack(&packet).unwrap();
+        assert_eq!(reply.from.to_string(), "192.168.1.20:443");
+        assert_eq!(reply.to_port, 40001);
+        assert_eq!(reply.signature.ttl, 128);
+        assert_eq!(reply.signature.window, 64240);
+        assert_eq!(reply.signature.mss, Some(1460));
+        assert_eq!(reply.signature.window_scale, Some(8));
+        assert!(!reply.signature.timestamps);
+        
+        // Plain SYN is not a reply
+        packet[33] = 0x02;
+        assert!(fingerprint::parse_syn_ack(&packet).is_none());
+    }
+    
+    #[test]
+    fn test_arp_frames() {
+        use netweaver_lib::scanner::arp;
+        
+        let mac = utils::MacAddress::new([0x02, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let src: Ipv4Addr = "192.168.1.10".parse().unwrap();
+        let target: Ipv4Addr = "192.168.1.77".parse().unwrap();
+        let mut frame = arp::request_frame(mac, src, target);
+        
+        assert_eq!(&frame[0..6], &[0xff; 6]);
+        assert_eq!(&frame[12..14], &[0x08, 0x06]);
+        assert_eq!(&frame[38..42], &target.octets());
+        // A request is not a reply
+        assert!(arp::parse_reply(&frame).is_none());
+        
+        // Flip the opcode to 2 and it parses as a reply from the sender
+        frame[21] = 2;
+        assert_eq!(arp::parse_reply(&frame), Some((src, mac)));
+    }
+    
+    #[test]
+    fn test_arp_conflicts() {
+        use netweaver_lib::scanner::arp::{self, MacSighting};
+        
+        let ip = |s: &str| s.parse::<Ipv4Addr>().unwrap();
+        let seen = |last: u8, at: u64| MacSighting {
+            mac: utils::MacAddress::new([0x02, 0, 0, 0, 0, last]),
+            first_seen: at,
+        };
+        let sightings = vec![
+            (ip("192.168.1.50"), seen(1, 100)),
+            (ip("192.168.1.20"), seen(2, 110)),
+            // The same host answering twice is not a conflict
+            (ip("192.168.1.20"), seen(2, 120)),
+            (ip("192.168.1.50"), seen(3, 130)),
+            (ip("192.168.1.50"), seen(1, 140)),
+        ];
+        
+        let conflicts = arp::find_conflicts(&sightings);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].ip, ip("192.168.1.50"));
+        assert_eq!(conflicts[0].macs, vec![seen(1, 100), seen(3, 130)]);
+    }
+    
+    #[test]
+    fn test_name_discovery_packets() {
+        use netweaver_lib::scanner::names;
+        
+        let ip: Ipv4Addr = "192.168.1.42".parse().unwrap();
+        let query = names::mdns_query(ip);
+        assert!(query.windows(4).any(|w| w == b"\x0242\x01"));
+        assert!(query.ends_with(&[0, 12, 0x80, 1]));
+        
+        // Echo the question, then answer with a pointer back to it
+        let mut reply = query.clone();
+        reply[2] = 0x84;
+        reply[7] = 1;
+        reply.extend_from_slice(&[0xC0, 0x0C, 0, 12, 0, 1, 0, 0, 0, 120, 0, 19]);
+        reply.extend_from_slice(b"\x0bLiving-Room\x05local\x00");
+        assert_eq!(names::parse_mdns_reply(&reply).as_deref(), Some("Living-Room"));
+        assert!(names::parse_mdns_reply(&query).is_none());
+        
+        let query = names::nbstat_query(ip);
+        assert_eq!(query.len(), 50);
+        assert_eq!(&query[13..15], b"CK");
+        
+        let mut reply = query[..12].to_vec();
+        reply[2] = 0x84;
+        reply[5] = 0;
+        reply[7] = 1;
+        reply.extend_from_slice(&query[12..46]);
+        reply.extend_from_slice(&[0, 0x21, 0, 1, 0, 0, 0, 0, 0, 43, 2]);
+        // The group name comes first and must be skipped
+        reply.extend_from_slice(b"WORKGROUP      \x00\x84\x00");
+        reply.extend_from_slice(b"NAS-BOX        \x00\x04\x00");
+        assert_eq!(names::parse_nbstat_reply(&reply).as_deref(), Some("NAS-BOX"));
+    }
+    
+    #[test]
+    fn test_snmp_get_roundtrip() {
+        use netweaver_lib::scanner::snmp;
+        
+        let request = snmp::get_request("public", 1, &[snmp::SYS_DESCR]);
+        assert_eq!(request, [
+            0x30, 0x26, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+            0xa0, 0x19, 0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+            0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+        ]);
+        
+        // GetResponse from a Catalyst switch: long-form lengths, a multi-byte
+        // OID arc (1208) and a multi-line sysDescr
+        let captured = "3081f102010104067075626c6963a281e3020204d20201000201003081d63081\
+                        a606082b06010201010100048199436973636f20494f5320536f667477617265\
+                        2c20433239363020536f667477617265202843323936302d4c414e424153454b\
+                        392d4d292c2056657273696f6e2031322e3228353529534531322c2052454c45\
+                        41534520534f4654574152452028666332290d0a546563686e6963616c205375\
+                        70706f72743a20687474703a2f2f7777772e636973636f2e636f6d2f74656368\
+                        737570706f7274301506082b0601020101020006092b06010401090189383014\
+                        06082b060102010105000408636f72652d737731";
+        let bytes: Vec<u8> = (0..captured.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&captured[i..i + 2], 16).unwrap())
+            .collect();
+        
+        let (id, info) = snmp::parse_response(&bytes).unwrap();
+        assert_eq!(id, 1234);
+        assert_eq!(info.name.as_deref(), Some("core-sw1"));
+        assert!(info.descr.as_deref().unwrap().starts_with("Cisco IOS Software, C2960"));
+        assert_eq!(info.object_id.as_deref(), Some(&[1, 3, 6, 1, 4, 1, 9, 1, 1208][..]));
+        assert!(snmp::parse_response(&bytes[..40]).is_none());
+        
+        let guess = fingerprint::from_snmp(info.descr.as_deref(), info.object_id.as_deref()).unwrap();
+        assert_eq!(guess.name, "Cisco IOS");
+        assert!(guess.confidence > 0.9);
+        
+        let guess = fingerprint::from_snmp(None, Some(&[1, 3, 6, 1, 4, 1, 14988, 1])).unwrap();
+        assert_eq!(guess.name, "MikroTik RouterOS");
+        assert!(fingerprint::from_snmp(Some("Custom appliance"), None).is_none());
+    }
+    
+    #[test]
+    fn test_ssdp_parsing() {
+        use netweaver_lib::scanner::ssdp;
+        
+        let response = "HTTP/1.1 200 OK\r\n\
+                        CACHE-CONTROL: max-age=1800\r\n\
+                        Location: http://192.168.1.50:8060/dial/dd.xml\r\n\
+                        SERVER: Linux/4.9 UPnP/1.0 Roku/11.5.0\r\n\
+                        ST: upnp:rootdevice\r\n\
+                        USN: uuid:roku:ecp:X00000000001::upnp:rootdevice\r\n\
+                        \r\n";
+        let info = ssdp::parse_response(response).unwrap();
+        assert_eq!(info.location.as_deref(), Some("http://192.168.1.50:8060/dial/dd.xml"));
+        assert_eq!(info.server.as_deref(), Some("Linux/4.9 UPnP/1.0 Roku/11.5.0"));
+        assert!(info.friendly_name.is_none());
+        
+        // Our own M-SEARCH echoed back is not a response
+        assert!(ssdp::parse_response(ssdp::M_SEARCH).is_none());
+        
+        let xml = "<?xml version=\"1.0\"?><root><device>\
+                   <deviceType>urn:roku-com:device:player:1-0</deviceType>\
+                   <friendlyName> Living Room &amp; Den </friendlyName>\
+                   </device></root>";
+        assert_eq!(ssdp::parse_friendly_name(xml).as_deref(), Some("Living Room & Den"));
+        assert!(ssdp::parse_friendly_name("<root><friendlyName></friendlyName></root>").is_none());
+    }
+    
+    #[test]
+    fn test_port_state_classification() {
+        use netweaver_lib::scanner::{PortResult, PortState};
+        use std::io::{Error, ErrorKind};
+        use std::net::{TcpListener, TcpStream};
+        
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let open = PortResult::from_connect(addr.port(), &TcpStream::connect(addr), Duration::from_millis(2));
+        assert_eq!(open.state, PortState::Open);
+        assert_eq!(open.latency_ms, Some(2.0));
+        
+        // Nothing listens once the listener is gone, so the kernel answers RST
+        drop(listener);
+        let closed = PortResult::from_connect(addr.port(), &TcpStream::connect(addr), Duration::from_millis(1));
+        assert_eq!(closed.state, PortState::Closed);
+        
+        let filtered = PortResult::from_connect(443, &Err(Error::from(ErrorKind::TimedOut)), Duration::from_secs(1));
+        assert_eq!(filtered.state, PortState::Filtered);
+        assert_eq!(filtered.latency_ms, None);
+        
+        let json = serde_json::to_value(filtered).unwrap();
+        assert_eq!(json, serde_json::json!({ "port": 443, "state": "filtered" }));
+    }
+
+    #[test]
+    fn test_slow_port_detection() {
+        use netweaver_lib::scanner::{PortResult, PortState};
+
+        let port = |port, state, latency_ms| PortResult { port, state, latency_ms: Some(latency_ms) };
+        let mut host = device("192.168.1.30", None, 2.0);
+        host.ports = vec![
+            port(22, PortState::Open, 2.1),
+            port(443, PortState::Open, 38.0),
+            port(8080, PortState::Open, 5.0),
+            // Refusals are quick or slow for their own reasons
+            port(25, PortState::Closed, 90.0),
+        ];
+        assert_eq!(host.slow_port().map(|p| p.port), Some(443));
+
+        host.ports[1].latency_ms = Some(5.5);
+        assert_eq!(host.slow_port(), None);
+
+        // Microseconds of LAN jitter aren't worth mentioning, however big the ratio
+        let mut lan = device("192.168.1.31", None, 0.05);
+        lan.ports = vec![port(22, PortState::Open, 0.05), port(80, PortState::Open, 0.4)];
+        assert_eq!(lan.slow_port(), None);
+
+        let json = serde_json::to_value(host.ports[1]).unwrap();
+        assert_eq!(json, serde_json::json!({ "port": 443, "state": "open", "latency_ms": 5.5 }));
+    }
+
+    #[tokio::test]
+    async fn test_library_scan_localhost() {
+        use netweaver_lib::scanner::{self, PortState, ScanOptions, ScanProgress};
+        use std::sync::Mutex;
+        
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = ScanOptions {
+            target: Some("127.0.0.1".to_string()),
+            ports: Some(port.to_string()),
+            timeout_ms: Some(300),
+            ..ScanOptions::default()
+        };
+        
+        // Spawned to check the future can run on any runtime worker
+        let result = tokio::spawn(scanner::scan(options.clone())).await.unwrap().unwrap();
+        assert_eq!(result.total_hosts, 1);
+        assert_eq!(result.responsive_hosts, 1);
+        let device = &result.devices[0];
+        assert_eq!(device.ip, Ipv4Addr::LOCALHOST);
+        assert_eq!(device.open_ports, vec![port]);
+        assert_eq!(device.ports[0].state, PortState::Open);
+        
+        let events = Mutex::new(Vec::new());
+        let progress = |event: ScanProgress<'_>| {
+            let name = match event {
+                ScanProgress::Planned { hosts, ports, .. } => format!("planned {} {}", hosts, ports),
+                ScanProgress::HostsStarted { total, done } => format!("started {} {}", total, done),
+                ScanProgress::HostDone => "host".to_string(),
+                ScanProgress::Device(device) => format!("device {}", device.ip),
+                ScanProgress::HostsFinished => "finished".to_string(),
+                other => format!("{:?}", other),
+            };
+            events.lock().unwrap().push(name);
+        };
+        scanner::scan_with_progress(options, &progress).await.unwrap();
+        assert_eq!(
+            events.into_inner().unwrap(),
+            ["planned 1 1", "started 1 0", "host", "device 127.0.0.1", "finished"]
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_watch_rejects_topology() {
+        use netweaver_lib::scanner::{self, ReportOptions, ScanOptions};
+        
+        let options = ScanOptions { target: Some("127.0.0.1".to_string()), ..ScanOptions::default() };
+        for report in [
+            ReportOptions { topology: true, watch: Some("30s".to_string()), ..ReportOptions::default() },
+            ReportOptions { topology_out: Some("map.dot".to_string()), watch: Some("30s".to_string()), ..ReportOptions::default() },
+        ] {
+            let err = scanner::run_scan(options.clone(), report).await.unwrap_err();
+            assert!(err.to_string().contains("--watch"), "{}", err);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_ping_only_scan_skips_ports() {
+        use netweaver_lib::scanner::{self, ScanOptions};
+        
+        let result = scanner::scan(ScanOptions {
+            target: Some("127.0.0.1".to_string()),
+            ping_only: true,
+            ..ScanOptions::default()
+        })
+        .await
+        .unwrap();
+        
+        assert_eq!(result.responsive_hosts, 1);
+        let device = &result.devices[0];
+        assert!(device.open_ports.is_empty());
+        assert!(device.ports.is_empty());
+        assert!(device.latency_ms.is_some_and(|ms| ms > 0.0));
+    }
+    
+    #[test]
+    fn test_adaptive_probe_timing() {
+        let adaptive = ProbeTiming::default();
+        
+        // LAN host: floor of 100ms instead of the old fixed 200ms
+        assert_eq!(adaptive.port_timeout(Duration::from_millis(1)), Duration::from_millis(100));
+        // VPN host: 2.5x RTT
+        assert_eq!(adaptive.port_timeout(Duration::from_millis(300)), Duration::from_millis(750));
+        // Very slow host: clamped to the max
+        assert_eq!(adaptive.port_timeout(Duration::from_secs(5)), Duration::from_secs(2));
+        
+        let fixed = ProbeTiming { timeout: Some(Duration::from_millis(300)), ..ProbeTiming::default() };
+        assert_eq!(fixed.port_timeout(Duration::from_millis(1)), Duration::from_millis(300));
+        assert_eq!(fixed.liveness_timeout(), Duration::from_millis(300));
+    }
+    
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.ckpt").to_string_lossy().to_string();
+        
+        let tracker = CheckpointTracker::new(path.clone(), 2, "10.0.0.0/24".to_string(), 254, None);
+        for idx in 0..5 {
+            tracker.start(idx);
+        }
+        tracker.finish(0, None).unwrap();
+        tracker.finish(2, Some(&device("10.0.0.3", None, 1.0))).unwrap();
+        tracker.finish(4, None).unwrap();
+        tracker.save().unwrap();
+        
+        let cp = Checkpoint::load(&path).unwrap();
+        assert_eq!(cp.next_index, 5);
+        assert_eq!(cp.pending, vec![1, 3]);
+        assert_eq!(cp.completed(), 3);
+        assert_eq!(cp.devices.len(), 1);
+        assert!(cp.is_done(0) && cp.is_done(2) && cp.is_done(4));
+        assert!(!cp.is_done(1) && !cp.is_done(3) && !cp.is_done(5));
+        
+        // No temp file is left behind after the atomic rename
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        
+        // Resuming carries prior progress and devices forward
+        let resumed = CheckpointTracker::new(path.clone(), 100, cp.network_range.clone(), 254, Some(&cp));
+        resumed.start(1);
+        resumed.finish(1, None).unwrap();
+        resumed.save().unwrap();
+        let cp = Checkpoint::load(&path).unwrap();
+        assert_eq!(cp.pending, vec![3]);
+        assert_eq!(cp.devices.len(), 1);
+        assert_eq!(cp.seed, None);
+        
+        // A randomized scan's seed survives into the checkpoint and its resumes
+        CheckpointTracker::new(path.clone(), 100, cp.network_range.clone(), 254, None)
+            .with_seed(Some(99))
+            .save()
+            .unwrap();
+        let cp = Checkpoint::load(&path).unwrap();
+        assert_eq!(cp.seed, Some(99));
+        let resumed = CheckpointTracker::new(path.clone(), 100, cp.network_range.clone(), 254, Some(&cp));
+        resumed.save().unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap().seed, Some(99));
+    }
+    
+    #[test]
+    fn test_scan_diff() {
+        let mac = utils::MacAddress::new([0xdc, 0xa6, 0x32, 0x01, 0x02, 0x03]);
+        
+        let mut moved_old = device("192.168.1.40", None, 1.0);
+        moved_old.mac = Some(mac);
+        let previous = scan_result(vec![
+            device("192.168.1.10", None, 1.0),
+            device("192.168.1.20", None, 1.0),
+            moved_old,
+        ]);
+        
+        let mut changed = device("192.168.1.10", None, 1.0);
+        changed.open_ports = vec![22, 443];
+        let mut moved_new = device("192.168.1.41", None, 1.0);
+        moved_new.mac = Some(mac);
+        let current = scan_result(vec![
+            changed,
+            device("192.168.1.30", None, 1.0),
+            moved_new,
+        ]);
+        
+        let changes = diff::diff(&previous, &current);
+        
+        assert_eq!(changes.new_devices.len(), 1);
+        assert_eq!(changes.new_devices[0].ip, "192.168.1.30".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(changes.missing_devices.len(), 1);
+        assert_eq!(changes.missing_devices[0].ip, "192.168.1.20".parse::<Ipv4Addr>().unwrap());
+        
+        assert_eq!(changes.changed_devices.len(), 1);
+        assert_eq!(changes.changed_devices[0].opened_ports, vec![443]);
+        assert!(changes.changed_devices[0].closed_ports.is_empty());
+        
+        assert_eq!(changes.moved_devices.len(), 1);
+        assert_eq!(changes.moved_devices[0].mac, mac);
+        assert_eq!(changes.moved_devices[0].from, "192.168.1.40".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(changes.moved_devices[0].to, "192.168.1.41".parse::<Ipv4Addr>().unwrap());
+        
+        assert!(diff::diff(&current, &current).is_empty());
+    }
+    
+    #[test]
+    fn test_json_stream_events() {
+        let result = scan_result(vec![device("192.168.1.20", Some("Linux"), 2.0)]);
+        
+        let line = serde_json::to_value(ScanEvent::Device(&result.devices[0])).unwrap();
+        assert_eq!(line["event"], "device");
+        assert_eq!(line["ip"], "192.168.1.20");
+        
+        let line = serde_json::to_value(ScanEvent::Complete(&result)).unwrap();
+        assert_eq!(line["event"], "complete");
+        assert_eq!(line["responsive_hosts"], 1);
+        assert_eq!(line["devices"][0]["os_guess"], "Linux");
+        
+        assert_eq!(output::format(), Format::Human);
+        output::set_format(Format::JsonStream);
+        assert!(!output::is_human());
+        output::set_format(Format::Human);
+    }
+    
+    #[test]
+    fn test_quiet_scan_prints_no_banner() {
+        let data = tempfile::tempdir().unwrap();
+        let scan = |extra: &[&str]| {
+            let out = std::process::Command::new(env!("CARGO_BIN_EXE_netweaver"))
+                .args(["scan", "--target", "127.0.0.1", "--ports", "1"])
+                .args(extra)
+                .env("XDG_DATA_HOME", data.path())
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            String::from_utf8(out.stdout).unwrap()
+        };
+        
+        assert!(scan(&[]).contains("NetWeaver Network Scanner"));
+        
+        let quiet = scan(&["--quiet"]);
+        assert!(!quiet.contains("NetWeaver Network Scanner"));
+        assert!(!quiet.contains("Hosts to scan"));
+        assert!(quiet.contains("Scan Results"));
+    }
+    
+    #[test]
+    fn test_scan_updates_inventory() {
+        let data = tempfile::tempdir().unwrap();
+        let netweaver = |args: &[&str]| {
+            let out = std::process::Command::new(env!("CARGO_BIN_EXE_netweaver"))
+                .args(args)
+                .env("XDG_DATA_HOME", data.path())
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            String::from_utf8(out.stdout).unwrap()
+        };
+        
+        assert!(netweaver(&["scan", "--target", "127.0.0.1", "--ports", "1"]).contains("Inventory: 1 new"));
+        assert!(netweaver(&["scan", "--target", "127.0.0.1", "--ports", "1"]).contains("Inventory: 0 new, 1 known"));
+        
+        let listing: serde_json::Value =
+            serde_json::from_str(&netweaver(&["scan", "--inventory", "--json"])).unwrap();
+        assert_eq!(listing["devices"][0]["ip"], "127.0.0.1");
+        assert!(netweaver(&["scan", "--inventory"]).contains("just now"));
+    }
+    
+    #[test]
+    fn test_service_probe_matching() {
+        use netweaver_lib::scanner::services::{self, BANNER};
+        
+        let ssh = services::recognize(BANNER, 2222, b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n").unwrap();
+        assert_eq!((ssh.name.as_str(), ssh.version.as_deref(), ssh.probe.as_str()),
+                   ("SSH", Some("OpenSSH_9.6p1 Ubuntu-3ubuntu13"), "banner"));
+        
+        let mysql = services::recognize(BANNER, 3306, b"J\x00\x00\x00\x0a8.0.36\x00\x08\x00\x00\x00").unwrap();
+        assert_eq!((mysql.name.as_str(), mysql.version.as_deref()), ("MySQL", Some("8.0.36")));
+        assert_eq!(services::recognize(BANNER, 5900, b"RFB 003.008\n").unwrap().name, "VNC");
+        
+        let http = services::recognize("http-get", 8000, b"HTTP/1.1 200 OK\r\nDate: now\r\nserver: nginx/1.24.0\r\n\r\n").unwrap();
+        assert_eq!((http.name.as_str(), http.version.as_deref(), http.probe.as_str()),
+                   ("HTTP", Some("nginx/1.24.0"), "http-get"));
+        assert_eq!(services::recognize("http-get", 80, b"HTTP/1.0 404 Not Found\r\n\r\n").unwrap().version, None);
+        
+        assert_eq!(services::recognize("tls-hello", 4443, b"\x16\x03\x03\x00\x5d\x02").unwrap().name, "TLS");
+        assert_eq!(services::recognize("redis-ping", 6379, b"+PONG\r\n").unwrap().name, "Redis");
+        assert_eq!(services::recognize("postgres-ssl", 5432, b"N").unwrap().name, "PostgreSQL");
+        assert_eq!(services::recognize("rdp-x224", 3389, b"\x03\x00\x00\x13\x0e\xd0\x00\x00").unwrap().name, "RDP");
+        
+        assert!(services::recognize(BANNER, 21, b"hello\r\n").is_none());
+        assert!(services::recognize("postgres-ssl", 5432, b"No thanks").is_none());
+        assert!(services::recognize("no-such-probe", 80, b"HTTP/1.1 200 OK").is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_service_identification_on_silent_port() {
+        use netweaver_lib::scanner::services;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        
+        // Says nothing until asked, like most HTTP servers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 512];
+                    let len = stream.read(&mut buf).await.unwrap_or(0);
+                    if buf[..len].starts_with(b"GET ") {
+                        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nServer: test/1.0\r\n\r\n").await;
+                    }
+                });
+            }
+        });
+        
+        let found = services::identify(Ipv4Addr::LOCALHOST, port, Duration::from_secs(5), None).await.unwrap();
+        assert_eq!((found.name.as_str(), found.version.as_deref(), found.probe.as_str()),
+                   ("HTTP", Some("test/1.0"), "http-get"));
+    }
+    
+    #[test]
+    fn test_http_response_parsing() {
+        use netweaver_lib::scanner::http;
+        
+        let response = http::parse_response(b"HTTP/1.1 200 OK\r\nserver: lighttpd/1.4.59\r\nContent-Type: text/html\r\n\r\n<html><head>\n<TITLE>\n  Synology&nbsp;DiskStation &amp; more\n</TITLE></head>").unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Server"), Some("lighttpd/1.4.59"));
+        assert_eq!(http::extract_title(&response.body).as_deref(), Some("Synology DiskStation & more"));
+        
+        assert_eq!(http::extract_title(b"<html><title></title></html>"), None);
+        assert_eq!(http::extract_title(b"no markup here"), None);
+        assert!(http::parse_response(b"SSH-2.0-OpenSSH_9.6\r\n").is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_http_info_follows_one_redirect() {
+        use netweaver_lib::scanner::http;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let len = stream.read(&mut buf).await.unwrap_or(0);
+                let reply: &[u8] = if buf[..len].starts_with(b"GET / ") {
+                    b"HTTP/1.0 302 Found\r\nLocation: /webfig/\r\n\r\n"
+                } else {
+                    b"HTTP/1.0 200 OK\r\nServer: mikrotik\r\n\r\n<title>RouterOS router configuration page</title>"
+                };
+                let _ = stream.write_all(reply).await;
+            }
+        });
+        
+        let info = http::fetch(Ipv4Addr::LOCALHOST, port, None, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(info.status, 200);
+        assert_eq!(info.server.as_deref(), Some("mikrotik"));
+        assert_eq!(info.title.as_deref(), Some("RouterOS router configuration page"));
+        assert_eq!(info.redirected_to, Some(format!("http://127.0.0.1:{}/webfig/", port)));
+    }
+    
+    #[test]
+    fn test_tls_certificate_details() {
+        use netweaver_lib::scanner::tls;
+        
+        let mut params = rcgen::CertificateParams::new(vec!["nas.local".to_string(), "backup.nas.local".to_string()]);
+        params.distinguished_name.push(rcgen::DnType::CommonName, "nas.local");
+        params.not_before = rcgen::date_time_ymd(2020, 1, 1);
+        params.not_after = rcgen::date_time_ymd(2021, 1, 1);
+        let der = rcgen::Certificate::from_params(params).unwrap().serialize_der().unwrap();
+        
+        let info = tls::parse_certificate(8443, &der).unwrap();
+        assert_eq!(info.port, 8443);
+        assert_eq!(info.common_name(), "nas.local");
+        assert_eq!(info.san, vec!["nas.local", "backup.nas.local"]);
+        assert!(info.self_signed);
+        assert_eq!(info.not_after.format("%Y-%m-%d").to_string(), "2021-01-01");
+        
+        let before_expiry = info.not_after - chrono::Duration::days(10);
+        assert!(info.is_expired(chrono::Utc::now()));
+        assert!(!info.is_expired(before_expiry));
+        assert!(info.expires_soon(before_expiry));
+        assert!(!info.expires_soon(info.not_after - chrono::Duration::days(90)));
+        
+        assert!(tls::parse_certificate(443, b"not a certificate").is_none());
+    }
+}
+
+mod analytics_tests {
+    use netweaver_lib::analytics::{LatencyAnalyzer, BandwidthAnalyzer, PacketLossDetector};
+    use std::time::Duration;
+    
+    #[test]
+    fn test_latency_analyzer() {
+        let mut analyzer = LatencyAnalyzer::new(100);
+        
+        // Add some samples
+        analyzer.add_sample(10.0);
+        analyzer.add_sample(15.0);
+        analyzer.add_sample(12.0);
+        analyzer.add_sample(20.0);
+        
+        assert_eq!(analyzer.average(), 14.25);
+        assert_eq!(analyzer.min(), 10.0);
+        assert_eq!(analyzer.max(), 20.0);
+        
+        // Jitter should be non-zero with varying samples
+        assert!(analyzer.jitter() > 0.0);
+    }
+    
+    #[test]
+    fn test_latency_anomaly_detection() {
+        let mut analyzer = LatencyAnalyzer::new(100);
+        
+        // Add consistent samples
+        for _ in 0..10 {
+            analyzer.add_sample(10.0);
+        }
+        
+        // No anomaly with consistent data
+        assert!(!analyzer.detect_anomaly(3.0));
+        
+        // Add a spike
+        analyzer.add_sample(100.0);
+        
+        // Should detect anomaly
+        assert!(analyzer.detect_anomaly(3.0));
+    }
+
+    #[test]
+    fn test_latency_median() {
+        let mut analyzer = LatencyAnalyzer::new(3);
+        assert_eq!(analyzer.median(), 0.0);
+
+        // One slow reply doesn't drag the median the way it drags the average
+        for sample in [1.2, 480.0, 1.0] {
+            analyzer.add_sample(sample);
+        }
+        assert_eq!(analyzer.median(), 1.2);
+        assert!(analyzer.average() > 100.0);
+
+        let mut even = LatencyAnalyzer::new(4);
+        for sample in [4.0, 1.0, 3.0, 2.0] {
+            even.add_sample(sample);
+        }
+        assert_eq!(even.median(), 2.5);
+    }
+
+    #[test]
+    fn test_bandwidth_analyzer() {
+        let mut analyzer = BandwidthAnalyzer::new(Duration::from_secs(1));
+        
+        analyzer.add_measurement(1000);
+        std::thread::sleep(Duration::from_millis(100));
+        analyzer.add_measurement(2000);
+        
+        let bandwidth = analyzer.current_bandwidth();
+        // Should have some measured bandwidth
+        assert!(bandwidth > 0.0);
+    }
+
+    #[test]
+    fn test_bandwidth_analyzer_excludes_window_start() {
+        let mut analyzer = BandwidthAnalyzer::new(Duration::from_secs(10));
+        analyzer.add_measurement(1_000_000);
+        std::thread::sleep(Duration::from_millis(200));
+        analyzer.add_measurement(1000);
+
+        // 1000 bytes in ~200ms; the first sample only starts the window
+        let bandwidth = analyzer.current_bandwidth();
+        assert!(bandwidth > 2000.0 && bandwidth <= 5000.0, "{}", bandwidth);
+    }
+    
+    #[test]
+    fn test_packet_loss_detector() {
+        let mut detector = PacketLossDetector::new();
+        
+        detector.record_sent(100);
+        detector.record_received(95);
+        
+        // 5% loss
+        assert_eq!(detector.loss_rate(), 5.0);
+        
+        detector.reset();
+        assert_eq!(detector.loss_rate(), 0.0);
+    }
+}
+
+mod network_utils_tests {
+    use netweaver_lib::diagnostics::TraceHop;
+    use netweaver_lib::utils::network;
+    use netweaver_lib::utils::rate_limit::RateLimiter;
+    use netweaver_lib::utils::retry::RetryPolicy;
+    use std::net::Ipv4Addr;
+    use std::time::{Duration, Instant};
+    
+    /// A hop with one 1 ms answer from `ip`, or a silent one
+    fn hop(ttl: u8, ip: Option<Ipv4Addr>) -> TraceHop {
+        serde_json::from_value(serde_json::json!({
+            "hop": ttl, "ip": ip, "hostname": null,
+            "rtt_ms": [1.0], "avg_rtt": 1.0, "packet_loss": 0.0,
+        })).unwrap()
+    }
+    
+    /// Hops from TTL 1 on, `""` for a silent one
+    fn hops(ips: &[&str]) -> Vec<TraceHop> {
+        ips.iter().zip(1..).map(|(ip, ttl)| hop(ttl, (!ip.is_empty()).then(|| ip.parse().unwrap()))).collect()
+    }
+    
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::new(3);
+        assert_eq!(policy.delay(1), Duration::from_millis(50));
+        assert_eq!(policy.delay(2), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(200));
+        assert_eq!(policy.delay(40), Duration::from_secs(1));
+        
+        let policy = RetryPolicy { retries: 3, backoff: Duration::ZERO };
+        let mut calls = 0;
+        let result = policy.run_blocking(|| { calls += 1; calls }, |&n| n == 2);
+        assert_eq!((result.value, result.attempts), (2, 2));
+        
+        // Gives up after the first attempt plus `retries`
+        let result = policy.run_blocking(|| None::<u8>, Option::is_some);
+        assert_eq!((result.value, result.attempts), (None, 4));
+        assert_eq!(RetryPolicy::none().run_blocking(|| false, |&ok| ok).attempts, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_retry_async() {
+        let limiter = RateLimiter::new(1000);
+        let policy = RetryPolicy { retries: 2, backoff: Duration::from_millis(1) };
+        let result = policy.run(|| async { limiter.acquire().await; false }, |&ok| ok).await;
+        assert_eq!(result.attempts, 3);
+        // Every attempt, retries included, took a token
+        assert_eq!(limiter.acquired(), 3);
+    }
+    
+    #[test]
+    fn test_rate_limiter_never_exceeds_rate() {
+        let rate = 50;
+        let limiter = RateLimiter::new(rate);
+        
+        let start = Instant::now();
+        let slots: Vec<Instant> = (0..rate * 3).map(|_| limiter.reserve()).collect();
+        
+        // Any window of `rate` consecutive tokens must span at least one second
+        for window in slots.windows(rate as usize + 1) {
+            assert!(window[rate as usize] - window[0] >= Duration::from_secs(1));
+        }
+        assert_eq!(limiter.acquired(), (rate * 3) as u64);
+        
+        // And blocking acquisition actually waits for its slot
+        let limiter = RateLimiter::new(rate);
+        for _ in 0..=rate {
+            limiter.acquire_blocking();
+        }
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+    
+    #[test]
+    fn test_port_list_parsing() {
+        let ports = network::parse_port_list("80,443,8080-8083");
+        assert_eq!(ports.len(), 6);
+        assert!(ports.contains(&80));
+        assert!(ports.contains(&443));
+        assert!(ports.contains(&8080));
+        assert!(ports.contains(&8081));
+        assert!(ports.contains(&8082));
+        assert!(ports.contains(&8083));
+    }
+    
+    #[test]
+    fn test_port_in_range() {
+        assert!(network::is_port_in_range(8080, "8080-8090"));
+        assert!(network::is_port_in_range(8085, "8080-8090"));
+        assert!(!network::is_port_in_range(9000, "8080-8090"));
+        
+        assert!(network::is_port_in_range(80, "80"));
+        assert!(!network::is_port_in_range(443, "80"));
+    }
+    
+    #[test]
+    fn test_common_ports() {
+        assert!(network::COMMON_PORTS.contains(&80));
+        assert!(network::COMMON_PORTS.contains(&443));
+        assert!(network::COMMON_PORTS.contains(&22));
+        assert!(network::COMMON_PORTS.contains(&3306));
+    }
+    
+    #[test]
+    fn test_icmp_reply_matching() {
+        use netweaver_lib::utils::icmp::{self, ReplyKind};
+        
+        let ip_header = |proto: u8, ttl: u8, src: [u8; 4]| {
+            let mut h = vec![0x45, 0, 0, 0, 0, 0, 0, 0, ttl, proto, 0, 0];
+            h.extend_from_slice(&src);
+            h.extend_from_slice(&[10, 0, 0, 2]);
+            h
+        };
+        
+        // Echo reply: type 0, id 0x1234, seq 7
+        let mut reply = ip_header(1, 57, [8, 8, 8, 8]);
+        reply.extend_from_slice(&[0, 0, 0, 0, 0x12, 0x34, 0, 7]);
+        assert_eq!(
+            icmp::parse_reply(&reply, 0x1234, 7),
+            Some(("8.8.8.8".parse().unwrap(), 57, ReplyKind::EchoReply))
+        );
+        assert!(icmp::parse_reply(&reply, 0x1234, 8).is_none());
+        
+        // Time exceeded quoting our original echo request
+        let mut exceeded = ip_header(1, 254, [192, 168, 1, 1]);
+        exceeded.extend_from_slice(&[11, 0, 0, 0, 0, 0, 0, 0]);
+        exceeded.extend(ip_header(1, 1, [10, 0, 0, 2]));
+        exceeded.extend_from_slice(&[8, 0, 0, 0, 0x12, 0x34, 0, 7]);
+        assert_eq!(
+            icmp::parse_reply(&exceeded, 0x1234, 7),
+            Some(("192.168.1.1".parse().unwrap(), 254, ReplyKind::TimeExceeded))
+        );
+        
+        assert_eq!(
+            icmp::parse_ping_time("64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=12.5 ms"),
+            Some(Duration::from_micros(12500))
+        );
+    }
+
+    #[test]
+    fn test_ping_command_per_platform() {
+        use netweaver_lib::utils::icmp::ReplyKind;
+        use netweaver_lib::utils::ping_cmd::{self, Platform};
+        use std::net::Ipv4Addr;
+
+        let ip = Ipv4Addr::new(10, 0, 0, 1);
+        let wait = Duration::from_millis(1500);
+        assert_eq!(ping_cmd::args(Platform::Linux, ip, 3, wait), ["-n", "-c", "1", "-t", "3", "-W", "1", "10.0.0.1"]);
+        assert_eq!(ping_cmd::args(Platform::Bsd, ip, 3, wait), ["-n", "-c", "1", "-m", "3", "-W", "1500", "10.0.0.1"]);
+        assert_eq!(ping_cmd::args(Platform::Windows, ip, 3, wait), ["-n", "1", "-i", "3", "-w", "1500", "10.0.0.1"]);
+        // Linux rounds the wait up to a whole second rather than 0 (wait forever)
+        assert_eq!(ping_cmd::args(Platform::Linux, ip, 64, Duration::from_millis(200))[6], "1");
+
+        let windows = "Reply from 1.1.1.1: bytes=32 time=12ms TTL=57";
+        assert_eq!(ping_cmd::parse_ping_time(windows), Some(Duration::from_millis(12)));
+        assert_eq!(ping_cmd::parse_ping_ttl(windows), Some(57));
+        assert_eq!(ping_cmd::parse_ping_time("Reply from 10.0.0.1: bytes=32 time<1ms TTL=64"), Some(Duration::from_millis(1)));
+        assert!(ping_cmd::parse_error(windows).is_none());
+
+        let exceeded = Some((ip, ReplyKind::TimeExceeded));
+        assert_eq!(ping_cmd::parse_error("From 10.0.0.1 icmp_seq=1 Time to live exceeded"), exceeded);
+        assert_eq!(ping_cmd::parse_error("36 bytes from 10.0.0.1: Time to live exceeded"), exceeded);
+        assert_eq!(ping_cmd::parse_error("Pinging 8.8.8.8 with 32 bytes of data:\nReply from 10.0.0.1: TTL expired in transit."), exceeded);
+        assert_eq!(
+            ping_cmd::parse_error("Reply from 10.0.0.1: Destination host unreachable."),
+            Some((ip, ReplyKind::Unreachable))
+        );
+        assert!(ping_cmd::parse_error("Request timed out.").is_none());
+    }
+    
+    #[test]
+    fn test_udp_trace_reply_matching() {
+        use netweaver_lib::diagnostics::{udp, ProbeProtocol, TraceResult};
+        use netweaver_lib::utils::icmp::ReplyKind;
+        use std::net::Ipv4Addr;
+
+        let target: Ipv4Addr = "203.0.113.9".parse().unwrap();
+        let error = |icmp_type: u8, code: u8, from: [u8; 4], src_port: u16, dst_port: u16| {
+            let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 250, 1, 0, 0];
+            packet.extend_from_slice(&from);
+            packet.extend_from_slice(&[10, 0, 0, 2, icmp_type, code, 0, 0, 0, 0, 0, 0]);
+            // Quoted IP header (protocol 17) and UDP header
+            packet.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0, 0, 1, 17, 0, 0, 10, 0, 0, 2, 203, 0, 113, 9]);
+            packet.extend_from_slice(&src_port.to_be_bytes());
+            packet.extend_from_slice(&dst_port.to_be_bytes());
+            packet.extend_from_slice(&[0, 17, 0, 0]);
+            packet
+        };
+        let port = udp::port_for(3);
+        assert_eq!((udp::port_for(1), port), (33434, 33436));
+
+        let router = udp::match_reply(&error(11, 0, [10, 0, 0, 1], 40000, port), target, 40000, port).unwrap();
+        assert_eq!((router.from, router.kind, router.ttl), ("10.0.0.1".parse().unwrap(), ReplyKind::TimeExceeded, Some(250)));
+
+        let arrived = udp::match_reply(&error(3, 3, [203, 0, 113, 9], 40000, port), target, 40000, port).unwrap();
+        assert_eq!((arrived.from, arrived.kind), (target, ReplyKind::EchoReply));
+
+        let filtered = udp::match_reply(&error(3, 13, [10, 0, 0, 1], 40000, port), target, 40000, port).unwrap();
+        assert_eq!(filtered.kind, ReplyKind::Unreachable);
+
+        // Someone else's probe
+        assert!(udp::match_reply(&error(11, 0, [10, 0, 0, 1], 40001, port), target, 40000, port).is_none());
+
+        // Exports from before the field existed were ICMP traces
+        let old = r#"{"target":"x","target_ip":"203.0.113.9","hops":[],"completed":true,"total_time":{"secs":1,"nanos":0}}"#;
+        assert_eq!(serde_json::from_str::<TraceResult>(old).unwrap().probe_protocol, ProbeProtocol::Icmp);
+    }
+
+    #[test]
+    fn test_flow_consistent_probes() {
+        use netweaver_lib::diagnostics::{flow, same_path, tcp, udp};
+        use netweaver_lib::utils::ip;
+        use std::net::Ipv4Addr;
+
+        // RFC 1071's worked example
+        let words = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!((ip::ones_sum(&words), ip::checksum(&words)), (0xddf2, 0x220d));
+
+        // Every echo of a path carries the same checksum, whatever its sequence
+        for path in [1, 2, flow::MAX_PATHS] {
+            for seq in [1u16, 2, 0x7fff, 0xfffe] {
+                let mut echo = vec![8, 0, 0, 0, 0x12, 0x34];
+                echo.extend_from_slice(&seq.to_be_bytes());
+                let pad = ip::checksum_padding(ip::ones_sum(&echo), flow::icmp_checksum(path));
+                echo.extend_from_slice(&pad.to_be_bytes());
+                assert_eq!(ip::checksum(&echo), flow::icmp_checksum(path));
+            }
+        }
+        assert_ne!(flow::icmp_checksum(1), flow::icmp_checksum(2));
+        assert_ne!(flow::tcp_source_port(1), flow::tcp_source_port(2));
+        assert_eq!(udp::port_for(2), udp::BASE_PORT + 1);
+
+        // A UDP probe's checksum is its id, and still valid
+        let (src, dst): (Ipv4Addr, Ipv4Addr) = ("10.0.0.2".parse().unwrap(), "203.0.113.9".parse().unwrap());
+        let id = flow::next_probe_id();
+        assert!(id != 0 && id != 0xffff);
+        let payload = udp::checksum_payload(src, dst, 40000, 33434, id);
+        let mut datagram = [&src.octets()[..], &dst.octets(), &[0, 17, 0, 10]].concat();
+        datagram.extend_from_slice(&[0x9c, 0x40, 0x82, 0x9a, 0, 10]);
+        datagram.extend_from_slice(&id.to_be_bytes());
+        datagram.extend_from_slice(&payload);
+        assert_eq!(ip::checksum(&datagram), 0);
+
+        // Probes of one path share their ports and are told apart by checksum or sequence
+        let target = dst;
+        let mut exceeded = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 250, 1, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 11, 0, 0, 0, 0, 0, 0, 0];
+        exceeded.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0, 0, 1, 17, 0, 0, 10, 0, 0, 2, 203, 0, 113, 9]);
+        exceeded.extend_from_slice(&[0x9c, 0x40, 0x82, 0x9a, 0, 10]);
+        exceeded.extend_from_slice(&id.to_be_bytes());
+        assert!(udp::match_probe(&exceeded, target, 40000, 33434, id).is_some());
+        assert!(udp::match_probe(&exceeded, target, 40000, 33434, id.wrapping_add(1)).is_none());
+
+        let mut syn_ack = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 52, 6, 0, 0, 203, 0, 113, 9, 10, 0, 0, 2];
+        syn_ack.extend_from_slice(&[0x01, 0xbb, 0x9c, 0x40, 0, 0, 0, 0]);
+        syn_ack.extend_from_slice(&1001u32.to_be_bytes());
+        syn_ack.extend_from_slice(&[0x50, 0x12, 0, 0, 0, 0, 0, 0]);
+        assert!(tcp::match_probe(&syn_ack, target, 40000, 443, 1000).is_some());
+        assert!(tcp::match_probe(&syn_ack, target, 40000, 443, 2000).is_none());
+
+        let first = hops(&["10.0.0.1", "203.0.113.1", "198.51.100.9"]);
+        assert!(same_path(&first, &hops(&["10.0.0.1", "", "198.51.100.9"])));
+        assert!(!same_path(&first, &hops(&["10.0.0.1", "203.0.113.5", "198.51.100.9"])));
+    }
+
+    #[test]
+    fn test_tcp_trace_reply_matching() {
+        use netweaver_lib::diagnostics::tcp;
+        use netweaver_lib::utils::icmp::ReplyKind;
+        use std::net::Ipv4Addr;
+
+        let target: Ipv4Addr = "203.0.113.9".parse().unwrap();
+        let ip_header = |proto: u8, ttl: u8, src: [u8; 4], dst: [u8; 4]| {
+            let mut h = vec![0x45, 0, 0, 0, 0, 0, 0, 0, ttl, proto, 0, 0];
+            h.extend_from_slice(&src);
+            h.extend_from_slice(&dst);
+            h
+        };
+        let tcp_header = |src_port: u16, dst_port: u16, flags: u8| {
+            let mut h = Vec::new();
+            h.extend_from_slice(&src_port.to_be_bytes());
+            h.extend_from_slice(&dst_port.to_be_bytes());
+            h.extend_from_slice(&[0; 9]);
+            h.push(flags);
+            h.extend_from_slice(&[0; 6]);
+            h
+        };
+
+        let mut syn_ack = ip_header(6, 52, [203, 0, 113, 9], [10, 0, 0, 2]);
+        syn_ack.extend(tcp_header(443, 40000, 0x12));
+        let arrived = tcp::match_reply(&syn_ack, target, 40000, 443).unwrap();
+        assert_eq!((arrived.from, arrived.kind, arrived.ttl), (target, ReplyKind::EchoReply, Some(52)));
+        assert!(tcp::match_reply(&syn_ack, target, 40001, 443).is_none());
+
+        let mut rst = ip_header(6, 52, [203, 0, 113, 9], [10, 0, 0, 2]);
+        rst.extend(tcp_header(443, 40000, 0x14));
+        assert_eq!(tcp::match_reply(&rst, target, 40000, 443).unwrap().kind, ReplyKind::EchoReply);
+
+        // A bare ACK isn't an answer to a SYN
+        let mut ack = ip_header(6, 52, [203, 0, 113, 9], [10, 0, 0, 2]);
+        ack.extend(tcp_header(443, 40000, 0x10));
+        assert!(tcp::match_reply(&ack, target, 40000, 443).is_none());
+
+        let mut exceeded = ip_header(1, 253, [10, 0, 0, 1], [10, 0, 0, 2]);
+        exceeded.extend_from_slice(&[11, 0, 0, 0, 0, 0, 0, 0]);
+        exceeded.extend(ip_header(6, 1, [10, 0, 0, 2], [203, 0, 113, 9]));
+        exceeded.extend_from_slice(&tcp_header(40000, 443, 0x02)[..8]);
+        let router = tcp::match_reply(&exceeded, target, 40000, 443).unwrap();
+        assert_eq!((router.from, router.kind), ("10.0.0.1".parse().unwrap(), ReplyKind::TimeExceeded));
+        assert!(tcp::match_reply(&exceeded, target, 40000, 80).is_none());
+    }
+
+    #[test]
+    fn test_trace_hop_asn_annotation() {
+        use netweaver_lib::diagnostics::{asn, TraceHop};
+
+        assert_eq!(asn::origin_query("8.8.4.1".parse().unwrap()), "1.4.8.8.origin.asn.cymru.com.");
+        assert_eq!(asn::parse_origin("15169 | 8.8.8.0/24 | US | arin | 2000-03-30"), Some(15169));
+        assert_eq!(asn::parse_origin("3356 1299 | 4.0.0.0/9 | US | arin | 1992-12-01"), Some(3356));
+        assert_eq!(asn::parse_origin("NA | 0.0.0.0/0"), None);
+        assert_eq!(
+            asn::parse_as_name("15169 | US | arin | 2000-03-30 | GOOGLE - Google LLC, US").as_deref(),
+            Some("GOOGLE - Google LLC")
+        );
+        assert_eq!(asn::parse_as_name("3356 | US | arin | 2000-03-10 | LEVEL3").as_deref(), Some("LEVEL3"));
+        assert_eq!(asn::parse_as_name("3356 | US | arin"), None);
+
+        let hop = |n: u8, asn: Option<u32>, name: Option<&str>, country: Option<&str>| {
+            serde_json::from_value::<TraceHop>(serde_json::json!({
+                "hop": n, "ip": format!("198.51.100.{}", n), "hostname": null,
+                "rtt_ms": [1.0], "avg_rtt": 1.0, "packet_loss": 0.0,
+                "asn": asn, "as_name": name, "country": country,
+            }))
+            .unwrap()
+        };
+        let hops = vec![
+            hop(1, None, None, None),
+            hop(2, Some(3320), Some("DTAG"), Some("DE")),
+            hop(3, Some(3356), None, Some("US")),
+            hop(4, Some(3356), None, Some("US")),
+            hop(5, Some(24940), Some("HETZNER-AS"), Some("DE")),
+        ];
+        assert_eq!(asn::as_label(&hops[1]).as_deref(), Some("AS3320 DTAG"));
+        assert_eq!(asn::as_path(&hops), ["AS3320 DTAG", "AS3356", "AS24940 HETZNER-AS"]);
+        let detours: Vec<u8> = asn::unexpected_country_hops(&hops).iter().map(|h| h.hop).collect();
+        assert_eq!(detours, [3, 4]);
+        assert!(asn::unexpected_country_hops(&hops[..4]).is_empty());
+    }
+
+    #[test]
+    fn test_trace_route_map() {
+        use netweaver_lib::diagnostics::{routemap, TraceResult};
+
+        let trace = |located: bool| -> TraceResult {
+            let location = |lat: f64, lon: f64| located.then_some([lat, lon]);
+            serde_json::from_value(serde_json::json!({
+                "target": "<example>", "target_ip": "203.0.113.9", "completed": true,
+                "total_time": { "secs": 2, "nanos": 0 },
+                "hops": [
+                    { "hop": 1, "ip": "192.168.1.1", "hostname": "router.lan", "rtt_ms": [1.0], "avg_rtt": 1.0, "packet_loss": 0.0 },
+                    { "hop": 2, "ip": null, "hostname": null, "rtt_ms": [-1.0], "avg_rtt": 0.0, "packet_loss": 100.0 },
+                    { "hop": 3, "ip": "198.51.100.3", "hostname": null, "rtt_ms": [20.0, -1.0], "avg_rtt": 20.0, "packet_loss": 50.0,
+                      "asn": 3356, "as_name": "LEVEL3", "country": "US", "location": location(40.7, -74.0) },
+                    { "hop": 4, "ip": "203.0.113.9", "hostname": null, "rtt_ms": [80.0], "avg_rtt": 80.0, "packet_loss": 0.0,
+                      "country": "DE", "location": location(50.1, 8.7) },
+                ],
+            }))
+            .unwrap()
+        };
+
+        let html = routemap::render(&trace(false));
+        assert!(html.starts_with("<!DOCTYPE html>") && html.trim_end().ends_with("</html>"));
+        // Nothing is fetched: no scripts, no links, no remote images
+        assert!(!html.contains("<script") && !html.contains("<link") && !html.contains("src="));
+        assert!(html.contains("&lt;example&gt;") && !html.contains("<example>"));
+        assert!(html.contains("AS3356 LEVEL3"));
+        // The slowest hop's bar fills the column, the others scale to it
+        assert!(html.contains("width:100.0%") && html.contains("width:25.0%"));
+        assert!(!html.contains("<h2>Map</h2>"));
+
+        let html = routemap::render(&trace(true));
+        assert!(html.contains("<h2>Map</h2>"));
+        let (x, y) = routemap::project(40.7, -74.0);
+        assert!(html.contains(&format!("<polyline points=\"{:.1},{:.1} ", x, y)));
+        assert_eq!(routemap::project(0.0, 0.0), (360.0, 180.0));
+
+        assert_eq!(routemap::loss_colour(Some(0.0)), "#43a047");
+        assert_eq!(routemap::loss_colour(Some(50.0)), "#e53935");
+        assert_eq!(routemap::loss_colour(None), "#9e9e9e");
+    }
+
+    #[test]
+    fn test_trace_route_comparison() {
+        use netweaver_lib::diagnostics::{compare, TraceResult};
+
+        // Each hop is (router's last octet or 0 for silence, avg RTT, loss)
+        let trace = |target: &str, hops: &[(u8, f64, f64)]| -> TraceResult {
+            let hops: Vec<serde_json::Value> = hops.iter().enumerate().map(|(i, &(router, rtt, loss))| serde_json::json!({
+                "hop": i + 1, "ip": (router > 0).then(|| format!("198.51.100.{}", router)), "hostname": null,
+                "rtt_ms": [rtt], "avg_rtt": rtt, "packet_loss": loss,
+            })).collect();
+            serde_json::from_value(serde_json::json!({
+                "target": target, "target_ip": "203.0.113.1", "completed": true,
+                "total_time": { "secs": 1, "nanos": 0 }, "hops": hops,
+            }))
+            .unwrap()
+        };
+
+        let eu = trace("eu.example.com", &[(1, 1.0, 0.0), (0, 0.0, 100.0), (3, 8.0, 0.0), (10, 12.0, 0.0), (11, 15.0, 0.0)]);
+        let us = trace("us.example.com", &[(1, 1.5, 0.0), (2, 5.0, 0.0), (3, 9.0, 0.0), (20, 60.0, 33.3), (21, 95.0, 0.0), (22, 98.0, 0.0)]);
+        let comparison = compare::compare(&[eu.clone(), us.clone()]);
+        // A silent hop doesn't split the routes
+        let shared: Vec<(u8, Option<String>)> = comparison.shared.iter().map(|hop| (hop.hop, hop.ip.map(|ip| ip.to_string()))).collect();
+        assert_eq!(shared, [(1, Some("198.51.100.1".into())), (2, Some("198.51.100.2".into())), (3, Some("198.51.100.3".into()))]);
+        assert_eq!(comparison.shared[1].avg_rtt_ms, [None, Some(5.0)]);
+        assert_eq!(comparison.diverges_at, Some(4));
+        let [eu_branch, us_branch] = &comparison.branches[..] else { panic!("two branches") };
+        assert_eq!((eu_branch.hops, eu_branch.final_rtt_ms, eu_branch.added_rtt_ms), (2, Some(15.0), Some(7.0)));
+        assert_eq!((us_branch.hops, us_branch.final_rtt_ms, us_branch.added_rtt_ms), (3, Some(98.0), Some(89.0)));
+        assert_eq!((us_branch.worst_loss_pct, us_branch.final_loss_pct), (33.3, 0.0));
+
+        // One target on the way to the other: the routes part where the
+        // longer one goes on
+        let near = trace("gw", &[(1, 1.0, 0.0), (2, 4.0, 0.0)]);
+        let comparison = compare::compare(&[near, us]);
+        assert_eq!((comparison.shared.len(), comparison.diverges_at), (2, Some(3)));
+        assert_eq!(comparison.branches[0].added_rtt_ms, Some(0.0));
+        assert_eq!(comparison.branches[1].hops, 4);
+
+        let comparison = compare::compare(&[eu.clone(), eu]);
+        assert_eq!((comparison.shared.len(), comparison.diverges_at), (5, None));
+    }
+
+    #[test]
+    fn test_ping_statistics() {
+        use netweaver_lib::diagnostics::ping::{PingMethod, PingReply, PingResult};
+
+        let reply = |seq: u32, rtt_ms: Option<f64>| PingReply { seq, rtt_ms, ttl: Some(64) };
+        let replies = vec![reply(1, Some(10.0)), reply(2, None), reply(3, Some(20.0)), reply(4, Some(30.0))];
+        let result = PingResult::new("gw".into(), "192.0.2.1".parse().unwrap(), PingMethod::Icmp, 80, replies, Duration::from_secs(4));
+        assert_eq!((result.sent, result.received, result.port), (4, 3, None));
+        assert!((result.loss_pct - 25.0).abs() < 1e-9);
+        assert_eq!((result.min_ms, result.avg_ms, result.max_ms), (Some(10.0), Some(20.0), Some(30.0)));
+        assert!((result.stddev_ms.unwrap() - (200.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(result.jitter_ms, Some(10.0));
+
+        let lost = PingResult::new("gw".into(), "192.0.2.1".parse().unwrap(), PingMethod::Tcp, 443, vec![reply(1, None)], Duration::from_secs(1));
+        assert_eq!((lost.received, lost.port, lost.avg_ms, lost.stddev_ms), (0, Some(443), None, None));
+        assert!((lost.loss_pct - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trace_hop_rtt_summary() {
+        use netweaver_lib::diagnostics::{rtt_summary, rtt_variation, RttSummary};
+
+        // One answer and two timeouts average to the answer, not a third of it
+        assert_eq!(rtt_summary(&[30.0, -1.0, -1.0]), Some(RttSummary { avg: 30.0, min: 30.0, max: 30.0 }));
+        assert_eq!(rtt_summary(&[-1.0, 10.0, 20.0]), Some(RttSummary { avg: 15.0, min: 10.0, max: 20.0 }));
+        assert_eq!(rtt_summary(&[12.0, 4.0, 8.0]), Some(RttSummary { avg: 8.0, min: 4.0, max: 12.0 }));
+        assert_eq!(rtt_summary(&[0.0]), Some(RttSummary { avg: 0.0, min: 0.0, max: 0.0 }));
+        assert_eq!(rtt_summary(&[-1.0, -1.0, -1.0]), None);
+        assert_eq!(rtt_summary(&[]), None);
+
+        let variation = rtt_variation(&[10.0, -1.0, 30.0, 20.0]).unwrap();
+        assert!((variation.stddev - (200.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(variation.jitter, 15.0);
+        assert_eq!(rtt_variation(&[30.0, -1.0, -1.0]), None);
+        assert_eq!(rtt_variation(&[]), None);
+    }
+
+    #[test]
+    fn test_routing_loop_detection() {
+        use netweaver_lib::diagnostics::{detect_loop, RoutingLoop};
+
+        let ip = |s: &str| s.parse().unwrap();
+
+        // Bouncing between two routers until max_hops
+        let looping = hops(&["10.0.0.1", "203.0.113.1", "203.0.113.2", "203.0.113.3", "203.0.113.2", "203.0.113.3", "", "203.0.113.3"]);
+        assert_eq!(detect_loop(&looping), Some(RoutingLoop {
+            routers: vec![ip("203.0.113.2"), ip("203.0.113.3")],
+            first_ttl: 3,
+            last_ttl: 8,
+        }));
+
+        // The same router at consecutive TTLs (MPLS, load balancers) is no loop
+        assert_eq!(detect_loop(&hops(&["10.0.0.1", "203.0.113.1", "203.0.113.1", "203.0.113.1", "198.51.100.9"])), None);
+        // Nor is a router reappearing after a silent hop with nobody else in between
+        assert_eq!(detect_loop(&hops(&["10.0.0.1", "203.0.113.1", "", "203.0.113.1", "198.51.100.9"])), None);
+        assert_eq!(detect_loop(&hops(&["10.0.0.1", "203.0.113.1", "203.0.113.2", "198.51.100.9"])), None);
+        assert_eq!(detect_loop(&[]), None);
+
+        let three = detect_loop(&hops(&["10.0.0.1", "203.0.113.1", "203.0.113.2", "203.0.113.3", "203.0.113.1"])).unwrap();
+        assert_eq!(three.routers.len(), 3);
+        assert_eq!((three.first_ttl, three.last_ttl), (2, 5));
+    }
+
+    #[test]
+    fn test_trace_end() {
+        use netweaver_lib::diagnostics::{trace_end, TraceEnd};
+
+        let target = "198.51.100.9".parse().unwrap();
+
+        assert_eq!(trace_end(&[], target, 30, 3), None);
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "203.0.113.1"]), target, 30, 3), None);
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "", "198.51.100.9"]), target, 30, 3), Some(TraceEnd::Reached));
+        // Reaching the target on the last TTL still completes the trace
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "198.51.100.9"]), target, 2, 3), Some(TraceEnd::Reached));
+
+        // Only silent hops in a row count
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "", "", "203.0.113.1", "", ""]), target, 30, 3), None);
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "", "", ""]), target, 30, 3), Some(TraceEnd::Unresponsive(3)));
+        // 0 never gives up early
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "", "", "", "", ""]), target, 30, 0), None);
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "", "", "", "", ""]), target, 6, 0), Some(TraceEnd::Exhausted));
+
+        // Out of TTLs without the target answering: not completed
+        assert_eq!(trace_end(&hops(&["10.0.0.1", "203.0.113.1", "203.0.113.2"]), target, 3, 3), Some(TraceEnd::Exhausted));
+    }
+
+    #[tokio::test]
+    async fn test_walk_hops_in_ttl_order() {
+        use netweaver_lib::diagnostics::{walk_hops, TraceEnd, TraceHop, PARALLEL_TTLS};
+        use std::time::Duration;
+
+        let target = Ipv4Addr::new(198, 51, 100, 9);
+        let mut probed = Vec::new();
+        // Later TTLs answer first, and the target answers at TTL 5
+        let probe = |ttl: u8| {
+            probed.push(ttl);
+            async move {
+                tokio::time::sleep(Duration::from_millis(5 * u64::from(20 - ttl))).await;
+                Ok(hop(ttl, Some(if ttl >= 5 { target } else { Ipv4Addr::new(10, 0, 0, ttl) })))
+            }
+        };
+        let mut reported = Vec::new();
+        let (hops, end) = walk_hops(target, 20, 3, probe, async |hop: &mut TraceHop| {
+            reported.push(hop.hop);
+            Ok(())
+        }).await.unwrap();
+        assert_eq!(reported, [1, 2, 3, 4, 5]);
+        assert_eq!(hops.iter().map(|hop| hop.hop).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(end, Some(TraceEnd::Reached));
+        // The probes past the target were in flight, just never reported
+        assert!(probed.len() > 5);
+        assert!(probed.len() <= 5 + PARALLEL_TTLS);
+
+        // Silence from TTL 3 on gives up after max_unresponsive of it
+        let silent = |ttl: u8| async move {
+            tokio::time::sleep(Duration::from_millis(5 * u64::from(10 - ttl))).await;
+            Ok(hop(ttl, (ttl < 3).then_some(Ipv4Addr::new(10, 0, 0, ttl))))
+        };
+        let (hops, end) = walk_hops(target, 10, 2, silent, async |_: &mut TraceHop| Ok(())).await.unwrap();
+        assert_eq!(hops.iter().map(|hop| hop.hop).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(end, Some(TraceEnd::Unresponsive(2)));
+    }
+
+    #[test]
+    fn test_trace_history_route_changes() {
+        use netweaver_lib::diagnostics::history::{self, RouteChange, StoredTrace};
+        use netweaver_lib::diagnostics::TraceResult;
+
+        let trace = |target: &str, hops: &[(&str, f64)]| {
+            let hops: Vec<_> = hops.iter().enumerate().map(|(i, (ip, rtt))| serde_json::json!({
+                "hop": i + 1, "ip": (!ip.is_empty()).then_some(ip), "hostname": null,
+                "rtt_ms": [rtt], "avg_rtt": rtt, "packet_loss": 0.0,
+            })).collect();
+            serde_json::from_value::<TraceResult>(serde_json::json!({
+                "target": target, "target_ip": "198.51.100.9", "hops": hops, "completed": true,
+                "total_time": { "secs": 1, "nanos": 0 },
+            }))
+            .unwrap()
+        };
+
+        let before = trace("dc", &[("10.0.0.1", 1.0), ("203.0.113.1", 5.0), ("203.0.113.2", 8.0), ("198.51.100.9", 10.0)]);
+        let after = trace("dc", &[("10.0.0.1", 1.2), ("", -1.0), ("203.0.113.7", 6.0), ("203.0.113.2", 40.0), ("198.51.100.9", 12.0)]);
+        let ip = |s: &str| s.parse().unwrap();
+        assert_eq!(history::compare(&before, &after), vec![
+            RouteChange::Removed { hop: 2, ip: ip("203.0.113.1") },
+            RouteChange::Added { hop: 3, ip: ip("203.0.113.7") },
+            RouteChange::Moved { ip: ip("203.0.113.2"), from_hop: 3, to_hop: 4 },
+            RouteChange::Moved { ip: ip("198.51.100.9"), from_hop: 4, to_hop: 5 },
+        ]);
+        let slower = trace("dc", &[("10.0.0.1", 1.5), ("203.0.113.1", 30.0), ("203.0.113.2", 12.0), ("198.51.100.9", 10.0)]);
+        assert_eq!(history::compare(&before, &slower), vec![
+            RouteChange::Latency { hop: 2, ip: ip("203.0.113.1"), from_ms: 5.0, to_ms: 30.0 },
+        ]);
+        assert!(history::compare(&before, &before).is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("traces.jsonl");
+        assert!(history::load(&path, "dc").unwrap().is_empty());
+        for (at, trace) in [(1, &before), (2, &after), (3, &before), (4, &slower)] {
+            history::append(&path, StoredTrace { at, trace: trace.clone() }, 3).unwrap();
+        }
+        history::append(&path, StoredTrace { at: 5, trace: trace("other", &[("10.0.0.1", 1.0)]) }, 3).unwrap();
+        let stored = history::load(&path, "dc").unwrap();
+        assert_eq!(stored.iter().map(|s| s.at).collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(history::load(&path, "other").unwrap().len(), 1);
+
+        // before and slower share a path, so they're one sighting
+        let sightings = history::sightings(&stored);
+        assert_eq!(sightings.len(), 2);
+        assert_eq!((sightings[0].times, sightings[0].first_seen, sightings[0].last_seen), (2, 3, 4));
+        assert_eq!(sightings[1].path, history::path_of(&after));
+    }
+
+    #[test]
+    fn test_mtr_hop_statistics() {
+        use netweaver_lib::diagnostics::mtr::{render_table, HopTracker};
+        use netweaver_lib::diagnostics::ProbeProtocol;
+        use std::net::Ipv4Addr;
+
+        let router: Ipv4Addr = "10.0.0.1".parse().unwrap();
+        let mut answering = HopTracker::new(1);
+        for rtt in [Some(10.0), None, Some(30.0), Some(20.0)] {
+            answering.record(rtt.map(|ms| (router, ms)));
+        }
+        let stats = answering.stats();
+        assert_eq!((stats.sent, stats.received), (4, 3));
+        assert!((stats.loss_pct - 25.0).abs() < 1e-9);
+        assert_eq!((stats.last_ms, stats.best_ms, stats.worst_ms), (Some(20.0), Some(10.0), Some(30.0)));
+        assert!((stats.avg_ms.unwrap() - 20.0).abs() < 1e-9);
+
+        let mut silent = HopTracker::new(2);
+        silent.record(None);
+        silent.record(None);
+        let stats = silent.stats();
+        assert_eq!((stats.received, stats.last_ms, stats.avg_ms), (0, None, None));
+        assert!((stats.loss_pct - 100.0).abs() < 1e-9);
+
+        let hops = [answering.hop(ProbeProtocol::Icmp), silent.hop(ProbeProtocol::Icmp)];
+        assert_eq!(hops[0].stats.as_ref().unwrap().sent, 4);
+        let table = render_table(&hops);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Hop  Host"));
+        assert!(lines[1].contains("10.0.0.1") && lines[1].contains("25.0%") && lines[1].contains("30.0"));
+        assert!(lines[2].contains("???") && lines[2].contains("100.0%") && lines[2].ends_with('-'));
+
+        // Each cycle updates the hop's spread: RTTs 10, 30, 20
+        let stddev = (200.0f64 / 3.0).sqrt();
+        assert!((hops[0].stddev_ms.unwrap() - stddev).abs() < 1e-9);
+        assert_eq!(hops[0].jitter_ms, Some(15.0));
+        assert_eq!((hops[1].stddev_ms, hops[1].jitter_ms), (None, None));
+        answering.record(Some((router, 20.0)));
+        let hop = answering.hop(ProbeProtocol::Icmp);
+        assert!((hop.stddev_ms.unwrap() - 50f64.sqrt()).abs() < 1e-9);
+        assert_eq!(hop.jitter_ms, Some(10.0));
+        assert!(lines[0].contains("StDev") && lines[1].contains("8.2"));
+    }
+
+    #[test]
+    fn test_traceroute_output_parsing() {
+        use netweaver_lib::diagnostics::parse_traceroute_hop;
+
+        let ms = |ms: u64| Duration::from_millis(ms);
+        let output = "\
+traceroute to 8.8.8.8 (8.8.8.8), 3 hops max, 60 byte packets
+ 3  10.0.0.1  12.000 ms *  10.0.0.5  14.000 ms
+";
+        assert_eq!(
+            parse_traceroute_hop(output, 3),
+            Some(vec![Some(("10.0.0.1".parse().unwrap(), ms(12))), None, Some(("10.0.0.5".parse().unwrap(), ms(14)))])
+        );
+        assert_eq!(parse_traceroute_hop(" 7  * * *\n", 7), Some(vec![None, None, None]));
+        assert_eq!(
+            parse_traceroute_hop("12  192.168.9.1  3.000 ms !H\n", 12),
+            Some(vec![Some(("192.168.9.1".parse().unwrap(), ms(3)))])
+        );
+        assert_eq!(parse_traceroute_hop(output, 4), None);
+    }
+    
+    #[test]
+    fn test_top_ports() {
+        let mut top10 = network::top_ports(10).unwrap();
+        top10.sort_unstable();
+        assert_eq!(top10, vec![21, 22, 23, 25, 80, 110, 139, 443, 445, 3389]);
+        
+        let all = network::top_ports(5000).unwrap();
+        assert_eq!(all.len(), 1000);
+        let mut unique = all.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), 1000);
+        
+        assert!(network::top_ports(0).is_err());
+    }
+}
+
+mod capture_tests {
+    use netweaver_lib::diagnostics::capture::{self, Protocol, ProtocolCounts};
+    use netweaver_lib::diagnostics::filter::{self, Filter};
+    use netweaver_lib::diagnostics::pcap::{linktype, PcapReader};
+    use netweaver_lib::diagnostics::traffic::{self, TrafficStats};
+    use chrono::TimeZone;
+
+    /// An Ethernet + IPv4 frame around `transport`
+    fn ipv4_frame(protocol: u8, src: [u8; 4], dst: [u8; 4], transport: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 1, 0x02, 0, 0, 0, 0, 2, 0x08, 0x00];
+        let total = (20 + transport.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(transport);
+        frame
+    }
+
+    fn tcp_header(src_port: u16, dst_port: u16, flags: u8) -> Vec<u8> {
+        tcp_segment(src_port, dst_port, flags, 0, 0)
+    }
+
+    /// A TCP header at sequence number `seq` followed by `payload` zero bytes
+    fn tcp_segment(src_port: u16, dst_port: u16, flags: u8, seq: u32, payload: usize) -> Vec<u8> {
+        let mut h = Vec::new();
+        h.extend_from_slice(&src_port.to_be_bytes());
+        h.extend_from_slice(&dst_port.to_be_bytes());
+        h.extend_from_slice(&seq.to_be_bytes());
+        h.extend_from_slice(&[0; 4]);
+        h.extend_from_slice(&[0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        h.resize(h.len() + payload, 0);
+        h
+    }
+
+    #[test]
+    fn test_dissect_layers() {
+        use netweaver_lib::diagnostics::dissect;
+
+        let mut segment = tcp_segment(51234, 80, 0x18, 1000, 0);
+        segment.extend_from_slice(b"GET / HTTP/1.1\r\n");
+        let frame = ipv4_frame(6, [10, 0, 0, 2], [93, 184, 216, 34], &segment);
+        let dissection = dissect::dissect(linktype::ETHERNET, &frame);
+        // IPv4 goes through the C core, which also names the application
+        assert!(dissection.core_parsed);
+        assert_eq!(dissection.application.as_deref(), Some("HTTP"));
+        let names: Vec<&str> = dissection.layers.iter().map(|layer| layer.name).collect();
+        assert_eq!(names, ["Ethernet", "IPv4", "TCP"]);
+        assert_eq!(dissection.layers[1].get("ttl"), Some("64"));
+        assert_eq!(dissection.layers[1].get("proto"), Some("TCP (6)"));
+        assert_eq!(dissection.layers[2].get("seq"), Some("1000"));
+        assert_eq!(dissection.layers[2].get("flags"), Some("[P.]"));
+        assert_eq!(&frame[dissection.payload_offset..], b"GET / HTTP/1.1\r\n");
+
+        let lines = dissect::format_dissection(&dissection, &frame, 8);
+        assert!(lines.iter().any(|line| line.trim() == "Payload   16 bytes (HTTP), first 8 shown"));
+        assert_eq!(lines.last().unwrap().trim(), "0000  47 45 54 20 2f 20 48 54                           GET / HT");
+
+        // A bare packet from 8.0.0.1 looks like Ethernet to the core; the
+        // Rust decode takes over and gets it right
+        let bare = ipv4_frame(17, [8, 0, 0, 1], [10, 0, 0, 2], &[0, 53, 0xc3, 0x50, 0, 12, 0, 0, 1, 2, 3, 4]);
+        let dissection = dissect::dissect(linktype::RAW, &bare[14..]);
+        assert!(!dissection.core_parsed);
+        assert_eq!(dissection.layers[0].get("src"), Some("8.0.0.1"));
+        assert_eq!(dissection.layers[1].get("dport"), Some("50000"));
+        assert_eq!(&bare[14..][dissection.payload_offset..], &[1, 2, 3, 4]);
+
+        let mut ipv6 = vec![0x60, 0, 0, 0, 0, 8, 17, 64];
+        ipv6.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        ipv6.extend_from_slice(&[0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xfb]);
+        ipv6.extend_from_slice(&[0x14, 0xe9, 0x14, 0xe9, 0, 8, 0, 0]);
+        let dissection = dissect::dissect(linktype::IPV6, &ipv6);
+        let names: Vec<&str> = dissection.layers.iter().map(|layer| layer.name).collect();
+        assert_eq!(names, ["IPv6", "UDP"]);
+        assert_eq!(dissection.layers[0].get("dst"), Some("ff02::fb"));
+        assert_eq!(dissection.payload_offset, ipv6.len());
+
+        assert_eq!(dissect::hex_dump(&[0x41; 20], 64), [
+            "0000  41 41 41 41 41 41 41 41  41 41 41 41 41 41 41 41  AAAAAAAAAAAAAAAA",
+            "0010  41 41 41 41                                       AAAA",
+        ]);
+    }
+
+    #[test]
+    fn test_decode_and_format_packets() {
+        let at = chrono::Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 1).unwrap();
+
+        let syn_ack = capture::decode_ethernet(&ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_header(51234, 443, 0x12)));
+        assert_eq!(syn_ack.protocol, Protocol::Tcp);
+        assert_eq!((syn_ack.src_port, syn_ack.dst_port), (Some(51234), Some(443)));
+        assert_eq!(capture::format_line(at, &syn_ack), "12:00:01.000000 IP 10.0.0.2.51234 > 1.1.1.1.443: TCP [S.] length 54");
+
+        let udp = capture::decode_ethernet(&ipv4_frame(17, [10, 0, 0, 2], [10, 0, 0, 1], &[0xc3, 0x50, 0, 53, 0, 8, 0, 0]));
+        assert_eq!(capture::format_line(at, &udp), "12:00:01.000000 IP 10.0.0.2.50000 > 10.0.0.1.53: UDP length 42");
+
+        let echo = capture::decode_ethernet(&ipv4_frame(1, [10, 0, 0, 2], [8, 8, 8, 8], &[8, 0, 0, 0, 0, 1, 0, 1]));
+        assert_eq!(capture::format_line(at, &echo), "12:00:01.000000 IP 10.0.0.2 > 8.8.8.8: ICMP echo request length 42");
+
+        let mut arp = vec![0xff; 6];
+        arp.extend_from_slice(&[0x02, 0, 0, 0, 0, 2, 0x08, 0x06, 0, 1, 0x08, 0, 6, 4, 0, 1]);
+        arp.extend_from_slice(&[0x02, 0, 0, 0, 0, 2, 10, 0, 0, 2, 0, 0, 0, 0, 0, 0, 10, 0, 0, 1]);
+        let arp = capture::decode_ethernet(&arp);
+        assert_eq!(capture::format_line(at, &arp), "12:00:01.000000 ARP 10.0.0.2 > 10.0.0.1: request length 42");
+
+        let lldp = capture::decode_ethernet(&[0x01, 0x80, 0xc2, 0, 0, 0x0e, 0x02, 0, 0, 0, 0, 2, 0x88, 0xcc, 0, 0]);
+        assert_eq!(lldp.protocol, Protocol::Other);
+        assert_eq!(capture::format_line(at, &lldp), "12:00:01.000000 ethertype 0x88cc length 16");
+
+        // A bare IP packet from a tun device
+        let bare = capture::decode_ip(&ipv4_frame(6, [10, 8, 0, 2], [10, 8, 0, 1], &tcp_header(1, 22, 0x18))[14..], 60);
+        assert_eq!((bare.protocol, bare.info.as_str(), bare.length), (Protocol::Tcp, "[P.]", 60));
+
+        assert_eq!(capture::tcp_flags(0x02), "S");
+        assert_eq!(capture::tcp_flags(0x11), "F.");
+        assert_eq!(capture::tcp_flags(0x14), "R.");
+
+        let mut counts = ProtocolCounts::default();
+        for packet in [&syn_ack, &udp, &echo, &arp, &lldp] {
+            counts.record(packet);
+        }
+        assert_eq!((counts.packets, counts.tcp, counts.udp, counts.icmp, counts.other), (5, 1, 1, 1, 2));
+        assert_eq!(counts.bytes, 54 + 42 + 42 + 42 + 16);
+    }
+
+    #[test]
+    fn test_capture_filters() {
+        let syn = capture::decode_ethernet(&ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_header(51234, 443, 0x02)));
+        let dns = capture::decode_ethernet(&ipv4_frame(17, [10, 0, 0, 2], [10, 0, 0, 1], &[0xc3, 0x50, 0, 53, 0, 8, 0, 0]));
+        let matches = |expression: &str, packet| Filter::parse(expression).unwrap().matches(packet);
+
+        assert!(matches("tcp port 443", &syn));
+        assert!(!matches("tcp port 443", &dns));
+        assert!(matches("udp and dst port 53", &dns));
+        assert!(!matches("src port 53", &dns));
+        assert!(matches("host 1.1.1.1 or port 53", &dns));
+        assert!(matches("net 10.0.0.0/24 and not tcp", &dns));
+        assert!(matches("dst 1.1.1.1 && (tcp || udp)", &syn));
+        assert!(matches("!udp", &syn));
+        assert!(matches("portrange 400-500 and greater 50", &syn));
+        assert!(!matches("less 50", &syn));
+        assert!(!matches("ip6", &syn));
+
+        let error = |expression: &str| Filter::parse(expression).unwrap_err().to_string();
+        assert!(error("tcp prot 80").contains("unknown primitive 'prot'"));
+        assert!(error("host 999.1.1.1").contains("'999.1.1.1' is not an IP address"));
+        assert!(error("port").contains("'port' needs a port number"));
+        assert!(error("(tcp or udp").contains("missing ')'"));
+        assert!(error("tcp )").contains("unexpected ')'"));
+        assert!(Filter::parse("   ").is_err());
+
+        assert_eq!(filter::compose(None, None, None, None), None);
+        assert_eq!(filter::compose(Some("tcp"), None, None, None).as_deref(), Some("tcp"));
+        assert_eq!(filter::compose(None, Some("10.0.0.1"), Some(53), Some("udp")).as_deref(), Some("udp and host 10.0.0.1 and port 53"));
+        assert_eq!(filter::compose(Some("icmp or arp"), None, Some(22), None).as_deref(), Some("(icmp or arp) and port 22"));
+        assert!(matches(&filter::compose(None, Some("1.1.1.1"), Some(443), Some("tcp")).unwrap(), &syn));
+    }
+
+    #[test]
+    fn test_read_capture_files() {
+        let frames = [
+            ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_segment(51234, 443, 0x02, 1000, 0)),
+            ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_segment(51234, 443, 0x18, 1001, 100)),
+            ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_segment(51234, 443, 0x18, 1001, 100)),
+            ipv4_frame(17, [10, 0, 0, 2], [10, 0, 0, 1], &[0xc3, 0x50, 0, 53, 0, 8, 0, 0]),
+            ipv4_frame(6, [1, 1, 1, 1], [10, 0, 0, 2], &tcp_segment(443, 51234, 0x14, 0, 0)),
+        ];
+
+        // Classic pcap, big-endian with nanosecond timestamps
+        let mut classic = Vec::new();
+        classic.extend_from_slice(&0xa1b2_3c4d_u32.to_be_bytes());
+        classic.extend_from_slice(&[0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 1]);
+        for (i, frame) in frames.iter().enumerate() {
+            classic.extend_from_slice(&1_700_000_000_u32.to_be_bytes());
+            classic.extend_from_slice(&(i as u32 * 250_000_000).to_be_bytes());
+            classic.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            classic.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            classic.extend_from_slice(frame);
+        }
+
+        // pcapng, little-endian, millisecond resolution, Linux cooked capture
+        let block = |kind: u32, body: &[u8]| {
+            let mut body = body.to_vec();
+            body.resize(body.len().next_multiple_of(4), 0);
+            let total = (body.len() + 12) as u32;
+            [&kind.to_le_bytes()[..], &total.to_le_bytes(), &body, &total.to_le_bytes()].concat()
+        };
+        let mut ng = block(0x0a0d_0d0a, &[&0x1a2b_3c4d_u32.to_le_bytes()[..], &[1, 0, 0, 0], &[0xff; 8]].concat());
+        ng.extend(block(1, &[113, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0]));
+        for (i, frame) in frames.iter().enumerate() {
+            let mut cooked = vec![0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 2, 0, 0];
+            cooked.extend_from_slice(&frame[12..]);
+            let ms = 1_700_000_000_000_u64 + i as u64 * 250;
+            let mut body = vec![0; 4];
+            body.extend_from_slice(&((ms >> 32) as u32).to_le_bytes());
+            body.extend_from_slice(&(ms as u32).to_le_bytes());
+            body.extend_from_slice(&(cooked.len() as u32).to_le_bytes());
+            body.extend_from_slice(&(cooked.len() as u32).to_le_bytes());
+            body.extend_from_slice(&cooked);
+            ng.extend(block(6, &body));
+        }
+
+        for (bytes, expected_linktype) in [(classic, linktype::ETHERNET), (ng, linktype::LINUX_SLL)] {
+            let mut reader = PcapReader::new(std::io::Cursor::new(bytes)).unwrap();
+            let mut stats = TrafficStats::default();
+            while let Some(record) = reader.next_record().unwrap() {
+                assert_eq!(record.linktype, expected_linktype);
+                let packet = capture::decode_frame(record.linktype, &record.data, record.orig_len);
+                stats.record(record.timestamp, &packet);
+            }
+
+            assert_eq!((stats.counts.packets, stats.counts.tcp, stats.counts.udp), (5, 4, 1));
+            assert_eq!(stats.duration(), std::time::Duration::from_secs(1));
+            assert_eq!((stats.retransmissions, stats.resets), (1, 1));
+
+            let by_packets = stats.top_talkers(2, |volume| volume.packets);
+            assert_eq!(by_packets[0].0.to_string(), "10.0.0.2");
+            assert_eq!(by_packets[0].1.packets, 5);
+            assert_eq!(by_packets[1].0.to_string(), "1.1.1.1");
+
+            let conversations = stats.conversations(10);
+            assert_eq!(conversations.len(), 2);
+            assert_eq!(conversations[0].protocol, Protocol::Tcp);
+            assert_eq!(conversations[0].volume.packets, 4);
+            assert_eq!(
+                (traffic::format_endpoint(conversations[0].a), traffic::format_endpoint(conversations[0].b)),
+                ("1.1.1.1:443".to_string(), "10.0.0.2:51234".to_string())
+            );
+        }
+
+        assert!(PcapReader::new(std::io::Cursor::new(b"GIF89a".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_stream_json_records() {
+        use netweaver_lib::diagnostics::filter::Filter;
+        use netweaver_lib::output::EventStream;
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        let frames = [
+            ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_header(51234, 443, 0x02)),
+            ipv4_frame(17, [10, 0, 0, 2], [10, 0, 0, 1], &[0xc3, 0x50, 0, 53, 0, 8, 0, 0]),
+        ];
+        let mut pcap = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0, 0, 0];
+        for (i, frame) in frames.iter().enumerate() {
+            pcap.extend_from_slice(&(1_700_000_000 + i as u32).to_le_bytes());
+            pcap.extend_from_slice(&[0; 4]);
+            pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            pcap.extend_from_slice(frame);
+        }
+        let dir = std::env::temp_dir().join(format!("netweaver-stream-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.pcap"), dir.join("out.jsonl"));
+        std::fs::write(&input, pcap).unwrap();
+
+        let filter = Filter::parse("udp").unwrap();
+        let mut stream = EventStream::open(output.to_str().unwrap()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let sinks = capture::PacketSinks { stream: Some(&mut stream), ..Default::default() };
+        let stats = capture::replay(&input, Some(&filter), None, sinks, stop).unwrap();
+        stream.emit("summary", &stats.report(10)).unwrap();
+        drop(stream);
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        // Packets carry their capture time, not the time they were streamed
+        assert_eq!(records[0]["type"], "packet");
+        assert_eq!(records[0]["timestamp"], "2023-11-14T22:13:21Z");
+        assert_eq!((&records[0]["protocol"], &records[0]["dst_port"]), (&"udp".into(), &53.into()));
+        assert_eq!(records[1]["type"], "summary");
+        assert!(records[1]["timestamp"].is_string());
+        assert_eq!(records[1]["counts"]["packets"], 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capture_file_rotation() {
+        use netweaver_lib::diagnostics::pcap::{Rotation, RotatingWriter};
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("netweaver-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("ring.pcap");
+        let frame = ipv4_frame(17, [10, 0, 0, 2], [10, 0, 0, 1], &[0xc3, 0x50, 0, 53, 0, 8, 0, 0]);
+        let at = |secs: i64| chrono::DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap();
+
+        // Two 58-byte records per 150-byte file, three files in the ring
+        let rotation = Rotation { max_bytes: Some(150), interval: None, max_files: Some(3) };
+        let mut writer = RotatingWriter::new(&base, rotation);
+        for i in 0..7 {
+            writer.write(at(i), linktype::ETHERNET, &frame, frame.len()).unwrap();
+        }
+        let files = writer.finish().unwrap();
+        let listed: Vec<(String, u64)> = files.iter()
+            .map(|file| (file.path.file_name().unwrap().to_string_lossy().into_owned(), file.packets))
+            .collect();
+        // The fourth file wrapped around onto ring.pcap
+        assert_eq!(listed, [("ring.pcap.1".to_string(), 2), ("ring.pcap.2".to_string(), 2), ("ring.pcap".to_string(), 1)]);
+
+        // Every packet written is readable, and the files are no larger than reported
+        let mut total = 0;
+        for file in &files {
+            assert_eq!(std::fs::metadata(&file.path).unwrap().len(), file.bytes);
+            let mut reader = PcapReader::open(&file.path).unwrap();
+            while let Some(record) = reader.next_record().unwrap() {
+                assert_eq!((record.linktype, record.data.as_slice()), (linktype::ETHERNET, frame.as_slice()));
+                total += 1;
+            }
+        }
+        assert_eq!(total, 5);
+        let mut reader = PcapReader::open(&base).unwrap();
+        assert_eq!(reader.next_record().unwrap().unwrap().timestamp, at(6));
+
+        // By time: a new file once the current one spans the interval
+        let rotation = Rotation { max_bytes: None, interval: Some(Duration::from_secs(60)), max_files: None };
+        let mut writer = RotatingWriter::new(dir.join("timed.pcap"), rotation);
+        for secs in [0, 30, 59, 60, 200] {
+            writer.write(at(secs), linktype::ETHERNET, &frame, frame.len()).unwrap();
+        }
+        // A raw IP packet can't go in an Ethernet capture
+        writer.write(at(201), linktype::RAW, &frame[14..], frame.len() - 14).unwrap();
+        assert_eq!(writer.skipped, 1);
+        let packets: Vec<u64> = writer.finish().unwrap().iter().map(|file| file.packets).collect();
+        assert_eq!(packets, [3, 1, 1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capture_statistics() {
+        use netweaver_lib::diagnostics::capstats::{parse_proc_packet, CaptureStats};
+
+        let proc_packet = "sk               RefCnt Type Proto  Iface R Rmem   User   Inode\n\
+                           000000007d9e9d5c 3      3    0003   1     1 0      0      194836\n";
+        assert_eq!(parse_proc_packet(proc_packet), [194836].into());
+
+        let stats = CaptureStats { kernel_received: Some(990), kernel_dropped: Some(10), buffer_bytes: Some(4 << 20), peak_packets_per_sec: 1500.0 };
+        assert_eq!(stats.drop_rate(), Some(1.0));
+        assert_eq!(CaptureStats::default().drop_rate(), None);
+
+        let line = capture::StatusLine { packets: 1520, bytes: 1_258_291, packets_per_sec: 310.4, kernel_dropped: Some(0), dropped_since_last: 0 };
+        assert_eq!(capture::format_status(&line), "📊 1520 packets, 1.20 MB, 310 pkt/s, 0 dropped");
+        let line = capture::StatusLine { kernel_dropped: None, ..line };
+        assert!(capture::format_status(&line).ends_with("drops unknown"));
+
+        // A live capture's statistics ride along in its analysis; a file's have none
+        let mut traffic = TrafficStats::default();
+        assert!(serde_json::to_value(traffic.report(10)).unwrap().get("capture").is_none());
+        traffic.capture = Some(stats);
+        let report = serde_json::to_value(traffic.report(10)).unwrap();
+        assert_eq!(report["capture"]["kernel_dropped"], 10);
+    }
+
+    #[test]
+    fn test_traffic_analysis_report() {
+        let at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut stats = TrafficStats::default();
+
+        let mut full = tcp_segment(51234, 443, 0x10, 5000, 0);
+        full[14..16].copy_from_slice(&[0, 0]);
+        stats.record(at, &capture::decode_ethernet(&ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &full)));
+        stats.record(at, &capture::decode_ethernet(&ipv4_frame(6, [10, 0, 0, 2], [1, 1, 1, 1], &tcp_segment(51234, 443, 0x18, 1, 1400))));
+        stats.record(at, &capture::decode_ethernet(&ipv4_frame(17, [10, 0, 0, 2], [10, 0, 0, 1], &[0xc3, 0x50, 0, 53, 0, 8, 0, 0])));
+
+        assert_eq!((stats.counts.tcp_bytes, stats.counts.udp_bytes), (54 + 1454, 42));
+        assert_eq!(stats.zero_windows, 1);
+        assert_eq!(stats.sizes, [2, 0, 0, 0, 0, 1, 0]);
+
+        let report = stats.report(1);
+        assert_eq!(report.top_talkers_by_bytes.len(), 1);
+        assert_eq!(report.sizes[5].range, "1024-1517");
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["top_talkers_by_bytes"][0]["ip"], "10.0.0.2");
+        assert_eq!(json["top_talkers_by_bytes"][0]["bytes"], 54 + 1454 + 42);
+        assert_eq!(json["conversations"][0]["b"]["port"], 51234);
+        assert_eq!(json["zero_windows"], 1);
+        assert_eq!(json["truncated"], false);
+
+        // Memory stays bounded: the quietest half goes once a table fills up
+        let mut stats = TrafficStats::default();
+        let busy = capture::decode_ethernet(&ipv4_frame(17, [10, 0, 0, 9], [10, 0, 0, 1], &[0, 1, 0, 2, 0, 8, 0, 0]));
+        for _ in 0..5 {
+            stats.record(at, &busy);
+        }
+        for i in 0..traffic::MAX_TRACKED as u32 {
+            let [_, b, c, d] = i.to_be_bytes();
+            let packet = capture::decode_ethernet(&ipv4_frame(17, [172, b, c, d], [192, 0, 2, 1], &[0, 1, 0, 2, 0, 8, 0, 0]));
+            stats.record(at, &packet);
+        }
+        assert!(stats.truncated);
+        assert!(stats.conversations(usize::MAX).len() <= traffic::MAX_TRACKED);
+        assert!(stats.top_talkers(usize::MAX, |volume| volume.bytes).len() <= traffic::MAX_TRACKED);
+        let top = stats.top_talkers(2, |volume| volume.packets);
+        assert_eq!(top[0].0.to_string(), "192.0.2.1");
+        assert_eq!(top[1].0.to_string(), "10.0.0.1");
+        assert_eq!(stats.counts.packets, 5 + traffic::MAX_TRACKED as u64);
+    }
+}
+
+mod dns_tests {
+    use netweaver_lib::diagnostics::dns::{self, DnsAnswer, DnsQuery, RecordKind, Resolver};
+    use hickory_resolver::proto::op::{Message, ResponseCode};
+    use hickory_resolver::proto::rr::{rdata, Name, RData, Record};
+
+    fn query(label: &str, kind: RecordKind, answers: &[(&str, &str)]) -> DnsQuery {
+        DnsQuery {
+            resolver: Resolver { label: label.to_string(), server: "192.0.2.53:53".parse().unwrap() },
+            record_type: kind,
+            latency_ms: Some(10.0),
+            rcode: Some("NOERROR".to_string()),
+            authenticated: false,
+            answers: answers
+                .iter()
+                .map(|(record_type, data)| DnsAnswer {
+                    name: "example.com.".to_string(),
+                    record_type: record_type.to_string(),
+                    ttl: 300,
+                    data: data.to_string(),
+                })
+                .collect(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_dns_answers_and_resolver_comparison() {
+        assert_eq!(dns::parse_resolver("9.9.9.9").unwrap().server, "9.9.9.9:53".parse().unwrap());
+        assert_eq!(dns::parse_resolver("127.0.0.1:5353").unwrap().server, "127.0.0.1:5353".parse().unwrap());
+        assert_eq!(dns::parse_resolver("[2620:fe::fe]:53").unwrap().server.port(), 53);
+        assert!(dns::parse_resolver("dns.google").is_err());
+
+        let name = Name::from_utf8("www.example.com.").unwrap();
+        let target = Name::from_utf8("example.com.").unwrap();
+        let mut response = Message::new();
+        response.add_answer(Record::from_rdata(name, 60, RData::CNAME(rdata::CNAME(target.clone()))));
+        response.add_answer(Record::from_rdata(target, 300, RData::A("93.184.216.34".parse().unwrap())));
+        let answers = dns::answers(&response);
+        assert_eq!(answers.len(), 2);
+        assert_eq!((answers[0].record_type.as_str(), answers[0].data.as_str(), answers[0].ttl), ("CNAME", "example.com.", 60));
+        assert_eq!((answers[1].record_type.as_str(), answers[1].data.as_str()), ("A", "93.184.216.34"));
+
+        assert_eq!(dns::rcode_name(ResponseCode::NXDomain), "NXDOMAIN");
+        assert_eq!(dns::rcode_name(ResponseCode::ServFail), "SERVFAIL");
+
+        // Order doesn't matter, CNAMEs on the way don't count, a silent resolver isn't compared
+        let mut silent = query("Quad9", RecordKind::A, &[]);
+        silent.latency_ms = None;
+        let queries = vec![
+            query("System", RecordKind::A, &[("A", "10.0.0.1"), ("A", "10.0.0.2")]),
+            query("Google", RecordKind::A, &[("CNAME", "edge.example.net."), ("A", "10.0.0.2"), ("A", "10.0.0.1")]),
+            query("Cloudflare", RecordKind::A, &[("A", "203.0.113.9")]),
+            silent,
+            query("System", RecordKind::Mx, &[("MX", "10 mail.example.com.")]),
+        ];
+        assert_eq!(queries[1].answer_set(), vec!["10.0.0.1", "10.0.0.2"]);
+        let odd: Vec<&str> = dns::disagreeing(&queries, RecordKind::A).iter().map(|q| q.resolver.label.as_str()).collect();
+        assert_eq!(odd, vec!["Cloudflare"]);
+        assert!(dns::disagreeing(&queries, RecordKind::Mx).is_empty());
+        assert!(dns::disagreeing(&queries, RecordKind::Txt).is_empty());
+    }
+}
+
+mod throughput_tests {
+    use netweaver_lib::diagnostics::throughput::{self, Direction, StreamHeader, ThroughputInterval, ThroughputResult};
+    use netweaver_lib::utils;
+    use std::time::Duration;
+
+    #[test]
+    fn test_throughput_header_and_summary() {
+        let header = StreamHeader { direction: Direction::Download, stream: 2, streams: 4, duration: Duration::from_secs(10) };
+        assert_eq!(StreamHeader::decode(&header.encode()).unwrap(), header);
+        let mut bogus = header.encode();
+        bogus[..4].copy_from_slice(b"GET ");
+        assert!(StreamHeader::decode(&bogus).is_err());
+
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+        assert_eq!(throughput::percentile(&sorted, 50.0), 5.0);
+        assert_eq!(throughput::percentile(&sorted, 90.0), 9.0);
+        assert_eq!(throughput::percentile(&sorted, 100.0), 10.0);
+        assert_eq!(throughput::percentile(&[], 50.0), 0.0);
+
+        let intervals: Vec<ThroughputInterval> = [900e6, 950e6, 400e6]
+            .iter()
+            .enumerate()
+            .map(|(i, &bps)| ThroughputInterval {
+                start_secs: i as f64,
+                end_secs: i as f64 + 1.0,
+                bytes: (bps / 8.0) as u64,
+                bits_per_sec: bps,
+            })
+            .collect();
+        let bytes = intervals.iter().map(|i| i.bytes).sum();
+        let result = ThroughputResult::new("lab".to_string(), Direction::Upload, 1, bytes, Duration::from_secs(3), intervals);
+        assert!((result.avg_bits_per_sec - 750e6).abs() < 1.0);
+        assert_eq!((result.min_bits_per_sec, result.p50_bits_per_sec, result.max_bits_per_sec), (400e6, 900e6, 950e6));
+
+        let json = serde_json::to_string(&result).unwrap();
+        let dir = std::env::temp_dir().join(format!("netweaver-throughput-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("throughput.json");
+        assert!(throughput::load_latest(&path).unwrap().is_none());
+        std::fs::write(&path, json).unwrap();
+        let loaded = throughput::load_latest(&path).unwrap().unwrap();
+        assert_eq!((loaded.server.as_str(), loaded.direction, loaded.intervals.len()), ("lab", Direction::Upload, 3));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(utils::format_bitrate(941_230_000.0), "941.23 Mbit/s");
+        assert_eq!(utils::format_bitrate(950.0), "950.00 bit/s");
+        assert_eq!(utils::format_bytes(1536), "1.50 KB");
+    }
+}
+
+mod connections_tests {
+    use netweaver_lib::diagnostics::connections::{self, Connection, ConnectionFilter, SocketState, Transport};
+    use std::net::SocketAddr;
+
+    const PROC_TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0CEA 00000000:0000 0A 00000000:00000000 00:00000000 00000000   999        0 41233 1 0000000000000000 100 0 0 10 0
+   1: 0501A8C0:D431 22D8B85D:01BB 01 00000000:00000000 02:000A7E2B 00000000  1000        0 98765 2 0000000000000000 20 4 30 10 -1
+   2: 0501A8C0:D432 22D8B85D:01BB 06 00000000:00000000 03:00000F3A 00000000     0        0 0 3 0000000000000000
+";
+
+    const PROC_UDP6: &str = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  10: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 5512 2 0000000000000000 0
+  11: 0000000000000000FFFF00000100007F:1F90 0000000000000000FFFF00000100007F:A5E2 01 00000000:00000000 00:00000000 00000000     0        0 5513 2 0000000000000000 0
+";
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_parse_proc_net() {
+        let tcp = connections::parse_proc_net(PROC_TCP, Transport::Tcp);
+        assert_eq!(tcp.len(), 3);
+        assert_eq!((tcp[0].local, tcp[0].remote, tcp[0].state), (addr("127.0.0.1:3306"), addr("0.0.0.0:0"), SocketState::Listen));
+        assert_eq!(tcp[0].inode, 41233);
+        assert_eq!((tcp[1].local, tcp[1].remote), (addr("192.168.1.5:54321"), addr("93.184.216.34:443")));
+        assert_eq!(tcp[1].state, SocketState::Established);
+        assert_eq!(tcp[2].state, SocketState::TimeWait);
+
+        let udp = connections::parse_proc_net(PROC_UDP6, Transport::Udp);
+        assert_eq!((udp[0].local, udp[0].state), (addr("[::1]:53"), SocketState::Unconnected));
+        // IPv4 peers of dual-stack sockets come out as plain IPv4
+        assert_eq!((udp[1].local, udp[1].remote), (addr("127.0.0.1:8080"), addr("127.0.0.1:42466")));
+        assert_eq!(udp[1].state, SocketState::Established);
+    }
+
+    #[test]
+    fn test_parse_netstat() {
+        let bsd = "Active Internet connections (including servers)
+Proto Recv-Q Send-Q  Local Address          Foreign Address        (state)
+tcp4       0      0  192.168.1.5.52345      17.57.144.7.5223       ESTABLISHED
+tcp46      0      0  *.22                   *.*                    LISTEN
+udp4       0      0  *.5353                 *.*
+";
+        let conns = connections::parse_netstat(bsd);
+        assert_eq!(conns.len(), 3);
+        assert_eq!((conns[0].local, conns[0].remote), (addr("192.168.1.5:52345"), addr("17.57.144.7:5223")));
+        assert_eq!((conns[1].local.port(), conns[1].state), (22, SocketState::Listen));
+        assert_eq!((conns[2].protocol, conns[2].state), (Transport::Udp, SocketState::Unconnected));
+
+        let windows = "
+  Proto  Local Address          Foreign Address        State
+  TCP    0.0.0.0:135            0.0.0.0:0              LISTENING
+  TCP    10.0.0.5:50123         52.96.1.2:443          ESTABLISHED
+  TCP    [::1]:5357             [::]:0                 LISTENING
+  UDP    0.0.0.0:5353           *:*
+";
+        let conns = connections::parse_netstat(windows);
+        assert_eq!(conns.len(), 4);
+        assert_eq!((conns[0].local.port(), conns[0].state), (135, SocketState::Listen));
+        assert_eq!((conns[1].remote, conns[1].state), (addr("52.96.1.2:443"), SocketState::Established));
+        assert_eq!(conns[2].local, addr("[::1]:5357"));
+        assert_eq!((conns[3].remote.port(), conns[3].state), (0, SocketState::Unconnected));
+    }
+
+    #[test]
+    fn test_connection_filter() {
+        let conn = Connection {
+            protocol: Transport::Tcp,
+            local: addr("192.168.1.5:54321"),
+            remote: addr("93.184.216.34:443"),
+            state: SocketState::Established,
+            inode: 1,
+            pid: Some(4242),
+            process: Some("Firefox".to_string()),
+        };
+        assert!(ConnectionFilter::default().matches(&conn));
+        assert!(ConnectionFilter { port: Some(443), ..Default::default() }.matches(&conn));
+        assert!(!ConnectionFilter { port: Some(80), ..Default::default() }.matches(&conn));
+        assert!(!ConnectionFilter { state: Some(SocketState::Listen), ..Default::default() }.matches(&conn));
+        assert!(ConnectionFilter { process: Some("firefox".to_string()), ..Default::default() }.matches(&conn));
+        assert!(ConnectionFilter { process: Some("4242".to_string()), ..Default::default() }.matches(&conn));
+        assert!(!ConnectionFilter { process: Some("sshd".to_string()), ..Default::default() }.matches(&conn));
+        assert!(!ConnectionFilter { udp_only: true, ..Default::default() }.matches(&conn));
+
+        assert_eq!(conn.owner(), "Firefox/4242");
+        assert_eq!(connections::format_endpoint(&addr("0.0.0.0:0")), "*:*");
+        assert_eq!(connections::format_endpoint(&addr("[::]:22")), "*:22");
+    }
+
+    #[test]
+    fn test_top_connections() {
+        let conn = |protocol, local: &str, state, pid| Connection {
+            protocol,
+            local: addr(local),
+            remote: addr("0.0.0.0:0"),
+            state,
+            inode: 0,
+            pid,
+            process: None,
+        };
+        let all = vec![
+            conn(Transport::Tcp, "0.0.0.0:22", SocketState::Listen, Some(1)),
+            conn(Transport::Tcp, "10.0.0.5:50000", SocketState::TimeWait, None),
+            conn(Transport::Udp, "0.0.0.0:53", SocketState::Unconnected, Some(2)),
+            conn(Transport::Tcp, "10.0.0.5:50001", SocketState::Established, None),
+            conn(Transport::Tcp, "10.0.0.5:50002", SocketState::Established, Some(3)),
+            conn(Transport::Tcp, "10.0.0.5:50003", SocketState::SynSent, Some(4)),
+        ];
+        let ports = |conns: Vec<Connection>| conns.iter().map(|conn| conn.local.port()).collect::<Vec<_>>();
+
+        assert_eq!(ports(connections::top_connections(all.clone(), None, 10)), vec![50002, 50001, 50003, 50000, 22, 53]);
+        assert_eq!(ports(connections::top_connections(all.clone(), None, 2)), vec![50002, 50001]);
+        assert_eq!(ports(connections::top_connections(all.clone(), Some(Transport::Udp), 10)), vec![53]);
+        assert!(connections::top_connections(Vec::new(), Some(Transport::Tcp), 10).is_empty());
+    }
+}
+
+mod http_timing_tests {
+    use netweaver_lib::diagnostics::http::{HttpAttempt, HttpRequestTiming, HttpTimingResult, PhaseTimings, Url};
+
+    fn request(url: &str, status: u16, location: Option<&str>, timings: PhaseTimings) -> HttpRequestTiming {
+        HttpRequestTiming {
+            url: url.to_string(),
+            address: "93.184.216.34:443".parse().unwrap(),
+            status,
+            location: location.map(str::to_string),
+            tls_version: None,
+            body_bytes: 0,
+            timings,
+        }
+    }
+
+    #[test]
+    fn test_url_parse_and_join() {
+        let url = Url::parse("https://api.example.com:8443/v1/users?page=2").unwrap();
+        assert_eq!((url.tls, url.host.as_str(), url.port, url.path.as_str()), (true, "api.example.com", 8443, "/v1/users?page=2"));
+        assert_eq!(url.to_string(), "https://api.example.com:8443/v1/users?page=2");
+
+        let bare = Url::parse("example.com").unwrap();
+        assert_eq!((bare.tls, bare.port, bare.path.as_str()), (false, 80, "/"));
+        assert_eq!(bare.to_string(), "http://example.com/");
+        assert!(Url::parse("ftp://example.com/").is_err());
+
+        assert_eq!(url.join("/login").unwrap().to_string(), "https://api.example.com:8443/login");
+        assert_eq!(url.join("groups").unwrap().to_string(), "https://api.example.com:8443/v1/groups");
+        assert_eq!(url.join("//cdn.example.com/a").unwrap().to_string(), "https://cdn.example.com/a");
+        assert_eq!(bare.join("https://www.example.com/").unwrap().to_string(), "https://www.example.com/");
+    }
+
+    #[test]
+    fn test_http_timing_result() {
+        let phases = |dns, tls, ttfb| PhaseTimings {
+            dns_ms: dns,
+            connect_ms: 10.0,
+            tls_ms: tls,
+            ttfb_ms: ttfb,
+            download_ms: 5.0,
+            total_ms: dns.unwrap_or(0.0) + 10.0 + tls.unwrap_or(0.0) + ttfb + 5.0,
+        };
+        // http:// redirecting to https://, then a slower and a failed attempt
+        let redirected = HttpAttempt {
+            requests: vec![
+                request("http://example.com/", 301, Some("https://example.com/"), phases(Some(20.0), None, 15.0)),
+                request("https://example.com/", 200, None, phases(Some(2.0), Some(30.0), 45.0)),
+            ],
+            error: None,
+        };
+        let slow = HttpAttempt {
+            requests: vec![request("https://example.com/", 200, None, phases(Some(2.0), Some(30.0), 245.0))],
+            error: None,
+        };
+        let failed = HttpAttempt { requests: Vec::new(), error: Some("timed out".to_string()) };
+
+        let chain = redirected.timings();
+        assert_eq!((chain.dns_ms, chain.tls_ms, chain.connect_ms), (Some(22.0), Some(30.0), 20.0));
+
+        let result = HttpTimingResult::new("http://example.com/".to_string(), "GET".to_string(), vec![redirected, slow, failed]);
+        assert_eq!(result.failures, 1);
+        assert_eq!(result.status, Some(200));
+        assert_eq!(result.final_url.as_deref(), Some("https://example.com/"));
+        assert_eq!(result.redirects.len(), 1);
+        assert_eq!((result.redirects[0].status, result.redirects[0].to.as_str()), (301, "https://example.com/"));
+
+        let stats = result.stats.unwrap();
+        assert_eq!((stats.ttfb.min_ms, stats.ttfb.avg_ms, stats.ttfb.max_ms), (60.0, 152.5, 245.0));
+        assert_eq!(stats.connect.max_ms, 20.0);
+        assert_eq!(stats.tls.map(|tls| tls.avg_ms), Some(30.0));
+        assert_eq!(stats.slowest().0, "TTFB");
+        let labels: Vec<&str> = stats.phases().iter().map(|(label, _)| *label).collect();
+        assert_eq!(labels, ["DNS", "Connect", "TLS", "TTFB", "Download"]);
+
+        let all_failed = HttpTimingResult::new("x".to_string(), "GET".to_string(), vec![HttpAttempt { requests: Vec::new(), error: Some("refused".to_string()) }]);
+        assert!(all_failed.stats.is_none() && all_failed.status.is_none());
+    }
+}
+
+mod tls_inspection_tests {
+    use netweaver_lib::diagnostics::tls::{self, TlsVersion};
+
+    #[test]
+    fn test_legacy_client_hello() {
+        let hello = tls::client_hello(TlsVersion::Tls11.wire(), Some("example.com"));
+        // Handshake record holding a ClientHello offering TLS 1.1
+        assert_eq!(&hello[..3], &[0x16, 0x03, 0x01]);
+        assert_eq!(u16::from_be_bytes([hello[3], hello[4]]) as usize, hello.len() - 5);
+        assert_eq!(hello[5], 0x01);
+        assert_eq!(&hello[9..11], &[0x03, 0x02]);
+        assert!(hello.windows(11).any(|w| w == b"example.com"));
+        // No SNI for addresses
+        let by_ip = tls::client_hello(TlsVersion::Tls10.wire(), Some("192.0.2.1"));
+        assert_eq!(hello.len() - by_ip.len(), "example.com".len() + 9);
+
+        let server_hello = [0x16, 0x03, 0x01, 0x00, 0x31, 0x02, 0x00, 0x00, 0x2d, 0x03, 0x01];
+        assert_eq!(tls::server_hello_version(&server_hello), Some(0x0301));
+        let alert = [0x15, 0x03, 0x01, 0x00, 0x02, 0x02, 0x46];
+        assert_eq!(tls::server_hello_version(&alert), None);
+        assert_eq!(tls::server_hello_version(&[]), None);
+
+        assert!(TlsVersion::Tls11.is_deprecated() && !TlsVersion::Tls12.is_deprecated());
+        assert_eq!(TlsVersion::Tls13.to_string(), "TLS 1.3");
+    }
+
+    #[test]
+    fn test_certificate_details() {
+        let mut params = rcgen::CertificateParams::new(vec!["api.example.com".to_string()]);
+        params.distinguished_name.push(rcgen::DnType::CommonName, "api.example.com");
+        let der = rcgen::Certificate::from_params(params).unwrap().serialize_der().unwrap();
+
+        let cert = tls::describe_certificate(443, &der).unwrap();
+        assert_eq!(cert.subject, "CN=api.example.com");
+        assert_eq!(cert.san, vec!["api.example.com"]);
+        // rcgen defaults to ECDSA P-256
+        assert_eq!((cert.key_type.as_str(), cert.key_bits), ("EC", 256));
+        assert!(cert.self_signed);
+    }
+}
+
+mod monitor_tests {
+    use netweaver_lib::monitor::interfaces::CRC_ERRORS;
+    use netweaver_lib::monitor::{select_interface, total_stats, traffic_delta, traffic_deltas, NetworkStats, ALL_INTERFACES};
+
+    /// `interface`'s counters `second` seconds in, having sent `sent` bytes and received twice that
+    fn sample(interface: &str, second: u64, sent: u64) -> NetworkStats {
+        NetworkStats {
+            interface: interface.to_string(),
+            bytes_sent: sent,
+            bytes_recv: sent * 2,
+            packets_sent: sent / 100,
+            timestamp: second * 1_000_000,
+            ..Default::default()
+        }
+    }
+
+    /// `stats` with `crc` CRC errors, counted in `errors` too
+    fn with_crc_errors(stats: NetworkStats, crc: u64) -> NetworkStats {
+        let error_detail = [(CRC_ERRORS.to_string(), crc)].into_iter().filter(|(_, count)| *count > 0).collect();
+        NetworkStats { errors: crc, error_detail, ..stats }
+    }
+
+    #[test]
+    fn test_per_interface_statistics() {
+        let all = vec![
+            NetworkStats { drops: Some(0), ..sample("docker0", 1, 1_000) },
+            NetworkStats { drops: Some(3), ..sample("eth0", 2, 50_000) },
+            sample("lo", 9, 900_000),
+        ];
+
+        assert_eq!(select_interface(&all, "eth0").unwrap().bytes_sent, 50_000);
+        let error = select_interface(&all, "wlan0").unwrap_err().to_string();
+        assert_eq!(error, "No interface named 'wlan0'. Available: docker0, eth0, lo");
+
+        let total = total_stats(&all);
+        assert_eq!(total.interface, ALL_INTERFACES);
+        // An interface without a drop counter doesn't make the total unavailable
+        assert_eq!((total.bytes_sent, total.bytes_recv, total.drops), (951_000, 1_902_000, Some(3)));
+        assert_eq!(total.timestamp, 9_000_000);
+        assert_eq!(total_stats(&[]).bytes_sent, 0);
+        assert_eq!(total_stats(&all[2..]).drops, None);
+
+        // Unavailable stays unavailable through deltas, not zero
+        let later = |drops| NetworkStats { drops, ..sample("eth0", 20, 60_000) };
+        assert_eq!(traffic_delta(&all[1], &later(Some(5))).drops, Some(2));
+        assert_eq!(traffic_delta(&all[1], &later(None)).drops, None);
+        assert_eq!(traffic_deltas(&all[2..], &[NetworkStats { interface: "lo".to_string(), ..later(None) }])[1].drops, None);
+    }
+
+    #[test]
+    fn test_interface_rates() {
+        let delta = traffic_delta(&sample("eth0", 10, 1_000_000), &sample("eth0", 12, 3_000_000));
+        assert_eq!((delta.bytes_sent, delta.bytes_recv, delta.seconds), (2_000_000, 4_000_000, 2.0));
+        assert_eq!((delta.tx_rate(), delta.rx_rate(), delta.packet_rate()), (1_000_000.0, 2_000_000.0, 10_000.0));
+
+        // A recreated interface starts its counters again
+        let reset = traffic_delta(&sample("tun0", 10, 5_000_000), &sample("tun0", 11, 4_000));
+        assert_eq!((reset.bytes_sent, reset.tx_rate()), (4_000, 4_000.0));
+        assert_eq!(traffic_delta(&sample("lo", 5, 0), &sample("lo", 5, 100)).tx_rate(), 0.0);
+
+        // An interface that vanished or just appeared moves nothing in the sum
+        let prev = vec![sample("eth0", 10, 1_000_000), sample("veth1", 10, 9_000_000_000)];
+        let cur = vec![sample("docker0", 11, 7_000_000_000), sample("eth0", 11, 1_500_000)];
+        let deltas = traffic_deltas(&prev, &cur);
+        let names: Vec<&str> = deltas.iter().map(|delta| delta.interface.as_str()).collect();
+        assert_eq!(names, vec!["eth0", ALL_INTERFACES]);
+        assert_eq!((deltas[1].bytes_sent, deltas[1].tx_rate()), (500_000, 500_000.0));
+        assert_eq!(traffic_deltas(&[], &cur).last().unwrap().bytes_sent, 0);
+    }
+
+    #[test]
+    fn test_daemon_pid_file_and_log() {
+        use netweaver_lib::monitor::daemon::{self, PidFile, RotatingLog};
+
+        let dir = tempfile::tempdir().unwrap();
+        let pid_path = dir.path().join("netweaver.pid");
+        let pid_file = PidFile::acquire(&pid_path).unwrap();
+        assert_eq!(daemon::running_pid(&pid_path), Some(std::process::id()));
+        let error = PidFile::acquire(&pid_path).unwrap_err().to_string();
+        assert!(error.starts_with("NetWeaver daemon already running"), "{}", error);
+        drop(pid_file);
+        assert!(!pid_path.exists());
+
+        // Left behind by a process that is gone
+        std::fs::write(&pid_path, "999999999\n").unwrap();
+        assert_eq!(daemon::running_pid(&pid_path), None);
+        let _pid_file = PidFile::acquire(&pid_path).unwrap();
+        assert_eq!(daemon::running_pid(&pid_path), Some(std::process::id()));
+
+        let log_path = dir.path().join("netweaver.log");
+        let mut log = RotatingLog::open(&log_path, 100, 2).unwrap();
+        for n in 0..10 {
+            log.append(&serde_json::json!({ "sample": n, "padding": "x".repeat(20) })).unwrap();
+        }
+        log.flush().unwrap();
+        let lines = |path: &std::path::Path| std::fs::read_to_string(path).unwrap().lines().count();
+        // 46-byte lines, two to a file; the oldest fall off past .2
+        assert_eq!((lines(&log_path), lines(&log.path_of(1)), lines(&log.path_of(2))), (2, 2, 2));
+        assert!(!log.path_of(3).exists());
+        assert!(std::fs::read_to_string(&log_path).unwrap().contains("\"sample\":9"));
+        assert!(std::fs::read_to_string(log.path_of(2)).unwrap().contains("\"sample\":4"));
+
+    }
+
+    #[test]
+    fn test_protocol_counters() {
+        use netweaver_lib::monitor::protocols;
+
+        let snmp = "Ip: Forwarding DefaultTTL InReceives
+Ip: 2 64 4073411
+Icmp: InMsgs InErrors InDestUnreachs InEchos InEchoReps OutMsgs OutErrors OutDestUnreachs OutEchos OutEchoReps
+Icmp: 120 4 100 10 10 130 0 110 10 10
+Tcp: RtoAlgorithm RtoMin RtoMax MaxConn ActiveOpens PassiveOpens AttemptFails EstabResets CurrEstab InSegs OutSegs RetransSegs InErrs OutRsts InCsumErrors
+Tcp: 1 200 120000 -1 3057 715 2465 467 2 549623 548000 1096 0 2898 0
+Udp: InDatagrams NoPorts InErrors OutDatagrams RcvbufErrors SndbufErrors InCsumErrors IgnoredMulti MemErrors
+Udp: 242 17 3 1302 2 0 0 1 0
+";
+        let netstat = "TcpExt: SyncookiesSent ListenDrops TCPTimeouts
+TcpExt: 0 5 31
+";
+        let sections = protocols::parse_snmp(snmp);
+        assert_eq!(sections["Ip"]["InReceives"], 4_073_411);
+        // -1 isn't a counter
+        assert!(!sections["Tcp"].contains_key("MaxConn"));
+
+        let counters = protocols::protocol_stats_from(snmp, netstat);
+        let tcp = counters.tcp.clone().unwrap();
+        assert_eq!((tcp.in_segs, tcp.out_segs, tcp.retrans_segs, tcp.curr_estab), (549_623, 548_000, 1096, 2));
+        assert_eq!((tcp.timeouts, tcp.listen_drops), (Some(31), Some(5)));
+        assert!((tcp.retransmit_rate() - 0.2).abs() < 1e-9);
+        let udp = counters.udp.clone().unwrap();
+        assert_eq!((udp.in_datagrams, udp.out_datagrams, udp.no_ports, udp.in_errors, udp.rcvbuf_errors), (242, 1302, 17, 3, 2));
+        let icmp = counters.icmp.clone().unwrap();
+        assert_eq!((icmp.in_msgs, icmp.out_dest_unreachs, icmp.in_echos), (120, 110, 10));
+        assert_eq!(protocols::protocol_stats_from(snmp, "").tcp.unwrap().timeouts, None);
+
+        let tcp_only = counters.clone().only("tcp");
+        assert!(tcp_only.tcp.is_some() && tcp_only.udp.is_none() && tcp_only.icmp.is_none());
+        assert_eq!(counters.clone().only("all"), counters);
+        let json = serde_json::to_value(&tcp_only).unwrap();
+        assert_eq!(json["tcp"]["retrans_segs"], 1096);
+        assert!(json.get("udp").is_none());
+
+        let mut later = counters.clone();
+        let tcp = later.tcp.as_mut().unwrap();
+        tcp.out_segs += 1_000;
+        tcp.retrans_segs += 50;
+        tcp.curr_estab = 7;
+        // Counters that went backwards were reset
+        later.udp.as_mut().unwrap().in_datagrams = 10;
+        let moved = later.since(&counters);
+        let tcp = moved.tcp.unwrap();
+        assert_eq!((tcp.out_segs, tcp.retrans_segs, tcp.curr_estab, tcp.in_segs), (1_000, 50, 7, 0));
+        assert_eq!(tcp.retransmit_rate(), 5.0);
+        assert_eq!(moved.udp.unwrap().in_datagrams, 10);
+    }
+
+    #[test]
+    fn test_alert_rules() {
+        use netweaver_lib::monitor::alerts::{self, AlertEngine, AlertInput, AlertRule, AlertState, Metric, MonitorConfig};
+        use netweaver_lib::monitor::TrafficDelta;
+        use std::time::{Duration, Instant};
+
+        let rule: AlertRule = "rx > 50MB/s for 30s".parse().unwrap();
+        assert_eq!((rule.metric, rule.threshold, rule.hold), (Metric::Rx, 50.0 * 1024.0 * 1024.0, Duration::from_secs(30)));
+        let rule: AlertRule = "TX > 800 KB/s".parse().unwrap();
+        assert_eq!((rule.metric, rule.threshold, rule.hold), (Metric::Tx, 800.0 * 1024.0, Duration::ZERO));
+        assert_eq!("errors".parse::<AlertRule>().unwrap().threshold, 0.0);
+        assert_eq!("drops > 100 for 1m".parse::<AlertRule>().unwrap().threshold, 100.0);
+        assert_eq!("down".parse::<AlertRule>().unwrap().metric, Metric::Down);
+        assert!("rx".parse::<AlertRule>().is_err());
+        assert!("down > 1".parse::<AlertRule>().is_err());
+        assert!("latency > 5".parse::<AlertRule>().is_err());
+        assert!("rx > fast".parse::<AlertRule>().is_err());
+
+        let input = |interface: &str, bytes_recv: u64, down: bool| AlertInput {
+            interface: interface.to_string(),
+            delta: Some(TrafficDelta { interface: interface.to_string(), bytes_recv, seconds: 1.0, ..Default::default() }),
+            down,
+            latency_ms: None,
+            signal_dbm: None,
+        };
+        let rules = vec!["rx > 1000 for 10s".parse().unwrap(), "down".parse().unwrap()];
+        let mut engine = AlertEngine::new(rules, Duration::from_secs(300));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // No rate before the first delta
+        let first = AlertInput { interface: "eth0".to_string(), delta: None, down: false, latency_ms: None, signal_dbm: None };
+        assert!(engine.evaluate(at(0), &[first]).is_empty());
+        // Held for less than 10s, then long enough
+        assert!(engine.evaluate(at(1), &[input("eth0", 5000, false)]).is_empty());
+        assert!(engine.evaluate(at(6), &[input("eth0", 5000, false)]).is_empty());
+        let fired = engine.evaluate(at(11), &[input("eth0", 5000, false)]);
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].state, fired[0].interface.as_str(), fired[0].value), (AlertState::Firing, "eth0", 5000.0));
+        assert_eq!(engine.firing(), vec![("rx > 1000 for 10s".to_string(), "eth0".to_string())]);
+        // Still firing: nothing new
+        assert!(engine.evaluate(at(12), &[input("eth0", 5000, false)]).is_empty());
+        let resolved = engine.evaluate(at(13), &[input("eth0", 10, false)]);
+        assert_eq!((resolved.len(), resolved[0].state), (1, AlertState::Resolved));
+
+        // Flapping inside the cooldown stays quiet, including its resolution
+        assert!(engine.evaluate(at(24), &[input("eth0", 5000, false)]).is_empty());
+        assert!(engine.evaluate(at(40), &[input("eth0", 5000, false)]).is_empty());
+        assert_eq!(engine.firing().len(), 1);
+        assert!(engine.evaluate(at(41), &[input("eth0", 10, false)]).is_empty());
+        // After it, the rule notifies again
+        assert!(engine.evaluate(at(400), &[input("eth0", 5000, false)]).is_empty());
+        assert_eq!(engine.evaluate(at(410), &[input("eth0", 5000, false)]).len(), 1);
+
+        // Rules are tracked per interface; down fires at once
+        let down = engine.evaluate(at(411), &[input("eth0", 5000, false), input("wlan0", 0, true)]);
+        assert_eq!(down.len(), 1);
+        assert_eq!((down[0].metric, down[0].message.as_str()), (Metric::Down, "wlan0 is down"));
+
+        let config = MonitorConfig {
+            alerts: vec!["down".to_string()],
+            webhook: Some("https://hooks.example.com/a".to_string()),
+            alert_cooldown: Some("10m".to_string()),
+            ..Default::default()
+        };
+        let settings = alerts::settings(config.clone(), vec!["errors".to_string()], None, Some("true".to_string()), None).unwrap();
+        assert_eq!(settings.rules.iter().map(|rule| rule.text.as_str()).collect::<Vec<_>>(), vec!["down", "errors"]);
+        assert_eq!((settings.webhook.as_deref(), settings.cooldown), (Some("https://hooks.example.com/a"), Duration::from_secs(600)));
+        assert_eq!(settings.command.as_deref(), Some("true"));
+        assert!(alerts::settings(config, Vec::new(), Some("not a url".to_string()), None, None).is_err());
+        assert_eq!(alerts::settings(MonitorConfig::default(), Vec::new(), None, None, None).unwrap().cooldown, alerts::DEFAULT_COOLDOWN);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[profiles.quick]\ntop-ports = 5\n\n[monitor]\nalerts = [\"rx > 1MB/s\"]\nalert-command = \"logger\"\n").unwrap();
+        let loaded = alerts::load_config(&path).unwrap();
+        assert_eq!((loaded.alerts, loaded.alert_command), (vec!["rx > 1MB/s".to_string()], Some("logger".to_string())));
+        assert_eq!(alerts::load_config(&dir.path().join("missing.toml")).unwrap(), MonitorConfig::default());
+    }
+
+    #[test]
+    fn test_prometheus_metrics() {
+        use netweaver_lib::diagnostics::ping::PingMethod;
+        use netweaver_lib::monitor::metrics::{self, InterfaceBandwidth, ProbeLatency, Snapshot};
+        use netweaver_lib::monitor::protocols::{ProtocolStats, TcpStats};
+
+        let snapshot = Snapshot {
+            interfaces: vec![NetworkStats {
+                interface: "eth0".to_string(),
+                bytes_sent: 1_000,
+                bytes_recv: 2_000,
+                drops: Some(3),
+                ..Default::default()
+            }],
+            protocols: Some(ProtocolStats {
+                tcp: Some(TcpStats { out_segs: 500, retrans_segs: 7, ..Default::default() }),
+                ..Default::default()
+            }),
+            bandwidth: vec![InterfaceBandwidth { interface: "eth0".to_string(), rx_bytes_per_sec: 1536.5, tx_bytes_per_sec: 0.0 }],
+            probes: vec![
+                ProbeLatency { target: "gw \"main\"".to_string(), method: PingMethod::Icmp, up: true, last_ms: Some(1.5), avg_ms: Some(2.0) },
+                ProbeLatency { target: "10.9.9.9".to_string(), method: PingMethod::Tcp, up: false, last_ms: None, avg_ms: None },
+            ],
+        };
+        let text = metrics::render(&snapshot);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"# TYPE netweaver_interface_receive_bytes_total counter"));
+        assert!(lines.contains(&"netweaver_interface_receive_bytes_total{interface=\"eth0\"} 2000"));
+        assert!(lines.contains(&"netweaver_interface_drops_total{interface=\"eth0\"} 3"));
+        assert!(lines.contains(&"# TYPE netweaver_interface_receive_bytes_per_second gauge"));
+        assert!(lines.contains(&"netweaver_interface_receive_bytes_per_second{interface=\"eth0\"} 1536.5"));
+        assert!(lines.contains(&"netweaver_tcp_segments_retransmitted_total 7"));
+        // Protocols that weren't read, and TCP extras that are missing, leave no families behind
+        assert!(!text.contains("netweaver_udp_") && !text.contains("netweaver_tcp_timeouts_total"));
+        // Nor does a counter the platform doesn't provide
+        assert!(!text.contains("netweaver_interface_overruns_total"));
+        assert!(lines.contains(&"netweaver_probe_up{target=\"gw \\\"main\\\"\",method=\"icmp\"} 1"));
+        assert!(lines.contains(&"netweaver_probe_up{target=\"10.9.9.9\",method=\"tcp\"} 0"));
+        assert!(lines.contains(&"netweaver_probe_rtt_seconds{target=\"gw \\\"main\\\"\",method=\"icmp\"} 0.0015"));
+        // No RTT sample for a target that never answered
+        assert!(!lines.iter().any(|line| line.starts_with("netweaver_probe_rtt_seconds{target=\"10.9.9.9\"")));
+        // Every sample's family is declared once, before it
+        for line in lines.iter().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            assert_eq!(text.matches(&format!("# TYPE {} ", name)).count(), 1, "{}", name);
+        }
+
+        let (status, content_type, body) = metrics::respond("GET /metrics HTTP/1.1", &snapshot);
+        assert_eq!((status, content_type), ("200 OK", "text/plain; version=0.0.4; charset=utf-8"));
+        assert_eq!(body, text);
+        assert_eq!(metrics::respond("GET /metrics?x=1 HTTP/1.1", &snapshot).0, "200 OK");
+        assert_eq!(metrics::respond("GET /other HTTP/1.1", &snapshot).0, "404 Not Found");
+        assert_eq!(metrics::respond("POST /metrics HTTP/1.1", &snapshot).0, "405 Method Not Allowed");
+    }
+
+    #[test]
+    fn test_monitoring_history() {
+        use chrono::{TimeZone, Utc};
+        use netweaver_lib::monitor::history::{self, Resolution};
+
+        let hour: u64 = 3600;
+        let base = 1_760_000_400 - 1_760_000_400 % (24 * hour);
+        let micros = |second: u64| second * 1_000_000;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("monitor.jsonl");
+        assert!(history::load(&path, 0, u64::MAX).unwrap().is_empty());
+        history::append(&path, &[sample("eth0", base, 1_000), sample("lo", base, 0)]).unwrap();
+        history::append(&path, &[sample("eth0", base + hour / 2, 7_000), sample("lo", base + hour / 2, 100)]).unwrap();
+        // Rebooted: the counter restarts, and that counts from zero
+        history::append(&path, &[sample("eth0", base + hour + 60, 500)]).unwrap();
+        // The monitor was off for a day; that gap isn't attributed
+        history::append(&path, &[sample("eth0", base + 26 * hour, 9_000)]).unwrap();
+        std::fs::write(&path, std::fs::read_to_string(&path).unwrap() + "not json\n").unwrap();
+
+        let samples = history::load(&path, micros(base), micros(base + 2 * hour)).unwrap();
+        assert_eq!(samples.len(), 5);
+        let since = Utc.timestamp_opt(base as i64, 0).unwrap();
+        let until = since + chrono::Duration::hours(2);
+        assert_eq!(Resolution::for_window(since, until), Resolution::Hour);
+        assert_eq!(Resolution::for_window(since, since + chrono::Duration::days(7)), Resolution::Day);
+
+        let report = history::build_report(&samples, since, until, Resolution::Hour);
+        assert_eq!(report.samples, 5);
+        let names: Vec<&str> = report.series.iter().map(|series| series.interface.as_str()).collect();
+        assert_eq!(names, ["all", "eth0", "lo"]);
+        let eth0 = &report.series[1].buckets;
+        assert_eq!(eth0.len(), 2);
+        assert_eq!(eth0[0].start, since);
+        assert_eq!((eth0[0].bytes_sent, eth0[0].bytes_recv), (6_000, 12_000));
+        assert!((eth0[0].avg_tx_bytes_per_sec - 6_000.0 / 1800.0).abs() < 1e-9);
+        assert_eq!(eth0[1].start, since + chrono::Duration::hours(1));
+        assert_eq!(eth0[1].bytes_sent, 500);
+        let all = &report.series[0].buckets;
+        assert_eq!((all[0].bytes_sent, all[0].covered_secs), (6_100, 1800.0));
+
+        // Pruning drops old samples and unreadable lines, and nothing else
+        assert_eq!(history::prune(&path, micros(base + hour)).unwrap(), 5);
+        assert_eq!(history::load(&path, 0, u64::MAX).unwrap().len(), 2);
+        assert_eq!(history::prune(&path, micros(base + hour)).unwrap(), 0);
+
+        let now = Utc.with_ymd_and_hms(2026, 10, 15, 12, 0, 0).unwrap();
+        assert_eq!(history::parse_time("24h", now).unwrap(), now - chrono::Duration::hours(24));
+        assert_eq!(history::parse_time("7d", now).unwrap(), now - chrono::Duration::days(7));
+        assert_eq!(history::parse_time("2026-10-01", now).unwrap(), Utc.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap());
+        assert_eq!(history::parse_time("2026-10-01T08:30:00+02:00", now).unwrap(),
+                   Utc.with_ymd_and_hms(2026, 10, 1, 6, 30, 0).unwrap());
+        assert!(history::parse_time("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn test_dashboard() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        use netweaver_lib::diagnostics::ping::PingMethod;
+        use netweaver_lib::monitor::dashboard::{Action, Dashboard, REFRESH_STEPS};
+        use netweaver_lib::monitor::probes::{ProbeStats, ProbeTarget};
+        use ratatui::{backend::TestBackend, Terminal};
+        use std::time::Duration;
+
+        let mut dashboard = Dashboard::new("all", Some("icmp".to_string()));
+        dashboard.update(vec![NetworkStats { errors: 1, ..sample("eth0", 0, 1_000) }, sample("lo", 0, 0)]);
+        dashboard.update(vec![NetworkStats { errors: 3, ..sample("eth0", 1, 3_048) }, sample("lo", 1, 0)]);
+        let mut gateway = ProbeStats::new(ProbeTarget { name: "gateway".to_string(), ip: "10.0.0.1".parse().unwrap() }, PingMethod::Icmp);
+        gateway.record(Some(12.0));
+        gateway.record(None);
+        dashboard.set_probes(vec![gateway]);
+        assert_eq!(dashboard.interfaces(), ["all", "eth0", "lo"]);
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(dashboard.handle_key(key(KeyCode::Right)), Action::Redraw);
+        assert_eq!(dashboard.selected_interface(), "eth0");
+        dashboard.handle_key(key(KeyCode::Left));
+        dashboard.handle_key(key(KeyCode::Left));
+        assert_eq!(dashboard.selected_interface(), "lo");
+        dashboard.handle_key(key(KeyCode::Tab));
+        dashboard.handle_key(key(KeyCode::Tab));
+        assert_eq!(dashboard.selected_interface(), "eth0");
+
+        dashboard.handle_key(key(KeyCode::Char('p')));
+        assert!(dashboard.is_paused());
+        dashboard.handle_key(key(KeyCode::Char('+')));
+        assert_eq!(dashboard.refresh(), Duration::from_secs(2));
+        for _ in 0..10 {
+            dashboard.handle_key(key(KeyCode::Char('-')));
+        }
+        assert_eq!(dashboard.refresh(), REFRESH_STEPS[0]);
+        assert_eq!(dashboard.handle_key(key(KeyCode::Char('x'))), Action::Ignore);
+        assert_eq!(dashboard.handle_key(key(KeyCode::Char('q'))), Action::Quit);
+        assert_eq!(dashboard.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), Action::Quit);
+
+        let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: Vec<String> = buffer
+            .content()
+            .chunks(usize::from(buffer.area.width))
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        let shows = |text: &str| screen.iter().any(|line| line.contains(text));
+        assert!(shows("eth0 (2/3)") && shows("PAUSED") && shows("Refresh 100ms"));
+        // 2048 bytes sent in a second, and two new errors
+        assert!(shows(" TX 2.00 KB/s ") && shows("3 (+2)"));
+        assert!(shows("gateway (10.0.0.1) via ICMP") && shows("Last: timeout") && shows("Lost: 1 of 2 (50.0%)"));
+        assert!(shows("ICMP has no connections"));
+        assert!(shows("q quit"));
+
+        // A terminal too small for every panel still draws
+        let mut tiny = Terminal::new(TestBackend::new(20, 5)).unwrap();
+        tiny.draw(|frame| dashboard.render(frame)).unwrap();
+    }
+
+    #[test]
+    fn test_sample_log() {
+        use netweaver_lib::monitor::sample_log::{self, LogFormat, LogRecord, SampleLog, CSV_HEADER};
+
+        let ticks: Vec<Vec<NetworkStats>> = (0..4)
+            .map(|tick| vec![NetworkStats { errors: tick, ..sample("eth0", tick * 2, 1_000 + tick * 2_000) }, sample("lo", tick * 2, 0)])
+            .collect();
+        let records = sample_log::log_records(&ticks[0], &ticks[1], "eth0");
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].bytes_sent, records[0].errors, records[0].interval_secs), (2_000, 1, 2.0));
+        assert_eq!(records[0].tx_bytes_per_sec, 1_000.0);
+        assert_eq!(records[0].timestamp.timestamp(), 2);
+        let names: Vec<String> = sample_log::log_records(&ticks[0], &ticks[1], ALL_INTERFACES)
+            .into_iter()
+            .map(|record| record.interface)
+            .collect();
+        assert_eq!(names, ["eth0", "lo", "all"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let run = |path: &std::path::Path| {
+            let mut log = SampleLog::open(path).unwrap();
+            for pair in ticks.windows(2) {
+                log.write_records(&sample_log::log_records(&pair[0], &pair[1], ALL_INTERFACES)).unwrap();
+            }
+            log
+        };
+
+        let jsonl = dir.path().join("monitor.jsonl");
+        assert_eq!(LogFormat::from_path(&jsonl), LogFormat::JsonLines);
+        run(&jsonl);
+        let parsed: Vec<LogRecord> = std::fs::read_to_string(&jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed.len(), 9);
+        assert_eq!(parsed[6].interface, "eth0");
+        assert_eq!((parsed[6].bytes_sent, parsed[6].errors, parsed[6].timestamp.timestamp()), (2_000, 1, 6));
+
+        let csv_path = dir.path().join("monitor.CSV");
+        assert_eq!(LogFormat::from_path(&csv_path), LogFormat::Csv);
+        let mut log = run(&csv_path);
+        let content = std::fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(content.lines().next(), Some(CSV_HEADER));
+        let parsed: Vec<LogRecord> = csv::Reader::from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(parsed.len(), 9);
+        assert_eq!(parsed[2].interface, "all");
+        assert_eq!(parsed[2].bytes_recv, 4_000);
+
+        // Moved away by logrotate: the log starts a new file, header first
+        let rotated = dir.path().join("monitor.CSV.1");
+        std::fs::rename(&csv_path, &rotated).unwrap();
+        log.write_records(&sample_log::log_records(&ticks[0], &ticks[1], "eth0")).unwrap();
+        let content = std::fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(content.lines().next(), Some(CSV_HEADER));
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap().lines().count(), 10);
+
+        // Unwritable for a while: the write fails, the next one after that recovers
+        let gone = dir.path().join("gone");
+        std::fs::create_dir(&gone).unwrap();
+        let path = gone.join("monitor.jsonl");
+        let mut log = SampleLog::open(&path).unwrap();
+        std::fs::remove_dir_all(&gone).unwrap();
+        assert!(log.write_records(&records).is_err());
+        std::fs::create_dir(&gone).unwrap();
+        log.write_records(&records).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_interface_stats_backends() {
+        use netweaver_lib::monitor::alert_inputs;
+        use netweaver_lib::monitor::interfaces::{parse_proc_net_dev, InterfaceCounters, InterfaceStats, NativeStats};
+        use netweaver_lib::monitor::{collect_stats, format_link};
+
+        let proc_net_dev = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 9120      80    0    0    0     0          0         0     9120      80    0    0    0     0       0          0
+  eth0: 123456789 98765 2    7    1     0          0        12 87654321  54321  3    4    5     0       0          0
+";
+        let parsed = parse_proc_net_dev(proc_net_dev).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1], InterfaceCounters {
+            name: "eth0".to_string(),
+            bytes_sent: 87_654_321,
+            bytes_recv: 123_456_789,
+            packets_sent: 54_321,
+            packets_recv: 98_765,
+            errors: 5,
+            drops: Some(11),
+            overruns: Some(6),
+            speed_mbps: None,
+            up: None,
+            error_detail: Default::default(),
+        });
+        assert!(parse_proc_net_dev("a\nb\n  eth0: 1 2 3\n").is_err());
+
+        // The monitor takes whatever a backend reports, sorted by name
+        struct Mock(Vec<InterfaceCounters>);
+        impl InterfaceStats for Mock {
+            fn interfaces(&self) -> anyhow::Result<Vec<InterfaceCounters>> {
+                Ok(self.0.clone())
+            }
+        }
+        let counters = |name: &str, up: Option<bool>, speed_mbps: Option<u64>| InterfaceCounters {
+            name: name.to_string(),
+            bytes_sent: 10,
+            up,
+            speed_mbps,
+            ..Default::default()
+        };
+        let stats = collect_stats(&Mock(vec![
+            counters("wlan0", Some(false), None),
+            counters("en0", Some(true), Some(2_500)),
+            counters("utun3", None, None),
+            counters("eth1", Some(true), Some(100)),
+        ]))
+        .unwrap();
+        let names: Vec<&str> = stats.iter().map(|row| row.interface.as_str()).collect();
+        assert_eq!(names, ["en0", "eth1", "utun3", "wlan0"]);
+        assert_eq!(stats[0].drops, None);
+        let links: Vec<String> = stats.iter().map(format_link).collect();
+        assert_eq!(links, ["up 2.5 Gb/s", "up 100 Mb/s", "n/a", "down"]);
+        assert_eq!(format_link(&NetworkStats { up: Some(true), speed_mbps: Some(10_000), ..Default::default() }), "up 10 Gb/s");
+        assert_eq!(format_link(&NetworkStats { up: Some(true), ..Default::default() }), "up");
+
+        // Only a link that says it's down is down to the alert rules
+        let down: Vec<(String, bool)> = alert_inputs(None, &stats, ALL_INTERFACES)
+            .into_iter()
+            .map(|input| (input.interface, input.down))
+            .collect();
+        assert_eq!(down.iter().filter(|(_, down)| *down).count(), 1);
+        assert!(down.contains(&("wlan0".to_string(), true)));
+
+        // A missing link speed and state stay out of the JSON
+        let json = serde_json::to_value(&stats[2]).unwrap();
+        assert!(json.get("speed_mbps").is_none() && json.get("up").is_none());
+
+        // The real backend sees loopback traffic
+        #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+        {
+            let loopback = || {
+                NativeStats
+                    .interfaces()
+                    .unwrap()
+                    .into_iter()
+                    .find(|row| row.name == "lo" || row.name == "lo0" || row.name.contains("Loopback"))
+            };
+            if let Some(before) = loopback() {
+                let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+                let target = socket.local_addr().unwrap();
+                for _ in 0..10 {
+                    socket.send_to(&[0u8; 512], target).unwrap();
+                }
+                let after = loopback().unwrap();
+                assert!(after.bytes_sent >= before.bytes_sent + 5_120, "{:?} -> {:?}", before, after);
+                assert!(after.packets_recv > before.packets_recv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_report_graphs() {
+        use chrono::{TimeZone, Utc};
+        use netweaver_lib::monitor::charts::{graphs_html, history_charts};
+        use netweaver_lib::monitor::history::{self, Resolution};
+
+        let minute: u64 = 60 * 1_000_000;
+        let since = Utc.with_ymd_and_hms(2026, 10, 14, 0, 0, 0).unwrap();
+        let base = since.timestamp_micros() as u64;
+        // Six hours of eth0 at 1 MB a minute, then nothing for three, then two more
+        let samples: Vec<NetworkStats> = (0..=6 * 60)
+            .chain(9 * 60..=11 * 60)
+            .step_by(10)
+            .map(|at| NetworkStats {
+                interface: "eth0".to_string(),
+                bytes_sent: at * 1_048_576,
+                bytes_recv: at * 524_288,
+                packets_sent: at * 1_000,
+                errors: at / 60,
+                timestamp: base + at * minute,
+                ..Default::default()
+            })
+            .collect();
+        let until = since + chrono::Duration::hours(12);
+        let report = history::build_report(&samples, since, until, Resolution::Hour);
+        let html = history_charts(&report).unwrap();
+
+        assert_eq!(html.matches("<svg").count(), 3);
+        for title in ["Bandwidth", "Packets per second", "Errors per hour", "Time (UTC)", "bytes/s", "packets/s"] {
+            assert!(html.contains(&format!(">{}</text>", title)), "missing {}", title);
+        }
+        // Value labels are in format_bandwidth units, time labels on whole hours
+        assert!(html.contains(">20.00 KB/s</text>"), "{}", html);
+        assert!(html.contains(">10-14 00:00</text>") && html.contains(">10-14 12:00</text>"));
+        // The three uncovered hours break each line into two runs
+        let bandwidth = html.split("</svg>").next().unwrap();
+        let runs: Vec<usize> = bandwidth
+            .split("<path d=\"")
+            .skip(2)
+            .map(|path| path.split('"').next().unwrap().matches('M').count())
+            .collect();
+        assert_eq!(runs, [2, 2]);
+        // No drop counter, no drops line
+        assert!(!html.contains(">Drops</text>"));
+
+        // A month is drawn per day with at most 8 date labels
+        let month = history::build_report(&samples, since, since + chrono::Duration::days(30), Resolution::Day);
+        let html = history_charts(&month).unwrap();
+        assert!(html.contains(">2026-10-15</text>") && html.contains("Errors per day"));
+        let labels = html.split("</svg>").next().unwrap().matches(">2026-").count();
+        assert!((1..=8).contains(&labels), "{} date labels", labels);
+
+        // Nothing to draw says why instead
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("monitor.jsonl");
+        let empty = history::build_report(&[], since, until, Resolution::Hour);
+        assert!(history_charts(&empty).is_none());
+        let missing = graphs_html(Some(&empty), Some(&store));
+        assert!(missing.contains("Graphs were requested, but no monitoring history store was found at"));
+        std::fs::write(&store, "").unwrap();
+        assert!(graphs_html(Some(&empty), Some(&store)).contains("has no traffic between 2026-10-14 00:00 UTC and 2026-10-14 12:00 UTC"));
+        assert!(graphs_html(Some(&report), Some(&store)).contains("<svg"));
+    }
+
+    #[test]
+    fn test_report_pdf() {
+        use chrono::{TimeZone, Utc};
+        use flate2::read::ZlibDecoder;
+        use netweaver_lib::monitor::charts::history_drawings;
+        use netweaver_lib::monitor::history::{self, Resolution};
+        use netweaver_lib::monitor::pdf::{text_width, Layout};
+        use std::io::Read;
+
+        assert!((text_width("Hello", 10.0, false) - 22.78).abs() < 1e-9);
+        assert!(text_width("W", 10.0, true) > text_width("W", 10.0, false));
+
+        let since = Utc.with_ymd_and_hms(2026, 10, 14, 0, 0, 0).unwrap();
+        let samples: Vec<NetworkStats> = (0..=12 * 60)
+            .step_by(10)
+            .map(|at| NetworkStats {
+                interface: "eth0".to_string(),
+                bytes_sent: at * 1_048_576,
+                timestamp: since.timestamp_micros() as u64 + at * 60_000_000,
+                ..Default::default()
+            })
+            .collect();
+        let report = history::build_report(&samples, since, since + chrono::Duration::hours(12), Resolution::Hour);
+
+        let mut layout = Layout::new("NetWeaver Network Report");
+        layout.title("NetWeaver Network Report", 26.0);
+        layout.heading("History");
+        for hour in 0..120 {
+            layout.row(&format!("2026-10-14 {:02}:00", hour % 24), &format!("{} MB sent (average TX 17.07 KB/s) — a value long enough to wrap onto a second line of the table", hour));
+        }
+        layout.heading("Graphs");
+        for drawing in history_drawings(&report).unwrap() {
+            layout.drawing(&drawing);
+        }
+        let pdf = layout.finish().unwrap();
+
+        // Every cross-reference entry points at its object
+        assert!(pdf.starts_with(b"%PDF-1.4\n") && pdf.ends_with(b"%%EOF\n"));
+        let find = |needle: &[u8], from: usize| pdf[from..].windows(needle.len()).position(|window| window == needle).map(|at| at + from);
+        let ascii = |range: std::ops::Range<usize>| String::from_utf8(pdf[range].to_vec()).unwrap();
+        let startxref = pdf.windows(9).rposition(|window| window == b"startxref").unwrap();
+        let xref: usize = ascii(startxref + 10..pdf.len()).lines().next().unwrap().parse().unwrap();
+        let table = ascii(xref..startxref);
+        assert!(table.starts_with("xref\n"));
+        let entries: Vec<&str> = table.lines().skip(3).take_while(|line| !line.starts_with("trailer")).collect();
+        for (index, entry) in entries.iter().enumerate() {
+            assert_eq!(entry.len(), 19);
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", index + 1).as_bytes()));
+        }
+
+        // The long table runs over several pages, repeating its heading
+        let mut pages = Vec::new();
+        let mut from = 0;
+        while let Some(at) = find(b"<< /Length ", from) {
+            let marker = find(b"stream\n", at).unwrap();
+            let length: usize = ascii(at + 11..marker).split(' ').next().unwrap().parse().unwrap();
+            let mut page = String::new();
+            ZlibDecoder::new(&pdf[marker + 7..marker + 7 + length]).read_to_string(&mut page).unwrap();
+            pages.push(page);
+            from = marker + 7 + length;
+        }
+        assert!(pages.len() >= 4, "{} pages", pages.len());
+        assert_eq!(pdf.windows(12).filter(|window| window == b"/Type /Page ").count(), pages.len());
+        assert!(pages[1].contains("(History \\(continued\\)) Tj"));
+        // Text is WinAnsi: the em dash is byte 0x97
+        assert!(pages[0].contains("KB/s\\) \\227 a value"));
+        assert!(pages.last().unwrap().contains(&format!("(NetWeaver Network Report - page {} of {}) Tj", pages.len(), pages.len())));
+        // Charts are vector paths with their labels, and stay whole on a page
+        for (title, label) in [("Bandwidth", "20.00 KB/s"), ("Packets per second", "packets/s"), ("Errors per hour", "Time \\(UTC\\)")] {
+            let page = pages.iter().find(|page| page.contains(&format!("({}) Tj", title))).unwrap();
+            assert!(page.contains(&format!("({}) Tj", label)) && page.contains(" l S\n"), "{} is split", title);
+        }
+    }
+
+    #[test]
+    fn test_interface_comparison() {
+        use netweaver_lib::monitor::dashboard::Dashboard;
+        use netweaver_lib::monitor::{comparison_rows, interface_names, select_interfaces, watches};
+        use ratatui::{backend::TestBackend, Terminal};
+
+        assert_eq!(interface_names(" eth0, wg0,,eth0 ,eth1"), ["eth0", "wg0", "eth1"]);
+        assert!(interface_names("all").is_empty());
+        assert!(watches("all", "lo") && watches("eth0,wg0", "wg0") && !watches("eth0,wg0", "lo"));
+
+        // Up, dropping a packet per kilobyte sent
+        let link = |stats: NetworkStats| NetworkStats { drops: Some(stats.bytes_sent / 1_000), up: Some(true), ..stats };
+        let before = [sample("eth0", 0, 1_000), sample("lo", 0, 0), sample("wg0", 0, 2_000)].map(link).to_vec();
+        let after = [sample("eth0", 1, 3_048), sample("lo", 1, 50_000), sample("wg0", 1, 3_024)].map(link).to_vec();
+
+        let selected = select_interfaces(&after, "wg0,eth0").unwrap();
+        assert_eq!(selected.iter().map(|row| row.interface.as_str()).collect::<Vec<_>>(), ["wg0", "eth0"]);
+        let error = select_interfaces(&after, "eth0,wlan0,tun9").unwrap_err().to_string();
+        assert!(error.contains("'wlan0', 'tun9'") && error.contains("Available: eth0, lo, wg0"), "{}", error);
+
+        let rows = comparison_rows(&before, &after, &["wg0", "eth0"]);
+        let row = |label: &str| rows.iter().find(|(name, _)| name == label).unwrap().1.clone();
+        assert_eq!(row("Interface"), ["wg0", "eth0", "total"]);
+        // 1024 and 2048 bytes sent in a second, and lo left out of the total
+        assert_eq!(row("TX rate"), ["1.00 KB/s", "2.00 KB/s", "3.00 KB/s"]);
+        assert_eq!(row("Packets TX"), ["30", "30", "60"]);
+        assert_eq!(row("Drops"), ["3", "3", "6"]);
+        assert_eq!(row("Link")[2], "");
+
+        // Without a previous sample there are no rates
+        assert_eq!(comparison_rows(&[], &after, &["eth0", "wg0"])[1].1, ["-", "-", "-"]);
+
+        let mut dashboard = Dashboard::new("wg0,eth0", None);
+        dashboard.update(before);
+        dashboard.update(after);
+        assert_eq!(dashboard.interfaces(), ["all", "wg0", "eth0"]);
+        assert_eq!(dashboard.selected_interface(), "all");
+        let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: Vec<String> = buffer
+            .content()
+            .chunks(usize::from(buffer.area.width))
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        let shows = |text: &str| screen.iter().any(|line| line.contains(text));
+        assert!(shows(" wg0 ") && shows(" eth0 ") && shows(" total "));
+        assert!(shows("TX 1.00 KB/s") && shows("TX 2.00 KB/s") && shows("TX 3.00 KB/s"));
+        assert!(!screen.iter().any(|line| line.contains("│lo ")));
+    }
+
+    #[test]
+    fn test_top_talkers() {
+        use chrono::{TimeZone, Utc};
+        use netweaver_lib::diagnostics::capture::{DecodedPacket, Protocol};
+        use netweaver_lib::monitor::sample_log::SampleLog;
+        use netweaver_lib::monitor::dashboard::Dashboard;
+        use netweaver_lib::monitor::talkers::{self, TalkerTable, TalkersReport, MAX_HOSTS};
+        use ratatui::{backend::TestBackend, Terminal};
+        use std::net::{IpAddr, Ipv4Addr};
+
+        let ip = |last: u8| IpAddr::V4(Ipv4Addr::new(10, 0, 0, last));
+        let packet = |protocol, src, dst, ports: Option<(u16, u16)>, length| DecodedPacket {
+            length,
+            protocol,
+            src: Some(src),
+            dst: Some(dst),
+            src_port: ports.map(|(src, _)| src),
+            dst_port: ports.map(|(_, dst)| dst),
+            info: String::new(),
+            tcp: None,
+        };
+        let mut table = TalkerTable::new(vec![ip(2)]);
+        for _ in 0..3 {
+            table.record(&packet(Protocol::Tcp, ip(2), ip(100), Some((51234, 443)), 1_000));
+        }
+        table.record(&packet(Protocol::Tcp, ip(100), ip(2), Some((443, 51234)), 500));
+        table.record(&packet(Protocol::Tcp, ip(100), ip(2), Some((22, 40000)), 500));
+        table.record(&packet(Protocol::Udp, ip(2), ip(53), Some((40001, 53)), 100));
+        table.record(&packet(Protocol::Icmp, ip(7), ip(2), None, 84));
+        table.record(&packet(Protocol::Arp, ip(9), ip(2), None, 60));
+        table.tick(2.0);
+
+        let top = table.leaderboard(10);
+        assert_eq!(top.iter().map(|talker| talker.ip).collect::<Vec<_>>(), [ip(100), ip(53), ip(7)]);
+        assert_eq!((top[0].sent.bytes, top[0].sent.packets, top[0].received.bytes), (3_000, 3, 1_000));
+        assert_eq!(top[0].ports, [443, 22]);
+        assert_eq!((top[0].tx_bytes_per_sec, top[0].rx_bytes_per_sec), (1_500.0, 500.0));
+        assert!(top[2].ports.is_empty());
+        assert_eq!(table.leaderboard(1).len(), 1);
+        // Nothing new since the last tick
+        table.tick(1.0);
+        assert_eq!(table.leaderboard(1)[0].tx_bytes_per_sec, 0.0);
+
+        let ports = table.top_ports(10);
+        assert_eq!((ports[0].protocol, ports[0].port, ports[0].volume.bytes, ports[0].volume.packets), (Protocol::Tcp, 443, 3_500, 4));
+        assert_eq!((ports[2].protocol, ports[2].port), (Protocol::Udp, 53));
+
+        // A flood of one-packet hosts can't grow the table past its cap, and
+        // the busy host survives
+        assert!(!table.truncated);
+        for n in 0..(MAX_HOSTS as u32 * 2) {
+            table.record(&packet(Protocol::Udp, IpAddr::V4(Ipv4Addr::from(0x0b00_0000 + n)), ip(2), None, 60));
+        }
+        assert!(table.truncated);
+        assert!(table.leaderboard(usize::MAX).len() <= MAX_HOSTS);
+        assert_eq!(table.leaderboard(1)[0].ip, ip(100));
+
+        let mut dashboard = Dashboard::new("all", None);
+        dashboard.set_talkers(top.clone());
+        let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: Vec<String> = buffer
+            .content()
+            .chunks(usize::from(buffer.area.width))
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        assert!(screen.iter().any(|line| line.contains(" Top Connections ") && line.contains(" Top Talkers ")));
+        assert!(screen.iter().any(|line| line.contains("10.0.0.100") && line.contains("1.46 KB/s") && line.contains("443,22")));
+
+        let started = Utc.with_ymd_and_hms(2026, 10, 15, 9, 0, 0).unwrap();
+        let report = TalkersReport::new(&table, 2, vec!["eth0".to_string()], started, started + chrono::Duration::seconds(10));
+        assert_eq!(report.hosts.len(), 2);
+        assert_eq!((report.hosts[0].tx_bytes_per_sec, report.hosts[0].rx_bytes_per_sec), (300.0, 100.0));
+        assert!(report.truncated);
+
+        let dir = tempfile::tempdir().unwrap();
+        let jsonl = dir.path().join("monitor.jsonl");
+        let mut log = SampleLog::open(&jsonl).unwrap();
+        log.write_talkers(&report).unwrap();
+        let line: serde_json::Value = serde_json::from_str(std::fs::read_to_string(&jsonl).unwrap().trim()).unwrap();
+        assert_eq!(line["top_talkers"]["hosts"][0]["ip"], "10.0.0.100");
+        assert_eq!(line["top_talkers"]["ports"][0]["port"], 443);
+        let csv_path = dir.path().join("monitor.csv");
+        SampleLog::open(&csv_path).unwrap().write_talkers(&report).unwrap();
+        assert!(!std::fs::read_to_string(&csv_path).unwrap().contains("10.0.0.100"));
+
+        // The latest ranking is kept for reports
+        let data = tempfile::tempdir().unwrap();
+        let stored = data.path().join("netweaver").join("talkers.json");
+        assert_eq!(talkers::load_latest(&stored).unwrap(), None);
+        talkers::store_latest(&stored, &report).unwrap();
+        assert_eq!(talkers::load_latest(&stored).unwrap(), Some(report));
+        let export = |format: &str| {
+            let path = dir.path().join(format!("report.{}", format));
+            let out = std::process::Command::new(env!("CARGO_BIN_EXE_netweaver"))
+                .args(["report", "--format", format, "--export"])
+                .arg(&path)
+                .env("XDG_DATA_HOME", data.path())
+                .output()
+                .unwrap();
+            assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+            std::fs::read_to_string(&path).unwrap()
+        };
+        let json: serde_json::Value = serde_json::from_str(&export("json")).unwrap();
+        assert_eq!(json["top_talkers"]["hosts"][1]["ip"], "10.0.0.53");
+        let html = export("html");
+        assert!(html.contains("<h2>Top Talkers</h2>") && html.contains("10.0.0.100:"));
+        assert!(html.contains("on eth0 (10s)"));
+        assert!(html.contains("2.93 KB sent, 1000.00 B received (average TX 300.00 B/s, RX 100.00 B/s), ports 443, 22"), "{}", html);
+        assert!(html.contains("TCP 443:"));
+    }
+
+    #[tokio::test]
+    async fn test_latency_probes() {
+        use netweaver_lib::analytics::LatencyAnalyzer;
+        use netweaver_lib::diagnostics::ping::PingMethod;
+        use netweaver_lib::monitor::alerts::{self, AlertEngine, AlertInput, AlertRule, AlertState, Metric};
+        use netweaver_lib::monitor::probes::{self, ProbeSample, ProbeStats, ProbeTarget, PROBE_TIMEOUT};
+        use netweaver_lib::monitor::sample_log::SampleLog;
+        use std::time::{Duration, Instant};
+
+        let mut rtts = LatencyAnalyzer::new(100);
+        for ms in 1..=20 {
+            rtts.add_sample(ms as f64);
+        }
+        assert_eq!((rtts.percentile(95.0), rtts.percentile(50.0), rtts.percentile(100.0)), (19.0, 10.0, 20.0));
+        assert_eq!(LatencyAnalyzer::new(10).percentile(95.0), 0.0);
+
+        // IP literals resolve without DNS, and repeats are dropped
+        let specs = vec!["1.1.1.1".to_string(), " 1.1.1.1".to_string(), "9.9.9.9".to_string()];
+        let targets = probes::resolve(&specs, false).await.unwrap();
+        assert_eq!(targets.iter().map(|target| target.label()).collect::<Vec<_>>(), ["1.1.1.1", "9.9.9.9"]);
+
+        let target = ProbeTarget { name: "gateway".to_string(), ip: "10.0.0.1".parse().unwrap() };
+        let mut stats = ProbeStats::new(target, PingMethod::Tcp);
+        assert!(stats.alert_input().is_none());
+        for rtt in [Some(10.0), Some(30.0), None, Some(20.0)] {
+            stats.record(rtt);
+        }
+        assert_eq!(stats.label(), "gateway (10.0.0.1) via TCP :80");
+        assert_eq!((stats.last(), stats.answered(), stats.sent(), stats.lost()), (Some(Some(20.0)), 3, 4, 1));
+        assert_eq!((stats.average(), stats.p95(), stats.jitter(), stats.loss_rate()), (20.0, 30.0, 15.0, 25.0));
+        // A lost probe counts as the timeout for alerts
+        stats.record(None);
+        assert_eq!(stats.alert_input().unwrap().latency_ms, Some(PROBE_TIMEOUT.as_secs_f64() * 1000.0));
+
+        let rule: AlertRule = "Gateway > 50ms for 30s".parse().unwrap();
+        assert_eq!((rule.metric, rule.threshold, rule.target.as_deref()), (Metric::Latency, 50.0, Some("gateway")));
+        let rule: AlertRule = "latency > 0.2s".parse().unwrap();
+        assert_eq!((rule.metric, rule.threshold, rule.target), (Metric::Latency, 200.0, None));
+        assert!("gateway > 50".parse::<AlertRule>().is_err());
+        assert!("gateway".parse::<AlertRule>().is_err());
+        assert!("latency".parse::<AlertRule>().is_err());
+
+        let rules: Vec<AlertRule> = vec!["gateway > 50ms for 30s".parse().unwrap(), "down".parse().unwrap()];
+        let names = vec!["gateway".to_string(), "1.1.1.1".to_string()];
+        alerts::check_probe_targets(&rules, &names).unwrap();
+        let unknown = alerts::check_probe_targets(&["vpn > 50ms".parse().unwrap()], &names).unwrap_err();
+        assert!(unknown.to_string().contains("probes gateway, 1.1.1.1"), "{}", unknown);
+        assert!(alerts::check_probe_targets(&["latency > 50ms".parse().unwrap()], &[]).is_err());
+
+        // Latency rules watch their target only, and interface rules ignore probes
+        let probe = |name: &str, ms: f64| AlertInput { interface: name.to_string(), latency_ms: Some(ms), ..Default::default() };
+        let mut engine = AlertEngine::new(rules, Duration::from_secs(300));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        assert!(engine.evaluate(at(0), &[probe("gateway", 80.0), probe("1.1.1.1", 500.0)]).is_empty());
+        let fired = engine.evaluate(at(30), &[probe("gateway", 75.5), probe("1.1.1.1", 500.0)]);
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].state, fired[0].interface.as_str(), fired[0].metric), (AlertState::Firing, "gateway", Metric::Latency));
+        assert_eq!(fired[0].message, "gateway: latency at 75.5 ms (rule 'gateway > 50ms for 30s')");
+        let resolved = engine.evaluate(at(32), &[probe("gateway", 4.0)]);
+        assert_eq!((resolved.len(), resolved[0].state), (1, AlertState::Resolved));
+
+        // Each probe goes to a JSON log as a line of its own; CSV has no room
+        let dir = tempfile::tempdir().unwrap();
+        let sample = ProbeSample {
+            timestamp: chrono::Utc::now(),
+            target: "gateway".to_string(),
+            ip: "10.0.0.1".parse().unwrap(),
+            method: PingMethod::Icmp,
+            rtt_ms: None,
+        };
+        let mut log = SampleLog::open(&dir.path().join("monitor.jsonl")).unwrap();
+        log.write_probe(&sample).unwrap();
+        log.flush().unwrap();
+        let line: serde_json::Value = serde_json::from_str(std::fs::read_to_string(dir.path().join("monitor.jsonl")).unwrap().trim()).unwrap();
+        assert_eq!(serde_json::from_value::<ProbeSample>(line["probe"].clone()).unwrap(), sample);
+        assert_eq!((line["probe"]["method"].as_str(), line["probe"]["rtt_ms"].is_null()), (Some("icmp"), true));
+        let mut csv = SampleLog::open(&dir.path().join("monitor.csv")).unwrap();
+        csv.write_probe(&sample).unwrap();
+        csv.flush().unwrap();
+        assert!(std::fs::read_to_string(dir.path().join("monitor.csv")).unwrap_or_default().is_empty());
+    }
+
+    #[test]
+    fn test_interface_events() {
+        use netweaver_lib::monitor::dashboard::Dashboard;
+        use netweaver_lib::monitor::events::{self, Change, InterfaceEvent, InterfaceState};
+        use netweaver_lib::monitor::sample_log::SampleLog;
+        use ratatui::{backend::TestBackend, Terminal};
+        use std::collections::HashMap;
+        use std::net::IpAddr;
+
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        let addresses = HashMap::from([("eth0".to_string(), vec![ip("fe80::1"), ip("192.168.1.23"), ip("192.168.1.23")])]);
+        let up = NetworkStats { up: Some(true), ..sample("eth0", 0, 0) };
+        let before = events::states(&[up, sample("lo", 0, 0), sample("all", 0, 0)], &addresses);
+        assert_eq!(before, vec![
+            InterfaceState { name: "eth0".to_string(), up: Some(true), addresses: vec![ip("192.168.1.23"), ip("fe80::1")] },
+            InterfaceState { name: "lo".to_string(), up: None, addresses: Vec::new() },
+        ]);
+
+        let state = |name: &str, up: Option<bool>, addresses: &[&str]| InterfaceState {
+            name: name.to_string(),
+            up,
+            addresses: addresses.iter().map(|text| ip(text)).collect(),
+        };
+        let at = chrono::Utc::now();
+        assert!(events::changes(&before, &before, "all", at).is_empty());
+        let after = vec![
+            state("eth0", Some(false), &["192.168.1.57", "10.0.0.5", "fe80::1"]),
+            state("wg0", Some(true), &["10.8.0.2"]),
+        ];
+        let messages = |found: Vec<InterfaceEvent>| found.into_iter().map(|event| event.message).collect::<Vec<_>>();
+        assert_eq!(messages(events::changes(&before, &after, "all", at)), [
+            "eth0: link down",
+            "eth0: address changed 192.168.1.23 → 192.168.1.57",
+            "eth0: address added 10.0.0.5",
+            "wg0: interface appeared",
+            "lo: interface gone",
+        ]);
+        // Only the watched interfaces, and the link coming back
+        let back = vec![state("eth0", Some(true), &[]), state("wg0", Some(true), &["10.8.0.2"])];
+        assert_eq!(messages(events::changes(&after, &back, "eth0,lo", at)), [
+            "eth0: link up",
+            "eth0: address removed 192.168.1.57",
+            "eth0: address removed 10.0.0.5",
+            "eth0: address removed fe80::1",
+        ]);
+
+        // Logged as an event line of its own, with the change tagged
+        let event = InterfaceEvent::new(at, "wlan0", Change::AddressChanged { from: ip("192.168.1.23"), to: ip("192.168.1.57") });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("monitor.jsonl");
+        let mut log = SampleLog::open(&path).unwrap();
+        log.write_event(&event).unwrap();
+        log.flush().unwrap();
+        let line: serde_json::Value = serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(line["event"]["change"], "address_changed");
+        assert_eq!(line["event"]["message"], "wlan0: address changed 192.168.1.23 → 192.168.1.57");
+        assert_eq!(serde_json::from_value::<InterfaceEvent>(line["event"].clone()).unwrap(), event);
+
+        let mut dashboard = Dashboard::new("all", None);
+        dashboard.set_events(&[event]);
+        let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: String = buffer.content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("Interface Events") && screen.contains("wlan0: address changed"));
+    }
+
+    #[test]
+    fn test_systemd_notify() {
+        use netweaver_lib::monitor::systemd::{self, Notifier};
+        use std::os::unix::net::UnixDatagram;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier::connect(path.to_str().unwrap()).unwrap();
+        let mut buffer = [0u8; 256];
+        for state in ["READY=1\nSTATUS=Sampling every 10s", "WATCHDOG=1", "STOPPING=1"] {
+            notifier.notify(state);
+            let received = manager.recv(&mut buffer).unwrap();
+            assert_eq!(std::str::from_utf8(&buffer[..received]).unwrap(), state);
+        }
+        // A manager that went away is only logged
+        drop(manager);
+        notifier.notify("WATCHDOG=1");
+        assert!(Notifier::connect(dir.path().join("missing").to_str().unwrap()).is_err());
+
+        let name = format!("@netweaver-test-{}", std::process::id());
+        let abstract_manager = {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(&name.as_bytes()[1..]).unwrap();
+            UnixDatagram::bind_addr(&address).unwrap()
+        };
+        Notifier::connect(&name).unwrap().notify("READY=1");
+        let received = abstract_manager.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..received], b"READY=1");
+
+        // Half the watchdog timeout, and only for the process it names
+        assert_eq!(systemd::watchdog_interval_from(Some("30000000"), None, 42), Some(Duration::from_secs(15)));
+        assert_eq!(systemd::watchdog_interval_from(Some("30000000"), Some("42"), 42), Some(Duration::from_secs(15)));
+        assert_eq!(systemd::watchdog_interval_from(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(systemd::watchdog_interval_from(Some("0"), None, 42), None);
+        assert_eq!(systemd::watchdog_interval_from(Some("soon"), None, 42), None);
+        assert_eq!(systemd::watchdog_interval_from(None, None, 42), None);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        use netweaver_lib::diagnostics::ping::PingMethod;
+        use netweaver_lib::monitor::probes::{ProbeSample, ProbeTarget};
+        use netweaver_lib::monitor::summary::{self, RunSummary, SummaryRecorder};
+
+        let ticks = [
+            vec![
+                NetworkStats { drops: Some(0), ..sample("eth0", 0, 0) },
+                sample("wg0", 0, 0),
+                NetworkStats { drops: Some(0), ..sample("lo", 0, 0) },
+            ],
+            vec![
+                NetworkStats { errors: 1, drops: Some(2), ..sample("eth0", 1, 1_000) },
+                sample("wg0", 1, 100),
+                NetworkStats { drops: Some(0), ..sample("lo", 1, 9_999) },
+            ],
+            vec![
+                NetworkStats { errors: 1, drops: Some(3), ..sample("eth0", 2, 4_000) },
+                sample("wg0", 2, 100),
+                NetworkStats { drops: Some(0), ..sample("lo", 2, 9_999) },
+            ],
+        ];
+
+        let mut recorder = SummaryRecorder::new("eth0", &[], PingMethod::Icmp);
+        recorder.record(&ticks[0], &ticks[1]);
+        recorder.record(&ticks[1], &ticks[2]);
+        let run = recorder.finish(true);
+        assert!(run.complete && run.probes.is_empty());
+        assert_eq!(run.interfaces.len(), 1);
+        let eth0 = &run.interfaces[0];
+        assert_eq!((eth0.interface.as_str(), eth0.seconds, eth0.bytes_sent, eth0.bytes_recv), ("eth0", 2.0, 4_000, 8_000));
+        assert_eq!((eth0.errors, eth0.drops), (1, Some(3)));
+        assert_eq!((eth0.avg_tx_bytes_per_sec, eth0.peak_tx_bytes_per_sec), (2_000.0, 3_000.0));
+        assert_eq!((eth0.avg_rx_bytes_per_sec, eth0.peak_rx_bytes_per_sec), (4_000.0, 6_000.0));
+
+        // Several interfaces get their total, without the unwatched ones
+        let target = ProbeTarget { name: "gateway".to_string(), ip: "10.0.0.1".parse().unwrap() };
+        let mut recorder = SummaryRecorder::new("eth0,wg0", std::slice::from_ref(&target), PingMethod::Tcp);
+        recorder.record(&ticks[0], &ticks[1]);
+        for rtt_ms in [Some(10.0), None, Some(30.0)] {
+            recorder.record_probe(&ProbeSample {
+                timestamp: chrono::Utc::now(),
+                target: "gateway".to_string(),
+                ip: target.ip,
+                method: PingMethod::Tcp,
+                rtt_ms,
+            });
+        }
+        let run = recorder.finish(false);
+        assert_eq!(run.interfaces.iter().map(|row| row.interface.as_str()).collect::<Vec<_>>(), ["eth0", "wg0", "all"]);
+        assert_eq!(run.interfaces[1].drops, None);
+        let total = &run.interfaces[2];
+        assert_eq!((total.bytes_sent, total.errors, total.drops), (1_100, 1, Some(2)));
+        let probe = &run.probes[0];
+        assert_eq!((probe.sent, probe.lost, probe.avg_ms, probe.min_ms, probe.max_ms), (3, 1, Some(20.0), Some(10.0), Some(30.0)));
+        assert!(!run.complete);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("summary.json");
+        summary::save_summary(&run, path.to_str().unwrap()).unwrap();
+        let saved: RunSummary = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, run);
+        let empty = SummaryRecorder::new("all", &[], PingMethod::Icmp).finish(false);
+        assert!(empty.interfaces.is_empty());
+        assert!(!serde_json::to_string(&empty).unwrap().contains("probes"));
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        use netweaver_lib::diagnostics::ping::PingMethod;
+        use netweaver_lib::monitor::probes::ProbeSample;
+        use netweaver_lib::monitor::sample_log::log_records;
+        use netweaver_lib::monitor::stream::{self, EventStream, StreamEvent, CLIENT_BACKLOG};
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        async fn connected(stream: &EventStream, clients: usize) {
+            for _ in 0..100 {
+                if stream.clients() == clients {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            panic!("expected {} stream clients, have {}", clients, stream.clients());
+        }
+
+        let records = log_records(&[sample("eth0", 0, 0)], &[sample("eth0", 2, 2_000)], "eth0");
+        let probe = ProbeSample {
+            timestamp: chrono::Utc::now(),
+            target: "gateway".to_string(),
+            ip: std::net::Ipv4Addr::new(10, 0, 0, 1),
+            method: PingMethod::Icmp,
+            rtt_ms: None,
+        };
+
+        // A plain TCP client reads one JSON line per event
+        let server = EventStream::start("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let client = tokio::net::TcpStream::connect(server.local_addr()).await.unwrap();
+        connected(&server, 1).await;
+        server.publish_records(&records);
+        server.publish(&StreamEvent::Probe(probe.clone()));
+        let mut lines = BufReader::new(client).lines();
+        let first: serde_json::Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(first["sample"]["interface"], "eth0");
+        assert_eq!(first["sample"]["bytes_sent"], 2_000);
+        assert_eq!(first["sample"]["tx_bytes_per_sec"], 1_000.0);
+        let second = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<StreamEvent>(&second).unwrap(), StreamEvent::Probe(probe.clone()));
+        let second: serde_json::Value = serde_json::from_str(&second).unwrap();
+        assert_eq!(second["probe"]["target"], "gateway");
+        assert!(second["probe"]["rtt_ms"].is_null());
+        // Gone once the next event finds it has hung up
+        drop(lines);
+        for _ in 0..100 {
+            server.publish(&StreamEvent::Probe(probe.clone()));
+            if server.clients() == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(server.clients(), 0);
+
+        // A WebSocket client gets one text frame per event
+        assert_eq!(stream::websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(stream::frame(0x1, &[b'x'; 300])[..4], [0x81, 126, 1, 44]);
+        let mut client = tokio::net::TcpStream::connect(server.local_addr()).await.unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                           Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").await.unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(client.read_u8().await.unwrap());
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        connected(&server, 1).await;
+        server.publish(&StreamEvent::Probe(probe.clone()));
+        let (opcode, length) = (client.read_u8().await.unwrap(), client.read_u8().await.unwrap());
+        assert_eq!(opcode, 0x81);
+        let mut payload = vec![0; length as usize];
+        client.read_exact(&mut payload).await.unwrap();
+        assert_eq!(serde_json::from_slice::<StreamEvent>(&payload).unwrap(), StreamEvent::Probe(probe.clone()));
+        drop(client);
+
+        // A client that doesn't keep up is dropped rather than waited for
+        let server = EventStream::start("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let _idle = tokio::net::TcpStream::connect(server.local_addr()).await.unwrap();
+        connected(&server, 1).await;
+        for _ in 0..=CLIENT_BACKLOG {
+            server.publish(&StreamEvent::Probe(probe.clone()));
+        }
+        assert_eq!(server.clients(), 0);
+    }
+
+    #[test]
+    fn test_anomaly_capture() {
+        use chrono::TimeZone;
+        use netweaver_lib::diagnostics::pcap::{linktype, PcapReader};
+        use netweaver_lib::diagnostics::ping::PingMethod;
+        use netweaver_lib::monitor::anomaly::{self, AnomalyRecorder, CAPTURE_AFTER, RING_WINDOW};
+        use netweaver_lib::monitor::probes::{ProbeSample, ProbeStats, ProbeTarget};
+        use std::path::Path;
+
+        let start = chrono::Utc.with_ymd_and_hms(2026, 10, 15, 10, 23, 49).unwrap();
+        let at = |millis: i64| start + chrono::Duration::milliseconds(millis);
+        assert_eq!(
+            anomaly::timestamped(Path::new("/tmp/spike.pcap"), start),
+            Path::new("/tmp/spike-20261015T102349Z.pcap")
+        );
+        assert_eq!(anomaly::timestamped(Path::new("spike"), start), Path::new("spike-20261015T102349Z"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut recorder = AnomalyRecorder::new(&dir.path().join("spike.pcap"));
+        // Only what is within RING_WINDOW of the anomaly is kept from before it
+        let before = RING_WINDOW.as_millis() as i64;
+        for (millis, byte) in [(0, 1u8), (2_000, 2), (2_000 + before - 500, 3)] {
+            recorder.record(at(millis), linktype::ETHERNET, &[byte; 60], 60).unwrap();
+        }
+        let trigger_at = at(2_000 + before);
+        let path = recorder.trigger(trigger_at).unwrap().unwrap();
+        assert_eq!(path, dir.path().join("spike-20261015T102356Z.pcap"));
+        assert_eq!(recorder.dumping(), Some(path.as_path()));
+        // A second anomaly goes into the same file
+        assert_eq!(recorder.trigger(trigger_at + chrono::Duration::seconds(1)).unwrap(), None);
+        let after = CAPTURE_AFTER.as_millis() as i64;
+        recorder.record(trigger_at + chrono::Duration::milliseconds(after - 1), linktype::ETHERNET, &[4; 60], 60).unwrap();
+        // Frames of another link type can't share the file
+        recorder.record(trigger_at + chrono::Duration::milliseconds(after - 1), linktype::RAW, &[5; 40], 40).unwrap();
+        recorder.record(trigger_at + chrono::Duration::milliseconds(after), linktype::ETHERNET, &[6; 60], 60).unwrap();
+        assert_eq!(recorder.dumping(), None);
+
+        let files = recorder.finish().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].path.clone(), files[0].packets), (path.clone(), 3));
+        let mut reader = PcapReader::open(&path).unwrap();
+        let mut first_bytes = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            assert_eq!(record.linktype, linktype::ETHERNET);
+            first_bytes.push(record.data[0]);
+        }
+        assert_eq!(first_bytes, [2, 3, 4]);
+
+        // No anomaly, no file
+        let quiet = AnomalyRecorder::new(&dir.path().join("quiet.pcap"));
+        assert!(quiet.finish().unwrap().is_empty());
+
+        // A probe far slower than its usual RTT is one; a little jitter isn't
+        let target = ProbeTarget { name: "gateway".to_string(), ip: "10.0.0.1".parse().unwrap() };
+        let sample = |rtt_ms: f64| ProbeSample {
+            timestamp: chrono::Utc::now(),
+            target: "gateway".to_string(),
+            ip: target.ip,
+            method: PingMethod::Icmp,
+            rtt_ms: Some(rtt_ms),
+        };
+        let mut stats = vec![ProbeStats::new(target.clone(), PingMethod::Icmp)];
+        for rtt in [10.0, 10.2, 9.8, 10.1, 9.9, 10.0, 10.3, 9.7, 10.0, 10.1] {
+            stats[0].record(Some(rtt));
+        }
+        stats[0].record(Some(11.0));
+        assert!(!stats[0].is_anomalous());
+        assert_eq!(anomaly::probe_anomaly(&stats, &sample(11.0)), None);
+        stats[0].record(Some(80.0));
+        assert!(stats[0].is_anomalous());
+        let reason = anomaly::probe_anomaly(&stats, &sample(80.0)).unwrap();
+        assert!(reason.starts_with("gateway latency 80.0 ms"), "{}", reason);
+        stats[0].record(None);
+        assert!(!stats[0].is_anomalous());
+    }
+
+    #[test]
+    fn test_combined_report() {
+        use netweaver_lib::scanner::{self, ScanResult};
+        use netweaver_lib::security::{self, Finding, SecurityAudit, Severity};
+        use chrono::{TimeZone, Utc};
+
+        let scan: ScanResult = serde_json::from_value(serde_json::json!({
+            "devices": [
+                {"ip": "192.168.1.1", "mac": [170, 187, 204, 221, 238, 1], "hostname": "router", "open_ports": [22, 80],
+                 "os_guess": "Linux", "latency_ms": 1.5, "vendor": "Acme", "last_seen": 0},
+                {"ip": "192.168.1.20", "hostname": "<b>printer</b>", "open_ports": [631], "last_seen": 0},
+                {"ip": "192.168.1.30", "open_ports": [], "last_seen": 0},
+            ],
+            "scan_duration": {"secs": 4, "nanos": 0},
+            "network_range": "192.168.1.0/24",
+            "total_hosts": 254,
+            "responsive_hosts": 3,
+        })).unwrap();
+        let finding = |severity, title: &str| Finding {
+            check: "ports".to_string(),
+            severity,
+            title: title.to_string(),
+            detail: None,
+        };
+        let audit = SecurityAudit {
+            generated_at: Utc.with_ymd_and_hms(2026, 10, 15, 9, 0, 0).unwrap(),
+            checks: vec!["ports".to_string(), "vpn".to_string()],
+            findings: vec![
+                finding(Severity::Info, "Port 8080 (HTTP-Alt) is open on localhost"),
+                Finding { check: "vpn".to_string(), detail: Some("IPv6 traffic bypasses the VPN".to_string()),
+                          ..finding(Severity::Warning, "IPv6 leak detected") },
+                finding(Severity::Critical, "DNS leak detected"),
+            ],
+        };
+        assert_eq!((audit.count(Severity::Critical), audit.count(Severity::Warning), audit.count(Severity::Info)), (1, 1, 1));
+        assert!(Severity::Critical < Severity::Warning && Severity::Warning < Severity::Info);
+
+        let dir = tempfile::tempdir().unwrap();
+        let scan_path = dir.path().join("scan.json");
+        std::fs::write(&scan_path, serde_json::to_string(&scan).unwrap()).unwrap();
+        let audit_path = dir.path().join("audit.yaml");
+        security::save_audit(&audit, &audit_path).unwrap();
+        assert_eq!(security::load_audit(&audit_path).unwrap(), audit);
+
+        let data = tempfile::tempdir().unwrap();
+        let export = |format: &str, extra: &[&std::ffi::OsStr]| {
+            let path = dir.path().join(format!("report.{}", format));
+            let out = std::process::Command::new(env!("CARGO_BIN_EXE_netweaver"))
+                .args(["report", "--format", format, "--export"])
+                .arg(&path)
+                .args(extra)
+                .env("XDG_DATA_HOME", data.path())
+                .output()
+                .unwrap();
+            assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+            String::from_utf8_lossy(&std::fs::read(&path).unwrap()).into_owned()
+        };
+        let inputs = ["--scan".as_ref(), scan_path.as_os_str(), "--security".as_ref(), audit_path.as_os_str()];
+
+        // Machine-readable output nests both inputs whole
+        let json: serde_json::Value = serde_json::from_str(&export("json", &inputs)).unwrap();
+        assert_eq!(json["scan"]["devices"][1]["open_ports"][0], 631);
+        assert_eq!(json["scan"]["network_range"], "192.168.1.0/24");
+        assert_eq!(json["security"]["findings"][2]["severity"], "critical");
+        assert_eq!(json["security"]["checks"][1], "vpn");
+        let yaml: serde_json::Value = serde_yaml::from_str(&export("yaml", &inputs)).unwrap();
+        assert_eq!(yaml["security"]["findings"][1]["detail"], "IPv6 traffic bypasses the VPN");
+
+        let html = export("html", &inputs);
+        assert!(html.contains("<h2>Device Inventory</h2>") && html.contains("<h2>Open Ports</h2>"));
+        assert!(html.contains("192.168.1.0/24: 3 of 254 hosts responded in 4.0s"));
+        assert!(html.contains("192.168.1.1 (router):") && html.contains("MAC aa:bb:cc:dd:ee:01, vendor Acme, OS Linux, latency 1.5 ms"));
+        // Names off the network are escaped
+        assert!(html.contains("192.168.1.20 (&lt;b&gt;printer&lt;/b&gt;)") && !html.contains("<b>printer"));
+        assert!(html.contains("<tr><th>Device</th><th>22</th><th>80</th><th>631</th></tr>"), "{}", html);
+        assert!(html.contains("<tr><td>192.168.1.20</td><td></td><td></td><td class=\"open\">●</td></tr>"));
+        assert!(!html.contains("<tr><td>192.168.1.30</td>"));
+        assert!(html.contains("<h2>Security Findings</h2>"));
+        assert!(html.contains("2026-10-15 09:00 UTC (ports, vpn): 1 critical, 1 warnings, 1 informational"));
+        // Most serious first
+        let critical = html.find("[CRITICAL] DNS leak detected").unwrap();
+        let warning = html.find("[WARNING] IPv6 leak detected").unwrap();
+        let info = html.find("[INFO] Port 8080 (HTTP-Alt) is open on localhost").unwrap();
+        assert!(critical < warning && warning < info);
+        assert!(html.contains("IPv6 traffic bypasses the VPN (vpn check)"));
+        assert!(export("pdf", &inputs).starts_with("%PDF"));
+
+        // Without inputs the sections are left out, not failed on
+        let json: serde_json::Value = serde_json::from_str(&export("json", &[])).unwrap();
+        assert!(json.get("scan").is_none() && json.get("security").is_none());
+        let html = export("html", &[]);
+        assert!(!html.contains("Device Inventory") && !html.contains("Open Ports") && !html.contains("Security Findings"));
+
+        // Otherwise the latest stored ones go in
+        let stored = data.path().join("netweaver");
+        assert!(scanner::load_latest(&stored.join("scan.json")).unwrap().is_none());
+        scanner::store_latest(&stored.join("scan.json"), &scan).unwrap();
+        security::store_latest(&stored.join("security.json"), &audit).unwrap();
+        assert_eq!(security::load_latest(&stored.join("security.json")).unwrap(), Some(audit));
+        let html = export("html", &[]);
+        assert!(html.contains("<h2>Device Inventory</h2>") && html.contains("<h2>Security Findings</h2>"));
+
+        // An input that was asked for must be there
+        let out = std::process::Command::new(env!("CARGO_BIN_EXE_netweaver"))
+            .args(["report", "--export"])
+            .arg(dir.path().join("missing.json"))
+            .arg("--scan")
+            .arg(dir.path().join("nope.json"))
+            .env("XDG_DATA_HOME", data.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("nope.json"));
+    }
+
+    #[test]
+    fn test_wifi_link() {
+        use netweaver_lib::monitor::alerts::{AlertEngine, AlertInput, AlertRule, AlertState, Metric};
+        use netweaver_lib::monitor::dashboard::Dashboard;
+        use netweaver_lib::monitor::sample_log::SampleLog;
+        use netweaver_lib::monitor::wifi::{self, WifiLink, WifiStats};
+        use ratatui::{backend::TestBackend, Terminal};
+        use std::time::{Duration, Instant};
+
+        assert_eq!(wifi::channel(2412), Some(1));
+        assert_eq!(wifi::channel(2484), Some(14));
+        assert_eq!(wifi::channel(5180), Some(36));
+        assert_eq!(wifi::channel(5955), Some(1));
+        assert_eq!(wifi::channel(900), None);
+
+        let airport = "     agrCtlRSSI: -58\n     agrCtlNoise: -92\n           state: running\n      lastTxRate: 867\n            SSID: Home Net\n         channel: 149,80\n";
+        let link = wifi::parse_airport("en0", airport).unwrap();
+        assert_eq!((link.ssid.as_deref(), link.signal_dbm, link.tx_bitrate_mbps, link.channel),
+                   (Some("Home Net"), Some(-58), Some(867.0), Some(149)));
+        assert_eq!(link.quality(), Some("good"));
+        assert!(wifi::parse_airport("en0", "AirPort: Off\n").is_none());
+        let idle = wifi::parse_airport("en0", "     agrCtlRSSI: 0\n           state: init\n            SSID: \n").unwrap();
+        assert_eq!((idle.ssid, idle.signal_dbm), (None, None));
+        assert_eq!(wifi::parse_hardware_ports("Hardware Port: Ethernet\nDevice: en1\n\nHardware Port: Wi-Fi\nDevice: en0\n"), ["en0"]);
+
+        let proc = "Inter-| sta-|   Quality        |   Discarded packets\n face | tus | link level noise |  nwid  crypt\n wlan0: 0000   54.  -56.  -256        0      0\n";
+        assert_eq!(wifi::parse_proc_net_wireless(proc), [("wlan0".to_string(), -56)]);
+
+        let link = WifiLink {
+            interface: "wlan0".to_string(),
+            ssid: Some("Home Net".to_string()),
+            signal_dbm: Some(-52),
+            tx_bitrate_mbps: Some(866.7),
+            frequency_mhz: Some(5180),
+            channel: Some(36),
+            ..Default::default()
+        };
+        assert_eq!(link.summary(), "Home Net, -52 dBm (good), TX 866.7 Mb/s, ch 36 (5180 MHz)");
+
+        // Signal rules fire below their level, and only on interfaces with a signal
+        let rule: AlertRule = "signal < -70dBm for 10s".parse().unwrap();
+        assert_eq!((rule.metric, rule.threshold), (Metric::Signal, -70.0));
+        assert!("signal > -70dBm".parse::<AlertRule>().is_err());
+        assert!("signal".parse::<AlertRule>().is_err());
+        assert!("signal < weak".parse::<AlertRule>().is_err());
+        assert!("rx < 50MB/s".parse::<AlertRule>().is_err());
+        let mut engine = AlertEngine::new(vec![rule], Duration::from_secs(300));
+        let inputs = |dbm: i32| {
+            let mut inputs = vec![
+                AlertInput { interface: "wlan0".to_string(), ..Default::default() },
+                AlertInput { interface: "eth0".to_string(), ..Default::default() },
+            ];
+            wifi::add_signal(&mut inputs, &[WifiLink { signal_dbm: Some(dbm), ..link.clone() }]);
+            inputs
+        };
+        assert_eq!(inputs(-75)[1].signal_dbm, None);
+        let start = Instant::now();
+        assert!(engine.evaluate(start, &inputs(-75)).is_empty());
+        let fired = engine.evaluate(start + Duration::from_secs(10), &inputs(-78));
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].interface.as_str(), fired[0].state), ("wlan0", AlertState::Firing));
+        assert!(fired[0].message.contains("signal at -78 dBm"));
+        let resolved = engine.evaluate(start + Duration::from_secs(11), &inputs(-60));
+        assert_eq!(resolved[0].state, AlertState::Resolved);
+
+        // A sparkline point per sample; interfaces that go away are dropped
+        let mut stats: Vec<WifiStats> = Vec::new();
+        wifi::record(&mut stats, std::slice::from_ref(&link));
+        wifi::record(&mut stats, &[WifiLink { signal_dbm: Some(-80), ..link.clone() }]);
+        assert_eq!(stats[0].points().iter().copied().collect::<Vec<_>>(), [58, 30]);
+        assert_eq!(stats[0].link.signal_dbm, Some(-80));
+        wifi::record(&mut stats, &[]);
+        assert!(stats.is_empty());
+
+        let draw = |dashboard: &Dashboard| {
+            let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+            terminal.draw(|frame| dashboard.render(frame)).unwrap();
+            terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect::<String>()
+        };
+        let mut dashboard = Dashboard::new("all", None);
+        assert!(!draw(&dashboard).contains("Wi-Fi"));
+        wifi::record(&mut stats, std::slice::from_ref(&link));
+        dashboard.set_wifi(stats);
+        let screen = draw(&dashboard);
+        assert!(screen.contains("Wi-Fi wlan0") && screen.contains("-52 dBm (good)"));
+        assert!(screen.contains("TX 866.7 Mb/s") && screen.contains("ch 36 (5180 MHz)"));
+        assert!(screen.contains("Latency"));
+
+        // Logged as a line of its own
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("monitor.jsonl");
+        let mut log = SampleLog::open(&path).unwrap();
+        log.write_wifi(&link).unwrap();
+        log.flush().unwrap();
+        let line: serde_json::Value = serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(line["wifi"]["signal_dbm"], -52);
+        assert_eq!(serde_json::from_value::<WifiLink>(line["wifi"].clone()).unwrap(), link);
+    }
+
+    #[test]
+    fn test_error_detail() {
+        use netweaver_lib::monitor::dashboard::Dashboard;
+        use netweaver_lib::monitor::interfaces;
+        use netweaver_lib::monitor::{format_error_detail, total_stats, traffic_delta};
+        use ratatui::{backend::TestBackend, Terminal};
+        use std::collections::BTreeMap;
+
+        // Only the known causes, and only the non-zero ones
+        let dir = tempfile::tempdir().unwrap();
+        for (name, value) in [("rx_crc_errors", "12\n"), ("collisions", "2\n"), ("tx_carrier_errors", "0\n"), ("rx_errors", "14\n")] {
+            std::fs::write(dir.path().join(name), value).unwrap();
+        }
+        let detail = interfaces::read_error_detail(dir.path());
+        assert_eq!(detail, BTreeMap::from([("collisions".to_string(), 2), (CRC_ERRORS.to_string(), 12)]));
+        assert!(interfaces::read_error_detail(&dir.path().join("missing")).is_empty());
+        assert_eq!(format_error_detail(&detail).as_deref(), Some("collisions 2, rx_crc_errors 12"));
+        assert_eq!(format_error_detail(&BTreeMap::new()), None);
+
+        let twelve = with_crc_errors(sample("eth0", 1, 0), 12);
+        let delta = traffic_delta(&twelve, &with_crc_errors(sample("eth0", 2, 0), 15));
+        assert_eq!(delta.error_detail, BTreeMap::from([(CRC_ERRORS.to_string(), 3)]));
+        assert!(traffic_delta(&twelve, &with_crc_errors(sample("eth0", 2, 0), 12)).error_detail.is_empty());
+        assert_eq!(total_stats(&[twelve.clone(), with_crc_errors(sample("eth1", 1, 0), 3)]).error_detail[CRC_ERRORS], 15);
+
+        // Stored and reported with the counters, left out when there is none
+        let json = serde_json::to_value(&twelve).unwrap();
+        assert_eq!(json["error_detail"][CRC_ERRORS], 12);
+        assert!(serde_json::to_value(with_crc_errors(sample("eth0", 1, 0), 0)).unwrap().get("error_detail").is_none());
+
+        let draw = |dashboard: &Dashboard| {
+            let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+            terminal.draw(|frame| dashboard.render(frame)).unwrap();
+            terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect::<String>()
+        };
+        let mut dashboard = Dashboard::new("all", None);
+        dashboard.update(vec![with_crc_errors(sample("eth0", 1, 0), 0)]);
+        assert!(!draw(&dashboard).contains("NIC Errors"));
+        dashboard.update(vec![with_crc_errors(sample("eth0", 2, 0), 12)]);
+        dashboard.update(vec![with_crc_errors(sample("eth0", 3, 0), 15)]);
+        let screen = draw(&dashboard);
+        assert!(screen.contains("NIC Errors") && screen.contains("rx_crc_errors 15 (+3)"));
+        assert!(screen.contains("CRC errors rising"));
+        dashboard.update(vec![with_crc_errors(sample("eth0", 4, 0), 15)]);
+        let screen = draw(&dashboard);
+        assert!(screen.contains("rx_crc_errors 15") && !screen.contains("CRC errors rising"));
+    }
+
+    #[test]
+    fn test_refresh_interval() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        use netweaver_lib::monitor::dashboard::{self, Dashboard, DEFAULT_REFRESH, REFRESH_STEPS};
+        use ratatui::{backend::TestBackend, Terminal};
+        use std::time::Duration;
+
+        assert!(dashboard::check_refresh(Duration::from_millis(100)).is_ok());
+        assert!(dashboard::check_refresh(Duration::from_secs(60)).is_ok());
+        assert!(dashboard::check_refresh(Duration::from_millis(50)).is_err());
+        assert!(dashboard::check_refresh(Duration::from_secs(61)).is_err());
+        // Milliseconds unless it says otherwise
+        assert_eq!(dashboard::parse_refresh("500").unwrap(), Duration::from_millis(500));
+        assert_eq!(dashboard::parse_refresh("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(dashboard::parse_refresh("250ms").unwrap(), Duration::from_millis(250));
+
+        let mut dashboard = Dashboard::new("all", None);
+        assert_eq!(dashboard.refresh(), DEFAULT_REFRESH);
+        dashboard.set_refresh(Duration::from_millis(500));
+        let mut terminal = Terminal::new(TestBackend::new(140, 45)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("Refresh 500ms"));
+
+        // An interval off the steps moves to the nearest one that way
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        dashboard.set_refresh(Duration::from_millis(750));
+        dashboard.handle_key(key(KeyCode::Char('+')));
+        assert_eq!(dashboard.refresh(), Duration::from_secs(1));
+        dashboard.set_refresh(Duration::from_millis(750));
+        dashboard.handle_key(key(KeyCode::Char('-')));
+        assert_eq!(dashboard.refresh(), Duration::from_millis(500));
+        for _ in 0..20 {
+            dashboard.handle_key(key(KeyCode::Char('+')));
+        }
+        assert_eq!(dashboard.refresh(), REFRESH_STEPS[REFRESH_STEPS.len() - 1]);
+
+        // Out-of-range intervals fail before the terminal is taken over
+        let out = std::process::Command::new(env!("CARGO_BIN_EXE_netweaver"))
+            .args(["monitor", "--realtime", "--interval", "50ms"])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("100ms to 60s"));
+    }
+
+    #[test]
+    fn test_snapshot_compare() {
+        use netweaver_lib::monitor::protocols::{ProtocolStats, TcpStats};
+        use netweaver_lib::monitor::snapshot::{self, StatsSnapshot};
+
+        let tcp = |out_segs: u64, retrans_segs: u64| Some(ProtocolStats {
+            tcp: Some(TcpStats { out_segs, retrans_segs, ..Default::default() }),
+            ..Default::default()
+        });
+
+        // Only what the interface option covers goes in
+        let rows = [NetworkStats { drops: Some(0), ..sample("eth0", 0, 1000) }, sample("wg0", 0, 50), sample(ALL_INTERFACES, 0, 1050)];
+        let before = StatsSnapshot::new(&rows, "eth0,wg0", tcp(1000, 10));
+        assert_eq!(before.interfaces.len(), 2);
+        assert_eq!(StatsSnapshot::new(&rows, "eth0", None).interfaces.len(), 1);
+
+        // Saved and loaded as is
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("before.json");
+        snapshot::save_snapshot(&before, &path).unwrap();
+        assert_eq!(snapshot::load_snapshot(&path).unwrap(), before);
+        std::fs::write(&path, "{}").unwrap();
+        assert!(snapshot::load_snapshot(&path).is_err());
+        assert!(snapshot::load_snapshot(&dir.path().join("missing.json")).is_err());
+
+        let eth0 = with_crc_errors(NetworkStats { drops: Some(0), ..sample("eth0", 0, 1500) }, 3);
+        let mut after = StatsSnapshot::new(&[eth0, sample("eth1", 0, 10)], "all", tcp(2000, 40));
+        after.taken_at = before.taken_at + chrono::Duration::seconds(90);
+        let comparison = snapshot::compare(&before, &after);
+        assert_eq!(comparison.elapsed_secs, 90.0);
+        // Interfaces in one snapshot only are reported, not dropped
+        assert_eq!(comparison.only_before, ["wg0"]);
+        assert_eq!(comparison.only_after, ["eth1"]);
+        assert_eq!(comparison.interfaces.len(), 1);
+
+        let eth0 = &comparison.interfaces[0];
+        let counter = |name: &str| eth0.counters.iter().find(|counter| counter.counter == name).cloned();
+        let sent = counter("bytes_sent").unwrap();
+        assert_eq!((sent.change, sent.percent, sent.regressed), (500, Some(50.0), false));
+        let errors = counter("errors").unwrap();
+        assert_eq!((errors.change, errors.percent, errors.regressed), (3, None, true));
+        // A cause missing from a snapshot counted as zero there
+        let crc = counter("rx_crc_errors").unwrap();
+        assert_eq!((crc.before, crc.after, crc.regressed), (0, 3, true));
+        // Only in both snapshots
+        assert!(counter("overruns").is_none());
+        assert!(counter("drops").is_some());
+
+        let retrans = comparison.protocols.iter().find(|counter| counter.counter == "tcp.retrans_segs").unwrap();
+        assert_eq!((retrans.change, retrans.percent, retrans.regressed), (30, Some(300.0), true));
+        assert_eq!(comparison.retransmit_rate_before, Some(1.0));
+        assert_eq!(comparison.retransmit_rate_between, Some(3.0));
+        assert_eq!(comparison.regressions(), ["eth0 errors", "eth0 rx_crc_errors", "tcp.retrans_segs", "tcp retransmit rate"]);
+
+        // Counters that went down (a reboot in between) aren't regressions
+        let reset = snapshot::compare(&after, &StatsSnapshot { taken_at: after.taken_at, ..before.clone() });
+        assert!(reset.regressions().is_empty());
+        assert_eq!(reset.interfaces[0].counters[0].change, -500);
+
+        // Exportable
+        let output = dir.path().join("comparison.json");
+        snapshot::save_comparison(&comparison, output.to_str().unwrap()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(json["only_before"][0], "wg0");
+        assert_eq!(json["interfaces"][0]["counters"][0]["change"], 500);
+    }
+}
+
+mod security_tests {
+    use netweaver_lib::security::dns_leak::{
+        classify, is_tunnel_name, parse_resolv_conf, parse_scutil_dns, tunnels, Link, ResolverCheck, Route,
+    };
+    use netweaver_lib::security::ipv6_leak::{global_addresses, route, verdict, Connection, Ipv6Route, Ipv6Signals, Verdict};
+    use netweaver_lib::security::public_ip::{
+        addresses, detect, format_ranges, in_exit, in_range, parse_exit_range, parse_stun_response, seen_by, stun_request,
+        SourceAnswer, VpnOptions,
+    };
+    use netweaver_lib::security::{parse_arp_a, parse_proc_net_arp};
+    use netweaver_lib::utils::MacAddress;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    fn mac(text: &str) -> MacAddress {
+        text.parse().unwrap()
+    }
+
+    fn link(name: &str, networks: &[(&str, u8)], tunnel: bool) -> Link {
+        Link { name: name.to_string(), networks: networks.iter().map(|(ip, prefix)| (ip.parse().unwrap(), *prefix)).collect(), tunnel }
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_proc_net_arp() {
+        let table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         00:50:56:c0:00:08     *        eth0
+192.168.1.100    0x1         0x2         f0:18:98:12:34:56     *        eth0
+192.168.1.77     0x1         0x0         00:00:00:00:00:00     *        eth0
+10.8.0.1         0x1         0x6         02:42:ac:11:00:02     *        docker0
+172.17.0.3       0x1         0x2         02:42:ac:11:00:03     *        docker0
+garbage line
+192.168.1.5      0x1         0x2         not-a-mac             *        eth0
+";
+        let entries = parse_proc_net_arp(table);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[&Ipv4Addr::new(192, 168, 1, 1)], mac("00:50:56:c0:00:08"));
+        assert_eq!(entries[&Ipv4Addr::new(10, 8, 0, 1)], mac("02:42:ac:11:00:02"));
+        // Incomplete and malformed entries are skipped
+        assert!(!entries.contains_key(&Ipv4Addr::new(192, 168, 1, 77)));
+        assert!(!entries.contains_key(&Ipv4Addr::new(192, 168, 1, 5)));
+        // Just the header
+        assert!(parse_proc_net_arp("IP address       HW type     Flags       HW address            Mask     Device\n").is_empty());
+        assert!(parse_proc_net_arp("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_arp_a() {
+        let macos = "\
+? (192.168.1.1) at 0:50:56:c0:0:8 on en0 ifscope [ethernet]
+router.lan (192.168.1.254) at f0:18:98:12:34:56 on en0 ifscope [ethernet]
+? (192.168.1.42) at (incomplete) on en0 ifscope [ethernet]
+? (192.168.1.255) at ff:ff:ff:ff:ff:ff on en0 ifscope [ethernet]
+? (224.0.0.251) at 1:0:5e:0:0:fb on en0 ifscope permanent [ethernet]
+";
+        let entries = parse_arp_a(macos);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[&Ipv4Addr::new(192, 168, 1, 1)], mac("00:50:56:c0:00:08"));
+        assert_eq!(entries[&Ipv4Addr::new(192, 168, 1, 254)], mac("f0:18:98:12:34:56"));
+
+        let windows = "\
+\r
+Interface: 192.168.1.100 --- 0xb\r
+  Internet Address      Physical Address      Type\r
+  192.168.1.1           00-50-56-c0-00-08     dynamic\r
+  192.168.1.20          00-50-56-c0-00-08     dynamic\r
+  192.168.1.255         ff-ff-ff-ff-ff-ff     static\r
+  224.0.0.22            01-00-5e-00-00-16     static\r
+  255.255.255.255       ff-ff-ff-ff-ff-ff     static\r
+\r
+Interface: 10.0.0.5 --- 0x12\r
+  Internet Address      Physical Address      Type\r
+  10.0.0.1              3c-84-6a-aa-bb-cc     dynamic\r
+";
+        let entries = parse_arp_a(windows);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[&Ipv4Addr::new(192, 168, 1, 20)], mac("00:50:56:c0:00:08"));
+        assert_eq!(entries[&Ipv4Addr::new(10, 0, 0, 1)], mac("3c:84:6a:aa:bb:cc"));
+        // The interface headings aren't entries
+        assert!(!entries.contains_key(&Ipv4Addr::new(192, 168, 1, 100)));
+        assert!(parse_arp_a("No ARP Entries Found.").is_empty());
+    }
+
+    #[test]
+    fn test_arp_a_mac_forms() {
+        let entry = |text: &str| parse_arp_a(&format!("? (192.168.1.9) at {} on en0 ifscope [ethernet]", text)).remove(&Ipv4Addr::new(192, 168, 1, 9));
+        assert_eq!(entry("a:b:c:d:e:f"), Some(mac("0a:0b:0c:0d:0e:0f")));
+        assert_eq!(entry("AA-BB-CC-DD-EE-01"), Some(mac("aa:bb:cc:dd:ee:01")));
+        for text in ["ff:ff:ff:ff:ff:ff", "01:00:5e:00:00:fb", "00:00:00:00:00:00", "aa:bb:cc:dd:ee", "aaa:bb:cc:dd:ee:ff",
+                     "+a:bb:cc:dd:ee:ff", "aa::cc:dd:ee:ff", "(incomplete)"] {
+            assert_eq!(entry(text), None, "{}", text);
+        }
+    }
+
+    const TRANSACTION: [u8; 12] = [0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae];
+
+    /// A binding response from RFC 5769 2.2: 192.0.2.1:32853, after a
+    /// SOFTWARE attribute that isn't a multiple of 4 bytes long
+    fn rfc5769_response() -> Vec<u8> {
+        let mut packet = vec![0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42];
+        packet.extend(TRANSACTION);
+        packet.extend([0x80, 0x22, 0x00, 0x0b]);
+        packet.extend(b"test vector\x20");
+        packet.extend([0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]);
+        packet.extend([0x00, 0x08, 0x00, 0x14]);
+        packet.extend([0u8; 20]);
+        packet.extend([0x80, 0x28, 0x00, 0x04, 0xc0, 0x7d, 0x4c, 0x96]);
+        packet
+    }
+
+    #[test]
+    fn test_stun_request() {
+        let request = stun_request(&TRANSACTION);
+        assert_eq!(request[..8], [0x00, 0x01, 0x00, 0x00, 0x21, 0x12, 0xa4, 0x42]);
+        assert_eq!(request[8..], TRANSACTION);
+    }
+
+    #[test]
+    fn test_parse_stun_response() {
+        let packet = rfc5769_response();
+        assert_eq!(parse_stun_response(&packet, &TRANSACTION), Some("192.0.2.1:32853".parse().unwrap()));
+
+        // Someone else's transaction, a request, or cut short
+        let mut other = TRANSACTION;
+        other[0] ^= 1;
+        assert_eq!(parse_stun_response(&packet, &other), None);
+        let mut request = packet.clone();
+        request[1] = 0x01;
+        request[0] = 0x00;
+        assert_eq!(parse_stun_response(&request, &TRANSACTION), None);
+        assert_eq!(parse_stun_response(&packet[..30], &TRANSACTION), None);
+        assert_eq!(parse_stun_response(&[], &TRANSACTION), None);
+
+        // An RFC 3489 server's MAPPED-ADDRESS, not XORed
+        let mut classic = vec![0x01, 0x01, 0x00, 0x0c, 0x21, 0x12, 0xa4, 0x42];
+        classic.extend(TRANSACTION);
+        classic.extend([0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x0d, 0x96, 203, 0, 113, 42]);
+        assert_eq!(parse_stun_response(&classic, &TRANSACTION), Some("203.0.113.42:3478".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_exit_ranges() {
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        assert_eq!(parse_exit_range("198.51.100.0/24").unwrap(), (ip("198.51.100.0"), 24));
+        assert_eq!(parse_exit_range("203.0.113.42").unwrap(), (ip("203.0.113.42"), 32));
+        assert_eq!(parse_exit_range("2001:db8::/32").unwrap(), (ip("2001:db8::"), 32));
+        assert_eq!(parse_exit_range("2001:db8::1").unwrap(), (ip("2001:db8::1"), 128));
+        for text in ["198.51.100.0/33", "2001:db8::/129", "198.51.100.0/", "vpn.example.com", ""] {
+            assert!(parse_exit_range(text).is_err(), "{}", text);
+        }
+
+        let range = parse_exit_range("198.51.100.0/24").unwrap();
+        assert!(in_range(ip("198.51.100.7"), range));
+        assert!(!in_range(ip("198.51.101.7"), range));
+        assert!(!in_range(ip("2001:db8::1"), range));
+        assert!(in_range(ip("8.8.8.8"), (ip("0.0.0.0"), 0)));
+        let range = parse_exit_range("2001:db8::/32").unwrap();
+        assert!(in_range(ip("2001:db8:5::1"), range));
+        assert!(!in_range(ip("2001:db9::1"), range));
+
+        // Only against the ranges of the address's own family
+        let ranges = [parse_exit_range("2001:db8::/32").unwrap()];
+        assert_eq!(in_exit(ip("2001:db8::1"), &ranges), Some(true));
+        assert_eq!(in_exit(ip("2001:db9::1"), &ranges), Some(false));
+        assert_eq!(in_exit(ip("198.51.100.7"), &ranges), None);
+        assert_eq!(format_ranges(&[(ip("198.51.100.0"), 24), ranges[0]]), "198.51.100.0/24, 2001:db8::/32");
+    }
+
+    #[test]
+    fn test_answers() {
+        let answer = |source: &str, ip: &str| SourceAnswer { source: source.to_string(), answer: Ok(ip.parse().unwrap()) };
+        let answers = [
+            answer("api.ipify.org", "198.51.100.7"),
+            SourceAnswer { source: "ifconfig.co".to_string(), answer: Err("timed out".to_string()) },
+            answer("STUN stun.example.com:3478", "203.0.113.42"),
+            answer("ip.example.net", "198.51.100.7"),
+        ];
+        let ips: Vec<IpAddr> = vec!["198.51.100.7".parse().unwrap(), "203.0.113.42".parse().unwrap()];
+        assert_eq!(addresses(&answers), ips);
+        assert_eq!(seen_by(&answers, ips[0]), ["api.ipify.org", "ip.example.net"]);
+
+        assert!(VpnOptions { ip_sources: vec!["https://ip.example.net/plain".to_string()], ..Default::default() }.check().is_ok());
+        assert!(VpnOptions { ip_sources: vec!["ftp://ip.example.net".to_string()], ..Default::default() }.check().is_err());
+        assert!(VpnOptions { ip_sources: vec!["ip.example.net".to_string()], ..Default::default() }.check().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_detect_local_sources() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let http = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ip?format=text", http.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = http.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\nConnection: close\r\n\r\n198.51.100.7\n").await.unwrap();
+        });
+
+        // Answers a binding request with an RFC 3489 MAPPED-ADDRESS
+        let stun = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = stun.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut request = [0u8; 576];
+            let (_, from) = stun.recv_from(&mut request).await.unwrap();
+            let mut response = vec![0x01, 0x01, 0x00, 0x0c, 0x21, 0x12, 0xa4, 0x42];
+            response.extend(&request[8..20]);
+            response.extend([0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x0d, 0x96, 203, 0, 113, 42]);
+            stun.send_to(&response, from).await.unwrap();
+        });
+
+        let answers = detect(&VpnOptions { ip_sources: vec![url], stun_server: Some(server.clone()), ..Default::default() }).await;
+        // An HTTP source goes by its host alone
+        assert_eq!(answers, [
+            SourceAnswer { source: "127.0.0.1".to_string(), answer: Ok("198.51.100.7".parse().unwrap()) },
+            SourceAnswer { source: format!("STUN {}", server), answer: Ok("203.0.113.42".parse().unwrap()) },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let text = "\
+# Generated by NetworkManager
+search lan
+nameserver 192.168.1.1
+nameserver fe80::1%wlan0
+ nameserver   2001:db8::53
+options edns0 trust-ad
+nameserver not-an-ip
+nameserver
+";
+        assert_eq!(parse_resolv_conf(text), [ip("192.168.1.1"), ip("fe80::1"), ip("2001:db8::53")]);
+        assert!(parse_resolv_conf("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_scutil_dns() {
+        let text = "\
+DNS configuration
+
+resolver #1
+  search domain[0] : lan
+  nameserver[0] : 10.8.0.1
+  if_index : 23 (utun4)
+  flags    : Request A records
+  reach    : 0x00000003 (Reachable,Transient Connection)
+
+resolver #2
+  domain   : local
+  options  : mdns
+  timeout  : 5
+
+DNS configuration (for scoped queries)
+
+resolver #1
+  nameserver[0] : 192.168.1.1
+  nameserver[1] : fe80::1%en0
+  if_index : 6 (en0)
+";
+        assert_eq!(parse_scutil_dns(text), [ip("10.8.0.1"), ip("192.168.1.1"), ip("fe80::1")]);
+    }
+
+    #[test]
+    fn test_classify() {
+        let links = [
+            link("eth0", &[("192.168.1.20", 24), ("fe80::2", 64)], false),
+            link("wg0", &[("10.8.0.2", 24)], true),
+            link("docker0", &[("172.17.0.1", 16)], false),
+        ];
+        assert!(is_tunnel_name("wg0") && is_tunnel_name("utun4") && is_tunnel_name("NordLynx"));
+        assert!(!is_tunnel_name("eth0") && !is_tunnel_name("wlan0"));
+        assert_eq!(tunnels(&links).len(), 1);
+        assert!(tunnels(&[link("utun0", &[("fe80::1", 64)], true)]).is_empty());
+
+        // In the tunnel's address space, or routed through it
+        assert_eq!(classify(ip("10.8.0.1"), Some(ip("10.8.0.2")), &links, &[]), (Route::Tunnel, Some("wg0".to_string())));
+        assert_eq!(classify(ip("1.1.1.1"), Some(ip("10.8.0.2")), &links, &[]).0, Route::Tunnel);
+        // Around it: the router, or a public resolver over the NIC
+        assert_eq!(classify(ip("192.168.1.1"), Some(ip("192.168.1.20")), &links, &[]), (Route::Outside, Some("eth0".to_string())));
+        assert_eq!(classify(ip("8.8.8.8"), Some(ip("192.168.1.20")), &links, &[]).0, Route::Outside);
+        // The VPN's own range counts as the VPN's, however it's routed
+        assert_eq!(classify(ip("198.51.100.53"), Some(ip("192.168.1.20")), &links, &[(ip("198.51.100.0"), 24)]).0, Route::Tunnel);
+        assert_eq!(classify(ip("127.0.0.53"), Some(ip("127.0.0.1")), &links, &[]), (Route::Local, None));
+        assert_eq!(classify(ip("172.17.0.1"), Some(ip("172.17.0.1")), &links, &[]).0, Route::Local);
+        assert_eq!(classify(ip("9.9.9.9"), None, &links, &[]), (Route::Unknown, None));
+
+        let check = |route: Route, latency_ms: Option<f64>| ResolverCheck { server: ip("192.168.1.1"), route, interface: None, latency_ms, error: None };
+        // Only an answer from outside the tunnel is a leak
+        assert!(check(Route::Outside, Some(12.0)).leaks());
+        assert!(!check(Route::Outside, None).leaks());
+        assert!(!check(Route::Tunnel, Some(12.0)).leaks());
+        assert!(!check(Route::Local, Some(0.2)).leaks());
+        assert_eq!(check(Route::Tunnel, None).owner(), "VPN provider");
+        assert_eq!(check(Route::Outside, None).owner(), "ISP or local network");
+    }
+
+    #[test]
+    fn test_global_addresses() {
+        let links = [
+            link("eth0", &[("192.168.1.20", 24), ("fe80::2", 64), ("fd00::2", 64), ("2001:db8:1::20", 64)], false),
+            link("wg0", &[("10.8.0.2", 24), ("2001:db8:ffff::2", 64)], true),
+        ];
+        let addresses = global_addresses(&links);
+        assert_eq!(addresses.len(), 2);
+        assert_eq!((addresses[0].interface.as_str(), addresses[0].tunnel), ("eth0", false));
+        assert_eq!(addresses[1].address, "2001:db8:ffff::2".parse::<Ipv6Addr>().unwrap());
+        assert!(global_addresses(&[link("eth0", &[("192.168.1.20", 24), ("fe80::2", 64)], false)]).is_empty());
+
+        assert_eq!(route(Some(ip("2001:db8:1::20")), &links), Some(Ipv6Route { interface: Some("eth0".to_string()), tunnel: false }));
+        assert!(route(Some(ip("2001:db8:ffff::2")), &links).unwrap().tunnel);
+        assert_eq!(route(Some(ip("192.168.1.20")), &links), None);
+        assert_eq!(route(None, &links), None);
+    }
+
+    #[test]
+    fn test_verdict() {
+        let signals = |route: Option<bool>, connection: Connection| Ipv6Signals {
+            addresses: Vec::new(),
+            route: route.map(|tunnel| Ipv6Route { interface: Some(if tunnel { "wg0" } else { "eth0" }.to_string()), tunnel }),
+            connection,
+        };
+        let revealed = |text: &str| Connection::Revealed(text.parse().unwrap());
+        let vpn_range = [(ip("2001:db8:ffff::"), 48)];
+
+        assert_eq!(verdict(&signals(Some(false), revealed("2001:db8:1::20")), false, &[]), Verdict::NoTunnel);
+        assert_eq!(verdict(&signals(None, Connection::Skipped), true, &[]), Verdict::NoIpv6);
+        assert_eq!(verdict(&signals(Some(false), revealed("2001:db8:1::20")), true, &[]), Verdict::Leak);
+        assert_eq!(verdict(&signals(Some(true), revealed("2001:db8:ffff::2")), true, &[]), Verdict::Tunnelled);
+        assert_eq!(verdict(&signals(Some(false), Connection::Failed("timed out".to_string())), true, &[]), Verdict::Exposed);
+        assert_eq!(verdict(&signals(Some(true), Connection::Failed("timed out".to_string())), true, &[]), Verdict::Tunnelled);
+        // The VPN's own range settles it, whatever the route looked like
+        assert_eq!(verdict(&signals(Some(true), revealed("2001:db8:1::20")), true, &vpn_range), Verdict::Leak);
+        assert_eq!(verdict(&signals(Some(false), revealed("2001:db8:ffff::9")), true, &vpn_range), Verdict::Tunnelled);
+        // An IPv4-only range says nothing about IPv6
+        assert_eq!(verdict(&signals(Some(true), revealed("2001:db8:1::20")), true, &[(ip("198.51.100.0"), 24)]), Verdict::Tunnelled);
     }
 }